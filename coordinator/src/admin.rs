@@ -0,0 +1,255 @@
+//! Operator endpoints.
+//!
+//! Routes under `/admin` require the admin token configured with
+//! `--admin-token` as a bearer token. It is separate from participant
+//! sessions, and the admin routes are disabled when no token is configured.
+//!
+//! * `GET /admin/queue` shows the active participant and the queue.
+//! * `POST /admin/pause` and `POST /admin/resume` stop and restart handing out
+//!   the contribution slot. Queued participants keep their place.
+//! * `POST /admin/evict` removes the active participant without counting it as
+//!   an offense.
+//! * `POST /admin/session-key/rotate` issues session tokens with a new key,
+//!   `{"key": "0x.."}` or random. The response contains the new key, so it can
+//!   be configured for restarts. Tokens of previous keys stay valid, unless
+//!   `?revokePrevious=true` logs out all participants. `DELETE
+//!   /admin/session-keys/{id}` revokes a single previous key.
+//! * `POST /admin/coordinator-key/rotate` signs receipts and responses with a
+//!   new coordinator key, `{"key": "0x.."}` or random. Signatures of the
+//!   previous key stay trusted until `DELETE
+//!   /admin/coordinator-keys/{address}`. The published keys are on `GET
+//!   /ceremony/keys`, see [`crate::signed`].
+//! * `POST /admin/sessions/revoke` revokes the sessions of identities, see
+//!   [`crate::revocation`].
+//! * `POST /admin/finalize` closes the ceremony and writes the signed final
+//!   artifact, see [`crate::finalize`].
+//! * `GET /admin/bans` and `DELETE /admin/bans/{identity}`, see
+//!   [`crate::bans`].
+//! * `GET /admin/audit` exports the audit log, see [`crate::audit`].
+//! * `GET /admin/pseudonyms/{pseudonym}` resolves a pseudonym, see
+//!   [`crate::pseudonym`].
+//!
+//! Rotated keys last until a restart, after which the keys configured with
+//! `--session-key`, `--coordinator-key` and their `--previous-*` counterparts
+//! apply again. Make a rotation permanent by updating the configuration: the
+//! rotated key becomes `--session-key` and the old one joins
+//! `--previous-session-keys`, so no session is invalidated.
+
+use crate::{
+    queue::{Queue, QueueStatus},
+    session::{SessionKey, SharedSessionKeys},
+    signing::{Address, CoordinatorKey, CoordinatorKeys, KeySet},
+};
+use axum::{
+    async_trait,
+    extract::{FromRequest, Path, Query, RequestParts},
+    http::{header::AUTHORIZATION, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use eyre::{ensure, Error as EyreError};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{fmt, str::FromStr, sync::Arc};
+use thiserror::Error;
+use tracing::{info, warn};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum AdminError {
+    #[error("Admin API is disabled")]
+    Disabled,
+    #[error("Invalid admin token")]
+    InvalidToken,
+}
+
+/// Secret bearer token for the admin API.
+///
+/// Only a hash is kept, so comparisons do not leak the token through timing.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AdminToken([u8; 32]);
+
+/// Proof that the request carries the admin token.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Admin;
+
+impl AdminToken {
+    #[must_use]
+    pub fn verify(&self, token: &str) -> bool {
+        self.0 == hash(token)
+    }
+}
+
+impl fmt::Debug for AdminToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AdminToken(..)")
+    }
+}
+
+impl FromStr for AdminToken {
+    type Err = EyreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ensure!(s.len() >= 16, "Admin token must be at least 16 characters");
+        Ok(Self(hash(s)))
+    }
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Admin {
+    type Rejection = AdminError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Extension(token) = Extension::<Option<Arc<AdminToken>>>::from_request(req)
+            .await
+            .expect("AdminToken extension missing");
+        let token = token.ok_or(AdminError::Disabled)?;
+        let presented = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(AdminError::InvalidToken)?;
+        if token.verify(presented) {
+            Ok(Self)
+        } else {
+            Err(AdminError::InvalidToken)
+        }
+    }
+}
+
+impl IntoResponse for AdminError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::Disabled => StatusCode::NOT_FOUND,
+            Self::InvalidToken => StatusCode::UNAUTHORIZED,
+        };
+        (status, Json(json!({ "error": self.to_string() }))).into_response()
+    }
+}
+
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn queue(_admin: Admin, Extension(queue): Extension<Arc<Queue>>) -> Json<QueueStatus> {
+    Json(queue.status())
+}
+
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn pause(_admin: Admin, Extension(queue): Extension<Arc<Queue>>) -> Json<QueueStatus> {
+    queue.set_paused(true);
+    Json(queue.status())
+}
+
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn resume(_admin: Admin, Extension(queue): Extension<Arc<Queue>>) -> Json<QueueStatus> {
+    queue.set_paused(false);
+    Json(queue.status())
+}
+
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn evict(
+    _admin: Admin,
+    Extension(queue): Extension<Arc<Queue>>,
+) -> Json<serde_json::Value> {
+    Json(json!({ "evicted": queue.evict() }))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RotateQuery {
+    #[serde(default)]
+    pub revoke_previous: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default, Deserialize)]
+pub struct RotateRequest {
+    /// New session or coordinator key, hex encoded. Random if not set.
+    pub key: Option<String>,
+}
+
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn rotate_session_key(
+    _admin: Admin,
+    Extension(keys): Extension<SharedSessionKeys>,
+    Query(query): Query<RotateQuery>,
+    request: Option<Json<RotateRequest>>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let key: SessionKey = match request.and_then(|Json(request)| request.key) {
+        Some(key) => key.parse().map_err(|_| StatusCode::BAD_REQUEST)?,
+        None => SessionKey::random(),
+    };
+    // Returned so the operator can configure it, or it is lost on restart.
+    let hex = key.to_hex();
+    let mut keys = keys.write().unwrap();
+    let id = keys.rotate(key);
+    if query.revoke_previous {
+        warn!("Rotating session key, all sessions are invalidated");
+        keys.revoke_previous();
+    } else {
+        info!(key_id = %id, "Rotating session key");
+    }
+    Ok(Json(json!({
+        "keyId": id,
+        "key": hex,
+        "previous": keys.previous_ids(),
+    })))
+}
+
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn revoke_session_key(
+    _admin: Admin,
+    Extension(keys): Extension<SharedSessionKeys>,
+    Path(id): Path<String>,
+) -> Json<serde_json::Value> {
+    let revoked = keys.write().unwrap().revoke(&id);
+    if revoked {
+        warn!(key_id = %id, "Revoked session key");
+    }
+    Json(json!({ "revoked": revoked }))
+}
+
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn rotate_coordinator_key(
+    _admin: Admin,
+    Extension(keys): Extension<Arc<CoordinatorKeys>>,
+    request: Option<Json<RotateRequest>>,
+) -> Result<Json<KeySet>, StatusCode> {
+    let key = match request.and_then(|Json(request)| request.key) {
+        Some(key) => key.parse().map_err(|_| StatusCode::BAD_REQUEST)?,
+        None => CoordinatorKey::random(),
+    };
+    let address = keys.rotate(key);
+    info!(%address, "Rotated coordinator key");
+    Ok(Json(keys.keys()))
+}
+
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn revoke_coordinator_key(
+    _admin: Admin,
+    Extension(keys): Extension<Arc<CoordinatorKeys>>,
+    Path(address): Path<String>,
+) -> Result<Json<KeySet>, StatusCode> {
+    let address: Address = address.parse().map_err(|_| StatusCode::BAD_REQUEST)?;
+    if !keys.revoke(address) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    warn!(%address, "Revoked coordinator key");
+    Ok(Json(keys.keys()))
+}
+
+fn hash(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_admin_token() {
+        assert!("short".parse::<AdminToken>().is_err());
+        let token: AdminToken = "0123456789abcdef".parse().unwrap();
+        assert!(token.verify("0123456789abcdef"));
+        assert!(!token.verify("0123456789abcdeF"));
+        assert_eq!(format!("{token:?}"), "AdminToken(..)");
+    }
+}
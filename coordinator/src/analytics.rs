@@ -0,0 +1,310 @@
+//! Queue funnel analytics.
+//!
+//! Participants move through the stages joined → reached front → started →
+//! completed. For every stage this counts how many participants reached it,
+//! how many gave up while in it, and how long they waited to get there from
+//! the previous stage. The counters are persisted next to the transcript so
+//! they survive restarts, and a funnel summary is served on `GET /stats`.
+//!
+//! Recording only updates the counters in memory. A background task writes a
+//! snapshot every [`SNAPSHOT_INTERVAL`] if anything changed, and a final one
+//! on shutdown, so queue requests do not wait for the disk.
+
+use crate::storage::write_atomic;
+use axum::{Extension, Json};
+use eyre::Result as EyreResult;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{task::spawn_blocking, time::interval};
+use tracing::{info, warn};
+
+const FILE: &str = "analytics.json";
+
+/// Interval between snapshots of changed analytics.
+pub const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Stage {
+    Joined,
+    ReachedFront,
+    Started,
+    Completed,
+}
+
+#[derive(Clone, Debug)]
+pub struct QueueAnalytics {
+    path:  PathBuf,
+    state: Arc<Mutex<State>>,
+    /// Whether the state changed since the last snapshot.
+    dirty: Arc<AtomicBool>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct State {
+    stages:  HashMap<Stage, StageStats>,
+    /// Participants that have not completed yet, with their current stage and
+    /// the unix time they entered it.
+    pending: HashMap<String, (Stage, u64)>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StageStats {
+    reached:        u64,
+    dropped:        u64,
+    total_wait_sec: u64,
+    max_wait_sec:   u64,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Funnel {
+    pub stages: Vec<FunnelStage>,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FunnelStage {
+    pub stage:        Stage,
+    /// Participants that reached this stage.
+    pub reached:      u64,
+    /// Participants currently in this stage.
+    pub in_progress:  u64,
+    /// Participants that left or were evicted while in this stage.
+    pub dropped:      u64,
+    /// Fraction of joined participants that reached this stage.
+    pub conversion:   f64,
+    /// Average time since the previous stage, in seconds.
+    pub avg_wait_sec: f64,
+    pub max_wait_sec: u64,
+}
+
+impl Stage {
+    pub const ALL: [Self; 4] = [
+        Self::Joined,
+        Self::ReachedFront,
+        Self::Started,
+        Self::Completed,
+    ];
+}
+
+impl QueueAnalytics {
+    /// Load the analytics persisted in `dir`, or start empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the persisted analytics can not be read or parsed.
+    pub fn open(dir: &Path) -> EyreResult<Self> {
+        let path = dir.join(FILE);
+        let state = if path.exists() {
+            serde_json::from_slice(&fs::read(&path)?)?
+        } else {
+            State::default()
+        };
+        info!(pending = state.pending.len(), "Loaded queue analytics");
+        Ok(Self {
+            path,
+            state: Arc::new(Mutex::new(state)),
+            dirty: Arc::default(),
+        })
+    }
+
+    /// Record that `identity` reached `stage`.
+    pub fn record(&self, identity: &str, stage: Stage) {
+        self.record_at(identity, stage, now());
+    }
+
+    /// Record that `identity` left the queue or lost their slot.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the analytics lock is poisoned.
+    pub fn drop_out(&self, identity: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some((stage, _)) = state.pending.remove(identity) {
+            state.stages.entry(stage).or_default().dropped += 1;
+            drop(state);
+            self.dirty.store(true, Ordering::Release);
+        }
+    }
+
+    /// Progress through the stages so far.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the analytics lock is poisoned.
+    #[must_use]
+    pub fn funnel(&self) -> Funnel {
+        self.state.lock().unwrap().funnel()
+    }
+
+    fn record_at(&self, identity: &str, stage: Stage, time: u64) {
+        let mut inner = self.state.lock().unwrap();
+        let previous = inner.pending.remove(identity);
+        let wait = match previous {
+            // Re-joining abandons the previous attempt.
+            Some((previous, _)) if stage == Stage::Joined => {
+                inner.stages.entry(previous).or_default().dropped += 1;
+                0
+            }
+            Some((_, since)) => time.saturating_sub(since),
+            None => 0,
+        };
+        let stats = inner.stages.entry(stage).or_default();
+        stats.reached += 1;
+        stats.total_wait_sec += wait;
+        stats.max_wait_sec = stats.max_wait_sec.max(wait);
+        if stage != Stage::Completed {
+            inner.pending.insert(identity.to_string(), (stage, time));
+        }
+        drop(inner);
+        self.dirty.store(true, Ordering::Release);
+    }
+
+    /// Write a snapshot if anything changed since the last one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the snapshot can not be written. It is retried on
+    /// the next call.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the analytics lock is poisoned.
+    pub fn persist(&self) -> EyreResult<()> {
+        if !self.dirty.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
+        let state = self.state.lock().unwrap().clone();
+        let result = serde_json::to_vec(&state)
+            .map_err(Into::into)
+            .and_then(|json| write_atomic(&self.path, &json));
+        if result.is_err() {
+            // Retry on the next snapshot.
+            self.dirty.store(true, Ordering::Release);
+        }
+        result
+    }
+
+    /// Start writing snapshots every [`SNAPSHOT_INTERVAL`].
+    ///
+    /// # Panics
+    ///
+    /// The task panics if a snapshot panicked.
+    pub fn spawn(&self) {
+        let analytics = self.clone();
+        tokio::spawn(async move {
+            let mut ticks = interval(SNAPSHOT_INTERVAL);
+            loop {
+                ticks.tick().await;
+                let analytics = analytics.clone();
+                // Analytics are best effort, a failed snapshot is retried.
+                if let Err(error) = spawn_blocking(move || analytics.persist())
+                    .await
+                    .expect("Snapshot task panicked")
+                {
+                    warn!(?error, "Could not persist queue analytics");
+                }
+            }
+        });
+    }
+}
+
+impl State {
+    #[allow(clippy::cast_precision_loss)] // Counts are far below 2^52
+    fn funnel(&self) -> Funnel {
+        let joined = self.stages.get(&Stage::Joined).map_or(0, |s| s.reached);
+        let stages = Stage::ALL
+            .iter()
+            .map(|&stage| {
+                let stats = self.stages.get(&stage).copied().unwrap_or_default();
+                let in_progress = self.pending.values().filter(|(s, _)| *s == stage).count();
+                FunnelStage {
+                    stage,
+                    reached: stats.reached,
+                    in_progress: in_progress as u64,
+                    dropped: stats.dropped,
+                    conversion: if joined == 0 {
+                        0.0
+                    } else {
+                        stats.reached as f64 / joined as f64
+                    },
+                    avg_wait_sec: if stats.reached == 0 {
+                        0.0
+                    } else {
+                        stats.total_wait_sec as f64 / stats.reached as f64
+                    },
+                    max_wait_sec: stats.max_wait_sec,
+                }
+            })
+            .collect();
+        Funnel { stages }
+    }
+}
+
+/// Serve the queue funnel summary.
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn handler(Extension(analytics): Extension<QueueAnalytics>) -> Json<Funnel> {
+    Json(analytics.funnel())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time before unix epoch")
+        .as_secs()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test::TestDir;
+
+    #[test]
+    fn test_funnel() {
+        let dir = TestDir::new("analytics");
+        fs::create_dir_all(&dir).unwrap();
+
+        let analytics = QueueAnalytics::open(&dir).unwrap();
+        for identity in ["a", "b", "c"] {
+            analytics.record_at(identity, Stage::Joined, 100);
+        }
+        analytics.record_at("a", Stage::ReachedFront, 110);
+        analytics.record_at("b", Stage::ReachedFront, 130);
+        analytics.drop_out("c");
+        analytics.record_at("a", Stage::Started, 111);
+        analytics.record_at("a", Stage::Completed, 171);
+        analytics.persist().unwrap();
+
+        // Reload from disk.
+        let funnel = QueueAnalytics::open(&dir).unwrap().funnel();
+        let stage = |stage| {
+            funnel
+                .stages
+                .iter()
+                .find(|s| s.stage == stage)
+                .unwrap()
+                .clone()
+        };
+        assert_eq!(stage(Stage::Joined).reached, 3);
+        assert_eq!(stage(Stage::Joined).dropped, 1);
+        assert_eq!(stage(Stage::ReachedFront).reached, 2);
+        assert_eq!(stage(Stage::ReachedFront).in_progress, 1);
+        assert!((stage(Stage::ReachedFront).avg_wait_sec - 20.0).abs() < f64::EPSILON);
+        assert_eq!(stage(Stage::ReachedFront).max_wait_sec, 30);
+        assert_eq!(stage(Stage::Completed).reached, 1);
+        assert_eq!(stage(Stage::Completed).max_wait_sec, 60);
+        assert!((stage(Stage::Completed).conversion - 1.0 / 3.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,245 @@
+//! Public attestation of transcript checkpoints on Ethereum.
+//!
+//! When configured, the coordinator periodically publishes the latest
+//! [`Checkpoint`] by calling
+//!
+//! ```text
+//! attest(uint256 index, bytes32 transcriptHash, bytes32 chainHash)
+//! ```
+//!
+//! on a contract. The transactions form a public, timestamped audit trail:
+//! anyone can check that the transcript they downloaded matches a hash the
+//! coordinator committed to at a given block. Only checkpoints newer than the
+//! last attested one are published, failed transactions are retried at the
+//! next interval.
+//!
+//! The RPC endpoint must be plain HTTP, e.g. a local node.
+
+use crate::{
+    checkpoint::{Checkpoint, HashChain},
+    Options,
+};
+use ethers::{
+    abi::{encode, Token},
+    middleware::SignerMiddleware,
+    providers::{Http, Middleware, Provider},
+    signers::{LocalWallet, Signer},
+    types::{Address, Bytes, TransactionRequest, H256, U256},
+    utils::id,
+};
+use eyre::{eyre, Error as EyreError, Result as EyreResult};
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter, register_int_gauge, IntCounter, IntGauge};
+use std::{fmt, str::FromStr, time::Duration};
+use tokio::time::interval;
+use tracing::{info, instrument, warn};
+use url::Url;
+
+/// Signature of the contract function called with every checkpoint.
+pub const ATTEST_SIGNATURE: &str = "attest(uint256,bytes32,bytes32)";
+
+static ATTESTED_INDEX: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "attested_contributions",
+        "Number of contributions in the latest checkpoint attested on Ethereum."
+    )
+    .unwrap()
+});
+
+static ATTESTATION_FAILURES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "attestation_failures_total",
+        "Number of failed attestation transactions."
+    )
+    .unwrap()
+});
+
+/// Private key of the account sending attestations, hex encoded.
+#[derive(Clone)]
+pub struct AttestationKey(LocalWallet);
+
+/// Where and how often to attest checkpoints.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub rpc:      Url,
+    pub contract: Address,
+    pub key:      AttestationKey,
+    pub interval: Duration,
+}
+
+type Client = SignerMiddleware<Provider<Http>, LocalWallet>;
+
+impl Config {
+    /// Attestation settings, if enabled with `--attestation-rpc`.
+    #[must_use]
+    pub fn from_options(options: &Options) -> Option<Self> {
+        Some(Self {
+            rpc:      options.attestation_rpc.clone()?,
+            contract: options.attestation_contract?,
+            key:      options.attestation_key.clone()?,
+            interval: Duration::from_secs(options.attestation_interval),
+        })
+    }
+}
+
+impl AttestationKey {
+    #[must_use]
+    pub fn address(&self) -> Address {
+        self.0.address()
+    }
+}
+
+impl PartialEq for AttestationKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.address() == other.address()
+    }
+}
+
+impl fmt::Debug for AttestationKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AttestationKey({:?})", self.address())
+    }
+}
+
+impl FromStr for AttestationKey {
+    type Err = EyreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let wallet = s.strip_prefix("0x").unwrap_or(s).parse::<LocalWallet>()?;
+        Ok(Self(wallet))
+    }
+}
+
+/// Calldata for attesting `checkpoint`.
+#[must_use]
+pub fn calldata(checkpoint: &Checkpoint) -> Bytes {
+    let mut data = id(ATTEST_SIGNATURE).to_vec();
+    data.extend(encode(&[
+        Token::Uint(U256::from(checkpoint.index)),
+        Token::FixedBytes(checkpoint.transcript_hash.to_vec()),
+        Token::FixedBytes(checkpoint.chain_hash.to_vec()),
+    ]));
+    data.into()
+}
+
+/// Start attesting checkpoints in the background.
+pub fn spawn(config: Option<Config>, chain: HashChain) {
+    if let Some(config) = config {
+        info!(
+            contract = ?config.contract,
+            sender = ?config.key.address(),
+            "Attesting checkpoints on Ethereum"
+        );
+        tokio::spawn(run(config, chain));
+    }
+}
+
+async fn run(config: Config, chain: HashChain) {
+    let mut client = None;
+    let mut attested = None;
+    let mut ticks = interval(config.interval);
+    loop {
+        ticks.tick().await;
+        let checkpoint = match chain.latest() {
+            Some(checkpoint) if attested.map_or(true, |index| index < checkpoint.index) => {
+                checkpoint
+            }
+            _ => continue,
+        };
+        if client.is_none() {
+            match connect(&config).await {
+                Ok(connected) => client = Some(connected),
+                Err(error) => {
+                    warn!(?error, "Could not connect to Ethereum node");
+                    ATTESTATION_FAILURES.inc();
+                    continue;
+                }
+            }
+        }
+        let client = client.as_ref().expect("Client is connected");
+        match attest(client, config.contract, &checkpoint).await {
+            Ok(transaction) => {
+                info!(
+                    index = checkpoint.index,
+                    ?transaction,
+                    "Attested checkpoint"
+                );
+                attested = Some(checkpoint.index);
+                ATTESTED_INDEX.set(i64::try_from(checkpoint.index).unwrap_or(i64::MAX));
+            }
+            Err(error) => {
+                warn!(index = checkpoint.index, ?error, "Attestation failed");
+                ATTESTATION_FAILURES.inc();
+            }
+        }
+    }
+}
+
+async fn connect(config: &Config) -> EyreResult<Client> {
+    let provider = Provider::<Http>::try_from(config.rpc.as_str())?;
+    Ok(SignerMiddleware::new_with_provider_chain(provider, config.key.0.clone()).await?)
+}
+
+/// Send the attestation and wait for it to be mined.
+#[instrument(level = "info", skip(client))]
+async fn attest(client: &Client, contract: Address, checkpoint: &Checkpoint) -> EyreResult<H256> {
+    let request = TransactionRequest::new()
+        .to(contract)
+        .data(calldata(checkpoint));
+    let pending = client.send_transaction(request, None).await?;
+    let hash = pending.tx_hash();
+    let receipt = pending
+        .await?
+        .ok_or_else(|| eyre!("Transaction {:?} was dropped", hash))?;
+    if receipt.status != Some(1.into()) {
+        return Err(eyre!("Transaction {:?} reverted", hash));
+    }
+    Ok(hash)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_calldata() {
+        let checkpoint = Checkpoint {
+            index:           3,
+            transcript_hash: [1; 32],
+            chain_hash:      [2; 32],
+        };
+        let data = calldata(&checkpoint);
+        assert_eq!(data.len(), 4 + 3 * 32);
+        assert_eq!(&data[..4], &id(ATTEST_SIGNATURE));
+        assert_eq!(data[4 + 31], 3);
+        assert_eq!(&data[36..68], &[1; 32]);
+        assert_eq!(&data[68..100], &[2; 32]);
+    }
+
+    #[test]
+    fn test_options() {
+        use clap::Parser;
+        let options = Options::parse_from(["coordinator"]);
+        assert!(Config::from_options(&options).is_none());
+        let rpc = ["coordinator", "--attestation-rpc", "http://localhost:8545"];
+        assert!(Options::try_parse_from(rpc).is_err());
+        let contract = format!("0x{}", "00".repeat(20));
+        let key = "01".repeat(32);
+        let options = Options::parse_from(
+            rpc.into_iter()
+                .chain(["--attestation-contract", &contract])
+                .chain(["--attestation-key", &key]),
+        );
+        let config = Config::from_options(&options).unwrap();
+        assert_eq!(config.interval, Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn test_key() {
+        let key: AttestationKey = format!("0x{}", "01".repeat(32)).parse().unwrap();
+        assert_eq!(key, "01".repeat(32).parse().unwrap());
+        assert!(format!("{key:?}").starts_with("AttestationKey(0x"));
+        assert!(!format!("{key:?}").contains(&"01".repeat(32)));
+        assert!("0x1234".parse::<AttestationKey>().is_err());
+    }
+}
@@ -0,0 +1,352 @@
+//! Append-only audit log.
+//!
+//! Every login, queue change and contribution outcome is appended as one JSON
+//! object per line to `audit.jsonl` in the transcript directory, with the
+//! unix time and the identity involved. Accepted contributions also record
+//! the transcript hashes before and after, so the log can be checked against
+//! the receipts and the hash chain after the ceremony.
+//!
+//! Entries are written as they happen, but synced to disk in batches every
+//! [`SYNC_INTERVAL`] by a background task, so callers holding the queue lock
+//! do not wait for the disk. An operating system crash may lose the entries
+//! of the last interval.
+//!
+//! A login is the first request with a new session token, as tokens may be
+//! issued by a sign-in flow outside the coordinator.
+//!
+//! `GET /admin/audit` exports the log as JSON lines, optionally only the
+//! entries at or after `?since={unix time}`.
+
+use crate::{admin::Admin, session::Session};
+use axum::{
+    extract::Query,
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use eyre::{Report as EyreReport, Result as EyreResult, WrapErr};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{task::spawn_blocking, time::interval};
+use tracing::{error, info};
+
+const FILE: &str = "audit.jsonl";
+
+/// Interval between syncs of new entries to disk.
+pub const SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum AuditEvent {
+    /// First request with a new session token.
+    #[serde(rename_all = "camelCase")]
+    Login { identity: String, expires: u64 },
+    /// The tier is recorded if queue tiers are configured.
+    #[serde(rename_all = "camelCase")]
+    QueueJoined {
+        identity: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tier:     Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    QueueLeft { identity: String },
+    #[serde(rename_all = "camelCase")]
+    ContributionStarted {
+        identity: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tier:     Option<String>,
+    },
+    /// Hashes are `0x` prefixed hex, as in receipts.
+    #[serde(rename_all = "camelCase")]
+    ContributionCompleted {
+        identity:          String,
+        index:             usize,
+        transcript_before: String,
+        transcript_after:  String,
+        upload_hash:       String,
+    },
+    #[serde(rename_all = "camelCase")]
+    ContributionRejected { identity: String, error: String },
+    #[serde(rename_all = "camelCase")]
+    ContributionAborted { identity: String },
+    /// Removed from the active slot for `reason`, `deadline` or `operator`.
+    #[serde(rename_all = "camelCase")]
+    ParticipantEvicted { identity: String, reason: String },
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Seconds since the unix epoch.
+    pub time:  u64,
+    #[serde(flatten)]
+    pub event: AuditEvent,
+}
+
+#[derive(Debug)]
+pub struct AuditLog {
+    path:     PathBuf,
+    state:    Mutex<State>,
+    /// Handle to the same file for syncing without holding the lock.
+    sync:     File,
+    /// Whether entries were written since the last sync.
+    unsynced: AtomicBool,
+}
+
+#[derive(Debug)]
+struct State {
+    file:     File,
+    /// Unexpired sessions already logged in, by identity and expiry.
+    sessions: HashSet<(String, u64)>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Deserialize)]
+pub struct ExportQuery {
+    since: Option<u64>,
+}
+
+impl AuditLog {
+    /// Open the log in `dir` for appending.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the log file can not be opened.
+    pub fn open(dir: &Path) -> EyreResult<Self> {
+        let path = dir.join(FILE);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .wrap_err_with(|| format!("Could not open audit log {}", path.display()))?;
+        info!("Appending audit log to {}", path.display());
+        Ok(Self {
+            path,
+            sync: file.try_clone()?,
+            state: Mutex::new(State {
+                file,
+                sessions: HashSet::new(),
+            }),
+            unsynced: AtomicBool::new(false),
+        })
+    }
+
+    /// Append `event`.
+    ///
+    /// The ceremony goes on if the entry can not be written, the failure is
+    /// logged as an error.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the log lock is poisoned.
+    pub fn record(&self, event: AuditEvent) {
+        let entry = AuditEntry { time: now(), event };
+        let mut state = self.state.lock().unwrap();
+        self.append(&mut state.file, &entry);
+    }
+
+    /// Record a login if `session` was not seen before.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the log lock is poisoned.
+    pub fn login(&self, session: &Session) {
+        let now = now();
+        let mut state = self.state.lock().unwrap();
+        let key = (session.pseudonym.clone(), session.expires);
+        if state.sessions.contains(&key) {
+            return;
+        }
+        state.sessions.retain(|(_, expires)| *expires > now);
+        state.sessions.insert(key);
+        let entry = AuditEntry {
+            time:  now,
+            event: AuditEvent::Login {
+                identity: session.pseudonym.clone(),
+                expires:  session.expires,
+            },
+        };
+        self.append(&mut state.file, &entry);
+    }
+
+    /// Sync the entries written since the last sync to disk.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can not be synced. It is retried on the
+    /// next call.
+    pub fn sync(&self) -> EyreResult<()> {
+        if self.unsynced.swap(false, Ordering::AcqRel) {
+            if let Err(error) = self.sync.sync_data() {
+                self.unsynced.store(true, Ordering::Release);
+                return Err(error.into());
+            }
+        }
+        Ok(())
+    }
+
+    fn append(&self, file: &mut File, entry: &AuditEntry) {
+        let result = serde_json::to_string(entry)
+            .map_err(EyreReport::from)
+            .and_then(|line| writeln!(file, "{line}").map_err(Into::into));
+        match result {
+            Ok(()) => self.unsynced.store(true, Ordering::Release),
+            Err(error) => error!(?error, ?entry, "Could not write audit log entry"),
+        }
+    }
+
+    /// All entries at or after `since`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the log can not be read or has a malformed entry.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the log lock is poisoned.
+    pub fn entries(&self, since: u64) -> EyreResult<Vec<AuditEntry>> {
+        // Hold the lock so no entry is half written.
+        let _state = self.state.lock().unwrap();
+        let entries = fs::read_to_string(&self.path)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<AuditEntry>, _>>()?;
+        Ok(entries
+            .into_iter()
+            .filter(|entry| entry.time >= since)
+            .collect())
+    }
+}
+
+impl Drop for AuditLog {
+    fn drop(&mut self) {
+        if let Err(error) = self.sync() {
+            error!(?error, "Could not sync audit log");
+        }
+    }
+}
+
+/// Sync the audit log to disk every [`SYNC_INTERVAL`].
+///
+/// # Panics
+///
+/// The task panics if a sync panicked.
+pub fn spawn(audit: Arc<AuditLog>) {
+    tokio::spawn(async move {
+        let mut ticks = interval(SYNC_INTERVAL);
+        loop {
+            ticks.tick().await;
+            let audit = audit.clone();
+            if let Err(error) = spawn_blocking(move || audit.sync())
+                .await
+                .expect("Sync task panicked")
+            {
+                error!(?error, "Could not sync audit log");
+            }
+        }
+    });
+}
+
+/// Export the audit log as JSON lines.
+///
+/// # Panics
+///
+/// Panics if an entry fails to serialize, which they do not.
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn export(
+    _admin: Admin,
+    Query(query): Query<ExportQuery>,
+    Extension(audit): Extension<Arc<AuditLog>>,
+) -> Response {
+    let entries = match audit.entries(query.since.unwrap_or_default()) {
+        Ok(entries) => entries,
+        Err(error) => {
+            error!(?error, "Could not read audit log");
+            let body = Json(json!({ "error": "Could not read audit log" }));
+            return (StatusCode::INTERNAL_SERVER_ERROR, body).into_response();
+        }
+    };
+    let mut body = String::new();
+    for entry in &entries {
+        body.push_str(&serde_json::to_string(entry).expect("Entries serialize"));
+        body.push('\n');
+    }
+    ([(CONTENT_TYPE, "application/x-ndjson")], body).into_response()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time before unix epoch")
+        .as_secs()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{session::Attributes, test::TestDir};
+
+    #[test]
+    fn test_audit_log() {
+        let dir = TestDir::new("audit");
+        fs::create_dir_all(&dir).unwrap();
+
+        let audit = AuditLog::open(&dir).unwrap();
+        let session = Session {
+            identity:   "git|1|recmo".to_string(),
+            expires:    now() + 60,
+            issued:     now(),
+            key_id:     None,
+            attributes: Attributes::default(),
+            pseudonym:  "git|1|recmo".to_string(),
+        };
+        audit.login(&session);
+        audit.login(&session);
+        audit.record(AuditEvent::QueueJoined {
+            identity: session.identity.clone(),
+            tier:     None,
+        });
+        audit.sync().unwrap();
+        assert!(!audit.unsynced.load(Ordering::Acquire));
+        drop(audit);
+
+        // Entries are appended across restarts.
+        let audit = AuditLog::open(&dir).unwrap();
+        audit.record(AuditEvent::ContributionAborted {
+            identity: session.identity.clone(),
+        });
+        let events = audit
+            .entries(0)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.event)
+            .collect::<Vec<_>>();
+        assert_eq!(events, vec![
+            AuditEvent::Login {
+                identity: session.identity.clone(),
+                expires:  session.expires,
+            },
+            AuditEvent::QueueJoined {
+                identity: session.identity.clone(),
+                tier:     None,
+            },
+            AuditEvent::ContributionAborted {
+                identity: session.identity,
+            },
+        ]);
+        assert!(audit.entries(now() + 1).unwrap().is_empty());
+
+        let line = fs::read_to_string(dir.join(FILE)).unwrap();
+        assert!(line.starts_with(r#"{"time":"#));
+        assert!(line.contains(r#""type":"login","identity":"git|1|recmo""#));
+    }
+}
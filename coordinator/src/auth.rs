@@ -0,0 +1,714 @@
+//! Sign-in with identity providers.
+//!
+//! Participants sign in with `POST /login/{provider}`, where the body holds
+//! the credentials of the provider. On success the coordinator issues a
+//! session token, see [`crate::session`]:
+//!
+//! ```json
+//! { "identity": "git|1234|recmo", "token": "..." }
+//! ```
+//!
+//! Providers implement [`AuthProvider`] and are enabled with
+//! `--auth-providers`, e.g. `--auth-providers github,siwe`:
+//!
+//! * `github` exchanges the `{"code": ...}` of the GitHub OAuth flow for the
+//!   `git|{id}|{login}` identity and the account creation time.
+//! * `siwe` checks a Sign-In with Ethereum (EIP-4361) message `{"message": ...,
+//!   "signature": ...}` for the `eth|{address}` identity and, with
+//!   `--siwe-rpc`, looks up the account nonce. The message nonce must come from
+//!   `GET /login/siwe/nonce` on the same coordinator less than ten minutes ago,
+//!   and a signer can use it once.
+//! * `mock` signs in as any identity, only in test ceremonies, see
+//!   [`crate::test_ceremony`].
+//!
+//! Without providers tokens are issued by a sign-in flow outside the
+//! coordinator sharing the session key.
+
+use crate::{
+    session::{Attributes, SharedSessionKeys},
+    signing::{self, Address},
+    test_ceremony, Options,
+};
+use axum::{
+    async_trait,
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use eyre::{bail, eyre, Result as EyreResult};
+use hyper::{
+    body::to_bytes,
+    client::HttpConnector,
+    header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, USER_AGENT},
+    Body, Client, Method, Request,
+};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use rand::Rng;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+use tracing::{info, warn};
+use url::{form_urlencoded, Url};
+
+/// Lifetime of the session tokens issued on sign-in.
+pub const TOKEN_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+const GITHUB_TOKEN_URL: &str = "https://github.com/login/oauth/access_token";
+const GITHUB_USER_URL: &str = "https://api.github.com/user";
+
+/// How long a signed SIWE message can be used to sign in.
+const SIWE_MAX_AGE: i64 = 10 * 60;
+
+const SIWE_HEADER: &str = " wants you to sign in with your Ethereum account:";
+
+/// Maximum number of unexpired SIWE nonces handed out.
+const SIWE_MAX_NONCES: usize = 100_000;
+
+/// HTTP client that also speaks HTTPS.
+pub type HttpsClient = Client<HttpsConnector<HttpConnector>>;
+
+#[derive(Clone, PartialEq, Eq, Debug, Error)]
+pub enum AuthError {
+    #[error("Unknown sign-in provider {0}")]
+    UnknownProvider(String),
+    #[error("Malformed sign-in credentials")]
+    InvalidCredentials,
+    #[error("Sign-in rejected: {0}")]
+    Rejected(&'static str),
+    #[error("Sign-in provider unavailable")]
+    Unavailable,
+    #[error("Sign-in provider {0} takes no nonces")]
+    NoNonce(&'static str),
+}
+
+/// A participant as established by a provider.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Identity {
+    /// E.g. `git|1234|recmo` or `eth|0x..`.
+    pub identity:   String,
+    pub attributes: Attributes,
+}
+
+/// An identity provider participants can sign in with.
+#[async_trait]
+pub trait AuthProvider: fmt::Debug + Send + Sync {
+    /// Name in `POST /login/{name}` and `--auth-providers`.
+    fn name(&self) -> &'static str;
+
+    /// Establish the identity behind the provider specific `credentials`.
+    async fn authenticate(&self, credentials: Value) -> Result<Identity, AuthError>;
+
+    /// Issue a nonce for the credentials of a following sign-in.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AuthError::NoNonce`] unless the provider takes nonces.
+    fn nonce(&self) -> Result<String, AuthError> {
+        Err(AuthError::NoNonce(self.name()))
+    }
+}
+
+/// The enabled providers by name.
+#[derive(Debug, Default)]
+pub struct AuthProviders(HashMap<&'static str, Arc<dyn AuthProvider>>);
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct NonceResponse {
+    pub nonce: String,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct LoginResponse {
+    pub identity: String,
+    pub token:    String,
+}
+
+/// Sign-in with GitHub OAuth.
+#[derive(Clone)]
+pub struct GitHub {
+    client_id:     String,
+    client_secret: String,
+    client:        HttpsClient,
+}
+
+/// Sign-In with Ethereum.
+#[derive(Debug)]
+pub struct Siwe {
+    /// Domain the messages must be addressed to.
+    domain: String,
+    /// JSON-RPC endpoint to look up account nonces.
+    rpc:    Option<Url>,
+    client: HttpsClient,
+    nonces: Mutex<SiweNonces>,
+}
+
+/// Message nonces of [`Siwe`], each with the unix time it expires at.
+#[derive(Debug, Default)]
+struct SiweNonces {
+    issued: HashMap<String, i64>,
+    used:   HashMap<(Address, String), i64>,
+}
+
+#[derive(Deserialize)]
+struct GitHubCredentials {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct SiweCredentials {
+    message:   String,
+    signature: String,
+}
+
+impl AuthError {
+    /// Stable error code for clients.
+    #[must_use]
+    pub const fn code(&self) -> &'static str {
+        match self {
+            Self::UnknownProvider(_) => "unknown_provider",
+            Self::InvalidCredentials => "invalid_credentials",
+            Self::Rejected(_) => "rejected",
+            Self::Unavailable => "provider_unavailable",
+            Self::NoNonce(_) => "no_nonce",
+        }
+    }
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::UnknownProvider(_) | Self::NoNonce(_) => StatusCode::NOT_FOUND,
+            Self::InvalidCredentials => StatusCode::BAD_REQUEST,
+            Self::Rejected(_) => StatusCode::UNAUTHORIZED,
+            Self::Unavailable => StatusCode::BAD_GATEWAY,
+        };
+        let body = Json(json!({ "error": self.to_string(), "code": self.code() }));
+        (status, body).into_response()
+    }
+}
+
+impl AuthProviders {
+    /// The providers of `--auth-providers`, and the mock provider in test
+    /// ceremonies.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for unknown providers and providers missing their
+    /// options.
+    pub fn from_options(options: &Options) -> EyreResult<Self> {
+        let mut providers = Self::default();
+        if options.test_ceremony {
+            providers = providers.with(test_ceremony::Mock::default());
+        }
+        for name in &options.auth_providers {
+            providers = match name.as_str() {
+                "github" => providers.with(GitHub::from_options(options)?),
+                "siwe" => providers.with(Siwe::from_options(options)?),
+                "mock" if options.test_ceremony => providers,
+                "mock" => bail!("The mock sign-in provider requires --test-ceremony"),
+                name => bail!("Unknown sign-in provider {}", name),
+            };
+        }
+        if !providers.0.is_empty() {
+            info!(providers = ?providers.0.keys().collect::<Vec<_>>(), "Sign-in enabled");
+        }
+        Ok(providers)
+    }
+
+    #[must_use]
+    pub fn with(mut self, provider: impl AuthProvider + 'static) -> Self {
+        self.0.insert(provider.name(), Arc::new(provider));
+        self
+    }
+
+    /// Authenticate with the provider `name` and issue a session token.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AuthError::UnknownProvider`] if `name` is not enabled, or the
+    /// error of the provider if it rejects `credentials`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the session key lock is poisoned.
+    pub async fn login(
+        &self,
+        key: &SharedSessionKeys,
+        name: &str,
+        credentials: Value,
+    ) -> Result<LoginResponse, AuthError> {
+        let provider = self
+            .0
+            .get(name)
+            .ok_or_else(|| AuthError::UnknownProvider(name.to_string()))?;
+        let Identity {
+            identity,
+            attributes,
+        } = provider.authenticate(credentials).await?;
+        // Not the identity, only pseudonyms are logged, see `crate::pseudonym`.
+        info!(provider = name, "Signed in");
+        let token = key
+            .read()
+            .unwrap()
+            .issue_with(&identity, TOKEN_LIFETIME, attributes);
+        Ok(LoginResponse { identity, token })
+    }
+
+    /// Issue a nonce for a sign-in with the provider `name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AuthError::UnknownProvider`] if `name` is not enabled and
+    /// [`AuthError::NoNonce`] if it takes no nonces.
+    pub fn nonce(&self, name: &str) -> Result<String, AuthError> {
+        self.0
+            .get(name)
+            .ok_or_else(|| AuthError::UnknownProvider(name.to_string()))?
+            .nonce()
+    }
+}
+
+impl GitHub {
+    /// Sign-in with GitHub using the OAuth app of `--github-client-id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the client id or secret is missing.
+    pub fn from_options(options: &Options) -> EyreResult<Self> {
+        let (client_id, client_secret) =
+            match (&options.github_client_id, &options.github_client_secret) {
+                (Some(client_id), Some(client_secret)) => (client_id, client_secret),
+                _ => bail!(
+                    "Sign-in with GitHub requires --github-client-id and --github-client-secret"
+                ),
+            };
+        Ok(Self {
+            client_id:     client_id.clone(),
+            client_secret: client_secret.clone(),
+            client:        https_client(),
+        })
+    }
+
+    async fn access_token(&self, code: &str) -> EyreResult<Option<String>> {
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            access_token: Option<String>,
+        }
+        let form = form_urlencoded::Serializer::new(String::new())
+            .append_pair("client_id", &self.client_id)
+            .append_pair("client_secret", &self.client_secret)
+            .append_pair("code", code)
+            .finish();
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(GITHUB_TOKEN_URL)
+            .header(ACCEPT, "application/json")
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(form))?;
+        // Invalid codes are reported in the body, without an access token.
+        let response: TokenResponse = request_json(&self.client, request).await?;
+        Ok(response.access_token)
+    }
+
+    async fn user(&self, token: &str) -> EyreResult<Identity> {
+        #[derive(Deserialize)]
+        struct User {
+            id:         u64,
+            login:      String,
+            created_at: String,
+        }
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(GITHUB_USER_URL)
+            .header(ACCEPT, "application/vnd.github+json")
+            .header(AUTHORIZATION, format!("Bearer {token}"))
+            .header(USER_AGENT, "kzg-ceremony-coordinator")
+            .body(Body::empty())?;
+        let user: User = request_json(&self.client, request).await?;
+        Ok(Identity {
+            identity:   format!("git|{}|{}", user.id, user.login),
+            attributes: Attributes {
+                account_created: Some(unix_time(&user.created_at)?.try_into()?),
+                nonce:           None,
+            },
+        })
+    }
+}
+
+impl fmt::Debug for GitHub {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GitHub")
+            .field("client_id", &self.client_id)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl AuthProvider for GitHub {
+    fn name(&self) -> &'static str {
+        "github"
+    }
+
+    async fn authenticate(&self, credentials: Value) -> Result<Identity, AuthError> {
+        let GitHubCredentials { code } =
+            serde_json::from_value(credentials).map_err(|_| AuthError::InvalidCredentials)?;
+        let token = self
+            .access_token(&code)
+            .await
+            .map_err(unavailable)?
+            .ok_or(AuthError::Rejected("invalid authorization code"))?;
+        self.user(&token).await.map_err(unavailable)
+    }
+}
+
+impl Siwe {
+    /// Sign-in with Ethereum for `--siwe-domain`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the domain is missing.
+    pub fn from_options(options: &Options) -> EyreResult<Self> {
+        let domain = match options.siwe_domain.clone() {
+            Some(domain) => domain,
+            None => bail!("Sign-in with Ethereum requires --siwe-domain"),
+        };
+        Ok(Self::new(domain, options.siwe_rpc.clone()))
+    }
+
+    #[must_use]
+    pub fn new(domain: String, rpc: Option<Url>) -> Self {
+        Self {
+            domain,
+            rpc,
+            client: https_client(),
+            nonces: Mutex::new(SiweNonces::default()),
+        }
+    }
+
+    /// Hand out a fresh message nonce at unix time `now`.
+    fn issue_nonce(&self, now: i64) -> Result<String, AuthError> {
+        let mut nonces = self.nonces.lock().unwrap();
+        nonces.expire(now);
+        if nonces.issued.len() >= SIWE_MAX_NONCES {
+            warn!("Too many sign-in nonces outstanding");
+            return Err(AuthError::Unavailable);
+        }
+        let nonce = hex::encode(rand::thread_rng().gen::<[u8; 16]>());
+        nonces.issued.insert(nonce.clone(), now + SIWE_MAX_AGE);
+        drop(nonces);
+        Ok(nonce)
+    }
+
+    /// Check the SIWE `message` and its `signature` at unix time `now`, and
+    /// return the signer.
+    fn verify(&self, message: &str, signature: &str, now: i64) -> Result<Address, AuthError> {
+        let mut lines = message.lines();
+        let domain = lines
+            .next()
+            .and_then(|line| line.strip_suffix(SIWE_HEADER))
+            .ok_or(AuthError::InvalidCredentials)?;
+        if domain != self.domain {
+            return Err(AuthError::Rejected("message for another domain"));
+        }
+        let address: Address = lines
+            .next()
+            .and_then(|line| line.parse().ok())
+            .ok_or(AuthError::InvalidCredentials)?;
+        let fields = lines
+            .filter_map(|line| line.split_once(": "))
+            .collect::<HashMap<_, _>>();
+        let time = |field| {
+            fields
+                .get(field)
+                .map(|value| unix_time(value).map_err(|_| AuthError::InvalidCredentials))
+                .transpose()
+        };
+        let issued = time("Issued At")?.ok_or(AuthError::InvalidCredentials)?;
+        let nonce = *fields.get("Nonce").ok_or(AuthError::InvalidCredentials)?;
+        if issued > now + 60 || issued + SIWE_MAX_AGE < now {
+            return Err(AuthError::Rejected("message is stale"));
+        }
+        if time("Expiration Time")?.map_or(false, |expires| expires <= now) {
+            return Err(AuthError::Rejected("message expired"));
+        }
+        if time("Not Before")?.map_or(false, |not_before| not_before > now) {
+            return Err(AuthError::Rejected("message not yet valid"));
+        }
+        signing::verify(message.as_bytes(), signature, address)
+            .map_err(|_| AuthError::Rejected("invalid signature"))?;
+        let mut nonces = self.nonces.lock().unwrap();
+        nonces.expire(now);
+        let expires = *nonces
+            .issued
+            .get(nonce)
+            .ok_or(AuthError::Rejected("nonce not issued or expired"))?;
+        if nonces
+            .used
+            .insert((address, nonce.to_string()), expires)
+            .is_some()
+        {
+            return Err(AuthError::Rejected("message already used"));
+        }
+        drop(nonces);
+        Ok(address)
+    }
+
+    async fn nonce(&self, rpc: &Url, address: Address) -> EyreResult<u64> {
+        #[derive(Deserialize)]
+        struct RpcResponse {
+            result: Option<String>,
+        }
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "eth_getTransactionCount",
+            "params": [address.to_string(), "latest"],
+        });
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(rpc.as_str())
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_vec(&body)?))?;
+        let response: RpcResponse = request_json(&self.client, request).await?;
+        let result = response
+            .result
+            .ok_or_else(|| eyre!("eth_getTransactionCount failed"))?;
+        Ok(u64::from_str_radix(
+            result.strip_prefix("0x").unwrap_or(&result),
+            16,
+        )?)
+    }
+}
+
+impl SiweNonces {
+    /// Forget the nonces expired at unix time `now`.
+    fn expire(&mut self, now: i64) {
+        self.issued.retain(|_, expires| *expires > now);
+        self.used.retain(|_, expires| *expires > now);
+    }
+}
+
+#[async_trait]
+impl AuthProvider for Siwe {
+    fn name(&self) -> &'static str {
+        "siwe"
+    }
+
+    fn nonce(&self) -> Result<String, AuthError> {
+        self.issue_nonce(now())
+    }
+
+    async fn authenticate(&self, credentials: Value) -> Result<Identity, AuthError> {
+        let SiweCredentials { message, signature } =
+            serde_json::from_value(credentials).map_err(|_| AuthError::InvalidCredentials)?;
+        let address = self.verify(&message, &signature, now())?;
+        let nonce = match &self.rpc {
+            Some(rpc) => Some(self.nonce(rpc, address).await.map_err(unavailable)?),
+            None => None,
+        };
+        Ok(Identity {
+            identity:   format!("eth|{address}"),
+            attributes: Attributes {
+                account_created: None,
+                nonce,
+            },
+        })
+    }
+}
+
+/// Sign in with a provider.
+///
+/// # Errors
+///
+/// Returns an [`AuthError`] if the sign-in fails.
+pub async fn login(
+    Path(provider): Path<String>,
+    Extension(key): Extension<SharedSessionKeys>,
+    Extension(providers): Extension<Arc<AuthProviders>>,
+    Json(credentials): Json<Value>,
+) -> Result<Json<LoginResponse>, AuthError> {
+    providers
+        .login(&key, &provider, credentials)
+        .await
+        .map(Json)
+}
+
+/// Issue a nonce for a sign-in with a provider, e.g. the message nonce of
+/// Sign-In with Ethereum.
+///
+/// # Errors
+///
+/// Returns an [`AuthError`] if the provider is not enabled or takes no nonces.
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn nonce(
+    Path(provider): Path<String>,
+    Extension(providers): Extension<Arc<AuthProviders>>,
+) -> Result<Json<NonceResponse>, AuthError> {
+    let nonce = providers.nonce(&provider)?;
+    Ok(Json(NonceResponse { nonce }))
+}
+
+#[must_use]
+pub fn https_client() -> HttpsClient {
+    let connector = HttpsConnectorBuilder::new()
+        .with_webpki_roots()
+        .https_or_http()
+        .enable_http1()
+        .build();
+    Client::builder().build(connector)
+}
+
+async fn request_json<T: DeserializeOwned>(
+    client: &HttpsClient,
+    request: Request<Body>,
+) -> EyreResult<T> {
+    let response = client.request(request).await?;
+    if !response.status().is_success() {
+        bail!("Responded with {}", response.status());
+    }
+    Ok(serde_json::from_slice(
+        &to_bytes(response.into_body()).await?,
+    )?)
+}
+
+#[allow(clippy::needless_pass_by_value)] // Used in `map_err`
+fn unavailable(error: eyre::Report) -> AuthError {
+    warn!(?error, "Sign-in provider failed");
+    AuthError::Unavailable
+}
+
+fn unix_time(rfc3339: &str) -> EyreResult<i64> {
+    Ok(chrono::DateTime::parse_from_rfc3339(rfc3339)?.timestamp())
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time before unix epoch")
+        .as_secs()
+        .try_into()
+        .expect("Unix time fits i64")
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{
+        session::{SessionKey, SessionKeys},
+        signing::CoordinatorKey,
+    };
+
+    fn siwe_message(address: Address, nonce: &str, issued: &str) -> String {
+        format!(
+            "ceremony.ethereum.org wants you to sign in with your Ethereum account:\n{address}\n\n\
+             Sign in to the KZG ceremony\n\nURI: https://ceremony.ethereum.org\nVersion: 1\n\
+             Chain ID: 1\nNonce: {nonce}\nIssued At: {issued}"
+        )
+    }
+
+    #[test]
+    fn test_siwe() {
+        let siwe = Siwe::new("ceremony.ethereum.org".to_string(), None);
+        let wallet = CoordinatorKey::random();
+        let now = unix_time("2022-12-01T12:00:00Z").unwrap();
+        let signed = |message: &str| (message.to_string(), wallet.sign(message.as_bytes()));
+
+        let nonce = siwe.issue_nonce(now).unwrap();
+        let (message, signature) = signed(&siwe_message(
+            wallet.address(),
+            &nonce,
+            "2022-12-01T11:59:00Z",
+        ));
+        assert_eq!(siwe.verify(&message, &signature, now), Ok(wallet.address()));
+        assert_eq!(
+            siwe.verify(&message, &signature, now),
+            Err(AuthError::Rejected("message already used"))
+        );
+
+        // Nonces are only accepted from this coordinator, until they expire.
+        let (message, signature) = signed(&siwe_message(
+            wallet.address(),
+            "chosen-by-client",
+            "2022-12-01T11:59:00Z",
+        ));
+        assert_eq!(
+            siwe.verify(&message, &signature, now),
+            Err(AuthError::Rejected("nonce not issued or expired"))
+        );
+        let nonce = siwe.issue_nonce(now - SIWE_MAX_AGE).unwrap();
+        let (message, signature) = signed(&siwe_message(
+            wallet.address(),
+            &nonce,
+            "2022-12-01T11:59:00Z",
+        ));
+        assert_eq!(
+            siwe.verify(&message, &signature, now),
+            Err(AuthError::Rejected("nonce not issued or expired"))
+        );
+
+        let (message, signature) = signed(&siwe_message(
+            wallet.address(),
+            &siwe.issue_nonce(now).unwrap(),
+            "2022-12-01T11:00:00Z",
+        ));
+        assert_eq!(
+            siwe.verify(&message, &signature, now),
+            Err(AuthError::Rejected("message is stale"))
+        );
+
+        let other = CoordinatorKey::random().address();
+        let (message, signature) = signed(&siwe_message(
+            other,
+            &siwe.issue_nonce(now).unwrap(),
+            "2022-12-01T12:00:00Z",
+        ));
+        assert_eq!(
+            siwe.verify(&message, &signature, now),
+            Err(AuthError::Rejected("invalid signature"))
+        );
+
+        let phishing = siwe_message(
+            wallet.address(),
+            &siwe.issue_nonce(now).unwrap(),
+            "2022-12-01T12:00:00Z",
+        )
+        .replace("ceremony.ethereum.org wants", "evil.example wants");
+        let (message, signature) = signed(&phishing);
+        assert_eq!(
+            siwe.verify(&message, &signature, now),
+            Err(AuthError::Rejected("message for another domain"))
+        );
+        assert_eq!(
+            siwe.verify("hello", &signature, now),
+            Err(AuthError::InvalidCredentials)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_providers() {
+        let key: SharedSessionKeys = Arc::new(std::sync::RwLock::new(SessionKeys::new(
+            SessionKey::random(),
+            vec![],
+        )));
+        let providers = AuthProviders::default().with(test_ceremony::Mock::default());
+        let response = providers
+            .login(&key, "mock", json!({ "identity": "git|1|alice" }))
+            .await
+            .unwrap();
+        assert_eq!(response.identity, "git|1|alice");
+        let session = key.read().unwrap().verify(&response.token).unwrap();
+        assert_eq!(session.identity, "git|1|alice");
+        assert_eq!(
+            providers.login(&key, "github", Value::Null).await,
+            Err(AuthError::UnknownProvider("github".to_string()))
+        );
+        assert_eq!(providers.nonce("mock"), Err(AuthError::NoNonce("mock")));
+    }
+}
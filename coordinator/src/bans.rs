@@ -0,0 +1,265 @@
+//! Repeat-offender tracking.
+//!
+//! Aborted contributions, invalid uploads and missed deadlines are counted per
+//! identity. An identity is banned from the queue once any count reaches its
+//! threshold. Counts and bans are persisted next to the transcript so they
+//! survive restarts, and can be inspected and cleared on `/admin/bans`.
+
+use crate::{admin::Admin, storage::write_atomic};
+use axum::{
+    extract::Path as UrlPath,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use eyre::{Report as EyreReport, Result as EyreResult};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tracing::{info, warn};
+
+const FILE: &str = "bans.json";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Offense {
+    Abort,
+    InvalidContribution,
+    DeadlineMiss,
+}
+
+/// Number of offenses of each kind that result in a ban.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Thresholds {
+    pub aborts:                u32,
+    pub invalid_contributions: u32,
+    pub deadline_misses:       u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Record {
+    pub aborts:                u32,
+    pub invalid_contributions: u32,
+    pub deadline_misses:       u32,
+    pub banned:                bool,
+}
+
+#[derive(Debug)]
+pub struct BanList {
+    path:       PathBuf,
+    thresholds: Thresholds,
+    records:    Mutex<HashMap<String, Record>>,
+}
+
+impl Record {
+    const fn count(&self, offense: Offense) -> u32 {
+        match offense {
+            Offense::Abort => self.aborts,
+            Offense::InvalidContribution => self.invalid_contributions,
+            Offense::DeadlineMiss => self.deadline_misses,
+        }
+    }
+
+    const fn count_mut(&mut self, offense: Offense) -> &mut u32 {
+        match offense {
+            Offense::Abort => &mut self.aborts,
+            Offense::InvalidContribution => &mut self.invalid_contributions,
+            Offense::DeadlineMiss => &mut self.deadline_misses,
+        }
+    }
+}
+
+impl Thresholds {
+    const fn get(&self, offense: Offense) -> u32 {
+        match offense {
+            Offense::Abort => self.aborts,
+            Offense::InvalidContribution => self.invalid_contributions,
+            Offense::DeadlineMiss => self.deadline_misses,
+        }
+    }
+}
+
+impl BanList {
+    /// Load the records persisted in `dir`, or start empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the persisted records can not be read or parsed.
+    pub fn open(dir: &Path, thresholds: Thresholds) -> EyreResult<Self> {
+        let path = dir.join(FILE);
+        let records = read_records(&path)?;
+        info!(
+            banned = records.values().filter(|r| r.banned).count(),
+            "Loaded ban list"
+        );
+        Ok(Self {
+            path,
+            thresholds,
+            records: Mutex::new(records),
+        })
+    }
+
+    /// Reload the records persisted by another coordinator sharing the
+    /// directory, see [`crate::leader`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the persisted records can not be read or parsed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the records lock is poisoned.
+    pub fn reload(&self) -> EyreResult<()> {
+        let records = read_records(&self.path)?;
+        *self.records.lock().unwrap() = records;
+        Ok(())
+    }
+
+    /// Count an offense by `identity` and ban them if it reaches the
+    /// threshold. Returns the updated record.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the records lock is poisoned.
+    pub fn record(&self, identity: &str, offense: Offense) -> Record {
+        let mut records = self.records.lock().unwrap();
+        let record = records.entry(identity.to_string()).or_default();
+        *record.count_mut(offense) += 1;
+        if !record.banned && record.count(offense) >= self.thresholds.get(offense) {
+            warn!(identity, ?offense, "Banning repeat offender");
+            record.banned = true;
+        }
+        let record = *record;
+        self.persist(&records);
+        drop(records);
+        record
+    }
+
+    /// Whether `identity` is banned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the records lock is poisoned.
+    #[must_use]
+    pub fn is_banned(&self, identity: &str) -> bool {
+        self.records
+            .lock()
+            .unwrap()
+            .get(identity)
+            .map_or(false, |record| record.banned)
+    }
+
+    /// Records of all banned identities.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the records lock is poisoned.
+    #[must_use]
+    pub fn banned(&self) -> HashMap<String, Record> {
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, record)| record.banned)
+            .map(|(identity, record)| (identity.clone(), *record))
+            .collect()
+    }
+
+    /// Lift the ban on `identity` and reset their counts. Returns `false` if
+    /// they were not banned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the records lock is poisoned.
+    pub fn clear(&self, identity: &str) -> bool {
+        let mut records = self.records.lock().unwrap();
+        let banned = records.get(identity).map_or(false, |record| record.banned);
+        if banned {
+            info!(identity, "Clearing ban");
+            records.remove(identity);
+            self.persist(&records);
+        }
+        drop(records);
+        banned
+    }
+
+    fn persist(&self, records: &HashMap<String, Record>) {
+        // Failing to persist only loses offenses, it must not fail the request.
+        let result = serde_json::to_vec(records)
+            .map_err(EyreReport::from)
+            .and_then(|json| write_atomic(&self.path, &json));
+        if let Err(error) = result {
+            warn!(?error, "Could not persist ban list");
+        }
+    }
+}
+
+fn read_records(path: &Path) -> EyreResult<HashMap<String, Record>> {
+    Ok(if path.exists() {
+        serde_json::from_slice(&fs::read(path)?)?
+    } else {
+        HashMap::new()
+    })
+}
+
+/// List banned identities.
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn list(
+    _admin: Admin,
+    Extension(bans): Extension<Arc<BanList>>,
+) -> Json<HashMap<String, Record>> {
+    Json(bans.banned())
+}
+
+/// Lift the ban on an identity.
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn clear(
+    _admin: Admin,
+    UrlPath(identity): UrlPath<String>,
+    Extension(bans): Extension<Arc<BanList>>,
+) -> Response {
+    if bans.clear(&identity) {
+        Json(json!({})).into_response()
+    } else {
+        let body = Json(json!({ "error": "Identity is not banned" }));
+        (StatusCode::NOT_FOUND, body).into_response()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test::TestDir;
+
+    #[test]
+    fn test_ban_list() {
+        let dir = TestDir::new("bans");
+        fs::create_dir_all(&dir).unwrap();
+        let thresholds = Thresholds {
+            aborts:                2,
+            invalid_contributions: 1,
+            deadline_misses:       3,
+        };
+
+        let bans = BanList::open(&dir, thresholds).unwrap();
+        assert!(!bans.record("a", Offense::Abort).banned);
+        assert!(!bans.record("a", Offense::DeadlineMiss).banned);
+        assert!(!bans.is_banned("a"));
+        assert!(bans.record("a", Offense::Abort).banned);
+        assert!(bans.record("b", Offense::InvalidContribution).banned);
+
+        // Reload from disk.
+        let bans = BanList::open(&dir, thresholds).unwrap();
+        assert!(bans.is_banned("a"));
+        assert_eq!(bans.banned()["a"].deadline_misses, 1);
+        assert!(bans.clear("a"));
+        assert!(!bans.clear("a"));
+        assert!(!bans.is_banned("a"));
+        assert_eq!(bans.banned().len(), 1);
+    }
+}
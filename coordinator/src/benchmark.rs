@@ -0,0 +1,198 @@
+//! Benchmark of the target machine.
+//!
+//! Runs the operations of the criterion benchmarks that bound a ceremony's
+//! throughput on the machine at hand, without needing a `bench` build:
+//!
+//! * `addTau`: a contributor's update of the powers, the bulk of the client
+//!   work.
+//! * `parse`: decoding an uploaded sub-contribution and checking its points are
+//!   in the prime order subgroup.
+//! * `verify`: checking a sub-contribution against the transcript.
+//! * `msm`: a G1 multi-scalar multiplication over all powers, the core of
+//!   `verify`, and `pairing` a two pair product, both for comparison with other
+//!   machines and backends.
+//!
+//! Every operation is run `--iterations` times and the median is reported in
+//! milliseconds. The estimated verification time of a sub-ceremony is its
+//! parse and verify time, and the estimate for a whole contribution is their
+//! sum. Operators can use it to size `--contribution-timeout` and the compute
+//! pool.
+
+use ark_bls12_381::{Fr, G1Affine, G2Affine};
+use ark_ec::{AffineCurve, ProjectiveCurve};
+use ark_ff::{PrimeField, UniformRand};
+use clap::Parser;
+use eyre::{ensure, Result as EyreResult};
+use kzg_ceremony_crypto::{
+    ContributionJson, CurveBackend, DefaultBackend, SanityChecks, SecretRng, SecretScalar,
+    Transcript, SIZES,
+};
+use serde::Serialize;
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use tracing::info;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+pub struct Options {
+    /// Number of runs of every operation, the median is reported.
+    #[clap(long, default_value = "3")]
+    pub iterations: usize,
+
+    /// Only benchmark the sub-ceremony with this many G1 powers.
+    #[clap(long)]
+    pub size: Option<usize>,
+
+    /// Write the report to this file instead of stdout.
+    #[clap(long)]
+    pub report: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    /// Curve backend, see [`CurveBackend::NAME`].
+    pub backend:                   String,
+    pub threads:                   usize,
+    pub iterations:                usize,
+    pub pairing_ms:                f64,
+    /// Estimated time to verify a contribution to every benchmarked size.
+    pub estimated_verification_ms: f64,
+    pub sizes:                     Vec<SizeReport>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SizeReport {
+    pub num_g1_powers:             usize,
+    pub num_g2_powers:             usize,
+    pub add_tau_ms:                f64,
+    pub parse_ms:                  f64,
+    pub verify_ms:                 f64,
+    pub msm_ms:                    f64,
+    pub estimated_verification_ms: f64,
+}
+
+/// # Errors
+///
+/// Returns an error if no size is selected or the report can not be written.
+pub async fn main(options: Options) -> EyreResult<()> {
+    ensure!(options.iterations > 0, "At least one iteration is required");
+    let sizes = SIZES
+        .iter()
+        .copied()
+        .filter(|(n1, _)| options.size.map_or(true, |size| size == *n1))
+        .collect::<Vec<_>>();
+    ensure!(!sizes.is_empty(), "No sub-ceremony of that size");
+    let iterations = options.iterations;
+    let report = tokio::task::spawn_blocking(move || run(&sizes, iterations)).await?;
+
+    let json = serde_json::to_string_pretty(&report)?;
+    match options.report {
+        Some(path) => fs::write(path, json)?,
+        None => println!("{json}"),
+    }
+    Ok(())
+}
+
+/// Benchmark every size in `sizes`, running each operation `iterations` times.
+///
+/// # Panics
+///
+/// Panics if an operation fails on the valid benchmark contribution.
+#[must_use]
+pub fn run(sizes: &[(usize, usize)], iterations: usize) -> Report {
+    let mut rng = rand::thread_rng();
+    let g1 = DefaultBackend::g1_mul(&G1Affine::prime_subgroup_generator(), &Fr::rand(&mut rng));
+    let g2 = DefaultBackend::g2_mul(&G2Affine::prime_subgroup_generator(), &Fr::rand(&mut rng));
+    let pairs = [(g1.into_affine(), g2.into_affine()); 2];
+    let pairing = median(iterations, || {
+        DefaultBackend::pairing_product_is_one(&pairs)
+    });
+    let sizes = sizes
+        .iter()
+        .map(|&(n1, n2)| {
+            info!(num_g1_powers = n1, num_g2_powers = n2, "Benchmarking");
+            let transcript = Transcript::new(n1, n2);
+            let mut contribution = transcript.contribution();
+            let add_tau = median(iterations, || {
+                contribution = transcript.contribution();
+                contribution.add_tau(&SecretScalar::random(&mut SecretRng::new()));
+            });
+            let json = ContributionJson::from(&contribution);
+            let parse = median(iterations, || {
+                json.parse_with(SanityChecks::default())
+                    .expect("Benchmark contribution is valid")
+            });
+            let verify = median(iterations, || {
+                contribution
+                    .verify(&transcript)
+                    .expect("Benchmark contribution is valid");
+            });
+            let scalars = (0..n1)
+                .map(|_| Fr::rand(&mut rng).into_repr())
+                .collect::<Vec<_>>();
+            let msm = median(iterations, || {
+                DefaultBackend::g1_msm(&contribution.g1_powers, &scalars)
+            });
+            SizeReport {
+                num_g1_powers:             n1,
+                num_g2_powers:             n2,
+                add_tau_ms:                millis(add_tau),
+                parse_ms:                  millis(parse),
+                verify_ms:                 millis(verify),
+                msm_ms:                    millis(msm),
+                estimated_verification_ms: millis(parse + verify),
+            }
+        })
+        .collect::<Vec<_>>();
+    Report {
+        backend: DefaultBackend::NAME.to_string(),
+        threads: rayon::current_num_threads(),
+        iterations,
+        pairing_ms: millis(pairing),
+        estimated_verification_ms: sizes.iter().map(|s| s.estimated_verification_ms).sum(),
+        sizes,
+    }
+}
+
+/// Median duration of `iterations` runs of `f`. Its results are kept until all
+/// runs are timed, so the optimizer can not skip the work.
+fn median<T>(iterations: usize, mut f: impl FnMut() -> T) -> Duration {
+    let mut results = Vec::with_capacity(iterations);
+    let mut durations = (0..iterations)
+        .map(|_| {
+            let start = Instant::now();
+            results.push(f());
+            start.elapsed()
+        })
+        .collect::<Vec<_>>();
+    drop(results);
+    durations.sort();
+    durations[durations.len() / 2]
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        let report = run(&[(4, 2), (8, 3)], 1);
+        assert_eq!(report.sizes.len(), 2);
+        assert_eq!(report.sizes[1].num_g1_powers, 8);
+        assert_eq!(report.sizes[1].num_g2_powers, 3);
+        let sum =
+            report.sizes[0].estimated_verification_ms + report.sizes[1].estimated_verification_ms;
+        assert!((report.estimated_verification_ms - sum).abs() < 1e-9);
+        let json = serde_json::to_value(&report).unwrap();
+        assert!(json["sizes"][0]["addTauMs"].is_number());
+        assert!(json["pairingMs"].is_number());
+    }
+}
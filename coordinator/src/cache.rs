@@ -0,0 +1,113 @@
+//! Cache of recently served transcript byte ranges.
+//!
+//! Status pages and light clients tend to request the same small ranges of
+//! the transcript in bursts, e.g. the first kilobytes of every sub-transcript
+//! or the smallest sub-transcript in full. Encoding those points again for
+//! every request is wasteful, so encoded ranges are kept in an LRU.
+//!
+//! Entries are keyed by transcript hash, so a new contribution implicitly
+//! invalidates everything; stale entries simply age out.
+
+use axum::body::Bytes;
+use lru::LruCache;
+use std::{ops::Range, sync::Mutex};
+use tracing::trace;
+
+/// Ranges larger than this are never cached, so a single full download can
+/// not evict all the hot entries.
+pub const MAX_CACHED_RANGE: usize = 1 << 20;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct RangeKey {
+    pub transcript: [u8; 32],
+    /// Index of the sub-transcript.
+    pub index:      usize,
+    pub start:      usize,
+    pub end:        usize,
+}
+
+#[derive(Debug)]
+pub struct RangeCache {
+    inner:    Mutex<Inner>,
+    capacity: usize,
+}
+
+#[derive(Debug)]
+struct Inner {
+    entries: LruCache<RangeKey, Bytes>,
+    size:    usize,
+}
+
+impl RangeKey {
+    #[must_use]
+    pub const fn new(transcript: [u8; 32], index: usize, range: &Range<usize>) -> Self {
+        Self {
+            transcript,
+            index,
+            start: range.start,
+            end: range.end,
+        }
+    }
+}
+
+impl RangeCache {
+    /// Create a cache holding up to `capacity` bytes.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                entries: LruCache::unbounded(),
+                size:    0,
+            }),
+            capacity,
+        }
+    }
+
+    /// Return the cached bytes for `key`, or compute and cache them.
+    pub fn get_or_insert_with<F, E>(&self, key: RangeKey, compute: F) -> Result<Bytes, E>
+    where
+        F: FnOnce() -> Result<Bytes, E>,
+    {
+        let cached = self.inner.lock().unwrap().entries.get(&key).cloned();
+        if let Some(bytes) = cached {
+            trace!(?key, "Range cache hit");
+            return Ok(bytes);
+        }
+        // Compute without holding the lock.
+        let bytes = compute()?;
+        if bytes.len() <= MAX_CACHED_RANGE && bytes.len() <= self.capacity {
+            let mut inner = self.inner.lock().unwrap();
+            if let Some(old) = inner.entries.put(key, bytes.clone()) {
+                inner.size -= old.len();
+            }
+            inner.size += bytes.len();
+            while inner.size > self.capacity {
+                let (_, evicted) = inner.entries.pop_lru().expect("Size implies entries");
+                inner.size -= evicted.len();
+            }
+            drop(inner);
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::convert::Infallible;
+
+    #[test]
+    fn test_eviction() {
+        let cache = RangeCache::new(10);
+        let key = |start| RangeKey::new([0; 32], 0, &(start..start + 4));
+        let fill = |byte| move || Ok::<_, Infallible>(Bytes::from(vec![byte; 4]));
+        assert_eq!(cache.get_or_insert_with(key(0), fill(1)).unwrap()[0], 1);
+        assert_eq!(cache.get_or_insert_with(key(4), fill(2)).unwrap()[0], 2);
+        // Hit, so key(4) becomes the least recently used.
+        assert_eq!(cache.get_or_insert_with(key(0), fill(9)).unwrap()[0], 1);
+        assert_eq!(cache.get_or_insert_with(key(8), fill(3)).unwrap()[0], 3);
+        assert_eq!(cache.inner.lock().unwrap().size, 8);
+        assert_eq!(cache.get_or_insert_with(key(0), fill(9)).unwrap()[0], 1);
+        assert_eq!(cache.get_or_insert_with(key(4), fill(5)).unwrap()[0], 5);
+    }
+}
@@ -0,0 +1,391 @@
+//! Shared ceremony state.
+//!
+//! A contribution covers any non-empty subset of the sub-ceremonies. It is
+//! accepted as a whole: if any covered sub-ceremony fails verification, none
+//! of them are updated. Contributions are numbered by a single sequence,
+//! regardless of the sub-ceremonies they cover.
+
+use crate::{
+    checkpoint::HashChain,
+    events::{CeremonyEvent, EventBus},
+    leader::Leadership,
+    observer::{ObserverFeed, TranscriptDelta},
+    points::MappedTranscript,
+    scheduler::{self, Timing},
+    storage::TranscriptStore,
+    wal::Wal,
+};
+use eyre::Report;
+use kzg_ceremony_crypto::{
+    BatchTranscript, BatchVerifyError, Contribution, Transcript, VerificationRng, VerifyError,
+};
+use rand::Rng;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::Mutex;
+use tracing::{info, instrument, warn};
+
+pub type SharedCeremony = Arc<Mutex<Ceremony>>;
+
+/// Number of G1 and G2 powers of each sub-ceremony.
+///
+/// An extension of its own, so uploads can be parsed without waiting for the
+/// ceremony lock.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Sizes(pub Arc<[(usize, usize)]>);
+
+/// Seeds the subgroup checks of uploads, the thread RNG if `None`.
+///
+/// An extension of its own for the same reason as [`Sizes`]. Test ceremonies
+/// seed it like [`Ceremony::with_rng`], so uploads are checked reproducibly.
+#[derive(Clone, Debug, Default)]
+pub struct UploadRng(Option<Arc<std::sync::Mutex<VerificationRng>>>);
+
+#[derive(Debug, Error)]
+pub enum CeremonyError {
+    #[error("Unexpected number of contributions: expected {0}, got {1}")]
+    InvalidContributionCount(usize, usize),
+    #[error("Contribution covers no sub-ceremony")]
+    NoSubContributions,
+    #[error("Contribution {0} failed verification: {1}")]
+    VerificationFailed(usize, #[source] VerifyError),
+    #[error("Contribution {0} has an invalid BLS signature of the identity")]
+    InvalidBlsSignature(usize),
+    #[error("Contribution {0} has an invalid proof of knowledge of tau")]
+    InvalidPubkeyProof(usize),
+    #[error("Contribution {0} has no proof of knowledge of tau")]
+    MissingPubkeyProof(usize),
+    #[error("Storage error: {0}")]
+    Storage(Report),
+    #[error("Not the leader: {0}")]
+    NotLeader(Report),
+}
+
+/// Outcome of a successfully applied contribution.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Accepted {
+    /// Number of contributions in the transcript, including this one.
+    pub index:             usize,
+    /// The sub-ceremonies contributed to.
+    pub sub_ceremonies:    Vec<usize>,
+    pub verification:      Timing,
+    pub transcript_before: [u8; 32],
+    pub transcript_after:  [u8; 32],
+}
+
+impl UploadRng {
+    #[must_use]
+    pub fn new(rng: Option<VerificationRng>) -> Self {
+        Self(rng.map(|rng| Arc::new(std::sync::Mutex::new(rng))))
+    }
+
+    /// RNG for the checks of one upload.
+    #[must_use]
+    pub fn rng(&self) -> VerificationRng {
+        self.0.as_ref().map_or_else(VerificationRng::new, |rng| {
+            VerificationRng::from_seed(rng.lock().unwrap().gen())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Ceremony {
+    store:                TranscriptStore,
+    transcript:           BatchTranscript,
+    hash:                 [u8; 32],
+    num_contributions:    usize,
+    events:               EventBus,
+    observers:            ObserverFeed,
+    chain:                HashChain,
+    /// `None` while another coordinator writes the store.
+    wal:                  Option<Wal>,
+    /// Fences appends to a shared store, see [`crate::leader`].
+    leadership:           Leadership,
+    require_pubkey_proof: bool,
+    /// Seeds the verification of each sub-contribution, the thread RNG if
+    /// `None`.
+    rng:                  Option<VerificationRng>,
+}
+
+impl From<BatchVerifyError> for CeremonyError {
+    fn from(error: BatchVerifyError) -> Self {
+        match error {
+            BatchVerifyError::InvalidContributionCount(expected, got) => {
+                Self::InvalidContributionCount(expected, got)
+            }
+            BatchVerifyError::NoSubContributions => Self::NoSubContributions,
+            BatchVerifyError::VerificationFailed(index, error) => {
+                Self::VerificationFailed(index, error)
+            }
+        }
+    }
+}
+
+impl Ceremony {
+    pub fn new(
+        store: TranscriptStore,
+        events: EventBus,
+        observers: ObserverFeed,
+    ) -> Result<Self, CeremonyError> {
+        let transcript = store.load().map_err(CeremonyError::Storage)?;
+        let hash = transcript.hash();
+        let num_contributions = store.num_entries().map_err(CeremonyError::Storage)? - 1;
+        let chain = HashChain::open(store.dir(), num_contributions, hash)
+            .map_err(CeremonyError::Storage)?;
+        // A shared store is reconciled by the coordinator writing it.
+        let wal = if store.is_shared() {
+            None
+        } else {
+            Some(open_wal(&store, &transcript)?)
+        };
+        Ok(Self {
+            store,
+            transcript,
+            hash,
+            num_contributions,
+            events,
+            observers,
+            chain,
+            wal,
+            leadership: Leadership::default(),
+            require_pubkey_proof: false,
+            rng: None,
+        })
+    }
+
+    /// Reject contributions without a proof of knowledge of tau.
+    #[must_use]
+    pub const fn with_required_pubkey_proof(mut self, require: bool) -> Self {
+        self.require_pubkey_proof = require;
+        self
+    }
+
+    /// Draw the random factors of verification from `rng` instead of the
+    /// thread RNG, so verification is reproducible.
+    #[must_use]
+    pub const fn with_rng(mut self, rng: Option<VerificationRng>) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    /// Only append while leading the coordinators sharing the store, see
+    /// [`crate::leader`].
+    #[must_use]
+    pub fn with_leadership(mut self, leadership: Leadership) -> Self {
+        self.leadership = leadership;
+        self
+    }
+
+    #[must_use]
+    pub const fn transcript(&self) -> &BatchTranscript {
+        &self.transcript
+    }
+
+    /// Hash of the current transcript.
+    #[must_use]
+    pub const fn hash(&self) -> [u8; 32] {
+        self.hash
+    }
+
+    /// Checkpoints of the transcript hashes, see [`crate::checkpoint`].
+    #[must_use]
+    pub const fn hash_chain(&self) -> &HashChain {
+        &self.chain
+    }
+
+    /// Number of contributions so far.
+    #[must_use]
+    pub const fn num_contributions(&self) -> usize {
+        self.num_contributions
+    }
+
+    /// The sub-ceremonies covered by every contribution so far.
+    pub fn rounds(&self) -> Result<Vec<Vec<usize>>, Report> {
+        self.store.rounds()
+    }
+
+    /// Map the transcript in storage, bypassing the in-memory copy. The
+    /// mapping is unaffected by later contributions, see [`crate::points`].
+    pub fn map_stored(&self) -> Result<MappedTranscript, Report> {
+        self.store.map()
+    }
+
+    /// Reload the transcript appended by another coordinator sharing the
+    /// store, see [`crate::leader`]. Returns whether it changed.
+    ///
+    /// With `recover` an append interrupted by the other coordinator is
+    /// completed first, which is only safe once it stopped writing.
+    pub fn reload(&mut self, recover: bool) -> Result<bool, CeremonyError> {
+        if recover {
+            let dir = self.store.dir().to_path_buf();
+            self.store = TranscriptStore::open(&dir).map_err(CeremonyError::Storage)?;
+            // Release the log before reopening it.
+            self.wal = None;
+            let transcript = self.store.load().map_err(CeremonyError::Storage)?;
+            self.wal = Some(open_wal(&self.store, &transcript)?);
+        }
+        let num_contributions = self.store.num_entries().map_err(CeremonyError::Storage)? - 1;
+        if num_contributions == self.num_contributions && !recover {
+            return Ok(false);
+        }
+        let transcript = self.store.load().map_err(CeremonyError::Storage)?;
+        let hash = transcript.hash();
+        self.chain
+            .reload(num_contributions, hash, recover)
+            .map_err(CeremonyError::Storage)?;
+        let changed = hash != self.hash;
+        self.transcript = transcript;
+        self.hash = hash;
+        self.num_contributions = num_contributions;
+        if changed {
+            self.observers.publish(TranscriptDelta::latest(
+                num_contributions,
+                &self.transcript,
+                hash,
+            ));
+        }
+        Ok(changed)
+    }
+
+    /// Verify contributions against the current transcript and apply them.
+    ///
+    /// `contributions` has an entry for every sub-ceremony, `None` for those
+    /// not contributed to.
+    ///
+    /// Identity signatures and pubkey proofs are optional, but if present they
+    /// must be valid for `identity`. Pubkey proofs can be made mandatory with
+    /// [`Self::with_required_pubkey_proof`]. Only the `pseudonym` of the
+    /// contributor is stored and logged, see [`crate::pseudonym`].
+    ///
+    /// This does heavy computation and should be called from a blocking task.
+    #[allow(clippy::disallowed_methods)] // False positive from macro
+    #[instrument(level = "info", skip(self, identity, contributions))]
+    pub fn contribute(
+        &mut self,
+        identity: &str,
+        pseudonym: &str,
+        contributions: Vec<Option<Contribution>>,
+    ) -> Result<Accepted, CeremonyError> {
+        let sub_ceremonies = self.transcript.sub_ceremonies(&contributions)?;
+        let seeds = sub_ceremonies
+            .iter()
+            .map(|_| self.verification_seed())
+            .collect::<Vec<_>>();
+        let jobs = sub_ceremonies
+            .iter()
+            .zip(seeds)
+            .filter_map(|(&i, seed)| {
+                let contribution = contributions[i].as_ref()?;
+                Some((i, (&self.transcript.transcripts[i], contribution, seed)))
+            })
+            .collect::<Vec<_>>();
+        let verification = scheduler::verify_all(&jobs, |i, (transcript, contribution, seed)| {
+            let mut rng = VerificationRng::from_seed(*seed);
+            self.verify_sub_contribution(i, transcript, contribution, identity, &mut rng)
+        })?;
+        let transcript_before = self.hash;
+        let index = self.append(contributions, None, Some(pseudonym))?;
+        info!(index, ?sub_ceremonies, "Contribution accepted");
+        Ok(Accepted {
+            index,
+            sub_ceremonies,
+            verification,
+            transcript_before,
+            transcript_after: self.hash,
+        })
+    }
+
+    /// Append a contribution to the primary's transcript, when following a
+    /// primary coordinator, see [`crate::follower`].
+    ///
+    /// The contribution must have been verified by the caller. Intermediate
+    /// powers are not published, so `hash` is the primary's transcript hash
+    /// after the contribution rather than the hash of the local transcript.
+    pub fn append_followed(
+        &mut self,
+        contributions: Vec<Option<Contribution>>,
+        hash: [u8; 32],
+    ) -> Result<usize, CeremonyError> {
+        self.append(contributions, Some(hash), None)
+    }
+
+    /// Store and apply verified contributions of `pseudonym`, recording `hash`
+    /// or else the resulting transcript hash.
+    fn append(
+        &mut self,
+        contributions: Vec<Option<Contribution>>,
+        hash: Option<[u8; 32]>,
+        pseudonym: Option<&str>,
+    ) -> Result<usize, CeremonyError> {
+        let index = self.num_contributions + 1;
+        // A leader that missed its renewals may have been taken over since the
+        // contribution was started.
+        self.leadership.fence().map_err(CeremonyError::NotLeader)?;
+        if let Some(wal) = &mut self.wal {
+            wal.begin(index, pseudonym, &contributions)
+                .map_err(CeremonyError::Storage)?;
+        }
+        if let Err(error) = self.store.append(&contributions) {
+            if let Some(wal) = &mut self.wal {
+                // Otherwise resolved on the next start.
+                let _ = wal.abort(index);
+            }
+            return Err(CeremonyError::Storage(error));
+        }
+        self.transcript.add(contributions);
+
+        self.hash = hash.unwrap_or_else(|| self.transcript.hash());
+        self.num_contributions = index;
+        if let Some(wal) = &mut self.wal {
+            // The contribution is stored, so it is committed on the next start
+            // regardless.
+            if let Err(error) = wal.commit(index) {
+                warn!(
+                    ?error,
+                    index, "Could not commit contribution to the write-ahead log"
+                );
+            }
+        }
+        self.chain.append(index, self.hash);
+        self.observers
+            .publish(TranscriptDelta::latest(index, &self.transcript, self.hash));
+        self.events
+            .publish(CeremonyEvent::ContributionAccepted { index });
+        Ok(index)
+    }
+
+    /// Seed for the verification of one sub-contribution.
+    fn verification_seed(&mut self) -> [u8; 32] {
+        self.rng
+            .as_mut()
+            .map_or_else(|| rand::thread_rng().gen(), Rng::gen)
+    }
+
+    fn verify_sub_contribution(
+        &self,
+        index: usize,
+        transcript: &Transcript,
+        contribution: &Contribution,
+        identity: &str,
+        rng: &mut VerificationRng,
+    ) -> Result<(), CeremonyError> {
+        contribution
+            .verify_with_rng(transcript, rng)
+            .map_err(|e| CeremonyError::VerificationFailed(index, e))?;
+        if contribution.verify_identity(identity) == Some(false) {
+            return Err(CeremonyError::InvalidBlsSignature(index));
+        }
+        match contribution.verify_possession(identity) {
+            Some(false) => Err(CeremonyError::InvalidPubkeyProof(index)),
+            None if self.require_pubkey_proof => Err(CeremonyError::MissingPubkeyProof(index)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Open the write-ahead log of `store` holding `transcript`, see
+/// [`crate::wal`].
+fn open_wal(store: &TranscriptStore, transcript: &BatchTranscript) -> Result<Wal, CeremonyError> {
+    let rounds = store.rounds().map_err(CeremonyError::Storage)?;
+    Wal::open(store.dir(), transcript, &rounds).map_err(CeremonyError::Storage)
+}
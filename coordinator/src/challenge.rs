@@ -0,0 +1,404 @@
+//! Challenges against automated queue joins.
+//!
+//! Joining the queue can require a proof of work, a CAPTCHA, or both, so
+//! flooding the queue with accounts gets expensive.
+//!
+//! With `--join-pow-bits` a participant first asks for a challenge on
+//! `POST /queue/challenge`. It is bound to their identity and expires after
+//! `--join-pow-ttl` seconds. They then search for a `solution` such that
+//! `SHA-256("{challenge}:{solution}")` starts with `difficulty` zero bits, and
+//! join with
+//!
+//! ```json
+//! { "pow": "{challenge}:{solution}" }
+//! ```
+//!
+//! Each challenge can be used once. The difficulty grows by one bit every
+//! time the queue doubles beyond [`PRESSURE`] waiting participants, up to
+//! `--join-pow-max-bits`.
+//!
+//! CAPTCHAs are pluggable through [`Captcha`]. With `--captcha-verify-url`
+//! and `--captcha-secret` the response token of the frontend widget, given
+//! as `{ "captcha": "{token}" }`, is checked with the `siteverify` protocol
+//! shared by hCaptcha, reCAPTCHA and Turnstile.
+
+use crate::{
+    auth::{https_client, HttpsClient},
+    Options,
+};
+use axum::async_trait;
+use eyre::{bail, Result as EyreResult};
+use hmac::{Hmac, Mac};
+use hyper::{body::to_bytes, Body, Method, Request};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+use tracing::warn;
+use url::{form_urlencoded, Url};
+
+/// Queue length at which the difficulty starts to grow.
+pub const PRESSURE: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum ChallengeError {
+    #[error("Joining the queue requires a solved challenge")]
+    Required,
+    #[error("Invalid challenge solution")]
+    Invalid,
+    #[error("Challenge expired")]
+    Expired,
+    #[error("Challenge was already used")]
+    Reused,
+    #[error("CAPTCHA verification failed")]
+    Captcha,
+}
+
+/// Verifies CAPTCHA response tokens.
+#[async_trait]
+pub trait Captcha: fmt::Debug + Send + Sync {
+    async fn verify(&self, response: &str) -> bool;
+}
+
+/// CAPTCHA verification with the `siteverify` protocol.
+#[derive(Clone)]
+pub struct SiteVerify {
+    url:    Url,
+    secret: String,
+    client: HttpsClient,
+}
+
+/// Proof of work settings.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PowConfig {
+    pub min_bits: u32,
+    pub max_bits: u32,
+    pub ttl:      Duration,
+}
+
+/// Checks the challenges of `/queue/join`.
+#[derive(Debug, Default)]
+pub struct JoinGuard {
+    pow:     Option<ProofOfWork>,
+    captcha: Option<Arc<dyn Captcha>>,
+}
+
+#[derive(Debug)]
+struct ProofOfWork {
+    config: PowConfig,
+    key:    [u8; 32],
+    /// Used challenges by expiry.
+    used:   Mutex<HashMap<String, u64>>,
+}
+
+/// A challenge issued to a participant.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Challenge {
+    /// `{expiry}.{difficulty}.{mac}`, to be solved and sent back.
+    pub challenge:  String,
+    /// Required number of leading zero bits.
+    pub difficulty: u32,
+    /// Unix time the challenge expires.
+    pub expires:    u64,
+}
+
+/// Solutions to the challenges, in the body of `/queue/join`.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct JoinRequest {
+    /// `{challenge}:{solution}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pow:     Option<String>,
+    /// Response token of the CAPTCHA widget.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub captcha: Option<String>,
+}
+
+impl ChallengeError {
+    /// Stable error code for clients.
+    #[must_use]
+    pub const fn code(&self) -> &'static str {
+        match self {
+            Self::Required => "challenge_required",
+            Self::Invalid => "challenge_invalid",
+            Self::Expired => "challenge_expired",
+            Self::Reused => "challenge_reused",
+            Self::Captcha => "captcha_failed",
+        }
+    }
+}
+
+impl PowConfig {
+    /// Proof of work settings, if enabled with `--join-pow-bits`.
+    #[must_use]
+    pub fn from_options(options: &Options) -> Option<Self> {
+        (options.join_pow_bits > 0).then(|| Self {
+            min_bits: options.join_pow_bits,
+            max_bits: options.join_pow_max_bits.max(options.join_pow_bits),
+            ttl:      Duration::from_secs(options.join_pow_ttl),
+        })
+    }
+
+    /// Difficulty with `queue_length` participants waiting.
+    #[must_use]
+    pub fn difficulty(&self, queue_length: usize) -> u32 {
+        let extra = usize::BITS - 1 - (1 + queue_length / PRESSURE).leading_zeros();
+        (self.min_bits + extra).min(self.max_bits)
+    }
+}
+
+impl SiteVerify {
+    /// CAPTCHA verification, if enabled with `--captcha-verify-url`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `--captcha-secret` is missing.
+    pub fn from_options(options: &Options) -> EyreResult<Option<Self>> {
+        let url = match options.captcha_verify_url.clone() {
+            Some(url) => url,
+            None => return Ok(None),
+        };
+        let secret = match options.captcha_secret.clone() {
+            Some(secret) => secret,
+            None => bail!("--captcha-verify-url requires --captcha-secret"),
+        };
+        Ok(Some(Self {
+            url,
+            secret,
+            client: https_client(),
+        }))
+    }
+
+    async fn siteverify(&self, response: &str) -> EyreResult<bool> {
+        #[derive(Deserialize)]
+        struct Verdict {
+            success: bool,
+        }
+        let form = form_urlencoded::Serializer::new(String::new())
+            .append_pair("secret", &self.secret)
+            .append_pair("response", response)
+            .finish();
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(self.url.as_str())
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(Body::from(form))?;
+        let response = self.client.request(request).await?;
+        if !response.status().is_success() {
+            bail!("CAPTCHA verification responded with {}", response.status());
+        }
+        let verdict: Verdict = serde_json::from_slice(&to_bytes(response.into_body()).await?)?;
+        Ok(verdict.success)
+    }
+}
+
+impl fmt::Debug for SiteVerify {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SiteVerify")
+            .field("url", &self.url)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl Captcha for SiteVerify {
+    async fn verify(&self, response: &str) -> bool {
+        match self.siteverify(response).await {
+            Ok(success) => success,
+            Err(error) => {
+                // Fail closed, the participant can retry.
+                warn!(%error, "CAPTCHA verification failed");
+                false
+            }
+        }
+    }
+}
+
+impl JoinGuard {
+    #[must_use]
+    pub fn new(pow: Option<PowConfig>, captcha: Option<Arc<dyn Captcha>>) -> Self {
+        Self {
+            pow: pow.map(|config| ProofOfWork {
+                config,
+                key: rand::thread_rng().gen(),
+                used: Mutex::new(HashMap::new()),
+            }),
+            captcha,
+        }
+    }
+
+    /// Issue a proof of work challenge to `identity`, if required.
+    #[must_use]
+    pub fn challenge(&self, identity: &str, queue_length: usize) -> Option<Challenge> {
+        let pow = self.pow.as_ref()?;
+        let difficulty = pow.config.difficulty(queue_length);
+        let expires = now() + pow.config.ttl.as_secs();
+        Some(Challenge {
+            challenge: format!(
+                "{}.{}.{}",
+                expires,
+                difficulty,
+                hex::encode(pow.mac(identity, expires, difficulty))
+            ),
+            difficulty,
+            expires,
+        })
+    }
+
+    /// Check the solutions `request` of `identity` to the required challenges.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ChallengeError::Required`] if a required solution is missing,
+    /// and the reason otherwise if a solution is rejected.
+    pub async fn check(&self, identity: &str, request: &JoinRequest) -> Result<(), ChallengeError> {
+        if let Some(pow) = &self.pow {
+            let solution = request.pow.as_deref().ok_or(ChallengeError::Required)?;
+            pow.check(identity, solution, now())?;
+        }
+        if let Some(captcha) = &self.captcha {
+            let response = request.captcha.as_deref().ok_or(ChallengeError::Required)?;
+            if !captcha.verify(response).await {
+                return Err(ChallengeError::Captcha);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ProofOfWork {
+    fn mac(&self, identity: &str, expires: u64, difficulty: u32) -> [u8; 32] {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts any key size");
+        mac.update(format!("{identity}.{expires}.{difficulty}").as_bytes());
+        mac.finalize().into_bytes().into()
+    }
+
+    fn check(&self, identity: &str, solution: &str, now: u64) -> Result<(), ChallengeError> {
+        let (challenge, _) = solution.rsplit_once(':').ok_or(ChallengeError::Invalid)?;
+        let mut parts = challenge.splitn(3, '.');
+        let mut next = || parts.next().ok_or(ChallengeError::Invalid);
+        let expires: u64 = next()?.parse().map_err(|_| ChallengeError::Invalid)?;
+        let difficulty: u32 = next()?.parse().map_err(|_| ChallengeError::Invalid)?;
+        let mac = hex::decode(next()?).map_err(|_| ChallengeError::Invalid)?;
+        if mac != self.mac(identity, expires, difficulty) {
+            return Err(ChallengeError::Invalid);
+        }
+        if expires <= now {
+            return Err(ChallengeError::Expired);
+        }
+        if leading_zeros(&Sha256::digest(solution.as_bytes())) < difficulty {
+            return Err(ChallengeError::Invalid);
+        }
+        let mut used = self.used.lock().unwrap();
+        used.retain(|_, expires| *expires > now);
+        if used.insert(challenge.to_string(), expires).is_some() {
+            return Err(ChallengeError::Reused);
+        }
+        drop(used);
+        Ok(())
+    }
+}
+
+/// Find a solution to `challenge`.
+///
+/// # Panics
+///
+/// Panics if no solution exists, which for any reachable difficulty is
+/// vanishingly unlikely.
+#[must_use]
+pub fn solve(challenge: &Challenge) -> String {
+    (0..=u64::MAX)
+        .map(|solution| format!("{}:{}", challenge.challenge, solution))
+        .find(|candidate| {
+            leading_zeros(&Sha256::digest(candidate.as_bytes())) >= challenge.difficulty
+        })
+        .expect("A solution exists")
+}
+
+fn leading_zeros(hash: &[u8]) -> u32 {
+    let mut zeros = 0;
+    for byte in hash {
+        zeros += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    zeros
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time before unix epoch")
+        .as_secs()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Fixed(&'static str);
+
+    #[async_trait]
+    impl Captcha for Fixed {
+        async fn verify(&self, response: &str) -> bool {
+            response == self.0
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pow() {
+        let config = PowConfig {
+            min_bits: 8,
+            max_bits: 10,
+            ttl:      Duration::from_secs(60),
+        };
+        assert_eq!(config.difficulty(0), 8);
+        assert_eq!(config.difficulty(PRESSURE), 9);
+        assert_eq!(config.difficulty(100 * PRESSURE), 10);
+
+        let guard = JoinGuard::new(Some(config), None);
+        let challenge = guard.challenge("a", 0).unwrap();
+        let pow = Some(solve(&challenge));
+        let request = JoinRequest { pow, captcha: None };
+        assert_eq!(
+            guard.check("b", &request).await,
+            Err(ChallengeError::Invalid)
+        );
+        guard.check("a", &request).await.unwrap();
+        assert_eq!(
+            guard.check("a", &request).await,
+            Err(ChallengeError::Reused)
+        );
+        assert_eq!(
+            guard.check("a", &JoinRequest::default()).await,
+            Err(ChallengeError::Required)
+        );
+        let pow = guard.pow.as_ref().unwrap();
+        let solution = solve(&guard.challenge("a", 0).unwrap());
+        assert_eq!(
+            pow.check("a", &solution, now() + 60),
+            Err(ChallengeError::Expired)
+        );
+        assert_eq!(leading_zeros(&[0, 0x1f, 0xff]), 11);
+
+        let captcha = JoinGuard::new(None, Some(Arc::new(Fixed("ok"))));
+        assert!(JoinGuard::default().challenge("a", 0).is_none());
+        let request = |captcha: &str| JoinRequest {
+            pow:     None,
+            captcha: Some(captcha.to_string()),
+        };
+        captcha.check("a", &request("ok")).await.unwrap();
+        assert_eq!(
+            captcha.check("a", &request("bot")).await,
+            Err(ChallengeError::Captcha)
+        );
+    }
+}
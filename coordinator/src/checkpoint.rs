@@ -0,0 +1,360 @@
+//! Transcript hash chain.
+//!
+//! After every contribution the coordinator records a [`Checkpoint`] with the
+//! SHA-256 hash of the binary transcript, see
+//! [`crate::storage::transcript_hash`]. Checkpoints are chained: each
+//! `chainHash` is the SHA-256 of the previous chain hash (zero for the first
+//! checkpoint), the index as a little-endian `u64` and the transcript hash. An
+//! auditor holding one chain hash can therefore check that later checkpoints
+//! extend the history it has seen, without downloading the transcript.
+//!
+//! The chain is stored one JSON checkpoint per line in `hash-chain.jsonl` in
+//! the transcript directory.
+//!
+//! * `GET /ceremony/checkpoint` returns the latest checkpoint.
+//! * `GET /ceremony/checkpoints` returns the whole chain.
+//! * `GET /ceremony/checkpoints/{index}` returns the checkpoint after
+//!   contribution `index`.
+
+use axum::{
+    extract::Path as UrlPath,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use eyre::{bail, ensure, Result as EyreResult, WrapErr};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+use thiserror::Error;
+use tracing::{instrument, warn};
+
+const CHAIN: &str = "hash-chain.jsonl";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum CheckpointError {
+    #[error("No checkpoint for contribution {0}")]
+    NotFound(usize),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Checkpoint {
+    /// Number of contributions in the transcript.
+    pub index:           usize,
+    #[serde(with = "hash_hex")]
+    pub transcript_hash: [u8; 32],
+    #[serde(with = "hash_hex")]
+    pub chain_hash:      [u8; 32],
+}
+
+/// Shared, persisted chain of checkpoints.
+#[derive(Clone, Debug)]
+pub struct HashChain {
+    path:        PathBuf,
+    checkpoints: Arc<RwLock<Vec<Checkpoint>>>,
+}
+
+impl Checkpoint {
+    /// Checkpoint following `previous`.
+    #[must_use]
+    pub fn new(previous: Option<&Self>, index: usize, transcript_hash: [u8; 32]) -> Self {
+        Self {
+            index,
+            transcript_hash,
+            chain_hash: chain_hash(previous, index, &transcript_hash),
+        }
+    }
+
+    /// Whether this checkpoint directly follows `previous`.
+    #[must_use]
+    pub fn follows(&self, previous: Option<&Self>) -> bool {
+        previous.map_or(true, |previous| previous.index < self.index)
+            && self.chain_hash == chain_hash(previous, self.index, &self.transcript_hash)
+    }
+}
+
+impl HashChain {
+    /// Load the chain from `dir` and make sure it ends at the transcript with
+    /// `index` contributions and hash `transcript_hash`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the chain can not be read or does not match the
+    /// transcript.
+    #[allow(clippy::disallowed_methods)] // False positive from macro
+    #[instrument(level = "info", skip(transcript_hash))]
+    pub fn open(dir: &Path, index: usize, transcript_hash: [u8; 32]) -> EyreResult<Self> {
+        let path = dir.join(CHAIN);
+        let checkpoints = read_chain(&path)?;
+        let chain = Self {
+            path,
+            checkpoints: Arc::new(RwLock::new(checkpoints)),
+        };
+        match chain.latest() {
+            Some(latest) if latest.index == index => {
+                ensure!(
+                    latest.transcript_hash == transcript_hash,
+                    "Latest checkpoint does not match the transcript"
+                );
+            }
+            Some(latest) if latest.index > index => {
+                bail!(
+                    "Hash chain is ahead of the transcript: {} > {}",
+                    latest.index,
+                    index
+                );
+            }
+            latest => {
+                if latest.is_some() {
+                    warn!(index, "Hash chain is missing checkpoints, resuming");
+                }
+                chain.append(index, transcript_hash);
+            }
+        }
+        Ok(chain)
+    }
+
+    /// Reload the chain persisted by another coordinator sharing the
+    /// directory, see [`crate::leader`]. It must end at the transcript with
+    /// `index` contributions and hash `transcript_hash`, unless `resume` is
+    /// set and the other coordinator stopped before recording it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the chain can not be read or does not match the
+    /// transcript.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the chain lock is poisoned.
+    pub fn reload(&self, index: usize, transcript_hash: [u8; 32], resume: bool) -> EyreResult<()> {
+        let checkpoints = read_chain(&self.path)?;
+        let latest = checkpoints.last().copied();
+        *self.checkpoints.write().unwrap() = checkpoints;
+        match latest {
+            Some(latest) if latest.index == index => {
+                ensure!(
+                    latest.transcript_hash == transcript_hash,
+                    "Latest checkpoint does not match the transcript"
+                );
+            }
+            Some(latest) if latest.index < index && resume => {
+                warn!(index, "Hash chain is missing checkpoints, resuming");
+                self.append(index, transcript_hash);
+            }
+            _ => bail!("Hash chain does not end at contribution {}", index),
+        }
+        Ok(())
+    }
+
+    /// Record the transcript after contribution `index`.
+    ///
+    /// A failure to persist the checkpoint is logged, it is added again from
+    /// the transcript on the next start.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the chain lock is poisoned.
+    pub fn append(&self, index: usize, transcript_hash: [u8; 32]) -> Checkpoint {
+        let mut checkpoints = self.checkpoints.write().unwrap();
+        let checkpoint = Checkpoint::new(checkpoints.last(), index, transcript_hash);
+        checkpoints.push(checkpoint);
+        if let Err(error) = self.persist(&checkpoint) {
+            warn!(?error, "Could not persist checkpoint");
+        }
+        drop(checkpoints);
+        checkpoint
+    }
+
+    /// The checkpoint of the latest contribution.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the chain lock is poisoned.
+    #[must_use]
+    pub fn latest(&self) -> Option<Checkpoint> {
+        self.checkpoints.read().unwrap().last().copied()
+    }
+
+    /// The checkpoint after contribution `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the chain lock is poisoned.
+    #[must_use]
+    pub fn get(&self, index: usize) -> Option<Checkpoint> {
+        let checkpoints = self.checkpoints.read().unwrap();
+        checkpoints
+            .binary_search_by_key(&index, |checkpoint| checkpoint.index)
+            .ok()
+            .map(|i| checkpoints[i])
+    }
+
+    /// The latest checkpoint with transcript or chain hash `hash`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the chain lock is poisoned.
+    #[must_use]
+    pub fn find(&self, hash: &[u8; 32]) -> Option<Checkpoint> {
+        self.checkpoints
+            .read()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|checkpoint| {
+                &checkpoint.transcript_hash == hash || &checkpoint.chain_hash == hash
+            })
+            .copied()
+    }
+
+    /// All checkpoints in order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the chain lock is poisoned.
+    #[must_use]
+    pub fn all(&self) -> Vec<Checkpoint> {
+        self.checkpoints.read().unwrap().clone()
+    }
+
+    fn persist(&self, checkpoint: &Checkpoint) -> EyreResult<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(checkpoint)?)?;
+        file.sync_all()?;
+        Ok(())
+    }
+}
+
+/// Read and check the chain stored at `path`.
+fn read_chain(path: &Path) -> EyreResult<Vec<Checkpoint>> {
+    let mut checkpoints: Vec<Checkpoint> = Vec::new();
+    if path.exists() {
+        let contents = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Could not read {}", path.display()))?;
+        for line in contents.lines().filter(|line| !line.is_empty()) {
+            let checkpoint: Checkpoint = serde_json::from_str(line)?;
+            ensure!(
+                checkpoint.follows(checkpoints.last()),
+                "Hash chain is broken at contribution {}",
+                checkpoint.index
+            );
+            checkpoints.push(checkpoint);
+        }
+    }
+    Ok(checkpoints)
+}
+
+fn chain_hash(previous: Option<&Checkpoint>, index: usize, transcript_hash: &[u8; 32]) -> [u8; 32] {
+    Sha256::new()
+        .chain_update(previous.map_or([0; 32], |previous| previous.chain_hash))
+        .chain_update((index as u64).to_le_bytes())
+        .chain_update(transcript_hash)
+        .finalize()
+        .into()
+}
+
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn latest(Extension(chain): Extension<HashChain>) -> Json<Option<Checkpoint>> {
+    Json(chain.latest())
+}
+
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn list(Extension(chain): Extension<HashChain>) -> Json<Vec<Checkpoint>> {
+    Json(chain.all())
+}
+
+/// The checkpoint after contribution `index`.
+///
+/// # Errors
+///
+/// Returns [`CheckpointError::NotFound`] if there is no such checkpoint.
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn get(
+    UrlPath(index): UrlPath<usize>,
+    Extension(chain): Extension<HashChain>,
+) -> Result<Json<Checkpoint>, CheckpointError> {
+    chain
+        .get(index)
+        .map(Json)
+        .ok_or(CheckpointError::NotFound(index))
+}
+
+impl IntoResponse for CheckpointError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+        };
+        (status, Json(json!({ "error": self.to_string() }))).into_response()
+    }
+}
+
+/// Hashes as `0x` prefixed hex strings.
+mod hash_hex {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(hash: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{}", hex::encode(hash)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 32], D::Error> {
+        let string = String::deserialize(deserializer)?;
+        let hex = string
+            .strip_prefix("0x")
+            .ok_or_else(|| D::Error::custom("missing 0x prefix"))?;
+        let mut hash = [0; 32];
+        hex::decode_to_slice(hex, &mut hash).map_err(D::Error::custom)?;
+        Ok(hash)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test::TestDir;
+
+    #[test]
+    fn test_hash_chain() {
+        let dir = TestDir::new("hash-chain");
+        fs::create_dir_all(&dir).unwrap();
+
+        let chain = HashChain::open(&dir, 0, [0; 32]).unwrap();
+        chain.append(1, [1; 32]);
+        chain.append(2, [2; 32]);
+        let latest = chain.latest().unwrap();
+        assert_eq!(latest.index, 2);
+        assert!(latest.follows(chain.get(1).as_ref()));
+        assert!(!latest.follows(chain.get(0).as_ref()));
+
+        // Reopening resumes after a gap.
+        let chain = HashChain::open(&dir, 4, [4; 32]).unwrap();
+        assert_eq!(chain.all().len(), 4);
+        assert_eq!(chain.get(3), None);
+        assert_eq!(chain.get(4).unwrap().transcript_hash, [4; 32]);
+        assert!(HashChain::open(&dir, 4, [5; 32]).is_err());
+        assert!(HashChain::open(&dir, 3, [3; 32]).is_err());
+
+        let json = serde_json::to_value(chain.get(0).unwrap()).unwrap();
+        assert_eq!(json["transcriptHash"], format!("0x{}", "00".repeat(32)));
+
+        // Tampering with a transcript hash breaks the chain.
+        let path = dir.join(CHAIN);
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::write(
+            &path,
+            contents.replacen(&"01".repeat(32), &"ff".repeat(32), 1),
+        )
+        .unwrap();
+        assert!(HashChain::open(&dir, 4, [4; 32]).is_err());
+    }
+}
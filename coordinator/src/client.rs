@@ -0,0 +1,619 @@
+//! Contributor client for the coordinator API.
+//!
+//! [`CeremonyClient`] drives a coordinator the way a contributor does: join
+//! the queue, wait for the contribution slot, download the current powers,
+//! add a secret derived from the contributor's entropy and upload the result.
+//!
+//! Sign-in happens in the browser with GitHub or an Ethereum wallet; the
+//! resulting session token is handed to [`CeremonyClient::login`].
+//!
+//! Responses are requested gzip compressed, and single request contribution
+//! uploads are sent gzip compressed.
+
+use crate::{
+    auth::{LoginResponse, NonceResponse},
+    challenge::{self, Challenge, ChallengeError, JoinRequest},
+    compression::Encoding,
+    diff::TranscriptDiff,
+    entropy::Entropy,
+    participants::{ContributionDetails, NameRequest, Participant},
+    queue::{AbortResponse, Checkin, Position, QueueError},
+    receipt::SignedReceipt,
+    resumable::{InitRequest, Progress},
+    session::{Session, SessionError},
+    test_ceremony::LoginRequest,
+    transcript::TranscriptDecodeError,
+};
+use cli_batteries::trace_to_headers;
+use hyper::{
+    body::{to_bytes, Bytes},
+    client::HttpConnector,
+    header::{HeaderValue, ACCEPT_ENCODING, AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE},
+    Body, Client, Method, Request, StatusCode,
+};
+use kzg_ceremony_crypto::{ContributionsError, ContributionsJson, SanityChecks, SIZES};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+use std::{cmp::min, time::Duration};
+use thiserror::Error;
+use tokio::{task::spawn_blocking, time::sleep};
+use tracing::{info, instrument};
+use url::Url;
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Failed chunks in a row before a resumable upload gives up.
+const MAX_RETRIES: usize = 3;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("Invalid url: {0}")]
+    Url(#[from] url::ParseError),
+    #[error("Request failed: {0}")]
+    Http(#[from] hyper::Error),
+    #[error("Invalid request: {0}")]
+    Request(#[from] hyper::http::Error),
+    #[error("Coordinator responded with {0}: {1}")]
+    Status(StatusCode, String),
+    #[error("Could not decompress response: {0}")]
+    Decompress(std::io::Error),
+    #[error("Invalid response: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid session token: {0}")]
+    Session(#[from] SessionError),
+    #[error("Not logged in")]
+    NotLoggedIn,
+    #[error("Invalid transcript: {0}")]
+    Transcript(#[from] TranscriptDecodeError),
+    #[error("Invalid powers: {0}")]
+    Powers(#[from] ContributionsError),
+}
+
+/// Client for a coordinator at a base url.
+#[derive(Clone, Debug)]
+pub struct CeremonyClient {
+    client:        Client<HttpConnector>,
+    base:          Url,
+    session:       Option<(String, Session)>,
+    poll_interval: Duration,
+    sizes:         Vec<(usize, usize)>,
+    chunk_size:    Option<usize>,
+}
+
+impl CeremonyClient {
+    #[must_use]
+    pub fn new(mut base: Url) -> Self {
+        // Relative paths are resolved against the last path segment.
+        if !base.path().ends_with('/') {
+            base.set_path(&format!("{}/", base.path()));
+        }
+        Self {
+            client: Client::new(),
+            base,
+            session: None,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            sizes: SIZES.to_vec(),
+            chunk_size: None,
+        }
+    }
+
+    /// Set how often [`Self::await_turn`] asks for the slot.
+    #[must_use]
+    pub const fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Set the sub-ceremony sizes of the coordinator, [`SIZES`] by default.
+    #[must_use]
+    pub fn with_sizes(mut self, sizes: Vec<(usize, usize)>) -> Self {
+        self.sizes = sizes;
+        self
+    }
+
+    /// Upload contributions in chunks of `chunk_size` bytes, see
+    /// [`crate::resumable`], instead of in one request.
+    #[must_use]
+    pub const fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = Some(chunk_size);
+        self
+    }
+
+    /// Use the session token obtained from signing in.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ClientError::Session`] if `token` is not a session token.
+    pub fn login(&mut self, token: &str) -> Result<&Session, ClientError> {
+        let session = Session::decode_unverified(token)?;
+        info!(identity = %session.identity, "Logged in");
+        Ok(&self.session.insert((token.to_string(), session)).1)
+    }
+
+    /// Sign in with the `provider` specific `credentials`, see
+    /// [`crate::auth`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ClientError::Status`] if the coordinator rejects the
+    /// credentials, or another [`ClientError`] if the request fails.
+    #[instrument(level = "info", skip(self, credentials))]
+    pub async fn login_with<T: Serialize + Sync>(
+        &mut self,
+        provider: &str,
+        credentials: &T,
+    ) -> Result<&Session, ClientError> {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(self.base.join("login/")?.join(provider)?.as_str())
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_vec(credentials)?))?;
+        let response: LoginResponse = serde_json::from_slice(&self.send(request).await?)?;
+        self.login(&response.token)
+    }
+
+    /// A nonce to include in the credentials of a `provider`, e.g. the
+    /// message nonce of Sign-In with Ethereum, see [`crate::auth`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ClientError::Status`] if the provider takes no nonces, or
+    /// another [`ClientError`] if the request fails.
+    pub async fn login_nonce(&self, provider: &str) -> Result<String, ClientError> {
+        let path = format!("login/{provider}/nonce");
+        let response: NonceResponse = serde_json::from_slice(&self.get(&path).await?)?;
+        Ok(response.nonce)
+    }
+
+    /// Get a session token from a test ceremony, see [`crate::test_ceremony`].
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ClientError`] if the coordinator is not a test ceremony or
+    /// the request fails.
+    pub async fn test_login(&mut self, identity: Option<&str>) -> Result<&Session, ClientError> {
+        self.login_with("mock", &LoginRequest {
+            identity: identity.map(ToString::to_string),
+        })
+        .await
+    }
+
+    /// End the session, revoking its token, see [`crate::revocation`].
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ClientError`] if the request fails.
+    #[instrument(level = "info", skip(self))]
+    pub async fn logout(&mut self) -> Result<(), ClientError> {
+        self.post("logout", Body::empty()).await?;
+        self.session = None;
+        Ok(())
+    }
+
+    /// Join the queue, solving the proof of work challenge if the coordinator
+    /// requires one.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ClientError::Status`] if the coordinator does not admit us, or
+    /// another [`ClientError`] if the request fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics if solving the challenge panicked.
+    #[instrument(level = "info", skip(self))]
+    pub async fn join_queue(&self) -> Result<Position, ClientError> {
+        let required = ChallengeError::Required.to_string();
+        match self.post_json("queue/join", Body::empty()).await {
+            Err(ClientError::Status(StatusCode::FORBIDDEN, message))
+                if message.contains(&required) =>
+            {
+                let challenge: Challenge = self.post_json("queue/challenge", Body::empty()).await?;
+                info!(difficulty = challenge.difficulty, "Solving join challenge");
+                let pow = spawn_blocking(move || challenge::solve(&challenge))
+                    .await
+                    .expect("Challenge task panicked");
+                let body = serde_json::to_vec(&JoinRequest {
+                    pow:     Some(pow),
+                    captcha: None,
+                })?;
+                self.post_json("queue/join", Body::from(body)).await
+            }
+            result => result,
+        }
+    }
+
+    /// Stay in the lottery, see [`crate::queue::QueueMode::Lottery`].
+    /// Polling [`Self::await_turn`] also checks in.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ClientError::Status`] if we are not queued, or another
+    /// [`ClientError`] if the request fails.
+    #[instrument(level = "info", skip(self))]
+    pub async fn checkin(&self) -> Result<Checkin, ClientError> {
+        self.post_json("queue/checkin", Body::empty()).await
+    }
+
+    /// Leave the queue.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ClientError::Status`] if we are not queued, or another
+    /// [`ClientError`] if the request fails.
+    #[instrument(level = "info", skip(self))]
+    pub async fn leave_queue(&self) -> Result<(), ClientError> {
+        self.post("queue/leave", Body::empty()).await?;
+        Ok(())
+    }
+
+    /// Wait until the contribution slot is ours.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ClientError::Status`] if we are no longer queued, or another
+    /// [`ClientError`] if a request fails.
+    #[instrument(level = "info", skip(self))]
+    pub async fn await_turn(&self) -> Result<(), ClientError> {
+        let not_queued = QueueError::NotQueued.to_string();
+        loop {
+            match self.post("contribution/start", Body::empty()).await {
+                Ok(_) => return Ok(()),
+                Err(ClientError::Status(
+                    StatusCode::CONFLICT | StatusCode::SERVICE_UNAVAILABLE,
+                    message,
+                )) if !message.contains(&not_queued) => {}
+                Err(error) => return Err(error),
+            }
+            sleep(self.poll_interval).await;
+        }
+    }
+
+    /// Contribute secrets derived from `entropy` and return the receipt.
+    ///
+    /// Must hold the contribution slot, see [`Self::await_turn`].
+    ///
+    /// # Errors
+    ///
+    /// See [`Self::contribute_subset`].
+    pub async fn contribute(&self, entropy: &Entropy) -> Result<SignedReceipt, ClientError> {
+        let all = (0..self.sizes.len()).collect::<Vec<_>>();
+        self.contribute_subset(entropy, &all).await
+    }
+
+    /// Contribute to the sub-ceremonies with indices in `sub_ceremonies` only.
+    ///
+    /// Must hold the contribution slot, see [`Self::await_turn`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ClientError::Powers`] if the current powers are invalid,
+    /// [`ClientError::Status`] if the coordinator rejects the contribution, or
+    /// another [`ClientError`] if a request fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics if computing the contribution panicked.
+    #[instrument(level = "info", skip(self, entropy))]
+    pub async fn contribute_subset(
+        &self,
+        entropy: &Entropy,
+        sub_ceremonies: &[usize],
+    ) -> Result<SignedReceipt, ClientError> {
+        let identity = self.session()?.1.identity.clone();
+        // A tentative slot builds on the contribution pending verification.
+        let powers = match self.pending_powers().await? {
+            Some(powers) => powers,
+            None => self.powers().await?,
+        };
+        let entropy = entropy.clone();
+        let sub_ceremonies = sub_ceremonies.to_vec();
+        let sizes = self.sizes.clone();
+        let encoding = if self.chunk_size.is_some() {
+            Encoding::Identity
+        } else {
+            Encoding::Gzip
+        };
+        let upload = spawn_blocking(move || {
+            // The current powers are the initial ones before the first contribution.
+            let contributions = powers
+                .parse_with_sizes(&sizes, SanityChecks::INITIAL)?
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| sub_ceremonies.contains(i))
+                .map(|(i, mut contribution)| {
+                    let tau = entropy.tau(i);
+                    contribution.add_tau(&tau);
+                    contribution.sign_identity(&tau, &identity);
+                    contribution.prove_possession(&tau, &identity);
+                    contribution
+                })
+                .collect::<Vec<_>>();
+            let json = ContributionsJson::from_contributions(&contributions);
+            Ok::<_, ClientError>(encoding.compress(&serde_json::to_vec(&json)?))
+        })
+        .await
+        .expect("Contribution task panicked")?;
+        if let Some(chunk_size) = self.chunk_size {
+            return self.upload_resumable(&upload, chunk_size).await;
+        }
+        let bytes = self
+            .post_encoded("contribution/complete", Body::from(upload), encoding)
+            .await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// Upload in chunks, resuming where the coordinator left off after a
+    /// failed request.
+    #[instrument(level = "info", skip(self, upload))]
+    async fn upload_resumable(
+        &self,
+        upload: &[u8],
+        chunk_size: usize,
+    ) -> Result<SignedReceipt, ClientError> {
+        let init = serde_json::to_vec(&InitRequest {
+            size:   upload.len(),
+            sha256: format!("0x{}", hex::encode(Sha256::digest(upload))),
+        })?;
+        let mut progress: Progress = self
+            .post_json("contribution/upload/init", Body::from(init.clone()))
+            .await?;
+        let mut retries = 0;
+        while progress.received < progress.size {
+            let chunk =
+                &upload[progress.received..min(progress.received + chunk_size, upload.len())];
+            let path = format!(
+                "contribution/upload/chunk?uploadId={}&offset={}&sha256=0x{}",
+                progress.upload_id,
+                progress.received,
+                hex::encode(Sha256::digest(chunk))
+            );
+            match self.post_json(&path, Body::from(chunk.to_vec())).await {
+                Ok(next) => {
+                    progress = next;
+                    retries = 0;
+                }
+                Err(ClientError::Http(error)) if retries < MAX_RETRIES => {
+                    info!(?error, "Chunk failed, resuming");
+                    retries += 1;
+                    sleep(self.poll_interval).await;
+                    progress = self
+                        .post_json("contribution/upload/init", Body::from(init.clone()))
+                        .await?;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        let path = format!("contribution/upload/finish?uploadId={}", progress.upload_id);
+        self.post_json(&path, Body::empty()).await
+    }
+
+    /// Download the full transcript, see [`crate::transcript::decode_all`].
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ClientError`] if the request fails.
+    #[instrument(level = "info", skip(self))]
+    pub async fn transcript(&self) -> Result<Bytes, ClientError> {
+        self.get("ceremony/transcript").await
+    }
+
+    /// Download the current powers only, see [`crate::transcript::Format`].
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ClientError`] if the request fails or the powers are not
+    /// valid JSON.
+    #[instrument(level = "info", skip(self))]
+    pub async fn powers(&self) -> Result<ContributionsJson, ClientError> {
+        let bytes = self.get("ceremony/transcript?format=powers").await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// The powers of the contribution pending verification, if the slot was
+    /// taken while it is verified.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ClientError::NotLoggedIn`] without a session, or another
+    /// [`ClientError`] if the request fails.
+    #[instrument(level = "info", skip(self))]
+    pub async fn pending_powers(&self) -> Result<Option<ContributionsJson>, ClientError> {
+        let (token, _) = self.session()?;
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(self.base.join("contribution/pending")?.as_str())
+            .header(AUTHORIZATION, format!("Bearer {token}"))
+            .body(Body::empty())?;
+        match self.send(request).await {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(ClientError::Status(StatusCode::NOT_FOUND, _)) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Download the contributions since the checkpoint with hash `since`, see
+    /// [`crate::diff`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ClientError::Status`] if `since` is not a checkpoint, or
+    /// another [`ClientError`] if the request fails.
+    #[instrument(level = "info", skip(self))]
+    pub async fn transcript_diff(&self, since: &[u8; 32]) -> Result<TranscriptDiff, ClientError> {
+        let path = format!("ceremony/transcript/diff?since=0x{}", hex::encode(since));
+        let bytes = self.get(&path).await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// The public list of contributions, see [`crate::participants`].
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ClientError`] if the request fails.
+    #[instrument(level = "info", skip(self))]
+    pub async fn participants(&self) -> Result<Vec<Participant>, ClientError> {
+        let bytes = self.get("ceremony/participants").await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// Contribution `index` with the data needed to audit it, see
+    /// [`crate::participants`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ClientError::Status`] if there is no such contribution, or
+    /// another [`ClientError`] if the request fails.
+    #[instrument(level = "info", skip(self))]
+    pub async fn contribution(&self, index: usize) -> Result<ContributionDetails, ClientError> {
+        let bytes = self.get(&format!("ceremony/contribution/{index}")).await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// Opt in to a public display name, or out with `None`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ClientError::Status`] if the name is rejected, or another
+    /// [`ClientError`] if the request fails.
+    #[instrument(level = "info", skip(self))]
+    pub async fn set_display_name(&self, name: Option<&str>) -> Result<(), ClientError> {
+        let request = NameRequest {
+            display_name: name.map(str::to_string),
+        };
+        let body = serde_json::to_vec(&request)?;
+        self.post("ceremony/participants/name", body.into()).await?;
+        Ok(())
+    }
+
+    /// Give up the contribution slot.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ClientError::Status`] if we do not hold the slot, or another
+    /// [`ClientError`] if the request fails.
+    #[instrument(level = "info", skip(self))]
+    pub async fn abort(&self) -> Result<AbortResponse, ClientError> {
+        self.post_json("contribution/abort", Body::empty()).await
+    }
+
+    fn session(&self) -> Result<&(String, Session), ClientError> {
+        self.session.as_ref().ok_or(ClientError::NotLoggedIn)
+    }
+
+    async fn get(&self, path: &str) -> Result<Bytes, ClientError> {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(self.base.join(path)?.as_str())
+            .body(Body::empty())?;
+        self.send(request).await
+    }
+
+    async fn post(&self, path: &str, body: Body) -> Result<Bytes, ClientError> {
+        self.post_encoded(path, body, Encoding::Identity).await
+    }
+
+    async fn post_encoded(
+        &self,
+        path: &str,
+        body: Body,
+        encoding: Encoding,
+    ) -> Result<Bytes, ClientError> {
+        let (token, _) = self.session()?;
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(self.base.join(path)?.as_str())
+            .header(AUTHORIZATION, format!("Bearer {token}"))
+            .header(CONTENT_TYPE, "application/json");
+        if encoding != Encoding::Identity {
+            request = request.header(CONTENT_ENCODING, encoding.as_str());
+        }
+        self.send(request.body(body)?).await
+    }
+
+    async fn post_json<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: Body,
+    ) -> Result<T, ClientError> {
+        let bytes = self.post(path, body).await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    async fn send(&self, mut request: Request<Body>) -> Result<Bytes, ClientError> {
+        trace_to_headers(request.headers_mut());
+        request
+            .headers_mut()
+            .insert(ACCEPT_ENCODING, HeaderValue::from_static("gzip"));
+        let response = self.client.request(request).await?;
+        let status = response.status();
+        let encoding = response
+            .headers()
+            .get(CONTENT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Encoding::from_content_encoding)
+            .unwrap_or(Encoding::Identity);
+        let mut body = to_bytes(response.into_body()).await?;
+        if encoding != Encoding::Identity {
+            body = encoding
+                .decompress(&body, usize::MAX)
+                .map_err(ClientError::Decompress)?
+                .into();
+        }
+        if !status.is_success() {
+            return Err(ClientError::Status(
+                status,
+                String::from_utf8_lossy(&body).into_owned(),
+            ));
+        }
+        Ok(body)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{app, test::TestDir, Options};
+    use axum::Server;
+    use clap::Parser;
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    #[tokio::test]
+    async fn test_client() {
+        let dir = TestDir::new("client");
+        let session_key = "0x".to_string() + &"42".repeat(32);
+        let options = Options::parse_from([
+            "coordinator",
+            "--transcript-dir",
+            dir.to_str().unwrap(),
+            "--session-key",
+            &session_key,
+        ]);
+        let app = app(&options).await.unwrap();
+        let server = Server::bind(&SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0))
+            .serve(app.into_make_service());
+        let url: Url = format!("http://{}/", server.local_addr()).parse().unwrap();
+        tokio::spawn(server);
+
+        let token = options
+            .session_key
+            .unwrap()
+            .issue("git|1|recmo", Duration::from_secs(60));
+        let mut client = CeremonyClient::new(url).with_poll_interval(Duration::from_millis(10));
+        assert!(matches!(
+            client.join_queue().await,
+            Err(ClientError::NotLoggedIn)
+        ));
+        client.login(&token).unwrap();
+        assert_eq!(client.join_queue().await.unwrap().position, 0);
+        client.await_turn().await.unwrap();
+        let response = client.abort().await.unwrap();
+        assert_eq!(response.aborts, 1);
+
+        // Not being in the queue is an error, not a reason to wait.
+        assert!(matches!(
+            client.await_turn().await,
+            Err(ClientError::Status(StatusCode::CONFLICT, _))
+        ));
+    }
+}
@@ -0,0 +1,296 @@
+//! Post-quantum commitments to contributions.
+//!
+//! Receipts are signed with secp256k1 and the transcript itself relies on
+//! pairings. To keep the audit trail verifiable if those assumptions fall,
+//! receipts can additionally be signed with a hash-based signature.
+//!
+//! Signers are pluggable through [`CommitmentSigner`]. The built-in
+//! [`MerkleLamport`] signer is a Merkle tree of Lamport one-time keys over
+//! SHA-256. Each contribution index is signed with its own leaf, so a key is
+//! never used twice and no signer state needs to be persisted. The receipt of
+//! the last contribution commits to the final transcript.
+
+use eyre::{eyre, Error as EyreError};
+use rand::Rng;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::{fmt, str::FromStr};
+use thiserror::Error;
+use tracing::{info, instrument};
+
+/// Number of message bits signed by a Lamport key.
+const BITS: usize = 256;
+
+/// Size of an encoded [`MerkleLamport`] signature without the auth path.
+const LAMPORT_SIZE: usize = 8 + 2 * BITS * 32;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum CommitmentError {
+    #[error("Signing key {0} is out of range, the signer has {1} keys")]
+    IndexOutOfRange(usize, usize),
+    #[error("Invalid signature encoding")]
+    InvalidEncoding,
+    #[error("Signature does not match public key")]
+    InvalidSignature,
+}
+
+/// A hash-based signer for contribution commitments.
+pub trait CommitmentSigner: fmt::Debug + Send + Sync {
+    /// Identifier of the signature scheme.
+    fn scheme(&self) -> &'static str;
+
+    /// Public key as `0x` prefixed hex.
+    fn public_key(&self) -> String;
+
+    /// Sign `message` with one-time key `index`, returning `0x` prefixed hex.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CommitmentError::IndexOutOfRange`] if there is no key `index`.
+    fn sign(&self, index: usize, message: &[u8]) -> Result<String, CommitmentError>;
+}
+
+/// Secret seed from which all one-time keys are derived.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Seed([u8; 32]);
+
+/// Merkle tree of Lamport one-time keys.
+pub struct MerkleLamport {
+    seed:   Seed,
+    height: usize,
+    /// Tree nodes in heap order: root at 1, children of `i` at `2i` and
+    /// `2i + 1`, leaves from `2^height`.
+    nodes:  Vec<[u8; 32]>,
+}
+
+impl Seed {
+    #[must_use]
+    pub fn random() -> Self {
+        Self(rand::thread_rng().gen())
+    }
+}
+
+impl fmt::Debug for Seed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Seed(..)")
+    }
+}
+
+impl FromStr for Seed {
+    type Err = EyreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut seed = [0; 32];
+        hex::decode_to_slice(s.strip_prefix("0x").unwrap_or(s), &mut seed)
+            .map_err(|_| eyre!("Seed must be 32 bytes of hex"))?;
+        Ok(Self(seed))
+    }
+}
+
+impl MerkleLamport {
+    pub const SCHEME: &'static str = "merkle-lamport-sha256";
+
+    /// Derive the key tree for `2^height` signatures from `seed`.
+    ///
+    /// This computes all public keys up front, which takes a while for large
+    /// heights.
+    #[must_use]
+    #[allow(clippy::disallowed_methods)] // False positive from macro
+    #[instrument(level = "info", skip(seed))]
+    pub fn new(seed: Seed, height: usize) -> Self {
+        let num_leaves = 1 << height;
+        let mut nodes = vec![[0; 32]; 2 * num_leaves];
+        nodes[num_leaves..]
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(index, node)| *node = leaf_hash(&public_hashes(&seed, index)));
+        for i in (1..num_leaves).rev() {
+            nodes[i] = node_hash(&nodes[2 * i], &nodes[2 * i + 1]);
+        }
+        info!(root = %hex::encode(nodes[1]), "Commitment key tree ready");
+        Self {
+            seed,
+            height,
+            nodes,
+        }
+    }
+
+    #[must_use]
+    pub fn root(&self) -> [u8; 32] {
+        self.nodes[1]
+    }
+
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        1 << self.height
+    }
+}
+
+impl fmt::Debug for MerkleLamport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MerkleLamport")
+            .field("height", &self.height)
+            .field("root", &hex::encode(self.root()))
+            .finish_non_exhaustive()
+    }
+}
+
+impl CommitmentSigner for MerkleLamport {
+    fn scheme(&self) -> &'static str {
+        Self::SCHEME
+    }
+
+    fn public_key(&self) -> String {
+        format!("0x{}", hex::encode(self.root()))
+    }
+
+    /// The signature is the leaf index, for every message bit the revealed
+    /// secret and the hash of the unrevealed one, followed by the auth path.
+    fn sign(&self, index: usize, message: &[u8]) -> Result<String, CommitmentError> {
+        if index >= self.capacity() {
+            return Err(CommitmentError::IndexOutOfRange(index, self.capacity()));
+        }
+        let digest = Sha256::digest(message);
+        let mut signature = Vec::with_capacity(LAMPORT_SIZE + self.height * 32);
+        signature.extend_from_slice(&(index as u64).to_be_bytes());
+        for bit in 0..BITS {
+            let revealed = message_bit(&digest, bit);
+            signature.extend_from_slice(&secret(&self.seed, index, bit, revealed));
+        }
+        for bit in 0..BITS {
+            let hidden = !message_bit(&digest, bit);
+            signature.extend_from_slice(&Sha256::digest(secret(&self.seed, index, bit, hidden)));
+        }
+        let mut node = self.capacity() + index;
+        while node > 1 {
+            signature.extend_from_slice(&self.nodes[node ^ 1]);
+            node /= 2;
+        }
+        Ok(format!("0x{}", hex::encode(signature)))
+    }
+}
+
+/// Verify a [`MerkleLamport`] signature against `public_key` and return the
+/// index of the one-time key used.
+///
+/// # Errors
+///
+/// Returns [`CommitmentError::InvalidEncoding`] if `public_key` or `signature`
+/// is malformed and [`CommitmentError::InvalidSignature`] if they do not match.
+///
+/// # Panics
+///
+/// Never, the length of `signature` is checked before it is split.
+pub fn verify(public_key: &str, message: &[u8], signature: &str) -> Result<usize, CommitmentError> {
+    let mut root = [0; 32];
+    hex::decode_to_slice(
+        public_key.strip_prefix("0x").unwrap_or(public_key),
+        &mut root,
+    )
+    .map_err(|_| CommitmentError::InvalidEncoding)?;
+    let signature = hex::decode(signature.strip_prefix("0x").unwrap_or(signature))
+        .map_err(|_| CommitmentError::InvalidEncoding)?;
+    if signature.len() < LAMPORT_SIZE || (signature.len() - LAMPORT_SIZE) % 32 != 0 {
+        return Err(CommitmentError::InvalidEncoding);
+    }
+    let index = u64::from_be_bytes(signature[..8].try_into().unwrap());
+    let index = usize::try_from(index).map_err(|_| CommitmentError::InvalidEncoding)?;
+    let (revealed, rest) = signature[8..].split_at(BITS * 32);
+    let (hidden, path) = rest.split_at(BITS * 32);
+    if path.len() / 32 < usize::BITS as usize && index >> (path.len() / 32) != 0 {
+        return Err(CommitmentError::InvalidEncoding);
+    }
+
+    // Reconstruct the Lamport public key and walk up to the root.
+    let digest = Sha256::digest(message);
+    let mut hashes = [[0; 32]; 2 * BITS];
+    for bit in 0..BITS {
+        let set = usize::from(message_bit(&digest, bit));
+        hashes[2 * bit + set] = Sha256::digest(&revealed[bit * 32..(bit + 1) * 32]).into();
+        hashes[2 * bit + 1 - set].copy_from_slice(&hidden[bit * 32..(bit + 1) * 32]);
+    }
+    let mut node = leaf_hash(&hashes);
+    for (level, sibling) in path.chunks_exact(32).enumerate() {
+        let sibling: [u8; 32] = sibling.try_into().unwrap();
+        node = if (index >> level) & 1 == 0 {
+            node_hash(&node, &sibling)
+        } else {
+            node_hash(&sibling, &node)
+        };
+    }
+    if node != root {
+        return Err(CommitmentError::InvalidSignature);
+    }
+    Ok(index)
+}
+
+/// Hashes of both secrets for every bit of one-time key `index`.
+fn public_hashes(seed: &Seed, index: usize) -> [[u8; 32]; 2 * BITS] {
+    let mut hashes = [[0; 32]; 2 * BITS];
+    for (i, hash) in hashes.iter_mut().enumerate() {
+        *hash = Sha256::digest(secret(seed, index, i / 2, i % 2 == 1)).into();
+    }
+    hashes
+}
+
+fn secret(seed: &Seed, index: usize, bit: usize, value: bool) -> [u8; 32] {
+    Sha256::new()
+        .chain_update(b"lamport")
+        .chain_update(seed.0)
+        .chain_update((index as u64).to_be_bytes())
+        .chain_update((bit as u64).to_be_bytes())
+        .chain_update([u8::from(value)])
+        .finalize()
+        .into()
+}
+
+fn message_bit(digest: &[u8], bit: usize) -> bool {
+    (digest[bit / 8] >> (7 - bit % 8)) & 1 == 1
+}
+
+fn leaf_hash(public: &[[u8; 32]; 2 * BITS]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([0]);
+    for hash in public {
+        hasher.update(hash);
+    }
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    Sha256::new()
+        .chain_update([1])
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_sign_verify() {
+        let signer = MerkleLamport::new(Seed::random(), 3);
+        let public_key = signer.public_key();
+        for index in 0..signer.capacity() {
+            let signature = signer.sign(index, b"receipt").unwrap();
+            assert_eq!(verify(&public_key, b"receipt", &signature), Ok(index));
+            assert_eq!(
+                verify(&public_key, b"tampered", &signature),
+                Err(CommitmentError::InvalidSignature)
+            );
+        }
+        assert_eq!(
+            signer.sign(8, b"receipt"),
+            Err(CommitmentError::IndexOutOfRange(8, 8))
+        );
+        let other = MerkleLamport::new(Seed::random(), 3).public_key();
+        let signature = signer.sign(0, b"receipt").unwrap();
+        assert_eq!(
+            verify(&other, b"receipt", &signature),
+            Err(CommitmentError::InvalidSignature)
+        );
+    }
+}
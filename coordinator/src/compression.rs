@@ -0,0 +1,291 @@
+//! HTTP compression.
+//!
+//! JSON and text responses of known size are compressed with gzip or deflate
+//! if the client accepts it, see [`middleware`]. The full transcript is
+//! compressed by its handler, which caches the result, see
+//! [`crate::transcript`].
+//!
+//! Uploads may be sent with `Content-Encoding: gzip` or `deflate`. The upload
+//! size limit applies before and after decompression. Every response lists
+//! the encodings accepted for uploads in its `Accept-Encoding` header, as in
+//! RFC 7694.
+//!
+//! The sizes of compressed bodies before and after compression are counted in
+//! the `compression_decoded_bytes_total` and `compression_encoded_bytes_total`
+//! metrics, by direction and encoding.
+
+use axum::{
+    body::{boxed, Body, Full, HttpBody},
+    http::{
+        header::{
+            ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, VARY,
+        },
+        HeaderValue, Request, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use flate2::{
+    read::{DeflateDecoder, GzDecoder},
+    write::{DeflateEncoder, GzEncoder},
+    Compression,
+};
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use std::io::{self, Read, Write};
+use tracing::warn;
+
+/// Encodings accepted for uploads, as an `Accept-Encoding` header value.
+pub const ACCEPTED: &str = "gzip, deflate";
+
+/// Responses smaller than this are not worth compressing.
+const MIN_SIZE: u64 = 1024;
+
+/// Content types of responses that are compressed.
+const COMPRESSIBLE: [&str; 4] = [
+    "application/json",
+    "application/x-ndjson",
+    "text/plain",
+    "text/csv",
+];
+
+static DECODED_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "compression_decoded_bytes_total",
+        "Size before compression of compressed bodies.",
+        &["direction", "encoding"]
+    )
+    .unwrap()
+});
+
+static ENCODED_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "compression_encoded_bytes_total",
+        "Size after compression of compressed bodies.",
+        &["direction", "encoding"]
+    )
+    .unwrap()
+});
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Encoding {
+    Identity,
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Identity => "identity",
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+        }
+    }
+
+    /// The encoding named in a `Content-Encoding` header, if supported.
+    #[must_use]
+    pub fn from_content_encoding(header: &str) -> Option<Self> {
+        match header.trim().to_ascii_lowercase().as_str() {
+            "identity" => Some(Self::Identity),
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            "deflate" => Some(Self::Deflate),
+            _ => None,
+        }
+    }
+
+    /// The preferred supported encoding allowed by an `Accept-Encoding`
+    /// header, gzip on ties.
+    #[must_use]
+    pub fn negotiate(header: &str) -> Self {
+        let mut gzip = None;
+        let mut deflate = None;
+        let mut any = None;
+        for coding in header.split(',') {
+            let mut parts = coding.split(';');
+            let name = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(1.0, |q| q.trim().parse::<f32>().unwrap_or(0.0));
+            match name.as_str() {
+                "gzip" | "x-gzip" => gzip = Some(quality),
+                "deflate" => deflate = Some(quality),
+                "*" => any = Some(quality),
+                _ => {}
+            }
+        }
+        let gzip = gzip.or(any).unwrap_or(0.0);
+        let deflate = deflate.or(any).unwrap_or(0.0);
+        if gzip > 0.0 && gzip >= deflate {
+            Self::Gzip
+        } else if deflate > 0.0 {
+            Self::Deflate
+        } else {
+            Self::Identity
+        }
+    }
+
+    #[must_use]
+    pub fn compress(self, bytes: &[u8]) -> Vec<u8> {
+        let result = match self {
+            Self::Identity => return bytes.to_vec(),
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(bytes).and_then(|()| encoder.finish())
+            }
+            Self::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(bytes).and_then(|()| encoder.finish())
+            }
+        };
+        result.expect("Writing to a Vec can not fail")
+    }
+
+    /// Decompress `bytes`, reading at most `limit + 1` bytes, so callers can
+    /// tell if the result exceeds `limit`.
+    pub fn decompress(self, bytes: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+        let limit = (limit as u64).saturating_add(1);
+        let mut result = Vec::new();
+        match self {
+            Self::Identity => return Ok(bytes.to_vec()),
+            Self::Gzip => GzDecoder::new(bytes).take(limit).read_to_end(&mut result)?,
+            Self::Deflate => DeflateDecoder::new(bytes)
+                .take(limit)
+                .read_to_end(&mut result)?,
+        };
+        Ok(result)
+    }
+}
+
+/// Count a compressed body in the metrics.
+pub fn record(direction: &str, encoding: Encoding, decoded: usize, encoded: usize) {
+    let labels = [direction, encoding.as_str()];
+    DECODED_BYTES
+        .with_label_values(&labels)
+        .inc_by(decoded as u64);
+    ENCODED_BYTES
+        .with_label_values(&labels)
+        .inc_by(encoded as u64);
+}
+
+/// Compress eligible responses and advertise the accepted upload encodings.
+pub async fn middleware(request: Request<Body>, next: Next<Body>) -> Response {
+    let encoding = request
+        .headers()
+        .get(ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map_or(Encoding::Identity, Encoding::negotiate);
+    let mut response = next.run(request).await;
+    response
+        .headers_mut()
+        .insert(ACCEPT_ENCODING, HeaderValue::from_static(ACCEPTED));
+    if encoding == Encoding::Identity || !is_compressible(&response) {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let bytes = match hyper::body::to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            warn!(?error, "Could not read response to compress");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let compressed = encoding.compress(&bytes);
+    record("response", encoding, bytes.len(), compressed.len());
+    parts.headers.remove(CONTENT_LENGTH);
+    parts.headers.insert(
+        CONTENT_ENCODING,
+        HeaderValue::from_static(encoding.as_str()),
+    );
+    parts
+        .headers
+        .append(VARY, HeaderValue::from_static("Accept-Encoding"));
+    Response::from_parts(parts, boxed(Full::from(compressed)))
+}
+
+/// Complete, uncompressed responses of a compressible type. Streams have no
+/// exact size and are never buffered.
+fn is_compressible(response: &Response) -> bool {
+    let headers = response.headers();
+    let is_compressible_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |value| {
+            COMPRESSIBLE.iter().any(|prefix| value.starts_with(prefix))
+        });
+    response.status() == StatusCode::OK
+        && is_compressible_type
+        && !headers.contains_key(CONTENT_ENCODING)
+        && !headers.contains_key(CONTENT_RANGE)
+        && response
+            .body()
+            .size_hint()
+            .exact()
+            .map_or(false, |len| len >= MIN_SIZE)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use axum::{middleware::from_fn, routing::get, Json, Router};
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(Encoding::negotiate("gzip, deflate, br"), Encoding::Gzip);
+        assert_eq!(Encoding::negotiate("br;q=1.0, gzip;q=0.8"), Encoding::Gzip);
+        assert_eq!(
+            Encoding::negotiate("gzip;q=0.5, deflate"),
+            Encoding::Deflate
+        );
+        assert_eq!(Encoding::negotiate("gzip;q=0"), Encoding::Identity);
+        assert_eq!(Encoding::negotiate("gzip;q=0.0, *"), Encoding::Deflate);
+        assert_eq!(Encoding::negotiate("identity"), Encoding::Identity);
+        assert_eq!(
+            Encoding::from_content_encoding("GZIP"),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(Encoding::from_content_encoding("br"), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = b"0x8000".repeat(1000);
+        for encoding in [Encoding::Gzip, Encoding::Deflate] {
+            let compressed = encoding.compress(&bytes);
+            assert_eq!(encoding.decompress(&compressed, 6000).unwrap(), bytes);
+            assert_eq!(encoding.decompress(&compressed, 100).unwrap().len(), 101);
+        }
+        assert!(Encoding::Gzip.decompress(b"not gzip", 100).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_middleware() {
+        let app = Router::new()
+            .route("/small", get(|| async { Json(json!({})) }))
+            .route("/large", get(|| async { Json(vec![0; 1000]) }))
+            .layer(from_fn(middleware));
+
+        let get = |path: &str| {
+            Request::builder()
+                .uri(path)
+                .header(ACCEPT_ENCODING, "gzip")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = app.clone().oneshot(get("/small")).await.unwrap();
+        assert_eq!(response.headers()[ACCEPT_ENCODING], ACCEPTED);
+        assert!(!response.headers().contains_key(CONTENT_ENCODING));
+
+        let response = app.oneshot(get("/large")).await.unwrap();
+        assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = Encoding::Gzip.decompress(&body, 10_000).unwrap();
+        assert_eq!(serde_json::from_slice::<Vec<u8>>(&body).unwrap(), vec![
+            0;
+            1000
+        ]);
+    }
+}
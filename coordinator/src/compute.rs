@@ -0,0 +1,205 @@
+//! Dedicated thread pool for crypto work.
+//!
+//! Verifying a contribution keeps every core busy for seconds. Run on the
+//! global rayon pool or tokio's blocking threads it competes with the HTTP
+//! server, so crypto jobs are dispatched to a [`ComputePool`] instead. Its
+//! threads also execute any nested rayon work of the job. At most
+//! `max_queued` jobs are accepted at a time, further jobs are rejected with
+//! [`ComputeError::Busy`] rather than queueing up without bound.
+//!
+//! In test and dev builds a job that panics fails with
+//! [`ComputeError::Panicked`] instead of taking down the caller. Release
+//! builds set `panic = "abort"`, so there a panicking job aborts the process
+//! and recovery relies on the write-ahead log, see [`crate::wal`].
+
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram, register_int_counter, register_int_gauge, Histogram, IntCounter, IntGauge,
+};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::Arc,
+    time::Instant,
+};
+use thiserror::Error;
+use tokio::sync::{oneshot, Semaphore};
+use tracing::{error, Span};
+
+static QUEUED: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "compute_jobs_queued",
+        "Number of crypto jobs queued or running."
+    )
+    .unwrap()
+});
+
+static REJECTED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "compute_jobs_rejected_total",
+        "Number of crypto jobs rejected because the queue was full."
+    )
+    .unwrap()
+});
+
+static WAIT_SECONDS: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "compute_job_wait_seconds",
+        "Time a crypto job waited for a thread."
+    )
+    .unwrap()
+});
+
+static RUN_SECONDS: Lazy<Histogram> =
+    Lazy::new(|| register_histogram!("compute_job_run_seconds", "Time a crypto job ran.").unwrap());
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum ComputeError {
+    #[error("Server is busy, try again later")]
+    Busy,
+    /// The job panicked. Only returned in builds that unwind on panic.
+    #[error("Internal error while processing the request")]
+    Panicked,
+}
+
+/// A job counted in [`QUEUED`], until dropped.
+struct Queued;
+
+impl Queued {
+    fn new() -> Self {
+        QUEUED.inc();
+        Self
+    }
+}
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        QUEUED.dec();
+    }
+}
+
+/// Thread pool for crypto jobs with a bounded queue.
+#[derive(Clone, Debug)]
+pub struct ComputePool {
+    pool:    Arc<ThreadPool>,
+    permits: Arc<Semaphore>,
+}
+
+impl ComputePool {
+    /// Pool with `threads` threads, or one per core if zero, accepting up to
+    /// `max_queued` jobs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the threads can not be spawned.
+    pub fn new(threads: usize, max_queued: usize) -> Result<Self, rayon::ThreadPoolBuildError> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("compute-{i}"))
+            .build()?;
+        Ok(Self {
+            pool:    Arc::new(pool),
+            permits: Arc::new(Semaphore::new(max_queued)),
+        })
+    }
+
+    /// Number of threads in the pool.
+    #[must_use]
+    pub fn threads(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+    /// Run `job` on the pool and wait for its result.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ComputeError::Busy`] if the queue is full and
+    /// [`ComputeError::Panicked`] if `job` panicked.
+    pub async fn run<T, F>(&self, job: F) -> Result<T, ComputeError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let permit = self.permits.clone().try_acquire_owned().map_err(|_| {
+            REJECTED.inc();
+            ComputeError::Busy
+        })?;
+        let queued_job = Queued::new();
+        let (sender, receiver) = oneshot::channel();
+        let queued = Instant::now();
+        // Keep verification spans under the request that submitted the job.
+        let span = Span::current();
+        self.pool.spawn(move || {
+            WAIT_SECONDS.observe(queued.elapsed().as_secs_f64());
+            let start = Instant::now();
+            let result = catch_unwind(AssertUnwindSafe(|| span.in_scope(job)));
+            RUN_SECONDS.observe(start.elapsed().as_secs_f64());
+            if result.is_err() {
+                span.in_scope(|| error!("Compute job panicked"));
+            }
+            // The job holds its slot until done, even if the caller went away.
+            drop(queued_job);
+            drop(permit);
+            let _ = sender.send(result);
+        });
+        receiver
+            .await
+            .ok()
+            .and_then(Result::ok)
+            .ok_or(ComputeError::Panicked)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::{sync::mpsc, time::Duration};
+    use tracing::Instrument;
+    use tracing_test::traced_test;
+
+    #[tokio::test]
+    async fn test_run() {
+        let pool = ComputePool::new(2, 1).unwrap();
+        assert_eq!(pool.threads(), 2);
+        let name = pool
+            .run(|| std::thread::current().name().map(String::from))
+            .await
+            .unwrap();
+        assert_eq!(
+            name.as_deref().map(|n| n.starts_with("compute-")),
+            Some(true)
+        );
+
+        // A second job is rejected while the first is queued.
+        let (sender, receiver) = mpsc::channel::<()>();
+        let blocked = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.run(move || receiver.recv().unwrap()).await }
+        });
+        while pool.permits.available_permits() > 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(pool.run(|| ()).await, Err(ComputeError::Busy));
+        sender.send(()).unwrap();
+        blocked.await.unwrap().unwrap();
+        assert_eq!(pool.run(|| 42).await, Ok(42));
+
+        // A panicking job is reported and frees its slot.
+        let panicked = pool.run(|| -> usize { panic!("job failed") }).await;
+        assert_eq!(panicked, Err(ComputeError::Panicked));
+        assert_eq!(pool.permits.available_permits(), 1);
+        assert_eq!(pool.run(|| 42).await, Ok(42));
+    }
+
+    #[tokio::test]
+    #[allow(clippy::disallowed_methods)] // False positive from macro
+    #[traced_test]
+    async fn test_span() {
+        let pool = ComputePool::new(1, 1).unwrap();
+        pool.run(|| tracing::info!("running job"))
+            .instrument(tracing::info_span!("submitter"))
+            .await
+            .unwrap();
+        assert!(logs_contain("submitter: kzg_ceremony_coordinator::compute"));
+    }
+}
@@ -0,0 +1,458 @@
+//! REST API conformance suite.
+//!
+//! Checks the wire behaviour this crate expects from a sequencer: status
+//! codes, headers and body encodings of the public endpoints. The checks only
+//! talk HTTP, so alternative sequencer implementations can run them against
+//! their own deployment with the `conformance` subcommand, or from their own
+//! tests through [`Suite`].
+//!
+//! The checks are read-only and do not require a session, so they are safe to
+//! run against a live ceremony.
+
+use ark_bls12_381::{G1Affine, G2Affine};
+use ark_ec::AffineCurve;
+use ark_serialize::CanonicalSerialize;
+use clap::Parser;
+use eyre::Result as EyreResult;
+use flate2::read::GzDecoder;
+use hyper::{
+    body::{to_bytes, Bytes},
+    client::HttpConnector,
+    header::{
+        ACCEPT_ENCODING, ACCEPT_RANGES, AUTHORIZATION, CONTENT_ENCODING, CONTENT_RANGE,
+        CONTENT_TYPE, ETAG, IF_NONE_MATCH, IF_RANGE, RANGE,
+    },
+    Body, Client, HeaderMap, Method, Request, Response, StatusCode,
+};
+use kzg_ceremony_crypto::SIZES;
+use serde::Serialize;
+use std::{fs, io::Read, path::PathBuf};
+use thiserror::Error;
+use tracing::{info, instrument, warn};
+use url::Url;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+pub struct Options {
+    /// Base url of the sequencer API.
+    pub url: Url,
+
+    /// Write the report to this file instead of stdout.
+    #[clap(long)]
+    pub report: Option<PathBuf>,
+}
+
+#[derive(Debug, Error)]
+pub enum ConformanceError {
+    #[error("Invalid url: {0}")]
+    Url(#[from] url::ParseError),
+    #[error("Request failed: {0}")]
+    Http(#[from] hyper::Error),
+    #[error("Could not build request: {0}")]
+    Request(#[from] hyper::http::Error),
+    #[error("Expected status {expected}, got {actual}")]
+    Status {
+        expected: StatusCode,
+        actual:   StatusCode,
+    },
+    #[error("Unexpected {0} header: {1:?}")]
+    Header(&'static str, Option<String>),
+    #[error("Unexpected body: {0}")]
+    Body(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub url:     String,
+    pub passed:  usize,
+    pub failed:  usize,
+    pub results: Vec<CheckReport>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckReport {
+    pub check:  &'static str,
+    pub passed: bool,
+    pub error:  Option<String>,
+}
+
+/// Conformance checks against a sequencer at a base url.
+#[derive(Clone, Debug)]
+pub struct Suite {
+    client: Client<HttpConnector>,
+    base:   Url,
+}
+
+/// # Errors
+///
+/// Returns an error if the report can not be written. Failed checks are only
+/// reported.
+pub async fn main(options: Options) -> EyreResult<()> {
+    let report = Suite::new(options.url).run().await;
+    info!(
+        passed = report.passed,
+        failed = report.failed,
+        "Conformance checks done"
+    );
+    let json = serde_json::to_string_pretty(&report)?;
+    match options.report {
+        Some(path) => fs::write(path, json)?,
+        None => println!("{json}"),
+    }
+    Ok(())
+}
+
+// The checks return the deviation they found, see [`ConformanceError`].
+#[allow(clippy::missing_errors_doc)]
+impl Suite {
+    #[must_use]
+    pub fn new(mut base: Url) -> Self {
+        // Make sure endpoints are resolved relative to the full base path.
+        if !base.path().ends_with('/') {
+            base.set_path(&format!("{}/", base.path()));
+        }
+        Self {
+            client: Client::new(),
+            base,
+        }
+    }
+
+    /// Run all checks.
+    pub async fn run(&self) -> Report {
+        let results = vec![
+            report(
+                "complete_requires_session",
+                self.complete_requires_session().await,
+            ),
+            report(
+                "complete_rejects_bad_token",
+                self.complete_rejects_bad_token().await,
+            ),
+            report("events_stream", self.events_stream().await),
+            report("transcript_full", self.transcript_full().await),
+            report("transcript_range", self.transcript_range().await),
+            report(
+                "transcript_suffix_range",
+                self.transcript_suffix_range().await,
+            ),
+            report(
+                "transcript_unsatisfiable_range",
+                self.transcript_unsatisfiable_range().await,
+            ),
+            report("transcript_not_found", self.transcript_not_found().await),
+            report("transcript_resume", self.transcript_resume().await),
+            report("transcript_gzip", self.transcript_gzip().await),
+        ];
+        let passed = results.iter().filter(|r| r.passed).count();
+        Report {
+            url: self.base.to_string(),
+            passed,
+            failed: results.len() - passed,
+            results,
+        }
+    }
+
+    /// Completing a contribution without a bearer token is unauthorized.
+    pub async fn complete_requires_session(&self) -> Result<(), ConformanceError> {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(self.url("contribution/complete")?)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from("{}"))?;
+        let (headers, body) = self.send(request, StatusCode::UNAUTHORIZED).await?;
+        expect_json_error(&headers, &body)
+    }
+
+    /// Completing a contribution with a forged bearer token is unauthorized.
+    pub async fn complete_rejects_bad_token(&self) -> Result<(), ConformanceError> {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(self.url("contribution/complete")?)
+            .header(CONTENT_TYPE, "application/json")
+            .header(AUTHORIZATION, "Bearer not-a-session-token")
+            .body(Body::from("{}"))?;
+        let (headers, body) = self.send(request, StatusCode::UNAUTHORIZED).await?;
+        expect_json_error(&headers, &body)
+    }
+
+    /// Ceremony events are served as server-sent events.
+    pub async fn events_stream(&self) -> Result<(), ConformanceError> {
+        // The stream does not end, so only look at the response head.
+        let response = self
+            .client
+            .request(self.get("ceremony/events")?.body(Body::empty())?)
+            .await?;
+        expect_status(&response, StatusCode::OK)?;
+        expect_header(response.headers(), CONTENT_TYPE.as_str(), |value| {
+            value.starts_with("text/event-stream")
+        })
+    }
+
+    /// Every sub-transcript is served in full with the expected layout.
+    pub async fn transcript_full(&self) -> Result<(), ConformanceError> {
+        for (index, &(num_g1, num_g2)) in SIZES.iter().enumerate() {
+            let body = self.transcript(index).await?;
+            let fixed = num_g1 * g1_size() + num_g2 * g2_size();
+            let per_contribution = g1_size() + g2_size();
+            if body.len() < fixed + per_contribution || (body.len() - fixed) % per_contribution != 0
+            {
+                return Err(ConformanceError::Body(format!(
+                    "Sub-transcript {} has invalid length {}",
+                    index,
+                    body.len()
+                )));
+            }
+            // The zeroth power of tau is always the generator.
+            if body[..g1_size()] != generator_bytes()[..] {
+                return Err(ConformanceError::Body(format!(
+                    "Sub-transcript {index} does not start with the G1 generator"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// A byte range is served as partial content.
+    pub async fn transcript_range(&self) -> Result<(), ConformanceError> {
+        let len = self.transcript(0).await?.len();
+        let request = self
+            .get("ceremony/transcript/0")?
+            .header(RANGE, format!("bytes=0-{}", g1_size() - 1))
+            .body(Body::empty())?;
+        let (headers, body) = self.send(request, StatusCode::PARTIAL_CONTENT).await?;
+        let expected = format!("bytes 0-{}/{}", g1_size() - 1, len);
+        expect_header(&headers, CONTENT_RANGE.as_str(), |value| value == expected)?;
+        if body[..] != generator_bytes()[..] {
+            return Err(ConformanceError::Body(
+                "Range does not contain the G1 generator".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// A suffix range is served as partial content.
+    pub async fn transcript_suffix_range(&self) -> Result<(), ConformanceError> {
+        let full = self.transcript(0).await?;
+        let request = self
+            .get("ceremony/transcript/0")?
+            .header(RANGE, format!("bytes=-{}", g2_size()))
+            .body(Body::empty())?;
+        let (headers, body) = self.send(request, StatusCode::PARTIAL_CONTENT).await?;
+        let start = full.len() - g2_size();
+        let expected = format!("bytes {}-{}/{}", start, full.len() - 1, full.len());
+        expect_header(&headers, CONTENT_RANGE.as_str(), |value| value == expected)?;
+        if body != full[start..] {
+            return Err(ConformanceError::Body(
+                "Suffix range does not match the full download".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// A range past the end is rejected with the resource length.
+    pub async fn transcript_unsatisfiable_range(&self) -> Result<(), ConformanceError> {
+        let len = self.transcript(0).await?.len();
+        let request = self
+            .get("ceremony/transcript/0")?
+            .header(RANGE, format!("bytes={len}-"))
+            .body(Body::empty())?;
+        let (headers, _) = self
+            .send(request, StatusCode::RANGE_NOT_SATISFIABLE)
+            .await?;
+        let expected = format!("bytes */{len}");
+        expect_header(&headers, CONTENT_RANGE.as_str(), |value| value == expected)
+    }
+
+    /// Sub-transcripts past the last one do not exist.
+    pub async fn transcript_not_found(&self) -> Result<(), ConformanceError> {
+        let request = self
+            .get(&format!("ceremony/transcript/{}", SIZES.len()))?
+            .body(Body::empty())?;
+        self.send(request, StatusCode::NOT_FOUND).await?;
+        Ok(())
+    }
+
+    /// The full transcript has an `ETag` and interrupted downloads can be
+    /// resumed.
+    pub async fn transcript_resume(&self) -> Result<(), ConformanceError> {
+        let request = self.get("ceremony/transcript")?.body(Body::empty())?;
+        let (headers, full) = self.send(request, StatusCode::OK).await?;
+        expect_header(&headers, ACCEPT_RANGES.as_str(), |value| value == "bytes")?;
+        expect_header(&headers, ETAG.as_str(), |value| value.starts_with('"'))?;
+        let etag = headers[ETAG].to_str().unwrap_or_default().to_string();
+
+        let request = self
+            .get("ceremony/transcript")?
+            .header(IF_NONE_MATCH, &etag)
+            .body(Body::empty())?;
+        self.send(request, StatusCode::NOT_MODIFIED).await?;
+
+        let start = full.len() / 2;
+        let request = self
+            .get("ceremony/transcript")?
+            .header(RANGE, format!("bytes={start}-"))
+            .header(IF_RANGE, &etag)
+            .body(Body::empty())?;
+        let (_, body) = self.send(request, StatusCode::PARTIAL_CONTENT).await?;
+        if body != full[start..] {
+            return Err(ConformanceError::Body(
+                "Resumed download does not match the full download".to_string(),
+            ));
+        }
+
+        // A stale `If-Range` restarts the download.
+        let request = self
+            .get("ceremony/transcript")?
+            .header(RANGE, format!("bytes={start}-"))
+            .header(IF_RANGE, "\"stale\"")
+            .body(Body::empty())?;
+        self.send(request, StatusCode::OK).await?;
+        Ok(())
+    }
+
+    /// The full transcript is gzip compressed on request.
+    pub async fn transcript_gzip(&self) -> Result<(), ConformanceError> {
+        let request = self.get("ceremony/transcript")?.body(Body::empty())?;
+        let (_, full) = self.send(request, StatusCode::OK).await?;
+        let request = self
+            .get("ceremony/transcript")?
+            .header(ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())?;
+        let (headers, body) = self.send(request, StatusCode::OK).await?;
+        expect_header(&headers, CONTENT_ENCODING.as_str(), |value| value == "gzip")?;
+        let mut decoded = Vec::new();
+        GzDecoder::new(&body[..])
+            .read_to_end(&mut decoded)
+            .map_err(|e| ConformanceError::Body(format!("Invalid gzip: {e}")))?;
+        if decoded != full[..] {
+            return Err(ConformanceError::Body(
+                "Decompressed download does not match the full download".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Download sub-transcript `index` in full.
+    #[instrument(level = "debug", skip(self))]
+    async fn transcript(&self, index: usize) -> Result<Bytes, ConformanceError> {
+        let request = self
+            .get(&format!("ceremony/transcript/{index}"))?
+            .body(Body::empty())?;
+        let (headers, body) = self.send(request, StatusCode::OK).await?;
+        expect_header(&headers, CONTENT_TYPE.as_str(), |value| {
+            value == "application/octet-stream"
+        })?;
+        expect_header(&headers, ACCEPT_RANGES.as_str(), |value| value == "bytes")?;
+        Ok(body)
+    }
+
+    fn url(&self, path: &str) -> Result<String, ConformanceError> {
+        Ok(self.base.join(path)?.to_string())
+    }
+
+    fn get(&self, path: &str) -> Result<hyper::http::request::Builder, ConformanceError> {
+        Ok(Request::builder().method(Method::GET).uri(self.url(path)?))
+    }
+
+    async fn send(
+        &self,
+        request: Request<Body>,
+        expected: StatusCode,
+    ) -> Result<(HeaderMap, Bytes), ConformanceError> {
+        let response = self.client.request(request).await?;
+        expect_status(&response, expected)?;
+        let (parts, body) = response.into_parts();
+        Ok((parts.headers, to_bytes(body).await?))
+    }
+}
+
+fn report(check: &'static str, result: Result<(), ConformanceError>) -> CheckReport {
+    if let Err(error) = &result {
+        warn!(check, %error, "Conformance check failed");
+    }
+    CheckReport {
+        check,
+        passed: result.is_ok(),
+        error: result.err().map(|e| e.to_string()),
+    }
+}
+
+fn expect_status(response: &Response<Body>, expected: StatusCode) -> Result<(), ConformanceError> {
+    let actual = response.status();
+    if actual != expected {
+        return Err(ConformanceError::Status { expected, actual });
+    }
+    Ok(())
+}
+
+fn expect_header(
+    headers: &HeaderMap,
+    name: &'static str,
+    predicate: impl FnOnce(&str) -> bool,
+) -> Result<(), ConformanceError> {
+    let value = headers.get(name).and_then(|value| value.to_str().ok());
+    if value.map_or(false, predicate) {
+        Ok(())
+    } else {
+        Err(ConformanceError::Header(
+            name,
+            value.map(ToString::to_string),
+        ))
+    }
+}
+
+/// Errors are returned as a JSON object with an `error` message.
+fn expect_json_error(headers: &HeaderMap, body: &[u8]) -> Result<(), ConformanceError> {
+    expect_header(headers, CONTENT_TYPE.as_str(), |value| {
+        value.starts_with("application/json")
+    })?;
+    let json: serde_json::Value = serde_json::from_slice(body)
+        .map_err(|e| ConformanceError::Body(format!("Invalid JSON: {e}")))?;
+    if json["error"].is_string() {
+        Ok(())
+    } else {
+        Err(ConformanceError::Body(format!(
+            "No error message in {json}"
+        )))
+    }
+}
+
+fn g1_size() -> usize {
+    G1Affine::default().uncompressed_size()
+}
+
+fn g2_size() -> usize {
+    G2Affine::default().uncompressed_size()
+}
+
+fn generator_bytes() -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(g1_size());
+    G1Affine::prime_subgroup_generator()
+        .serialize_uncompressed(&mut bytes)
+        .expect("Writing to a Vec can not fail");
+    bytes
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{app, test::TestDir, Options as ServerOptions};
+    use axum::Server;
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    #[tokio::test]
+    async fn test_conformance() {
+        let dir = TestDir::new("conformance");
+        let options =
+            ServerOptions::parse_from(["coordinator", "--transcript-dir", dir.to_str().unwrap()]);
+        let app = app(&options).await.unwrap();
+        let server = Server::bind(&SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0))
+            .serve(app.into_make_service());
+        let url = format!("http://{}", server.local_addr()).parse().unwrap();
+        tokio::spawn(server);
+
+        let report = Suite::new(url).run().await;
+        assert_eq!(report.failed, 0, "{:#?}", report.results);
+    }
+}
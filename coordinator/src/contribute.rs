@@ -0,0 +1,292 @@
+//! Contribution endpoints.
+
+use crate::{
+    analytics::{QueueAnalytics, Stage},
+    audit::{AuditEvent, AuditLog},
+    bans::{BanList, Offense},
+    ceremony::{CeremonyError, SharedCeremony, Sizes, UploadRng},
+    commitment::CommitmentSigner,
+    compute::{ComputeError, ComputePool},
+    eip712::{self, EcdsaError},
+    ipfs::Ipfs,
+    participants::ParticipantList,
+    policy::PolicyEngine,
+    queue::{Queue, QueueError},
+    receipt::{self, Receipt, ReceiptLog, SignedReceipt},
+    schema::{ContributionSchema, SchemaError},
+    session::Session,
+    signing::CoordinatorKeys,
+    upload::Upload,
+};
+use axum::{
+    body::Bytes,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use kzg_ceremony_crypto::{
+    BatchTranscript, BatchVerifyError, ContributionJson, ContributionsError, ContributionsJson,
+    SanityChecks,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use thiserror::Error;
+use tracing::{error, info, instrument, warn};
+
+/// Maximum number of contribution errors listed in a response.
+const MAX_REPORTED_ERRORS: usize = 256;
+
+#[derive(Debug, Error)]
+pub enum CompleteError {
+    #[error("Invalid contribution JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Schema(#[from] SchemaError),
+    /// Every error found in the contribution, never empty.
+    #[error("Invalid contribution: {}", .0[0])]
+    Parse(Vec<ContributionsError>),
+    #[error(transparent)]
+    Ecdsa(#[from] EcdsaError),
+    #[error(transparent)]
+    Ceremony(#[from] CeremonyError),
+    #[error(transparent)]
+    Queue(#[from] QueueError),
+    #[error(transparent)]
+    Compute(#[from] ComputeError),
+}
+
+/// Verify and apply an uploaded contribution and return a signed receipt.
+///
+/// Repeating the latest accepted upload returns the original receipt, so
+/// clients can safely retry.
+#[instrument(level = "info", skip_all, fields(identity = %session.pseudonym))]
+#[allow(clippy::too_many_arguments)] // Axum extractors
+pub async fn complete(
+    session: Session,
+    Extension(ceremony): Extension<SharedCeremony>,
+    // Grouped, axum handlers take at most 16 extractors.
+    (Extension(sizes), Extension(upload_rng)): (Extension<Sizes>, Extension<UploadRng>),
+    Extension(key): Extension<Arc<CoordinatorKeys>>,
+    Extension(commitment): Extension<Option<Arc<dyn CommitmentSigner>>>,
+    Extension(analytics): Extension<QueueAnalytics>,
+    Extension(queue): Extension<Arc<Queue>>,
+    Extension(policy): Extension<Arc<PolicyEngine>>,
+    Extension(bans): Extension<Arc<BanList>>,
+    Extension(audit): Extension<Arc<AuditLog>>,
+    Extension(ipfs): Extension<Ipfs>,
+    Extension(compute): Extension<ComputePool>,
+    Extension(schema): Extension<Option<Arc<ContributionSchema>>>,
+    Extension(receipts): Extension<Arc<ReceiptLog>>,
+    Extension(participants): Extension<Arc<ParticipantList>>,
+    upload: Upload,
+) -> Result<Json<SignedReceipt>, CompleteError> {
+    if let Some(receipt) = receipts.get(&session.pseudonym, &upload.hash) {
+        info!("Upload already accepted, returning the original receipt");
+        return Ok(Json(receipt));
+    }
+    queue.ensure_active(&session.pseudonym)?;
+    let identity = session.identity.clone();
+    let upload_hash = upload.hash;
+    let upload_bytes = upload.bytes.clone();
+    let pseudonym = session.pseudonym.clone();
+    let pipeline = queue.clone();
+    // A busy server keeps the slot, so the upload can be retried.
+    let (handed_over, result) = compute
+        .run(move || {
+            let mut handed_over = false;
+            let result = (|| {
+                let contribution: ContributionsJson = match schema {
+                    Some(schema) => {
+                        let value: Value = serde_json::from_slice(&upload.bytes)?;
+                        schema.validate(&value)?;
+                        serde_json::from_value(value)?
+                    }
+                    None => serde_json::from_slice(&upload.bytes)?,
+                };
+                eip712::verify_contribution(&contribution, &identity)?;
+                let contributions = contribution
+                    .parse_subset_exhaustive_with_rng(
+                        &sizes.0,
+                        SanityChecks::default(),
+                        &mut upload_rng.rng(),
+                    )
+                    .map_err(CompleteError::Parse)?;
+                let mut ceremony = ceremony.blocking_lock();
+                // A tentative slot may have been rolled back while waiting for
+                // the pending contribution.
+                pipeline.ensure_active(&pseudonym)?;
+                let hand_over = pipeline.hand_over_guarded(
+                    &pseudonym,
+                    ceremony.num_contributions() + 1,
+                    || pending_powers(ceremony.transcript(), &contribution),
+                );
+                handed_over = hand_over.is_some();
+                // Holding the lock until confirmed, so nobody verifies against
+                // the transcript without the pending contribution. Anything
+                // but success rolls it back when the guard is dropped.
+                let accepted = ceremony.contribute(&identity, &pseudonym, contributions);
+                if let (Some(hand_over), Ok(_)) = (hand_over, &accepted) {
+                    hand_over.confirm();
+                }
+                drop(ceremony);
+                Ok::<_, CompleteError>((accepted?, contribution))
+            })();
+            (handed_over, result)
+        })
+        .await?;
+    // The slot is released whether or not the upload was valid, unless it was
+    // handed over already.
+    if !handed_over {
+        queue.finish(&session.pseudonym)?;
+    }
+    if let Err(error) = &result {
+        if error.is_invalid_upload() {
+            bans.record(&session.pseudonym, Offense::InvalidContribution);
+        }
+        audit.record(AuditEvent::ContributionRejected {
+            identity: session.pseudonym.clone(),
+            error:    error.to_string(),
+        });
+    }
+    let (accepted, contribution) = result?;
+    analytics.record(&session.pseudonym, Stage::Completed);
+    policy.record_contribution(&session.pseudonym);
+    ipfs.pin_contribution(accepted.index, upload_bytes);
+    participants
+        .record(
+            &session.pseudonym,
+            accepted.index,
+            &contribution,
+            &accepted.transcript_before,
+            &accepted.transcript_after,
+        )
+        .await;
+
+    let receipt = Receipt {
+        identity:          session.pseudonym,
+        index:             accepted.index,
+        transcript_before: format!("0x{}", hex::encode(accepted.transcript_before)),
+        transcript_after:  format!("0x{}", hex::encode(accepted.transcript_after)),
+        upload_hash:       Some(format!("0x{}", hex::encode(upload_hash))),
+    };
+    audit.record(AuditEvent::ContributionCompleted {
+        identity:          receipt.identity.clone(),
+        index:             receipt.index,
+        transcript_before: receipt.transcript_before.clone(),
+        transcript_after:  receipt.transcript_after.clone(),
+        upload_hash:       format!("0x{}", hex::encode(upload_hash)),
+    });
+    let mut signed = receipt::sign(&key.active(), &receipt);
+    if let Some(signer) = commitment {
+        // The contribution is already accepted, so a receipt without
+        // commitment is better than none.
+        if let Err(error) = receipt::commit(signer.as_ref(), receipt.index, &mut signed) {
+            warn!(?error, "Could not add post-quantum commitment to receipt");
+        }
+    }
+    receipts.record(&receipt.identity, &upload_hash, &signed);
+    Ok(Json(signed))
+}
+
+/// Powers of `transcript` with the sub-contributions of `contribution`
+/// applied, in the format of `/ceremony/transcript?format=powers`.
+fn pending_powers(transcript: &BatchTranscript, contribution: &ContributionsJson) -> Bytes {
+    let sub_contributions = transcript
+        .transcripts
+        .iter()
+        .map(|transcript| {
+            let size = (transcript.g1_powers.len(), transcript.g2_powers.len());
+            contribution
+                .sub_contributions
+                .iter()
+                .find(|sub| (sub.num_g1_powers, sub.num_g2_powers) == size)
+                .map_or_else(
+                    || ContributionJson::from(&transcript.contribution()),
+                    |sub| ContributionJson {
+                        bls_signature: None,
+                        pot_pubkey_proof: None,
+                        ..sub.clone()
+                    },
+                )
+        })
+        .collect();
+    let powers = ContributionsJson {
+        sub_contributions,
+        ecdsa_signature: None,
+    };
+    serde_json::to_vec(&powers)
+        .expect("Serializing to a vector does not fail")
+        .into()
+}
+
+impl CompleteError {
+    /// Whether the error is caused by the uploaded contribution.
+    #[must_use]
+    pub const fn is_invalid_upload(&self) -> bool {
+        matches!(
+            self,
+            Self::Json(_)
+                | Self::Schema(_)
+                | Self::Parse(_)
+                | Self::Ecdsa(_)
+                | Self::Ceremony(
+                    CeremonyError::InvalidContributionCount(..)
+                        | CeremonyError::NoSubContributions
+                        | CeremonyError::VerificationFailed(..)
+                        | CeremonyError::InvalidBlsSignature(_)
+                        | CeremonyError::InvalidPubkeyProof(_)
+                        | CeremonyError::MissingPubkeyProof(_),
+                )
+        )
+    }
+}
+
+impl IntoResponse for CompleteError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            Self::Json(_)
+            | Self::Schema(_)
+            | Self::Parse(_)
+            | Self::Ecdsa(_)
+            | Self::Ceremony(
+                CeremonyError::InvalidContributionCount(..)
+                | CeremonyError::NoSubContributions
+                | CeremonyError::VerificationFailed(..)
+                | CeremonyError::InvalidBlsSignature(_)
+                | CeremonyError::InvalidPubkeyProof(_)
+                | CeremonyError::MissingPubkeyProof(_),
+            ) => StatusCode::BAD_REQUEST,
+            Self::Ceremony(CeremonyError::Storage(error)) => {
+                error!(?error, "Storage failure while applying contribution");
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            Self::Queue(error) => return (*error).into_response(),
+            Self::Ceremony(CeremonyError::NotLeader(error)) => {
+                warn!(?error, "Lost the leader lease while applying contribution");
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            Self::Compute(ComputeError::Busy) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Compute(ComputeError::Panicked) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let body = match &self {
+            Self::Schema(error) => {
+                json!({ "error": self.to_string(), "violations": error.violations })
+            }
+            Self::Parse(errors) => {
+                let listed = &errors[..errors.len().min(MAX_REPORTED_ERRORS)];
+                json!({
+                    "error": self.to_string(),
+                    "errors": listed,
+                    "errorCount": errors.len(),
+                })
+            }
+            Self::Ceremony(CeremonyError::VerificationFailed(index, error)) => json!({
+                "error": self.to_string(),
+                "errors": [BatchVerifyError::VerificationFailed(*index, *error)],
+                "errorCount": 1,
+            }),
+            _ => json!({ "error": self.to_string() }),
+        };
+        (status, Json(body)).into_response()
+    }
+}
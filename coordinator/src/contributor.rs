@@ -0,0 +1,113 @@
+//! Contribute from the terminal.
+//!
+//! Logs into a remote coordinator with a session token, waits in the queue
+//! and contributes secrets derived from the sources in [`crate::entropy`].
+
+use crate::{
+    client::CeremonyClient,
+    entropy::{fetch_beacon, Entropy},
+    test_ceremony,
+};
+use clap::Parser;
+use eyre::{Result as EyreResult, WrapErr};
+use std::{fs, path::PathBuf, time::Duration};
+use tracing::{info, warn};
+use url::Url;
+use zeroize::Zeroizing;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+pub struct Options {
+    /// Base url of the coordinator API.
+    pub url: Url,
+
+    /// Session token obtained by signing in on the ceremony website.
+    #[clap(long, env, required_unless_present = "test-ceremony")]
+    pub session_token: Option<String>,
+
+    /// Additional entropy to mix into the secret.
+    #[clap(long)]
+    pub entropy: Option<String>,
+
+    /// Also mix in the latest round of the drand beacon at this url.
+    #[clap(long)]
+    pub drand: Option<Url>,
+
+    /// Contribute to these sub-ceremonies only, comma separated indices.
+    #[clap(long, value_delimiter = ',')]
+    pub sub_ceremonies: Vec<usize>,
+
+    /// Seconds between attempts to take the contribution slot.
+    #[clap(long, default_value = "5")]
+    pub poll_interval: u64,
+
+    /// Write the receipt to this file instead of stdout.
+    #[clap(long)]
+    pub receipt: Option<PathBuf>,
+
+    /// Contribute to a coordinator running `--test-ceremony`. Logs in without
+    /// a session token and derives the secrets from `--entropy` only, so
+    /// contributions are reproducible.
+    #[clap(long)]
+    pub test_ceremony: bool,
+}
+
+pub async fn main(options: Options) -> EyreResult<()> {
+    let mut client = CeremonyClient::new(options.url)
+        .with_poll_interval(Duration::from_secs(options.poll_interval));
+    if options.test_ceremony {
+        client = client.with_sizes(test_ceremony::SIZES.to_vec());
+    }
+    match &options.session_token {
+        Some(token) => client.login(token)?,
+        None => client.test_login(None).await?,
+    };
+
+    let position = client.join_queue().await?;
+    info!(
+        position = position.position,
+        queue_length = position.queue_length,
+        "Joined the queue"
+    );
+    client.await_turn().await?;
+    info!("Contribution slot acquired, contributing");
+
+    let user = Zeroizing::new(options.entropy.unwrap_or_default());
+    let mut entropy = if options.test_ceremony {
+        let mut entropy = Entropy::deterministic();
+        entropy.add_user_input(&user);
+        entropy
+    } else {
+        Entropy::gather(&user)
+    };
+    if let Some(url) = &options.drand {
+        let beacon = fetch_beacon(url)
+            .await
+            .wrap_err("Could not fetch drand beacon")?;
+        entropy.add_beacon(beacon.round, &beacon.randomness);
+    }
+    let result = if options.sub_ceremonies.is_empty() {
+        client.contribute(&entropy).await
+    } else {
+        client
+            .contribute_subset(&entropy, &options.sub_ceremonies)
+            .await
+    };
+    let receipt = match result {
+        Ok(receipt) => receipt,
+        Err(error) => {
+            // Release the slot rather than letting it time out.
+            if let Err(abort_error) = client.abort().await {
+                warn!(?abort_error, "Could not abort the contribution");
+            }
+            return Err(error.into());
+        }
+    };
+    info!("Contribution accepted");
+
+    let json = serde_json::to_string_pretty(&receipt)?;
+    match options.receipt {
+        Some(path) => fs::write(path, json)?,
+        None => println!("{json}"),
+    }
+    Ok(())
+}
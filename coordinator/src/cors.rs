@@ -0,0 +1,97 @@
+//! Cross-origin access to the API.
+//!
+//! The browser frontend is served from a different origin than the
+//! coordinator, so browsers only let it call the API if the responses carry
+//! CORS headers for its origin. Origins are opt-in: without `--cors-origins`
+//! no CORS headers are sent and cross-origin requests fail in the browser.
+
+use crate::Options;
+use axum::http::HeaderValue;
+use eyre::{ensure, Result as EyreResult, WrapErr};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// The CORS layer configured in `options`, if any origins are allowed.
+pub fn layer(options: &Options) -> EyreResult<Option<CorsLayer>> {
+    if options.cors_origins.is_empty() {
+        return Ok(None);
+    }
+    let origins = if options.cors_origins.iter().any(|origin| origin == "*") {
+        // Browsers reject credentialed responses for any origin.
+        ensure!(
+            !options.cors_credentials,
+            "--cors-credentials requires explicit --cors-origins"
+        );
+        AllowOrigin::any()
+    } else {
+        let origins = options
+            .cors_origins
+            .iter()
+            .map(|origin| {
+                HeaderValue::from_str(origin.trim_end_matches('/'))
+                    .wrap_err_with(|| format!("Invalid CORS origin {origin}"))
+            })
+            .collect::<EyreResult<Vec<_>>>()?;
+        AllowOrigin::list(origins)
+    };
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(options.cors_methods.clone())
+            .allow_headers(options.cors_headers.clone())
+            .allow_credentials(options.cors_credentials),
+    ))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::{header, Method, Request},
+        routing::get,
+        Router,
+    };
+    use clap::Parser;
+    use tower::ServiceExt;
+
+    async fn preflight(app: Router, origin: &str) -> Option<HeaderValue> {
+        let request = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/ceremony/status")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .cloned()
+    }
+
+    #[tokio::test]
+    async fn test_layer() {
+        let options = Options::parse_from(["coordinator"]);
+        assert!(layer(&options).unwrap().is_none());
+
+        let options = Options::parse_from([
+            "coordinator",
+            "--cors-origins",
+            "https://ceremony.ethereum.org/,http://localhost:3000",
+            "--cors-credentials",
+        ]);
+        let cors = layer(&options).unwrap().unwrap();
+        let app = Router::new()
+            .route("/ceremony/status", get(|| async { "ok" }))
+            .layer(cors);
+        assert_eq!(
+            preflight(app.clone(), "https://ceremony.ethereum.org").await,
+            Some(HeaderValue::from_static("https://ceremony.ethereum.org"))
+        );
+        assert_eq!(preflight(app, "https://example.com").await, None);
+
+        let options =
+            Options::parse_from(["coordinator", "--cors-origins", "*", "--cors-credentials"]);
+        assert!(layer(&options).is_err());
+    }
+}
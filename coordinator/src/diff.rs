@@ -0,0 +1,305 @@
+//! Incremental transcript download.
+//!
+//! `GET /ceremony/transcript/diff?since={hash}` returns the contributions
+//! appended after the checkpoint with transcript or chain hash `hash`, see
+//! [`crate::checkpoint`]. Mirrors and verifiers holding the transcript at
+//! that checkpoint can bring it up to date by appending, for every
+//! contribution and covered sub-ceremony, the running product, pubkey and
+//! identity signature to the witness, and replacing the powers of the changed
+//! sub-ceremonies. Intermediate powers are not stored, so only the current
+//! ones are included. Points are in zcash format, as in the transcript JSON.
+//! Every contribution comes with the transcript hash of its checkpoint, which
+//! can only be checked for the latest one.
+//!
+//! A hash that matches no checkpoint is answered with `404 Not Found`, in
+//! which case the full transcript needs to be downloaded.
+
+use crate::ceremony::SharedCeremony;
+use ark_bls12_381::{G1Affine, G2Affine};
+use axum::{
+    extract::Query,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use eyre::Report;
+use kzg_ceremony_crypto::{format_g, BatchTranscript, PowersOfTau};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error;
+use tokio::task::spawn_blocking;
+use tracing::error;
+
+#[derive(Debug, Error)]
+pub enum DiffError {
+    #[error("Invalid hash {0}")]
+    InvalidHash(String),
+    #[error("No checkpoint with hash {0}")]
+    UnknownCheckpoint(String),
+    #[error("Could not read the transcript rounds")]
+    Storage(Report),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize)]
+pub struct DiffQuery {
+    since: String,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscriptDiff {
+    /// Number of contributions at the known checkpoint.
+    pub since:         usize,
+    /// Number of contributions now.
+    pub index:         usize,
+    /// Transcript hash now.
+    pub transcript:    String,
+    pub contributions: Vec<DiffContribution>,
+    /// Current powers of every sub-ceremony, `None` if unchanged since.
+    pub powers:        Vec<Option<PowersOfTau>>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffContribution {
+    pub index:      usize,
+    /// Transcript hash of the checkpoint after this contribution, if any.
+    pub transcript: Option<String>,
+    /// Witness entry for every sub-ceremony, `None` if not covered.
+    pub entries:    Vec<Option<WitnessEntry>>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WitnessEntry {
+    pub running_product: String,
+    pub pot_pubkey:      String,
+    pub bls_signature:   Option<String>,
+}
+
+/// Points appended since a checkpoint, copied out of the ceremony lock.
+struct Appended {
+    since:         usize,
+    index:         usize,
+    hash:          [u8; 32],
+    contributions: Vec<Vec<Option<Entry>>>,
+    /// Checkpointed transcript hash after every contribution.
+    hashes:        Vec<Option<[u8; 32]>>,
+    powers:        Vec<Option<(Vec<G1Affine>, Vec<G2Affine>)>>,
+}
+
+/// Running product, pubkey and identity signature of a sub-contribution.
+type Entry = (G1Affine, G2Affine, Option<G1Affine>);
+
+/// Entries of `batch` appended after contribution `since`, given the
+/// sub-ceremonies covered by every contribution and the checkpointed hashes.
+fn appended<F>(
+    batch: &BatchTranscript,
+    rounds: &[Vec<usize>],
+    checkpoint: F,
+    since: usize,
+    hash: [u8; 32],
+) -> Appended
+where
+    F: Fn(usize) -> Option<[u8; 32]>,
+{
+    let rounds = &rounds[since.min(rounds.len())..];
+    // Position in the witness of the first entry after `since`.
+    let mut next = batch
+        .transcripts
+        .iter()
+        .enumerate()
+        .map(|(i, transcript)| {
+            transcript.pubkeys.len() - rounds.iter().filter(|round| round.contains(&i)).count()
+        })
+        .collect::<Vec<_>>();
+    let contributions = rounds
+        .iter()
+        .map(|round| {
+            batch
+                .transcripts
+                .iter()
+                .enumerate()
+                .map(|(i, transcript)| {
+                    round.contains(&i).then(|| {
+                        let position = next[i];
+                        next[i] += 1;
+                        (
+                            transcript.products[position],
+                            transcript.pubkeys[position],
+                            transcript.signatures[position],
+                        )
+                    })
+                })
+                .collect()
+        })
+        .collect();
+    let powers = batch
+        .transcripts
+        .iter()
+        .enumerate()
+        .map(|(i, transcript)| {
+            rounds
+                .iter()
+                .any(|round| round.contains(&i))
+                .then(|| (transcript.g1_powers.clone(), transcript.g2_powers.clone()))
+        })
+        .collect();
+    let index = since + rounds.len();
+    Appended {
+        since,
+        index,
+        hash,
+        contributions,
+        hashes: (since + 1..=index).map(checkpoint).collect(),
+        powers,
+    }
+}
+
+impl From<Appended> for TranscriptDiff {
+    fn from(appended: Appended) -> Self {
+        Self {
+            since:         appended.since,
+            index:         appended.index,
+            transcript:    format!("0x{}", hex::encode(appended.hash)),
+            contributions: appended
+                .contributions
+                .iter()
+                .enumerate()
+                .zip(&appended.hashes)
+                .map(|((offset, entries), hash)| DiffContribution {
+                    index:      appended.since + offset + 1,
+                    transcript: hash.map(|hash| format!("0x{}", hex::encode(hash))),
+                    entries:    entries
+                        .iter()
+                        .map(|entry| {
+                            entry.map(|(product, pubkey, signature)| WitnessEntry {
+                                running_product: format_g(&product),
+                                pot_pubkey:      format_g(&pubkey),
+                                bls_signature:   signature.as_ref().map(format_g),
+                            })
+                        })
+                        .collect(),
+                })
+                .collect(),
+            powers:        appended
+                .powers
+                .iter()
+                .map(|powers| {
+                    powers.as_ref().map(|(g1, g2)| PowersOfTau {
+                        g1_powers: g1.iter().map(format_g).collect(),
+                        g2_powers: g2.iter().map(format_g).collect(),
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+pub async fn handler(
+    Query(query): Query<DiffQuery>,
+    Extension(ceremony): Extension<SharedCeremony>,
+) -> Result<Json<TranscriptDiff>, DiffError> {
+    let mut hash = [0; 32];
+    hex::decode_to_slice(query.since.trim_start_matches("0x"), &mut hash)
+        .map_err(|_| DiffError::InvalidHash(query.since.clone()))?;
+    let ceremony = ceremony.lock().await;
+    let since = ceremony
+        .hash_chain()
+        .find(&hash)
+        .map(|checkpoint| checkpoint.index)
+        .ok_or(DiffError::UnknownCheckpoint(query.since))?;
+    let rounds = ceremony.rounds().map_err(DiffError::Storage)?;
+    let chain = ceremony.hash_chain();
+    let appended = appended(
+        ceremony.transcript(),
+        &rounds,
+        |index| {
+            chain
+                .get(index)
+                .map(|checkpoint| checkpoint.transcript_hash)
+        },
+        since,
+        ceremony.hash(),
+    );
+    drop(ceremony);
+    let diff = spawn_blocking(move || TranscriptDiff::from(appended))
+        .await
+        .expect("Encoding task panicked");
+    Ok(Json(diff))
+}
+
+impl IntoResponse for DiffError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::InvalidHash(_) => StatusCode::BAD_REQUEST,
+            Self::UnknownCheckpoint(_) => StatusCode::NOT_FOUND,
+            Self::Storage(ref error) => {
+                error!(?error, "Could not read the transcript rounds");
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        (status, Json(json!({ "error": self.to_string() }))).into_response()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use kzg_ceremony_crypto::{SecretRng, SecretScalar};
+
+    #[test]
+    fn test_appended() {
+        let sizes = [(4, 2), (8, 2)];
+        let mut batch = BatchTranscript::new(&sizes);
+        let mut rng = SecretRng::new();
+        let rounds = vec![vec![0, 1], vec![1], vec![0]];
+        for round in &rounds {
+            let contributions = batch
+                .transcripts
+                .iter()
+                .enumerate()
+                .map(|(i, transcript)| {
+                    round.contains(&i).then(|| {
+                        let mut contribution = transcript.contribution();
+                        contribution.add_tau(&SecretScalar::random(&mut rng));
+                        contribution
+                    })
+                })
+                .collect();
+            batch.add(contributions);
+        }
+
+        let hash = batch.hash();
+        let checkpoint = |index| (index == 3).then_some(hash);
+        let diff = TranscriptDiff::from(appended(&batch, &rounds, checkpoint, 1, hash));
+        assert_eq!((diff.since, diff.index), (1, 3));
+        assert_eq!(diff.contributions.len(), 2);
+        assert_eq!(diff.contributions[0].index, 2);
+        assert!(diff.contributions[0].entries[0].is_none());
+        assert_eq!(diff.contributions[0].transcript, None);
+        assert_eq!(
+            diff.contributions[1].transcript,
+            Some(diff.transcript.clone())
+        );
+        assert_eq!(
+            diff.contributions[0].entries[1]
+                .as_ref()
+                .unwrap()
+                .pot_pubkey,
+            format_g(&batch.transcripts[1].pubkeys[2])
+        );
+        assert_eq!(
+            diff.contributions[1].entries[0]
+                .as_ref()
+                .unwrap()
+                .running_product,
+            format_g(&batch.transcripts[0].products[2])
+        );
+        assert!(diff.powers.iter().all(Option::is_some));
+
+        let diff = TranscriptDiff::from(appended(&batch, &rounds, checkpoint, 3, hash));
+        assert!(diff.contributions.is_empty());
+        assert!(diff.powers.iter().all(Option::is_none));
+    }
+}
@@ -0,0 +1,159 @@
+//! EIP-712 typed data for the `ecdsaSignature` of a contribution.
+//!
+//! Ethereum participants can sign the `potPubkeys` of their contribution with
+//! their wallet, binding their address to the contribution. The typed data is
+//! defined by the ceremony specification:
+//!
+//! ```text
+//! domain:  { name: "Ethereum KZG Ceremony", version: "1.0", chainId: 1 }
+//! message: PoTPubkeys(contributionPubkey[] potPubkeys)
+//!          contributionPubkey(uint256 numG1Powers,uint256 numG2Powers,bytes potPubkey)
+//! ```
+
+use crate::signing::{self, Address, SignatureError};
+use kzg_ceremony_crypto::ContributionsJson;
+use sha3::{Digest, Keccak256};
+use thiserror::Error;
+
+const DOMAIN_NAME: &str = "Ethereum KZG Ceremony";
+const DOMAIN_VERSION: &str = "1.0";
+const CHAIN_ID: u64 = 1;
+
+const DOMAIN_TYPE: &str = "EIP712Domain(string name,string version,uint256 chainId)";
+const POT_PUBKEYS_TYPE: &str = "PoTPubkeys(contributionPubkey[] \
+                                potPubkeys)contributionPubkey(uint256 numG1Powers,uint256 \
+                                numG2Powers,bytes potPubkey)";
+const CONTRIBUTION_PUBKEY_TYPE: &str =
+    "contributionPubkey(uint256 numG1Powers,uint256 numG2Powers,bytes potPubkey)";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum EcdsaError {
+    #[error("Contribution {0} has no potPubkey")]
+    MissingPubkey(usize),
+    #[error("Contribution {0} has an invalid potPubkey encoding")]
+    InvalidPubkey(usize),
+    #[error("ECDSA signature requires an Ethereum identity")]
+    NotEthereumIdentity,
+    #[error("Invalid ECDSA signature: {0}")]
+    InvalidSignature(#[from] SignatureError),
+}
+
+/// Check the optional `ecdsaSignature` of `contribution` against `identity`.
+///
+/// If a signature is present, `identity` must be of the form `eth|0x...` and
+/// the signature must recover to that address.
+pub fn verify_contribution(
+    contribution: &ContributionsJson,
+    identity: &str,
+) -> Result<(), EcdsaError> {
+    let signature = match &contribution.ecdsa_signature {
+        Some(signature) => signature,
+        None => return Ok(()),
+    };
+    let expected = eth_address(identity).ok_or(EcdsaError::NotEthereumIdentity)?;
+    let recovered = signing::recover_digest(&digest(contribution)?, signature)?;
+    if recovered != expected {
+        return Err(SignatureError::WrongSigner(recovered, expected).into());
+    }
+    Ok(())
+}
+
+/// Address of an `eth|0x...` identity.
+#[must_use]
+pub fn eth_address(identity: &str) -> Option<Address> {
+    identity.strip_prefix("eth|")?.parse().ok()
+}
+
+/// EIP-712 digest of the `potPubkeys` in `contribution`.
+pub fn digest(contribution: &ContributionsJson) -> Result<[u8; 32], EcdsaError> {
+    let mut pubkeys = Keccak256::new();
+    for (i, sub) in contribution.sub_contributions.iter().enumerate() {
+        let pubkey = sub
+            .pot_pubkey
+            .as_ref()
+            .ok_or(EcdsaError::MissingPubkey(i))?;
+        let pubkey = hex::decode(pubkey.strip_prefix("0x").unwrap_or(pubkey))
+            .map_err(|_| EcdsaError::InvalidPubkey(i))?;
+        pubkeys.update(
+            Keccak256::new()
+                .chain_update(keccak(CONTRIBUTION_PUBKEY_TYPE))
+                .chain_update(uint256(sub.num_g1_powers as u64))
+                .chain_update(uint256(sub.num_g2_powers as u64))
+                .chain_update(Keccak256::digest(pubkey))
+                .finalize(),
+        );
+    }
+    let message = Keccak256::new()
+        .chain_update(keccak(POT_PUBKEYS_TYPE))
+        .chain_update(pubkeys.finalize())
+        .finalize();
+    let domain = Keccak256::new()
+        .chain_update(keccak(DOMAIN_TYPE))
+        .chain_update(keccak(DOMAIN_NAME))
+        .chain_update(keccak(DOMAIN_VERSION))
+        .chain_update(uint256(CHAIN_ID))
+        .finalize();
+    Ok(Keccak256::new()
+        .chain_update([0x19, 0x01])
+        .chain_update(domain)
+        .chain_update(message)
+        .finalize()
+        .into())
+}
+
+fn keccak(text: &str) -> [u8; 32] {
+    Keccak256::digest(text.as_bytes()).into()
+}
+
+fn uint256(value: u64) -> [u8; 32] {
+    let mut bytes = [0; 32];
+    bytes[24..].copy_from_slice(&value.to_be_bytes());
+    bytes
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::signing::CoordinatorKey;
+    use kzg_ceremony_crypto::ContributionJson;
+
+    fn contribution() -> ContributionsJson {
+        let mut contribution = ContributionsJson {
+            sub_contributions: vec![ContributionJson::initial(4, 2); 2],
+            ecdsa_signature:   None,
+        };
+        for sub in &mut contribution.sub_contributions {
+            sub.pot_pubkey = Some(sub.powers_of_tau.g2_powers[0].clone());
+        }
+        contribution
+    }
+
+    #[test]
+    fn test_verify_contribution() {
+        let wallet = CoordinatorKey::random();
+        let identity = format!("eth|{}", wallet.address());
+        let mut contribution = contribution();
+        assert_eq!(verify_contribution(&contribution, &identity), Ok(()));
+
+        contribution.ecdsa_signature = Some(wallet.sign_digest(&digest(&contribution).unwrap()));
+        assert_eq!(verify_contribution(&contribution, &identity), Ok(()));
+        assert_eq!(
+            verify_contribution(&contribution, "git|1234|recmo"),
+            Err(EcdsaError::NotEthereumIdentity)
+        );
+        let other = format!("eth|{}", CoordinatorKey::random().address());
+        assert!(matches!(
+            verify_contribution(&contribution, &other),
+            Err(EcdsaError::InvalidSignature(SignatureError::WrongSigner(
+                ..
+            )))
+        ));
+
+        // Signature does not cover a different pubkey.
+        contribution.sub_contributions[1].pot_pubkey = contribution.sub_contributions[1]
+            .powers_of_tau
+            .g1_powers
+            .pop();
+        assert!(verify_contribution(&contribution, &identity).is_err());
+    }
+}
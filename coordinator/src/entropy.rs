@@ -0,0 +1,74 @@
+//! Entropy for contribution secrets.
+//!
+//! The inputs are mixed by [`Entropy`] in the crypto crate, the only place
+//! secrets are derived. This adds the latest [drand](https://drand.love)
+//! beacon as an optional input.
+
+use hyper::{body::to_bytes, client::HttpConnector, Client, StatusCode};
+use serde::Deserialize;
+use thiserror::Error;
+use tracing::{info, instrument};
+use url::Url;
+
+pub use kzg_ceremony_crypto::Entropy;
+
+#[derive(Debug, Error)]
+pub enum EntropyError {
+    #[error("Invalid beacon url: {0}")]
+    Url(#[from] url::ParseError),
+    #[error("Beacon request failed: {0}")]
+    Http(#[from] hyper::Error),
+    #[error("Beacon responded with {0}")]
+    Status(StatusCode),
+    #[error("Invalid beacon response: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// A round of the drand randomness beacon.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize)]
+pub struct Beacon {
+    pub round:      u64,
+    #[serde(with = "hex")]
+    pub randomness: Vec<u8>,
+}
+
+/// Fetch the latest round from a drand HTTP endpoint.
+///
+/// # Errors
+///
+/// Returns [`EntropyError::Status`] if the endpoint responds with an error, or
+/// another [`EntropyError`] if the request fails or the round is malformed.
+///
+/// # Panics
+///
+/// Panics if the joined `url` is not a valid uri, which a parsed url always is.
+#[instrument(level = "info")]
+pub async fn fetch_beacon(url: &Url) -> Result<Beacon, EntropyError> {
+    let url = url.join("public/latest")?;
+    let response = Client::<HttpConnector>::new()
+        .get(url.as_str().parse().expect("Url is a valid uri"))
+        .await?;
+    if !response.status().is_success() {
+        return Err(EntropyError::Status(response.status()));
+    }
+    let beacon: Beacon = serde_json::from_slice(&to_bytes(response.into_body()).await?)?;
+    info!(round = beacon.round, "Fetched drand beacon");
+    Ok(beacon)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_beacon_json() {
+        let beacon: Beacon = serde_json::from_str(
+            r#"{"round": 42, "randomness": "0a0b", "signature": "00", "previous_signature": "00"}"#,
+        )
+        .unwrap();
+        assert_eq!(beacon, Beacon {
+            round:      42,
+            randomness: vec![10, 11],
+        });
+    }
+}
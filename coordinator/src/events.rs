@@ -0,0 +1,122 @@
+//! Live ceremony feed.
+//!
+//! Ceremony state changes are published on an [`EventBus`] and streamed to
+//! the frontend as server-sent events on `/ceremony/events`.
+
+use crate::schedule::Phase;
+use axum::{
+    response::sse::{Event, KeepAlive, Sse},
+    Extension,
+};
+use futures::{stream, Stream};
+use serde::Serialize;
+use std::{convert::Infallible, time::Duration};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{trace, warn};
+
+/// Number of events buffered for slow subscribers.
+const CAPACITY: usize = 256;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum CeremonyEvent {
+    /// A contribution was verified and appended to the transcript.
+    #[serde(rename_all = "camelCase")]
+    ContributionAccepted { index: usize },
+    /// The participant at the front of the queue changed.
+    #[serde(rename_all = "camelCase")]
+    QueueAdvanced { queue_length: usize },
+    /// A participant was removed from the queue or lost their slot.
+    #[serde(rename_all = "camelCase")]
+    ParticipantEvicted { reason: String },
+    /// The ceremony opened or closed according to its schedule.
+    #[serde(rename_all = "camelCase")]
+    PhaseChanged { phase: Phase },
+}
+
+#[derive(Clone, Debug)]
+pub struct EventBus {
+    sender: broadcast::Sender<CeremonyEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    #[must_use]
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        Self { sender }
+    }
+
+    /// Publish an event to all current subscribers.
+    pub fn publish(&self, event: CeremonyEvent) {
+        trace!(?event, "Publishing ceremony event");
+        // Sending only fails if there are no subscribers, which is fine.
+        let _ = self.sender.send(event);
+    }
+
+    #[must_use]
+    pub fn subscribe(&self) -> broadcast::Receiver<CeremonyEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Wait on `receiver` for the next accepted contribution, or for events
+    /// being missed. Returns `false` once the bus is gone.
+    pub async fn next_contribution(receiver: &mut broadcast::Receiver<CeremonyEvent>) -> bool {
+        loop {
+            match receiver.recv().await {
+                Ok(CeremonyEvent::ContributionAccepted { .. }) | Err(RecvError::Lagged(_)) => {
+                    return true;
+                }
+                Ok(_) => {}
+                Err(RecvError::Closed) => return false,
+            }
+        }
+    }
+}
+
+/// Stream ceremony events to the client as server-sent events.
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn handler(
+    Extension(events): Extension<EventBus>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let stream = stream::unfold(events.subscribe(), |mut receiver| async move {
+        let event = match receiver.recv().await {
+            Ok(event) => Event::default()
+                .json_data(&event)
+                .unwrap_or_else(|_| Event::default().event("error")),
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(skipped, "Event subscriber lagged behind");
+                Event::default().event("lagged").data(skipped.to_string())
+            }
+            Err(RecvError::Closed) => return None,
+        };
+        Some((Ok(event), receiver))
+    });
+    Sse::new(stream).keep_alive(KeepAlive::new().interval(Duration::from_secs(15)))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_publish_subscribe() {
+        let bus = EventBus::new();
+        bus.publish(CeremonyEvent::QueueAdvanced { queue_length: 3 });
+        let mut receiver = bus.subscribe();
+        bus.publish(CeremonyEvent::ContributionAccepted { index: 1 });
+        assert_eq!(
+            receiver.recv().await.unwrap(),
+            CeremonyEvent::ContributionAccepted { index: 1 }
+        );
+        assert_eq!(
+            serde_json::to_string(&CeremonyEvent::QueueAdvanced { queue_length: 3 }).unwrap(),
+            r#"{"type":"queueAdvanced","queueLength":3}"#
+        );
+    }
+}
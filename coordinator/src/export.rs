@@ -0,0 +1,163 @@
+//! Export of the ceremony output for KZG libraries.
+//!
+//! Writes the current powers of each sub-ceremony in one of the formats
+//! consumers load:
+//!
+//! * `txt`: the EIP-4844 `trusted_setup.txt` read by c-kzg. The number of G1
+//!   and G2 points on the first two lines, then the G1 points in Lagrange basis
+//!   and the G2 points in monomial basis, one hex encoded compressed point per
+//!   line without `0x` prefix.
+//! * `json`: the `trusted_setup_<n>.json` of the consensus specs and go-kzg,
+//!   with `g1_monomial`, `g1_lagrange` and `g2_monomial` arrays of `0x`
+//!   prefixed compressed points.
+//!
+//! The Lagrange basis is over the roots of unity in natural order, libraries
+//! apply their own bit-reversal permutation on load. Files are written to
+//! `<out-dir>/trusted_setup_<numG1Powers>.<format>`.
+
+use crate::{transcript::decode_all, verify_transcript::read_source};
+use clap::Parser;
+use eyre::{bail, eyre, Error as EyreError, Result as EyreResult, WrapErr};
+use kzg_ceremony_crypto::{format_g, lagrange_g1, Transcript, SIZES};
+use serde::Serialize;
+use std::{fmt::Write, fs, path::PathBuf, str::FromStr};
+use tracing::info;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+pub struct Options {
+    /// Transcript file, or the base url of a coordinator to download it from.
+    pub source: String,
+
+    /// Output format, `txt` or `json`.
+    #[clap(long, default_value = "json")]
+    pub format: Format,
+
+    /// Only export the sub-ceremony with this many G1 powers.
+    #[clap(long)]
+    pub size: Option<usize>,
+
+    /// Directory to write the files to.
+    #[clap(long, default_value = ".")]
+    pub out_dir: PathBuf,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Txt,
+    Json,
+}
+
+/// The consensus specs' trusted setup JSON.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TrustedSetupJson {
+    pub g1_monomial: Vec<String>,
+    pub g1_lagrange: Vec<String>,
+    pub g2_monomial: Vec<String>,
+}
+
+pub async fn main(options: Options) -> EyreResult<()> {
+    let bytes = read_source(&options.source).await?;
+    let transcript = decode_all(&bytes, &SIZES)?;
+    let mut exported = 0;
+    for transcript in &transcript.transcripts {
+        let n = transcript.g1_powers.len();
+        if options.size.map_or(false, |size| size != n) {
+            continue;
+        }
+        transcript
+            .verify_powers()
+            .wrap_err_with(|| format!("Invalid powers in sub-ceremony of size {n}"))?;
+        let contents = match options.format {
+            Format::Txt => trusted_setup_txt(transcript),
+            Format::Json => serde_json::to_string_pretty(&trusted_setup_json(transcript))?,
+        };
+        let path = options
+            .out_dir
+            .join(format!("trusted_setup_{}.{}", n, options.format));
+        fs::write(&path, contents)
+            .wrap_err_with(|| format!("Could not write {}", path.display()))?;
+        info!(path = %path.display(), "Exported trusted setup");
+        exported += 1;
+    }
+    if exported == 0 {
+        bail!("Transcript has no sub-ceremony of the requested size");
+    }
+    Ok(())
+}
+
+/// The powers as EIP-4844 `trusted_setup.txt`.
+#[must_use]
+pub fn trusted_setup_txt(transcript: &Transcript) -> String {
+    let mut txt = String::new();
+    writeln!(txt, "{}", transcript.g1_powers.len()).unwrap();
+    writeln!(txt, "{}", transcript.g2_powers.len()).unwrap();
+    for point in lagrange_g1(&transcript.g1_powers) {
+        writeln!(txt, "{}", &format_g(&point)[2..]).unwrap();
+    }
+    for point in &transcript.g2_powers {
+        writeln!(txt, "{}", &format_g(point)[2..]).unwrap();
+    }
+    txt
+}
+
+/// The powers as consensus specs trusted setup JSON.
+#[must_use]
+pub fn trusted_setup_json(transcript: &Transcript) -> TrustedSetupJson {
+    TrustedSetupJson {
+        g1_monomial: transcript.g1_powers.iter().map(format_g).collect(),
+        g1_lagrange: lagrange_g1(&transcript.g1_powers)
+            .iter()
+            .map(format_g)
+            .collect(),
+        g2_monomial: transcript.g2_powers.iter().map(format_g).collect(),
+    }
+}
+
+impl FromStr for Format {
+    type Err = EyreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "txt" => Ok(Self::Txt),
+            "json" => Ok(Self::Json),
+            _ => Err(eyre!("Format must be txt or json")),
+        }
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Txt => "txt",
+            Self::Json => "json",
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use kzg_ceremony_crypto::{SecretRng, SecretScalar};
+
+    #[test]
+    fn test_trusted_setup() {
+        let mut transcript = Transcript::new(8, 3);
+        let mut contribution = transcript.contribution();
+        contribution.add_tau(&SecretScalar::random(&mut SecretRng::new()));
+        transcript.add(contribution);
+
+        let txt = trusted_setup_txt(&transcript);
+        let lines = txt.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2 + 8 + 3);
+        assert_eq!(&lines[..2], ["8", "3"]);
+        assert!(lines[2..10].iter().all(|line| line.len() == 96));
+        assert_eq!(lines[10], &format_g(&transcript.g2_powers[0])[2..]);
+
+        let json = trusted_setup_json(&transcript);
+        assert_eq!(json.g1_monomial[1], format_g(&transcript.g1_powers[1]));
+        assert_eq!(json.g1_lagrange[0], format!("0x{}", lines[2]));
+        assert_eq!(json.g2_monomial.len(), 3);
+        assert_eq!("txt".parse::<Format>().unwrap(), Format::Txt);
+        assert!("csv".parse::<Format>().is_err());
+    }
+}
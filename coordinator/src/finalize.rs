@@ -0,0 +1,302 @@
+//! Final ceremony artifact.
+//!
+//! Once the last contribution is in, `POST /admin/finalize` turns the
+//! ceremony into one distributable file:
+//!
+//! 1. The queue is closed for good, nobody can join or start again. Finalizing
+//!    fails while a participant holds the contribution slot.
+//! 2. The transcript is exported as `transcript.json` and, for every
+//!    sub-ceremony, as `trusted_setup_<n>.txt` and `trusted_setup_<n>.json`,
+//!    see [`crate::export`].
+//! 3. `manifest.json` lists the transcript hash, the number of contributions
+//!    and the SHA-256 of every file. `manifest.sig` holds its EIP-191 signature
+//!    by the coordinator key, see [`crate::signing`].
+//! 4. All of it is packed into `final/ceremony.tar.gz` in the transcript
+//!    directory, served on `GET /ceremony/final`.
+//!
+//! The artifact is reproducible: the same transcript always yields the same
+//! tarball, byte for byte, so anyone can rebuild and compare it. The queue
+//! stays closed after a restart while the artifact exists.
+
+use crate::{
+    admin::Admin,
+    ceremony::SharedCeremony,
+    export::{trusted_setup_json, trusted_setup_txt},
+    queue::Queue,
+    signing::CoordinatorKeys,
+    storage::write_atomic,
+    transcript::encode_all,
+};
+use axum::{
+    body::Full,
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use eyre::Result as EyreResult;
+use flate2::{write::GzEncoder, Compression};
+use kzg_ceremony_crypto::BatchTranscript;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use thiserror::Error;
+use tokio::task::spawn_blocking;
+use tracing::{error, info, instrument};
+
+const DIR: &str = "final";
+const ARTIFACT: &str = "ceremony.tar.gz";
+
+#[derive(Clone, PartialEq, Eq, Debug, Error)]
+pub enum FinalizeError {
+    #[error("A participant is contributing, evict them or wait")]
+    ContributionInProgress,
+    #[error("The ceremony is not finalized")]
+    NotFinalized,
+    #[error("Could not write the final artifact")]
+    Storage,
+}
+
+/// Contents of `manifest.json`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub contributions:   usize,
+    /// Transcript hash, `0x` prefixed hex.
+    pub transcript_hash: String,
+    /// Address of the signing coordinator key.
+    pub coordinator:     String,
+    pub files:           Vec<FileHash>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileHash {
+    pub name:   String,
+    /// SHA-256 of the file, `0x` prefixed hex.
+    pub sha256: String,
+}
+
+/// The files of the artifact along with the packed tarball.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Artifact {
+    pub manifest:  Manifest,
+    /// Signature of the serialized manifest.
+    pub signature: String,
+    pub tarball:   Vec<u8>,
+}
+
+impl FinalizeError {
+    /// Stable error code for clients.
+    #[must_use]
+    pub const fn code(&self) -> &'static str {
+        match self {
+            Self::ContributionInProgress => "contribution_in_progress",
+            Self::NotFinalized => "not_finalized",
+            Self::Storage => "storage",
+        }
+    }
+}
+
+impl IntoResponse for FinalizeError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::ContributionInProgress => StatusCode::CONFLICT,
+            Self::NotFinalized => StatusCode::NOT_FOUND,
+            Self::Storage => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let body = Json(json!({ "error": self.to_string(), "code": self.code() }));
+        (status, body).into_response()
+    }
+}
+
+/// Build the artifact of `transcript`, see the module documentation.
+pub fn build(
+    transcript: &BatchTranscript,
+    contributions: usize,
+    hash: &[u8; 32],
+    key: &CoordinatorKeys,
+) -> EyreResult<Artifact> {
+    let mut files = vec![(
+        "transcript.json".to_string(),
+        encode_all(transcript).to_vec(),
+    )];
+    for sub_ceremony in &transcript.transcripts {
+        let n = sub_ceremony.g1_powers.len();
+        files.push((
+            format!("trusted_setup_{n}.txt"),
+            trusted_setup_txt(sub_ceremony).into_bytes(),
+        ));
+        files.push((
+            format!("trusted_setup_{n}.json"),
+            serde_json::to_vec_pretty(&trusted_setup_json(sub_ceremony))?,
+        ));
+    }
+    files.sort();
+    let manifest = Manifest {
+        contributions,
+        transcript_hash: format!("0x{}", hex::encode(hash)),
+        coordinator: key.address().to_string(),
+        files: files
+            .iter()
+            .map(|(name, contents)| FileHash {
+                name:   name.clone(),
+                sha256: format!("0x{}", hex::encode(Sha256::digest(contents))),
+            })
+            .collect(),
+    };
+    let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+    let signature = key.sign(&manifest_json);
+    files.push(("manifest.json".to_string(), manifest_json));
+    files.push(("manifest.sig".to_string(), signature.clone().into_bytes()));
+    Ok(Artifact {
+        manifest,
+        signature,
+        tarball: pack(&files)?,
+    })
+}
+
+/// Tarball of `files` that only depends on their names and contents.
+fn pack(files: &[(String, Vec<u8>)]) -> EyreResult<Vec<u8>> {
+    // Gzip headers carry no timestamp by default.
+    let mut tar = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::best()));
+    for (name, contents) in files {
+        let mut header = tar::Header::new_ustar();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(0);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_entry_type(tar::EntryType::Regular);
+        tar.append_data(&mut header, name, contents.as_slice())?;
+    }
+    Ok(tar.into_inner()?.finish()?)
+}
+
+/// Path of the artifact in the transcript directory `dir`.
+#[must_use]
+pub fn artifact_path(dir: &Path) -> PathBuf {
+    dir.join(DIR).join(ARTIFACT)
+}
+
+/// Whether the ceremony in `dir` was finalized.
+#[must_use]
+pub fn is_finalized(dir: &Path) -> bool {
+    artifact_path(dir).exists()
+}
+
+/// Directory the artifact is written to.
+#[derive(Clone, Debug)]
+pub struct FinalDir(pub PathBuf);
+
+/// Freeze the ceremony and write the final artifact.
+#[instrument(level = "info", skip_all)]
+pub async fn finalize(
+    _admin: Admin,
+    Extension(queue): Extension<Arc<Queue>>,
+    Extension(ceremony): Extension<SharedCeremony>,
+    Extension(key): Extension<Arc<CoordinatorKeys>>,
+    Extension(FinalDir(dir)): Extension<FinalDir>,
+) -> Result<Json<serde_json::Value>, FinalizeError> {
+    queue.close();
+    if !queue.is_idle() {
+        return Err(FinalizeError::ContributionInProgress);
+    }
+    // Hold the lock, so nothing is appended while exporting.
+    let ceremony = ceremony.lock().await;
+    let transcript = ceremony.transcript().clone();
+    let contributions = ceremony.num_contributions();
+    let hash = ceremony.hash();
+    let artifact = spawn_blocking(move || {
+        let artifact = build(&transcript, contributions, &hash, &key)?;
+        let path = artifact_path(&dir);
+        fs::create_dir_all(dir.join(DIR))?;
+        write_atomic(&path, &artifact.tarball)?;
+        Ok::<_, eyre::Report>(artifact)
+    })
+    .await
+    .expect("Finalize task panicked")
+    .map_err(|error| {
+        error!(?error, "Could not write the final artifact");
+        FinalizeError::Storage
+    })?;
+    drop(ceremony);
+    let sha256 = format!("0x{}", hex::encode(Sha256::digest(&artifact.tarball)));
+    info!(contributions, %sha256, "Ceremony finalized");
+    Ok(Json(json!({
+        "manifest": artifact.manifest,
+        "signature": artifact.signature,
+        "sha256": sha256,
+    })))
+}
+
+/// Download the final artifact.
+pub async fn download(
+    Extension(FinalDir(dir)): Extension<FinalDir>,
+) -> Result<Response, FinalizeError> {
+    let tarball = tokio::fs::read(artifact_path(&dir))
+        .await
+        .map_err(|_| FinalizeError::NotFinalized)?;
+    Ok(([(CONTENT_TYPE, "application/gzip")], Full::from(tarball)).into_response())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::signing::{verify, CoordinatorKey};
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_artifact() {
+        let transcript = BatchTranscript::new(&[(4, 2), (8, 2)]);
+        let key = CoordinatorKeys::new(CoordinatorKey::random(), &[]);
+        let artifact = build(&transcript, 0, &transcript.hash(), &key).unwrap();
+        let again = build(&transcript, 0, &transcript.hash(), &key).unwrap();
+        assert_eq!(artifact.tarball, again.tarball);
+
+        let mut archive = tar::Archive::new(GzDecoder::new(artifact.tarball.as_slice()));
+        let mut files = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents).unwrap();
+            files.push((name, contents));
+        }
+        let names = files
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, [
+            "transcript.json",
+            "trusted_setup_4.json",
+            "trusted_setup_4.txt",
+            "trusted_setup_8.json",
+            "trusted_setup_8.txt",
+            "manifest.json",
+            "manifest.sig",
+        ]);
+
+        let (_, manifest) = &files[5];
+        let (_, signature) = &files[6];
+        verify(
+            manifest,
+            std::str::from_utf8(signature).unwrap(),
+            key.address(),
+        )
+        .unwrap();
+        let manifest: Manifest = serde_json::from_slice(manifest).unwrap();
+        assert_eq!(manifest, artifact.manifest);
+        for (file, (_, contents)) in manifest.files.iter().zip(&files) {
+            assert_eq!(
+                file.sha256,
+                format!("0x{}", hex::encode(Sha256::digest(contents)))
+            );
+        }
+    }
+}
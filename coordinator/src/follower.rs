@@ -0,0 +1,337 @@
+//! Follower mode.
+//!
+//! With `--follow {url}` the coordinator runs as a read-only mirror of the
+//! primary coordinator at `url`. It polls the primary's transcript diff, see
+//! [`crate::diff`], re-verifies the result independently and appends the new
+//! contributions to its own transcript store, so it serves the status,
+//! transcript and checkpoint endpoints from verified data. Requests other
+//! than `GET`, `HEAD` and `OPTIONS` are rejected with `403 Forbidden`.
+//!
+//! The primary does not publish intermediate powers, so a diff is verified as
+//! a whole: every new running product must follow from the previous one and
+//! its pubkey, the new powers must be consistent with the last running
+//! product, and the resulting transcript must hash to the primary's latest
+//! checkpoint. The intermediate checkpoints are taken from the primary as is.
+//!
+//! The follower must start from the same initial transcript as the primary.
+//! If it stops half-way through appending a diff, its store holds powers that
+//! never existed and it refuses to start; it then has to be resynchronized
+//! from an empty transcript directory.
+
+use crate::{
+    ceremony::SharedCeremony,
+    client::{CeremonyClient, ClientError},
+    diff::TranscriptDiff,
+};
+use ark_bls12_381::{G1Affine, G2Affine};
+use axum::{
+    body::Body,
+    http::{Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use kzg_ceremony_crypto::{
+    parse_g, BatchTranscript, Contribution, ParseError, PowersOfTau, VerifyError,
+};
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter, IntCounter};
+use serde_json::json;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::{task::spawn_blocking, time::sleep};
+use tracing::{error, info, warn};
+use url::Url;
+
+static FOLLOW_FAILURES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "follow_failures_total",
+        "Number of failed attempts to follow the primary coordinator."
+    )
+    .unwrap()
+});
+
+#[derive(Debug, Error)]
+pub enum FollowError {
+    #[error("Could not fetch the transcript diff: {0}")]
+    Client(#[from] ClientError),
+    #[error("Diff does not start at the local transcript")]
+    Mismatch,
+    #[error("Malformed diff: {0}")]
+    Malformed(&'static str),
+    #[error("Invalid point in diff: {0}")]
+    InvalidPoint(#[from] ParseError),
+    #[error("Sub-transcript {0} failed verification: {1}")]
+    Invalid(usize, #[source] VerifyError),
+    #[error("Transcript does not hash to the primary's checkpoint")]
+    HashMismatch,
+    #[error("Could not append to the local transcript: {0}")]
+    Ceremony(String),
+}
+
+/// Contributions of a diff, ready to append.
+struct Followed {
+    contributions: Vec<(Vec<Option<Contribution>>, [u8; 32])>,
+}
+
+/// Start following the primary coordinator at `primary`, polling every
+/// `interval`.
+pub fn spawn(primary: Url, interval: Duration, ceremony: SharedCeremony) {
+    info!(%primary, "Following primary coordinator");
+    tokio::spawn(follow(CeremonyClient::new(primary), interval, ceremony));
+}
+
+async fn follow(client: CeremonyClient, interval: Duration, ceremony: SharedCeremony) {
+    loop {
+        match poll(&client, &ceremony).await {
+            Ok(0) => {}
+            Ok(count) => info!(count, "Followed new contributions"),
+            Err(error) => {
+                FOLLOW_FAILURES.inc();
+                if let FollowError::Client(_) = error {
+                    warn!(?error, "Could not reach the primary");
+                } else {
+                    error!(?error, "Could not follow the primary");
+                }
+            }
+        }
+        sleep(interval).await;
+    }
+}
+
+/// Fetch, verify and append the contributions since the local transcript.
+/// Returns the number of contributions appended.
+async fn poll(client: &CeremonyClient, ceremony: &SharedCeremony) -> Result<usize, FollowError> {
+    let (index, hash, transcript) = {
+        let ceremony = ceremony.lock().await;
+        (
+            ceremony.num_contributions(),
+            ceremony.hash(),
+            ceremony.transcript().clone(),
+        )
+    };
+    let diff = client.transcript_diff(&hash).await?;
+    if diff.since != index {
+        return Err(FollowError::Mismatch);
+    }
+    if diff.contributions.is_empty() {
+        return Ok(0);
+    }
+    let followed = spawn_blocking(move || verify(transcript, &diff))
+        .await
+        .expect("Verification task panicked")?;
+
+    // This is the only writer, so the transcript did not change meanwhile.
+    let mut ceremony = ceremony.lock().await;
+    let count = followed.contributions.len();
+    for (contributions, hash) in followed.contributions {
+        ceremony
+            .append_followed(contributions, hash)
+            .map_err(|error| FollowError::Ceremony(error.to_string()))?;
+    }
+    drop(ceremony);
+    Ok(count)
+}
+
+/// Apply `diff` to `transcript` and verify the result.
+fn verify(mut transcript: BatchTranscript, diff: &TranscriptDiff) -> Result<Followed, FollowError> {
+    let num_sub = transcript.transcripts.len();
+    if diff.powers.len() != num_sub {
+        return Err(FollowError::Malformed("wrong number of sub-ceremonies"));
+    }
+    let powers = diff
+        .powers
+        .iter()
+        .map(|powers| powers.as_ref().map(parse_powers).transpose())
+        .collect::<Result<Vec<_>, _>>()?;
+    let expected = parse_hash(&diff.transcript)?;
+
+    let mut contributions = Vec::with_capacity(diff.contributions.len());
+    for (offset, contribution) in diff.contributions.iter().enumerate() {
+        if contribution.index != diff.since + offset + 1 || contribution.entries.len() != num_sub {
+            return Err(FollowError::Malformed("unexpected contribution"));
+        }
+        let entries = contribution
+            .entries
+            .iter()
+            .zip(&powers)
+            .map(|(entry, powers)| {
+                entry
+                    .as_ref()
+                    .map(|entry| {
+                        let (g1_powers, g2_powers) = powers
+                            .as_ref()
+                            .ok_or(FollowError::Malformed("missing powers"))?;
+                        // The running product is the first power after the
+                        // contribution. Later powers are only known for the
+                        // last one, they are replaced by later contributions.
+                        let mut g1_powers = g1_powers.clone();
+                        g1_powers[1] = parse_g(&entry.running_product)?;
+                        Ok(Contribution {
+                            pubkey: parse_g(&entry.pot_pubkey)?,
+                            g1_powers,
+                            g2_powers: g2_powers.clone(),
+                            bls_signature: entry
+                                .bls_signature
+                                .as_deref()
+                                .map(parse_g)
+                                .transpose()?,
+                            pubkey_proof: None,
+                        })
+                    })
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, FollowError>>()?;
+        if entries.iter().all(Option::is_none) {
+            return Err(FollowError::Malformed(
+                "contribution covers no sub-ceremony",
+            ));
+        }
+        let hash = match &contribution.transcript {
+            Some(hash) => parse_hash(hash)?,
+            None if offset + 1 == diff.contributions.len() => expected,
+            None => return Err(FollowError::Malformed("missing checkpoint")),
+        };
+        transcript.add(entries.clone());
+        contributions.push((entries, hash));
+    }
+
+    for (i, sub) in transcript.transcripts.iter().enumerate() {
+        if powers[i].is_some() {
+            sub.verify()
+                .map_err(|error| FollowError::Invalid(i, error))?;
+        }
+    }
+    if transcript.hash() != expected
+        || contributions.last().map(|(_, hash)| *hash) != Some(expected)
+    {
+        return Err(FollowError::HashMismatch);
+    }
+    Ok(Followed { contributions })
+}
+
+fn parse_powers(powers: &PowersOfTau) -> Result<(Vec<G1Affine>, Vec<G2Affine>), FollowError> {
+    let g1 = powers
+        .g1_powers
+        .iter()
+        .map(|point| parse_g(point))
+        .collect::<Result<Vec<_>, _>>()?;
+    let g2 = powers
+        .g2_powers
+        .iter()
+        .map(|point| parse_g(point))
+        .collect::<Result<Vec<_>, _>>()?;
+    if g1.len() < 2 {
+        return Err(FollowError::Malformed("too few powers"));
+    }
+    Ok((g1, g2))
+}
+
+fn parse_hash(hash: &str) -> Result<[u8; 32], FollowError> {
+    let mut bytes = [0; 32];
+    hex::decode_to_slice(hash.trim_start_matches("0x"), &mut bytes)
+        .map_err(|_| FollowError::Malformed("invalid hash"))?;
+    Ok(bytes)
+}
+
+/// Reject requests that could modify state.
+pub async fn read_only(request: Request<Body>, next: Next<Body>) -> Response {
+    if matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    ) {
+        return next.run(request).await;
+    }
+    let body = Json(json!({ "error": "This coordinator is a read-only follower" }));
+    (StatusCode::FORBIDDEN, body).into_response()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::diff::{DiffContribution, WitnessEntry};
+    use ark_ec::AffineCurve;
+    use kzg_ceremony_crypto::{format_g, SecretRng, SecretScalar};
+
+    #[test]
+    fn test_verify() {
+        let sizes = [(4, 2), (8, 2)];
+        let initial = BatchTranscript::new(&sizes);
+        let mut primary = initial.clone();
+        let mut rng = SecretRng::new();
+        let mut diff = TranscriptDiff {
+            since:         0,
+            index:         2,
+            transcript:    String::new(),
+            contributions: Vec::new(),
+            powers:        Vec::new(),
+        };
+        for (index, round) in [vec![0, 1], vec![1]].into_iter().enumerate() {
+            let contributions = primary
+                .transcripts
+                .iter()
+                .enumerate()
+                .map(|(i, transcript)| {
+                    round.contains(&i).then(|| {
+                        let mut contribution = transcript.contribution();
+                        contribution.add_tau(&SecretScalar::random(&mut rng));
+                        contribution
+                    })
+                })
+                .collect::<Vec<_>>();
+            diff.contributions.push(DiffContribution {
+                index:      index + 1,
+                transcript: None,
+                entries:    contributions
+                    .iter()
+                    .map(|contribution| {
+                        contribution.as_ref().map(|c| WitnessEntry {
+                            running_product: format_g(&c.g1_powers[1]),
+                            pot_pubkey:      format_g(&c.pubkey),
+                            bls_signature:   None,
+                        })
+                    })
+                    .collect(),
+            });
+            primary.add(contributions);
+            diff.contributions[index].transcript =
+                Some(format!("0x{}", hex::encode(primary.hash())));
+        }
+        diff.transcript = format!("0x{}", hex::encode(primary.hash()));
+        diff.powers = primary
+            .transcripts
+            .iter()
+            .map(|transcript| {
+                Some(PowersOfTau {
+                    g1_powers: transcript.g1_powers.iter().map(format_g).collect(),
+                    g2_powers: transcript.g2_powers.iter().map(format_g).collect(),
+                })
+            })
+            .collect();
+
+        let followed = verify(initial.clone(), &diff).unwrap();
+        assert_eq!(followed.contributions.len(), 2);
+        let mut replica = initial.clone();
+        for (contributions, _) in followed.contributions {
+            replica.add(contributions);
+        }
+        assert_eq!(replica, primary);
+
+        // A tampered running product breaks the chain.
+        let mut tampered = diff.clone();
+        tampered.contributions[0].entries[1]
+            .as_mut()
+            .unwrap()
+            .running_product = format_g(&G1Affine::prime_subgroup_generator());
+        assert!(matches!(
+            verify(initial.clone(), &tampered),
+            Err(FollowError::Invalid(1, _))
+        ));
+
+        diff.transcript = format!("0x{}", hex::encode([0; 32]));
+        assert!(matches!(
+            verify(initial, &diff),
+            Err(FollowError::HashMismatch)
+        ));
+    }
+}
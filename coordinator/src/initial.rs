@@ -0,0 +1,123 @@
+//! Initial ceremony state for a new transcript store.
+//!
+//! By default the ceremony starts from generators only. Operators can instead
+//! start from an `--initial-contribution`, e.g. the output of an earlier
+//! ceremony, or resume from the latest batch contribution of an interrupted
+//! ceremony with `--transcript`. Both files are in the contribution JSON
+//! format and must match the ceremony sizes, [`SIZES`] unless running a
+//! [`crate::test_ceremony`].
+
+use eyre::{ensure, Result as EyreResult, WrapErr};
+use kzg_ceremony_crypto::{BatchTranscript, ContributionsJson, SanityChecks, Transcript};
+use std::{fs, path::Path};
+use tracing::{info, instrument};
+
+/// Upper bound on the size of a contribution file.
+///
+/// A batch contribution in compressed hex encoding is about 7 MB.
+pub const MAX_FILE_SIZE: u64 = 32 << 20;
+
+/// Transcript to create a new store with.
+pub fn load(
+    sizes: &[(usize, usize)],
+    initial_contribution: Option<&Path>,
+    transcript: Option<&Path>,
+) -> EyreResult<BatchTranscript> {
+    match (initial_contribution, transcript) {
+        (Some(path), _) => from_initial_contribution(sizes, path),
+        (None, Some(path)) => resume(sizes, path),
+        (None, None) => Ok(BatchTranscript::new(sizes)),
+    }
+}
+
+/// Start from the powers in an initial contribution.
+///
+/// The initial contribution is allowed to be degenerate, e.g. consist of
+/// generators only.
+#[allow(clippy::disallowed_methods)] // False positive from macro
+#[instrument(level = "info", skip(sizes))]
+pub fn from_initial_contribution(
+    sizes: &[(usize, usize)],
+    path: &Path,
+) -> EyreResult<BatchTranscript> {
+    let contributions = read(path)?.parse_with_sizes(sizes, SanityChecks::INITIAL)?;
+    info!("Starting from initial contribution {}", path.display());
+    Ok(contributions
+        .into_iter()
+        .map(|contribution| Transcript {
+            // The cumulative pubkey is the first G2 power.
+            products:   vec![contribution.g1_powers[1]],
+            pubkeys:    vec![contribution.g2_powers[1]],
+            signatures: vec![None],
+            g1_powers:  contribution.g1_powers,
+            g2_powers:  contribution.g2_powers,
+        })
+        .collect::<Vec<_>>()
+        .into())
+}
+
+/// Resume from the latest batch contribution.
+///
+/// Only the latest running product and pubkey are known, earlier witness
+/// entries are lost.
+#[allow(clippy::disallowed_methods)] // False positive from macro
+#[instrument(level = "info", skip(sizes))]
+pub fn resume(sizes: &[(usize, usize)], path: &Path) -> EyreResult<BatchTranscript> {
+    let contributions = read(path)?.parse_with_sizes(sizes, SanityChecks::default())?;
+    info!("Resuming from batch contribution {}", path.display());
+    Ok(contributions
+        .iter()
+        .map(Transcript::from_contribution)
+        .collect::<Result<Vec<_>, _>>()?
+        .into())
+}
+
+fn read(path: &Path) -> EyreResult<ContributionsJson> {
+    let size = fs::metadata(path)
+        .wrap_err_with(|| format!("Could not read {}", path.display()))?
+        .len();
+    ensure!(
+        size <= MAX_FILE_SIZE,
+        "{} is {} bytes, more than the maximum of {}",
+        path.display(),
+        size,
+        MAX_FILE_SIZE
+    );
+    let json = fs::read_to_string(path)?;
+    serde_json::from_str(&json)
+        .wrap_err_with(|| format!("{} does not match the contribution schema", path.display()))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test::TestDir;
+    use kzg_ceremony_crypto::SIZES;
+
+    #[test]
+    fn test_load() {
+        let dir = TestDir::new("initial");
+        fs::create_dir_all(&dir).unwrap();
+
+        let initial = dir.join("initial.json");
+        fs::write(
+            &initial,
+            serde_json::to_string(&ContributionsJson::initial()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            load(&SIZES, Some(&initial), None).unwrap(),
+            load(&SIZES, None, None).unwrap()
+        );
+
+        let invalid = dir.join("invalid.json");
+        fs::write(&invalid, r#"{"subContributions": {}}"#).unwrap();
+        let error = load(&SIZES, Some(&invalid), None).unwrap_err();
+        assert!(error.to_string().contains("schema"));
+
+        let empty = dir.join("empty.json");
+        fs::write(&empty, r#"{"subContributions": []}"#).unwrap();
+        let error = load(&SIZES, None, Some(&empty)).unwrap_err();
+        assert!(error.to_string().contains("number of contributions"));
+    }
+}
@@ -0,0 +1,239 @@
+//! Background re-verification of the stored transcript.
+//!
+//! Contributions are verified once when they are accepted. To guard against
+//! storage corruption afterwards, a worker thread periodically maps the stored
+//! transcript, checks that it hashes to the checkpoint of its contribution
+//! index and re-runs the full verification of every sub-transcript: the
+//! powers and the running product chain. The powers are read through the
+//! mapping in chunks, so no second copy of the transcript is held in memory,
+//! see [`crate::points`].
+//!
+//! The worker runs its checks on a single thread of its own, so it only uses
+//! spare CPU and does not compete with contribution verification for the
+//! shared thread pool. The ceremony is locked only while mapping the store.
+//!
+//! Once a check fails the transcript stays marked as corrupt until restart.
+//! The result is reported on `/ceremony/status` and in the
+//! `transcript_integrity` metric, one while intact and zero once corrupt.
+
+use crate::{
+    ceremony::SharedCeremony,
+    checkpoint::HashChain,
+    points::{MappedError, MappedTranscript},
+};
+use kzg_ceremony_crypto::{VerificationRng, VerifyError};
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter, register_int_gauge, IntCounter, IntGauge};
+use rayon::ThreadPoolBuilder;
+use serde::Serialize;
+use std::{
+    sync::{Arc, RwLock},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+use tracing::{error, info};
+
+static INTEGRITY: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "transcript_integrity",
+        "Whether the stored transcript passed every re-verification."
+    )
+    .unwrap()
+});
+
+static REVERIFIED_INDEX: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "transcript_reverified_contributions",
+        "Number of contributions in the latest re-verified transcript."
+    )
+    .unwrap()
+});
+
+static REVERIFICATIONS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "transcript_reverifications_total",
+        "Number of completed transcript re-verifications."
+    )
+    .unwrap()
+});
+
+#[derive(Clone, PartialEq, Eq, Debug, Error)]
+pub enum IntegrityError {
+    #[error("Could not read the stored transcript: {0}")]
+    Storage(String),
+    #[error("No checkpoint for contribution {0}")]
+    MissingCheckpoint(usize),
+    #[error("Stored transcript does not match checkpoint {0}")]
+    HashMismatch(usize),
+    #[error("Sub-transcript {0} failed verification: {1}")]
+    Invalid(usize, #[source] VerifyError),
+}
+
+/// Result of the re-verifications so far, as reported on `/ceremony/status`.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityStatus {
+    /// False once any re-verification failed.
+    pub intact:        bool,
+    /// Number of contributions in the latest re-verified transcript.
+    pub verified:      Option<usize>,
+    /// Unix time the latest re-verification completed.
+    pub verified_at:   Option<u64>,
+    /// The first failure, if any.
+    pub error:         Option<String>,
+    pub verifications: u64,
+}
+
+/// Handle to the worker, reporting nothing if re-verification is disabled.
+#[derive(Clone, Debug, Default)]
+pub struct Integrity {
+    status: Option<Arc<RwLock<IntegrityStatus>>>,
+}
+
+impl Integrity {
+    #[must_use]
+    pub fn status(&self) -> Option<IntegrityStatus> {
+        self.status
+            .as_ref()
+            .map(|status| status.read().unwrap().clone())
+    }
+}
+
+/// Start re-verifying the stored transcript every `interval`, disabled if
+/// `None`.
+pub fn spawn(interval: Option<Duration>, ceremony: SharedCeremony, chain: HashChain) -> Integrity {
+    let interval = match interval {
+        Some(interval) => interval,
+        None => return Integrity::default(),
+    };
+    info!(
+        ?interval,
+        "Re-verifying the stored transcript in the background"
+    );
+    let status = Arc::new(RwLock::new(IntegrityStatus {
+        intact: true,
+        ..IntegrityStatus::default()
+    }));
+    INTEGRITY.set(1);
+    let integrity = Integrity {
+        status: Some(status.clone()),
+    };
+    thread::Builder::new()
+        .name("reverify".to_string())
+        .spawn(move || {
+            let pool = ThreadPoolBuilder::new()
+                .num_threads(1)
+                .thread_name(|_| "reverify-pool".to_string())
+                .build()
+                .expect("Could not start re-verification thread pool");
+            // The transcript was checked against the hash chain on startup.
+            loop {
+                thread::sleep(interval);
+                pool.install(|| reverify(&ceremony, &chain, &status));
+            }
+        })
+        .expect("Could not start re-verification thread");
+    integrity
+}
+
+fn reverify(ceremony: &SharedCeremony, chain: &HashChain, status: &RwLock<IntegrityStatus>) {
+    let (index, stored) = {
+        let ceremony = ceremony.blocking_lock();
+        (ceremony.num_contributions(), ceremony.map_stored())
+    };
+    let result = stored
+        .map_err(|error| IntegrityError::Storage(error.to_string()))
+        .and_then(|transcript| {
+            let checkpoint = chain
+                .get(index)
+                .ok_or(IntegrityError::MissingCheckpoint(index))?;
+            check(&transcript, index, checkpoint.transcript_hash)
+        });
+    REVERIFICATIONS.inc();
+    let mut status = status.write().unwrap();
+    status.verifications += 1;
+    match result {
+        Ok(()) => {
+            info!(index, "Stored transcript re-verified");
+            REVERIFIED_INDEX.set(i64::try_from(index).unwrap_or(i64::MAX));
+            status.verified = Some(index);
+            status.verified_at = Some(now());
+        }
+        Err(error) => {
+            error!(index, ?error, "Stored transcript failed re-verification");
+            INTEGRITY.set(0);
+            if status.intact {
+                status.intact = false;
+                status.error = Some(error.to_string());
+            }
+        }
+    }
+}
+
+/// Check that `transcript` is valid and has hash `expected`.
+fn check(
+    transcript: &MappedTranscript,
+    index: usize,
+    expected: [u8; 32],
+) -> Result<(), IntegrityError> {
+    if transcript.hash() != expected {
+        return Err(IntegrityError::HashMismatch(index));
+    }
+    let mut rng = VerificationRng::new();
+    for (i, transcript) in transcript.transcripts.iter().enumerate() {
+        transcript.verify(&mut rng).map_err(|error| match error {
+            MappedError::Decode(error) => IntegrityError::Storage(error.to_string()),
+            MappedError::Invalid(error) => IntegrityError::Invalid(i, error),
+        })?;
+    }
+    Ok(())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time before unix epoch")
+        .as_secs()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{storage::TranscriptStore, test::TestDir};
+    use ark_bls12_381::G1Affine;
+    use ark_ec::AffineCurve;
+    use kzg_ceremony_crypto::{BatchTranscript, SecretRng, SecretScalar};
+
+    #[test]
+    fn test_check() {
+        let dir = TestDir::new("integrity");
+        let mut batch = BatchTranscript::new(&[(4, 2), (8, 2)]);
+        let mut rng = SecretRng::new();
+        let contributions = batch
+            .transcripts
+            .iter()
+            .map(|transcript| {
+                let mut contribution = transcript.contribution();
+                contribution.add_tau(&SecretScalar::random(&mut rng));
+                Some(contribution)
+            })
+            .collect();
+        batch.add(contributions);
+        let store = TranscriptStore::create(&dir, &batch).unwrap();
+        let mapped = store.map().unwrap();
+        check(&mapped, 1, batch.hash()).unwrap();
+        assert_eq!(
+            check(&mapped, 1, [0; 32]),
+            Err(IntegrityError::HashMismatch(1))
+        );
+
+        // A corrupted product is caught even if the hash is updated.
+        batch.transcripts[1].products[1] = G1Affine::prime_subgroup_generator();
+        let store = TranscriptStore::create(&dir.join("corrupt"), &batch).unwrap();
+        assert!(matches!(
+            check(&store.map().unwrap(), 1, batch.hash()),
+            Err(IntegrityError::Invalid(1, _))
+        ));
+    }
+}
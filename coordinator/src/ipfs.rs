@@ -0,0 +1,248 @@
+//! IPFS pinning of ceremony data.
+//!
+//! When configured with the HTTP RPC API of an IPFS node, every accepted
+//! contribution is added and pinned as uploaded, and after every round the
+//! full transcript as served on `/ceremony/transcript` is pinned as well. The
+//! content identifiers of the latest pins are reported on `/ceremony/status`,
+//! so mirrors can fetch ceremony data content-addressed from any IPFS node.
+//!
+//! Data is added with `POST {api}/api/v0/add?pin=true&cid-version=1`, which is
+//! served by Kubo and compatible pinning services. Failed contribution pins
+//! are logged and dropped, the data is also part of the next transcript pin.
+//! Failed transcript pins are retried with the next round.
+
+use crate::{ceremony::SharedCeremony, events::EventBus, transcript::encode_all};
+use axum::body::Bytes;
+use eyre::{bail, Result as EyreResult};
+use hyper::{body::to_bytes, client::HttpConnector, Body, Client, Method, Request};
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+use tokio::{sync::mpsc, task::spawn_blocking};
+use tracing::{info, instrument, warn};
+use url::Url;
+
+const BOUNDARY: &str = "kzg-ceremony-ipfs-boundary";
+
+static PIN_FAILURES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!("ipfs_pin_failures_total", "Number of failed IPFS pins.", &[
+        "kind"
+    ])
+    .unwrap()
+});
+
+/// A pinned transcript or contribution.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct Pin {
+    /// Number of contributions in the transcript, including the pinned one.
+    pub index: usize,
+    pub cid:   String,
+}
+
+/// Latest pins, as reported on `/ceremony/status`.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize)]
+pub struct Pins {
+    pub transcript:   Option<Pin>,
+    pub contribution: Option<Pin>,
+}
+
+/// Handle to the pinning tasks, a no-op if IPFS is not configured.
+#[derive(Clone, Debug, Default)]
+pub struct Ipfs {
+    contributions: Option<mpsc::UnboundedSender<(usize, Bytes)>>,
+    pins:          Arc<RwLock<Pins>>,
+}
+
+/// Response of `/api/v0/add`.
+#[derive(Deserialize)]
+struct Added {
+    #[serde(rename = "Hash")]
+    hash: String,
+}
+
+impl Ipfs {
+    /// Queue an accepted contribution for pinning.
+    pub fn pin_contribution(&self, index: usize, upload: Bytes) {
+        if let Some(sender) = &self.contributions {
+            // Sending only fails if the pinning task stopped.
+            let _ = sender.send((index, upload));
+        }
+    }
+
+    /// The latest pins, or `None` if IPFS is not configured.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pins lock is poisoned.
+    #[must_use]
+    pub fn status(&self) -> Option<Pins> {
+        self.contributions
+            .as_ref()
+            .map(|_| self.pins.read().unwrap().clone())
+    }
+}
+
+/// Start pinning to the IPFS node at `api` in the background.
+pub fn spawn(api: Option<Url>, ceremony: SharedCeremony, events: EventBus) -> Ipfs {
+    let api = match api {
+        Some(api) => api,
+        None => return Ipfs::default(),
+    };
+    info!(%api, "Pinning ceremony data to IPFS");
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let ipfs = Ipfs {
+        contributions: Some(sender),
+        pins:          Arc::default(),
+    };
+    let client = Client::new();
+    tokio::spawn(contributions(
+        client.clone(),
+        api.clone(),
+        receiver,
+        ipfs.pins.clone(),
+    ));
+    tokio::spawn(transcripts(
+        client,
+        api,
+        ceremony,
+        events,
+        ipfs.pins.clone(),
+    ));
+    ipfs
+}
+
+/// Pin contributions as they are accepted.
+async fn contributions(
+    client: Client<HttpConnector>,
+    api: Url,
+    mut receiver: mpsc::UnboundedReceiver<(usize, Bytes)>,
+    pins: Arc<RwLock<Pins>>,
+) {
+    while let Some((index, upload)) = receiver.recv().await {
+        match add(&client, &api, &format!("contribution-{index}.json"), upload).await {
+            Ok(cid) => {
+                info!(index, %cid, "Pinned contribution");
+                pins.write().unwrap().contribution = Some(Pin { index, cid });
+            }
+            Err(error) => {
+                warn!(index, "Pinning contribution failed: {}", error);
+                PIN_FAILURES.with_label_values(&["contribution"]).inc();
+            }
+        }
+    }
+}
+
+/// Pin the transcript on start and after every round.
+async fn transcripts(
+    client: Client<HttpConnector>,
+    api: Url,
+    ceremony: SharedCeremony,
+    events: EventBus,
+    pins: Arc<RwLock<Pins>>,
+) {
+    let mut receiver = events.subscribe();
+    loop {
+        let (index, transcript) = {
+            let ceremony = ceremony.lock().await;
+            (ceremony.num_contributions(), ceremony.transcript().clone())
+        };
+        let body = spawn_blocking(move || encode_all(&transcript))
+            .await
+            .expect("Encoding task panicked");
+        match add(&client, &api, &format!("transcript-{index}"), body).await {
+            Ok(cid) => {
+                info!(index, %cid, "Pinned transcript");
+                pins.write().unwrap().transcript = Some(Pin { index, cid });
+            }
+            Err(error) => {
+                warn!(index, "Pinning transcript failed: {}", error);
+                PIN_FAILURES.with_label_values(&["transcript"]).inc();
+            }
+        }
+        if !EventBus::next_contribution(&mut receiver).await {
+            return;
+        }
+    }
+}
+
+/// Add and pin `data` as a file called `name`, returning its CID.
+#[instrument(level = "info", skip(client, data))]
+async fn add(
+    client: &Client<HttpConnector>,
+    api: &Url,
+    name: &str,
+    data: Bytes,
+) -> EyreResult<String> {
+    let url = api.join("api/v0/add?pin=true&cid-version=1")?;
+    let mut body = format!(
+        "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; \
+         filename=\"{name}\"\r\nContent-Type: application/octet-stream\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend_from_slice(&data);
+    body.extend_from_slice(format!("\r\n--{BOUNDARY}--\r\n").as_bytes());
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(url.as_str())
+        .header(
+            "content-type",
+            format!("multipart/form-data; boundary={BOUNDARY}"),
+        )
+        .body(Body::from(body))?;
+    let response = client.request(request).await?;
+    if !response.status().is_success() {
+        bail!("IPFS node responded with {}", response.status());
+    }
+    let added: Added = serde_json::from_slice(&to_bytes(response.into_body()).await?)?;
+    Ok(added.hash)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use axum::{extract::RawQuery, routing::post, Router, Server};
+    use serde_json::json;
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        sync::Mutex,
+    };
+
+    #[tokio::test]
+    async fn test_add() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let app = Router::new().route(
+            "/api/v0/add",
+            post({
+                let received = received.clone();
+                move |RawQuery(query): RawQuery, body: Bytes| async move {
+                    received.lock().unwrap().push((query, body));
+                    axum::Json(json!({ "Name": "file", "Hash": "bafytest", "Size": "4" }))
+                }
+            }),
+        );
+        let server = Server::bind(&SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0))
+            .serve(app.into_make_service());
+        let api: Url = format!("http://{}/", server.local_addr()).parse().unwrap();
+        tokio::spawn(server);
+
+        let data = Bytes::from_static(b"\0\x01\x02\x03");
+        let cid = add(&Client::new(), &api, "transcript-3", data)
+            .await
+            .unwrap();
+        assert_eq!(cid, "bafytest");
+
+        let (query, body) = received.lock().unwrap()[0].clone();
+        assert_eq!(query.as_deref(), Some("pin=true&cid-version=1"));
+        let body = body.to_vec();
+        assert!(body.starts_with(format!("--{BOUNDARY}\r\n").as_bytes()));
+        assert!(body.ends_with(format!("\r\n\0\x01\x02\x03\r\n--{BOUNDARY}--\r\n").as_bytes()));
+    }
+
+    #[test]
+    fn test_disabled() {
+        let ipfs = Ipfs::default();
+        ipfs.pin_contribution(1, Bytes::new());
+        assert_eq!(ipfs.status(), None);
+    }
+}
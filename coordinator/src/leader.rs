@@ -0,0 +1,510 @@
+//! Hot-standby failover.
+//!
+//! With `--leader-lease {seconds}` several coordinators can share one
+//! transcript directory. They elect a leader through leases in that
+//! directory. Every leadership term has its own file `leader-{term}.json`,
+//! holding the term, the node id of the leader and the unix time the lease
+//! expires. The leader renews it every third of the lease duration. Only the
+//! leader handles the queue and contributions; standbys
+//! answer other requests than reads with `503 Service Unavailable`, so a load
+//! balancer can fail over to the leader.
+//!
+//! A standby keeps serving the status, transcript and checkpoint endpoints by
+//! reloading the transcript whenever the leader appended to it. Once the lease
+//! lapses, a standby takes it over, completes any append the leader was
+//! interrupted in and reloads the contributors and bans before accepting
+//! requests. The queue is held in memory, so waiting participants need to
+//! join again after a failover.
+//!
+//! A new term is only started once the latest lease expired, by creating its
+//! file exclusively, so of several standbys competing for it exactly one wins.
+//! Only the holder of a term rewrites its file, with an atomic rename, and it
+//! stops renewing a third of the lease before the lease expires. A leader
+//! stands down at that point unless it renewed the lease, also if its
+//! renewals stall, so with clocks synchronized to within a third of the lease
+//! it has stopped before a standby can take over. Before every append to the
+//! shared store the leader also checks that its term is still the latest, so
+//! a leader that missed its renewals can not write once a standby started the
+//! next term. This relies on exclusive creation and atomic renames on the
+//! shared filesystem.
+
+use crate::{
+    bans::BanList, ceremony::SharedCeremony, policy::PolicyEngine, storage::write_atomic, Options,
+};
+use axum::{
+    body::Body,
+    http::{Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use eyre::{ensure, Result as EyreResult, WrapErr};
+use once_cell::sync::Lazy;
+use prometheus::{register_int_gauge, IntGauge};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{task::spawn_blocking, time::interval};
+use tracing::{error, info, warn};
+
+/// Lease files are named `{PREFIX}{term}{SUFFIX}`.
+const PREFIX: &str = "leader-";
+const SUFFIX: &str = ".json";
+
+/// Number of latest terms to keep the lease files of, for debugging.
+const KEEP_TERMS: u64 = 2;
+
+static LEADER: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "coordinator_leader",
+        "Whether this coordinator holds the leader lease."
+    )
+    .unwrap()
+});
+
+/// Node id and lease duration to compete for leadership with.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub node_id: String,
+    pub lease:   Duration,
+}
+
+impl Config {
+    /// Leader election settings, if enabled with `--leader-lease`.
+    #[must_use]
+    pub fn from_options(options: &Options) -> Option<Self> {
+        (options.leader_lease > 0).then(|| Self {
+            node_id: options
+                .node_id
+                .clone()
+                .unwrap_or_else(|| hex::encode(rand::random::<[u8; 8]>())),
+            lease:   Duration::from_secs(options.leader_lease),
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Role {
+    Leader,
+    Standby,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lease {
+    /// Leadership term, increasing with every change of leader.
+    pub term:    u64,
+    pub holder:  String,
+    /// Unix time the lease expires.
+    pub expires: u64,
+}
+
+/// Handle to the election, always leading if it is disabled.
+#[derive(Clone, Debug, Default)]
+pub struct Leadership {
+    lease: Option<Arc<HeldLease>>,
+}
+
+/// The lease as held by this coordinator.
+#[derive(Debug)]
+struct HeldLease {
+    dir:     PathBuf,
+    node_id: String,
+    /// Term last acquired or renewed.
+    term:    AtomicU64,
+    /// Unix time to stand down at unless the lease is renewed, a third of
+    /// the lease before it expires. Zero on standby.
+    until:   AtomicU64,
+}
+
+impl Leadership {
+    /// Handle to compete for the leader lease in `dir` with, disabled if
+    /// `config` is `None`. Starts out as a standby, see [`spawn`].
+    #[must_use]
+    pub fn new(config: Option<&Config>, dir: &Path) -> Self {
+        Self {
+            lease: config.map(|config| {
+                Arc::new(HeldLease {
+                    dir:     dir.to_path_buf(),
+                    node_id: config.node_id.clone(),
+                    term:    AtomicU64::new(0),
+                    until:   AtomicU64::new(0),
+                })
+            }),
+        }
+    }
+
+    /// Whether this coordinator leads. Turns false by itself once the lease
+    /// was not renewed in time, without waiting for the election task.
+    #[must_use]
+    pub fn is_leader(&self) -> bool {
+        self.lease
+            .as_ref()
+            .map_or(true, |lease| lease.leading(now()))
+    }
+
+    /// Role of this coordinator, if leader election is enabled.
+    #[must_use]
+    pub fn role(&self) -> Option<Role> {
+        self.lease.as_ref().map(|lease| {
+            if lease.leading(now()) {
+                Role::Leader
+            } else {
+                Role::Standby
+            }
+        })
+    }
+
+    /// Check that this coordinator still leads in the latest term, right
+    /// before it writes to the shared store. The term is the fencing token
+    /// against a leader that missed its renewals while a standby took over.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if this coordinator is on standby, the lease was taken
+    /// over or the lease could not be read.
+    pub fn fence(&self) -> EyreResult<()> {
+        let lease = match &self.lease {
+            Some(lease) => lease,
+            None => return Ok(()),
+        };
+        let term = lease.term.load(Ordering::SeqCst);
+        ensure!(
+            lease.leading(now()),
+            "Leader lease of term {term} was not renewed"
+        );
+        let latest = latest_lease(&lease.dir)?;
+        ensure!(
+            latest.map_or(false, |latest| latest.term == term
+                && latest.holder == lease.node_id),
+            "Leader lease of term {term} was taken over"
+        );
+        Ok(())
+    }
+}
+
+impl HeldLease {
+    fn leading(&self, now: u64) -> bool {
+        now < self.until.load(Ordering::SeqCst)
+    }
+
+    /// Lead in `term` until `until`.
+    fn hold(&self, term: u64, until: u64) {
+        self.term.store(term, Ordering::SeqCst);
+        self.until.store(until, Ordering::SeqCst);
+    }
+
+    fn stand_down(&self) {
+        self.until.store(0, Ordering::SeqCst);
+    }
+}
+
+/// Start competing for the leader lease of `leadership`, unless disabled.
+pub fn spawn(
+    config: Option<Config>,
+    leadership: &Leadership,
+    ceremony: SharedCeremony,
+    policy: Arc<PolicyEngine>,
+    bans: Arc<BanList>,
+) {
+    let (config, lease) = match (config, &leadership.lease) {
+        (Some(config), Some(lease)) => (config, lease.clone()),
+        _ => return,
+    };
+    info!(node_id = %config.node_id, lease = ?config.lease, "Competing for the leader lease");
+    LEADER.set(0);
+    tokio::spawn(run(config, lease, ceremony, policy, bans));
+}
+
+async fn run(
+    config: Config,
+    lease: Arc<HeldLease>,
+    ceremony: SharedCeremony,
+    policy: Arc<PolicyEngine>,
+    bans: Arc<BanList>,
+) {
+    let mut ticks = interval(config.lease / 3);
+    let margin = config.lease.as_secs() / 3;
+    loop {
+        ticks.tick().await;
+        let now = now();
+        let leading = lease.leading(now);
+        // Stand down relative to when the lease was read, however long the
+        // shared filesystem takes.
+        let until = now + config.lease.as_secs() - margin;
+        let acquired = {
+            let (lease, duration) = (lease.clone(), config.lease);
+            spawn_blocking(move || try_acquire(&lease.dir, &lease.node_id, duration, now))
+                .await
+                .expect("Lease task panicked")
+        };
+        match acquired {
+            Ok(Some(term)) if leading => lease.hold(term, until),
+            Ok(Some(term)) => {
+                let (ceremony, policy, bans) = (ceremony.clone(), policy.clone(), bans.clone());
+                let result = spawn_blocking(move || take_over(&ceremony, &policy, &bans))
+                    .await
+                    .expect("Takeover task panicked");
+                match result {
+                    Ok(()) => {
+                        info!(node_id = %config.node_id, term, "Took over as leader");
+                        lease.hold(term, until);
+                    }
+                    // The lease is retried on the next tick.
+                    Err(error) => error!(?error, "Could not take over as leader"),
+                }
+            }
+            Ok(None) if leading => {
+                error!("Leader lease was taken over, standing down");
+                lease.stand_down();
+            }
+            Ok(None) => {
+                let ceremony = ceremony.clone();
+                let result = spawn_blocking(move || ceremony.blocking_lock().reload(false))
+                    .await
+                    .expect("Reload task panicked");
+                if let Err(error) = result {
+                    // The leader may be half-way through an append.
+                    warn!(?error, "Could not reload the transcript");
+                }
+            }
+            Err(error) => {
+                warn!(?error, "Could not renew the leader lease");
+                // Requests already stopped at the deadline, see `is_leader`.
+                if leading && !lease.leading(self::now()) {
+                    error!("Leader lease expired, standing down");
+                }
+            }
+        }
+        LEADER.set(i64::from(lease.leading(self::now())));
+    }
+}
+
+/// Acquire or renew the lease in `dir` for `node_id`, unless another node
+/// holds an unexpired lease. Returns the term `node_id` holds the lease for.
+///
+/// A lease is renewed only until a third of it is left. After that, or once
+/// it expired, the holder competes for the next term like any other node.
+fn try_acquire(dir: &Path, node_id: &str, lease: Duration, now: u64) -> EyreResult<Option<u64>> {
+    let current = latest_lease(dir)?;
+    if let Some(current) = &current {
+        if current.holder == node_id && current.expires >= now + lease.as_secs() / 3 {
+            let renewed = Lease {
+                expires: now + lease.as_secs(),
+                ..current.clone()
+            };
+            write_atomic(
+                &lease_path(dir, current.term),
+                &serde_json::to_vec(&renewed)?,
+            )?;
+            return Ok(Some(current.term));
+        }
+        if current.holder != node_id && current.expires > now {
+            return Ok(None);
+        }
+    }
+    let lease = Lease {
+        term:    current.map_or(1, |current| current.term + 1),
+        holder:  node_id.to_string(),
+        expires: now + lease.as_secs(),
+    };
+    if !create_lease(dir, &lease)? {
+        // Another node started the term first.
+        return Ok(None);
+    }
+    if let Some(old) = lease.term.checked_sub(KEEP_TERMS) {
+        let _ = fs::remove_file(lease_path(dir, old));
+    }
+    Ok(Some(lease.term))
+}
+
+/// Create the file for a new term, unless it already exists. The lease is
+/// written to a temporary file first and then hard linked into place, which
+/// fails if the file exists, so readers never see a partial lease.
+fn create_lease(dir: &Path, lease: &Lease) -> EyreResult<bool> {
+    let path = lease_path(dir, lease.term);
+    let tmp = path.with_extension(format!("{}.tmp", lease.holder));
+    write_atomic(&tmp, &serde_json::to_vec(lease)?)?;
+    let result = fs::hard_link(&tmp, &path);
+    fs::remove_file(&tmp)?;
+    match result {
+        Ok(()) => Ok(true),
+        Err(error) if error.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+        Err(error) => Err(error).wrap_err_with(|| format!("Could not create {}", path.display())),
+    }
+}
+
+fn lease_path(dir: &Path, term: u64) -> PathBuf {
+    dir.join(format!("{PREFIX}{term}{SUFFIX}"))
+}
+
+/// The lease of the highest term in `dir`.
+fn latest_lease(dir: &Path) -> EyreResult<Option<Lease>> {
+    let mut latest = None;
+    for entry in fs::read_dir(dir).wrap_err_with(|| format!("Could not list {}", dir.display()))? {
+        let name = entry?.file_name();
+        let term = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(PREFIX))
+            .and_then(|name| name.strip_suffix(SUFFIX))
+            .and_then(|term| term.parse::<u64>().ok());
+        latest = latest.max(term);
+    }
+    latest
+        .map(|term| read_lease(&lease_path(dir, term)))
+        .transpose()
+}
+
+fn read_lease(path: &Path) -> EyreResult<Lease> {
+    let contents = fs::read(path).wrap_err_with(|| format!("Could not read {}", path.display()))?;
+    Ok(serde_json::from_slice(&contents)?)
+}
+
+/// Recover the state the previous leader left in the shared directory.
+fn take_over(ceremony: &SharedCeremony, policy: &PolicyEngine, bans: &BanList) -> EyreResult<()> {
+    ceremony.blocking_lock().reload(true)?;
+    policy.reload()?;
+    bans.reload()?;
+    Ok(())
+}
+
+/// Reject requests other than reads while on standby.
+pub async fn standby(request: Request<Body>, next: Next<Body>) -> Response {
+    let leader = request
+        .extensions()
+        .get::<Leadership>()
+        .map_or(true, Leadership::is_leader);
+    if leader
+        || matches!(
+            *request.method(),
+            Method::GET | Method::HEAD | Method::OPTIONS
+        )
+    {
+        return next.run(request).await;
+    }
+    let body = Json(json!({ "error": "This coordinator is on standby" }));
+    (StatusCode::SERVICE_UNAVAILABLE, body).into_response()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time before unix epoch")
+        .as_secs()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test::TestDir;
+
+    #[test]
+    fn test_lease() {
+        let dir = TestDir::new("leader");
+        fs::create_dir_all(&dir).unwrap();
+        let lease = Duration::from_secs(9);
+
+        assert_eq!(try_acquire(&dir, "a", lease, 100).unwrap(), Some(1));
+        assert_eq!(try_acquire(&dir, "b", lease, 105).unwrap(), None);
+        // Renewal extends the lease.
+        assert_eq!(try_acquire(&dir, "a", lease, 105).unwrap(), Some(1));
+        assert_eq!(try_acquire(&dir, "b", lease, 112).unwrap(), None);
+        // Once it lapses another node takes over in a new term.
+        assert_eq!(try_acquire(&dir, "b", lease, 114).unwrap(), Some(2));
+        assert_eq!(try_acquire(&dir, "a", lease, 115).unwrap(), None);
+        assert_eq!(
+            latest_lease(&dir).unwrap(),
+            Some(Lease {
+                term:    2,
+                holder:  "b".to_string(),
+                expires: 123,
+            })
+        );
+        // Close to expiry the lease is not renewed, but a new term started.
+        assert_eq!(try_acquire(&dir, "b", lease, 121).unwrap(), Some(3));
+    }
+
+    #[test]
+    fn test_missed_renewals() {
+        let dir = TestDir::new("leader-missed");
+        fs::create_dir_all(&dir).unwrap();
+        let config = Config {
+            node_id: "a".to_string(),
+            lease:   Duration::from_secs(9),
+        };
+        let leadership = Leadership::new(Some(&config), &dir);
+        let lease = leadership.lease.as_ref().unwrap();
+        assert!(!leadership.is_leader());
+        assert!(leadership.fence().is_err());
+
+        // Acquired a lease that lapsed since, without any renewal attempt.
+        let then = now() - 10;
+        assert_eq!(try_acquire(&dir, "a", config.lease, then).unwrap(), Some(1));
+        lease.hold(1, then + 6);
+        assert!(!leadership.is_leader());
+        assert_eq!(leadership.role(), Some(Role::Standby));
+        assert!(leadership.fence().is_err());
+
+        // A standby takes over, and a late renewal of the old term does not
+        // let the old leader write.
+        assert_eq!(
+            try_acquire(&dir, "b", config.lease, now()).unwrap(),
+            Some(2)
+        );
+        lease.hold(1, now() + 6);
+        assert!(leadership.is_leader());
+        assert!(leadership.fence().is_err());
+
+        lease.hold(2, now() + 6);
+        assert!(leadership.fence().is_err());
+    }
+
+    #[test]
+    fn test_fence() {
+        let dir = TestDir::new("leader-fence");
+        fs::create_dir_all(&dir).unwrap();
+        let config = Config {
+            node_id: "a".to_string(),
+            lease:   Duration::from_secs(9),
+        };
+        let leadership = Leadership::new(Some(&config), &dir);
+        let now = now();
+        assert_eq!(try_acquire(&dir, "a", config.lease, now).unwrap(), Some(1));
+        leadership.lease.as_ref().unwrap().hold(1, now + 6);
+        assert!(leadership.is_leader());
+        assert_eq!(leadership.role(), Some(Role::Leader));
+        leadership.fence().unwrap();
+
+        // Always leading without election.
+        let alone = Leadership::default();
+        assert!(alone.is_leader());
+        assert_eq!(alone.role(), None);
+        alone.fence().unwrap();
+    }
+
+    #[test]
+    fn test_exclusive() {
+        let dir = TestDir::new("leader-exclusive");
+        fs::create_dir_all(&dir).unwrap();
+        let lease = |holder: &str| Lease {
+            term:    1,
+            holder:  holder.to_string(),
+            expires: 100,
+        };
+
+        // Two nodes that both saw the lease lapse compete for the next term.
+        assert!(create_lease(&dir, &lease("a")).unwrap());
+        assert!(!create_lease(&dir, &lease("b")).unwrap());
+        assert_eq!(latest_lease(&dir).unwrap(), Some(lease("a")));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+}
@@ -2,89 +2,960 @@
 #![warn(clippy::all, clippy::pedantic, clippy::cargo, clippy::nursery)]
 #![cfg_attr(any(test, feature = "bench"), allow(clippy::wildcard_imports))]
 
-use ark_bls12_381::Fr;
-use ark_ff::UniformRand;
+mod admin;
+pub mod analytics;
+pub mod attestation;
+pub mod audit;
+pub mod auth;
+pub mod bans;
+pub mod benchmark;
+mod cache;
+mod ceremony;
+pub mod challenge;
+pub mod checkpoint;
+pub mod client;
+pub mod commitment;
+mod compression;
+pub mod compute;
+#[cfg(any(test, feature = "conformance-tests"))]
+pub mod conformance;
+mod contribute;
+mod contributor;
+mod cors;
+mod diff;
+mod eip712;
+pub mod entropy;
+mod events;
+mod export;
+mod finalize;
+mod follower;
+mod initial;
+mod integrity;
+pub mod ipfs;
+mod leader;
+mod limits;
+mod logfile;
+mod mirror;
+mod notify;
+mod observer;
+mod participants;
+mod points;
+pub mod policy;
+mod pseudonym;
+pub mod queue;
+pub mod receipt;
+mod request_id;
+mod resumable;
+mod revocation;
+mod schedule;
+mod scheduler;
+mod schema;
+mod session;
+mod shutdown;
+mod signed;
+pub mod signing;
+mod simulate;
+mod snapshot;
+mod status;
+mod storage;
+mod tenants;
+pub mod test_ceremony;
+pub mod tiers;
+mod transcript;
+mod upload;
+mod validate_batch;
+mod verify_transcript;
+mod wal;
+
+/// The ceremony cryptography, see [`kzg_ceremony_crypto`].
+pub use kzg_ceremony_crypto as crypto;
+
+use admin::AdminToken;
+use analytics::QueueAnalytics;
+use attestation::AttestationKey;
+use audit::AuditLog;
 use axum::{
-    routing::{get, post},
-    Router, Server,
+    http::{header::HeaderName, Method},
+    middleware,
+    routing::{delete, get, post},
+    Extension, Router,
+};
+use axum_server::{tls_rustls::RustlsConfig, Handle};
+use bans::{BanList, Thresholds};
+use cache::RangeCache;
+use ceremony::{Ceremony, SharedCeremony, Sizes, UploadRng};
+use challenge::{Captcha, JoinGuard, PowConfig, SiteVerify};
+use clap::{Parser, Subcommand};
+use commitment::{CommitmentSigner, MerkleLamport, Seed};
+use compute::ComputePool;
+use events::EventBus;
+use eyre::{bail, ensure, Result as EyreResult, Result, WrapErr};
+use futures::future::join_all;
+use kzg_ceremony_crypto::{CurveBackend, VerificationRng, SIZES};
+use limits::{BodyTimeout, MaxRequestSize};
+use notify::{Notifier, Webhooks};
+use observer::ObserverFeed;
+use participants::ParticipantList;
+use policy::{Policy, PolicyEngine};
+use pseudonym::{MappingKey, Pseudonyms, Salt};
+use queue::{Queue, QueueMode};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use receipt::ReceiptLog;
+use resumable::UploadSessions;
+use revocation::Revocations;
+use schedule::{QuietHours, Schedule};
+use schema::ContributionSchema;
+use session::{SessionKey, SessionKeys, SharedSessionKeys};
+use shutdown::Drain;
+use signing::{Address, CoordinatorKey, CoordinatorKeys};
+use snapshot::Snapshots;
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::Duration,
 };
-use clap::Parser;
-use cli_batteries::await_shutdown;
-use eyre::{bail, ensure, Result as EyreResult, Result};
-use kzg_ceremony_crypto::{Contribution, ContributionsError, Transcript};
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use storage::TranscriptStore;
+use tiers::Tiers;
+use tokio::{spawn, sync::Mutex, task::spawn_blocking};
 use tower_http::trace::TraceLayer;
-use tracing::{info, info_span};
+use tracing::{info, warn};
+use transcript::FullTranscriptCache;
+use upload::MaxUploadSize;
 use url::{Host, Url};
 
 #[derive(Clone, Debug, PartialEq, Parser)]
+#[allow(clippy::struct_excessive_bools)] // Command line flags
 pub struct Options {
     /// API Server url
     #[clap(long, env, default_value = "http://127.0.0.1:8080/")]
     pub server: Url,
+
+    /// PEM certificate chain to serve `https://` server urls with
+    #[clap(long, env, requires = "tls-key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key of the certificate
+    #[clap(long, env, requires = "tls-cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Directory where the transcript is stored
+    #[clap(long, env, default_value = "transcript")]
+    pub transcript_dir: PathBuf,
+
+    /// Contribution file to start a new ceremony from, instead of generators.
+    #[clap(long, env, conflicts_with = "transcript")]
+    pub initial_contribution: Option<PathBuf>,
+
+    /// Latest batch contribution file to resume a new transcript store from.
+    #[clap(long, env)]
+    pub transcript: Option<PathBuf>,
+
+    /// Secp256k1 key used to sign receipts, hex encoded. Random if not set.
+    #[clap(long, env)]
+    pub coordinator_key: Option<CoordinatorKey>,
+
+    /// Addresses of previous coordinator keys whose receipts are still
+    /// trusted, comma separated
+    #[clap(long, env, value_delimiter = ',')]
+    pub previous_coordinator_addresses: Vec<Address>,
+
+    /// Key used to authenticate session tokens, hex encoded. Random if not set.
+    #[clap(long, env)]
+    pub session_key: Option<SessionKey>,
+
+    /// Previous session keys whose tokens are still accepted, hex encoded and
+    /// comma separated
+    #[clap(long, env, value_delimiter = ',')]
+    pub previous_session_keys: Vec<SessionKey>,
+
+    /// Sign-in providers to issue session tokens with, comma separated: any of
+    /// `github`, `siwe` and, in test ceremonies, `mock`. Tokens are issued
+    /// outside the coordinator if not set.
+    #[clap(long, env, value_delimiter = ',')]
+    pub auth_providers: Vec<String>,
+
+    /// Client id of the GitHub OAuth app
+    #[clap(long, env)]
+    pub github_client_id: Option<String>,
+
+    /// Client secret of the GitHub OAuth app
+    #[clap(long, env)]
+    pub github_client_secret: Option<String>,
+
+    /// Domain Sign-In with Ethereum messages must be addressed to, e.g.
+    /// `ceremony.ethereum.org`
+    #[clap(long, env)]
+    pub siwe_domain: Option<String>,
+
+    /// Ethereum JSON-RPC endpoint to look up the nonce of accounts signing in
+    #[clap(long, env)]
+    pub siwe_rpc: Option<Url>,
+
+    /// Salt of identity pseudonyms, hex encoded. Identities are stored as they
+    /// are if not set.
+    #[clap(long, env)]
+    pub identity_salt: Option<Salt>,
+
+    /// Key encrypting the mapping from pseudonyms to identities, hex encoded.
+    /// No mapping is kept if not set.
+    #[clap(long, env)]
+    pub identity_mapping_key: Option<MappingKey>,
+
+    /// Seed for the post-quantum receipt commitment keys, hex encoded. Receipts
+    /// carry no commitment if not set.
+    #[clap(long, env)]
+    pub commitment_seed: Option<Seed>,
+
+    /// Height of the commitment key tree, allowing `2^height` contributions.
+    #[clap(long, env, default_value = "17")]
+    pub commitment_tree_height: usize,
+
+    /// Mirrors to push transcript snapshots to after every contribution,
+    /// comma separated.
+    #[clap(long, env, value_delimiter = ',')]
+    pub mirrors: Vec<Url>,
+
+    /// Webhooks to post to when a participant reaches the front of the queue
+    /// or nears their contribution deadline, comma separated.
+    #[clap(long, env, value_delimiter = ',')]
+    pub webhooks: Vec<Url>,
+
+    /// Seconds before the contribution deadline to notify the participant
+    #[clap(long, env, default_value = "30")]
+    pub deadline_warning: u64,
+
+    /// Leading zero bits of the proof of work required to join the queue,
+    /// `0` to disable it
+    #[clap(long, env, default_value = "0")]
+    pub join_pow_bits: u32,
+
+    /// Difficulty the proof of work may grow to under queue pressure
+    #[clap(long, env, default_value = "24")]
+    pub join_pow_max_bits: u32,
+
+    /// Seconds a proof of work challenge stays valid
+    #[clap(long, env, default_value = "300")]
+    pub join_pow_ttl: u64,
+
+    /// `siteverify` endpoint of a CAPTCHA provider, e.g.
+    /// `https://hcaptcha.com/siteverify`. Joining the queue requires a solved
+    /// CAPTCHA if set.
+    #[clap(long, env, requires = "captcha-secret")]
+    pub captcha_verify_url: Option<Url>,
+
+    /// Secret key of the CAPTCHA site
+    #[clap(long, env)]
+    pub captcha_secret: Option<String>,
+
+    /// HTTP RPC API of an IPFS node to pin contributions and transcripts to,
+    /// e.g. `http://127.0.0.1:5001/`. Pinning is disabled if not set.
+    #[clap(long, env)]
+    pub ipfs_api: Option<Url>,
+
+    /// S3 compatible bucket URL to upload transcript snapshots to, e.g.
+    /// `https://{bucket}.s3.{region}.amazonaws.com/{prefix}/` or
+    /// `https://storage.googleapis.com/{bucket}/{prefix}/`. Snapshots are
+    /// disabled if not set.
+    #[clap(long, env, requires_all = &["snapshot-access-key", "snapshot-secret-key"])]
+    pub snapshot_url: Option<Url>,
+
+    /// Access key id for the snapshot bucket
+    #[clap(long, env)]
+    pub snapshot_access_key: Option<String>,
+
+    /// Secret access key for the snapshot bucket
+    #[clap(long, env)]
+    pub snapshot_secret_key: Option<String>,
+
+    /// Region of the snapshot bucket, `auto` for Google Cloud Storage
+    #[clap(long, env, default_value = "us-east-1")]
+    pub snapshot_region: String,
+
+    /// Number of contributions between transcript snapshots
+    #[clap(long, env, default_value = "10")]
+    pub snapshot_interval: usize,
+
+    /// Number of transcript snapshots to keep, zero to keep all
+    #[clap(long, env, default_value = "24")]
+    pub snapshot_retain: usize,
+
+    /// Create a missing transcript store from the latest snapshot
+    #[clap(long, env, requires = "snapshot-url")]
+    pub snapshot_restore: bool,
+
+    /// Ethereum JSON-RPC endpoint to attest transcript checkpoints through,
+    /// plain HTTP only. Attestation is disabled if not set.
+    #[clap(long, env, requires_all = &["attestation-contract", "attestation-key"])]
+    pub attestation_rpc: Option<Url>,
+
+    /// Contract whose `attest(uint256,bytes32,bytes32)` function receives the
+    /// checkpoints
+    #[clap(long, env)]
+    pub attestation_contract: Option<ethers::types::Address>,
+
+    /// Private key of the account paying for attestations, hex encoded
+    #[clap(long, env)]
+    pub attestation_key: Option<AttestationKey>,
+
+    /// Seconds between attestations of the latest checkpoint
+    #[clap(long, env, default_value = "3600")]
+    pub attestation_interval: u64,
+
+    /// Seconds between background re-verifications of the stored transcript,
+    /// zero to disable
+    #[clap(long, env, default_value = "600")]
+    pub reverify_interval: u64,
+
+    /// Primary coordinator to follow as a read-only mirror. Its contributions
+    /// are re-verified and served from the local transcript, and requests
+    /// other than reads are rejected.
+    #[clap(long, env)]
+    pub follow: Option<Url>,
+
+    /// Seconds between polls of the primary coordinator when following
+    #[clap(long, env, default_value = "5")]
+    pub follow_interval: u64,
+
+    /// Seconds a leader lease lasts when several coordinators share the
+    /// transcript directory, zero to run alone. Only the leader accepts
+    /// contributions, the others are hot standbys.
+    #[clap(long, env, default_value = "0")]
+    pub leader_lease: u64,
+
+    /// Node id to hold the leader lease with, random by default
+    #[clap(long, env)]
+    pub node_id: Option<String>,
+
+    /// File to append the logs to, in addition to stderr. The format and
+    /// levels are set with `--log-format` and `--log-filter`.
+    #[clap(long, env)]
+    pub log_file: Option<PathBuf>,
+
+    /// Size in MiB to rotate the log file at
+    #[clap(long, env, default_value = "100")]
+    pub log_file_size: u64,
+
+    /// Number of rotated log files to keep
+    #[clap(long, env, default_value = "5")]
+    pub log_file_keep: usize,
+
+    /// JSON file with the participation policy, defaults to one contribution
+    /// per identity
+    #[clap(long, env)]
+    pub policy: Option<PathBuf>,
+
+    /// Bearer token for the `/admin` routes, which are disabled without it
+    #[clap(long, env)]
+    pub admin_token: Option<AdminToken>,
+
+    /// Seconds a participant has to upload after starting their contribution
+    #[clap(long, env, default_value = "180")]
+    pub contribution_timeout: u64,
+
+    /// How the next contributor is picked: `fifo` in order of joining, or
+    /// `lottery` at random among participants who recently checked in
+    #[clap(long, env, default_value = "fifo")]
+    pub queue_mode: QueueMode,
+
+    /// Seconds a check-in keeps a participant in the lottery
+    #[clap(long, env, default_value = "30")]
+    pub checkin_window: u64,
+
+    /// Let the next participant start on an upload while it is verified. They
+    /// go back to the front of the queue if verification fails.
+    #[clap(long, env)]
+    pub pipeline: bool,
+
+    /// Unix time the ceremony opens. Nobody can join the queue before.
+    #[clap(long, env)]
+    pub opens_at: Option<u64>,
+
+    /// Unix time the ceremony closes. Nobody can join the queue or start a
+    /// contribution after.
+    #[clap(long, env)]
+    pub closes_at: Option<u64>,
+
+    /// Daily UTC hours the ceremony is closed, e.g. `22-6`
+    #[clap(long, env)]
+    pub quiet_hours: Option<QuietHours>,
+
+    /// JSON file with queue priority tiers and reserved windows. All
+    /// participants are treated equally if not set.
+    #[clap(long, env)]
+    pub queue_tiers: Option<PathBuf>,
+
+    /// Reject contributions without a proof of knowledge of tau for their
+    /// pubkeys. Proofs that are present are always checked.
+    #[clap(long, env)]
+    pub require_pubkey_proof: bool,
+
+    /// Number of aborted contributions before an identity is banned
+    #[clap(long, env, default_value = "3")]
+    pub max_aborts: u32,
+
+    /// Number of invalid uploads before an identity is banned
+    #[clap(long, env, default_value = "3")]
+    pub max_invalid_contributions: u32,
+
+    /// Number of missed deadlines before an identity is banned
+    #[clap(long, env, default_value = "2")]
+    pub max_deadline_misses: u32,
+
+    /// JSON Schema file to validate uploaded contributions against. Uploads
+    /// are not schema validated if not set.
+    #[clap(long, env)]
+    pub contribution_schema: Option<PathBuf>,
+
+    /// Maximum size in bytes of an uploaded contribution
+    #[clap(long, env, default_value = "33554432")]
+    pub max_upload_size: usize,
+
+    /// Maximum size in bytes of request bodies other than contribution
+    /// uploads
+    #[clap(long, env, default_value = "16384")]
+    pub max_request_size: usize,
+
+    /// Seconds a client has for the TLS handshake and for sending request
+    /// headers
+    #[clap(long, env, default_value = "10")]
+    pub header_read_timeout: u64,
+
+    /// Seconds a client may pause while sending a request body
+    #[clap(long, env, default_value = "30")]
+    pub body_read_timeout: u64,
+
+    /// Maximum number of connections served at a time, unlimited if zero
+    #[clap(long, env, default_value = "1024")]
+    pub max_connections: usize,
+
+    /// Threads for verifying contributions, one per core if zero
+    #[clap(long, env, default_value = "0")]
+    pub compute_threads: usize,
+
+    /// Maximum number of verifications queued or running at a time
+    #[clap(long, env, default_value = "4")]
+    pub compute_queue: usize,
+
+    /// Memory budget in bytes for cached transcript ranges.
+    #[clap(long, env, default_value = "67108864")]
+    pub range_cache_size: usize,
+
+    /// Origins allowed to call the API from a browser, comma separated, or
+    /// `*` for any. Cross-origin requests are not allowed if not set.
+    #[clap(long, env, value_delimiter = ',')]
+    pub cors_origins: Vec<String>,
+
+    /// Methods allowed in cross-origin requests, comma separated
+    #[clap(long, env, value_delimiter = ',', default_value = "GET,POST,DELETE")]
+    pub cors_methods: Vec<Method>,
+
+    /// Request headers allowed in cross-origin requests, comma separated
+    #[clap(
+        long,
+        env,
+        value_delimiter = ',',
+        default_value = "authorization,content-type"
+    )]
+    pub cors_headers: Vec<HeaderName>,
+
+    /// Allow cross-origin requests with credentials. Requires explicit
+    /// origins.
+    #[clap(long, env)]
+    pub cors_credentials: bool,
+
+    /// Run a throwaway ceremony with tiny sizes, seeded keys and logins
+    /// without authentication, for local development. Never use it for a
+    /// real ceremony.
+    #[clap(long, env)]
+    pub test_ceremony: bool,
+
+    /// Let identities contribute more than once. Only meant for test
+    /// ceremonies.
+    #[clap(long, env)]
+    pub allow_repeat_contributions: bool,
+
+    /// Seed for the keys, lottery draws and verification randomness of a test
+    /// ceremony
+    #[clap(long, env, default_value = "0")]
+    pub test_seed: u64,
+
+    /// JSON file with further ceremonies to host under `/ceremonies/<id>`,
+    /// see [`tenants`]
+    #[clap(long, env)]
+    pub ceremonies: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, Subcommand)]
+pub enum Command {
+    /// Validate a directory of candidate contributions against a transcript
+    ValidateBatch(validate_batch::Options),
+
+    /// Contribute to a remote ceremony from the terminal
+    Contribute(contributor::Options),
+
+    /// Verify a transcript file or a coordinator's transcript from scratch
+    VerifyTranscript(verify_transcript::Options),
+
+    /// Export the ceremony output as trusted setup files for KZG libraries
+    Export(export::Options),
+
+    /// Load test a coordinator with concurrent virtual contributors
+    Simulate(simulate::Options),
+
+    /// Benchmark verification on this machine and report its capacity as JSON
+    Bench(benchmark::Options),
+
+    /// Check a running sequencer against the coordinator REST API
+    #[cfg(feature = "conformance-tests")]
+    Conformance(conformance::Options),
+}
+
+/// # Errors
+///
+/// Returns an error if the command fails.
 pub async fn main(options: Options) -> EyreResult<()> {
-    let app = Router::new()
-        .layer(TraceLayer::new_for_http())
-        .route("/login", post(|| async { "Hello, World!" }))
-        .route("/ceremony/status", get(|| async { "Hello, World!" }))
-        .route("/queue/join", post(|| async { "Hello, World!" }))
-        .route("/queue/checkin", post(|| async { "Hello, World!" }))
-        .route("/queue/leave", post(|| async { "Hello, World!" }))
-        .route("/contribution/start", post(|| async { "Hello, World!" }))
-        .route("/contribution/complete", post(|| async { "Hello, World!" }))
-        .route("/contribution/abort", post(|| async { "Hello, World!" }));
-
-    let transcripts = kzg_ceremony_crypto::SIZES
-        .iter()
-        .map(|(n1, n2)| Transcript::new(*n1, *n2))
-        .collect::<Vec<_>>();
-
-    let mut rng = rand::thread_rng();
-    let contributions = {
-        let span = info_span!("Generating contributions ");
-        let _guard = span.enter();
-        let contributions = transcripts
-            .iter()
-            .map(|t| {
-                let mut contribution = Contribution::new(t.g1_powers.len(), t.g2_powers.len());
-                contribution.add_tau(&Fr::rand(&mut rng));
-                contribution
-            })
-            .collect::<Vec<_>>();
-        contributions
-    };
-    {
-        let span = info_span!("Contributions subgroup check", n = contributions.len());
-        let _guard = span.enter();
-        contributions
-            .iter()
-            .for_each(|contribution| contribution.subgroup_check());
-    };
+    logfile::start(logfile::Config::from_options(&options))?;
+    match options.command {
+        Some(Command::ValidateBatch(options)) => validate_batch::main(options).await,
+        Some(Command::Contribute(options)) => contributor::main(options).await,
+        Some(Command::VerifyTranscript(options)) => verify_transcript::main(options).await,
+        Some(Command::Export(options)) => export::main(options).await,
+        Some(Command::Simulate(options)) => simulate::main(options).await,
+        Some(Command::Bench(options)) => benchmark::main(options).await,
+        #[cfg(feature = "conformance-tests")]
+        Some(Command::Conformance(options)) => conformance::main(options).await,
+        None => serve(options).await,
+    }
+}
 
-    {
-        let span = info_span!("Verifying contributions");
-        let _guard = span.enter();
-        transcripts
-            .iter()
-            .zip(contributions.iter())
-            .for_each(|(transcript, contribution)| contribution.verify(&transcript));
-    };
+async fn serve(options: Options) -> EyreResult<()> {
+    let (app, drain) = build(&options).await?;
+    let (app, mut drains) = tenants::mount(app, &options).await?;
+    drains.push(drain);
 
     // Run the server
     let (addr, prefix) = parse_url(&options.server)?;
     let app = Router::new().nest(prefix, app);
-    let server = Server::try_bind(&addr)?.serve(app.into_make_service());
-    info!("Listening on http://{}{}", server.local_addr(), prefix);
-    server.with_graceful_shutdown(await_shutdown()).await?;
+    let limits = limits::Config::from_options(&options);
+    let handle = Handle::new();
+    spawn({
+        let handle = handle.clone();
+        async move {
+            join_all(drains.into_iter().map(Drain::on_shutdown)).await;
+            handle.graceful_shutdown(None);
+        }
+    });
+    spawn({
+        let handle = handle.clone();
+        let scheme = options.server.scheme().to_string();
+        let prefix = prefix.to_string();
+        async move {
+            if let Some(addr) = handle.listening().await {
+                info!("Listening on {}://{}{}", scheme, addr, prefix);
+            }
+        }
+    });
+    if let (Some(cert), Some(key)) = (&options.tls_cert, &options.tls_key) {
+        ensure!(
+            options.server.scheme() == "https",
+            "--tls-cert requires an https:// server url"
+        );
+        let config = RustlsConfig::from_pem_file(cert, key)
+            .await
+            .wrap_err("Could not load TLS certificate")?;
+        axum_server::bind_rustls(addr, config)
+            .map(|tls| {
+                tls.handshake_timeout(limits.header_read_timeout)
+                    .acceptor(limits.connection_limit())
+            })
+            .http_config(limits.http_config())
+            .handle(handle)
+            .serve(app.into_make_service())
+            .await?;
+    } else {
+        ensure!(
+            options.server.scheme() == "http",
+            "https:// server urls require --tls-cert and --tls-key"
+        );
+        axum_server::bind(addr)
+            .acceptor(limits.connection_limit())
+            .http_config(limits.http_config())
+            .handle(handle)
+            .serve(app.into_make_service())
+            .await?;
+    }
     Ok(())
 }
 
+/// Load the ceremony state and build the API routes.
+#[cfg(test)]
+async fn app(options: &Options) -> EyreResult<Router> {
+    Ok(build(options).await?.0)
+}
+
+/// Load the ceremony state and build the API routes, along with the state to
+/// wind down on shutdown.
+#[allow(clippy::too_many_lines)] // Mostly route and layer declarations
+async fn build(options: &Options) -> EyreResult<(Router, Drain)> {
+    let sizes: &[(usize, usize)] = if options.test_ceremony {
+        warn!("Running a test ceremony, anyone can log in as anyone");
+        &test_ceremony::SIZES
+    } else {
+        &SIZES
+    };
+    // Test ceremonies derive missing keys from the seed, so they are the same
+    // across restarts.
+    let mut test_rng = options
+        .test_ceremony
+        .then(|| test_ceremony::rng(options.test_seed));
+    let coordinator_key = Arc::new(CoordinatorKeys::new(
+        match (&options.coordinator_key, &mut test_rng) {
+            (Some(key), _) => key.clone(),
+            (None, Some(rng)) => CoordinatorKey::from_rng(rng),
+            (None, None) => {
+                warn!("No coordinator key configured, using a random key");
+                CoordinatorKey::random()
+            }
+        },
+        &options.previous_coordinator_addresses,
+    ));
+    info!(address = %coordinator_key.address(), "Coordinator key loaded");
+    let session_key = match (&options.session_key, &mut test_rng) {
+        (Some(key), _) => key.clone(),
+        (None, Some(rng)) => SessionKey::from_rng(rng),
+        (None, None) => SessionKey::random(),
+    };
+
+    let commitment: Option<Arc<dyn CommitmentSigner>> = match options.commitment_seed.clone() {
+        Some(seed) => {
+            let height = options.commitment_tree_height;
+            let signer = spawn_blocking(move || MerkleLamport::new(seed, height)).await?;
+            info!(public_key = %signer.public_key(), "Commitment signer loaded");
+            Some(Arc::new(signer))
+        }
+        None => None,
+    };
+
+    let schema = options
+        .contribution_schema
+        .as_deref()
+        .map(ContributionSchema::load)
+        .transpose()?
+        .map(Arc::new);
+
+    let compute = ComputePool::new(options.compute_threads, options.compute_queue)?;
+    info!(
+        threads = compute.threads(),
+        asm = crypto::ASM,
+        backend = crypto::DefaultBackend::NAME,
+        "Compute pool started"
+    );
+
+    let events = EventBus::new();
+    if TranscriptStore::exists(&options.transcript_dir) {
+        ensure!(
+            options.initial_contribution.is_none() && options.transcript.is_none(),
+            "Transcript store {} already exists, --initial-contribution and --transcript only \
+             apply to new stores",
+            options.transcript_dir.display()
+        );
+    }
+    let snapshots = Snapshots::from_options(options).map(Arc::new);
+    if let (true, Some(snapshots)) = (options.snapshot_restore, &snapshots) {
+        if !TranscriptStore::exists(&options.transcript_dir) {
+            if let Some((snapshot, transcript)) = snapshots.restore(sizes).await? {
+                info!(index = snapshot.index, "Restoring transcript snapshot");
+                TranscriptStore::create(&options.transcript_dir, &transcript)?;
+            } else {
+                warn!("No transcript snapshot to restore");
+            }
+        }
+    }
+    let leader_config = leader::Config::from_options(options);
+    let leadership = leader::Leadership::new(leader_config.as_ref(), &options.transcript_dir);
+    let store = if leader_config.is_some() && TranscriptStore::exists(&options.transcript_dir) {
+        // The leader may be writing, it is recovered on takeover.
+        TranscriptStore::open_shared(&options.transcript_dir)?
+    } else {
+        TranscriptStore::open_or_create(&options.transcript_dir, || {
+            initial::load(
+                sizes,
+                options.initial_contribution.as_deref(),
+                options.transcript.as_deref(),
+            )
+        })?
+    };
+    let analytics = QueueAnalytics::open(&options.transcript_dir)?;
+    analytics.spawn();
+    let policy = match &options.policy {
+        Some(path) => Policy::load(path)?,
+        None => Policy::default(),
+    };
+    let policy = Arc::new(
+        PolicyEngine::open(&options.transcript_dir, policy)?
+            .with_repeat_contributions(options.allow_repeat_contributions),
+    );
+    let bans = Arc::new(BanList::open(&options.transcript_dir, Thresholds {
+        aborts:                options.max_aborts,
+        invalid_contributions: options.max_invalid_contributions,
+        deadline_misses:       options.max_deadline_misses,
+    })?);
+    let audit = Arc::new(AuditLog::open(&options.transcript_dir)?);
+    audit::spawn(audit.clone());
+    let receipts = Arc::new(ReceiptLog::open(&options.transcript_dir)?);
+    let participants = Arc::new(ParticipantList::open(&options.transcript_dir)?);
+    let revocations = Arc::new(Revocations::open(&options.transcript_dir)?);
+    let schedule = Schedule::from_options(options)?;
+    let pseudonyms = Arc::new(Pseudonyms::open(
+        options.identity_salt.clone(),
+        options.identity_mapping_key.clone(),
+        &options.transcript_dir,
+    )?);
+    let tiers = match &options.queue_tiers {
+        Some(path) => Tiers::load(path)?.pseudonymized(|identity| pseudonyms.hash(identity)),
+        None => Tiers::default(),
+    };
+    // Lottery draws and verification are reproducible in test ceremonies as
+    // well.
+    let mut fork_seed = || test_rng.as_mut().map(Rng::gen::<[u8; 32]>);
+    let notifier = (!options.webhooks.is_empty()).then(|| {
+        Arc::new(Webhooks::new(
+            options.webhooks.clone(),
+            coordinator_key.clone(),
+        )) as Arc<dyn Notifier>
+    });
+    let queue = Arc::new(
+        Queue::new(
+            events.clone(),
+            analytics.clone(),
+            bans.clone(),
+            audit.clone(),
+            Duration::from_secs(options.contribution_timeout),
+        )
+        .with_mode(
+            options.queue_mode,
+            Duration::from_secs(options.checkin_window),
+        )
+        .with_pipelining(options.pipeline)
+        .with_tiers(tiers)
+        .with_schedule(schedule)
+        .with_notifier(notifier.clone())
+        .with_rng(fork_seed().map(StdRng::from_seed)),
+    );
+    schedule::spawn(schedule, events.clone());
+    let join_guard = Arc::new(JoinGuard::new(
+        PowConfig::from_options(options),
+        SiteVerify::from_options(options)?.map(|captcha| Arc::new(captcha) as Arc<dyn Captcha>),
+    ));
+    if notifier.is_some() {
+        notify::spawn(queue.clone(), Duration::from_secs(options.deadline_warning));
+    }
+    let observers = ObserverFeed::new();
+    let ceremony = Ceremony::new(store, events.clone(), observers.clone())?
+        .with_required_pubkey_proof(options.require_pubkey_proof)
+        .with_rng(fork_seed().map(VerificationRng::from_seed))
+        .with_leadership(leadership.clone());
+    let upload_rng = UploadRng::new(fork_seed().map(VerificationRng::from_seed));
+    ensure!(
+        ceremony.transcript().sizes() == sizes,
+        "Transcript store {} has sub-ceremony sizes {:?}, expected {:?}",
+        options.transcript_dir.display(),
+        ceremony.transcript().sizes(),
+        sizes
+    );
+    info!(
+        contributions = ceremony.num_contributions(),
+        "Loaded transcript from {}",
+        options.transcript_dir.display()
+    );
+    let hash_chain = ceremony.hash_chain().clone();
+    let ceremony: SharedCeremony = Arc::new(Mutex::new(ceremony));
+    attestation::spawn(
+        attestation::Config::from_options(options),
+        hash_chain.clone(),
+    );
+    mirror::spawn(
+        options.mirrors.clone(),
+        ceremony.clone(),
+        events.clone(),
+        coordinator_key.clone(),
+    );
+    snapshot::spawn(snapshots, ceremony.clone(), events.clone());
+    let transcript_cache = Arc::new(FullTranscriptCache::new());
+    transcript::spawn(transcript_cache.clone(), ceremony.clone(), events.clone());
+    let ipfs = ipfs::spawn(options.ipfs_api.clone(), ceremony.clone(), events.clone());
+    let integrity = integrity::spawn(
+        (options.reverify_interval > 0).then(|| Duration::from_secs(options.reverify_interval)),
+        ceremony.clone(),
+        hash_chain.clone(),
+    );
+    let drain = Drain::new(queue.clone(), ceremony.clone(), &options.transcript_dir);
+    drain.restore()?;
+    if finalize::is_finalized(&options.transcript_dir) {
+        info!("Ceremony is finalized");
+        queue.close();
+    }
+    leader::spawn(
+        leader_config,
+        &leadership,
+        ceremony.clone(),
+        policy.clone(),
+        bans.clone(),
+    );
+    if let Some(primary) = options.follow.clone() {
+        follower::spawn(
+            primary,
+            Duration::from_secs(options.follow_interval),
+            ceremony.clone(),
+        );
+    }
+
+    let cors = cors::layer(options)?;
+    let limits = limits::Config::from_options(options);
+
+    // Mock logins only exist in test ceremonies, otherwise `/login` is a 404.
+    let mut app = Router::new();
+    if options.test_ceremony {
+        app = app.route("/login", post(test_ceremony::login));
+    }
+
+    let app = app
+        .route("/login/:provider", post(auth::login))
+        .route("/login/:provider/nonce", get(auth::nonce))
+        .route("/logout", post(revocation::logout))
+        .route(
+            "/ceremony/status",
+            get(status::handler).layer(middleware::from_fn(signed::middleware)),
+        )
+        .route("/queue/join", post(queue::join))
+        .route("/queue/challenge", post(queue::challenge))
+        .route("/queue/checkin", post(queue::checkin))
+        .route("/queue/leave", post(queue::leave))
+        .route("/contribution/start", post(queue::start))
+        .route("/contribution/pending", get(queue::pending))
+        .route("/contribution/upload/init", post(resumable::init))
+        .route("/contribution/upload/finish", post(resumable::finish))
+        .route("/contribution/abort", post(queue::abort))
+        .route("/ceremony/events", get(events::handler))
+        .route("/ceremony/observe", get(observer::handler))
+        .route("/ceremony/participants", get(participants::list))
+        .route("/ceremony/participants/name", post(participants::set_name))
+        .route(
+            "/ceremony/participants/identity",
+            post(participants::set_public),
+        )
+        .route("/ceremony/contribution/:index", get(participants::get))
+        .route("/ceremony/transcript", get(transcript::full_handler))
+        .route("/ceremony/transcript/diff", get(diff::handler))
+        .route("/ceremony/transcript/:index", get(transcript::handler))
+        .route(
+            "/ceremony/checkpoint",
+            get(checkpoint::latest).layer(middleware::from_fn(signed::middleware)),
+        )
+        .route(
+            "/ceremony/checkpoints",
+            get(checkpoint::list).layer(middleware::from_fn(signed::middleware)),
+        )
+        .route(
+            "/ceremony/checkpoints/:index",
+            get(checkpoint::get).layer(middleware::from_fn(signed::middleware)),
+        )
+        .route(
+            "/ceremony/keys",
+            get(signed::keys).layer(middleware::from_fn(signed::middleware)),
+        )
+        .route("/ceremony/final", get(finalize::download))
+        .route("/stats", get(analytics::handler))
+        .route("/admin/queue", get(admin::queue))
+        .route("/admin/pause", post(admin::pause))
+        .route("/admin/resume", post(admin::resume))
+        .route("/admin/evict", post(admin::evict))
+        .route("/admin/session-key/rotate", post(admin::rotate_session_key))
+        .route("/admin/session-keys/:id", delete(admin::revoke_session_key))
+        .route(
+            "/admin/coordinator-key/rotate",
+            post(admin::rotate_coordinator_key),
+        )
+        .route(
+            "/admin/coordinator-keys/:address",
+            delete(admin::revoke_coordinator_key),
+        )
+        .route("/admin/sessions/revoke", post(revocation::revoke))
+        .route("/admin/finalize", post(finalize::finalize))
+        .route("/admin/bans", get(bans::list))
+        .route("/admin/bans/:identity", delete(bans::clear))
+        .route("/admin/audit", get(audit::export))
+        .route("/admin/pseudonyms/:pseudonym", get(pseudonym::reveal))
+        .layer(middleware::from_fn(limits::body_limit))
+        // Uploads are bounded by `--max-upload-size` instead.
+        .route("/contribution/complete", post(contribute::complete))
+        .route("/contribution/upload/chunk", post(resumable::chunk))
+        .layer(middleware::from_fn(leader::standby))
+        .layer(Extension(leadership))
+        .layer(Extension(events))
+        .layer(Extension(analytics))
+        .layer(Extension(queue))
+        .layer(Extension(policy))
+        .layer(Extension(join_guard))
+        .layer(Extension(bans))
+        .layer(Extension(audit))
+        .layer(Extension(receipts))
+        .layer(Extension(participants))
+        .layer(Extension(pseudonyms))
+        .layer(Extension(revocations))
+        .layer(Extension(finalize::FinalDir(
+            options.transcript_dir.clone(),
+        )))
+        .layer(Extension(options.admin_token.clone().map(Arc::new)))
+        .layer(Extension(observers))
+        .layer(Extension(ceremony))
+        .layer(Extension(Sizes(sizes.into())))
+        .layer(Extension(upload_rng))
+        .layer(Extension(hash_chain))
+        .layer(Extension(ipfs))
+        .layer(Extension(integrity))
+        .layer(Extension(compute))
+        .layer(Extension(schema))
+        .layer(Extension(MaxUploadSize(options.max_upload_size)))
+        .layer(Extension(MaxRequestSize(limits.max_request_size)))
+        .layer(Extension(BodyTimeout(limits.body_read_timeout)))
+        .layer(Extension(Arc::new(UploadSessions::default())))
+        .layer(Extension(transcript_cache))
+        .layer(Extension(Arc::new(RangeCache::new(
+            options.range_cache_size,
+        ))))
+        .layer(Extension(coordinator_key))
+        .layer(Extension(commitment))
+        .layer(Extension(Arc::new(RwLock::new(SessionKeys::new(
+            session_key,
+            options.previous_session_keys.clone(),
+        ))) as SharedSessionKeys))
+        .layer(Extension(Arc::new(auth::AuthProviders::from_options(
+            options,
+        )?)))
+        .layer(middleware::from_fn(compression::middleware))
+        .layer(TraceLayer::new_for_http().make_span_with(request_id::make_span))
+        .layer(middleware::from_fn(request_id::middleware));
+    let app = if options.follow.is_some() {
+        app.layer(middleware::from_fn(follower::read_only))
+    } else {
+        app
+    };
+    let app = match cors {
+        Some(cors) => app.layer(cors),
+        None => app,
+    };
+    Ok((app, drain))
+}
+
 fn parse_url(url: &Url) -> Result<(SocketAddr, &str)> {
     ensure!(
-        url.scheme() == "http",
-        "Only http:// is supported in {}",
+        url.scheme() == "http" || url.scheme() == "https",
+        "Only http:// and https:// are supported in {}",
         url
     );
     let prefix = url.path();
@@ -102,14 +973,53 @@ fn parse_url(url: &Url) -> Result<(SocketAddr, &str)> {
 #[cfg(test)]
 pub mod test {
     use super::*;
-    use ark_bls12_381::{FrParameters, G1Affine, G2Affine};
+    use ark_bls12_381::{Fr, FrParameters, G1Affine, G2Affine};
     use ark_ec::{AffineCurve, ProjectiveCurve};
     use ark_ff::{BigInteger256, FpParameters, PrimeField};
     use proptest::{arbitrary::any, proptest, strategy::Strategy};
     use ruint::aliases::U256;
+    use std::{env::temp_dir, fs, ops::Deref, path::Path};
     use tracing::{error, warn};
     use tracing_test::traced_test;
 
+    /// Scratch directory for a test, removed again when dropped, also when the
+    /// test fails. The directory itself is not created.
+    #[derive(Debug)]
+    pub struct TestDir(PathBuf);
+
+    impl TestDir {
+        /// A fresh `kzg-{name}-{pid}` in the temporary directory. Names must be
+        /// unique among the tests, which run concurrently.
+        #[must_use]
+        pub fn new(name: &str) -> Self {
+            let dir = temp_dir().join(format!("kzg-{name}-{}", std::process::id()));
+            Self(dir)
+        }
+    }
+
+    impl Deref for TestDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl AsRef<Path> for TestDir {
+        fn as_ref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// # Panics
+    ///
+    /// Never, `n` is reduced below the modulus first.
     pub fn arb_fr() -> impl Strategy<Value = Fr> {
         any::<U256>().prop_map(|mut n| {
             n %= U256::from(FrParameters::MODULUS);
@@ -161,14 +1071,34 @@ pub mod test {
         assert!(logs_contain("logged on the warn level"));
         assert!(!logs_contain("logged on the error level"));
     }
+
+    #[test]
+    fn test_parse_url() {
+        let url = Url::parse("https://0.0.0.0:8443/api").unwrap();
+        let (addr, prefix) = parse_url(&url).unwrap();
+        assert_eq!(addr, "0.0.0.0:8443".parse().unwrap());
+        assert_eq!(prefix, "/api");
+        assert!(parse_url(&Url::parse("ftp://127.0.0.1/").unwrap()).is_err());
+
+        let tls = [
+            "coordinator",
+            "--tls-cert",
+            "cert.pem",
+            "--tls-key",
+            "key.pem",
+        ];
+        assert!(Options::try_parse_from(tls).is_ok());
+        assert!(Options::try_parse_from(&tls[..3]).is_err());
+    }
 }
 
 #[cfg(feature = "bench")]
 #[doc(hidden)]
 pub mod bench {
     use super::*;
-    use ark_bls12_381::{G1Affine, G2Affine};
+    use ark_bls12_381::{Fr, G1Affine, G2Affine};
     use ark_ec::{AffineCurve, ProjectiveCurve};
+    use ark_ff::UniformRand;
     use criterion::{black_box, BatchSize, Criterion};
     use proptest::{
         strategy::{Strategy, ValueTree},
@@ -197,6 +1127,7 @@ pub mod bench {
     pub fn group(criterion: &mut Criterion) {
         bench_example_proptest(criterion);
         bench_example_async(criterion);
+        transcript::bench::group(criterion);
     }
 
     /// Constructs an executor for async tests
@@ -0,0 +1,303 @@
+//! Request and connection limits.
+//!
+//! Hardening against trivial resource exhaustion:
+//!
+//! * Bodies of requests other than contribution uploads are capped at
+//!   `--max-request-size` bytes, uploads at `--max-upload-size`, see
+//!   [`crate::upload`]. Larger bodies are answered with `413 Payload Too
+//!   Large`.
+//! * Clients have `--header-read-timeout` seconds for the TLS handshake and
+//!   again for sending the request headers, after which the connection is
+//!   closed. While sending a body they may not pause for more than
+//!   `--body-read-timeout` seconds, answered with `408 Request Timeout`. Slow
+//!   but steady uploads are fine.
+//! * At most `--max-connections` connections are served at a time. Further
+//!   connections are closed right away.
+
+use crate::Options;
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    http::{header::CONTENT_LENGTH, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_server::{accept::Accept, HttpConfig};
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter, IntCounter};
+use serde_json::json;
+use std::{
+    future::{ready, Ready},
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::timeout,
+};
+use tracing::warn;
+
+static REJECTED_CONNECTIONS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "connections_rejected_total",
+        "Number of connections closed because of the connection limit."
+    )
+    .unwrap()
+});
+
+#[derive(Debug, Error)]
+pub enum LimitError {
+    #[error("Request body exceeds the maximum size of {0} bytes")]
+    TooLarge(usize),
+    #[error("Timed out waiting for the request body")]
+    Timeout,
+    #[error("Error receiving request body: {0}")]
+    Body(#[from] hyper::Error),
+}
+
+/// Limits from the command line.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub max_request_size:    usize,
+    pub header_read_timeout: Duration,
+    pub body_read_timeout:   Duration,
+    /// Unlimited if zero.
+    pub max_connections:     usize,
+}
+
+/// Maximum size in bytes of request bodies other than uploads.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MaxRequestSize(pub usize);
+
+/// Maximum time to wait for the next part of a request body.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BodyTimeout(pub Duration);
+
+impl Config {
+    #[must_use]
+    pub const fn from_options(options: &Options) -> Self {
+        Self {
+            max_request_size:    options.max_request_size,
+            header_read_timeout: Duration::from_secs(options.header_read_timeout),
+            body_read_timeout:   Duration::from_secs(options.body_read_timeout),
+            max_connections:     options.max_connections,
+        }
+    }
+
+    #[must_use]
+    pub fn http_config(&self) -> HttpConfig {
+        HttpConfig::new()
+            .http1_header_read_timeout(self.header_read_timeout)
+            .build()
+    }
+
+    #[must_use]
+    pub fn connection_limit(&self) -> ConnectionLimit {
+        ConnectionLimit {
+            permits: (self.max_connections > 0)
+                .then(|| Arc::new(Semaphore::new(self.max_connections))),
+        }
+    }
+}
+
+/// Read the whole body of `request`, rejecting it if it exceeds
+/// [`MaxRequestSize`] or stalls for longer than [`BodyTimeout`].
+pub async fn body_limit(request: Request<Body>, next: Next<Body>) -> Response {
+    let max = request
+        .extensions()
+        .get::<MaxRequestSize>()
+        .copied()
+        .expect("MaxRequestSize extension missing")
+        .0;
+    let stall = request.extensions().get::<BodyTimeout>().map(|t| t.0);
+    let declared = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if declared.map_or(false, |len| len > max) {
+        return LimitError::TooLarge(max).into_response();
+    }
+    let (parts, mut body) = request.into_parts();
+    let mut bytes = Vec::new();
+    loop {
+        let chunk = match read_chunk(&mut body, stall).await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(error) => return error.into_response(),
+        };
+        if bytes.len() + chunk.len() > max {
+            return LimitError::TooLarge(max).into_response();
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    next.run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+}
+
+/// Read the next chunk of `body`, waiting at most `stall`.
+async fn read_chunk(body: &mut Body, stall: Option<Duration>) -> Result<Option<Bytes>, LimitError> {
+    let chunk = match stall {
+        Some(stall) => timeout(stall, body.data())
+            .await
+            .map_err(|_| LimitError::Timeout)?,
+        None => body.data().await,
+    };
+    Ok(chunk.transpose()?)
+}
+
+impl IntoResponse for LimitError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::Timeout => StatusCode::REQUEST_TIMEOUT,
+            Self::Body(_) => StatusCode::BAD_REQUEST,
+        };
+        (status, Json(json!({ "error": self.to_string() }))).into_response()
+    }
+}
+
+/// Acceptor closing connections beyond the limit.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionLimit {
+    permits: Option<Arc<Semaphore>>,
+}
+
+/// A connection holding a place within the limit until dropped.
+#[derive(Debug)]
+pub struct Limited<I> {
+    stream:  I,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl<I, S> Accept<I, S> for ConnectionLimit {
+    type Future = Ready<io::Result<(Self::Stream, Self::Service)>>;
+    type Service = S;
+    type Stream = Limited<I>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let permit = match &self.permits {
+            Some(permits) => {
+                if let Ok(permit) = permits.clone().try_acquire_owned() {
+                    Some(permit)
+                } else {
+                    warn!("Connection limit reached, closing connection");
+                    REJECTED_CONNECTIONS.inc();
+                    return ready(Err(io::Error::new(
+                        io::ErrorKind::Other,
+                        "Connection limit reached",
+                    )));
+                }
+            }
+            None => None,
+        };
+        ready(Ok((
+            Limited {
+                stream,
+                _permit: permit,
+            },
+            service,
+        )))
+    }
+}
+
+impl<I: AsyncRead + Unpin> AsyncRead for Limited<I> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl<I: AsyncWrite + Unpin> AsyncWrite for Limited<I> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use axum::{middleware::from_fn, routing::post, Extension, Router};
+    use futures::stream;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_body_limit() {
+        let app = Router::new()
+            .route("/", post(|body: Bytes| async move { body }))
+            .layer(from_fn(body_limit))
+            .layer(Extension(MaxRequestSize(4)))
+            .layer(Extension(BodyTimeout(Duration::from_millis(50))));
+        let post = |body: Body| Request::post("/").body(body).unwrap();
+
+        let response = app.clone().oneshot(post(Body::from("1234"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app
+            .clone()
+            .oneshot(post(Body::from("12345")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Without a content length the limit applies while reading.
+        let chunks = stream::iter(["12", "345"].map(Ok::<_, io::Error>));
+        let response = app
+            .clone()
+            .oneshot(post(Body::wrap_stream(chunks)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let stalled = stream::pending::<Result<Bytes, io::Error>>();
+        let response = app.oneshot(post(Body::wrap_stream(stalled))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+    }
+
+    #[test]
+    fn test_connection_limit() {
+        let limit = Config {
+            max_request_size:    0,
+            header_read_timeout: Duration::ZERO,
+            body_read_timeout:   Duration::ZERO,
+            max_connections:     1,
+        }
+        .connection_limit();
+        let accept = |limit: &ConnectionLimit| limit.accept((), ()).into_inner();
+        let first = accept(&limit).unwrap();
+        assert!(accept(&limit).is_err());
+        drop(first);
+        assert!(accept(&limit).is_ok());
+    }
+}
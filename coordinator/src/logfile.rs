@@ -0,0 +1,265 @@
+//! Log file output.
+//!
+//! Logs are written to stderr, in the format given by `--log-format` (`json`
+//! for machine-parseable logs) and filtered per module by `--log-filter`, for
+//! example `kzg_ceremony_coordinator::queue=debug,hyper=warn`.
+//!
+//! With `--log-file` everything written to stderr is also appended to a file,
+//! so a collector can ship it. The file is rotated once it exceeds
+//! `--log-file-size` MiB, keeping `--log-file-keep` old files as `{path}.1`,
+//! `{path}.2` and so on, the most recent first. Rotation waits for the end of
+//! a line.
+//!
+//! A thread reads stderr through a pipe and hands the output to a second
+//! thread that writes the file. If the file system stalls and the writer falls
+//! more than [`BACKLOG`] chunks behind, output is dropped from the file, with
+//! a note of how much, rather than blocking every write to stderr.
+
+use crate::Options;
+use eyre::{Result as EyreResult, WrapErr};
+use std::{
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError},
+    thread,
+};
+use tracing::info;
+
+const MIB: u64 = 1024 * 1024;
+
+/// Number of chunks of output, of up to 64 KiB each, the file writer may fall
+/// behind before output is dropped from the file.
+const BACKLOG: usize = 256;
+
+/// Where and how much to log.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub path:     PathBuf,
+    /// Size in bytes to rotate at.
+    pub max_size: u64,
+    /// Number of rotated files to keep.
+    pub keep:     usize,
+}
+
+impl Config {
+    /// Log file settings, if enabled with `--log-file`.
+    #[must_use]
+    pub fn from_options(options: &Options) -> Option<Self> {
+        Some(Self {
+            path:     options.log_file.clone()?,
+            max_size: options.log_file_size.saturating_mul(MIB),
+            keep:     options.log_file_keep,
+        })
+    }
+}
+
+/// Start copying stderr to the log file, if enabled.
+pub fn start(config: Option<Config>) -> EyreResult<()> {
+    let config = match config {
+        Some(config) => config,
+        None => return Ok(()),
+    };
+    let file = RotatingFile::open(config.clone())
+        .wrap_err_with(|| format!("Could not open log file {}", config.path.display()))?;
+    let (pipe, stderr) = redirect_stderr()?;
+    let (sender, receiver) = sync_channel(BACKLOG);
+    let errors = stderr.try_clone()?;
+    thread::Builder::new()
+        .name("log-file".to_string())
+        .spawn(move || write(&receiver, errors, file))?;
+    thread::Builder::new()
+        .name("log-pipe".to_string())
+        .spawn(move || copy(pipe, stderr, &sender))?;
+    info!(path = %config.path.display(), "Writing logs to file");
+    Ok(())
+}
+
+/// Chunk of output for the file writer.
+enum Chunk {
+    Output(Vec<u8>),
+    /// Number of bytes dropped because the writer fell behind.
+    Dropped(usize),
+}
+
+/// Copy everything read from `pipe` to `stderr`, and hand it to the file
+/// writer without waiting for it.
+fn copy(mut pipe: File, mut stderr: File, file: &SyncSender<Chunk>) {
+    let mut buffer = vec![0; 64 * 1024];
+    let mut dropped = 0;
+    loop {
+        let n = match pipe.read(&mut buffer) {
+            Ok(0) => return,
+            Ok(n) => n,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => {
+                let _ = writeln!(stderr, "Could not read log output: {error}");
+                return;
+            }
+        };
+        let _ = stderr.write_all(&buffer[..n]);
+        if dropped > 0 {
+            match file.try_send(Chunk::Dropped(dropped)) {
+                Ok(()) => dropped = 0,
+                Err(TrySendError::Full(_)) => {
+                    dropped += n;
+                    continue;
+                }
+                Err(TrySendError::Disconnected(_)) => return,
+            }
+        }
+        match file.try_send(Chunk::Output(buffer[..n].to_vec())) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => dropped += n,
+            Err(TrySendError::Disconnected(_)) => return,
+        }
+    }
+}
+
+/// Write the chunks handed over by [`copy`] to `file`.
+fn write(chunks: &Receiver<Chunk>, mut stderr: File, mut file: RotatingFile) {
+    for chunk in chunks {
+        let result = match chunk {
+            Chunk::Output(bytes) => file.write(&bytes),
+            Chunk::Dropped(n) => file.write(
+                format!("\n[log file fell behind, dropped {n} bytes of output]\n").as_bytes(),
+            ),
+        };
+        // Errors can not be logged, that would feed back into the pipe.
+        if let Err(error) = result {
+            let _ = writeln!(stderr, "Could not write log file: {error}");
+        }
+    }
+}
+
+/// Point stderr at a new pipe. Returns the reading end of the pipe and the
+/// original stderr.
+#[cfg(unix)]
+fn redirect_stderr() -> EyreResult<(File, File)> {
+    use std::os::unix::io::FromRawFd;
+
+    let mut fds = [0; 2];
+    // SAFETY: The file descriptors are fresh and each is owned by exactly one
+    // `File` afterwards. Stderr is only replaced, never closed.
+    unsafe {
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let stderr = libc::dup(libc::STDERR_FILENO);
+        if stderr < 0 || libc::dup2(fds[1], libc::STDERR_FILENO) < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        libc::close(fds[1]);
+        Ok((File::from_raw_fd(fds[0]), File::from_raw_fd(stderr)))
+    }
+}
+
+#[cfg(not(unix))]
+fn redirect_stderr() -> EyreResult<(File, File)> {
+    eyre::bail!("--log-file is only supported on unix")
+}
+
+/// Append-only file that is rotated once it exceeds a size.
+#[derive(Debug)]
+struct RotatingFile {
+    config: Config,
+    file:   File,
+    size:   u64,
+}
+
+impl RotatingFile {
+    fn open(config: Config) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { config, file, size })
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.file.write_all(bytes)?;
+        self.size += bytes.len() as u64;
+        if self.size >= self.config.max_size && bytes.ends_with(b"\n") {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let path = &self.config.path;
+        for i in (1..self.config.keep).rev() {
+            match fs::rename(rotated(path, i), rotated(path, i + 1)) {
+                Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+                _ => {}
+            }
+        }
+        if self.config.keep > 0 {
+            fs::rename(path, rotated(path, 1))?;
+        } else {
+            fs::remove_file(path)?;
+        }
+        *self = Self::open(self.config.clone())?;
+        Ok(())
+    }
+}
+
+/// Path of the `index`-th most recent rotated file.
+fn rotated(path: &Path, index: usize) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(format!(".{index}"));
+    path.into()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test::TestDir;
+
+    #[test]
+    fn test_rotate() {
+        let dir = TestDir::new("logfile");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("coordinator.log");
+        let mut file = RotatingFile::open(Config {
+            path:     path.clone(),
+            max_size: 10,
+            keep:     2,
+        })
+        .unwrap();
+
+        // Rotation waits for the end of the line.
+        file.write(b"first part ").unwrap();
+        file.write(b"of a line\n").unwrap();
+        file.write(b"second line\n").unwrap();
+        file.write(b"third\n").unwrap();
+        file.write(b"fourth line\n").unwrap();
+        let read = |path| fs::read_to_string(path).unwrap();
+        assert_eq!(read(rotated(&path, 1)), "third\nfourth line\n");
+        assert_eq!(read(rotated(&path, 2)), "second line\n");
+        assert!(!rotated(&path, 3).exists());
+        assert_eq!(read(path), "");
+    }
+
+    #[test]
+    fn test_copy_drops_when_behind() {
+        let dir = TestDir::new("logfile-behind");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("pipe"), vec![b'x'; 3 * 64 * 1024]).unwrap();
+        let pipe = File::open(dir.join("pipe")).unwrap();
+        let stderr = File::create(dir.join("stderr")).unwrap();
+
+        // The writer never catches up, yet the copy runs to completion.
+        let (sender, receiver) = sync_channel(1);
+        copy(pipe, stderr, &sender);
+        assert_eq!(
+            fs::metadata(dir.join("stderr")).unwrap().len(),
+            3 * 64 * 1024
+        );
+        assert!(
+            matches!(receiver.try_recv(), Ok(Chunk::Output(bytes)) if bytes.len() == 64 * 1024)
+        );
+        assert!(receiver.try_recv().is_err());
+    }
+}
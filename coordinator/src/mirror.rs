@@ -0,0 +1,268 @@
+//! Transcript mirroring.
+//!
+//! After every accepted contribution the full transcript is pushed to a list
+//! of mirrors, so the ceremony survives loss of the primary host. A snapshot
+//! is sent as
+//!
+//! ```text
+//! PUT {mirror}/mirror/{index}
+//! X-Transcript-Hash: 0x{transcript hash}
+//! X-Coordinator-Signature: 0x{EIP-191 signature of the mirror message}
+//!
+//! full transcript as served on `/ceremony/transcript`
+//! ```
+//!
+//! where `index` is the number of contributions. Mirrors that fall behind
+//! only receive the latest snapshot, failed pushes are retried with
+//! exponential backoff.
+
+use crate::{
+    ceremony::SharedCeremony,
+    events::EventBus,
+    signing::{CoordinatorKey, CoordinatorKeys},
+    transcript::encode_all,
+};
+use axum::body::Bytes;
+use eyre::{bail, Result as EyreResult};
+use hyper::{client::HttpConnector, Body, Client, Method, Request};
+use kzg_ceremony_crypto::BatchTranscript;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter_vec, register_int_gauge_vec, IntCounterVec, IntGaugeVec};
+use std::{cmp::min, sync::Arc, time::Duration};
+use tokio::{select, sync::watch, task::spawn_blocking, time::sleep};
+use tracing::{error, info, instrument, warn};
+use url::Url;
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+static MIRROR_LAG: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "mirror_lag_contributions",
+        "Number of contributions a mirror is behind.",
+        &["mirror"]
+    )
+    .unwrap()
+});
+
+static MIRROR_FAILURES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "mirror_failures_total",
+        "Number of failed pushes to a mirror.",
+        &["mirror"]
+    )
+    .unwrap()
+});
+
+/// A signed encoding of the full transcript.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    /// Number of contributions in the transcript.
+    pub index:     usize,
+    pub hash:      [u8; 32],
+    pub signature: String,
+    pub body:      Bytes,
+}
+
+impl Snapshot {
+    #[must_use]
+    pub fn new(
+        key: &CoordinatorKey,
+        index: usize,
+        hash: [u8; 32],
+        transcript: &BatchTranscript,
+    ) -> Self {
+        Self {
+            index,
+            hash,
+            signature: key.sign(&message(index, &hash)),
+            body: encode_all(transcript),
+        }
+    }
+}
+
+/// Message signed by the coordinator for a snapshot.
+#[must_use]
+pub fn message(index: usize, hash: &[u8; 32]) -> Vec<u8> {
+    format!("KZG ceremony transcript {} 0x{}", index, hex::encode(hash)).into_bytes()
+}
+
+/// Start pushing snapshots to `mirrors` in the background.
+pub fn spawn(
+    mirrors: Vec<Url>,
+    ceremony: SharedCeremony,
+    events: EventBus,
+    key: Arc<CoordinatorKeys>,
+) {
+    if mirrors.is_empty() {
+        return;
+    }
+    let (sender, receiver) = watch::channel(None);
+    let client = Client::new();
+    for mirror in mirrors {
+        tokio::spawn(push(client.clone(), mirror, receiver.clone()));
+    }
+    tokio::spawn(snapshots(ceremony, events, key, sender));
+}
+
+/// Produce a new snapshot after every contribution.
+async fn snapshots(
+    ceremony: SharedCeremony,
+    events: EventBus,
+    key: Arc<CoordinatorKeys>,
+    sender: watch::Sender<Option<Arc<Snapshot>>>,
+) {
+    let mut receiver = events.subscribe();
+    loop {
+        let (index, hash, transcript) = {
+            let ceremony = ceremony.lock().await;
+            (
+                ceremony.num_contributions(),
+                ceremony.hash(),
+                ceremony.transcript().clone(),
+            )
+        };
+        let key = key.active();
+        let snapshot = spawn_blocking(move || Snapshot::new(&key, index, hash, &transcript))
+            .await
+            .expect("Snapshot task panicked");
+        if sender.send(Some(Arc::new(snapshot))).is_err() {
+            return;
+        }
+        if !EventBus::next_contribution(&mut receiver).await {
+            return;
+        }
+    }
+}
+
+/// Push the latest snapshot to `mirror`, retrying until it succeeds.
+#[allow(clippy::incompatible_msrv)] // False positive from macro
+#[instrument(level = "info", skip(client, receiver), fields(mirror = %mirror))]
+async fn push(
+    client: Client<HttpConnector>,
+    mirror: Url,
+    mut receiver: watch::Receiver<Option<Arc<Snapshot>>>,
+) {
+    let label = mirror.to_string();
+    let mut mirrored = None;
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        let snapshot = receiver.borrow_and_update().clone();
+        if let Some(snapshot) = snapshot {
+            if mirrored.map_or(true, |index| index < snapshot.index) {
+                MIRROR_LAG
+                    .with_label_values(&[&label])
+                    .set(lag(mirrored, snapshot.index));
+                match put(&client, &mirror, &snapshot).await {
+                    Ok(()) => {
+                        info!(index = snapshot.index, "Mirrored transcript");
+                        mirrored = Some(snapshot.index);
+                        backoff = INITIAL_BACKOFF;
+                        MIRROR_LAG.with_label_values(&[&label]).set(0);
+                    }
+                    Err(error) => {
+                        warn!(
+                            index = snapshot.index,
+                            ?backoff,
+                            "Mirroring failed: {}",
+                            error
+                        );
+                        MIRROR_FAILURES.with_label_values(&[&label]).inc();
+                        // Retry after the backoff, or earlier with a newer snapshot.
+                        select! {
+                            () = sleep(backoff) => {},
+                            changed = receiver.changed() => if changed.is_err() { return },
+                        }
+                        backoff = min(backoff * 2, MAX_BACKOFF);
+                        continue;
+                    }
+                }
+            }
+        }
+        if receiver.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+#[allow(clippy::cast_possible_wrap)] // Contribution counts fit i64
+fn lag(mirrored: Option<usize>, latest: usize) -> i64 {
+    (latest + 1 - mirrored.map_or(0, |index| index + 1)) as i64
+}
+
+async fn put(client: &Client<HttpConnector>, mirror: &Url, snapshot: &Snapshot) -> EyreResult<()> {
+    let url = mirror.join(&format!("mirror/{}", snapshot.index))?;
+    let request = Request::builder()
+        .method(Method::PUT)
+        .uri(url.as_str())
+        .header("content-type", "application/octet-stream")
+        .header(
+            "x-transcript-hash",
+            format!("0x{}", hex::encode(snapshot.hash)),
+        )
+        .header("x-coordinator-signature", &snapshot.signature)
+        .body(Body::from(snapshot.body.clone()))?;
+    let response = client.request(request).await?;
+    if !response.status().is_success() {
+        error!(status = %response.status(), "Mirror rejected snapshot");
+        bail!("Mirror responded with {}", response.status());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::signing;
+    use axum::{
+        extract::Path,
+        http::{HeaderMap, StatusCode},
+        routing::put as put_route,
+        Router, Server,
+    };
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        sync::Mutex,
+    };
+
+    #[tokio::test]
+    async fn test_push() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let app = Router::new().route(
+            "/mirror/:index",
+            put_route({
+                let received = received.clone();
+                move |Path(index): Path<usize>, headers: HeaderMap, body: Bytes| async move {
+                    // Fail the first attempt to exercise the retry.
+                    let mut received = received.lock().unwrap();
+                    received.push((index, headers, body));
+                    if received.len() == 1 {
+                        StatusCode::SERVICE_UNAVAILABLE
+                    } else {
+                        StatusCode::OK
+                    }
+                }
+            }),
+        );
+        let server = Server::bind(&SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0))
+            .serve(app.into_make_service());
+        let mirror: Url = format!("http://{}/", server.local_addr()).parse().unwrap();
+        tokio::spawn(server);
+
+        let key = CoordinatorKey::random();
+        let snapshot = Snapshot::new(&key, 3, [7; 32], &BatchTranscript::new(&[(4, 2)]));
+        let (sender, snapshots) = watch::channel(Some(Arc::new(snapshot.clone())));
+        let task = tokio::spawn(push(Client::new(), mirror, snapshots));
+        while received.lock().unwrap().len() < 2 {
+            sleep(Duration::from_millis(50)).await;
+        }
+        drop(sender);
+        task.await.unwrap();
+
+        let (index, headers, body) = received.lock().unwrap()[1].clone();
+        assert_eq!(index, 3);
+        assert_eq!(body, snapshot.body);
+        let signature = headers["x-coordinator-signature"].to_str().unwrap();
+        signing::verify(&message(3, &[7; 32]), signature, key.address()).unwrap();
+    }
+}
@@ -0,0 +1,193 @@
+//! Notifications to participants who stepped away.
+//!
+//! The wait in the queue can be long, so the coordinator tells frontends and
+//! bots when a participant reaches the front of the queue and when the holder
+//! of the slot is about to miss their deadline. Notifications go to a
+//! [`Notifier`], by default to the webhooks of `--webhooks` as
+//!
+//! ```text
+//! POST {webhook}
+//! X-Coordinator-Signature: 0x{EIP-191 signature of the body}
+//!
+//! {"type":"turn","identity":"git|1|alice"}
+//! ```
+//!
+//! Delivery is best effort: failed webhooks are logged and not retried, a
+//! late notification is of no use.
+
+use crate::{queue::Queue, signing::CoordinatorKeys};
+use eyre::{bail, Result as EyreResult};
+use hyper::{client::HttpConnector, Body, Client, Method, Request};
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use serde::Serialize;
+use std::{fmt, sync::Arc, time::Duration};
+use tokio::{runtime::Handle, time::interval};
+use tracing::{info, warn};
+use url::Url;
+
+/// How often the deadline of the active participant is checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+static NOTIFICATIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "notifications_total",
+        "Number of participant notifications sent.",
+        &["type"]
+    )
+    .unwrap()
+});
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Notification {
+    /// The participant is next and may start their contribution.
+    Turn { identity: String },
+    /// The participant holds the slot and will be evicted in
+    /// `seconds_left` unless they upload.
+    #[serde(rename_all = "camelCase")]
+    DeadlineApproaching {
+        identity:     String,
+        seconds_left: u64,
+    },
+}
+
+/// Delivers notifications, e.g. by webhook or email.
+pub trait Notifier: fmt::Debug + Send + Sync {
+    /// Deliver `notification`. Must not block, slow deliveries should be
+    /// spawned.
+    fn notify(&self, notification: &Notification);
+}
+
+/// Posts notifications to webhooks.
+#[derive(Clone, Debug)]
+pub struct Webhooks {
+    urls:   Vec<Url>,
+    key:    Arc<CoordinatorKeys>,
+    client: Client<HttpConnector>,
+}
+
+impl Notification {
+    #[must_use]
+    pub const fn kind(&self) -> &'static str {
+        match self {
+            Self::Turn { .. } => "turn",
+            Self::DeadlineApproaching { .. } => "deadlineApproaching",
+        }
+    }
+}
+
+impl Webhooks {
+    #[must_use]
+    pub fn new(urls: Vec<Url>, key: Arc<CoordinatorKeys>) -> Self {
+        Self {
+            urls,
+            key,
+            client: Client::new(),
+        }
+    }
+}
+
+impl Notifier for Webhooks {
+    fn notify(&self, notification: &Notification) {
+        // Queue tests run without a runtime, and there is nothing to post to.
+        let runtime = match Handle::try_current() {
+            Ok(runtime) => runtime,
+            Err(_) => return,
+        };
+        NOTIFICATIONS
+            .with_label_values(&[notification.kind()])
+            .inc();
+        let body = serde_json::to_vec(notification).expect("Notifications serialize");
+        let signature = self.key.sign(&body);
+        for url in &self.urls {
+            let client = self.client.clone();
+            let url = url.clone();
+            let body = body.clone();
+            let signature = signature.clone();
+            runtime.spawn(async move {
+                if let Err(error) = post(&client, &url, body, &signature).await {
+                    warn!(%url, "Webhook failed: {}", error);
+                }
+            });
+        }
+    }
+}
+
+async fn post(
+    client: &Client<HttpConnector>,
+    url: &Url,
+    body: Vec<u8>,
+    signature: &str,
+) -> EyreResult<()> {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(url.as_str())
+        .header("content-type", "application/json")
+        .header("x-coordinator-signature", signature)
+        .body(Body::from(body))?;
+    let response = client.request(request).await?;
+    if !response.status().is_success() {
+        bail!("Webhook responded with {}", response.status());
+    }
+    Ok(())
+}
+
+/// Warn the active participant `warning` before their deadline, and evict
+/// them once it passes without waiting for the next request.
+pub fn spawn(queue: Arc<Queue>, warning: Duration) {
+    info!(?warning, "Watching contribution deadlines");
+    tokio::spawn(async move {
+        let mut ticks = interval(CHECK_INTERVAL);
+        loop {
+            ticks.tick().await;
+            queue.check_deadline(warning);
+        }
+    });
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::signing::{self, CoordinatorKey};
+    use axum::{body::Bytes, http::HeaderMap, routing::post as post_route, Router, Server};
+    use std::net::{Ipv4Addr, SocketAddr};
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_webhooks() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/hook",
+            post_route(move |headers: HeaderMap, body: Bytes| {
+                let sender = sender.clone();
+                async move {
+                    let signature = headers["x-coordinator-signature"]
+                        .to_str()
+                        .unwrap()
+                        .to_string();
+                    sender.send((body, signature)).unwrap();
+                }
+            }),
+        );
+        let server = Server::bind(&SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0))
+            .serve(app.into_make_service());
+        let url: Url = format!("http://{}/hook", server.local_addr())
+            .parse()
+            .unwrap();
+        tokio::spawn(server);
+
+        let key = Arc::new(CoordinatorKeys::new(CoordinatorKey::random(), &[]));
+        let webhooks = Webhooks::new(vec![url], key.clone());
+        webhooks.notify(&Notification::DeadlineApproaching {
+            identity:     "git|1|alice".to_string(),
+            seconds_left: 30,
+        });
+        let (body, signature) = receiver.recv().await.unwrap();
+        assert_eq!(
+            body.as_ref(),
+            br#"{"type":"deadlineApproaching","identity":"git|1|alice","secondsLeft":30}"#
+        );
+        signing::verify(&body, &signature, key.address()).unwrap();
+    }
+}
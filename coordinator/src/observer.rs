@@ -0,0 +1,214 @@
+//! Observer feed.
+//!
+//! Independent verifiers keep a local copy of the transcript and follow the
+//! ceremony over a WebSocket on `/ceremony/observe`. Instead of re-downloading
+//! the transcript after every contribution, they receive compact deltas: the
+//! new running product and pubkey for each sub-transcript. The powers
+//! themselves are not streamed.
+//!
+//! On connect the observer first receives a `sync` message with the current
+//! contribution index and transcript hash, followed by a `delta` message for
+//! every accepted contribution. If the observer falls behind it receives a
+//! `lagged` message and the connection is closed; it should resynchronize
+//! from a snapshot and reconnect.
+
+use crate::ceremony::SharedCeremony;
+use axum::{
+    extract::ws::{Message, WebSocket, WebSocketUpgrade},
+    response::Response,
+    Extension,
+};
+use futures::{SinkExt, StreamExt};
+use kzg_ceremony_crypto::{format_g, BatchTranscript};
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, trace, warn};
+
+/// Number of deltas buffered for slow observers.
+const CAPACITY: usize = 64;
+
+/// Change to the transcript caused by a single contribution.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscriptDelta {
+    /// Number of contributions in the transcript after applying the delta.
+    pub index:      usize,
+    /// Transcript hash after applying the delta.
+    pub transcript: String,
+    /// One entry per sub-transcript.
+    pub entries:    Vec<DeltaEntry>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeltaEntry {
+    /// New running product of all contributors' tau in G1.
+    pub running_product: String,
+    /// Public key of the new contribution.
+    pub pot_pubkey:      String,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ObserverMessage {
+    /// Current position of the transcript when the observer connected.
+    Sync {
+        index:      usize,
+        transcript: String,
+    },
+    Delta(Arc<TranscriptDelta>),
+    /// The observer missed deltas and must resynchronize.
+    Lagged {
+        skipped: u64,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct ObserverFeed {
+    sender: broadcast::Sender<Arc<TranscriptDelta>>,
+}
+
+impl Default for ObserverFeed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TranscriptDelta {
+    /// Delta corresponding to contribution `index`, the latest in
+    /// `transcript`.
+    #[must_use]
+    pub fn latest(index: usize, transcript: &BatchTranscript, hash: [u8; 32]) -> Self {
+        Self {
+            index,
+            transcript: format!("0x{}", hex::encode(hash)),
+            entries: transcript
+                .transcripts
+                .iter()
+                .map(|transcript| DeltaEntry {
+                    running_product: format_g(transcript.products.last().unwrap()),
+                    pot_pubkey:      format_g(transcript.pubkeys.last().unwrap()),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl ObserverFeed {
+    #[must_use]
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        Self { sender }
+    }
+
+    pub fn publish(&self, delta: TranscriptDelta) {
+        trace!(index = delta.index, "Publishing transcript delta");
+        // Sending only fails if there are no observers, which is fine.
+        let _ = self.sender.send(Arc::new(delta));
+    }
+
+    #[must_use]
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<TranscriptDelta>> {
+        self.sender.subscribe()
+    }
+}
+
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn handler(
+    upgrade: WebSocketUpgrade,
+    Extension(feed): Extension<ObserverFeed>,
+    Extension(ceremony): Extension<SharedCeremony>,
+) -> Response {
+    upgrade.on_upgrade(move |socket| observe(socket, feed, ceremony))
+}
+
+#[allow(clippy::incompatible_msrv)] // False positive from macro
+async fn observe(socket: WebSocket, feed: ObserverFeed, ceremony: SharedCeremony) {
+    // Subscribe before reading the state so no delta falls in between.
+    let mut receiver = feed.subscribe();
+    let (index, hash) = {
+        let ceremony = ceremony.lock().await;
+        (ceremony.num_contributions(), ceremony.hash())
+    };
+    let sync = ObserverMessage::Sync {
+        index,
+        transcript: format!("0x{}", hex::encode(hash)),
+    };
+    let (mut sink, mut stream) = socket.split();
+    if send(&mut sink, &sync).await.is_err() {
+        return;
+    }
+    loop {
+        tokio::select! {
+            delta = receiver.recv() => {
+                let message = match delta {
+                    // Deltas from before the sync point are already included.
+                    Ok(delta) if delta.index <= index => continue,
+                    Ok(delta) => ObserverMessage::Delta(delta),
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!(skipped, "Observer lagged behind");
+                        let _ = send(&mut sink, &ObserverMessage::Lagged { skipped }).await;
+                        break;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if send(&mut sink, &message).await.is_err() {
+                    break;
+                }
+            }
+            incoming = stream.next() => {
+                // Observers have nothing to say, only watch for disconnects.
+                match incoming {
+                    Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                }
+            }
+        }
+    }
+    let _ = sink.close().await;
+    debug!("Observer disconnected");
+}
+
+async fn send<S>(sink: &mut S, message: &ObserverMessage) -> Result<(), axum::Error>
+where
+    S: SinkExt<Message, Error = axum::Error> + Unpin,
+{
+    let text = serde_json::to_string(message).expect("Observer messages serialize");
+    sink.send(Message::Text(text)).await
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use ark_bls12_381::{G1Affine, G2Affine};
+    use ark_ec::AffineCurve;
+    use kzg_ceremony_crypto::Transcript;
+
+    #[tokio::test]
+    async fn test_delta() {
+        let mut transcript = Transcript::new(2, 2);
+        transcript
+            .products
+            .push(G1Affine::prime_subgroup_generator());
+        transcript
+            .pubkeys
+            .push(G2Affine::prime_subgroup_generator());
+        let delta = TranscriptDelta::latest(1, &vec![transcript].into(), [0; 32]);
+        assert_eq!(delta.index, 1);
+        assert_eq!(
+            delta.entries[0].running_product,
+            "0x97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb"
+        );
+
+        let feed = ObserverFeed::new();
+        let mut receiver = feed.subscribe();
+        feed.publish(delta.clone());
+        let message = receiver.recv().await.unwrap();
+        assert_eq!(*message, delta);
+        let json = serde_json::to_value(ObserverMessage::Delta(message)).unwrap();
+        assert_eq!(json["type"], "delta");
+        assert_eq!(json["index"], 1);
+        assert_eq!(json["entries"][0]["potPubkey"], delta.entries[0].pot_pubkey);
+    }
+}
@@ -0,0 +1,740 @@
+//! Public list of contributions.
+//!
+//! Previous ceremonies published who contributed, so contributions can be
+//! attributed and participants can point to their `potPubkey`. Every accepted
+//! contribution is listed on `GET /ceremony/participants` with its index,
+//! `potPubkeys` and the transcript hash after it. Identities are never listed.
+//! Participants can opt in to a display name with
+//! `POST /ceremony/participants/name`, before or after contributing, and
+//! withdraw it by posting `null`.
+//!
+//! `GET /ceremony/contribution/{index}` shows a single contribution with its
+//! `potPubkeys`, BLS and ECDSA signatures and the transcript hashes before and
+//! after it, so auditors can inspect any point of the chain without parsing
+//! the transcript. It includes the identity, or pseudonym, only if the
+//! participant published it with `POST /ceremony/participants/identity` and
+//! `{"public": true}`.
+//!
+//! The `potPubkeys`, BLS signatures and transcript hashes of a contribution
+//! are taken from the transcript witness and the hash chain, see
+//! [`crate::checkpoint`], so every contribution in the transcript can be
+//! looked up, including imported ones. Display names, published identities
+//! and ECDSA signatures are only known for contributions accepted by this
+//! coordinator.
+//!
+//! Changes to the list are appended to `participants.jsonl` next to the
+//! transcript. Lists persisted by earlier versions as `participants.json` are
+//! loaded before it.
+
+use crate::{ceremony::SharedCeremony, session::Session};
+use ark_bls12_381::{G1Affine, G2Affine};
+use axum::{
+    extract::Path as UrlPath,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use eyre::{Result as EyreResult, WrapErr};
+use kzg_ceremony_crypto::{format_g, BatchTranscript, ContributionsJson};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    mem::take,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use thiserror::Error;
+use tokio::task::spawn_blocking;
+use tracing::{error, info, warn};
+
+const FILE: &str = "participants.jsonl";
+
+/// Whole list as persisted by earlier versions.
+const LEGACY_FILE: &str = "participants.json";
+
+/// Maximum length of a display name in characters.
+const MAX_NAME_LENGTH: usize = 64;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum ParticipantsError {
+    #[error("Display name must be 1 to {} printable characters", MAX_NAME_LENGTH)]
+    InvalidName,
+    #[error("No contribution with index {0}")]
+    NotFound(usize),
+    #[error("Could not read the transcript rounds")]
+    Storage,
+}
+
+/// A contribution as listed publicly.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Participant {
+    /// Number of contributions in the transcript, including this one.
+    pub index:           usize,
+    /// Opt-in name of the contributor.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name:    Option<String>,
+    /// `potPubkey` of every sub-contribution, `None` for skipped
+    /// sub-ceremonies.
+    pub pot_pubkeys:     Vec<Option<String>>,
+    /// Transcript hash after the contribution, `0x` prefixed hex.
+    pub transcript_hash: String,
+}
+
+/// A contribution with the data needed to audit it.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContributionDetails {
+    /// Number of contributions in the transcript, including this one.
+    pub index:             usize,
+    /// Identity or pseudonym of the contributor, if they published it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity:          Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name:      Option<String>,
+    /// `potPubkey` of every sub-contribution, `None` for skipped
+    /// sub-ceremonies.
+    pub pot_pubkeys:       Vec<Option<String>>,
+    /// `blsSignature` of every sub-contribution, if any.
+    pub bls_signatures:    Vec<Option<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ecdsa_signature:   Option<String>,
+    /// Transcript hash before the contribution, if known.
+    pub transcript_before: Option<String>,
+    /// Transcript hash after the contribution, if known.
+    pub transcript_after:  Option<String>,
+}
+
+/// `potPubkey` and identity signature of a sub-contribution.
+pub type WitnessEntry = (G2Affine, Option<G1Affine>);
+
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Records {
+    contributions: Vec<Contribution>,
+    /// Display names by identity.
+    names:         HashMap<String, String>,
+    /// Identities that are shown with their contributions.
+    #[serde(default)]
+    public:        HashSet<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Contribution {
+    identity:          String,
+    index:             usize,
+    pot_pubkeys:       Vec<Option<String>>,
+    transcript_hash:   String,
+    // Not recorded by earlier versions.
+    #[serde(default)]
+    transcript_before: Option<String>,
+    #[serde(default)]
+    bls_signatures:    Vec<Option<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ecdsa_signature:   Option<String>,
+}
+
+/// A change to the list, one per line of the file.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Change {
+    Contribution(Contribution),
+    Name {
+        identity: String,
+        name:     Option<String>,
+    },
+    Public {
+        identity: String,
+        public:   bool,
+    },
+}
+
+#[derive(Debug)]
+pub struct ParticipantList {
+    records: Mutex<Records>,
+    log:     Arc<ChangeLog>,
+}
+
+/// Appends changes to the file in the order they were made.
+#[derive(Debug)]
+struct ChangeLog {
+    file:    Mutex<File>,
+    /// Serialized changes not written yet.
+    pending: Mutex<Vec<String>>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NameRequest {
+    pub display_name: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct IdentityRequest {
+    pub public: bool,
+}
+
+impl Records {
+    fn apply(&mut self, change: Change) {
+        match change {
+            Change::Contribution(contribution) => self.contributions.push(contribution),
+            Change::Name {
+                identity,
+                name: Some(name),
+            } => {
+                self.names.insert(identity, name);
+            }
+            Change::Name {
+                identity,
+                name: None,
+            } => {
+                self.names.remove(&identity);
+            }
+            Change::Public {
+                identity,
+                public: true,
+            } => {
+                self.public.insert(identity);
+            }
+            Change::Public {
+                identity,
+                public: false,
+            } => {
+                self.public.remove(&identity);
+            }
+        }
+    }
+}
+
+impl ParticipantList {
+    /// Load the list persisted in `dir`, or start empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the list can not be opened or an earlier version of
+    /// it is malformed.
+    pub fn open(dir: &Path) -> EyreResult<Self> {
+        let legacy = dir.join(LEGACY_FILE);
+        let mut records: Records = if legacy.exists() {
+            serde_json::from_slice(&fs::read(&legacy)?)?
+        } else {
+            Records::default()
+        };
+        let path = dir.join(FILE);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)
+            .wrap_err_with(|| format!("Could not open participant list {}", path.display()))?;
+        for line in BufReader::new(&mut file).lines() {
+            match serde_json::from_str(&line?) {
+                Ok(change) => records.apply(change),
+                // A change interrupted by a crash, the list is best-effort.
+                Err(error) => warn!(?error, "Skipping malformed participant list entry"),
+            }
+        }
+        info!(
+            contributions = records.contributions.len(),
+            names = records.names.len(),
+            "Loaded participant list"
+        );
+        Ok(Self {
+            records: Mutex::new(records),
+            log:     Arc::new(ChangeLog {
+                file:    Mutex::new(file),
+                pending: Mutex::new(Vec::new()),
+            }),
+        })
+    }
+
+    /// Add the accepted `contribution` of `identity`, which moved the
+    /// transcript hash from `transcript_before` to `transcript_after`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the list lock is poisoned.
+    pub async fn record(
+        &self,
+        identity: &str,
+        index: usize,
+        contribution: &ContributionsJson,
+        transcript_before: &[u8; 32],
+        transcript_after: &[u8; 32],
+    ) {
+        let sub_contributions = &contribution.sub_contributions;
+        self.change(Change::Contribution(Contribution {
+            identity: identity.to_string(),
+            index,
+            pot_pubkeys: sub_contributions
+                .iter()
+                .map(|sub_contribution| sub_contribution.pot_pubkey.clone())
+                .collect(),
+            transcript_hash: format!("0x{}", hex::encode(transcript_after)),
+            transcript_before: Some(format!("0x{}", hex::encode(transcript_before))),
+            bls_signatures: sub_contributions
+                .iter()
+                .map(|sub_contribution| sub_contribution.bls_signature.clone())
+                .collect(),
+            ecdsa_signature: contribution.ecdsa_signature.clone(),
+        }))
+        .await;
+    }
+
+    /// Set or, with `None`, withdraw the display name of `identity`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParticipantsError::InvalidName`] if the name is empty, too
+    /// long or not printable.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the list lock is poisoned.
+    pub async fn set_name(
+        &self,
+        identity: &str,
+        name: Option<String>,
+    ) -> Result<(), ParticipantsError> {
+        let name = match name {
+            Some(name) => {
+                let name = name.trim();
+                if name.is_empty()
+                    || name.chars().count() > MAX_NAME_LENGTH
+                    || name.chars().any(char::is_control)
+                {
+                    return Err(ParticipantsError::InvalidName);
+                }
+                Some(name.to_string())
+            }
+            None => None,
+        };
+        self.change(Change::Name {
+            identity: identity.to_string(),
+            name,
+        })
+        .await;
+        Ok(())
+    }
+
+    /// Publish or hide the identity of `identity` with its contributions.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the list lock is poisoned.
+    pub async fn set_public(&self, identity: &str, public: bool) {
+        self.change(Change::Public {
+            identity: identity.to_string(),
+            public,
+        })
+        .await;
+    }
+
+    /// Contribution `index` with the witness `entries` of its
+    /// sub-contributions, `None` for skipped sub-ceremonies, and the
+    /// checkpointed transcript hashes before and after it, if any. The
+    /// identity if it is public, display name and ECDSA signature are added
+    /// if this coordinator accepted the contribution.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the list lock is poisoned.
+    #[must_use]
+    pub fn details(
+        &self,
+        index: usize,
+        entries: &[Option<WitnessEntry>],
+        before: Option<[u8; 32]>,
+        after: Option<[u8; 32]>,
+    ) -> ContributionDetails {
+        let hex = |hash: [u8; 32]| format!("0x{}", hex::encode(hash));
+        let mut details = ContributionDetails {
+            index,
+            identity: None,
+            display_name: None,
+            pot_pubkeys: entries
+                .iter()
+                .map(|entry| entry.map(|(pubkey, _)| format_g(&pubkey)))
+                .collect(),
+            bls_signatures: entries
+                .iter()
+                .map(|entry| entry.and_then(|(_, signature)| signature.as_ref().map(format_g)))
+                .collect(),
+            ecdsa_signature: None,
+            transcript_before: before.map(hex),
+            transcript_after: after.map(hex),
+        };
+        let records = self.records.lock().unwrap();
+        let contribution = match records
+            .contributions
+            .iter()
+            .find(|contribution| contribution.index == index)
+        {
+            Some(contribution) => contribution,
+            None => return details,
+        };
+        details.identity = records
+            .public
+            .contains(&contribution.identity)
+            .then(|| contribution.identity.clone());
+        details.display_name = records.names.get(&contribution.identity).cloned();
+        details
+            .ecdsa_signature
+            .clone_from(&contribution.ecdsa_signature);
+        // Hashes not checkpointed, e.g. of a store from before the hash chain.
+        if details.transcript_before.is_none() {
+            // Earlier versions did not record the hash before, it is the hash
+            // after the preceding contribution.
+            details.transcript_before = contribution.transcript_before.clone().or_else(|| {
+                records
+                    .contributions
+                    .iter()
+                    .filter(|previous| previous.index < index)
+                    .max_by_key(|previous| previous.index)
+                    .map(|previous| previous.transcript_hash.clone())
+            });
+        }
+        if details.transcript_after.is_none() {
+            details.transcript_after = Some(contribution.transcript_hash.clone());
+        }
+        drop(records);
+        details
+    }
+
+    /// All contributions in order, without identities.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the list lock is poisoned.
+    #[must_use]
+    pub fn list(&self) -> Vec<Participant> {
+        let records = self.records.lock().unwrap();
+        let mut list = records
+            .contributions
+            .iter()
+            .map(|contribution| Participant {
+                index:           contribution.index,
+                display_name:    records.names.get(&contribution.identity).cloned(),
+                pot_pubkeys:     contribution.pot_pubkeys.clone(),
+                transcript_hash: contribution.transcript_hash.clone(),
+            })
+            .collect::<Vec<_>>();
+        drop(records);
+        list.sort_by_key(|participant| participant.index);
+        list
+    }
+
+    /// Apply `change` and append it to the file.
+    async fn change(&self, change: Change) {
+        let line = serde_json::to_string(&change).expect("Change serializes");
+        {
+            // Applied while queued, so changes are written in order.
+            let mut pending = self.log.pending.lock().unwrap();
+            self.records.lock().unwrap().apply(change);
+            pending.push(line);
+        }
+        let log = self.log.clone();
+        spawn_blocking(move || log.write())
+            .await
+            .expect("Participant list task panicked");
+    }
+}
+
+impl ChangeLog {
+    /// Write the pending changes.
+    fn write(&self) {
+        let mut file = self.file.lock().unwrap();
+        let lines = take(&mut *self.pending.lock().unwrap());
+        // Failing to persist only loses the public list, it must not fail the
+        // request.
+        let result = lines
+            .iter()
+            .try_for_each(|line| writeln!(file, "{line}"))
+            .and_then(|()| file.sync_data());
+        drop(file);
+        if let Err(error) = result {
+            error!(?error, "Could not persist participant list");
+        }
+    }
+}
+
+/// `potPubkey` and identity signature of every sub-contribution of
+/// contribution `index` in `batch`, given the sub-ceremonies covered by
+/// every contribution. `None` for sub-ceremonies it did not cover.
+fn witness_entries(
+    batch: &BatchTranscript,
+    rounds: &[Vec<usize>],
+    index: usize,
+) -> Vec<Option<WitnessEntry>> {
+    let round = &rounds[index - 1];
+    batch
+        .transcripts
+        .iter()
+        .enumerate()
+        .map(|(i, transcript)| {
+            if !round.contains(&i) {
+                return None;
+            }
+            // The witness starts with the generators.
+            let position = 1 + rounds[..index - 1]
+                .iter()
+                .filter(|round| round.contains(&i))
+                .count();
+            Some((
+                *transcript.pubkeys.get(position)?,
+                transcript.signatures.get(position).copied().flatten(),
+            ))
+        })
+        .collect()
+}
+
+impl IntoResponse for ParticipantsError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::InvalidName => StatusCode::BAD_REQUEST,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Storage => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let body = Json(json!({ "error": self.to_string() }));
+        (status, body).into_response()
+    }
+}
+
+/// List the accepted contributions.
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn list(
+    Extension(participants): Extension<Arc<ParticipantList>>,
+) -> Json<Vec<Participant>> {
+    Json(participants.list())
+}
+
+/// Look up a single contribution.
+pub async fn get(
+    UrlPath(index): UrlPath<usize>,
+    Extension(participants): Extension<Arc<ParticipantList>>,
+    Extension(ceremony): Extension<SharedCeremony>,
+) -> Result<Json<ContributionDetails>, ParticipantsError> {
+    let ceremony = ceremony.lock().await;
+    if index == 0 || index > ceremony.num_contributions() {
+        return Err(ParticipantsError::NotFound(index));
+    }
+    let rounds = ceremony.rounds().map_err(|error| {
+        error!(?error, "Could not read the transcript rounds");
+        ParticipantsError::Storage
+    })?;
+    let entries = witness_entries(ceremony.transcript(), &rounds, index);
+    let chain = ceremony.hash_chain();
+    let hash = |index| {
+        chain
+            .get(index)
+            .map(|checkpoint| checkpoint.transcript_hash)
+    };
+    let (before, after) = (hash(index - 1), hash(index));
+    drop(ceremony);
+    Ok(Json(participants.details(index, &entries, before, after)))
+}
+
+/// Publish or hide the identity of the session with its contributions.
+pub async fn set_public(
+    session: Session,
+    Extension(participants): Extension<Arc<ParticipantList>>,
+    Json(request): Json<IdentityRequest>,
+) -> Json<serde_json::Value> {
+    participants
+        .set_public(&session.pseudonym, request.public)
+        .await;
+    Json(json!({}))
+}
+
+/// Opt in to or out of a public display name.
+pub async fn set_name(
+    session: Session,
+    Extension(participants): Extension<Arc<ParticipantList>>,
+    Json(request): Json<NameRequest>,
+) -> Result<Json<serde_json::Value>, ParticipantsError> {
+    participants
+        .set_name(&session.pseudonym, request.display_name)
+        .await?;
+    Ok(Json(json!({})))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test::TestDir;
+    use kzg_ceremony_crypto::{ContributionJson, PowersOfTau, SecretRng, SecretScalar};
+
+    fn contribution(
+        pot_pubkeys: &[Option<&str>],
+        ecdsa_signature: Option<&str>,
+    ) -> ContributionsJson {
+        ContributionsJson {
+            sub_contributions: pot_pubkeys
+                .iter()
+                .map(|pot_pubkey| ContributionJson {
+                    num_g1_powers:    0,
+                    num_g2_powers:    0,
+                    powers_of_tau:    PowersOfTau {
+                        g1_powers: vec![],
+                        g2_powers: vec![],
+                    },
+                    pot_pubkey:       pot_pubkey.map(str::to_string),
+                    bls_signature:    pot_pubkey.map(|_| "0xb15".to_string()),
+                    pot_pubkey_proof: None,
+                })
+                .collect(),
+            ecdsa_signature:   ecdsa_signature.map(str::to_string),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_participants() {
+        let dir = TestDir::new("participants");
+        fs::create_dir_all(&dir).unwrap();
+        let participants = ParticipantList::open(&dir).unwrap();
+        participants
+            .set_name("git|1|alice", Some(" Alice ".to_string()))
+            .await
+            .unwrap();
+        participants
+            .record(
+                "git|1|alice",
+                1,
+                &contribution(&[Some("0xa1"), None], None),
+                &[0; 32],
+                &[1; 32],
+            )
+            .await;
+        participants
+            .record(
+                "git|2|bob",
+                2,
+                &contribution(&[Some("0xb1"); 2], Some("0xec")),
+                &[1; 32],
+                &[2; 32],
+            )
+            .await;
+        assert_eq!(
+            participants
+                .set_name("git|2|bob", Some("\u{7}".to_string()))
+                .await,
+            Err(ParticipantsError::InvalidName)
+        );
+        assert_eq!(
+            participants
+                .set_name("git|2|bob", Some("b".repeat(65)))
+                .await,
+            Err(ParticipantsError::InvalidName)
+        );
+
+        // Reload from disk.
+        let list = ParticipantList::open(&dir).unwrap().list();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].display_name.as_deref(), Some("Alice"));
+        assert_eq!(list[0].pot_pubkeys, vec![Some("0xa1".to_string()), None]);
+        assert_eq!(list[1].display_name, None);
+        assert_eq!(
+            list[1].transcript_hash,
+            format!("0x{}", hex::encode([2; 32]))
+        );
+        assert!(!serde_json::to_string(&list).unwrap().contains("git|"));
+
+        participants.set_name("git|1|alice", None).await.unwrap();
+        assert_eq!(participants.list()[0].display_name, None);
+        participants.set_public("git|2|bob", true).await;
+        let participants = ParticipantList::open(&dir).unwrap();
+        assert_eq!(participants.list()[0].display_name, None);
+
+        // Hashes not checkpointed are taken from the list.
+        let details = participants.details(2, &[], None, None);
+        assert_eq!(details.identity.as_deref(), Some("git|2|bob"));
+        assert_eq!(details.ecdsa_signature.as_deref(), Some("0xec"));
+        assert_eq!(
+            details.transcript_before,
+            Some(format!("0x{}", hex::encode([1; 32])))
+        );
+        assert_eq!(
+            details.transcript_after,
+            Some(format!("0x{}", hex::encode([2; 32])))
+        );
+        assert_eq!(participants.details(1, &[], None, None).identity, None);
+
+        // Contributions not accepted here only have the transcript data.
+        let details = participants.details(3, &[], Some([2; 32]), Some([3; 32]));
+        assert_eq!(details.identity, None);
+        assert_eq!(details.ecdsa_signature, None);
+        assert_eq!(
+            details.transcript_after,
+            Some(format!("0x{}", hex::encode([3; 32])))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_legacy_list() {
+        let dir = TestDir::new("participants-legacy");
+        fs::create_dir_all(&dir).unwrap();
+        let records = Records {
+            contributions: vec![],
+            names:         [("git|1|alice".to_string(), "Alice".to_string())].into(),
+            public:        HashSet::new(),
+        };
+        fs::write(dir.join(LEGACY_FILE), serde_json::to_vec(&records).unwrap()).unwrap();
+        let participants = ParticipantList::open(&dir).unwrap();
+        participants
+            .set_name("git|2|bob", Some("Bob".to_string()))
+            .await
+            .unwrap();
+
+        let records = ParticipantList::open(&dir)
+            .unwrap()
+            .records
+            .into_inner()
+            .unwrap();
+        assert_eq!(records.names.len(), 2);
+        assert_eq!(records.names["git|1|alice"], "Alice");
+    }
+
+    #[test]
+    fn test_witness_entries() {
+        let sizes = [(4, 2), (8, 2)];
+        let mut batch = BatchTranscript::new(&sizes);
+        let mut rng = SecretRng::new();
+        let rounds = vec![vec![0, 1], vec![1], vec![0]];
+        for round in &rounds {
+            let contributions = batch
+                .transcripts
+                .iter()
+                .enumerate()
+                .map(|(i, transcript)| {
+                    round.contains(&i).then(|| {
+                        let mut contribution = transcript.contribution();
+                        contribution.add_tau(&SecretScalar::random(&mut rng));
+                        contribution
+                    })
+                })
+                .collect();
+            batch.add(contributions);
+        }
+
+        let entries = witness_entries(&batch, &rounds, 2);
+        assert_eq!(entries[0], None);
+        assert_eq!(entries[1].unwrap().0, batch.transcripts[1].pubkeys[2]);
+        let entries = witness_entries(&batch, &rounds, 3);
+        assert_eq!(entries[0].unwrap().0, batch.transcripts[0].pubkeys[2]);
+        assert_eq!(entries[1], None);
+
+        let dir = TestDir::new("participants-witness");
+        fs::create_dir_all(&dir).unwrap();
+        let participants = ParticipantList::open(&dir).unwrap();
+        let details = participants.details(3, &entries, Some([2; 32]), Some([3; 32]));
+        assert_eq!(details.pot_pubkeys, vec![
+            Some(format_g(&batch.transcripts[0].pubkeys[2])),
+            None
+        ]);
+        assert_eq!(details.bls_signatures, vec![None, None]);
+    }
+}
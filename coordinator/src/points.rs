@@ -0,0 +1,345 @@
+//! Memory-mapped transcript powers.
+//!
+//! The transcript store keeps the current powers of every sub-ceremony as a
+//! flat file of uncompressed points, see [`crate::storage`]. For large setups,
+//! 2^21 powers and up, reading another copy of them into memory for every
+//! check is expensive. A [`PointFile`] maps such a file instead and decodes
+//! points on access, so only the pages in use are resident.
+//!
+//! A [`MappedTranscript`] is the stored transcript with mapped powers. Its
+//! powers are verified in chunks of [`CHUNK`] points, so the working set of a
+//! verification stays bounded whatever the size.
+//!
+//! Only the background re-verification reads the transcript this way, see
+//! [`crate::integrity`]. The [`crate::ceremony::Ceremony`] still holds the
+//! current powers in memory, to verify uploads against and to serve the
+//! transcript from.
+//!
+//! The store replaces power files by renaming rather than overwriting them, so
+//! a mapping stays valid while contributions are appended, and keeps showing
+//! the powers as they were when mapped.
+//!
+//! Decoded points are checked to be on the curve and in the prime order
+//! subgroup, see [`StoredPoint`], so a corrupted file is an error rather than
+//! input to the pairing checks.
+
+use ark_bls12_381::{g1, g2, Fr, G1Affine, G1Projective, G2Affine};
+use ark_ec::{short_weierstrass_jacobian::GroupAffine, AffineCurve};
+use ark_ff::{PrimeField, UniformRand, Zero};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use eyre::{ensure, Result as EyreResult, WrapErr};
+use kzg_ceremony_crypto::{
+    g1_subgroup_check, g2_subgroup_check, CurveBackend, DefaultBackend, ScalarInt, Transcript,
+    VerificationRng, VerifyError,
+};
+use memmap2::Mmap;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::{cmp::min, fmt, fs::File, marker::PhantomData, ops::Range, path::Path};
+use thiserror::Error;
+
+/// Number of points decoded at once when verifying.
+pub const CHUNK: usize = 1 << 14;
+
+#[derive(Debug, Error)]
+pub enum MappedError {
+    #[error("Could not decode a stored point: {0}")]
+    Decode(#[from] SerializationError),
+    #[error(transparent)]
+    Invalid(#[from] VerifyError),
+}
+
+/// A point as stored, uncompressed.
+pub trait StoredPoint: CanonicalDeserialize + CanonicalSerialize + Default + Send + Sync {
+    /// Whether the point is on the curve and in the prime order subgroup.
+    fn is_valid(&self) -> bool;
+}
+
+/// Read-only mapping of a file of uncompressed points.
+pub struct PointFile<P> {
+    map:   Mmap,
+    len:   usize,
+    point: PhantomData<P>,
+}
+
+/// A stored transcript with mapped powers and the witness in memory.
+#[derive(Debug)]
+pub struct MappedTranscript {
+    pub transcripts: Vec<MappedSubTranscript>,
+}
+
+#[derive(Debug)]
+pub struct MappedSubTranscript {
+    pub g1_powers: PointFile<G1Affine>,
+    pub g2_powers: PointFile<G2Affine>,
+    pub products:  Vec<G1Affine>,
+    pub pubkeys:   Vec<G2Affine>,
+}
+
+// Spelled out, coherence does not see through the `Bls12` type aliases.
+impl StoredPoint for GroupAffine<g1::Parameters> {
+    fn is_valid(&self) -> bool {
+        self.is_on_curve() && g1_subgroup_check(self)
+    }
+}
+
+impl StoredPoint for GroupAffine<g2::Parameters> {
+    fn is_valid(&self) -> bool {
+        self.is_on_curve() && g2_subgroup_check(self)
+    }
+}
+
+/// Decode `n` points from `reader`, checking them in parallel.
+pub fn decode_checked<P: StoredPoint, R: std::io::Read>(
+    reader: &mut R,
+    n: usize,
+) -> Result<Vec<P>, SerializationError> {
+    let points = (0..n)
+        .map(|_| P::deserialize_unchecked(&mut *reader))
+        .collect::<Result<Vec<_>, _>>()?;
+    if points.par_iter().all(StoredPoint::is_valid) {
+        Ok(points)
+    } else {
+        Err(SerializationError::InvalidData)
+    }
+}
+
+impl<P: StoredPoint> PointFile<P> {
+    /// Map the points in the file at `path`.
+    pub fn open(path: &Path) -> EyreResult<Self> {
+        let file =
+            File::open(path).wrap_err_with(|| format!("Could not open {}", path.display()))?;
+        // SAFETY: The store never modifies a power file in place, new powers
+        // are written to a new file that is renamed over the old one.
+        let map = unsafe { Mmap::map(&file)? };
+        let size = P::default().uncompressed_size();
+        ensure!(
+            map.len() % size == 0,
+            "File {} is truncated",
+            path.display()
+        );
+        Ok(Self {
+            len: map.len() / size,
+            map,
+            point: PhantomData,
+        })
+    }
+
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The encoded points, as stored.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// Point `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds, like indexing a slice.
+    pub fn get(&self, index: usize) -> Result<P, SerializationError> {
+        Ok(self.read(index..index + 1)?.remove(0))
+    }
+
+    /// Points in `range`, checked to be on the curve and in the subgroup.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is out of bounds, like slicing.
+    pub fn read(&self, range: Range<usize>) -> Result<Vec<P>, SerializationError> {
+        assert!(range.end <= self.len, "Range out of bounds");
+        let size = P::default().uncompressed_size();
+        let mut bytes = &self.map[range.start * size..range.end * size];
+        decode_checked(&mut bytes, range.len())
+    }
+
+    /// Consecutive chunks of at most `len` points.
+    pub fn chunks(
+        &self,
+        len: usize,
+    ) -> impl Iterator<Item = Result<Vec<P>, SerializationError>> + '_ {
+        (0..self.len)
+            .step_by(len)
+            .map(move |start| self.read(start..min(start + len, self.len)))
+    }
+}
+
+impl<P> fmt::Debug for PointFile<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PointFile")
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MappedTranscript {
+    /// Same as [`kzg_ceremony_crypto::BatchTranscript::hash`], read from the
+    /// mapped files.
+    #[must_use]
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        let mut buffer = Vec::new();
+        for transcript in &self.transcripts {
+            hasher.update(transcript.g1_powers.as_bytes());
+            hasher.update(transcript.g2_powers.as_bytes());
+            for product in &transcript.products {
+                buffer.clear();
+                product
+                    .serialize_uncompressed(&mut buffer)
+                    .expect("Writing to a Vec can not fail");
+                hasher.update(&buffer);
+            }
+            for pubkey in &transcript.pubkeys {
+                buffer.clear();
+                pubkey
+                    .serialize_uncompressed(&mut buffer)
+                    .expect("Writing to a Vec can not fail");
+                hasher.update(&buffer);
+            }
+        }
+        hasher.finalize().into()
+    }
+}
+
+impl MappedSubTranscript {
+    /// Same checks as [`Transcript::verify`].
+    pub fn verify(&self, rng: &mut VerificationRng) -> Result<(), MappedError> {
+        self.verify_powers(rng)?;
+        let witness = Transcript {
+            g1_powers:  Vec::new(),
+            g2_powers:  Vec::new(),
+            products:   self.products.clone(),
+            pubkeys:    self.pubkeys.clone(),
+            signatures: vec![None; self.pubkeys.len()],
+        };
+        witness.verify_witness_with_rng(rng)?;
+        Ok(())
+    }
+
+    /// Same checks as [`Transcript::verify_powers`], decoding at most
+    /// [`CHUNK`] points at a time.
+    pub fn verify_powers(&self, rng: &mut VerificationRng) -> Result<(), MappedError> {
+        let (n1, n2) = (self.g1_powers.len(), self.g2_powers.len());
+        if self.products.len() != self.pubkeys.len() {
+            return Err(VerifyError::ChainLength(self.products.len(), self.pubkeys.len()).into());
+        }
+        if n1 < 2 || n2 < 2 {
+            return Err(VerifyError::TooFewPowers.into());
+        }
+        let g1 = G1Affine::prime_subgroup_generator();
+        let g2 = G2Affine::prime_subgroup_generator();
+        let tau_g2 = self.g2_powers.get(1)?;
+        if self.g1_powers.get(0)? != g1 || self.g2_powers.get(0)? != g2 {
+            return Err(VerifyError::InvalidGenesis.into());
+        }
+        let product = self.products.last().ok_or(VerifyError::EmptyTranscript)?;
+        if self.g1_powers.get(1)? != *product {
+            return Err(VerifyError::PowersMismatch.into());
+        }
+
+        // e(sum r_i g1[i + 1], g2) = e(sum r_i g1[i], tau g2)
+        let mut lhs = G1Projective::zero();
+        let mut rhs = G1Projective::zero();
+        let mut start = 0;
+        while start + 1 < n1 {
+            let end = min(start + CHUNK, n1 - 1);
+            let points = self.g1_powers.read(start..end + 1)?;
+            let factors = random_factors(end - start, rng);
+            lhs += DefaultBackend::g1_msm(&points[1..], &factors);
+            rhs += DefaultBackend::g1_msm(&points[..factors.len()], &factors);
+            start = end;
+        }
+        if !pairings_equal(lhs, g2, rhs, tau_g2) {
+            return Err(VerifyError::InconsistentG1Powers.into());
+        }
+
+        // e(sum r_j g1[j], g2) = e(g1, sum r_j g2[j])
+        let n = min(n1, n2);
+        let factors = random_factors(n, rng);
+        let lhs = DefaultBackend::g1_msm(&self.g1_powers.read(0..n)?, &factors);
+        let rhs = DefaultBackend::g2_msm(&self.g2_powers.read(0..n)?, &factors);
+        if !DefaultBackend::pairing_product_is_one(&[
+            (lhs.into(), g2),
+            ((-g1.into_projective()).into(), rhs.into()),
+        ]) {
+            return Err(VerifyError::InconsistentG2Powers.into());
+        }
+        Ok(())
+    }
+}
+
+fn random_factors(n: usize, rng: &mut VerificationRng) -> Vec<ScalarInt> {
+    (0..n).map(|_| Fr::rand(rng).into_repr()).collect()
+}
+
+/// Whether `e(a, b) = e(c, d)`.
+fn pairings_equal(a: G1Projective, b: G2Affine, c: G1Projective, d: G2Affine) -> bool {
+    DefaultBackend::pairing_product_is_one(&[(a.into(), b), ((-c).into(), d)])
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{storage::TranscriptStore, test::TestDir};
+    use kzg_ceremony_crypto::{BatchTranscript, SecretRng, SecretScalar};
+
+    #[test]
+    fn test_mapped() {
+        let dir = TestDir::new("points");
+        let mut batch = BatchTranscript::new(&[(CHUNK + 5, 2), (8, 3)]);
+        let mut store = TranscriptStore::create(&dir, &batch).unwrap();
+        let contributions = batch
+            .transcripts
+            .iter()
+            .map(|transcript| {
+                let mut contribution = transcript.contribution();
+                contribution.add_tau(&SecretScalar::random(&mut SecretRng::new()));
+                Some(contribution)
+            })
+            .collect::<Vec<_>>();
+        store.append(&contributions).unwrap();
+        batch.add(contributions);
+
+        let mapped = store.map().unwrap();
+        assert_eq!(mapped.hash(), batch.hash());
+        let powers = &mapped.transcripts[0].g1_powers;
+        assert_eq!(powers.len(), CHUNK + 5);
+        assert_eq!(powers.get(3).unwrap(), batch.transcripts[0].g1_powers[3]);
+        let chunks = powers.chunks(CHUNK).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(chunks.concat(), batch.transcripts[0].g1_powers);
+        let mut rng = VerificationRng::new();
+        for transcript in &mapped.transcripts {
+            transcript.verify(&mut rng).unwrap();
+        }
+
+        // The mapping keeps the powers it was made with.
+        let contribution = {
+            let mut contribution = batch.transcripts[1].contribution();
+            contribution.add_tau(&SecretScalar::random(&mut SecretRng::new()));
+            contribution
+        };
+        store.append(&[None, Some(contribution)]).unwrap();
+        assert_eq!(mapped.hash(), batch.hash());
+        assert_ne!(store.map().unwrap().hash(), batch.hash());
+
+        // Powers that do not follow from tau are caught past the first chunk.
+        let mut transcript = batch.transcripts[0].clone();
+        transcript.g1_powers[CHUNK + 2] = G1Affine::prime_subgroup_generator();
+        let dir = dir.join("corrupt");
+        let store =
+            TranscriptStore::create(&dir, &BatchTranscript::from(vec![transcript])).unwrap();
+        assert!(matches!(
+            store.map().unwrap().transcripts[0].verify_powers(&mut rng),
+            Err(MappedError::Invalid(VerifyError::InconsistentG1Powers))
+        ));
+    }
+}
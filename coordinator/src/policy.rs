@@ -0,0 +1,373 @@
+//! Participation policy.
+//!
+//! Decides whether an authenticated identity may join the queue. Policies are
+//! composed from rules and loaded from a JSON file given with `--policy`, for
+//! example
+//!
+//! ```json
+//! { "all": [
+//!     { "minGithubAgeDays": 30 },
+//!     { "minEthNonce": 4 },
+//!     "oneContribution",
+//!     { "deny": ["git|1234|sybil"] }
+//! ] }
+//! ```
+//!
+//! Account age only applies to GitHub identities (`git|..`) and the nonce
+//! only to Ethereum identities (`eth|..`); other identities pass these rules.
+//!
+//! Identities that contributed are persisted and may not join again, whatever
+//! the policy, unless `--allow-repeat-contributions` is set for a test
+//! ceremony. The `oneContribution` rule is kept for existing policy files.
+
+use crate::{session::Session, storage::write_atomic};
+use eyre::{Result as EyreResult, WrapErr};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+use tracing::{info, warn};
+
+const CONTRIBUTORS_FILE: &str = "contributors.json";
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum PolicyError {
+    #[error("Identity is not allowed to participate")]
+    Denied,
+    #[error("Identity has already contributed")]
+    AlreadyContributed,
+    #[error("GitHub account is {0} days old, at least {1} days required")]
+    AccountTooNew(u64, u64),
+    #[error("Ethereum account has nonce {0}, at least {1} required")]
+    NonceTooLow(u64, u64),
+    #[error("Session is missing the {0} attribute required by the policy")]
+    MissingAttribute(&'static str),
+    #[error("Identity does not satisfy any of the allowed policies")]
+    NoneSatisfied,
+}
+
+/// A composable participation rule.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Policy {
+    /// All rules must pass.
+    All(Vec<Self>),
+    /// At least one rule must pass.
+    Any(Vec<Self>),
+    MinGithubAgeDays(u64),
+    MinEthNonce(u64),
+    /// Identities may only contribute once.
+    #[default]
+    OneContribution,
+    /// Identities that may never participate.
+    Deny(Vec<String>),
+}
+
+/// A participation policy with the set of identities that contributed.
+#[derive(Debug)]
+pub struct PolicyEngine {
+    policy:       Policy,
+    path:         PathBuf,
+    contributors: Mutex<HashSet<String>>,
+    allow_repeat: bool,
+}
+
+impl PolicyError {
+    /// Stable error code for clients.
+    #[must_use]
+    pub const fn code(&self) -> &'static str {
+        match self {
+            Self::Denied => "denied",
+            Self::AlreadyContributed => "already_contributed",
+            Self::AccountTooNew(..) => "account_too_new",
+            Self::NonceTooLow(..) => "nonce_too_low",
+            Self::MissingAttribute(_) => "missing_attribute",
+            Self::NoneSatisfied => "none_satisfied",
+        }
+    }
+}
+
+impl Policy {
+    /// Load a policy from a JSON file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can not be read or is not a valid policy.
+    pub fn load(path: &Path) -> EyreResult<Self> {
+        let json = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Could not read policy {}", path.display()))?;
+        serde_json::from_str(&json).wrap_err_with(|| format!("Invalid policy {}", path.display()))
+    }
+
+    /// Check `session` against the policy at unix time `now`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the [`PolicyError`] of the first rule `session` does not
+    /// satisfy.
+    pub fn check(&self, session: &Session, contributed: bool, now: u64) -> Result<(), PolicyError> {
+        match self {
+            Self::All(policies) => policies
+                .iter()
+                .try_for_each(|policy| policy.check(session, contributed, now)),
+            Self::Any(policies) => {
+                if policies
+                    .iter()
+                    .any(|policy| policy.check(session, contributed, now).is_ok())
+                {
+                    Ok(())
+                } else {
+                    Err(PolicyError::NoneSatisfied)
+                }
+            }
+            Self::MinGithubAgeDays(days) => {
+                if !session.identity.starts_with("git|") {
+                    return Ok(());
+                }
+                let created = session
+                    .attributes
+                    .account_created
+                    .ok_or(PolicyError::MissingAttribute("account creation time"))?;
+                let age = now.saturating_sub(created) / SECONDS_PER_DAY;
+                if age < *days {
+                    return Err(PolicyError::AccountTooNew(age, *days));
+                }
+                Ok(())
+            }
+            Self::MinEthNonce(min) => {
+                if !session.identity.starts_with("eth|") {
+                    return Ok(());
+                }
+                let nonce = session
+                    .attributes
+                    .nonce
+                    .ok_or(PolicyError::MissingAttribute("nonce"))?;
+                if nonce < *min {
+                    return Err(PolicyError::NonceTooLow(nonce, *min));
+                }
+                Ok(())
+            }
+            Self::OneContribution => {
+                if contributed {
+                    return Err(PolicyError::AlreadyContributed);
+                }
+                Ok(())
+            }
+            Self::Deny(identities) => {
+                if identities.contains(&session.identity) {
+                    return Err(PolicyError::Denied);
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl PolicyEngine {
+    /// Load the contributors persisted in `dir`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the persisted contributors can not be read or
+    /// parsed.
+    pub fn open(dir: &Path, policy: Policy) -> EyreResult<Self> {
+        let path = dir.join(CONTRIBUTORS_FILE);
+        let contributors = read_contributors(&path)?;
+        info!(
+            ?policy,
+            contributors = contributors.len(),
+            "Loaded participation policy"
+        );
+        Ok(Self {
+            policy,
+            path,
+            contributors: Mutex::new(contributors),
+            allow_repeat: false,
+        })
+    }
+
+    /// Reload the contributors persisted by another coordinator sharing the
+    /// directory, see [`crate::leader`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the persisted contributors can not be read or
+    /// parsed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the contributors lock is poisoned.
+    pub fn reload(&self) -> EyreResult<()> {
+        let contributors = read_contributors(&self.path)?;
+        *self.contributors.lock().unwrap() = contributors;
+        Ok(())
+    }
+
+    /// Let identities contribute more than once, for test ceremonies.
+    #[must_use]
+    pub fn with_repeat_contributions(mut self, allow: bool) -> Self {
+        if allow {
+            warn!("Identities may contribute more than once");
+        }
+        self.allow_repeat = allow;
+        self
+    }
+
+    /// Decide whether `session` may join the queue.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PolicyError::AlreadyContributed`] for repeat contributions,
+    /// unless allowed, or the violation of the policy.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the contributors lock is poisoned.
+    pub fn check(&self, session: &Session) -> Result<(), PolicyError> {
+        let contributed = !self.allow_repeat
+            && self
+                .contributors
+                .lock()
+                .unwrap()
+                .contains(&session.pseudonym);
+        if contributed {
+            return Err(PolicyError::AlreadyContributed);
+        }
+        self.policy.check(session, contributed, now())
+    }
+
+    /// Record that `identity` contributed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the contributors lock is poisoned.
+    pub fn record_contribution(&self, identity: &str) {
+        let mut contributors = self.contributors.lock().unwrap();
+        if contributors.insert(identity.to_string()) {
+            let result = serde_json::to_vec(&*contributors)
+                .map_err(Into::into)
+                .and_then(|json| write_atomic(&self.path, &json));
+            if let Err(error) = result {
+                warn!(?error, "Could not persist contributors");
+            }
+        }
+        drop(contributors);
+    }
+}
+
+fn read_contributors(path: &Path) -> EyreResult<HashSet<String>> {
+    Ok(if path.exists() {
+        serde_json::from_slice(&fs::read(path)?)?
+    } else {
+        HashSet::new()
+    })
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time before unix epoch")
+        .as_secs()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{session::Attributes, test::TestDir};
+
+    fn session(identity: &str, account_created: Option<u64>, nonce: Option<u64>) -> Session {
+        Session {
+            identity:   identity.to_string(),
+            expires:    0,
+            issued:     0,
+            key_id:     None,
+            attributes: Attributes {
+                account_created,
+                nonce,
+            },
+            pseudonym:  identity.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_policy() {
+        let policy: Policy = serde_json::from_str(
+            r#"{ "all": [
+                { "minGithubAgeDays": 30 },
+                { "minEthNonce": 4 },
+                "oneContribution",
+                { "deny": ["git|2|sybil"] }
+            ] }"#,
+        )
+        .unwrap();
+        let now = 100 * SECONDS_PER_DAY;
+        let check = |session: &Session, contributed| policy.check(session, contributed, now);
+
+        assert_eq!(check(&session("git|1|old", Some(0), None), false), Ok(()));
+        assert_eq!(
+            check(&session("git|1|old", Some(0), None), true),
+            Err(PolicyError::AlreadyContributed)
+        );
+        assert_eq!(
+            check(
+                &session("git|1|new", Some(now - SECONDS_PER_DAY), None),
+                false
+            ),
+            Err(PolicyError::AccountTooNew(1, 30))
+        );
+        assert_eq!(
+            check(&session("git|1|new", None, None), false),
+            Err(PolicyError::MissingAttribute("account creation time"))
+        );
+        assert_eq!(
+            check(&session("git|2|sybil", Some(0), None), false),
+            Err(PolicyError::Denied)
+        );
+        assert_eq!(check(&session("eth|0x01", None, Some(4)), false), Ok(()));
+        assert_eq!(
+            check(&session("eth|0x01", None, Some(3)), false),
+            Err(PolicyError::NonceTooLow(3, 4))
+        );
+
+        let any = Policy::Any(vec![Policy::MinEthNonce(10), Policy::Deny(vec![])]);
+        assert_eq!(
+            any.check(&session("eth|0x01", None, Some(3)), false, now),
+            Ok(())
+        );
+        let any = Policy::Any(vec![Policy::MinEthNonce(10)]);
+        assert_eq!(
+            any.check(&session("eth|0x01", None, Some(3)), false, now),
+            Err(PolicyError::NoneSatisfied)
+        );
+    }
+
+    #[test]
+    fn test_repeat_contributions() {
+        let dir = TestDir::new("policy");
+        fs::create_dir_all(&dir).unwrap();
+        let session = session("git|1|old", Some(0), None);
+
+        // Enforced even if the policy does not ask for it.
+        let engine = PolicyEngine::open(&dir, Policy::All(vec![])).unwrap();
+        assert_eq!(engine.check(&session), Ok(()));
+        engine.record_contribution(&session.identity);
+        assert_eq!(engine.check(&session), Err(PolicyError::AlreadyContributed));
+        assert_eq!(
+            PolicyError::AlreadyContributed.code(),
+            "already_contributed"
+        );
+
+        // Persisted across restarts, unless overridden.
+        let engine = PolicyEngine::open(&dir, Policy::default()).unwrap();
+        assert_eq!(engine.check(&session), Err(PolicyError::AlreadyContributed));
+        let engine = engine.with_repeat_contributions(true);
+        assert_eq!(engine.check(&session), Ok(()));
+    }
+}
@@ -0,0 +1,388 @@
+//! Pseudonymous identities.
+//!
+//! With `--identity-salt` the coordinator stores no GitHub or Ethereum
+//! identities. Sessions are mapped to a pseudonym `anon|{hex}`, the
+//! HMAC-SHA256 of the identity under the salt, on every request, and the
+//! queue, audit log, receipts, bans, analytics and participant list only see
+//! the pseudonym. The identity itself is only used to check the signatures in
+//! a contribution and the participation policy.
+//!
+//! Resolving disputes needs the identity behind a pseudonym, so with
+//! `--identity-mapping-key` every new pseudonym is appended to
+//! `pseudonyms.jsonl` together with the identity encrypted under that key
+//! (AES-256-CTR, then HMAC-SHA256 over nonce, pseudonym and ciphertext). The
+//! operator resolves a pseudonym on `GET /admin/pseudonyms/{pseudonym}`.
+//! Without the key, pseudonyms can only be resolved by hashing a suspected
+//! identity.
+//!
+//! Priority tiers list identities, they are hashed on load.
+
+use crate::admin::Admin;
+use aes::{
+    cipher::{KeyIvInit, StreamCipher},
+    Aes256,
+};
+use axum::{
+    extract::Path as UrlPath,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use ctr::Ctr128BE;
+use eyre::{bail, ensure, eyre, Error as EyreError, Result as EyreResult, WrapErr};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use kzg_ceremony_crypto::SecretRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use std::{
+    collections::HashSet,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+use tracing::{error, info, warn};
+
+const FILE: &str = "pseudonyms.jsonl";
+const PREFIX: &str = "anon|";
+const NONCE_SIZE: usize = 16;
+const TAG_SIZE: usize = 32;
+
+/// Secret salt of the pseudonyms.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Salt([u8; 32]);
+
+/// Secret key of the encrypted mapping from pseudonyms to identities.
+#[derive(Clone, PartialEq, Eq)]
+pub struct MappingKey([u8; 32]);
+
+#[derive(Debug)]
+pub struct Pseudonyms {
+    salt:    Option<Salt>,
+    mapping: Option<Mapping>,
+}
+
+#[derive(Debug)]
+struct Mapping {
+    path:    PathBuf,
+    enc_key: [u8; 32],
+    mac_key: [u8; 32],
+    state:   Mutex<MappingState>,
+}
+
+#[derive(Debug)]
+struct MappingState {
+    file:  File,
+    /// Pseudonyms already in the file.
+    known: HashSet<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    pseudonym: String,
+    /// Nonce, ciphertext and tag, `0x` prefixed hex.
+    identity:  String,
+}
+
+impl Pseudonyms {
+    /// Identities are stored as they are.
+    #[must_use]
+    pub const fn disabled() -> Self {
+        Self {
+            salt:    None,
+            mapping: None,
+        }
+    }
+
+    /// Pseudonymize with `salt`, keeping the encrypted mapping in `dir` if a
+    /// `key` is given.
+    pub fn open(salt: Option<Salt>, key: Option<MappingKey>, dir: &Path) -> EyreResult<Self> {
+        let salt = if let Some(salt) = salt {
+            salt
+        } else {
+            ensure!(
+                key.is_none(),
+                "--identity-mapping-key requires --identity-salt"
+            );
+            return Ok(Self::disabled());
+        };
+        let mapping = key.map(|key| Mapping::open(&key, dir)).transpose()?;
+        if mapping.is_none() {
+            warn!("No identity mapping key, pseudonyms can not be resolved");
+        }
+        info!("Pseudonymizing identities");
+        Ok(Self {
+            salt: Some(salt),
+            mapping,
+        })
+    }
+
+    /// The pseudonym of `identity`, recording it in the mapping if new.
+    #[must_use]
+    pub fn pseudonym(&self, identity: &str) -> String {
+        let pseudonym = self.hash(identity);
+        if let Some(mapping) = &self.mapping {
+            mapping.record(&pseudonym, identity);
+        }
+        pseudonym
+    }
+
+    /// The pseudonym of `identity` without recording it.
+    #[must_use]
+    pub fn hash(&self, identity: &str) -> String {
+        match &self.salt {
+            Some(Salt(salt)) => {
+                let mut mac =
+                    Hmac::<Sha256>::new_from_slice(salt).expect("HMAC accepts any key size");
+                mac.update(identity.as_bytes());
+                format!("{}{}", PREFIX, hex::encode(mac.finalize().into_bytes()))
+            }
+            None => identity.to_string(),
+        }
+    }
+
+    /// The identity behind `pseudonym`, if it is in the mapping.
+    pub fn reveal(&self, pseudonym: &str) -> EyreResult<Option<String>> {
+        let mapping = match &self.mapping {
+            Some(mapping) => mapping,
+            None => bail!("No identity mapping key configured"),
+        };
+        let identity = match mapping.reveal(pseudonym)? {
+            Some(identity) => identity,
+            None => return Ok(None),
+        };
+        ensure!(
+            self.hash(&identity) == pseudonym,
+            "Identity mapping does not match the salt"
+        );
+        Ok(Some(identity))
+    }
+}
+
+impl Mapping {
+    fn open(key: &MappingKey, dir: &Path) -> EyreResult<Self> {
+        let path = dir.join(FILE);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .wrap_err_with(|| format!("Could not open identity mapping {}", path.display()))?;
+        let known = read_entries(&path)?
+            .into_iter()
+            .map(|entry| entry.pseudonym)
+            .collect::<HashSet<_>>();
+        info!(pseudonyms = known.len(), "Loaded identity mapping");
+        let mut okm = [0; 64];
+        Hkdf::<Sha256>::new(None, &key.0)
+            .expand(b"kzg-ceremony identity mapping", &mut okm)
+            .expect("Output size is valid");
+        let (enc_key, mac_key) = okm.split_at(32);
+        Ok(Self {
+            path,
+            enc_key: enc_key.try_into().unwrap(),
+            mac_key: mac_key.try_into().unwrap(),
+            state: Mutex::new(MappingState { file, known }),
+        })
+    }
+
+    /// Append `identity` under `pseudonym` if not already there.
+    ///
+    /// The request goes on if the entry can not be written, the failure is
+    /// logged as an error and retried on the next request.
+    fn record(&self, pseudonym: &str, identity: &str) {
+        let mut state = self.state.lock().unwrap();
+        if state.known.contains(pseudonym) {
+            return;
+        }
+        let entry = Entry {
+            pseudonym: pseudonym.to_string(),
+            identity:  format!("0x{}", hex::encode(self.encrypt(pseudonym, identity))),
+        };
+        let result = serde_json::to_string(&entry)
+            .map_err(EyreError::from)
+            .and_then(|line| {
+                writeln!(state.file, "{line}")?;
+                state.file.sync_data()?;
+                Ok(())
+            });
+        match result {
+            Ok(()) => {
+                state.known.insert(entry.pseudonym);
+            }
+            Err(error) => error!(?error, pseudonym, "Could not write identity mapping"),
+        }
+        drop(state);
+    }
+
+    fn reveal(&self, pseudonym: &str) -> EyreResult<Option<String>> {
+        // Hold the lock so no entry is half written.
+        let _state = self.state.lock().unwrap();
+        let Some(entry) = read_entries(&self.path)?
+            .into_iter()
+            .find(|entry| entry.pseudonym == pseudonym)
+        else {
+            return Ok(None);
+        };
+        let sealed = hex::decode(entry.identity.strip_prefix("0x").unwrap_or(&entry.identity))?;
+        self.decrypt(pseudonym, &sealed).map(Some)
+    }
+
+    fn encrypt(&self, pseudonym: &str, identity: &str) -> Vec<u8> {
+        let mut nonce = [0; NONCE_SIZE];
+        SecretRng::new().fill_bytes(&mut nonce);
+        let mut sealed = nonce.to_vec();
+        let mut ciphertext = identity.as_bytes().to_vec();
+        Ctr128BE::<Aes256>::new(&self.enc_key.into(), &nonce.into())
+            .apply_keystream(&mut ciphertext);
+        sealed.extend_from_slice(&ciphertext);
+        let tag = self
+            .mac(&nonce, pseudonym, &ciphertext)
+            .finalize()
+            .into_bytes();
+        sealed.extend_from_slice(&tag);
+        sealed
+    }
+
+    fn decrypt(&self, pseudonym: &str, sealed: &[u8]) -> EyreResult<String> {
+        ensure!(
+            sealed.len() >= NONCE_SIZE + TAG_SIZE,
+            "Identity mapping entry too short"
+        );
+        let (nonce, rest) = sealed.split_at(NONCE_SIZE);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_SIZE);
+        self.mac(nonce, pseudonym, ciphertext)
+            .verify_slice(tag)
+            .map_err(|_| eyre!("Identity mapping entry does not match the key"))?;
+        let mut plaintext = ciphertext.to_vec();
+        Ctr128BE::<Aes256>::new(&self.enc_key.into(), nonce.into()).apply_keystream(&mut plaintext);
+        Ok(String::from_utf8(plaintext)?)
+    }
+
+    fn mac(&self, nonce: &[u8], pseudonym: &str, ciphertext: &[u8]) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.mac_key).expect("HMAC accepts any key size");
+        mac.update(nonce);
+        mac.update(pseudonym.as_bytes());
+        mac.update(ciphertext);
+        mac
+    }
+}
+
+fn read_entries(path: &Path) -> EyreResult<Vec<Entry>> {
+    let contents = fs::read_to_string(path)
+        .wrap_err_with(|| format!("Could not read identity mapping {}", path.display()))?;
+    contents
+        .lines()
+        .map(|line| serde_json::from_str(line).map_err(EyreError::from))
+        .collect()
+}
+
+fn parse_key(s: &str) -> EyreResult<[u8; 32]> {
+    let mut key = [0; 32];
+    hex::decode_to_slice(s.strip_prefix("0x").unwrap_or(s), &mut key)
+        .map_err(|_| eyre!("Key must be 32 bytes of hex"))?;
+    Ok(key)
+}
+
+impl fmt::Debug for Salt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Salt(..)")
+    }
+}
+
+impl FromStr for Salt {
+    type Err = EyreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_key(s).map(Self)
+    }
+}
+
+impl fmt::Debug for MappingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MappingKey(..)")
+    }
+}
+
+impl FromStr for MappingKey {
+    type Err = EyreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_key(s).map(Self)
+    }
+}
+
+/// Resolve a pseudonym to the identity behind it.
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn reveal(
+    _admin: Admin,
+    UrlPath(pseudonym): UrlPath<String>,
+    Extension(pseudonyms): Extension<Arc<Pseudonyms>>,
+) -> Response {
+    match pseudonyms.reveal(&pseudonym) {
+        Ok(Some(identity)) => Json(json!({ "identity": identity })).into_response(),
+        Ok(None) => {
+            let body = Json(json!({ "error": "Unknown pseudonym" }));
+            (StatusCode::NOT_FOUND, body).into_response()
+        }
+        Err(error) => {
+            error!(?error, "Could not resolve pseudonym");
+            let body = Json(json!({ "error": error.to_string() }));
+            (StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test::TestDir;
+
+    #[test]
+    fn test_pseudonyms() {
+        let dir = TestDir::new("pseudonyms");
+        fs::create_dir_all(&dir).unwrap();
+        let salt = || Some(Salt([1; 32]));
+        let key = || Some(MappingKey([2; 32]));
+
+        let pseudonyms = Pseudonyms::open(salt(), key(), &dir).unwrap();
+        let alice = pseudonyms.pseudonym("git|1|alice");
+        assert!(alice.starts_with(PREFIX));
+        assert_eq!(alice, pseudonyms.pseudonym("git|1|alice"));
+        assert_ne!(alice, pseudonyms.hash("git|2|bob"));
+        assert_ne!(
+            alice,
+            Pseudonyms::open(Some(Salt([3; 32])), None, &dir)
+                .unwrap()
+                .hash("git|1|alice")
+        );
+        let mapping = fs::read_to_string(dir.join(FILE)).unwrap();
+        assert_eq!(mapping.lines().count(), 1);
+        assert!(!mapping.contains("alice"));
+
+        // Reload from disk.
+        let pseudonyms = Pseudonyms::open(salt(), key(), &dir).unwrap();
+        assert_eq!(
+            pseudonyms.reveal(&alice).unwrap().as_deref(),
+            Some("git|1|alice")
+        );
+        assert_eq!(
+            pseudonyms.reveal(&pseudonyms.hash("git|2|bob")).unwrap(),
+            None
+        );
+        let wrong_key = Pseudonyms::open(salt(), Some(MappingKey([4; 32])), &dir).unwrap();
+        assert!(wrong_key.reveal(&alice).is_err());
+
+        assert_eq!(
+            Pseudonyms::disabled().pseudonym("git|1|alice"),
+            "git|1|alice"
+        );
+        assert!(Pseudonyms::open(None, key(), &dir).is_err());
+    }
+}
@@ -0,0 +1,1627 @@
+//! Participant queue and the active contribution slot.
+//!
+//! Participants join a queue, and the next participant may take the single
+//! active slot with `/contribution/start`, which locks the transcript for them
+//! until they complete, abort or miss the deadline. Aborts and missed
+//! deadlines count towards a ban, see [`crate::bans`].
+//!
+//! In [`QueueMode::Fifo`] the next participant is the one at the front. In
+//! [`QueueMode::Lottery`] the queue is a lobby instead: participants keep
+//! checking in with `/queue/checkin`, and once the slot is free the next
+//! participant is drawn uniformly at random among those who checked in within
+//! the check-in window. The draw stands until that participant starts, leaves
+//! or stops checking in.
+//!
+//! Either way, participants of higher priority tiers go first and reserved
+//! windows hold the slot for their tier, see [`crate::tiers`].
+//!
+//! Participants reaching the front and the active participant nearing their
+//! deadline are told through a [`Notifier`], see [`crate::notify`].
+//!
+//! Outside of the ceremony [`Schedule`] nobody can join or start either.
+//!
+//! When shutting down the queue is closed: nobody can join or start, but the
+//! active participant may still finish, see [`crate::shutdown`].
+//!
+//! Each held slot has a `contribution` span that lasts from start to
+//! completion, abort or eviction. The requests of the participant in between
+//! link to it, so exported traces show a contribution as one unit.
+//!
+//! With pipelining, a participant whose upload passed the quick checks hands
+//! the slot over before it is verified. The next participant takes the slot
+//! tentatively and may download the uploaded powers with
+//! `/contribution/pending` to build on. Once verification is done the pending
+//! contribution is confirmed, or rolled back: a tentative participant then
+//! loses the slot without an offense and goes back to the front of the queue.
+//! At most one contribution is pending at a time.
+
+use crate::{
+    analytics::{QueueAnalytics, Stage},
+    audit::{AuditEvent, AuditLog},
+    bans::{BanList, Offense},
+    ceremony::SharedCeremony,
+    challenge::{Challenge, ChallengeError, JoinGuard, JoinRequest},
+    events::{CeremonyEvent, EventBus},
+    notify::{Notification, Notifier},
+    policy::{PolicyEngine, PolicyError},
+    schedule::{Phase, Schedule},
+    session::Session,
+    status::ScheduleStatus,
+    tiers::{Tiers, DEFAULT_TIER},
+};
+use axum::{
+    body::{Bytes, Full},
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use eyre::{eyre, Error as EyreError};
+use rand::{rngs::StdRng, seq::SliceRandom};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tracing::{info, info_span, instrument, warn, Span};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum QueueError {
+    #[error("Identity is banned")]
+    Banned,
+    #[error("Already in the queue")]
+    AlreadyQueued,
+    #[error("Not in the queue")]
+    NotQueued,
+    #[error("Not the next participant in the queue")]
+    NotYourTurn,
+    #[error("The ceremony is paused")]
+    Paused,
+    #[error("The coordinator is shutting down")]
+    ShuttingDown,
+    #[error("The ceremony is not open")]
+    NotOpen(Schedule),
+    #[error("The slot is reserved for another tier")]
+    Reserved,
+    #[error("Another participant is contributing")]
+    SlotTaken,
+    #[error("Session does not hold the active contribution slot")]
+    NotActive,
+    #[error("No contribution is pending verification")]
+    NotPending,
+    #[error(transparent)]
+    Policy(#[from] PolicyError),
+    #[error(transparent)]
+    Challenge(#[from] ChallengeError),
+}
+
+#[derive(Debug)]
+pub struct Queue {
+    state:      Mutex<State>,
+    events:     EventBus,
+    analytics:  QueueAnalytics,
+    bans:       Arc<BanList>,
+    audit:      Arc<AuditLog>,
+    /// Time a participant has to upload after starting.
+    timeout:    Duration,
+    mode:       QueueMode,
+    /// Time a check-in keeps a participant in the lottery.
+    window:     Duration,
+    tiers:      Tiers,
+    /// Source of the lottery draws, the thread RNG if `None`.
+    rng:        Option<Mutex<StdRng>>,
+    schedule:   Schedule,
+    notifier:   Option<Arc<dyn Notifier>>,
+    /// Whether the slot is handed over before verification.
+    pipelining: bool,
+}
+
+/// How the next participant is picked.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum QueueMode {
+    /// In order of joining.
+    Fifo,
+    /// Uniformly at random among participants who recently checked in.
+    Lottery,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    waiting:  VecDeque<String>,
+    active:   Option<Slot>,
+    /// No new contributions are started while paused.
+    paused:   bool,
+    /// Nobody joins or starts once closed for shutdown.
+    closed:   bool,
+    /// Last check-in of the waiting participants.
+    checkins: HashMap<String, Instant>,
+    /// Winner of the current lottery draw.
+    drawn:    Option<String>,
+    /// Last participant told that it is their turn.
+    notified: Option<String>,
+    /// Contribution handed over before verification.
+    pending:  Option<Pending>,
+}
+
+#[derive(Debug)]
+struct Pending {
+    identity:          String,
+    /// Number of contributions including the pending one.
+    num_contributions: usize,
+    /// Powers of the transcript including the contribution, in the format of
+    /// `/ceremony/transcript?format=powers`.
+    powers:            Bytes,
+}
+
+#[derive(Debug)]
+struct Slot {
+    identity:  String,
+    started:   Instant,
+    /// Whether the participant was told that the deadline is near.
+    warned:    bool,
+    /// Closed when the slot is dropped.
+    span:      Span,
+    /// Started while a contribution was pending verification.
+    tentative: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Position {
+    /// Zero-based position in the queue.
+    pub position:     usize,
+    pub queue_length: usize,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AbortResponse {
+    /// Number of contributions this participant has aborted.
+    pub aborts: u32,
+    /// Absent if the participant is now banned.
+    pub rejoin: Option<Rejoin>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Checkin {
+    /// Whether the participant may now start contributing.
+    pub your_turn:  bool,
+    pub lobby_size: usize,
+}
+
+/// Priority tiers in `/ceremony/status`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TierStatus {
+    /// Tier of the active participant.
+    pub active:   Option<String>,
+    /// Tier with a reserved window now.
+    pub reserved: Option<String>,
+    /// Number of waiting participants by tier.
+    pub waiting:  BTreeMap<String, usize>,
+}
+
+/// Queue state for operators.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueStatus {
+    pub paused:  bool,
+    pub active:  Option<ActiveStatus>,
+    pub waiting: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActiveStatus {
+    pub identity:    String,
+    pub elapsed_sec: u64,
+}
+
+/// How an aborting participant can get back in line.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rejoin {
+    pub endpoint:     String,
+    /// Position the participant would get when rejoining now.
+    pub position:     usize,
+    pub queue_length: usize,
+}
+
+/// A contribution handed over with [`Queue::hand_over_guarded`], rolled back
+/// when dropped unless confirmed.
+#[must_use]
+pub struct HandOver<'a> {
+    queue:     &'a Queue,
+    identity:  String,
+    confirmed: bool,
+}
+
+impl HandOver<'_> {
+    /// The contribution was verified and applied.
+    pub fn confirm(mut self) {
+        self.queue.confirm(&self.identity);
+        self.confirmed = true;
+    }
+}
+
+impl Drop for HandOver<'_> {
+    fn drop(&mut self) {
+        if !self.confirmed {
+            self.queue.roll_back(&self.identity);
+        }
+    }
+}
+
+impl Queue {
+    #[must_use]
+    pub fn new(
+        events: EventBus,
+        analytics: QueueAnalytics,
+        bans: Arc<BanList>,
+        audit: Arc<AuditLog>,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            state: Mutex::new(State::default()),
+            events,
+            analytics,
+            bans,
+            audit,
+            timeout,
+            mode: QueueMode::Fifo,
+            window: Duration::MAX,
+            tiers: Tiers::default(),
+            rng: None,
+            schedule: Schedule::default(),
+            notifier: None,
+            pipelining: false,
+        }
+    }
+
+    #[must_use]
+    pub fn with_tiers(mut self, tiers: Tiers) -> Self {
+        self.tiers = tiers;
+        self
+    }
+
+    /// Pick the next participant according to `mode`. In lottery mode,
+    /// participants that did not check in within `window` are skipped.
+    #[must_use]
+    pub const fn with_mode(mut self, mode: QueueMode, window: Duration) -> Self {
+        self.mode = mode;
+        self.window = window;
+        self
+    }
+
+    /// Draw lottery winners from `rng` instead of the thread RNG, so draws
+    /// are reproducible.
+    #[must_use]
+    pub fn with_rng(mut self, rng: Option<StdRng>) -> Self {
+        self.rng = rng.map(Mutex::new);
+        self
+    }
+
+    /// Only accept participants while `schedule` is open.
+    #[must_use]
+    pub const fn with_schedule(mut self, schedule: Schedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    /// Tell participants when it is their turn or their deadline is near.
+    #[must_use]
+    pub fn with_notifier(mut self, notifier: Option<Arc<dyn Notifier>>) -> Self {
+        self.notifier = notifier;
+        self
+    }
+
+    /// Hand the slot to the next participant while an upload is verified.
+    #[must_use]
+    pub const fn with_pipelining(mut self, pipelining: bool) -> Self {
+        self.pipelining = pipelining;
+        self
+    }
+
+    /// Add `identity` to the back of the queue.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QueueError::Banned`], [`QueueError::AlreadyQueued`] or why the
+    /// queue is not open.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    pub fn join(&self, identity: &str) -> Result<Position, QueueError> {
+        if self.bans.is_banned(identity) {
+            return Err(QueueError::Banned);
+        }
+        self.ensure_open()?;
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(QueueError::ShuttingDown);
+        }
+        self.expire(&mut state);
+        if state.is_active(identity) || state.waiting.iter().any(|i| i == identity) {
+            return Err(QueueError::AlreadyQueued);
+        }
+        state.waiting.push_back(identity.to_string());
+        state.checkins.insert(identity.to_string(), Instant::now());
+        self.analytics.record(identity, Stage::Joined);
+        self.audit.record(AuditEvent::QueueJoined {
+            identity: identity.to_string(),
+            tier:     self.tier(identity),
+        });
+        let position = state.waiting.len() - 1;
+        if position == 0 {
+            self.promote(&mut state);
+        }
+        Ok(Position {
+            position,
+            queue_length: state.waiting.len(),
+        })
+    }
+
+    /// Remove `identity` from the queue.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QueueError::NotQueued`] if `identity` is not waiting.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    pub fn leave(&self, identity: &str) -> Result<(), QueueError> {
+        let mut state = self.state.lock().unwrap();
+        let position = state
+            .waiting
+            .iter()
+            .position(|i| i == identity)
+            .ok_or(QueueError::NotQueued)?;
+        state.waiting.remove(position);
+        state.forget(identity);
+        self.analytics.drop_out(identity);
+        self.audit.record(AuditEvent::QueueLeft {
+            identity: identity.to_string(),
+        });
+        if position == 0 {
+            self.promote(&mut state);
+        }
+        drop(state);
+        Ok(())
+    }
+
+    /// Mark `identity` as present in the lobby.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QueueError::NotQueued`] if `identity` is not waiting.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    pub fn checkin(&self, identity: &str) -> Result<Checkin, QueueError> {
+        let mut state = self.state.lock().unwrap();
+        self.expire(&mut state);
+        if !state.waiting.iter().any(|i| i == identity) {
+            return Err(QueueError::NotQueued);
+        }
+        state.checkins.insert(identity.to_string(), Instant::now());
+        let your_turn = !state.paused
+            && state.active.is_none()
+            && self.next(&mut state).as_deref() == Some(identity);
+        Ok(Checkin {
+            your_turn,
+            lobby_size: state.waiting.len(),
+        })
+    }
+
+    /// Give the active slot to `identity`, who must be next. Also counts as a
+    /// check-in.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QueueError::NotYourTurn`] if someone else is next, or why the
+    /// slot can not be handed out now.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    pub fn start(&self, identity: &str) -> Result<(), QueueError> {
+        self.start_at(identity, Instant::now())
+    }
+
+    fn start_at(&self, identity: &str, now: Instant) -> Result<(), QueueError> {
+        let mut state = self.state.lock().unwrap();
+        self.expire_at(&mut state, now);
+        let position = state
+            .waiting
+            .iter()
+            .position(|i| i == identity)
+            .ok_or(QueueError::NotQueued)?;
+        state.checkins.insert(identity.to_string(), now);
+        if state.closed {
+            return Err(QueueError::ShuttingDown);
+        }
+        if state.paused {
+            return Err(QueueError::Paused);
+        }
+        self.ensure_open()?;
+        if state.active.is_some() {
+            return Err(QueueError::SlotTaken);
+        }
+        if self.next(&mut state).as_deref() != Some(identity) {
+            if !Tiers::admits(self.tiers.reserved(), identity) {
+                return Err(QueueError::Reserved);
+            }
+            return Err(QueueError::NotYourTurn);
+        }
+        state.waiting.remove(position);
+        state.forget(identity);
+        let span = info_span!(parent: None, "contribution", identity = %identity);
+        span.follows_from(Span::current());
+        state.active = Some(Slot {
+            identity: identity.to_string(),
+            started: now,
+            warned: false,
+            span,
+            tentative: state.pending.is_some(),
+        });
+        self.analytics.record(identity, Stage::Started);
+        self.audit.record(AuditEvent::ContributionStarted {
+            identity: identity.to_string(),
+            tier:     self.tier(identity),
+        });
+        self.promote(&mut state);
+        drop(state);
+        Ok(())
+    }
+
+    /// Check that `identity` holds the active slot.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QueueError::NotActive`] if `identity` does not hold the slot.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    pub fn ensure_active(&self, identity: &str) -> Result<(), QueueError> {
+        let mut state = self.state.lock().unwrap();
+        self.expire(&mut state);
+        let slot = state
+            .active
+            .as_ref()
+            .filter(|slot| slot.identity == identity)
+            .ok_or(QueueError::NotActive)?;
+        Span::current().follows_from(&slot.span);
+        drop(state);
+        Ok(())
+    }
+
+    /// Release the active slot held by `identity` while their upload is
+    /// verified, leaving the powers including their contribution, number
+    /// `num_contributions`, for the next participant. Returns `false` without
+    /// calling `powers` if pipelining is disabled or another contribution is
+    /// pending.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    pub fn hand_over(
+        &self,
+        identity: &str,
+        num_contributions: usize,
+        powers: impl FnOnce() -> Bytes,
+    ) -> bool {
+        if !self.pipelining {
+            return false;
+        }
+        let state = self.state.lock().unwrap();
+        if state.pending.is_some() || !state.is_active(identity) {
+            return false;
+        }
+        // Build the powers outside the queue lock, the slot is ours meanwhile.
+        drop(state);
+        let powers = powers();
+        let mut state = self.state.lock().unwrap();
+        let slot = match state.release(identity) {
+            Ok(slot) => slot,
+            Err(_) => return false,
+        };
+        slot.span
+            .in_scope(|| info!(elapsed = ?slot.started.elapsed(), "Contribution slot handed over"));
+        state.pending = Some(Pending {
+            identity: identity.to_string(),
+            num_contributions,
+            powers,
+        });
+        self.promote(&mut state);
+        drop(state);
+        true
+    }
+
+    /// Same as [`Self::hand_over`], but the pending contribution is rolled
+    /// back when the returned guard is dropped without being confirmed, also
+    /// on an early return while it is verified.
+    ///
+    /// A panic only unwinds through the guard in test and dev builds. Release
+    /// builds abort on panic, and the interrupted contribution is resolved
+    /// from the write-ahead log on restart, see [`crate::wal`].
+    pub fn hand_over_guarded(
+        &self,
+        identity: &str,
+        num_contributions: usize,
+        powers: impl FnOnce() -> Bytes,
+    ) -> Option<HandOver<'_>> {
+        self.hand_over(identity, num_contributions, powers)
+            .then(|| HandOver {
+                queue:     self,
+                identity:  identity.to_string(),
+                confirmed: false,
+            })
+    }
+
+    /// The contribution handed over by `identity` was verified and applied.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    pub fn confirm(&self, identity: &str) {
+        let mut state = self.state.lock().unwrap();
+        if state.pending.as_ref().map(|p| p.identity.as_str()) != Some(identity) {
+            return;
+        }
+        state.pending = None;
+        if let Some(slot) = state.active.as_mut() {
+            slot.tentative = false;
+        }
+        drop(state);
+        info!(identity, "Pending contribution confirmed");
+    }
+
+    /// The contribution handed over by `identity` failed verification. A
+    /// participant building on it loses the slot and goes back to the front.
+    /// Returns their identity, if any.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    pub fn roll_back(&self, identity: &str) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        if state.pending.as_ref().map(|p| p.identity.as_str()) != Some(identity) {
+            return None;
+        }
+        state.pending = None;
+        warn!(identity, "Pending contribution rolled back");
+        if !state.active.as_ref().map_or(false, |slot| slot.tentative) {
+            return None;
+        }
+        let slot = state.active.take().unwrap();
+        slot.span
+            .in_scope(|| warn!(identity = %slot.identity, "Rolling back tentative slot"));
+        state.waiting.push_front(slot.identity.clone());
+        state.checkins.insert(slot.identity.clone(), Instant::now());
+        self.audit.record(AuditEvent::ParticipantEvicted {
+            identity: slot.identity.clone(),
+            reason:   "rollback".to_string(),
+        });
+        self.events.publish(CeremonyEvent::ParticipantEvicted {
+            reason: "rollback".to_string(),
+        });
+        self.promote(&mut state);
+        drop(state);
+        Some(slot.identity)
+    }
+
+    /// Powers of the pending contribution, for the tentative holder of the
+    /// active slot.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QueueError::NotActive`] if `identity` does not hold the slot
+    /// and [`QueueError::NotPending`] if no contribution is pending.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    pub fn pending_powers(&self, identity: &str) -> Result<Bytes, QueueError> {
+        let mut state = self.state.lock().unwrap();
+        self.expire(&mut state);
+        if !state.is_active(identity) {
+            return Err(QueueError::NotActive);
+        }
+        state
+            .pending
+            .as_ref()
+            .map(|pending| pending.powers.clone())
+            .ok_or(QueueError::NotPending)
+    }
+
+    /// Number of contributions including the one pending verification, if
+    /// any.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    pub fn pending_contributions(&self) -> Option<usize> {
+        let state = self.state.lock().unwrap();
+        state
+            .pending
+            .as_ref()
+            .map(|pending| pending.num_contributions)
+    }
+
+    /// Release the active slot held by `identity` after an upload.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QueueError::NotActive`] if `identity` does not hold the slot.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    pub fn finish(&self, identity: &str) -> Result<(), QueueError> {
+        let mut state = self.state.lock().unwrap();
+        let slot = state.release(identity)?;
+        drop(state);
+        Span::current().follows_from(&slot.span);
+        slot.span
+            .in_scope(|| info!(elapsed = ?slot.started.elapsed(), "Contribution slot released"));
+        Ok(())
+    }
+
+    /// Give up the active slot held by `identity`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QueueError::NotActive`] if `identity` does not hold the slot.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    pub fn abort(&self, identity: &str) -> Result<AbortResponse, QueueError> {
+        let mut state = self.state.lock().unwrap();
+        let slot = state.release(identity)?;
+        Span::current().follows_from(&slot.span);
+        slot.span
+            .in_scope(|| info!(elapsed = ?slot.started.elapsed(), "Contribution aborted"));
+        let record = self.bans.record(identity, Offense::Abort);
+        self.analytics.drop_out(identity);
+        self.audit.record(AuditEvent::ContributionAborted {
+            identity: identity.to_string(),
+        });
+        self.events.publish(CeremonyEvent::ParticipantEvicted {
+            reason: "aborted".to_string(),
+        });
+        // The front of the queue may now start.
+        let queue_length = state.waiting.len();
+        self.events
+            .publish(CeremonyEvent::QueueAdvanced { queue_length });
+        drop(state);
+        Ok(AbortResponse {
+            aborts: record.aborts,
+            rejoin: (!record.banned).then(|| Rejoin {
+                endpoint: "/queue/join".to_string(),
+                position: queue_length,
+                queue_length,
+            }),
+        })
+    }
+
+    /// Evict the active participant if they missed the deadline, or warn them
+    /// once if it is less than `warning` away.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    pub fn check_deadline(&self, warning: Duration) {
+        let mut state = self.state.lock().unwrap();
+        self.expire(&mut state);
+        let slot = match state.active.as_mut() {
+            Some(slot) => slot,
+            None => return,
+        };
+        let left = self.timeout.saturating_sub(slot.started.elapsed());
+        if slot.warned || left > warning {
+            return;
+        }
+        slot.warned = true;
+        slot.span
+            .in_scope(|| info!(identity = %slot.identity, ?left, "Contribution deadline near"));
+        if let Some(notifier) = &self.notifier {
+            notifier.notify(&Notification::DeadlineApproaching {
+                identity:     slot.identity.clone(),
+                seconds_left: left.as_secs(),
+            });
+        }
+        drop(state);
+    }
+
+    /// Stop or resume handing out the active slot.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    pub fn set_paused(&self, paused: bool) {
+        info!(paused, "Setting queue pause");
+        self.state.lock().unwrap().paused = paused;
+    }
+
+    /// Stop admitting joins and starts, for shutdown.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    pub fn close(&self) {
+        info!("Closing the queue");
+        self.state.lock().unwrap().closed = true;
+    }
+
+    /// Whether nobody holds the active slot and no contribution is pending,
+    /// evicting the active participant if they missed the deadline.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    pub fn is_idle(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        self.expire(&mut state);
+        state.active.is_none() && state.pending.is_none()
+    }
+
+    /// Identities waiting in the queue, in order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    pub fn waiting(&self) -> Vec<String> {
+        self.state.lock().unwrap().waiting.iter().cloned().collect()
+    }
+
+    #[must_use]
+    pub const fn analytics(&self) -> &QueueAnalytics {
+        &self.analytics
+    }
+
+    /// Number of identities waiting in the queue.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    pub fn queue_length(&self) -> usize {
+        self.state.lock().unwrap().waiting.len()
+    }
+
+    /// Put identities persisted on shutdown back in the queue, in order.
+    /// Banned identities and those already queued are skipped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    pub fn restore(&self, identities: &[String]) {
+        let mut state = self.state.lock().unwrap();
+        for identity in identities {
+            if self.bans.is_banned(identity)
+                || state.is_active(identity)
+                || state.waiting.contains(identity)
+            {
+                continue;
+            }
+            state.waiting.push_back(identity.clone());
+            state.checkins.insert(identity.clone(), Instant::now());
+        }
+        info!(waiting = state.waiting.len(), "Restored the queue");
+        self.promote(&mut state);
+    }
+
+    /// Remove the active participant without counting an offense. Returns
+    /// their identity, if any.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    pub fn evict(&self) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        let slot = state.active.take()?;
+        slot.span
+            .in_scope(|| warn!(identity = %slot.identity, "Evicting active participant"));
+        self.analytics.drop_out(&slot.identity);
+        self.audit.record(AuditEvent::ParticipantEvicted {
+            identity: slot.identity.clone(),
+            reason:   "operator".to_string(),
+        });
+        self.events.publish(CeremonyEvent::ParticipantEvicted {
+            reason: "operator".to_string(),
+        });
+        self.events.publish(CeremonyEvent::QueueAdvanced {
+            queue_length: state.waiting.len(),
+        });
+        drop(state);
+        Some(slot.identity)
+    }
+
+    /// Whether the queue is paused, the active participant and who is waiting.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    #[must_use]
+    pub fn status(&self) -> QueueStatus {
+        let state = self.state.lock().unwrap();
+        QueueStatus {
+            paused:  state.paused,
+            active:  state.active.as_ref().map(|slot| ActiveStatus {
+                identity:    slot.identity.clone(),
+                elapsed_sec: slot.started.elapsed().as_secs(),
+            }),
+            waiting: state.waiting.iter().cloned().collect(),
+        }
+    }
+
+    /// The schedule and current phase, if the ceremony is scheduled.
+    #[must_use]
+    pub fn schedule_status(&self) -> Option<ScheduleStatus> {
+        (!self.schedule.is_unbounded()).then(|| ScheduleStatus {
+            schedule: self.schedule,
+            phase:    self.schedule.phase(),
+        })
+    }
+
+    /// Tiers of the active and waiting participants, if tiers are configured.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the queue lock is poisoned.
+    #[must_use]
+    pub fn tier_status(&self) -> Option<TierStatus> {
+        if self.tiers.is_empty() {
+            return None;
+        }
+        let state = self.state.lock().unwrap();
+        let mut waiting = BTreeMap::new();
+        waiting.insert(DEFAULT_TIER.to_string(), 0);
+        for identity in &state.waiting {
+            *waiting
+                .entry(self.tiers.name(identity).to_string())
+                .or_insert(0) += 1;
+        }
+        Some(TierStatus {
+            active: state
+                .active
+                .as_ref()
+                .map(|slot| self.tiers.name(&slot.identity).to_string()),
+            reserved: self.tiers.reserved().map(|tier| tier.name.clone()),
+            waiting,
+        })
+    }
+
+    /// Tier of `identity` for the audit log, if tiers are configured.
+    fn tier(&self, identity: &str) -> Option<String> {
+        (!self.tiers.is_empty()).then(|| self.tiers.name(identity).to_string())
+    }
+
+    /// Tell `identity` it is their turn, unless they were told already.
+    fn notify_turn(&self, state: &mut State, identity: String) {
+        if state.notified.as_ref() == Some(&identity) {
+            return;
+        }
+        if let Some(notifier) = &self.notifier {
+            notifier.notify(&Notification::Turn {
+                identity: identity.clone(),
+            });
+        }
+        state.notified = Some(identity);
+    }
+
+    fn ensure_open(&self) -> Result<(), QueueError> {
+        if self.schedule.phase() == Phase::Open {
+            Ok(())
+        } else {
+            Err(QueueError::NotOpen(self.schedule))
+        }
+    }
+
+    /// Evict the active participant if they missed the deadline.
+    fn expire(&self, state: &mut State) {
+        self.expire_at(state, Instant::now());
+    }
+
+    fn expire_at(&self, state: &mut State, now: Instant) {
+        let expired = state.active.as_ref().map_or(false, |slot| {
+            now.saturating_duration_since(slot.started) > self.timeout
+        });
+        if !expired {
+            return;
+        }
+        let slot = state.active.take().unwrap();
+        slot.span
+            .in_scope(|| warn!(identity = %slot.identity, "Contribution deadline missed"));
+        self.bans.record(&slot.identity, Offense::DeadlineMiss);
+        self.analytics.drop_out(&slot.identity);
+        self.audit.record(AuditEvent::ParticipantEvicted {
+            identity: slot.identity,
+            reason:   "deadline".to_string(),
+        });
+        self.events.publish(CeremonyEvent::ParticipantEvicted {
+            reason: "deadline".to_string(),
+        });
+        self.events.publish(CeremonyEvent::QueueAdvanced {
+            queue_length: state.waiting.len(),
+        });
+    }
+
+    /// The participant who may take the free slot, drawing a new one in
+    /// lottery mode if needed.
+    fn next(&self, state: &mut State) -> Option<String> {
+        let eligible = self.eligible(state);
+        if self.mode == QueueMode::Fifo {
+            return eligible.first().map(|i| (*i).clone());
+        }
+        if let Some(drawn) = &state.drawn {
+            if eligible.contains(&drawn) {
+                return Some(drawn.clone());
+            }
+        }
+        let drawn = self
+            .rng
+            .as_ref()
+            .map_or_else(
+                || eligible.choose(&mut rand::thread_rng()),
+                |rng| eligible.choose(&mut *rng.lock().unwrap()),
+            )
+            .map(|i| (*i).clone());
+        if let Some(drawn) = &drawn {
+            info!(identity = %drawn, eligible = eligible.len(), "Drew next participant");
+            self.analytics.record(drawn, Stage::ReachedFront);
+            self.notify_turn(state, drawn.clone());
+        }
+        state.drawn.clone_from(&drawn);
+        drawn
+    }
+
+    /// Waiting participants that may be picked next: those of the highest
+    /// priority among the ones admitted by a reserved window, and in lottery
+    /// mode also live.
+    fn eligible<'a>(&self, state: &'a State) -> Vec<&'a String> {
+        let reserved = self.tiers.reserved();
+        let admitted = state
+            .waiting
+            .iter()
+            .filter(|i| Tiers::admits(reserved, i))
+            .filter(|i| self.mode == QueueMode::Fifo || state.is_live(i, self.window))
+            .collect::<Vec<_>>();
+        let top = admitted.iter().map(|i| self.tiers.priority(i)).max();
+        admitted
+            .into_iter()
+            .filter(|i| Some(self.tiers.priority(i)) == top)
+            .collect()
+    }
+
+    /// Announce the new front of the queue. In lottery mode there is no front
+    /// until the next draw.
+    fn promote(&self, state: &mut State) {
+        if self.mode == QueueMode::Fifo {
+            if let Some(front) = self.eligible(state).first().map(|i| (*i).clone()) {
+                self.analytics.record(&front, Stage::ReachedFront);
+                self.notify_turn(state, front);
+            }
+        }
+        self.events.publish(CeremonyEvent::QueueAdvanced {
+            queue_length: state.waiting.len(),
+        });
+    }
+}
+
+impl State {
+    fn is_active(&self, identity: &str) -> bool {
+        self.active
+            .as_ref()
+            .map_or(false, |slot| slot.identity == identity)
+    }
+
+    fn is_live(&self, identity: &str, window: Duration) -> bool {
+        self.checkins
+            .get(identity)
+            .map_or(false, |checkin| checkin.elapsed() <= window)
+    }
+
+    /// Drop the lobby state of a participant that left the queue.
+    fn forget(&mut self, identity: &str) {
+        self.checkins.remove(identity);
+        if self.drawn.as_deref() == Some(identity) {
+            self.drawn = None;
+        }
+    }
+
+    fn release(&mut self, identity: &str) -> Result<Slot, QueueError> {
+        if !self.is_active(identity) {
+            return Err(QueueError::NotActive);
+        }
+        Ok(self.active.take().unwrap())
+    }
+}
+
+impl fmt::Display for QueueMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Fifo => "fifo",
+            Self::Lottery => "lottery",
+        })
+    }
+}
+
+impl FromStr for QueueMode {
+    type Err = EyreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fifo" => Ok(Self::Fifo),
+            "lottery" => Ok(Self::Lottery),
+            _ => Err(eyre!("Queue mode must be fifo or lottery")),
+        }
+    }
+}
+
+impl QueueError {
+    /// Stable error code for clients.
+    #[must_use]
+    pub const fn code(&self) -> &'static str {
+        match self {
+            Self::Banned => "banned",
+            Self::AlreadyQueued => "already_queued",
+            Self::NotQueued => "not_queued",
+            Self::NotYourTurn => "not_your_turn",
+            Self::Paused => "paused",
+            Self::ShuttingDown => "shutting_down",
+            Self::NotOpen(_) => "not_open",
+            Self::Reserved => "reserved",
+            Self::SlotTaken => "slot_taken",
+            Self::NotActive => "not_active",
+            Self::NotPending => "not_pending",
+            Self::Policy(error) => error.code(),
+            Self::Challenge(error) => error.code(),
+        }
+    }
+}
+
+impl IntoResponse for QueueError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::Paused | Self::ShuttingDown | Self::NotOpen(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::NotPending => StatusCode::NOT_FOUND,
+            Self::Banned | Self::NotActive | Self::Policy(_) | Self::Challenge(_) => {
+                StatusCode::FORBIDDEN
+            }
+            _ => StatusCode::CONFLICT,
+        };
+        let mut body = json!({ "error": self.to_string(), "code": self.code() });
+        if let Self::NotOpen(schedule) = self {
+            body["schedule"] = json!(schedule);
+            body["phase"] = json!(schedule.phase());
+        }
+        (status, Json(body)).into_response()
+    }
+}
+
+/// Join the back of the queue, if the policy and join challenges admit us.
+///
+/// # Errors
+///
+/// Returns a [`QueueError`] if the session is not admitted.
+#[instrument(level = "info", skip_all, fields(identity = %session.pseudonym))]
+pub async fn join(
+    session: Session,
+    Extension(queue): Extension<Arc<Queue>>,
+    Extension(policy): Extension<Arc<PolicyEngine>>,
+    Extension(guard): Extension<Arc<JoinGuard>>,
+    request: Option<Json<JoinRequest>>,
+) -> Result<Json<Position>, QueueError> {
+    policy.check(&session)?;
+    let request = request.map(|Json(request)| request).unwrap_or_default();
+    guard.check(&session.pseudonym, &request).await?;
+    queue.join(&session.pseudonym).map(Json)
+}
+
+/// Issue a proof of work challenge for joining the queue.
+///
+/// # Errors
+///
+/// Returns `404 Not Found` if no proof of work is required.
+#[instrument(level = "info", skip_all, fields(identity = %session.pseudonym))]
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn challenge(
+    session: Session,
+    Extension(queue): Extension<Arc<Queue>>,
+    Extension(guard): Extension<Arc<JoinGuard>>,
+) -> Result<Json<Challenge>, StatusCode> {
+    guard
+        .challenge(&session.pseudonym, queue.queue_length())
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Keep a place in the lobby and learn whether it is our turn.
+///
+/// # Errors
+///
+/// See [`Queue::checkin`].
+#[instrument(level = "info", skip_all, fields(identity = %session.pseudonym))]
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn checkin(
+    session: Session,
+    Extension(queue): Extension<Arc<Queue>>,
+) -> Result<Json<Checkin>, QueueError> {
+    queue.checkin(&session.pseudonym).map(Json)
+}
+
+/// Leave the queue.
+///
+/// # Errors
+///
+/// See [`Queue::leave`].
+#[instrument(level = "info", skip_all, fields(identity = %session.pseudonym))]
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn leave(
+    session: Session,
+    Extension(queue): Extension<Arc<Queue>>,
+) -> Result<Json<serde_json::Value>, QueueError> {
+    queue.leave(&session.pseudonym)?;
+    Ok(Json(json!({})))
+}
+
+/// Take the active slot and return the number of contributions to build on.
+///
+/// # Errors
+///
+/// See [`Queue::start`].
+#[instrument(level = "info", skip_all, fields(identity = %session.pseudonym))]
+pub async fn start(
+    session: Session,
+    Extension(queue): Extension<Arc<Queue>>,
+    Extension(ceremony): Extension<SharedCeremony>,
+) -> Result<Json<serde_json::Value>, QueueError> {
+    queue.start(&session.pseudonym)?;
+    // The ceremony is locked while the pending contribution is verified.
+    let (num_contributions, pending) = match queue.pending_contributions() {
+        Some(num_contributions) => (num_contributions, true),
+        None => (ceremony.lock().await.num_contributions(), false),
+    };
+    Ok(Json(json!({
+        "numContributions": num_contributions,
+        "pending": pending,
+    })))
+}
+
+/// Download the powers of the contribution pending verification, to build on
+/// while holding the slot tentatively.
+///
+/// # Errors
+///
+/// See [`Queue::pending_powers`].
+#[instrument(level = "info", skip_all, fields(identity = %session.pseudonym))]
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn pending(
+    session: Session,
+    Extension(queue): Extension<Arc<Queue>>,
+) -> Result<Response, QueueError> {
+    let powers = queue.pending_powers(&session.pseudonym)?;
+    Ok(([(CONTENT_TYPE, "application/json")], Full::from(powers)).into_response())
+}
+
+/// Give up the active slot and let the next participant contribute.
+///
+/// # Errors
+///
+/// See [`Queue::abort`].
+#[instrument(level = "info", skip_all, fields(identity = %session.pseudonym))]
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn abort(
+    session: Session,
+    Extension(queue): Extension<Arc<Queue>>,
+) -> Result<Json<AbortResponse>, QueueError> {
+    queue.abort(&session.pseudonym).map(Json)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{bans::Thresholds, test::TestDir};
+    use rand::SeedableRng;
+    use std::{fs, panic::AssertUnwindSafe, thread::sleep};
+
+    fn queue(dir: &std::path::Path, events: EventBus, timeout: Duration) -> Queue {
+        fs::create_dir_all(dir).unwrap();
+        let thresholds = Thresholds {
+            aborts:                2,
+            invalid_contributions: 2,
+            deadline_misses:       2,
+        };
+        Queue::new(
+            events,
+            QueueAnalytics::open(dir).unwrap(),
+            Arc::new(BanList::open(dir, thresholds).unwrap()),
+            Arc::new(AuditLog::open(dir).unwrap()),
+            timeout,
+        )
+    }
+
+    #[test]
+    fn test_abort() {
+        let dir = TestDir::new("queue");
+        let events = EventBus::new();
+        let mut receiver = events.subscribe();
+        let queue = queue(&dir, events, Duration::from_secs(60));
+
+        queue.join("a").unwrap();
+        assert_eq!(queue.join("b").unwrap().position, 1);
+        assert_eq!(queue.join("a"), Err(QueueError::AlreadyQueued));
+        assert_eq!(queue.start("b"), Err(QueueError::NotYourTurn));
+        queue.start("a").unwrap();
+        assert_eq!(queue.abort("b"), Err(QueueError::NotActive));
+
+        let response = queue.abort("a").unwrap();
+        assert_eq!(response.aborts, 1);
+        assert_eq!(response.rejoin.unwrap().position, 1);
+        assert_eq!(queue.abort("a"), Err(QueueError::NotActive));
+
+        // The slot is free for the next participant.
+        queue.start("b").unwrap();
+        queue.ensure_active("b").unwrap();
+        queue.finish("b").unwrap();
+        assert_eq!(queue.ensure_active("b"), Err(QueueError::NotActive));
+
+        let mut evicted = 0;
+        while let Ok(event) = receiver.try_recv() {
+            if matches!(event, CeremonyEvent::ParticipantEvicted { .. }) {
+                evicted += 1;
+            }
+        }
+        assert_eq!(evicted, 1);
+
+        // A second abort results in a ban.
+        queue.join("a").unwrap();
+        queue.start("a").unwrap();
+        assert_eq!(queue.abort("a").unwrap().rejoin, None);
+        assert_eq!(queue.join("a"), Err(QueueError::Banned));
+    }
+
+    #[test]
+    fn test_deadline() {
+        let dir = TestDir::new("queue-deadline");
+        let queue = queue(&dir, EventBus::new(), Duration::from_secs(60));
+        let now = Instant::now();
+        queue.join("a").unwrap();
+        queue.join("b").unwrap();
+        queue.start_at("a", now).unwrap();
+        let before = now + Duration::from_secs(60);
+        assert_eq!(queue.start_at("b", before), Err(QueueError::SlotTaken));
+        queue
+            .start_at("b", before + Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(queue.ensure_active("a"), Err(QueueError::NotActive));
+        assert_eq!(queue.bans.banned().len(), 0);
+        let evicted = queue
+            .audit
+            .entries(0)
+            .unwrap()
+            .into_iter()
+            .filter(|entry| matches!(&entry.event, AuditEvent::ParticipantEvicted { reason, .. } if reason == "deadline"))
+            .count();
+        assert_eq!(evicted, 1);
+    }
+
+    #[test]
+    fn test_close() {
+        let dir = TestDir::new("queue-close");
+        let queue = queue(&dir, EventBus::new(), Duration::from_millis(50));
+        queue.join("a").unwrap();
+        queue.join("b").unwrap();
+        queue.start("a").unwrap();
+        queue.close();
+        assert_eq!(queue.join("c"), Err(QueueError::ShuttingDown));
+        assert!(!queue.is_idle());
+        queue.finish("a").unwrap();
+        assert!(queue.is_idle());
+        assert_eq!(queue.start("b"), Err(QueueError::ShuttingDown));
+        assert_eq!(queue.waiting(), vec!["b".to_string()]);
+
+        let restarted = self::queue(&dir, EventBus::new(), Duration::from_secs(60));
+        restarted.join("c").unwrap();
+        restarted.restore(&["b".to_string(), "c".to_string(), "d".to_string()]);
+        assert_eq!(restarted.waiting(), vec!["c", "b", "d"]);
+    }
+
+    #[test]
+    fn test_pause_evict() {
+        let dir = TestDir::new("queue-admin");
+        let queue = queue(&dir, EventBus::new(), Duration::from_secs(60));
+        queue.join("a").unwrap();
+        queue.join("b").unwrap();
+        queue.set_paused(true);
+        assert_eq!(queue.start("a"), Err(QueueError::Paused));
+        queue.set_paused(false);
+        queue.start("a").unwrap();
+
+        let status = queue.status();
+        assert_eq!(status.active.unwrap().identity, "a");
+        assert_eq!(status.waiting, vec!["b".to_string()]);
+        assert_eq!(queue.evict(), Some("a".to_string()));
+        assert_eq!(queue.evict(), None);
+        queue.start("b").unwrap();
+        assert!(!queue.bans.is_banned("a"));
+    }
+
+    #[test]
+    fn test_pipelining() {
+        let dir = TestDir::new("queue-pipeline");
+        let queue = queue(&dir, EventBus::new(), Duration::from_secs(60)).with_pipelining(true);
+        let powers = || Bytes::from_static(b"{}");
+        queue.join("a").unwrap();
+        queue.join("b").unwrap();
+        queue.join("c").unwrap();
+        assert!(!queue.hand_over("b", 1, powers));
+        queue.start("a").unwrap();
+        assert_eq!(queue.pending_powers("a"), Err(QueueError::NotPending));
+
+        // The next participant builds on the pending contribution.
+        assert!(queue.hand_over("a", 1, powers));
+        assert!(!queue.is_idle());
+        assert_eq!(queue.pending_contributions(), Some(1));
+        queue.start("b").unwrap();
+        assert_eq!(queue.pending_powers("b"), Ok(powers()));
+        assert!(!queue.hand_over("b", 2, powers));
+        queue.confirm("a");
+        assert_eq!(queue.pending_contributions(), None);
+        assert_eq!(queue.pending_powers("b"), Err(QueueError::NotPending));
+
+        // A confirmed slot survives a later roll back.
+        assert!(queue.hand_over("b", 2, powers));
+        assert_eq!(queue.roll_back("b"), None);
+        queue.start("c").unwrap();
+        assert_eq!(queue.roll_back("b"), None);
+        queue.finish("c").unwrap();
+
+        // A tentative slot is rolled back without an offense.
+        queue.join("a").unwrap();
+        queue.join("b").unwrap();
+        queue.start("a").unwrap();
+        assert!(queue.hand_over("a", 3, powers));
+        queue.start("b").unwrap();
+        assert_eq!(queue.roll_back("a"), Some("b".to_string()));
+        assert_eq!(queue.ensure_active("b"), Err(QueueError::NotActive));
+        assert_eq!(queue.waiting(), vec!["b".to_string()]);
+        assert!(queue.is_idle());
+        assert!(!queue.bans.is_banned("b"));
+        queue.start("b").unwrap();
+    }
+
+    #[test]
+    fn test_hand_over_guard() {
+        let dir = TestDir::new("queue-guard");
+        let queue = queue(&dir, EventBus::new(), Duration::from_secs(60)).with_pipelining(true);
+        let powers = || Bytes::from_static(b"{}");
+        queue.join("a").unwrap();
+        queue.join("b").unwrap();
+        queue.start("a").unwrap();
+
+        // Applying the contribution fails after the hand over, so the guard is
+        // dropped unconfirmed.
+        let guard = queue.hand_over_guarded("a", 1, powers).unwrap();
+        queue.start("b").unwrap();
+        drop(guard);
+        assert_eq!(queue.pending_contributions(), None);
+        assert_eq!(queue.waiting(), vec!["b".to_string()]);
+        assert!(!queue.bans.is_banned("b"));
+
+        // So does a panic.
+        queue.start("b").unwrap();
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            let _guard = queue.hand_over_guarded("b", 1, powers).unwrap();
+            queue.join("a").unwrap();
+            queue.start("a").unwrap();
+            panic!("verification failed");
+        }));
+        assert!(result.is_err());
+        assert_eq!(queue.waiting(), vec!["a".to_string()]);
+        assert!(!queue.bans.is_banned("a"));
+
+        // A confirmed hand over stands.
+        queue.start("a").unwrap();
+        queue.hand_over_guarded("a", 1, powers).unwrap().confirm();
+        assert_eq!(queue.pending_contributions(), None);
+        assert!(queue.is_idle());
+    }
+
+    #[test]
+    fn test_lottery() {
+        let dir = TestDir::new("queue-lottery");
+        let queue = queue(&dir, EventBus::new(), Duration::from_secs(60))
+            .with_mode(QueueMode::Lottery, Duration::from_millis(50));
+        queue.join("a").unwrap();
+        queue.join("b").unwrap();
+        sleep(Duration::from_millis(60));
+
+        // Only live check-ins take part in the draw.
+        assert!(queue.checkin("b").unwrap().your_turn);
+        assert!(!queue.checkin("a").unwrap().your_turn);
+        assert_eq!(queue.start("a"), Err(QueueError::NotYourTurn));
+        assert_eq!(queue.checkin("c"), Err(QueueError::NotQueued));
+        queue.start("b").unwrap();
+        assert_eq!(queue.checkin("b"), Err(QueueError::NotQueued));
+        queue.finish("b").unwrap();
+        queue.start("a").unwrap();
+        queue.finish("a").unwrap();
+
+        // Either participant can win a draw.
+        let mut wins = HashMap::new();
+        for _ in 0..64 {
+            queue.join("x").unwrap();
+            queue.join("y").unwrap();
+            let (winner, loser) = if queue.start("x").is_ok() {
+                ("x", "y")
+            } else {
+                queue.start("y").unwrap();
+                ("y", "x")
+            };
+            queue.finish(winner).unwrap();
+            queue.leave(loser).unwrap();
+            *wins.entry(winner).or_insert(0) += 1;
+        }
+        assert_eq!(wins.len(), 2);
+    }
+
+    #[test]
+    fn test_lottery_rng() {
+        let draws = |run: usize| {
+            let dir = TestDir::new(&format!("queue-lottery-rng-{run}"));
+            let queue = queue(&dir, EventBus::new(), Duration::from_secs(60))
+                .with_mode(QueueMode::Lottery, Duration::from_secs(60))
+                .with_rng(Some(StdRng::seed_from_u64(42)));
+            let mut winners = Vec::new();
+            for _ in 0..16 {
+                for identity in ["x", "y", "z"] {
+                    queue.join(identity).unwrap();
+                }
+                let winner = ["x", "y", "z"]
+                    .into_iter()
+                    .find(|identity| queue.start(identity).is_ok())
+                    .unwrap();
+                queue.finish(winner).unwrap();
+                for identity in ["x", "y", "z"] {
+                    if identity != winner {
+                        queue.leave(identity).unwrap();
+                    }
+                }
+                winners.push(winner);
+            }
+            winners
+        };
+        // Same seed, same draws.
+        assert_eq!(draws(0), draws(1));
+    }
+
+    #[test]
+    fn test_tiers() {
+        let dir = TestDir::new("queue-tiers");
+        let tiers: Tiers = serde_json::from_str(
+            r#"[
+                { "name": "ecosystem", "priority": 1, "identities": ["c"] },
+                { "name": "airgapped", "identities": ["d"] }
+            ]"#,
+        )
+        .unwrap();
+        let queue = queue(&dir, EventBus::new(), Duration::from_secs(60)).with_tiers(tiers);
+        queue.join("a").unwrap();
+        queue.join("b").unwrap();
+        queue.join("c").unwrap();
+
+        // Higher priorities go first, in order of joining within a tier.
+        assert_eq!(queue.start("a"), Err(QueueError::NotYourTurn));
+        queue.start("c").unwrap();
+        let status = queue.tier_status().unwrap();
+        assert_eq!(status.active.as_deref(), Some("ecosystem"));
+        assert_eq!(status.reserved, None);
+        assert_eq!(status.waiting[DEFAULT_TIER], 2);
+        queue.finish("c").unwrap();
+        queue.start("a").unwrap();
+        queue.finish("a").unwrap();
+
+        let started = queue
+            .audit
+            .entries(0)
+            .unwrap()
+            .into_iter()
+            .filter_map(|entry| match entry.event {
+                AuditEvent::ContributionStarted { tier, .. } => tier,
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(started, vec!["ecosystem", DEFAULT_TIER]);
+
+        // A reserved window holds the slot for its tier.
+        let tiers: Tiers = serde_json::from_str(
+            r#"[{ "name": "airgapped", "identities": ["d"], "reserved": [{ "start": 0, "end": 18446744073709551615 }] }]"#,
+        )
+        .unwrap();
+        let queue = Queue { tiers, ..queue };
+        assert_eq!(queue.start("b"), Err(QueueError::Reserved));
+        queue.join("d").unwrap();
+        assert_eq!(
+            queue.tier_status().unwrap().reserved.as_deref(),
+            Some("airgapped")
+        );
+        queue.start("d").unwrap();
+    }
+
+    #[test]
+    fn test_schedule() {
+        let dir = TestDir::new("queue-schedule");
+        let pending = Schedule {
+            opens_at: Some(u64::MAX),
+            ..Schedule::default()
+        };
+        let queue = queue(&dir, EventBus::new(), Duration::from_secs(60)).with_schedule(pending);
+        assert_eq!(queue.join("a"), Err(QueueError::NotOpen(pending)));
+        assert_eq!(QueueError::NotOpen(pending).code(), "not_open");
+
+        // Participants already waiting keep their place but can not start.
+        let closed = Schedule {
+            closes_at: Some(1),
+            ..Schedule::default()
+        };
+        let queue = self::queue(&dir, EventBus::new(), Duration::from_secs(60));
+        queue.join("a").unwrap();
+        let queue = Queue {
+            schedule: closed,
+            ..queue
+        };
+        assert_eq!(queue.start("a"), Err(QueueError::NotOpen(closed)));
+        assert_eq!(queue.waiting(), vec!["a".to_string()]);
+    }
+
+    #[derive(Debug, Default)]
+    struct Recorder(Mutex<Vec<Notification>>);
+
+    impl Notifier for Recorder {
+        fn notify(&self, notification: &Notification) {
+            self.0.lock().unwrap().push(notification.clone());
+        }
+    }
+
+    #[test]
+    fn test_notify() {
+        let dir = TestDir::new("queue-notify");
+        let recorder = Arc::new(Recorder::default());
+        let queue = queue(&dir, EventBus::new(), Duration::from_millis(100))
+            .with_notifier(Some(recorder.clone()));
+        queue.join("a").unwrap();
+        queue.join("b").unwrap();
+        queue.start("a").unwrap();
+        queue.check_deadline(Duration::from_millis(10));
+        queue.check_deadline(Duration::from_secs(1));
+        queue.check_deadline(Duration::from_secs(1));
+        let turn = |identity: &str| Notification::Turn {
+            identity: identity.to_string(),
+        };
+        assert_eq!(*recorder.0.lock().unwrap(), vec![
+            turn("a"),
+            turn("b"),
+            Notification::DeadlineApproaching {
+                identity:     "a".to_string(),
+                seconds_left: 0,
+            }
+        ]);
+
+        // The deadline check evicts without waiting for a request.
+        sleep(Duration::from_millis(150));
+        queue.check_deadline(Duration::from_secs(1));
+        assert!(queue.is_idle());
+        assert_eq!(recorder.0.lock().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,344 @@
+//! Contribution receipts.
+//!
+//! After accepting a contribution the coordinator hands the participant a
+//! signed receipt. It commits to the participant's identity, their position in
+//! the ceremony, the transcript hashes before and after and the hash of the
+//! uploaded contribution, so the participant can later prove their
+//! contribution was included. With pseudonymization the receipt commits to
+//! the pseudonym instead, see [`crate::pseudonym`].
+//!
+//! Receipts name the coordinator key that signed them, so they can be checked
+//! against the published keys after a rotation, see
+//! [`crate::signing::CoordinatorKeys`].
+//!
+//! Issued receipts are appended to `receipts.jsonl` in the transcript
+//! directory. A client that retries an upload after losing the response gets
+//! the original receipt from the [`ReceiptLog`] instead of an error.
+
+use crate::{
+    commitment::{CommitmentError, CommitmentSigner},
+    signing::{self, Address, CoordinatorKey, SignatureError},
+};
+use eyre::{Result as EyreResult, WrapErr};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use thiserror::Error;
+use tracing::{error, info};
+
+const LOG_FILE: &str = "receipts.jsonl";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum ReceiptError {
+    #[error("Invalid receipt signature: {0}")]
+    InvalidSignature(#[from] SignatureError),
+    #[error("Malformed receipt")]
+    Malformed,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Receipt {
+    pub identity:          String,
+    /// Number of contributions in the transcript, including this one.
+    pub index:             usize,
+    pub transcript_before: String,
+    pub transcript_after:  String,
+    /// SHA-256 of the uploaded contribution JSON.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload_hash:       Option<String>,
+}
+
+/// A receipt together with the coordinator's signature.
+///
+/// The receipt is kept as the exact JSON string that was signed.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedReceipt {
+    pub receipt:    String,
+    pub signature:  String,
+    /// Address of the signing coordinator key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id:     Option<String>,
+    /// Optional post-quantum signature over the same receipt string.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commitment: Option<Commitment>,
+}
+
+/// A hash-based signature, see [`crate::commitment`].
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Commitment {
+    pub scheme:     String,
+    pub public_key: String,
+    pub signature:  String,
+}
+
+/// Sign `receipt` with `key`.
+///
+/// # Panics
+///
+/// Panics if the receipt fails to serialize, which it does not.
+#[must_use]
+pub fn sign(key: &CoordinatorKey, receipt: &Receipt) -> SignedReceipt {
+    let receipt = serde_json::to_string(receipt).expect("Receipt serializes");
+    let signature = key.sign(receipt.as_bytes());
+    SignedReceipt {
+        receipt,
+        signature,
+        key_id: Some(key.address().to_string()),
+        commitment: None,
+    }
+}
+
+/// Add a post-quantum commitment to `signed`, using one-time key `index` of
+/// `key`.
+///
+/// # Errors
+///
+/// Returns [`CommitmentError::IndexOutOfRange`] if `key` has no one-time key
+/// `index`.
+pub fn commit(
+    key: &dyn CommitmentSigner,
+    index: usize,
+    signed: &mut SignedReceipt,
+) -> Result<(), CommitmentError> {
+    signed.commitment = Some(Commitment {
+        scheme:     key.scheme().to_string(),
+        public_key: key.public_key(),
+        signature:  key.sign(index, signed.receipt.as_bytes())?,
+    });
+    Ok(())
+}
+
+/// Receipts issued so far, by identity and upload hash.
+///
+/// Receipts with commitments are large, so only the offsets into the file are
+/// kept in memory.
+#[derive(Debug)]
+pub struct ReceiptLog {
+    path:  PathBuf,
+    state: Mutex<LogState>,
+}
+
+#[derive(Debug)]
+struct LogState {
+    file:    File,
+    /// Upload hash and offset of the latest receipt of each identity.
+    offsets: HashMap<String, ([u8; 32], u64)>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LogEntry {
+    identity:    String,
+    upload_hash: String,
+    receipt:     SignedReceipt,
+}
+
+impl ReceiptLog {
+    /// Open the log in `dir` for appending.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the log can not be opened or has a malformed entry.
+    pub fn open(dir: &Path) -> EyreResult<Self> {
+        let path = dir.join(LOG_FILE);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)
+            .wrap_err_with(|| format!("Could not open receipt log {}", path.display()))?;
+        let mut offsets = HashMap::new();
+        let mut offset = 0;
+        for line in BufReader::new(&mut file).lines() {
+            let line = line?;
+            let entry: LogEntry = serde_json::from_str(&line)
+                .wrap_err_with(|| format!("Invalid receipt log {}", path.display()))?;
+            let mut hash = [0; 32];
+            hex::decode_to_slice(&entry.upload_hash[2..], &mut hash)?;
+            offsets.insert(entry.identity, (hash, offset));
+            offset += line.len() as u64 + 1;
+        }
+        info!(receipts = offsets.len(), "Loaded receipt log");
+        Ok(Self {
+            path,
+            state: Mutex::new(LogState { file, offsets }),
+        })
+    }
+
+    /// Append the receipt issued to `identity` for the upload with
+    /// `upload_hash`.
+    ///
+    /// The receipt is still handed out if it can not be written, the failure
+    /// is logged as an error.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the log lock is poisoned.
+    pub fn record(&self, identity: &str, upload_hash: &[u8; 32], receipt: &SignedReceipt) {
+        let entry = LogEntry {
+            identity:    identity.to_string(),
+            upload_hash: format!("0x{}", hex::encode(upload_hash)),
+            receipt:     receipt.clone(),
+        };
+        let mut state = self.state.lock().unwrap();
+        let result = state
+            .file
+            .seek(SeekFrom::End(0))
+            .map_err(Into::into)
+            .and_then(|offset| {
+                writeln!(state.file, "{}", serde_json::to_string(&entry)?)?;
+                state.file.sync_data()?;
+                Ok::<_, eyre::Error>(offset)
+            });
+        match result {
+            Ok(offset) => {
+                state
+                    .offsets
+                    .insert(identity.to_string(), (*upload_hash, offset));
+            }
+            Err(error) => error!(?error, identity, "Could not write receipt log entry"),
+        }
+        drop(state);
+    }
+
+    /// The receipt issued to `identity` for the upload with `upload_hash`, if
+    /// that was their latest accepted upload.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the log lock is poisoned.
+    pub fn get(&self, identity: &str, upload_hash: &[u8; 32]) -> Option<SignedReceipt> {
+        let (hash, offset) = *self.state.lock().unwrap().offsets.get(identity)?;
+        if hash != *upload_hash {
+            return None;
+        }
+        let result = File::open(&self.path)
+            .and_then(|mut file| {
+                file.seek(SeekFrom::Start(offset))?;
+                let mut line = String::new();
+                BufReader::new(file).read_line(&mut line)?;
+                Ok(line)
+            })
+            .map_err(Into::into)
+            .and_then(|line| Ok::<LogEntry, eyre::Error>(serde_json::from_str(&line)?));
+        match result {
+            Ok(entry) => Some(entry.receipt),
+            Err(error) => {
+                error!(?error, identity, "Could not read receipt log entry");
+                None
+            }
+        }
+    }
+}
+
+/// Verify that `signed` was signed by `coordinator` and return its contents.
+///
+/// # Errors
+///
+/// Returns [`ReceiptError::InvalidSignature`] if the signature does not match
+/// and [`ReceiptError::Malformed`] if the contents can not be parsed.
+pub fn verify(signed: &SignedReceipt, coordinator: Address) -> Result<Receipt, ReceiptError> {
+    signing::verify(signed.receipt.as_bytes(), &signed.signature, coordinator)?;
+    serde_json::from_str(&signed.receipt).map_err(|_| ReceiptError::Malformed)
+}
+
+/// Verify that `signed` was signed by one of the `trusted` coordinator keys,
+/// e.g. those of [`crate::signing::KeySet`], and return its contents.
+///
+/// # Errors
+///
+/// Returns [`ReceiptError::InvalidSignature`] if no trusted key signed it and
+/// [`ReceiptError::Malformed`] if the contents can not be parsed.
+pub fn verify_any(signed: &SignedReceipt, trusted: &[Address]) -> Result<Receipt, ReceiptError> {
+    signing::verify_any(signed.receipt.as_bytes(), &signed.signature, trusted)?;
+    serde_json::from_str(&signed.receipt).map_err(|_| ReceiptError::Malformed)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{
+        commitment::{self, MerkleLamport, Seed},
+        test::TestDir,
+    };
+    use std::fs;
+
+    #[test]
+    fn test_sign_verify() {
+        let key = CoordinatorKey::random();
+        let receipt = Receipt {
+            identity:          "git|1|recmo".into(),
+            index:             1,
+            transcript_before: "0x00".into(),
+            transcript_after:  "0x01".into(),
+            upload_hash:       None,
+        };
+        let signed = sign(&key, &receipt);
+        assert_eq!(verify(&signed, key.address()).unwrap(), receipt);
+        assert!(verify(&signed, CoordinatorKey::random().address()).is_err());
+        assert_eq!(signed.key_id, Some(key.address().to_string()));
+        let other = CoordinatorKey::random().address();
+        assert_eq!(
+            verify_any(&signed, &[other, key.address()]).unwrap(),
+            receipt
+        );
+        assert!(verify_any(&signed, &[other]).is_err());
+
+        let mut tampered = signed.clone();
+        tampered.receipt = tampered.receipt.replace("recmo", "eve");
+        assert!(verify(&tampered, key.address()).is_err());
+
+        let lamport = MerkleLamport::new(Seed::random(), 2);
+        let mut committed = signed;
+        commit(&lamport, receipt.index, &mut committed).unwrap();
+        let commitment = committed.commitment.unwrap();
+        assert_eq!(
+            commitment::verify(
+                &commitment.public_key,
+                committed.receipt.as_bytes(),
+                &commitment.signature
+            ),
+            Ok(receipt.index)
+        );
+    }
+
+    #[test]
+    fn test_receipt_log() {
+        let dir = TestDir::new("receipts");
+        fs::create_dir_all(&dir).unwrap();
+        let key = CoordinatorKey::random();
+        let receipt = |index| {
+            sign(&key, &Receipt {
+                identity: "git|1|recmo".into(),
+                index,
+                transcript_before: "0x00".into(),
+                transcript_after: "0x01".into(),
+                upload_hash: None,
+            })
+        };
+
+        let log = ReceiptLog::open(&dir).unwrap();
+        assert_eq!(log.get("git|1|recmo", &[1; 32]), None);
+        log.record("git|2|other", &[1; 32], &receipt(1));
+        log.record("git|1|recmo", &[1; 32], &receipt(2));
+        assert_eq!(log.get("git|1|recmo", &[1; 32]), Some(receipt(2)));
+        assert_eq!(log.get("git|1|recmo", &[2; 32]), None);
+        drop(log);
+
+        // Only the latest upload of an identity counts, also after a restart.
+        let log = ReceiptLog::open(&dir).unwrap();
+        assert_eq!(log.get("git|1|recmo", &[1; 32]), Some(receipt(2)));
+        log.record("git|1|recmo", &[3; 32], &receipt(3));
+        assert_eq!(log.get("git|1|recmo", &[1; 32]), None);
+        assert_eq!(log.get("git|1|recmo", &[3; 32]), Some(receipt(3)));
+        assert_eq!(log.get("git|2|other", &[1; 32]), Some(receipt(1)));
+    }
+}
@@ -0,0 +1,251 @@
+//! Request IDs and trace context propagation.
+//!
+//! Every request gets an ID, taken from the `X-Request-Id` header when the
+//! client or a proxy set a usable one and random otherwise. The ID is recorded
+//! in the request span, so it appears in every log line emitted while handling
+//! the request, and returned in the `X-Request-Id` response header and the
+//! `requestId` field of JSON error responses. A contributor reporting a
+//! failed upload can quote it and operators find the matching logs.
+//!
+//! A W3C `traceparent` header is parsed as well. Its trace ID is recorded in
+//! the span next to the request ID and the header is echoed in the response,
+//! so requests can be followed through tracing proxies. With `--trace-otlp`
+//! the exported request span becomes a child of the caller's span.
+
+use axum::{
+    body::{boxed, Body, Full},
+    http::{
+        header::{HeaderName, CONTENT_LENGTH, CONTENT_TYPE},
+        HeaderMap, HeaderValue, Request,
+    },
+    middleware::Next,
+    response::Response,
+};
+use cli_batteries::trace_from_headers;
+use rand::{thread_rng, Rng};
+use serde_json::Value;
+use std::fmt::{self, Display, Formatter};
+use tracing::{field, info_span, warn, Span};
+
+const X_REQUEST_ID: &str = "x-request-id";
+const TRACEPARENT: &str = "traceparent";
+
+/// Longest request ID accepted from clients.
+const MAX_LENGTH: usize = 64;
+
+/// The ID of the request being handled, available as an extension.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RequestId(String);
+
+/// A parsed W3C `traceparent` header.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TraceParent {
+    pub trace_id:  [u8; 16],
+    pub parent_id: [u8; 8],
+    pub flags:     u8,
+}
+
+impl RequestId {
+    #[must_use]
+    pub fn random() -> Self {
+        Self(hex::encode(thread_rng().gen::<[u8; 16]>()))
+    }
+
+    /// The client provided ID, if it is short printable ASCII.
+    #[must_use]
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let id = headers.get(X_REQUEST_ID)?.to_str().ok()?;
+        let valid =
+            !id.is_empty() && id.len() <= MAX_LENGTH && id.bytes().all(|b| b.is_ascii_graphic());
+        valid.then(|| Self(id.to_string()))
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for RequestId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl TraceParent {
+    /// Parse version `00` headers, `00-{trace_id}-{parent_id}-{flags}`.
+    #[must_use]
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = headers.get(TRACEPARENT)?.to_str().ok()?;
+        let mut parts = header.split('-');
+        if parts.next()? != "00" {
+            return None;
+        }
+        let mut trace_id = [0; 16];
+        hex::decode_to_slice(parts.next()?, &mut trace_id).ok()?;
+        let mut parent_id = [0; 8];
+        hex::decode_to_slice(parts.next()?, &mut parent_id).ok()?;
+        let mut flags = [0; 1];
+        hex::decode_to_slice(parts.next()?, &mut flags).ok()?;
+        // All zero IDs are invalid according to the specification.
+        let valid = parts.next().is_none() && trace_id != [0; 16] && parent_id != [0; 8];
+        valid.then_some(Self {
+            trace_id,
+            parent_id,
+            flags: flags[0],
+        })
+    }
+}
+
+impl Display for TraceParent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "00-{}-{}-{:02x}",
+            hex::encode(self.trace_id),
+            hex::encode(self.parent_id),
+            self.flags
+        )
+    }
+}
+
+/// Span for `TraceLayer`, carrying the request and trace IDs.
+pub fn make_span<B>(request: &Request<B>) -> Span {
+    let span = info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        request_id = field::Empty,
+        trace_id = field::Empty,
+    );
+    if let Some(id) = request.extensions().get::<RequestId>() {
+        span.record("request_id", field::display(id));
+    }
+    if let Some(trace) = request.extensions().get::<TraceParent>() {
+        span.record("trace_id", field::display(hex::encode(trace.trace_id)));
+        // Exported spans continue the caller's trace.
+        span.in_scope(|| trace_from_headers(request.headers()));
+    }
+    span
+}
+
+/// Assign the request ID and add it to the response.
+///
+/// Must wrap the `TraceLayer` so the ID is known when its span is created.
+pub async fn middleware(mut request: Request<Body>, next: Next<Body>) -> Response {
+    let id = RequestId::from_headers(request.headers()).unwrap_or_else(RequestId::random);
+    let trace = TraceParent::from_headers(request.headers());
+    request.extensions_mut().insert(id.clone());
+    if let Some(trace) = trace {
+        request.extensions_mut().insert(trace);
+    }
+
+    let mut response = next.run(request).await;
+    if response.status().is_client_error() || response.status().is_server_error() {
+        response = add_to_error(response, &id).await;
+    }
+    let headers = response.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(id.as_str()) {
+        headers.insert(HeaderName::from_static(X_REQUEST_ID), value);
+    }
+    if let Some(trace) = trace {
+        if let Ok(value) = HeaderValue::from_str(&trace.to_string()) {
+            headers.insert(HeaderName::from_static(TRACEPARENT), value);
+        }
+    }
+    response
+}
+
+/// Add a `requestId` field to JSON error bodies.
+async fn add_to_error(response: Response, id: &RequestId) -> Response {
+    let is_json = response.headers().get(CONTENT_TYPE).map_or(false, |value| {
+        value.as_bytes().starts_with(b"application/json")
+    });
+    if !is_json {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let bytes = match hyper::body::to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            warn!(?error, "Could not read error response");
+            return Response::from_parts(parts, boxed(Full::default()));
+        }
+    };
+    let bytes = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(Value::Object(mut object)) => {
+            object.insert("requestId".to_string(), Value::String(id.to_string()));
+            parts.headers.remove(CONTENT_LENGTH);
+            serde_json::to_vec(&object).map_or(bytes, Into::into)
+        }
+        _ => bytes,
+    };
+    Response::from_parts(parts, boxed(Full::from(bytes)))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use axum::{
+        http::StatusCode, middleware::from_fn, response::IntoResponse, routing::get, Json, Router,
+    };
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_parse() {
+        let mut headers = HeaderMap::new();
+        let header = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        headers.insert(TRACEPARENT, HeaderValue::from_static(header));
+        let trace = TraceParent::from_headers(&headers).unwrap();
+        assert_eq!(trace.to_string(), header);
+        assert_eq!(trace.flags, 1);
+
+        for invalid in [
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        ] {
+            headers.insert(TRACEPARENT, HeaderValue::from_static(invalid));
+            assert_eq!(TraceParent::from_headers(&headers), None);
+        }
+
+        headers.insert(X_REQUEST_ID, HeaderValue::from_static("abc-123"));
+        assert_eq!(
+            RequestId::from_headers(&headers),
+            Some(RequestId("abc-123".to_string()))
+        );
+        headers.insert(X_REQUEST_ID, HeaderValue::from_static("a b"));
+        assert_eq!(RequestId::from_headers(&headers), None);
+    }
+
+    #[tokio::test]
+    async fn test_middleware() {
+        let app = Router::new()
+            .route("/ok", get(|| async { "ok" }))
+            .route(
+                "/error",
+                get(|| async {
+                    (StatusCode::BAD_REQUEST, Json(json!({"error": "bad"}))).into_response()
+                }),
+            )
+            .layer(from_fn(middleware));
+
+        let request = Request::builder()
+            .uri("/error")
+            .header(X_REQUEST_ID, "report-me")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.headers()[X_REQUEST_ID], "report-me");
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, json!({"error": "bad", "requestId": "report-me"}));
+
+        let request = Request::builder().uri("/ok").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.headers()[X_REQUEST_ID].len(), 32);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(body, "ok");
+    }
+}
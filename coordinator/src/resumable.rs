@@ -0,0 +1,397 @@
+//! Resumable contribution uploads.
+//!
+//! A contribution is tens of megabytes, and a single dropped connection during
+//! `/contribution/complete` can cost a contributor on a poor connection their
+//! slot. Instead the holder of the slot can upload in chunks:
+//!
+//! 1. `POST /contribution/upload/init` with `{ "size": .., "sha256": "0x.." }`
+//!    of the whole contribution starts an upload, or resumes the one with the
+//!    same size and hash. The response has the `uploadId` and the number of
+//!    bytes `received` so far.
+//! 2. `POST /contribution/upload/chunk?uploadId=..&offset=..&sha256=0x..`
+//!    appends a chunk at `offset`, which must not be past the bytes received.
+//!    Chunks that were already received are acknowledged again, so a chunk can
+//!    be retried when the response was lost.
+//! 3. `POST /contribution/upload/finish?uploadId=..` checks the hash of the
+//!    whole contribution and applies it like `/contribution/complete`.
+//!
+//! There is one upload at a time, as there is one slot. It is kept after
+//! `finish`, so a retried `finish` returns the original receipt.
+
+use crate::{
+    analytics::QueueAnalytics,
+    audit::AuditLog,
+    bans::BanList,
+    ceremony::{SharedCeremony, Sizes, UploadRng},
+    commitment::CommitmentSigner,
+    compute::ComputePool,
+    contribute,
+    ipfs::Ipfs,
+    participants::ParticipantList,
+    policy::PolicyEngine,
+    queue::{Queue, QueueError},
+    receipt::ReceiptLog,
+    schema::ContributionSchema,
+    session::Session,
+    signing::CoordinatorKeys,
+    upload::{MaxUploadSize, Upload},
+};
+use axum::{
+    extract::Query,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tracing::{info, instrument};
+
+#[derive(Clone, PartialEq, Eq, Debug, Error)]
+pub enum ResumableError {
+    #[error("No upload with this id, start one with /contribution/upload/init")]
+    UnknownUpload,
+    #[error("Invalid SHA-256 hash, expected 32 bytes of hex")]
+    InvalidHash,
+    #[error("Upload exceeds the maximum size of {0} bytes")]
+    TooLarge(usize),
+    #[error("Chunk starts at {0}, but only {1} bytes were received")]
+    Gap(usize, usize),
+    #[error("Chunk does not match its SHA-256 hash")]
+    ChunkMismatch,
+    #[error("Upload is incomplete, received {0} of {1} bytes")]
+    Incomplete(usize, usize),
+    #[error("Upload does not match its SHA-256 hash, start over")]
+    UploadMismatch,
+    #[error(transparent)]
+    Queue(#[from] QueueError),
+}
+
+/// The upload in progress, if any.
+#[derive(Debug, Default)]
+pub struct UploadSessions(Mutex<Option<UploadSession>>);
+
+#[derive(Debug)]
+struct UploadSession {
+    identity: String,
+    id:       String,
+    size:     usize,
+    sha256:   [u8; 32],
+    bytes:    Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitRequest {
+    pub size:   usize,
+    /// `0x` prefixed hex.
+    pub sha256: String,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkQuery {
+    pub upload_id: String,
+    pub offset:    usize,
+    /// `0x` prefixed hex.
+    pub sha256:    String,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FinishQuery {
+    pub upload_id: String,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Progress {
+    pub upload_id: String,
+    pub size:      usize,
+    pub received:  usize,
+}
+
+impl UploadSessions {
+    /// Start an upload for `identity`, or resume theirs with the same size and
+    /// hash.
+    pub fn init(
+        &self,
+        identity: &str,
+        size: usize,
+        sha256: [u8; 32],
+        max: usize,
+    ) -> Result<Progress, ResumableError> {
+        if size > max {
+            return Err(ResumableError::TooLarge(max));
+        }
+        let mut current = self.0.lock().unwrap();
+        match current.as_ref() {
+            Some(upload)
+                if upload.identity == identity
+                    && upload.size == size
+                    && upload.sha256 == sha256 =>
+            {
+                info!(received = upload.bytes.len(), "Resuming upload");
+            }
+            _ => {
+                let id = hex::encode(rand::thread_rng().gen::<[u8; 16]>());
+                info!(%id, size, "Starting upload");
+                *current = Some(UploadSession {
+                    identity: identity.to_string(),
+                    id,
+                    size,
+                    sha256,
+                    bytes: Vec::with_capacity(size),
+                });
+            }
+        }
+        Ok(current.as_ref().unwrap().progress())
+    }
+
+    /// Add `chunk` with hash `sha256` at `offset`.
+    pub fn chunk(
+        &self,
+        identity: &str,
+        id: &str,
+        offset: usize,
+        chunk: &Upload,
+        sha256: [u8; 32],
+    ) -> Result<Progress, ResumableError> {
+        let mut current = self.0.lock().unwrap();
+        let upload = current
+            .as_mut()
+            .filter(|upload| upload.identity == identity && upload.id == id)
+            .ok_or(ResumableError::UnknownUpload)?;
+        if chunk.hash != sha256 {
+            return Err(ResumableError::ChunkMismatch);
+        }
+        let received = upload.bytes.len();
+        if offset > received {
+            return Err(ResumableError::Gap(offset, received));
+        }
+        if offset + chunk.bytes.len() > upload.size {
+            return Err(ResumableError::TooLarge(upload.size));
+        }
+        // Skip the part received before, in case this is a retry.
+        let new = chunk.bytes.get(received - offset..).unwrap_or_default();
+        upload.bytes.extend_from_slice(new);
+        let progress = upload.progress();
+        drop(current);
+        Ok(progress)
+    }
+
+    /// The complete upload, if it matches the announced hash.
+    pub fn assemble(&self, identity: &str, id: &str) -> Result<Upload, ResumableError> {
+        let mut current = self.0.lock().unwrap();
+        let upload = current
+            .as_ref()
+            .filter(|upload| upload.identity == identity && upload.id == id)
+            .ok_or(ResumableError::UnknownUpload)?;
+        if upload.bytes.len() < upload.size {
+            return Err(ResumableError::Incomplete(upload.bytes.len(), upload.size));
+        }
+        let hash: [u8; 32] = Sha256::digest(&upload.bytes).into();
+        if hash != upload.sha256 {
+            *current = None;
+            return Err(ResumableError::UploadMismatch);
+        }
+        let bytes = upload.bytes.clone().into();
+        drop(current);
+        Ok(Upload { bytes, hash })
+    }
+}
+
+impl UploadSession {
+    fn progress(&self) -> Progress {
+        Progress {
+            upload_id: self.id.clone(),
+            size:      self.size,
+            received:  self.bytes.len(),
+        }
+    }
+}
+
+fn parse_hash(hex: &str) -> Result<[u8; 32], ResumableError> {
+    let mut hash = [0; 32];
+    hex::decode_to_slice(hex.strip_prefix("0x").unwrap_or(hex), &mut hash)
+        .map_err(|_| ResumableError::InvalidHash)?;
+    Ok(hash)
+}
+
+impl IntoResponse for ResumableError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::UnknownUpload => StatusCode::NOT_FOUND,
+            Self::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::Gap(..) | Self::Incomplete(..) => StatusCode::CONFLICT,
+            Self::InvalidHash | Self::ChunkMismatch | Self::UploadMismatch => {
+                StatusCode::BAD_REQUEST
+            }
+            Self::Queue(error) => return error.into_response(),
+        };
+        (status, Json(json!({ "error": self.to_string() }))).into_response()
+    }
+}
+
+#[instrument(level = "info", skip_all, fields(identity = %session.pseudonym))]
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn init(
+    session: Session,
+    Extension(queue): Extension<Arc<Queue>>,
+    Extension(sessions): Extension<Arc<UploadSessions>>,
+    Extension(MaxUploadSize(max)): Extension<MaxUploadSize>,
+    Json(request): Json<InitRequest>,
+) -> Result<Json<Progress>, ResumableError> {
+    queue.ensure_active(&session.pseudonym)?;
+    let sha256 = parse_hash(&request.sha256)?;
+    sessions
+        .init(&session.pseudonym, request.size, sha256, max)
+        .map(Json)
+}
+
+#[instrument(level = "info", skip_all, fields(identity = %session.pseudonym))]
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn chunk(
+    session: Session,
+    Query(query): Query<ChunkQuery>,
+    Extension(queue): Extension<Arc<Queue>>,
+    Extension(sessions): Extension<Arc<UploadSessions>>,
+    chunk: Upload,
+) -> Result<Json<Progress>, ResumableError> {
+    queue.ensure_active(&session.pseudonym)?;
+    let sha256 = parse_hash(&query.sha256)?;
+    sessions
+        .chunk(
+            &session.pseudonym,
+            &query.upload_id,
+            query.offset,
+            &chunk,
+            sha256,
+        )
+        .map(Json)
+}
+
+/// Apply the complete upload, see [`contribute::complete`].
+#[instrument(level = "info", skip_all, fields(identity = %session.pseudonym))]
+#[allow(clippy::too_many_arguments)] // Axum extractors
+pub async fn finish(
+    session: Session,
+    Query(query): Query<FinishQuery>,
+    Extension(sessions): Extension<Arc<UploadSessions>>,
+    ceremony: Extension<SharedCeremony>,
+    checks: (Extension<Sizes>, Extension<UploadRng>),
+    key: Extension<Arc<CoordinatorKeys>>,
+    commitment: Extension<Option<Arc<dyn CommitmentSigner>>>,
+    analytics: Extension<QueueAnalytics>,
+    queue: Extension<Arc<Queue>>,
+    policy: Extension<Arc<PolicyEngine>>,
+    bans: Extension<Arc<BanList>>,
+    audit: Extension<Arc<AuditLog>>,
+    ipfs: Extension<Ipfs>,
+    compute: Extension<ComputePool>,
+    schema: Extension<Option<Arc<ContributionSchema>>>,
+    // Grouped, axum handlers take at most 16 extractors.
+    (receipts, participants): (Extension<Arc<ReceiptLog>>, Extension<Arc<ParticipantList>>),
+) -> Response {
+    let upload = match sessions.assemble(&session.pseudonym, &query.upload_id) {
+        Ok(upload) => upload,
+        Err(error) => return error.into_response(),
+    };
+    contribute::complete(
+        session,
+        ceremony,
+        checks,
+        key,
+        commitment,
+        analytics,
+        queue,
+        policy,
+        bans,
+        audit,
+        ipfs,
+        compute,
+        schema,
+        receipts,
+        participants,
+        upload,
+    )
+    .await
+    .into_response()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn chunk(bytes: &'static [u8]) -> (Upload, [u8; 32]) {
+        let hash = Sha256::digest(bytes).into();
+        let upload = Upload {
+            bytes: bytes.into(),
+            hash,
+        };
+        (upload, hash)
+    }
+
+    #[test]
+    fn test_resume() {
+        let sessions = UploadSessions::default();
+        let sha256 = Sha256::digest(b"hello world").into();
+        assert_eq!(
+            sessions.init("a", 11, sha256, 10),
+            Err(ResumableError::TooLarge(10))
+        );
+        let id = sessions.init("a", 11, sha256, 100).unwrap().upload_id;
+        assert_eq!(
+            sessions.assemble("b", &id).unwrap_err(),
+            ResumableError::UnknownUpload
+        );
+
+        let (hello, hash) = chunk(b"hello");
+        assert_eq!(
+            sessions.chunk("a", &id, 0, &hello, hash).unwrap().received,
+            5
+        );
+        assert_eq!(
+            sessions.chunk("a", &id, 0, &hello, [0; 32]),
+            Err(ResumableError::ChunkMismatch)
+        );
+        let (world, hash) = chunk(b"world");
+        assert_eq!(
+            sessions.chunk("a", &id, 6, &world, hash),
+            Err(ResumableError::Gap(6, 5))
+        );
+        assert_eq!(
+            sessions.assemble("a", &id).unwrap_err(),
+            ResumableError::Incomplete(5, 11)
+        );
+
+        // Init again resumes, and overlapping chunks are accepted.
+        let progress = sessions.init("a", 11, sha256, 100).unwrap();
+        assert_eq!(
+            (progress.upload_id.as_str(), progress.received),
+            (id.as_str(), 5)
+        );
+        let (tail, hash) = chunk(b"lo world");
+        assert_eq!(
+            sessions.chunk("a", &id, 3, &tail, hash).unwrap().received,
+            11
+        );
+        let upload = sessions.assemble("a", &id).unwrap();
+        assert_eq!(
+            (&upload.bytes[..], upload.hash),
+            (&b"hello world"[..], sha256)
+        );
+
+        // A different upload replaces it.
+        let other = sessions.init("b", 5, hash, 100).unwrap().upload_id;
+        assert_ne!(other, id);
+        let (hello, hash) = chunk(b"hello");
+        assert_eq!(
+            sessions.chunk("a", &id, 0, &hello, hash),
+            Err(ResumableError::UnknownUpload)
+        );
+    }
+}
@@ -0,0 +1,265 @@
+//! Session revocation.
+//!
+//! Session tokens are self-contained and stay valid until they expire, see
+//! [`crate::session`]. Leaked or stolen tokens are cut short by revoking them:
+//!
+//! * `POST /logout` revokes the token of the request.
+//! * `POST /admin/sessions/revoke` with `{"identities": [...]}` revokes every
+//!   token issued so far to the listed identities or pseudonyms. They can sign
+//!   in again afterwards.
+//!
+//! Rotating the session key revokes all tokens at once, see [`crate::admin`].
+//!
+//! Revocations are appended to `revocations.jsonl` in the transcript
+//! directory. Revoked tokens are forgotten once they would have expired
+//! anyway.
+
+use crate::{
+    admin::Admin,
+    session::{bearer_token, Session},
+    storage::write_atomic,
+};
+use axum::{http::HeaderMap, Extension, Json};
+use eyre::{Result as EyreResult, WrapErr};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{error, info, instrument};
+
+const FILE: &str = "revocations.jsonl";
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Revocation {
+    /// A single token, by hash.
+    Token { hash: String, expires: u64 },
+    /// Tokens of an identity issued at or before `before`.
+    Identity { identity: String, before: u64 },
+}
+
+#[derive(Debug)]
+pub struct Revocations {
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    file:       File,
+    /// Expiry of revoked tokens by hash.
+    tokens:     HashMap<String, u64>,
+    /// Latest revocation time by identity or pseudonym.
+    identities: HashMap<String, u64>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct RevokeRequest {
+    pub identities: Vec<String>,
+}
+
+impl Revocations {
+    /// Load the revocations persisted in `dir`, dropping expired tokens.
+    pub fn open(dir: &Path) -> EyreResult<Self> {
+        let path = dir.join(FILE);
+        let now = now();
+        let mut tokens = HashMap::new();
+        let mut identities = HashMap::new();
+        if path.exists() {
+            let contents = fs::read_to_string(&path)
+                .wrap_err_with(|| format!("Could not read {}", path.display()))?;
+            for line in contents.lines() {
+                match serde_json::from_str(line)? {
+                    Revocation::Token { hash, expires } => {
+                        if expires > now {
+                            tokens.insert(hash, expires);
+                        }
+                    }
+                    Revocation::Identity { identity, before } => {
+                        let latest = identities.entry(identity).or_insert(before);
+                        *latest = before.max(*latest);
+                    }
+                }
+            }
+            // Compact, so the file does not grow with every logout.
+            let mut compacted = String::new();
+            for revocation in entries(&tokens, &identities) {
+                compacted.push_str(&serde_json::to_string(&revocation)?);
+                compacted.push('\n');
+            }
+            write_atomic(&path, compacted.as_bytes())?;
+        }
+        info!(
+            tokens = tokens.len(),
+            identities = identities.len(),
+            "Loaded session revocations"
+        );
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .wrap_err_with(|| format!("Could not open {}", path.display()))?;
+        Ok(Self {
+            state: Mutex::new(State {
+                file,
+                tokens,
+                identities,
+            }),
+        })
+    }
+
+    /// Revoke `token` of `session`.
+    pub fn revoke_token(&self, token: &str, session: &Session) {
+        let hash = hash(token);
+        let mut state = self.state.lock().unwrap();
+        let now = now();
+        state.tokens.retain(|_, expires| *expires > now);
+        state.tokens.insert(hash.clone(), session.expires);
+        state.append(&Revocation::Token {
+            hash,
+            expires: session.expires,
+        });
+    }
+
+    /// Revoke all tokens issued to `identity` so far.
+    pub fn revoke_identity(&self, identity: &str) {
+        let before = now();
+        let mut state = self.state.lock().unwrap();
+        state.identities.insert(identity.to_string(), before);
+        state.append(&Revocation::Identity {
+            identity: identity.to_string(),
+            before,
+        });
+    }
+
+    /// Whether `token` of `session` was revoked.
+    #[must_use]
+    pub fn is_revoked(&self, token: &str, session: &Session) -> bool {
+        let state = self.state.lock().unwrap();
+        let revoked = |identity: &str| {
+            state
+                .identities
+                .get(identity)
+                .map_or(false, |before| session.issued <= *before)
+        };
+        revoked(&session.identity)
+            || revoked(&session.pseudonym)
+            || state.tokens.contains_key(&hash(token))
+    }
+}
+
+impl State {
+    fn append(&mut self, revocation: &Revocation) {
+        let result = serde_json::to_string(revocation)
+            .map_err(eyre::Report::from)
+            .and_then(|json| {
+                writeln!(self.file, "{json}")?;
+                self.file.sync_data()?;
+                Ok(())
+            });
+        // The revocation holds until restart regardless.
+        if let Err(error) = result {
+            error!(?error, ?revocation, "Could not persist session revocation");
+        }
+    }
+}
+
+fn entries<'a>(
+    tokens: &'a HashMap<String, u64>,
+    identities: &'a HashMap<String, u64>,
+) -> impl Iterator<Item = Revocation> + 'a {
+    let tokens = tokens.iter().map(|(hash, expires)| Revocation::Token {
+        hash:    hash.clone(),
+        expires: *expires,
+    });
+    let identities = identities
+        .iter()
+        .map(|(identity, before)| Revocation::Identity {
+            identity: identity.clone(),
+            before:   *before,
+        });
+    tokens.chain(identities)
+}
+
+/// End the session of the request.
+#[instrument(level = "info", skip_all, fields(identity = %session.pseudonym))]
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn logout(
+    session: Session,
+    headers: HeaderMap,
+    Extension(revocations): Extension<Arc<Revocations>>,
+) -> Json<serde_json::Value> {
+    if let Some(token) = bearer_token(&headers) {
+        revocations.revoke_token(token, &session);
+        info!("Logged out");
+    }
+    Json(json!({}))
+}
+
+/// Revoke all sessions of some identities.
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn revoke(
+    _admin: Admin,
+    Extension(revocations): Extension<Arc<Revocations>>,
+    Json(request): Json<RevokeRequest>,
+) -> Json<serde_json::Value> {
+    for identity in &request.identities {
+        revocations.revoke_identity(identity);
+    }
+    info!(identities = ?request.identities, "Revoked sessions");
+    Json(json!({ "revoked": request.identities.len() }))
+}
+
+fn hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time before unix epoch")
+        .as_secs()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{session::SessionKey, test::TestDir};
+    use std::time::Duration;
+
+    #[test]
+    fn test_revocations() {
+        let dir = TestDir::new("revocations");
+        fs::create_dir_all(&dir).unwrap();
+        let key = SessionKey::random();
+        let session = |token: &str| {
+            let mut session = key.verify(token).unwrap();
+            session.pseudonym = session.identity.clone();
+            session
+        };
+        let alice = key.issue("git|1|alice", Duration::from_secs(60));
+        let alice2 = key.issue("git|1|alice", Duration::from_secs(120));
+        let bob = key.issue("git|2|bob", Duration::from_secs(60));
+        let carol = key.issue("git|3|carol", Duration::from_secs(60));
+
+        let revocations = Revocations::open(&dir).unwrap();
+        revocations.revoke_token(&alice, &session(&alice));
+        revocations.revoke_identity("git|2|bob");
+        assert!(revocations.is_revoked(&alice, &session(&alice)));
+        assert!(!revocations.is_revoked(&alice2, &session(&alice2)));
+        assert!(revocations.is_revoked(&bob, &session(&bob)));
+        assert!(!revocations.is_revoked(&carol, &session(&carol)));
+
+        // Reload from disk.
+        let revocations = Revocations::open(&dir).unwrap();
+        assert!(revocations.is_revoked(&alice, &session(&alice)));
+        assert!(revocations.is_revoked(&bob, &session(&bob)));
+        assert!(!revocations.is_revoked(&carol, &session(&carol)));
+    }
+}
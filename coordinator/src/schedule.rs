@@ -0,0 +1,219 @@
+//! Opening hours of the ceremony.
+//!
+//! `--opens-at` and `--closes-at` bound the ceremony in unix seconds, and
+//! `--quiet-hours`, e.g. `22-6`, closes it every day between these UTC hours.
+//! Outside of the schedule nobody can join the queue or take the contribution
+//! slot, but a participant holding the slot may still finish. Participants
+//! already waiting keep their place.
+//!
+//! The [`Phase`] follows from the clock. A background task announces every
+//! transition on the event feed, see [`crate::events`].
+
+use crate::{
+    events::{CeremonyEvent, EventBus},
+    Options,
+};
+use eyre::{ensure, eyre, Error as EyreError, Result as EyreResult};
+use serde::Serialize;
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{task, time::sleep};
+use tracing::info;
+
+const DAY: u64 = 24 * 60 * 60;
+const HOUR: u64 = 60 * 60;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Schedule {
+    /// Unix time the ceremony opens.
+    pub opens_at:    Option<u64>,
+    /// Unix time the ceremony closes for good.
+    pub closes_at:   Option<u64>,
+    pub quiet_hours: Option<QuietHours>,
+}
+
+/// Daily closing time, from the `start` to the `end` hour in UTC. Wraps around
+/// midnight if `end` is before `start`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+pub struct QuietHours {
+    pub start: u8,
+    pub end:   u8,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Phase {
+    /// Before `opens_at`.
+    Pending,
+    Open,
+    /// During quiet hours.
+    Quiet,
+    /// After `closes_at`.
+    Closed,
+}
+
+impl Schedule {
+    pub fn from_options(options: &Options) -> EyreResult<Self> {
+        let schedule = Self {
+            opens_at:    options.opens_at,
+            closes_at:   options.closes_at,
+            quiet_hours: options.quiet_hours,
+        };
+        if let (Some(opens_at), Some(closes_at)) = (schedule.opens_at, schedule.closes_at) {
+            ensure!(
+                opens_at < closes_at,
+                "--opens-at must be before --closes-at"
+            );
+        }
+        Ok(schedule)
+    }
+
+    /// Whether the ceremony is open at all times.
+    #[must_use]
+    pub fn is_unbounded(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The phase at the current time.
+    #[must_use]
+    pub fn phase(&self) -> Phase {
+        self.phase_at(now())
+    }
+
+    /// The phase at unix time `time`.
+    #[must_use]
+    pub fn phase_at(&self, time: u64) -> Phase {
+        if self.opens_at.map_or(false, |opens_at| time < opens_at) {
+            Phase::Pending
+        } else if self.closes_at.map_or(false, |closes_at| time >= closes_at) {
+            Phase::Closed
+        } else if self.quiet_hours.map_or(false, |quiet| quiet.contains(time)) {
+            Phase::Quiet
+        } else {
+            Phase::Open
+        }
+    }
+
+    /// The first time after `time` at which the phase may change.
+    #[must_use]
+    pub fn next_change(&self, time: u64) -> Option<u64> {
+        let quiet = self.quiet_hours.into_iter().flat_map(|quiet| {
+            let midnight = time - time % DAY;
+            [midnight, midnight + DAY].into_iter().flat_map(move |day| {
+                [quiet.start, quiet.end].map(|hour| day + u64::from(hour) * HOUR)
+            })
+        });
+        [self.opens_at, self.closes_at]
+            .into_iter()
+            .flatten()
+            .chain(quiet)
+            .filter(|&change| change > time)
+            .min()
+    }
+}
+
+impl QuietHours {
+    fn contains(self, time: u64) -> bool {
+        let hour = (time % DAY / HOUR) as u8;
+        if self.start < self.end {
+            (self.start..self.end).contains(&hour)
+        } else {
+            hour >= self.start || hour < self.end
+        }
+    }
+}
+
+impl FromStr for QuietHours {
+    type Err = EyreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || eyre!("Quiet hours must be two distinct UTC hours, e.g. 22-6");
+        let (start, end) = s.split_once('-').ok_or_else(error)?;
+        let start: u8 = start.trim().parse().map_err(|_| error())?;
+        let end: u8 = end.trim().parse().map_err(|_| error())?;
+        if start >= 24 || end >= 24 || start == end {
+            return Err(error());
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+/// Announce phase transitions on `events`, if the schedule has any.
+pub fn spawn(schedule: Schedule, events: EventBus) {
+    if schedule.is_unbounded() {
+        return;
+    }
+    task::spawn(async move {
+        let mut phase = schedule.phase();
+        info!(?phase, "Ceremony schedule loaded");
+        loop {
+            let time = now();
+            let change = match schedule.next_change(time) {
+                Some(change) => change,
+                None => return,
+            };
+            sleep(Duration::from_secs(change - time)).await;
+            let next = schedule.phase();
+            if next != phase {
+                phase = next;
+                info!(?phase, "Ceremony phase changed");
+                events.publish(CeremonyEvent::PhaseChanged { phase });
+            }
+        }
+    });
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time before unix epoch")
+        .as_secs()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_phase() {
+        let day = 100 * DAY;
+        let schedule = Schedule {
+            opens_at:    Some(day + 9 * HOUR),
+            closes_at:   Some(day + 3 * DAY),
+            quiet_hours: Some("22-6".parse().unwrap()),
+        };
+        assert_eq!(schedule.phase_at(day), Phase::Pending);
+        assert_eq!(schedule.phase_at(day + 9 * HOUR), Phase::Open);
+        assert_eq!(schedule.phase_at(day + 22 * HOUR), Phase::Quiet);
+        assert_eq!(schedule.phase_at(day + DAY + 5 * HOUR), Phase::Quiet);
+        assert_eq!(schedule.phase_at(day + DAY + 6 * HOUR), Phase::Open);
+        assert_eq!(schedule.phase_at(day + 3 * DAY), Phase::Closed);
+
+        assert_eq!(schedule.next_change(day), Some(day + 6 * HOUR));
+        assert_eq!(schedule.next_change(day + 9 * HOUR), Some(day + 22 * HOUR));
+        assert_eq!(
+            schedule.next_change(day + 23 * HOUR),
+            Some(day + DAY + 6 * HOUR)
+        );
+        assert_eq!(Schedule::default().next_change(day), None);
+        assert_eq!(Schedule::default().phase_at(day), Phase::Open);
+
+        let daytime: QuietHours = "9-17".parse().unwrap();
+        assert!(daytime.contains(day + 12 * HOUR));
+        assert!(!daytime.contains(day + 17 * HOUR));
+        assert_eq!(daytime.to_string(), "9-17");
+        assert!("6-6".parse::<QuietHours>().is_err());
+        assert!("22-24".parse::<QuietHours>().is_err());
+        assert!("22".parse::<QuietHours>().is_err());
+    }
+}
@@ -0,0 +1,129 @@
+//! Concurrent verification of sub-contributions.
+//!
+//! The sub-ceremonies are independent, so their verifications run
+//! concurrently on the rayon pool instead of one after the other. Each job is
+//! timed and the timings are exported as metrics, so the time between
+//! contributors can be attributed to the sub-ceremonies.
+
+use once_cell::sync::Lazy;
+use prometheus::{register_histogram, register_histogram_vec, Histogram, HistogramVec};
+use rayon::prelude::*;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+static SUB_CEREMONY_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "sub_ceremony_verification_seconds",
+        "Time to verify a sub-contribution.",
+        &["sub_ceremony"]
+    )
+    .unwrap()
+});
+
+static CONTRIBUTION_SECONDS: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "contribution_verification_seconds",
+        "Wall time to verify all sub-contributions of a contribution."
+    )
+    .unwrap()
+});
+
+/// Timings of a verification run.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Timing {
+    /// Time spent on each sub-ceremony, by sub-ceremony index.
+    pub sub_ceremonies: Vec<(usize, Duration)>,
+    /// Time until all verifications completed.
+    pub wall:           Duration,
+}
+
+impl Timing {
+    /// Sum of the sub-ceremony times, the wall time a sequential run would
+    /// have taken.
+    #[must_use]
+    pub fn sequential(&self) -> Duration {
+        self.sub_ceremonies.iter().map(|(_, time)| *time).sum()
+    }
+}
+
+/// Run `verify` for all `jobs` concurrently.
+///
+/// All jobs run to completion. If any fail, the error of the job with the
+/// lowest sub-ceremony index is returned, so the outcome does not depend on
+/// scheduling.
+pub fn verify_all<T, E, F>(jobs: &[(usize, T)], verify: F) -> Result<Timing, E>
+where
+    T: Sync,
+    E: Send,
+    F: Fn(usize, &T) -> Result<(), E> + Sync,
+{
+    let start = Instant::now();
+    let results = jobs
+        .par_iter()
+        .map(|(index, job)| {
+            let start = Instant::now();
+            let result = verify(*index, job);
+            (*index, start.elapsed(), result)
+        })
+        .collect::<Vec<_>>();
+    let wall = start.elapsed();
+    let mut sub_ceremonies = Vec::with_capacity(results.len());
+    let mut error = None;
+    for (index, time, result) in results {
+        SUB_CEREMONY_SECONDS
+            .with_label_values(&[&index.to_string()])
+            .observe(time.as_secs_f64());
+        sub_ceremonies.push((index, time));
+        if let Err(e) = result {
+            error.get_or_insert(e);
+        }
+    }
+    CONTRIBUTION_SECONDS.observe(wall.as_secs_f64());
+    let timing = Timing {
+        sub_ceremonies,
+        wall,
+    };
+    info!(
+        wall = ?timing.wall,
+        sequential = ?timing.sequential(),
+        sub_ceremonies = ?timing.sub_ceremonies,
+        "Verified sub-contributions"
+    );
+    error.map_or(Ok(timing), Err)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::thread::sleep;
+
+    #[test]
+    fn test_verify_all() {
+        let jobs = [(0, 3_u64), (1, 0), (2, 2), (3, 1)];
+        let timing = verify_all(&jobs, |_, millis| {
+            sleep(Duration::from_millis(*millis * 10));
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+        assert_eq!(
+            timing
+                .sub_ceremonies
+                .iter()
+                .map(|(i, _)| *i)
+                .collect::<Vec<_>>(),
+            vec![0, 1, 2, 3]
+        );
+        assert!(timing.sequential() >= Duration::from_millis(60));
+
+        // The lowest failing index wins, regardless of completion order.
+        let result = verify_all(&jobs, |index, millis| {
+            sleep(Duration::from_millis(*millis * 10));
+            if index == 1 {
+                Ok(())
+            } else {
+                Err(index)
+            }
+        });
+        assert_eq!(result, Err(0));
+    }
+}
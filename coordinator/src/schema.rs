@@ -0,0 +1,622 @@
+//! JSON Schema validation of uploaded contributions.
+//!
+//! Operators can pass the contribution schema of the specification, or a
+//! stricter one, with `--contribution-schema`. Uploads are then validated
+//! against it before they are parsed, and rejected with every violation
+//! listed in the response:
+//!
+//! ```json
+//! {
+//!   "error": "Contribution does not match the schema: 1 violation(s)",
+//!   "violations": [{ "path": "/subContributions/0/numG1Powers", "code": "wrong_type", "title": "Type of the value is wrong" }]
+//! }
+//! ```
+//!
+//! The schema is compiled once into an immutable tree, so uploads from any
+//! number of workers are validated concurrently without locking. The subset
+//! of JSON Schema used by contribution schemas is supported: `type`, `enum`,
+//! `const`, `properties`, `required`, `additionalProperties`, `items`,
+//! `prefixItems`, `minItems`, `maxItems`, `minLength`, `maxLength`, `pattern`,
+//! `minimum`, `maximum`, `allOf`, `anyOf`, `oneOf` and local `$ref`s. Schemas
+//! using any other keyword are rejected on load rather than silently
+//! validating less than the operator intended.
+
+use eyre::{bail, eyre, Result as EyreResult, WrapErr};
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::{collections::HashMap, fs, path::Path};
+use thiserror::Error;
+use tracing::{info, instrument};
+
+/// Keywords that do not affect validation.
+const ANNOTATIONS: &[&str] = &[
+    "$schema",
+    "$id",
+    "$defs",
+    "$comment",
+    "definitions",
+    "title",
+    "description",
+    "examples",
+    "default",
+];
+
+/// A compiled contribution schema.
+#[derive(Debug)]
+pub struct ContributionSchema {
+    /// Compiled nodes, the root first. Nodes refer to each other by index so
+    /// that `$ref`s can be recursive.
+    nodes: Vec<Node>,
+}
+
+/// A single schema violation.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+pub struct Violation {
+    /// JSON pointer to the offending value.
+    pub path:   String,
+    pub code:   String,
+    pub title:  String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Error)]
+#[error("Contribution does not match the schema: {} violation(s)", .violations.len())]
+pub struct SchemaError {
+    pub violations: Vec<Violation>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Type {
+    Null,
+    Boolean,
+    Object,
+    Array,
+    Number,
+    Integer,
+    String,
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    types:                 Option<Vec<Type>>,
+    enumeration:           Option<Vec<Value>>,
+    properties:            Vec<(String, usize)>,
+    required:              Vec<String>,
+    additional_properties: Option<usize>,
+    no_additional:         bool,
+    prefix_items:          Vec<usize>,
+    items:                 Option<usize>,
+    min_items:             Option<usize>,
+    max_items:             Option<usize>,
+    min_length:            Option<usize>,
+    max_length:            Option<usize>,
+    pattern:               Option<Regex>,
+    minimum:               Option<f64>,
+    maximum:               Option<f64>,
+    all_of:                Vec<usize>,
+    any_of:                Vec<usize>,
+    one_of:                Vec<usize>,
+}
+
+/// Compiles schema nodes by their JSON pointer in the schema document.
+struct Compiler<'a> {
+    document:   &'a Value,
+    nodes:      Vec<Option<Node>>,
+    by_pointer: HashMap<String, usize>,
+}
+
+impl ContributionSchema {
+    /// Load and compile the schema in `path`.
+    #[allow(clippy::disallowed_methods)] // False positive from macro
+    #[instrument(level = "info")]
+    pub fn load(path: &Path) -> EyreResult<Self> {
+        let json = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Could not read schema {}", path.display()))?;
+        let schema = serde_json::from_str(&json)
+            .wrap_err_with(|| format!("Schema {} is not valid JSON", path.display()))?;
+        let schema = Self::compile(&schema)?;
+        info!("Loaded contribution schema {}", path.display());
+        Ok(schema)
+    }
+
+    pub fn compile(schema: &Value) -> EyreResult<Self> {
+        let mut compiler = Compiler {
+            document:   schema,
+            nodes:      Vec::new(),
+            by_pointer: HashMap::new(),
+        };
+        compiler
+            .compile("")
+            .wrap_err("Invalid contribution schema")?;
+        Ok(Self {
+            nodes: compiler
+                .nodes
+                .into_iter()
+                .map(|node| node.expect("All nodes are compiled"))
+                .collect(),
+        })
+    }
+
+    /// Validate an uploaded contribution.
+    pub fn validate(&self, contribution: &Value) -> Result<(), SchemaError> {
+        let mut violations = Vec::new();
+        self.validate_node(0, contribution, "", &mut violations);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(SchemaError { violations })
+        }
+    }
+
+    fn validate_node(&self, index: usize, value: &Value, path: &str, out: &mut Vec<Violation>) {
+        let node = &self.nodes[index];
+        if let Some(types) = &node.types {
+            if !types.iter().any(|t| t.matches(value)) {
+                out.push(violation(
+                    path.to_string(),
+                    "wrong_type",
+                    "Type of the value is wrong",
+                    None,
+                ));
+                // Other keywords would only repeat the type mismatch.
+                return;
+            }
+        }
+        if let Some(values) = &node.enumeration {
+            if !values.contains(value) {
+                out.push(violation(
+                    path.to_string(),
+                    "enum",
+                    "Value is not one of the allowed values",
+                    None,
+                ));
+            }
+        }
+        match value {
+            Value::String(string) => {
+                let len = string.chars().count();
+                if node.min_length.map_or(false, |min| len < min) {
+                    out.push(violation(
+                        path.to_string(),
+                        "min_length",
+                        "String is shorter than allowed",
+                        None,
+                    ));
+                }
+                if node.max_length.map_or(false, |max| len > max) {
+                    out.push(violation(
+                        path.to_string(),
+                        "max_length",
+                        "String is longer than allowed",
+                        None,
+                    ));
+                }
+                if let Some(pattern) = &node.pattern {
+                    if !pattern.is_match(string) {
+                        out.push(violation(
+                            path.to_string(),
+                            "pattern",
+                            "String does not match the pattern",
+                            Some(pattern.as_str().to_string()),
+                        ));
+                    }
+                }
+            }
+            Value::Number(number) => {
+                let number = number.as_f64().unwrap_or(f64::NAN);
+                if node.minimum.map_or(false, |min| number < min) {
+                    out.push(violation(
+                        path.to_string(),
+                        "minimum",
+                        "Number is less than the minimum",
+                        None,
+                    ));
+                }
+                if node.maximum.map_or(false, |max| number > max) {
+                    out.push(violation(
+                        path.to_string(),
+                        "maximum",
+                        "Number is greater than the maximum",
+                        None,
+                    ));
+                }
+            }
+            Value::Array(items) => self.validate_array(node, items, path, out),
+            Value::Object(object) => self.validate_object(node, object, path, out),
+            Value::Null | Value::Bool(_) => {}
+        }
+        self.validate_combinators(node, value, path, out);
+    }
+
+    fn validate_array(&self, node: &Node, items: &[Value], path: &str, out: &mut Vec<Violation>) {
+        if node.min_items.map_or(false, |min| items.len() < min) {
+            out.push(violation(
+                path.to_string(),
+                "min_items",
+                "Array has fewer items than allowed",
+                None,
+            ));
+        }
+        if node.max_items.map_or(false, |max| items.len() > max) {
+            out.push(violation(
+                path.to_string(),
+                "max_items",
+                "Array has more items than allowed",
+                None,
+            ));
+        }
+        for (i, item) in items.iter().enumerate() {
+            if let Some(schema) = node.prefix_items.get(i).copied().or(node.items) {
+                self.validate_node(schema, item, &format!("{path}/{i}"), out);
+            }
+        }
+    }
+
+    fn validate_object(
+        &self,
+        node: &Node,
+        object: &Map<String, Value>,
+        path: &str,
+        out: &mut Vec<Violation>,
+    ) {
+        for key in &node.required {
+            if !object.contains_key(key) {
+                out.push(violation(
+                    format!("{}/{}", path, escape(key)),
+                    "required",
+                    "This property is required",
+                    None,
+                ));
+            }
+        }
+        for (key, item) in object {
+            let item_path = format!("{}/{}", path, escape(key));
+            if let Some((_, schema)) = node.properties.iter().find(|(name, _)| name == key) {
+                self.validate_node(*schema, item, &item_path, out);
+            } else if node.no_additional {
+                out.push(violation(
+                    item_path,
+                    "properties",
+                    "Property is not allowed",
+                    None,
+                ));
+            } else if let Some(schema) = node.additional_properties {
+                self.validate_node(schema, item, &item_path, out);
+            }
+        }
+    }
+
+    /// Validate `allOf`, `anyOf` and `oneOf`.
+    fn validate_combinators(
+        &self,
+        node: &Node,
+        value: &Value,
+        path: &str,
+        out: &mut Vec<Violation>,
+    ) {
+        for schema in &node.all_of {
+            self.validate_node(*schema, value, path, out);
+        }
+        let matching = |schemas: &[usize]| {
+            schemas
+                .iter()
+                .filter(|schema| {
+                    let mut scratch = Vec::new();
+                    self.validate_node(**schema, value, path, &mut scratch);
+                    scratch.is_empty()
+                })
+                .count()
+        };
+        if !node.any_of.is_empty() && matching(&node.any_of) == 0 {
+            out.push(violation(
+                path.to_string(),
+                "any_of",
+                "Value does not match any of the schemas",
+                None,
+            ));
+        }
+        if !node.one_of.is_empty() && matching(&node.one_of) != 1 {
+            out.push(violation(
+                path.to_string(),
+                "one_of",
+                "Value does not match exactly one of the schemas",
+                None,
+            ));
+        }
+    }
+}
+
+impl Type {
+    fn parse(name: &str) -> EyreResult<Self> {
+        Ok(match name {
+            "null" => Self::Null,
+            "boolean" => Self::Boolean,
+            "object" => Self::Object,
+            "array" => Self::Array,
+            "number" => Self::Number,
+            "integer" => Self::Integer,
+            "string" => Self::String,
+            _ => bail!("Unknown type {}", name),
+        })
+    }
+
+    fn matches(self, value: &Value) -> bool {
+        match (self, value) {
+            (Self::Null, Value::Null)
+            | (Self::Boolean, Value::Bool(_))
+            | (Self::Object, Value::Object(_))
+            | (Self::Array, Value::Array(_))
+            | (Self::Number, Value::Number(_))
+            | (Self::String, Value::String(_)) => true,
+            (Self::Integer, Value::Number(number)) => {
+                number.is_i64()
+                    || number.is_u64()
+                    || number.as_f64().map_or(false, |f| f.fract() == 0.0)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Compiler<'_> {
+    /// Compile the schema at `pointer`, returning its node index.
+    fn compile(&mut self, pointer: &str) -> EyreResult<usize> {
+        if let Some(index) = self.by_pointer.get(pointer) {
+            return Ok(*index);
+        }
+        let index = self.nodes.len();
+        self.nodes.push(None);
+        self.by_pointer.insert(pointer.to_string(), index);
+        let node = match self.document.pointer(pointer) {
+            Some(Value::Bool(true)) => Node::default(),
+            // Nothing is in an empty enum.
+            Some(Value::Bool(false)) => Node {
+                enumeration: Some(Vec::new()),
+                ..Node::default()
+            },
+            Some(Value::Object(schema)) => self
+                .compile_object(pointer, schema)
+                .wrap_err_with(|| format!("In schema #{pointer}"))?,
+            Some(_) => bail!("Schema #{} is not an object or boolean", pointer),
+            None => bail!("No schema at #{}", pointer),
+        };
+        self.nodes[index] = Some(node);
+        Ok(index)
+    }
+
+    fn compile_object(&mut self, pointer: &str, schema: &Map<String, Value>) -> EyreResult<Node> {
+        let mut node = Node::default();
+        for (keyword, value) in schema {
+            let at = format!("{}/{}", pointer, escape(keyword));
+            match keyword.as_str() {
+                "type" => {
+                    let names = match value {
+                        Value::Array(names) => names.iter().collect(),
+                        name => vec![name],
+                    };
+                    node.types = Some(
+                        names
+                            .into_iter()
+                            .map(|name| Type::parse(name.as_str().unwrap_or_default()))
+                            .collect::<EyreResult<_>>()?,
+                    );
+                }
+                "enum" => {
+                    let values = value.as_array().ok_or_else(|| eyre!("Invalid enum"))?;
+                    node.enumeration = Some(values.clone());
+                }
+                "const" => node.enumeration = Some(vec![value.clone()]),
+                "properties" => {
+                    let properties = value
+                        .as_object()
+                        .ok_or_else(|| eyre!("Invalid properties"))?;
+                    for name in properties.keys() {
+                        let index = self.compile(&format!("{}/{}", at, escape(name)))?;
+                        node.properties.push((name.clone(), index));
+                    }
+                }
+                "required" => {
+                    node.required = value
+                        .as_array()
+                        .and_then(|names| {
+                            names
+                                .iter()
+                                .map(|name| name.as_str().map(String::from))
+                                .collect()
+                        })
+                        .ok_or_else(|| eyre!("Invalid required"))?;
+                }
+                "additionalProperties" => {
+                    if value == &Value::Bool(false) {
+                        node.no_additional = true;
+                    } else {
+                        node.additional_properties = Some(self.compile(&at)?);
+                    }
+                }
+                // Draft 7 tuples are arrays of `items`.
+                "items" if value.is_array() => node.prefix_items = self.compile_all(&at, value)?,
+                "items" => node.items = Some(self.compile(&at)?),
+                "prefixItems" => node.prefix_items = self.compile_all(&at, value)?,
+                "minItems" => node.min_items = Some(count(keyword, value)?),
+                "maxItems" => node.max_items = Some(count(keyword, value)?),
+                "minLength" => node.min_length = Some(count(keyword, value)?),
+                "maxLength" => node.max_length = Some(count(keyword, value)?),
+                "pattern" => {
+                    let pattern = value.as_str().ok_or_else(|| eyre!("Invalid pattern"))?;
+                    node.pattern = Some(Regex::new(pattern)?);
+                }
+                "minimum" => {
+                    node.minimum = Some(value.as_f64().ok_or_else(|| eyre!("Invalid minimum"))?);
+                }
+                "maximum" => {
+                    node.maximum = Some(value.as_f64().ok_or_else(|| eyre!("Invalid maximum"))?);
+                }
+                "allOf" => node.all_of.extend(self.compile_all(&at, value)?),
+                "anyOf" => node.any_of = self.compile_all(&at, value)?,
+                "oneOf" => node.one_of = self.compile_all(&at, value)?,
+                "$ref" => {
+                    let target = value
+                        .as_str()
+                        .and_then(|reference| reference.strip_prefix('#'))
+                        .ok_or_else(|| eyre!("Only local $refs are supported"))?;
+                    node.all_of.push(self.compile(target)?);
+                }
+                keyword if ANNOTATIONS.contains(&keyword) => {}
+                keyword => bail!("Unsupported keyword {}", keyword),
+            }
+        }
+        Ok(node)
+    }
+
+    /// Compile the array of schemas at `pointer`.
+    fn compile_all(&mut self, pointer: &str, value: &Value) -> EyreResult<Vec<usize>> {
+        let len = value
+            .as_array()
+            .ok_or_else(|| eyre!("Expected an array of schemas at #{}", pointer))?
+            .len();
+        (0..len)
+            .map(|i| self.compile(&format!("{pointer}/{i}")))
+            .collect()
+    }
+}
+
+fn count(keyword: &str, value: &Value) -> EyreResult<usize> {
+    value
+        .as_u64()
+        .and_then(|n| usize::try_from(n).ok())
+        .ok_or_else(|| eyre!("Invalid {}", keyword))
+}
+
+fn violation(path: String, code: &str, title: &str, detail: Option<String>) -> Violation {
+    Violation {
+        path,
+        code: code.to_string(),
+        title: title.to_string(),
+        detail,
+    }
+}
+
+/// Escape a key for use in a JSON pointer.
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use kzg_ceremony_crypto::ContributionsJson;
+    use serde_json::json;
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn test_validate() {
+        let schema = ContributionSchema::compile(&json!({
+            "type": "object",
+            "required": ["subContributions"],
+            "properties": {
+                "subContributions": {
+                    "type": "array",
+                    "minItems": 1,
+                    "items": {
+                        "type": "object",
+                        "required": ["numG1Powers", "powersOfTau"],
+                        "properties": {
+                            "numG1Powers": { "type": "integer" },
+                            "potPubkey": { "type": ["string", "null"], "pattern": "^0x[a-f0-9]{192}$" }
+                        }
+                    }
+                }
+            }
+        }))
+        .unwrap();
+
+        let valid = serde_json::to_value(ContributionsJson::initial()).unwrap();
+        schema.validate(&valid).unwrap();
+
+        let mut invalid = valid;
+        invalid["subContributions"][1]["numG1Powers"] = json!("8192");
+        invalid["subContributions"][2]["potPubkey"] = json!("0x00");
+        invalid["subContributions"][3]
+            .as_object_mut()
+            .unwrap()
+            .remove("powersOfTau");
+        let error = schema.validate(&invalid).unwrap_err();
+        let paths = error
+            .violations
+            .iter()
+            .map(|v| v.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec![
+            "/subContributions/1/numG1Powers",
+            "/subContributions/2/potPubkey",
+            "/subContributions/3/powersOfTau"
+        ]);
+    }
+
+    #[test]
+    fn test_refs() {
+        let schema = ContributionSchema::compile(&json!({
+            "$defs": {
+                "g1": { "type": "string", "pattern": "^0x[a-f0-9]{96}$" },
+                "list": {
+                    "type": "array",
+                    "items": { "anyOf": [{ "$ref": "#/$defs/g1" }, { "$ref": "#/$defs/list" }] }
+                }
+            },
+            "type": "object",
+            "additionalProperties": false,
+            "properties": {
+                "points": { "$ref": "#/$defs/list" },
+                "count": { "const": 2 }
+            }
+        }))
+        .unwrap();
+        let g1 = format!("0x{}", "a".repeat(96));
+        schema
+            .validate(&json!({ "points": [g1, [g1]], "count": 2 }))
+            .unwrap();
+        let error = schema
+            .validate(&json!({ "points": [[g1, "0x"]], "count": 3, "extra": 1 }))
+            .unwrap_err();
+        let codes = error
+            .violations
+            .iter()
+            .map(|v| (v.path.as_str(), v.code.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(codes, vec![
+            ("/count", "enum"),
+            ("/extra", "properties"),
+            ("/points/0", "any_of")
+        ]);
+
+        assert!(ContributionSchema::compile(&json!({ "uniqueItems": true })).is_err());
+        assert!(ContributionSchema::compile(&json!({ "$ref": "#/missing" })).is_err());
+        assert!(ContributionSchema::compile(&json!({ "pattern": "(" })).is_err());
+    }
+
+    #[test]
+    fn test_concurrent() {
+        let schema = Arc::new(
+            ContributionSchema::compile(
+                &json!({ "type": "array", "items": { "type": "integer" } }),
+            )
+            .unwrap(),
+        );
+        let threads = (0..4)
+            .map(|i| {
+                let schema = schema.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        assert!(schema.validate(&json!([i, i + 1])).is_ok());
+                        assert!(schema.validate(&json!([i, "x"])).is_err());
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+}
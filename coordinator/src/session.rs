@@ -0,0 +1,360 @@
+//! Participant sessions.
+//!
+//! A session token is issued after login, see [`crate::auth`], and presented as
+//! a bearer token on subsequent requests. Tokens are self-contained: they carry
+//! the identity and expiry, authenticated with an HMAC under the coordinator's
+//! session key. Tokens can be revoked before they expire, see
+//! [`crate::revocation`].
+//!
+//! Tokens name the key that signed them. After a rotation new tokens are
+//! signed with the new key, while tokens of previous keys stay valid until
+//! they expire or their key is revoked, see [`SessionKeys`].
+
+use crate::{audit::AuditLog, pseudonym::Pseudonyms, revocation::Revocations};
+use axum::{
+    async_trait,
+    extract::{FromRequest, RequestParts},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use eyre::Error as EyreError;
+use hmac::{Hmac, Mac};
+use rand::{CryptoRng, Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum SessionError {
+    #[error("Missing bearer token")]
+    MissingToken,
+    #[error("Malformed session token")]
+    InvalidToken,
+    #[error("Invalid session token signature")]
+    InvalidSignature,
+    #[error("Session expired")]
+    Expired,
+    #[error("Session revoked")]
+    Revoked,
+}
+
+/// Session keys that can be rotated while the server runs.
+pub type SharedSessionKeys = Arc<RwLock<SessionKeys>>;
+
+/// Secret used to authenticate session tokens.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SessionKey([u8; 32]);
+
+/// The key issuing tokens and previous keys still accepted.
+#[derive(Clone, Debug)]
+pub struct SessionKeys {
+    active:   SessionKey,
+    previous: Vec<SessionKey>,
+}
+
+/// An authenticated participant session.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Session {
+    /// Identity of the participant, e.g. `git|1234|recmo`.
+    #[serde(rename = "sub")]
+    pub identity:   String,
+    /// Expiry in seconds since the unix epoch.
+    #[serde(rename = "exp")]
+    pub expires:    u64,
+    /// Issue time in seconds since the unix epoch, `0` for tokens from before
+    /// it was recorded.
+    #[serde(rename = "iat", default)]
+    pub issued:     u64,
+    /// Id of the signing key, see [`SessionKey::id`].
+    #[serde(rename = "kid", default, skip_serializing_if = "Option::is_none")]
+    pub key_id:     Option<String>,
+    /// Facts about the identity established at login.
+    #[serde(flatten)]
+    pub attributes: Attributes,
+    /// What the coordinator stores instead of the identity, the identity
+    /// itself unless pseudonymization is enabled, see [`crate::pseudonym`].
+    #[serde(skip)]
+    pub pseudonym:  String,
+}
+
+/// Identity facts used by the participation policy.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct Attributes {
+    /// Creation time of the GitHub account in seconds since the unix epoch.
+    #[serde(rename = "created", default, skip_serializing_if = "Option::is_none")]
+    pub account_created: Option<u64>,
+    /// Transaction count of the Ethereum account.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce:           Option<u64>,
+}
+
+impl SessionKey {
+    #[must_use]
+    pub fn random() -> Self {
+        Self::from_rng(&mut rand::thread_rng())
+    }
+
+    #[must_use]
+    pub fn from_rng<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        Self(rng.gen())
+    }
+
+    /// The secret key, hex encoded as accepted by `--session-key`.
+    #[must_use]
+    pub fn to_hex(&self) -> String {
+        format!("0x{}", hex::encode(self.0))
+    }
+
+    /// Public identifier of the key, the first bytes of its hash.
+    #[must_use]
+    pub fn id(&self) -> String {
+        hex::encode(&Sha256::digest(self.0)[..4])
+    }
+
+    /// Issue a token for `identity` valid for `lifetime`.
+    #[must_use]
+    pub fn issue(&self, identity: &str, lifetime: Duration) -> String {
+        self.issue_with(identity, lifetime, Attributes::default())
+    }
+
+    /// Issue a token for `identity` carrying `attributes`.
+    #[must_use]
+    pub fn issue_with(&self, identity: &str, lifetime: Duration, attributes: Attributes) -> String {
+        let session = Session {
+            identity: identity.to_string(),
+            expires: (now() + lifetime).as_secs(),
+            issued: now().as_secs(),
+            key_id: Some(self.id()),
+            attributes,
+            pseudonym: String::new(),
+        };
+        let payload = hex::encode(serde_json::to_vec(&session).unwrap());
+        let signature = hex::encode(self.mac(&payload).finalize().into_bytes());
+        format!("{payload}.{signature}")
+    }
+
+    pub fn verify(&self, token: &str) -> Result<Session, SessionError> {
+        let (payload, signature) = token.split_once('.').ok_or(SessionError::InvalidToken)?;
+        let signature = hex::decode(signature).map_err(|_| SessionError::InvalidToken)?;
+        self.mac(payload)
+            .verify_slice(&signature)
+            .map_err(|_| SessionError::InvalidSignature)?;
+        let session = Session::decode_unverified(token)?;
+        if session.expires <= now().as_secs() {
+            return Err(SessionError::Expired);
+        }
+        Ok(session)
+    }
+
+    fn mac(&self, payload: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC accepts any key size");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+impl SessionKeys {
+    #[must_use]
+    pub const fn new(active: SessionKey, previous: Vec<SessionKey>) -> Self {
+        Self { active, previous }
+    }
+
+    /// Id of the key issuing tokens.
+    #[must_use]
+    pub fn active_id(&self) -> String {
+        self.active.id()
+    }
+
+    /// Ids of the previous keys still accepted.
+    #[must_use]
+    pub fn previous_ids(&self) -> Vec<String> {
+        self.previous.iter().map(SessionKey::id).collect()
+    }
+
+    /// Issue a token with the active key, see [`SessionKey::issue_with`].
+    #[must_use]
+    pub fn issue_with(&self, identity: &str, lifetime: Duration, attributes: Attributes) -> String {
+        self.active.issue_with(identity, lifetime, attributes)
+    }
+
+    /// Verify a token of the active or a previous key. Tokens without a key id
+    /// predate rotation and are checked against every key.
+    pub fn verify(&self, token: &str) -> Result<Session, SessionError> {
+        let key_id = Session::decode_unverified(token)?.key_id;
+        let mut result = Err(SessionError::InvalidSignature);
+        for key in std::iter::once(&self.active).chain(&self.previous) {
+            if key_id.as_ref().map_or(true, |id| *id == key.id()) {
+                result = key.verify(token);
+                if result != Err(SessionError::InvalidSignature) {
+                    break;
+                }
+            }
+        }
+        result
+    }
+
+    /// Issue tokens with `key` from now on, keeping the current key valid.
+    /// Returns the id of the new key.
+    pub fn rotate(&mut self, key: SessionKey) -> String {
+        let id = key.id();
+        let previous = std::mem::replace(&mut self.active, key);
+        self.previous.push(previous);
+        id
+    }
+
+    /// Invalidate the tokens of the previous key `id`. The active key can not
+    /// be revoked, rotate first.
+    pub fn revoke(&mut self, id: &str) -> bool {
+        let before = self.previous.len();
+        self.previous.retain(|key| key.id() != id);
+        self.previous.len() != before
+    }
+
+    /// Invalidate the tokens of all previous keys.
+    pub fn revoke_previous(&mut self) {
+        self.previous.clear();
+    }
+}
+
+impl Session {
+    /// Read the session from a token without checking its signature.
+    ///
+    /// For clients, which do not know the session key.
+    pub fn decode_unverified(token: &str) -> Result<Self, SessionError> {
+        let (payload, _) = token.split_once('.').ok_or(SessionError::InvalidToken)?;
+        let payload = hex::decode(payload).map_err(|_| SessionError::InvalidToken)?;
+        serde_json::from_slice(&payload).map_err(|_| SessionError::InvalidToken)
+    }
+}
+
+impl fmt::Debug for SessionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SessionKey(..)")
+    }
+}
+
+impl FromStr for SessionKey {
+    type Err = EyreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut key = [0; 32];
+        hex::decode_to_slice(s.strip_prefix("0x").unwrap_or(s), &mut key)?;
+        Ok(Self(key))
+    }
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Session {
+    type Rejection = SessionError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Extension(key) = Extension::<SharedSessionKeys>::from_request(req)
+            .await
+            .expect("SessionKeys extension missing");
+        let token = bearer_token(req.headers())
+            .ok_or(SessionError::MissingToken)?
+            .to_string();
+        let mut session = key.read().unwrap().verify(&token)?;
+        session.pseudonym = match Extension::<Arc<Pseudonyms>>::from_request(req).await {
+            Ok(Extension(pseudonyms)) => pseudonyms.pseudonym(&session.identity),
+            Err(_) => session.identity.clone(),
+        };
+        if let Ok(Extension(revocations)) = Extension::<Arc<Revocations>>::from_request(req).await {
+            if revocations.is_revoked(&token, &session) {
+                return Err(SessionError::Revoked);
+            }
+        }
+        if let Ok(Extension(audit)) = Extension::<Arc<AuditLog>>::from_request(req).await {
+            audit.login(&session);
+        }
+        Ok(session)
+    }
+}
+
+impl IntoResponse for SessionError {
+    fn into_response(self) -> Response {
+        let body = Json(json!({ "error": self.to_string() }));
+        (StatusCode::UNAUTHORIZED, body).into_response()
+    }
+}
+
+/// The bearer token in the `Authorization` header.
+#[must_use]
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+fn now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time before unix epoch")
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_issue_verify() {
+        let key = SessionKey::random();
+        let token = key.issue("git|1|recmo", Duration::from_secs(60));
+        let session = key.verify(&token).unwrap();
+        assert_eq!(session.identity, "git|1|recmo");
+
+        assert_eq!(
+            SessionKey::random().verify(&token),
+            Err(SessionError::InvalidSignature)
+        );
+        assert_eq!(key.verify("garbage"), Err(SessionError::InvalidToken));
+        let expired = key.issue("git|1|recmo", Duration::ZERO);
+        assert_eq!(key.verify(&expired), Err(SessionError::Expired));
+    }
+
+    #[test]
+    fn test_rotation() {
+        let first = SessionKey::random();
+        let mut keys = SessionKeys::new(first.clone(), vec![]);
+        let old = keys.issue_with(
+            "git|1|recmo",
+            Duration::from_secs(60),
+            Attributes::default(),
+        );
+        let second = SessionKey::random();
+        assert_eq!(second.to_hex().parse::<SessionKey>().unwrap(), second);
+        let id = keys.rotate(second);
+        let new = keys.issue_with(
+            "git|1|recmo",
+            Duration::from_secs(60),
+            Attributes::default(),
+        );
+        assert_eq!(keys.verify(&new).unwrap().key_id, Some(id));
+        assert_eq!(keys.verify(&old).unwrap().key_id, Some(first.id()));
+
+        // Tokens from before key ids.
+        let legacy = first.issue("git|1|recmo", Duration::from_secs(60));
+        let payload = hex::decode(legacy.split_once('.').unwrap().0).unwrap();
+        let mut session: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        session.as_object_mut().unwrap().remove("kid");
+        let payload = hex::encode(serde_json::to_vec(&session).unwrap());
+        let signature = hex::encode(first.mac(&payload).finalize().into_bytes());
+        let legacy = format!("{payload}.{signature}");
+        assert_eq!(keys.verify(&legacy).unwrap().key_id, None);
+
+        assert!(!keys.revoke(&keys.active_id()));
+        assert!(keys.revoke(&first.id()));
+        assert_eq!(keys.verify(&old), Err(SessionError::InvalidSignature));
+        assert_eq!(keys.verify(&legacy), Err(SessionError::InvalidSignature));
+        assert!(keys.verify(&new).is_ok());
+    }
+}
@@ -0,0 +1,96 @@
+//! Graceful shutdown.
+//!
+//! On `SIGINT` or `SIGTERM` the coordinator winds down before the server
+//! stops:
+//!
+//! 1. The queue is closed, so nobody can join or start contributing.
+//! 2. The participant holding the active slot may still upload, until they
+//!    finish, abort or miss the deadline.
+//! 3. The ceremony lock is taken, so a contribution being verified is written
+//!    to storage before exiting. Appends are synced to disk as they happen.
+//! 4. The waiting participants are persisted to `queue.json` in the transcript
+//!    directory, and put back in the queue on the next start, together with a
+//!    last snapshot of the queue analytics.
+//!
+//! Only then does the server stop, letting open requests complete.
+
+use crate::{ceremony::SharedCeremony, queue::Queue, storage::write_atomic};
+use cli_batteries::await_shutdown;
+use eyre::{Result as EyreResult, WrapErr};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::time::sleep;
+use tracing::{error, info};
+
+const FILE: &str = "queue.json";
+
+/// Interval to check whether the active participant finished.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// State to wind down before exiting.
+#[derive(Clone, Debug)]
+pub struct Drain {
+    queue:    Arc<Queue>,
+    ceremony: SharedCeremony,
+    dir:      PathBuf,
+}
+
+impl Drain {
+    #[must_use]
+    pub fn new(queue: Arc<Queue>, ceremony: SharedCeremony, dir: &Path) -> Self {
+        Self {
+            queue,
+            ceremony,
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// Put the participants persisted on the last shutdown back in the queue.
+    pub fn restore(&self) -> EyreResult<()> {
+        let path = self.dir.join(FILE);
+        if !path.exists() {
+            return Ok(());
+        }
+        let contents =
+            fs::read(&path).wrap_err_with(|| format!("Could not read {}", path.display()))?;
+        let waiting: Vec<String> = serde_json::from_slice(&contents)?;
+        self.queue.restore(&waiting);
+        // A crash must not restore a stale queue.
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    /// Wait for the shutdown signal, then wind down.
+    pub async fn on_shutdown(self) {
+        await_shutdown().await;
+        self.drain().await;
+    }
+
+    pub async fn drain(self) {
+        info!("Shutting down, waiting for the active contribution");
+        self.queue.close();
+        while !self.queue.is_idle() {
+            sleep(POLL_INTERVAL).await;
+        }
+        let ceremony = self.ceremony.lock().await;
+        info!(
+            contributions = ceremony.num_contributions(),
+            "Transcript flushed"
+        );
+        let waiting = self.queue.waiting();
+        let result = serde_json::to_vec(&waiting)
+            .map_err(Into::into)
+            .and_then(|json| write_atomic(&self.dir.join(FILE), &json));
+        match result {
+            Ok(()) => info!(waiting = waiting.len(), "Queue persisted"),
+            Err(error) => error!(?error, "Could not persist the queue"),
+        }
+        if let Err(error) = self.queue.analytics().persist() {
+            error!(?error, "Could not persist queue analytics");
+        }
+    }
+}
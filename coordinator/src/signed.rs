@@ -0,0 +1,97 @@
+//! Signed API responses.
+//!
+//! Responses of `/ceremony/status` and the checkpoint routes carry an
+//! `X-Coordinator-Signature` header with the EIP-191 signature of the response
+//! body by the coordinator key, see [`crate::signing`]. Mirrors and monitoring
+//! tools relaying these responses can pass on the body and signature, and
+//! anyone can check them against the coordinator address with
+//! [`crate::signing::verify`]. The signature covers the body before any
+//! `Content-Encoding` is applied.
+//!
+//! Only successful responses are signed. The status includes the time it was
+//! served, so a relayed status can not pass for a more recent one.
+//!
+//! `GET /ceremony/keys` lists the active, previous and revoked coordinator
+//! addresses, so signatures and receipts can be checked after a key rotation
+//! with [`crate::signing::verify_any`].
+
+use crate::signing::{CoordinatorKeys, KeySet};
+use axum::{
+    body::{boxed, Body, Full},
+    http::{HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use std::sync::Arc;
+use tracing::warn;
+
+pub const SIGNATURE_HEADER: &str = "x-coordinator-signature";
+
+/// Sign successful responses with the coordinator key.
+pub async fn middleware(request: Request<Body>, next: Next<Body>) -> Response {
+    let key = request
+        .extensions()
+        .get::<Arc<CoordinatorKeys>>()
+        .cloned()
+        .expect("CoordinatorKeys extension missing");
+    let response = next.run(request).await;
+    if !response.status().is_success() {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let bytes = match hyper::body::to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            warn!(?error, "Could not read response to sign");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let signature = key.sign(&bytes);
+    parts.headers.insert(
+        SIGNATURE_HEADER,
+        HeaderValue::from_str(&signature).expect("Hex is a valid header value"),
+    );
+    Response::from_parts(parts, boxed(Full::from(bytes)))
+}
+
+/// The coordinator keys.
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn keys(Extension(keys): Extension<Arc<CoordinatorKeys>>) -> Json<KeySet> {
+    Json(keys.keys())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::signing::{verify, CoordinatorKey};
+    use axum::{middleware::from_fn, routing::get, Extension, Router};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_signed() {
+        let key = Arc::new(CoordinatorKeys::new(CoordinatorKey::random(), &[]));
+        let address = key.address();
+        let app = Router::new()
+            .route(
+                "/signed",
+                get(|| async { "status" }).layer(from_fn(middleware)),
+            )
+            .route("/unsigned", get(|| async { "other" }))
+            .layer(Extension(key));
+
+        let get = |path: &str| Request::builder().uri(path).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(get("/signed")).await.unwrap();
+        let signature = response.headers()[SIGNATURE_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(body, "status");
+        verify(&body, &signature, address).unwrap();
+        assert!(verify(b"forged", &signature, address).is_err());
+
+        let response = app.oneshot(get("/unsigned")).await.unwrap();
+        assert!(!response.headers().contains_key(SIGNATURE_HEADER));
+    }
+}
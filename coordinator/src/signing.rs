@@ -0,0 +1,382 @@
+//! Coordinator signing key.
+//!
+//! Messages are signed the same way as Ethereum's `personal_sign`, so
+//! participants can verify coordinator statements with standard wallet
+//! tooling and identify the coordinator by its Ethereum address.
+//!
+//! The key can be rotated while the ceremony runs, see [`CoordinatorKeys`].
+//! Signatures by previous keys stay valid unless the key is revoked, and the
+//! address of the signing key doubles as its key id.
+
+use eyre::{eyre, Error as EyreError};
+use k256::{
+    ecdsa::{recoverable, signature::hazmat::PrehashSigner, SigningKey, VerifyingKey},
+    elliptic_curve::sec1::ToEncodedPoint,
+};
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+use thiserror::Error;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum SignatureError {
+    #[error("Invalid signature encoding")]
+    InvalidEncoding,
+    #[error("Could not recover signer from signature")]
+    RecoveryFailed,
+    #[error("Signed by {0}, expected {1}")]
+    WrongSigner(Address, Address),
+    #[error("Signed by {0}, which is not a trusted coordinator key")]
+    UntrustedSigner(Address),
+}
+
+/// An Ethereum address.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Address(pub [u8; 20]);
+
+#[derive(Clone)]
+pub struct CoordinatorKey(SigningKey);
+
+/// The active coordinator key and the addresses of previous keys.
+#[derive(Debug)]
+pub struct CoordinatorKeys {
+    active: RwLock<Arc<CoordinatorKey>>,
+    keys:   RwLock<KeySet>,
+}
+
+/// Addresses of the coordinator keys, as published on `GET /ceremony/keys`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeySet {
+    /// Key signing from now on.
+    pub active:   String,
+    /// Keys that signed before and are still trusted.
+    pub previous: Vec<String>,
+    /// Keys that must no longer be trusted, e.g. because they leaked.
+    pub revoked:  Vec<String>,
+}
+
+impl Address {
+    #[must_use]
+    pub fn from_verifying_key(key: &VerifyingKey) -> Self {
+        let point = key.to_encoded_point(false);
+        let hash = Keccak256::digest(&point.as_bytes()[1..]);
+        let mut address = [0; 20];
+        address.copy_from_slice(&hash[12..]);
+        Self(address)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{}", hex::encode(self.0))
+    }
+}
+
+impl fmt::Debug for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl FromStr for Address {
+    type Err = EyreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.strip_prefix("0x").unwrap_or(s);
+        let mut address = [0; 20];
+        hex::decode_to_slice(s, &mut address)?;
+        Ok(Self(address))
+    }
+}
+
+impl CoordinatorKey {
+    #[must_use]
+    pub fn random() -> Self {
+        Self::from_rng(&mut rand::thread_rng())
+    }
+
+    #[must_use]
+    pub fn from_rng<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        Self(SigningKey::random(rng))
+    }
+
+    #[must_use]
+    pub fn address(&self) -> Address {
+        Address::from_verifying_key(&self.0.verifying_key())
+    }
+
+    /// Sign `message` as Ethereum's `personal_sign` would.
+    ///
+    /// Returns the 65 byte `r || s || v` signature as `0x` prefixed hex.
+    #[must_use]
+    pub fn sign(&self, message: &[u8]) -> String {
+        self.sign_digest(&personal_digest(message).finalize().into())
+    }
+
+    /// Sign a pre-hashed message, such as an EIP-712 digest.
+    ///
+    /// # Panics
+    ///
+    /// Never, any 32 byte digest can be signed.
+    #[must_use]
+    pub fn sign_digest(&self, digest: &[u8; 32]) -> String {
+        let signature: recoverable::Signature = self
+            .0
+            .sign_prehash(digest)
+            .expect("Signing a 32 byte digest can not fail");
+        let mut bytes = [0; 65];
+        bytes.copy_from_slice(signature.as_ref());
+        // Ethereum uses 27 and 28 for the recovery id.
+        bytes[64] += 27;
+        format!("0x{}", hex::encode(bytes))
+    }
+}
+
+impl fmt::Debug for CoordinatorKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CoordinatorKey({})", self.address())
+    }
+}
+
+impl PartialEq for CoordinatorKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.address() == other.address()
+    }
+}
+
+impl FromStr for CoordinatorKey {
+    type Err = EyreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.strip_prefix("0x").unwrap_or(s);
+        let bytes = hex::decode(s)?;
+        let key = SigningKey::from_bytes(&bytes).map_err(|_| eyre!("Invalid secp256k1 key"))?;
+        Ok(Self(key))
+    }
+}
+
+impl CoordinatorKeys {
+    #[must_use]
+    pub fn new(active: CoordinatorKey, previous: &[Address]) -> Self {
+        let keys = KeySet {
+            active:   active.address().to_string(),
+            previous: previous.iter().map(ToString::to_string).collect(),
+            revoked:  Vec::new(),
+        };
+        Self {
+            active: RwLock::new(Arc::new(active)),
+            keys:   RwLock::new(keys),
+        }
+    }
+
+    /// The key signing from now on.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key lock is poisoned.
+    #[must_use]
+    pub fn active(&self) -> Arc<CoordinatorKey> {
+        self.active.read().unwrap().clone()
+    }
+
+    /// Address of the active key.
+    #[must_use]
+    pub fn address(&self) -> Address {
+        self.active().address()
+    }
+
+    /// Sign `message` with the active key, see [`CoordinatorKey::sign`].
+    #[must_use]
+    pub fn sign(&self, message: &[u8]) -> String {
+        self.active().sign(message)
+    }
+
+    /// The active, previous and revoked key addresses.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key lock is poisoned.
+    #[must_use]
+    pub fn keys(&self) -> KeySet {
+        self.keys.read().unwrap().clone()
+    }
+
+    /// Addresses whose signatures are trusted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key lock is poisoned.
+    #[must_use]
+    pub fn trusted(&self) -> Vec<Address> {
+        let keys = self.keys.read().unwrap();
+        std::iter::once(&keys.active)
+            .chain(&keys.previous)
+            .filter_map(|address| address.parse().ok())
+            .collect()
+    }
+
+    /// Sign with `key` from now on, keeping the current key trusted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a key lock is poisoned.
+    pub fn rotate(&self, key: CoordinatorKey) -> Address {
+        let address = key.address();
+        let mut active = self.active.write().unwrap();
+        let mut keys = self.keys.write().unwrap();
+        let previous = std::mem::replace(&mut keys.active, address.to_string());
+        keys.previous.push(previous);
+        *active = Arc::new(key);
+        drop(keys);
+        drop(active);
+        address
+    }
+
+    /// Stop trusting the previous key `address`. The active key can not be
+    /// revoked, rotate first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key lock is poisoned.
+    pub fn revoke(&self, address: Address) -> bool {
+        let address = address.to_string();
+        let mut keys = self.keys.write().unwrap();
+        let before = keys.previous.len();
+        keys.previous.retain(|previous| *previous != address);
+        if keys.previous.len() == before {
+            return false;
+        }
+        keys.revoked.push(address);
+        true
+    }
+}
+
+/// Recover the address that produced a [`CoordinatorKey::sign`] signature.
+///
+/// # Errors
+///
+/// See [`recover_digest`].
+pub fn recover(message: &[u8], signature: &str) -> Result<Address, SignatureError> {
+    recover_digest(&personal_digest(message).finalize().into(), signature)
+}
+
+/// Recover the address that signed a pre-hashed message, such as an EIP-712
+/// digest.
+///
+/// # Errors
+///
+/// Returns [`SignatureError::InvalidEncoding`] for malformed signatures and
+/// [`SignatureError::RecoveryFailed`] if no key can be recovered.
+pub fn recover_digest(digest: &[u8; 32], signature: &str) -> Result<Address, SignatureError> {
+    let signature = signature.strip_prefix("0x").unwrap_or(signature);
+    let mut bytes = [0; 65];
+    hex::decode_to_slice(signature, &mut bytes).map_err(|_| SignatureError::InvalidEncoding)?;
+    bytes[64] = match bytes[64] {
+        v @ (0 | 1) => v,
+        v @ (27 | 28) => v - 27,
+        _ => return Err(SignatureError::InvalidEncoding),
+    };
+    let signature = recoverable::Signature::try_from(&bytes[..])
+        .map_err(|_| SignatureError::InvalidEncoding)?;
+    let key = signature
+        .recover_verifying_key_from_digest_bytes(digest.into())
+        .map_err(|_| SignatureError::RecoveryFailed)?;
+    Ok(Address::from_verifying_key(&key))
+}
+
+/// Check that `signature` over `message` was produced by `signer`.
+///
+/// # Errors
+///
+/// Returns [`SignatureError::WrongSigner`] if someone else signed, or why the
+/// signer can not be recovered.
+pub fn verify(message: &[u8], signature: &str, signer: Address) -> Result<(), SignatureError> {
+    let recovered = recover(message, signature)?;
+    if recovered != signer {
+        return Err(SignatureError::WrongSigner(recovered, signer));
+    }
+    Ok(())
+}
+
+/// Check that `signature` over `message` was produced by one of `trusted`, and
+/// return the signer.
+///
+/// # Errors
+///
+/// Returns [`SignatureError::UntrustedSigner`] if someone else signed, or why
+/// the signer can not be recovered.
+pub fn verify_any(
+    message: &[u8],
+    signature: &str,
+    trusted: &[Address],
+) -> Result<Address, SignatureError> {
+    let recovered = recover(message, signature)?;
+    if !trusted.contains(&recovered) {
+        return Err(SignatureError::UntrustedSigner(recovered));
+    }
+    Ok(recovered)
+}
+
+/// Hash according to EIP-191 version `0x45`.
+fn personal_digest(message: &[u8]) -> Keccak256 {
+    let mut digest = Keccak256::new();
+    digest.update(format!("\x19Ethereum Signed Message:\n{}", message.len()));
+    digest.update(message);
+    digest
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_known_address() {
+        // Well known development key (Hardhat account #0).
+        let key: CoordinatorKey =
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            key.address().to_string(),
+            "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266"
+        );
+    }
+
+    #[test]
+    fn test_sign_recover() {
+        let key = CoordinatorKey::random();
+        let signature = key.sign(b"hello");
+        assert_eq!(recover(b"hello", &signature).unwrap(), key.address());
+        assert!(verify(b"hello", &signature, key.address()).is_ok());
+        assert!(verify(b"goodbye", &signature, key.address()).is_err());
+    }
+
+    #[test]
+    fn test_rotation() {
+        let first = CoordinatorKey::random();
+        let keys = CoordinatorKeys::new(first.clone(), &[]);
+        let old = keys.sign(b"hello");
+        let second = keys.rotate(CoordinatorKey::random());
+        let new = keys.sign(b"hello");
+        assert_eq!(keys.address(), second);
+        assert_eq!(
+            verify_any(b"hello", &old, &keys.trusted()),
+            Ok(first.address())
+        );
+        assert_eq!(verify_any(b"hello", &new, &keys.trusted()), Ok(second));
+
+        assert!(!keys.revoke(second));
+        assert!(keys.revoke(first.address()));
+        assert_eq!(
+            verify_any(b"hello", &old, &keys.trusted()),
+            Err(SignatureError::UntrustedSigner(first.address()))
+        );
+        assert_eq!(keys.keys().revoked, vec![first.address().to_string()]);
+    }
+}
@@ -0,0 +1,329 @@
+//! Load test with virtual contributors.
+//!
+//! Runs `--contributors` concurrent participants against a coordinator in
+//! `--test-ceremony` mode. Each logs in, joins the queue, waits for the slot
+//! and then either contributes, aborts, or holds the slot without uploading
+//! until the coordinator times it out, at the given rates. Without a url, a
+//! test ceremony coordinator is started in-process on a local port.
+//!
+//! The report lists the outcomes, the contribution throughput and the latency
+//! percentiles of every step.
+
+use crate::{build, client::CeremonyClient, entropy::Entropy, test_ceremony};
+use axum::Server;
+use clap::Parser;
+use eyre::{ensure, Result as EyreResult};
+use futures::future::join_all;
+use rand::Rng;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    env::temp_dir,
+    fs,
+    net::{Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tokio::spawn;
+use tracing::{info, warn};
+use url::Url;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+pub struct Options {
+    /// Base url of a coordinator running `--test-ceremony`. Starts one
+    /// in-process if omitted.
+    pub url: Option<Url>,
+
+    /// Number of virtual contributors.
+    #[clap(long, default_value = "10")]
+    pub contributors: usize,
+
+    /// Percentage of contributors that abort once they hold the slot.
+    #[clap(long, default_value = "10")]
+    pub abort_percent: u32,
+
+    /// Percentage of contributors that hold the slot without uploading until
+    /// the coordinator times them out.
+    #[clap(long, default_value = "0")]
+    pub timeout_percent: u32,
+
+    /// Contribution timeout in seconds of the in-process coordinator.
+    #[clap(long, default_value = "30")]
+    pub contribution_timeout: u64,
+
+    /// Seed for the contributors' choices, entropy and proof nonces.
+    #[clap(long, default_value = "0")]
+    pub seed: u64,
+
+    /// Milliseconds between attempts to take the contribution slot.
+    #[clap(long, default_value = "100")]
+    pub poll_interval: u64,
+
+    /// Write the report to this file instead of stdout.
+    #[clap(long)]
+    pub report: Option<PathBuf>,
+}
+
+/// What a virtual contributor ended up doing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Outcome {
+    Contributed,
+    Aborted,
+    TimedOut,
+    Failed,
+}
+
+/// Steps of a virtual contributor that are timed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Step {
+    Login,
+    Join,
+    /// From joining the queue until holding the slot.
+    Wait,
+    Contribute,
+    Abort,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub contributors: usize,
+    pub elapsed_secs: f64,
+    /// Accepted contributions per second.
+    pub throughput:   f64,
+    pub outcomes:     BTreeMap<Outcome, usize>,
+    pub latencies:    BTreeMap<Step, Latencies>,
+}
+
+/// Latency percentiles of a step in milliseconds.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Latencies {
+    pub count: usize,
+    pub p50:   f64,
+    pub p99:   f64,
+    pub max:   f64,
+}
+
+/// Outcome and step timings of one virtual contributor.
+#[derive(Clone, Debug)]
+struct Run {
+    outcome: Outcome,
+    timings: Vec<(Step, Duration)>,
+}
+
+pub async fn main(options: Options) -> EyreResult<()> {
+    options.check()?;
+    let (url, dir) = if let Some(url) = &options.url {
+        (url.clone(), None)
+    } else {
+        let dir = temp_dir().join(format!("kzg-simulate-{}", std::process::id()));
+        let url = serve(&dir, options.contribution_timeout).await?;
+        info!(%url, "Started in-process coordinator");
+        (url, Some(dir))
+    };
+    let report = simulate(&options, url).await;
+    if let Some(dir) = dir {
+        fs::remove_dir_all(dir)?;
+    }
+
+    let json = serde_json::to_string_pretty(&report)?;
+    match options.report {
+        Some(path) => fs::write(path, json)?,
+        None => println!("{json}"),
+    }
+    Ok(())
+}
+
+/// Start a test ceremony coordinator on a local port.
+async fn serve(dir: &Path, contribution_timeout: u64) -> EyreResult<Url> {
+    let _ = fs::remove_dir_all(dir);
+    let options = crate::Options::parse_from([
+        "coordinator",
+        "--test-ceremony",
+        "--transcript-dir",
+        &dir.display().to_string(),
+        "--contribution-timeout",
+        &contribution_timeout.to_string(),
+    ]);
+    let (app, _drain) = build(&options).await?;
+    let server = Server::bind(&SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0))
+        .serve(app.into_make_service());
+    let url = format!("http://{}/", server.local_addr()).parse()?;
+    spawn(server);
+    Ok(url)
+}
+
+/// Run the virtual contributors to completion and report on them.
+pub async fn simulate(options: &Options, url: Url) -> Report {
+    let start = Instant::now();
+    let runs = join_all((0..options.contributors).map(|index| {
+        let options = options.clone();
+        let url = url.clone();
+        spawn(async move { contributor(&options, url, index).await })
+    }))
+    .await
+    .into_iter()
+    .map(|result| {
+        result.unwrap_or_else(|error| {
+            warn!(?error, "Virtual contributor panicked");
+            Run {
+                outcome: Outcome::Failed,
+                timings: Vec::new(),
+            }
+        })
+    })
+    .collect::<Vec<_>>();
+    report(options.contributors, start.elapsed(), &runs)
+}
+
+async fn contributor(options: &Options, url: Url, index: usize) -> Run {
+    let seed = options.seed.wrapping_add(index as u64);
+    let mut rng = test_ceremony::rng(seed);
+    let mut client = CeremonyClient::new(url)
+        .with_poll_interval(Duration::from_millis(options.poll_interval))
+        .with_sizes(test_ceremony::SIZES.to_vec());
+    let mut timings = Vec::new();
+    let outcome = match run_steps(options, &mut client, &mut rng, index, &mut timings).await {
+        Ok(outcome) => outcome,
+        Err(error) => {
+            warn!(index, %error, "Virtual contributor failed");
+            Outcome::Failed
+        }
+    };
+    Run { outcome, timings }
+}
+
+async fn run_steps<R: Rng>(
+    options: &Options,
+    client: &mut CeremonyClient,
+    rng: &mut R,
+    index: usize,
+    timings: &mut Vec<(Step, Duration)>,
+) -> EyreResult<Outcome> {
+    let identity = format!("git|{index}|simulated{index}");
+    let start = Instant::now();
+    client.test_login(Some(&identity)).await?;
+    timings.push((Step::Login, start.elapsed()));
+
+    let start = Instant::now();
+    client.join_queue().await?;
+    timings.push((Step::Join, start.elapsed()));
+
+    let start = Instant::now();
+    client.await_turn().await?;
+    timings.push((Step::Wait, start.elapsed()));
+
+    let roll = rng.gen_range(0..100);
+    if roll < options.abort_percent {
+        let start = Instant::now();
+        client.abort().await?;
+        timings.push((Step::Abort, start.elapsed()));
+        return Ok(Outcome::Aborted);
+    }
+    if roll < options.abort_percent + options.timeout_percent {
+        // Walk away with the slot, the coordinator has to reclaim it.
+        return Ok(Outcome::TimedOut);
+    }
+    let mut entropy = Entropy::deterministic();
+    entropy.add_user_input(&identity);
+    let start = Instant::now();
+    client.contribute(&entropy).await?;
+    timings.push((Step::Contribute, start.elapsed()));
+    Ok(Outcome::Contributed)
+}
+
+#[allow(clippy::cast_precision_loss)] // Counts are small
+fn report(contributors: usize, elapsed: Duration, runs: &[Run]) -> Report {
+    let mut outcomes = BTreeMap::new();
+    let mut durations = BTreeMap::<Step, Vec<Duration>>::new();
+    for run in runs {
+        *outcomes.entry(run.outcome).or_default() += 1;
+        for (step, duration) in &run.timings {
+            durations.entry(*step).or_default().push(*duration);
+        }
+    }
+    let contributed = outcomes.get(&Outcome::Contributed).copied().unwrap_or(0);
+    Report {
+        contributors,
+        elapsed_secs: elapsed.as_secs_f64(),
+        throughput: contributed as f64 / elapsed.as_secs_f64(),
+        outcomes,
+        latencies: durations
+            .into_iter()
+            .map(|(step, durations)| (step, latencies(durations)))
+            .collect(),
+    }
+}
+
+fn latencies(mut durations: Vec<Duration>) -> Latencies {
+    durations.sort_unstable();
+    let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    Latencies {
+        count: durations.len(),
+        p50:   millis(percentile(&durations, 50)),
+        p99:   millis(percentile(&durations, 99)),
+        max:   millis(durations.last().copied().unwrap_or_default()),
+    }
+}
+
+/// Nearest-rank percentile of sorted values, zero if there are none.
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (sorted.len() * percent + 99) / 100;
+    sorted[rank.max(1) - 1]
+}
+
+impl Options {
+    fn check(&self) -> EyreResult<()> {
+        ensure!(
+            self.abort_percent + self.timeout_percent <= 100,
+            "Abort and timeout percentages add up to more than 100"
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test::TestDir;
+
+    #[test]
+    fn test_percentile() {
+        let sorted = (1..=100).map(Duration::from_millis).collect::<Vec<_>>();
+        assert_eq!(percentile(&sorted, 50), Duration::from_millis(50));
+        assert_eq!(percentile(&sorted, 99), Duration::from_millis(99));
+        assert_eq!(percentile(&sorted[..1], 99), Duration::from_millis(1));
+        assert_eq!(percentile(&[], 50), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_simulate() {
+        let dir = TestDir::new("test-simulate");
+        let url = serve(&dir, 30).await.unwrap();
+        let options = Options::parse_from([
+            "simulate",
+            "--contributors",
+            "4",
+            "--abort-percent",
+            "50",
+            "--poll-interval",
+            "10",
+        ]);
+        let report = simulate(&options, url).await;
+
+        assert_eq!(report.contributors, 4);
+        assert_eq!(report.outcomes.get(&Outcome::Failed), None);
+        assert_eq!(report.outcomes.values().sum::<usize>(), 4);
+        assert_eq!(report.latencies[&Step::Login].count, 4);
+        assert_eq!(
+            report.latencies.get(&Step::Contribute).map(|l| l.count),
+            report.outcomes.get(&Outcome::Contributed).copied()
+        );
+    }
+}
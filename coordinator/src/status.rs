@@ -0,0 +1,80 @@
+//! Ceremony status endpoint.
+//!
+//! Served from the hash chain rather than the ceremony, so it does not wait
+//! for a contribution being verified. Responses are signed by the coordinator,
+//! see [`crate::signed`].
+
+use crate::{
+    checkpoint::HashChain,
+    integrity::{Integrity, IntegrityStatus},
+    ipfs::{Ipfs, Pins},
+    leader::{Leadership, Role},
+    queue::{Queue, TierStatus},
+    schedule::{Phase, Schedule},
+};
+use axum::{Extension, Json};
+use serde::Serialize;
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Status {
+    /// Unix time the status was served.
+    pub time:              u64,
+    pub num_contributions: usize,
+    /// Hash of the current transcript, `0x` prefixed hex.
+    pub transcript_hash:   String,
+    /// Latest IPFS pins, if pinning is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipfs:              Option<Pins>,
+    /// Queue priority tiers, if configured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tiers:             Option<TierStatus>,
+    /// Background re-verification of the stored transcript, if enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub integrity:         Option<IntegrityStatus>,
+    /// Leader or standby, if several coordinators share the transcript.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role:              Option<Role>,
+    /// Opening hours and current phase, if the ceremony is scheduled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule:          Option<ScheduleStatus>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+pub struct ScheduleStatus {
+    #[serde(flatten)]
+    pub schedule: Schedule,
+    pub phase:    Phase,
+}
+
+#[allow(clippy::unused_async)] // Required for axum handler
+pub async fn handler(
+    Extension(chain): Extension<HashChain>,
+    Extension(ipfs): Extension<Ipfs>,
+    Extension(queue): Extension<Arc<Queue>>,
+    Extension(integrity): Extension<Integrity>,
+    Extension(leadership): Extension<Leadership>,
+) -> Json<Status> {
+    let latest = chain.latest().expect("Hash chain is never empty");
+    Json(Status {
+        time:              now(),
+        num_contributions: latest.index,
+        transcript_hash:   format!("0x{}", hex::encode(latest.transcript_hash)),
+        ipfs:              ipfs.status(),
+        tiers:             queue.tier_status(),
+        integrity:         integrity.status(),
+        role:              leadership.role(),
+        schedule:          queue.schedule_status(),
+    })
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time before unix epoch")
+        .as_secs()
+}
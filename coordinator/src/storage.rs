@@ -0,0 +1,725 @@
+//! Append-only on-disk transcript storage.
+//!
+//! Every sub-ceremony is stored as five flat files of uncompressed points:
+//!
+//! * `{i}.g1` and `{i}.g2` hold the current powers. They have a fixed size and
+//!   are replaced as a whole by renaming a new file over them, so they can be
+//!   memory-mapped, see [`crate::points`].
+//! * `{i}.products`, `{i}.pubkeys` and `{i}.signatures` hold the witness. They
+//!   only ever grow by one entry per contribution to the sub-ceremony. A
+//!   missing identity signature is stored as the point at infinity.
+//!
+//! A contribution need not cover every sub-ceremony. For every contribution
+//! `rounds` holds the bitmask of the sub-ceremonies it covered as a
+//! little-endian `u64`, so contributions keep a single sequence of indices.
+//!
+//! A contribution is first written to a journal which is atomically moved
+//! into place. Only then are the data files updated. Applying a journal is
+//! idempotent, so if the process dies half-way the journal is simply replayed
+//! on the next [`TranscriptStore::open`].
+//!
+//! Points read back are checked to be on the curve and in the prime order
+//! subgroup, and the witness files are checked against `rounds`, so a
+//! truncated or corrupted store is refused rather than loaded.
+
+use crate::points::{
+    decode_checked, MappedSubTranscript, MappedTranscript, PointFile, StoredPoint,
+};
+use ark_bls12_381::{G1Affine, G2Affine};
+use ark_ff::Zero;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use eyre::{bail, ensure, Result as EyreResult, WrapErr};
+use kzg_ceremony_crypto::{BatchTranscript, Contribution, Transcript};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+use tracing::{info, instrument, warn};
+
+const MANIFEST: &str = "manifest.json";
+const JOURNAL: &str = "journal";
+const JOURNAL_TMP: &str = "journal.tmp";
+const ROUNDS: &str = "rounds";
+
+/// Maximum number of sub-ceremonies, the width of a `rounds` bitmask.
+const MAX_SUB_CEREMONIES: usize = 64;
+
+#[derive(Debug)]
+pub struct TranscriptStore {
+    dir:    PathBuf,
+    sizes:  Vec<(usize, usize)>,
+    /// Opened with [`Self::open_shared`], another coordinator may be writing.
+    shared: bool,
+}
+
+/// A contribution in the journal.
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Journal {
+    /// Number of contributions before this one.
+    round:   usize,
+    /// Entries for the covered sub-ceremonies, in order.
+    entries: Vec<JournalEntry>,
+}
+
+/// One sub-ceremony entry in the journal.
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct JournalEntry {
+    sub_ceremony: usize,
+    /// Number of witness entries before this contribution.
+    witness_len:  usize,
+    contribution: Contribution,
+}
+
+impl TranscriptStore {
+    /// Create a new store in `dir` holding `transcript`.
+    #[allow(clippy::disallowed_methods)] // False positive from macro
+    #[instrument(level = "info", skip(transcript))]
+    pub fn create(dir: &Path, transcript: &BatchTranscript) -> EyreResult<Self> {
+        let transcripts = &transcript.transcripts;
+        ensure!(
+            !dir.join(MANIFEST).exists(),
+            "Transcript store already exists in {}",
+            dir.display()
+        );
+        ensure!(
+            transcripts.len() <= MAX_SUB_CEREMONIES,
+            "At most {} sub-ceremonies are supported",
+            MAX_SUB_CEREMONIES
+        );
+        fs::create_dir_all(dir)?;
+        let store = Self {
+            dir:    dir.to_path_buf(),
+            sizes:  transcripts
+                .iter()
+                .map(|t| (t.g1_powers.len(), t.g2_powers.len()))
+                .collect(),
+            shared: false,
+        };
+        for (i, transcript) in transcripts.iter().enumerate() {
+            write_points(&store.path(i, "g1"), &transcript.g1_powers)?;
+            write_points(&store.path(i, "g2"), &transcript.g2_powers)?;
+            write_points(&store.path(i, "products"), &transcript.products)?;
+            write_points(&store.path(i, "pubkeys"), &transcript.pubkeys)?;
+            write_points(
+                &store.path(i, "signatures"),
+                &encode_signatures(&transcript.signatures),
+            )?;
+        }
+        // Contributions already in the initial transcripts cover everything.
+        let rounds = transcripts.first().map_or(0, |t| t.pubkeys.len() - 1);
+        write_points(&dir.join(ROUNDS), &vec![store.full_mask(); rounds])?;
+        // The manifest is written last, so its presence marks a complete store.
+        fs::write(
+            dir.join(MANIFEST),
+            serde_json::to_string_pretty(&store.sizes)?,
+        )?;
+        sync_dir(dir)?;
+        Ok(store)
+    }
+
+    /// Open an existing store, recovering from an interrupted append.
+    #[allow(clippy::disallowed_methods)] // False positive from macro
+    #[instrument(level = "info")]
+    pub fn open(dir: &Path) -> EyreResult<Self> {
+        let manifest = fs::read_to_string(dir.join(MANIFEST))
+            .wrap_err_with(|| format!("Could not read transcript store {}", dir.display()))?;
+        let store = Self {
+            dir:    dir.to_path_buf(),
+            sizes:  serde_json::from_str(&manifest)?,
+            shared: false,
+        };
+        if !dir.join(ROUNDS).exists() {
+            // Stores from before partial contributions have full rounds only.
+            let rounds = store.witness_len(0)? - 1;
+            info!(rounds, "Creating round index of existing store");
+            write_points(&dir.join(ROUNDS), &vec![store.full_mask(); rounds])?;
+        }
+        for i in 0..store.sizes.len() {
+            if !store.path(i, "signatures").exists() {
+                // Stores from before signatures were kept have none.
+                let len = store.witness_len(i)?;
+                info!(sub_ceremony = i, "Creating signatures of existing store");
+                write_points(&store.path(i, "signatures"), &vec![G1Affine::zero(); len])?;
+            }
+        }
+        if dir.join(JOURNAL_TMP).exists() {
+            warn!("Discarding incomplete journal");
+            fs::remove_file(dir.join(JOURNAL_TMP))?;
+        }
+        if dir.join(JOURNAL).exists() {
+            warn!("Replaying journal of interrupted append");
+            let journal = store.read_journal()?;
+            store.apply(&journal)?;
+            fs::remove_file(dir.join(JOURNAL))?;
+            sync_dir(dir)?;
+        }
+        Ok(store)
+    }
+
+    /// Open an existing store without recovering from an interrupted append,
+    /// for a standby sharing the store with a running leader, see
+    /// [`crate::leader`]. Reads may fail while the leader is appending.
+    #[allow(clippy::disallowed_methods)] // False positive from macro
+    #[instrument(level = "info")]
+    pub fn open_shared(dir: &Path) -> EyreResult<Self> {
+        let manifest = fs::read_to_string(dir.join(MANIFEST))
+            .wrap_err_with(|| format!("Could not read transcript store {}", dir.display()))?;
+        let store = Self {
+            dir:    dir.to_path_buf(),
+            sizes:  serde_json::from_str(&manifest)?,
+            shared: true,
+        };
+        ensure!(
+            dir.join(ROUNDS).exists(),
+            "Transcript store {} needs to be upgraded by the leader",
+            dir.display()
+        );
+        Ok(store)
+    }
+
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Whether the store was opened with [`Self::open_shared`].
+    #[must_use]
+    pub const fn is_shared(&self) -> bool {
+        self.shared
+    }
+
+    /// Whether a complete store exists in `dir`.
+    #[must_use]
+    pub fn exists(dir: &Path) -> bool {
+        dir.join(MANIFEST).exists()
+    }
+
+    /// Open the store in `dir`, or create it with the transcripts returned by
+    /// `initial`.
+    pub fn open_or_create<F>(dir: &Path, initial: F) -> EyreResult<Self>
+    where
+        F: FnOnce() -> EyreResult<BatchTranscript>,
+    {
+        if Self::exists(dir) {
+            Self::open(dir)
+        } else {
+            info!("Creating new transcript store in {}", dir.display());
+            Self::create(dir, &initial()?)
+        }
+    }
+
+    /// Number of contributions in the store, including the initial state.
+    ///
+    /// Checks that the witness of every sub-ceremony has an entry for every
+    /// contribution covering it. In a shared store the leader may be ahead.
+    pub fn num_entries(&self) -> EyreResult<usize> {
+        let rounds = self.rounds()?;
+        for i in 0..self.sizes.len() {
+            let expected = 1 + rounds.iter().filter(|round| round.contains(&i)).count();
+            for (kind, size) in [
+                ("products", G1Affine::zero().uncompressed_size()),
+                ("pubkeys", G2Affine::zero().uncompressed_size()),
+                ("signatures", G1Affine::zero().uncompressed_size()),
+            ] {
+                let path = self.path(i, kind);
+                let len = usize::try_from(fs::metadata(&path)?.len())?;
+                ensure!(len % size == 0, "File {} is truncated", path.display());
+                ensure!(
+                    len / size == expected || (self.shared && len / size > expected),
+                    "File {} has {} entries, expected {}",
+                    path.display(),
+                    len / size,
+                    expected
+                );
+            }
+        }
+        Ok(rounds.len() + 1)
+    }
+
+    /// The sub-ceremonies covered by every contribution.
+    pub fn rounds(&self) -> EyreResult<Vec<Vec<usize>>> {
+        Ok(read_values::<u64>(&self.dir.join(ROUNDS))?
+            .into_iter()
+            .map(|mask| {
+                (0..self.sizes.len())
+                    .filter(|i| mask & 1 << i != 0)
+                    .collect()
+            })
+            .collect())
+    }
+
+    /// Read the full transcript.
+    #[allow(clippy::disallowed_methods)] // False positive from macro
+    #[instrument(level = "info", skip(self))]
+    pub fn load(&self) -> EyreResult<BatchTranscript> {
+        let transcripts = self
+            .sizes
+            .iter()
+            .enumerate()
+            .map(|(i, (n1, n2))| {
+                let transcript = Transcript {
+                    g1_powers:  read_points(&self.path(i, "g1"))?,
+                    g2_powers:  read_points(&self.path(i, "g2"))?,
+                    products:   read_points(&self.path(i, "products"))?,
+                    pubkeys:    read_points(&self.path(i, "pubkeys"))?,
+                    signatures: read_points::<G1Affine>(&self.path(i, "signatures"))?
+                        .into_iter()
+                        .map(|signature| (!signature.is_zero()).then_some(signature))
+                        .collect(),
+                };
+                ensure!(
+                    transcript.g1_powers.len() == *n1 && transcript.g2_powers.len() == *n2,
+                    "Transcript {} has unexpected size",
+                    i
+                );
+                ensure!(
+                    transcript.products.len() == transcript.pubkeys.len()
+                        && transcript.signatures.len() == transcript.pubkeys.len(),
+                    "Transcript {} has inconsistent witness",
+                    i
+                );
+                Ok(transcript)
+            })
+            .collect::<EyreResult<Vec<_>>>()?;
+        Ok(transcripts.into())
+    }
+
+    /// Map the current powers and read the witness, see [`crate::points`].
+    #[allow(clippy::disallowed_methods)] // False positive from macro
+    #[instrument(level = "info", skip(self))]
+    pub fn map(&self) -> EyreResult<MappedTranscript> {
+        let transcripts = self
+            .sizes
+            .iter()
+            .enumerate()
+            .map(|(i, (n1, n2))| {
+                let transcript = MappedSubTranscript {
+                    g1_powers: PointFile::open(&self.path(i, "g1"))?,
+                    g2_powers: PointFile::open(&self.path(i, "g2"))?,
+                    products:  read_points(&self.path(i, "products"))?,
+                    pubkeys:   read_points(&self.path(i, "pubkeys"))?,
+                };
+                ensure!(
+                    transcript.g1_powers.len() == *n1 && transcript.g2_powers.len() == *n2,
+                    "Transcript {} has unexpected size",
+                    i
+                );
+                Ok(transcript)
+            })
+            .collect::<EyreResult<Vec<_>>>()?;
+        Ok(MappedTranscript { transcripts })
+    }
+
+    /// Append a verified contribution, with an entry for every sub-ceremony
+    /// that is `None` for the ones it does not cover.
+    #[allow(clippy::disallowed_methods)] // False positive from macro
+    #[allow(clippy::needless_pass_by_ref_mut)] // There is a single appender
+    #[instrument(level = "info", skip_all)]
+    pub fn append(&mut self, contributions: &[Option<Contribution>]) -> EyreResult<()> {
+        ensure!(
+            contributions.len() == self.sizes.len(),
+            "Expected {} contributions, got {}",
+            self.sizes.len(),
+            contributions.len()
+        );
+        ensure!(
+            contributions.iter().any(Option::is_some),
+            "Contribution covers no sub-ceremony"
+        );
+        let round = self.num_entries()? - 1;
+        let entries = contributions
+            .iter()
+            .zip(self.sizes.iter())
+            .enumerate()
+            .filter_map(|(i, (contribution, size))| contribution.as_ref().map(|c| (i, c, size)))
+            .map(|(sub_ceremony, contribution, (n1, n2))| {
+                ensure!(
+                    contribution.g1_powers.len() == *n1 && contribution.g2_powers.len() == *n2,
+                    "Contribution has unexpected size"
+                );
+                Ok(JournalEntry {
+                    sub_ceremony,
+                    witness_len: self.witness_len(sub_ceremony)?,
+                    contribution: contribution.clone(),
+                })
+            })
+            .collect::<EyreResult<Vec<_>>>()?;
+        let journal = Journal { round, entries };
+
+        // Write the journal and atomically move it into place.
+        write_journal(&self.dir.join(JOURNAL_TMP), &journal)?;
+        fs::rename(self.dir.join(JOURNAL_TMP), self.dir.join(JOURNAL))?;
+        sync_dir(&self.dir)?;
+
+        self.apply(&journal)?;
+        fs::remove_file(self.dir.join(JOURNAL))?;
+        sync_dir(&self.dir)?;
+        Ok(())
+    }
+
+    fn read_journal(&self) -> EyreResult<Journal> {
+        let mut reader = BufReader::new(File::open(self.dir.join(JOURNAL))?);
+        let round = read_u64(&mut reader)?;
+        let mut mask = [0; 8];
+        reader.read_exact(&mut mask)?;
+        let mask = u64::from_le_bytes(mask);
+        let entries = self
+            .sizes
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| mask & 1 << i != 0)
+            .map(|(sub_ceremony, (n1, n2))| {
+                let witness_len = read_u64(&mut reader)?;
+                let pubkey = decode_checked::<G2Affine, _>(&mut reader, 1)?[0];
+                let signature = decode_checked::<G1Affine, _>(&mut reader, 1)?[0];
+                let g1_powers = decode_checked(&mut reader, *n1)?;
+                let g2_powers = decode_checked(&mut reader, *n2)?;
+                Ok(JournalEntry {
+                    sub_ceremony,
+                    witness_len,
+                    contribution: Contribution {
+                        pubkey,
+                        g1_powers,
+                        g2_powers,
+                        bls_signature: (!signature.is_zero()).then_some(signature),
+                        pubkey_proof: None,
+                    },
+                })
+            })
+            .collect::<EyreResult<Vec<_>>>()?;
+        if reader.read(&mut [0])? != 0 {
+            bail!("Trailing data in journal");
+        }
+        Ok(Journal { round, entries })
+    }
+
+    /// Write a journal to the data files. This is idempotent.
+    fn apply(&self, journal: &Journal) -> EyreResult<()> {
+        for entry in &journal.entries {
+            let i = entry.sub_ceremony;
+            let contribution = &entry.contribution;
+            write_points(&self.path(i, "g1"), &contribution.g1_powers)?;
+            write_points(&self.path(i, "g2"), &contribution.g2_powers)?;
+            write_at(&self.path(i, "products"), entry.witness_len, &[
+                contribution.g1_powers[1],
+            ])?;
+            write_at(&self.path(i, "pubkeys"), entry.witness_len, &[
+                contribution.pubkey
+            ])?;
+            write_at(
+                &self.path(i, "signatures"),
+                entry.witness_len,
+                &encode_signatures(&[contribution.bls_signature]),
+            )?;
+        }
+        write_at(&self.dir.join(ROUNDS), journal.round, &[journal.mask()])?;
+        Ok(())
+    }
+
+    /// Number of witness entries of sub-ceremony `index`.
+    fn witness_len(&self, index: usize) -> EyreResult<usize> {
+        let size = G1Affine::zero().uncompressed_size();
+        let len = fs::metadata(self.path(index, "products"))?.len();
+        Ok(usize::try_from(len)? / size)
+    }
+
+    /// Bitmask of all sub-ceremonies.
+    fn full_mask(&self) -> u64 {
+        u64::MAX >> (MAX_SUB_CEREMONIES - self.sizes.len())
+    }
+
+    fn path(&self, index: usize, kind: &str) -> PathBuf {
+        self.dir.join(format!("{index}.{kind}"))
+    }
+}
+
+impl Journal {
+    fn mask(&self) -> u64 {
+        self.entries
+            .iter()
+            .fold(0, |mask, entry| mask | 1 << entry.sub_ceremony)
+    }
+}
+
+fn write_journal(path: &Path, journal: &Journal) -> EyreResult<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_u64(&mut writer, journal.round)?;
+    writer.write_all(&journal.mask().to_le_bytes())?;
+    for entry in &journal.entries {
+        write_u64(&mut writer, entry.witness_len)?;
+        write_all(&mut writer, &[entry.contribution.pubkey])?;
+        write_all(
+            &mut writer,
+            &encode_signatures(&[entry.contribution.bls_signature]),
+        )?;
+        write_all(&mut writer, &entry.contribution.g1_powers)?;
+        write_all(&mut writer, &entry.contribution.g2_powers)?;
+    }
+    writer.into_inner()?.sync_all()?;
+    Ok(())
+}
+
+/// Signatures as points, with the point at infinity for missing ones.
+fn encode_signatures(signatures: &[Option<G1Affine>]) -> Vec<G1Affine> {
+    signatures
+        .iter()
+        .map(|signature| signature.unwrap_or_else(G1Affine::zero))
+        .collect()
+}
+
+/// Replace the file at `path` with `points`. The rename is made durable by
+/// the next [`sync_dir`].
+fn write_points<P: CanonicalSerialize>(path: &Path, points: &[P]) -> EyreResult<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    write_all(&mut writer, points)?;
+    writer.into_inner()?.sync_all()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Write `points` starting at entry `index`, truncating anything after.
+fn write_at<P: CanonicalSerialize + Default>(
+    path: &Path,
+    index: usize,
+    points: &[P],
+) -> EyreResult<()> {
+    let size = P::default().uncompressed_size();
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let offset = u64::try_from(index * size)?;
+    ensure!(
+        file.metadata()?.len() >= offset,
+        "Witness file {} is too short",
+        path.display()
+    );
+    file.seek(SeekFrom::Start(offset))?;
+    let mut writer = BufWriter::new(file);
+    write_all(&mut writer, points)?;
+    let mut file = writer.into_inner()?;
+    let len = file.stream_position()?;
+    file.set_len(len)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Read a file of points, checking every point.
+fn read_points<P: StoredPoint>(path: &Path) -> EyreResult<Vec<P>> {
+    let size = P::default().uncompressed_size();
+    let len = usize::try_from(fs::metadata(path)?.len())?;
+    ensure!(len % size == 0, "File {} is truncated", path.display());
+    let mut reader = BufReader::new(File::open(path)?);
+    decode_checked(&mut reader, len / size)
+        .wrap_err_with(|| format!("File {} holds an invalid point", path.display()))
+}
+
+/// Read a file of values that need no checks, such as `rounds`.
+fn read_values<P: CanonicalDeserialize + CanonicalSerialize + Default>(
+    path: &Path,
+) -> EyreResult<Vec<P>> {
+    let size = P::default().uncompressed_size();
+    let len = usize::try_from(fs::metadata(path)?.len())?;
+    ensure!(len % size == 0, "File {} is truncated", path.display());
+    let mut reader = BufReader::new(File::open(path)?);
+    (0..len / size)
+        .map(|_| Ok(P::deserialize_unchecked(&mut reader)?))
+        .collect()
+}
+
+fn write_all<P: CanonicalSerialize, W: Write>(writer: &mut W, points: &[P]) -> EyreResult<()> {
+    for point in points {
+        point.serialize_uncompressed(&mut *writer)?;
+    }
+    Ok(())
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> EyreResult<usize> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(usize::try_from(u64::from_le_bytes(bytes))?)
+}
+
+fn write_u64<W: Write>(writer: &mut W, value: usize) -> EyreResult<()> {
+    writer.write_all(&u64::try_from(value)?.to_le_bytes())?;
+    Ok(())
+}
+
+/// Atomically replace the file at `path` with `contents`.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> EyreResult<()> {
+    let tmp = path.with_extension("tmp");
+    let mut file = File::create(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => sync_dir(dir),
+        _ => Ok(()),
+    }
+}
+
+/// Make renames and file creations in `dir` durable.
+fn sync_dir(dir: &Path) -> EyreResult<()> {
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test::TestDir;
+    use ark_ec::AffineCurve;
+    use kzg_ceremony_crypto::{SecretRng, SecretScalar};
+
+    fn contribute(transcripts: &[Transcript]) -> Vec<Contribution> {
+        let mut rng = SecretRng::new();
+        transcripts
+            .iter()
+            .map(|t| {
+                let mut contribution = t.contribution();
+                contribution.add_tau(&SecretScalar::random(&mut rng));
+                contribution
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_append_and_reopen() {
+        let dir = TestDir::new("storage-append");
+        let sizes = [(8, 3), (16, 3)];
+        let mut store =
+            TranscriptStore::open_or_create(&dir, || Ok(BatchTranscript::new(&sizes))).unwrap();
+        let mut transcripts = store.load().unwrap().transcripts;
+        assert_eq!(store.num_entries().unwrap(), 1);
+
+        let mut contributions = contribute(&transcripts);
+        contributions[0].bls_signature = Some(G1Affine::prime_subgroup_generator());
+        store
+            .append(&contributions.iter().cloned().map(Some).collect::<Vec<_>>())
+            .unwrap();
+        for (transcript, contribution) in transcripts.iter_mut().zip(contributions) {
+            transcript.add(contribution);
+        }
+
+        let store = TranscriptStore::open(&dir).unwrap();
+        assert_eq!(store.num_entries().unwrap(), 2);
+        assert_eq!(store.load().unwrap().transcripts, transcripts);
+
+        // Stores without a round index have full rounds only.
+        fs::remove_file(dir.join(ROUNDS)).unwrap();
+        let store = TranscriptStore::open(&dir).unwrap();
+        assert_eq!(store.rounds().unwrap(), vec![vec![0, 1]]);
+
+        // Stores without signatures have none.
+        fs::remove_file(dir.join("0.signatures")).unwrap();
+        let store = TranscriptStore::open(&dir).unwrap();
+        assert_eq!(store.load().unwrap().transcripts[0].signatures, vec![
+            None, None
+        ]);
+    }
+
+    #[test]
+    fn test_append_subset() {
+        let dir = TestDir::new("storage-subset");
+        let sizes = [(8, 3), (16, 3), (8, 2)];
+        let mut store =
+            TranscriptStore::open_or_create(&dir, || Ok(BatchTranscript::new(&sizes))).unwrap();
+        let mut transcripts = store.load().unwrap().transcripts;
+        for subset in [&[1][..], &[0, 2], &[1, 2]] {
+            let mut contributions = vec![None; sizes.len()];
+            for (i, contribution) in contribute(&transcripts).into_iter().enumerate() {
+                if subset.contains(&i) {
+                    transcripts[i].add(contribution.clone());
+                    contributions[i] = Some(contribution);
+                }
+            }
+            store.append(&contributions).unwrap();
+        }
+        assert!(store.append(&[None, None, None]).is_err());
+
+        let store = TranscriptStore::open(&dir).unwrap();
+        assert_eq!(store.num_entries().unwrap(), 4);
+        assert_eq!(store.rounds().unwrap(), vec![vec![1], vec![0, 2], vec![
+            1, 2
+        ]]);
+        let loaded = store.load().unwrap().transcripts;
+        assert_eq!(loaded, transcripts);
+        assert_eq!(loaded[0].pubkeys.len(), 2);
+        assert_eq!(loaded[1].pubkeys.len(), 3);
+        for transcript in &loaded {
+            transcript.verify().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_replay_journal() {
+        let dir = TestDir::new("storage-replay");
+        let sizes = [(8, 3)];
+        let mut store =
+            TranscriptStore::open_or_create(&dir, || Ok(BatchTranscript::new(&sizes))).unwrap();
+        let mut transcripts = store.load().unwrap().transcripts;
+        let contributions = contribute(&transcripts);
+        store.append(&[Some(contributions[0].clone())]).unwrap();
+        transcripts[0].add(contributions[0].clone());
+
+        // Simulate a crash after the journal was committed but while the data
+        // files were only partially updated.
+        let journal = Journal {
+            round:   0,
+            entries: vec![JournalEntry {
+                sub_ceremony: 0,
+                witness_len:  1,
+                contribution: contributions[0].clone(),
+            }],
+        };
+        write_journal(&dir.join(JOURNAL), &journal).unwrap();
+        write_points(&dir.join("0.g1"), &[G1Affine::zero(); 8]).unwrap();
+        fs::write(dir.join(JOURNAL_TMP), b"garbage").unwrap();
+
+        write_at(&dir.join(ROUNDS), 0, &[0_u64; 0]).unwrap();
+
+        let store = TranscriptStore::open(&dir).unwrap();
+        assert!(!dir.join(JOURNAL).exists());
+        assert!(!dir.join(JOURNAL_TMP).exists());
+        assert_eq!(store.load().unwrap().transcripts, transcripts);
+        assert_eq!(store.num_entries().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_corrupted() {
+        let dir = TestDir::new("storage-corrupted");
+        let sizes = [(8, 3), (16, 3)];
+        let mut store =
+            TranscriptStore::open_or_create(&dir, || Ok(BatchTranscript::new(&sizes))).unwrap();
+        let transcripts = store.load().unwrap().transcripts;
+        let contributions = contribute(&transcripts);
+        store
+            .append(&contributions.into_iter().map(Some).collect::<Vec<_>>())
+            .unwrap();
+
+        // A point that is not on the curve.
+        let path = dir.join("1.g1");
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[100] ^= 1;
+        fs::write(&path, bytes).unwrap();
+        assert!(store.load().is_err());
+        assert!(store.map().unwrap().transcripts[1]
+            .g1_powers
+            .read(0..16)
+            .is_err());
+        write_points(&path, &transcripts[1].g1_powers).unwrap();
+        store.load().unwrap();
+
+        // A witness file of a later sub-ceremony lost its last entry.
+        let path = dir.join("1.pubkeys");
+        let len = fs::metadata(&path).unwrap().len();
+        let size = G2Affine::zero().uncompressed_size() as u64;
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - size)
+            .unwrap();
+        assert!(store.num_entries().is_err());
+    }
+}
@@ -0,0 +1,306 @@
+//! Several ceremonies on one coordinator.
+//!
+//! Besides its own ceremony, a coordinator can host further independent
+//! ceremonies, e.g. a public test ceremony alongside the real one. They are
+//! listed in a JSON file given with `--ceremonies`, each with an id and the
+//! command line arguments of its ceremony:
+//!
+//! ```json
+//! [
+//!     { "id": "test", "args": ["--test-ceremony", "--contribution-timeout", "60"] }
+//! ]
+//! ```
+//!
+//! Each ceremony has its own transcript, queue, sessions and keys, and is
+//! served under `/ceremonies/<id>` with the same routes as the main one.
+//! Ceremonies can not share coordinator keys or commitment seeds, so that no
+//! receipt is valid for two ceremonies, and test ceremonies can not share
+//! their test seed or session keys.
+//! `GET /ceremonies` lists the ids. Ceremonies are stored in
+//! `<transcript-dir>/ceremonies/<id>` unless their arguments give a
+//! `--transcript-dir`.
+//!
+//! Arguments are parsed like the main command line, so environment variables
+//! apply to every ceremony. Server, TLS and connection settings are those of
+//! the main command line. Metrics are shared.
+
+use crate::{build, shutdown::Drain, signing::CoordinatorKey, Options};
+use axum::{routing::get, Json, Router};
+use clap::Parser;
+use eyre::{ensure, Result as EyreResult, WrapErr};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, fs, iter, path::Path};
+use tracing::info;
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Tenant {
+    /// Path segment of the ceremony, lowercase letters, digits and dashes.
+    pub id:   String,
+    /// Command line arguments of the ceremony, without the program name.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Load the hosted ceremonies from a JSON file.
+pub fn load(path: &Path) -> EyreResult<Vec<Tenant>> {
+    let json = fs::read_to_string(path)
+        .wrap_err_with(|| format!("Could not read ceremonies {}", path.display()))?;
+    let tenants: Vec<Tenant> = serde_json::from_str(&json)
+        .wrap_err_with(|| format!("Invalid ceremonies {}", path.display()))?;
+    let mut ids = HashSet::new();
+    for tenant in &tenants {
+        ensure!(
+            !tenant.id.is_empty()
+                && tenant
+                    .id
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-'),
+            "Ceremony id {:?} must be lowercase letters, digits and dashes",
+            tenant.id
+        );
+        ensure!(
+            ids.insert(&tenant.id),
+            "Duplicate ceremony id {}",
+            tenant.id
+        );
+    }
+    Ok(tenants)
+}
+
+impl Tenant {
+    /// The options of this ceremony, hosted by a coordinator with `main`.
+    pub fn options(&self, main: &Options) -> EyreResult<Options> {
+        let mut options = Options::try_parse_from(
+            iter::once("coordinator").chain(self.args.iter().map(String::as_str)),
+        )
+        .wrap_err_with(|| format!("Invalid arguments for ceremony {}", self.id))?;
+        ensure!(
+            options.command.is_none() && options.ceremonies.is_none(),
+            "Ceremony {} can not have subcommands or ceremonies",
+            self.id
+        );
+        let explicit_dir = self
+            .args
+            .iter()
+            .any(|arg| arg.starts_with("--transcript-dir"));
+        if !explicit_dir {
+            options.transcript_dir = main.transcript_dir.join("ceremonies").join(&self.id);
+        }
+        Ok(options)
+    }
+}
+
+/// Build the ceremonies of `--ceremonies` and serve each under
+/// `/ceremonies/<id>` of `app`.
+pub async fn mount(app: Router, main: &Options) -> EyreResult<(Router, Vec<Drain>)> {
+    let tenants = match &main.ceremonies {
+        Some(path) => load(path)?,
+        None => return Ok((app, Vec::new())),
+    };
+    let mut app = app;
+    let mut drains = Vec::with_capacity(tenants.len());
+    let mut dirs = vec![main.transcript_dir.clone()];
+    let mut options = Vec::with_capacity(tenants.len());
+    for tenant in &tenants {
+        let tenant_options = tenant.options(main)?;
+        ensure!(
+            !dirs.contains(&tenant_options.transcript_dir),
+            "Ceremony {} shares transcript directory {}",
+            tenant.id,
+            tenant_options.transcript_dir.display()
+        );
+        dirs.push(tenant_options.transcript_dir.clone());
+        options.push(tenant_options);
+    }
+    check_keys(main, &options)?;
+    for (tenant, options) in tenants.iter().zip(&options) {
+        info!(id = %tenant.id, "Loading hosted ceremony");
+        let (router, drain) = build(options)
+            .await
+            .wrap_err_with(|| format!("Could not start ceremony {}", tenant.id))?;
+        app = app.nest(&format!("/ceremonies/{}", tenant.id), router);
+        drains.push(drain);
+    }
+    let ids = tenants
+        .into_iter()
+        .map(|tenant| tenant.id)
+        .collect::<Vec<_>>();
+    let app = app.route("/ceremonies", get(move || async move { Json(ids) }));
+    Ok((app, drains))
+}
+
+/// Keys must not be valid for more than one ceremony. Receipts signed for one
+/// ceremony would verify for the other, and test ceremonies issue tokens for
+/// any identity. Test ceremonies derive the keys that are not set from their
+/// test seed.
+fn check_keys(main: &Options, tenants: &[Options]) -> EyreResult<()> {
+    let addresses = |options: &Options| {
+        options
+            .coordinator_key
+            .iter()
+            .map(CoordinatorKey::address)
+            .chain(options.previous_coordinator_addresses.iter().copied())
+            .collect::<Vec<_>>()
+    };
+    let session_keys = |options: &Options| {
+        options
+            .session_key
+            .iter()
+            .chain(&options.previous_session_keys)
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    let all = iter::once(main).chain(tenants).collect::<Vec<_>>();
+    for (i, a) in all.iter().enumerate() {
+        for b in &all[i + 1..] {
+            ensure!(
+                !addresses(a)
+                    .iter()
+                    .any(|address| addresses(b).contains(address)),
+                "Ceremonies can not share a coordinator key"
+            );
+            ensure!(
+                a.commitment_seed.is_none() || a.commitment_seed != b.commitment_seed,
+                "Ceremonies can not share a commitment seed"
+            );
+            ensure!(
+                !(a.test_ceremony && b.test_ceremony) || a.test_seed != b.test_seed,
+                "Test ceremonies can not share a test seed"
+            );
+            ensure!(
+                !(a.test_ceremony || b.test_ceremony)
+                    || !session_keys(a)
+                        .iter()
+                        .any(|key| session_keys(b).contains(key)),
+                "Test ceremonies can not share a session key with other ceremonies"
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{client::CeremonyClient, test::TestDir, test_ceremony};
+    use axum::Server;
+    use std::net::{Ipv4Addr, SocketAddr};
+    use url::Url;
+
+    #[tokio::test]
+    async fn test_tenants() {
+        let dir = TestDir::new("test-tenants");
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("ceremonies.json");
+        fs::write(
+            &file,
+            r#"[{ "id": "public-test", "args": ["--test-ceremony", "--test-seed", "7"] }]"#,
+        )
+        .unwrap();
+        let main = Options::parse_from([
+            "coordinator",
+            "--test-ceremony",
+            "--transcript-dir",
+            dir.join("main").to_str().unwrap(),
+            "--ceremonies",
+            file.to_str().unwrap(),
+        ]);
+        let tenant = &load(&file).unwrap()[0];
+        assert_eq!(
+            tenant.options(&main).unwrap().transcript_dir,
+            dir.join("main/ceremonies/public-test")
+        );
+
+        let (app, _) = build(&main).await.unwrap();
+        let (app, drains) = mount(app, &main).await.unwrap();
+        assert_eq!(drains.len(), 1);
+        let server = Server::bind(&SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0))
+            .serve(app.into_make_service());
+        let base: Url = format!("http://{}/", server.local_addr()).parse().unwrap();
+        tokio::spawn(server);
+
+        // Both ceremonies have independent sessions and queues.
+        for url in [base.clone(), base.join("ceremonies/public-test/").unwrap()] {
+            let mut client = CeremonyClient::new(url).with_sizes(test_ceremony::SIZES.to_vec());
+            client.test_login(Some("git|1|alice")).await.unwrap();
+            assert_eq!(client.join_queue().await.unwrap().position, 0);
+        }
+        let mut client = CeremonyClient::new(base.join("ceremonies/unknown/").unwrap());
+        assert!(client.test_login(None).await.is_err());
+    }
+
+    #[test]
+    fn test_shared_keys() {
+        const KEY: &str = "0x0101010101010101010101010101010101010101010101010101010101010101";
+        const OTHER: &str = "0x0202020202020202020202020202020202020202020202020202020202020202";
+        let main = Options::parse_from(["coordinator"]);
+        let check = |a: &[&str], b: &[&str]| {
+            let tenants = [a, b]
+                .iter()
+                .enumerate()
+                .map(|(i, args)| {
+                    Tenant {
+                        id:   format!("t{i}"),
+                        args: args.iter().map(ToString::to_string).collect(),
+                    }
+                    .options(&main)
+                    .unwrap()
+                })
+                .collect::<Vec<_>>();
+            check_keys(&main, &tenants).is_ok()
+        };
+
+        // Two tenants configured from a shared environment.
+        assert!(!check(&["--coordinator-key", KEY], &[
+            "--coordinator-key",
+            KEY
+        ]));
+        assert!(!check(&["--commitment-seed", KEY], &[
+            "--commitment-seed",
+            KEY
+        ]));
+        assert!(check(
+            &["--coordinator-key", KEY, "--commitment-seed", OTHER],
+            &["--coordinator-key", OTHER, "--commitment-seed", KEY]
+        ));
+
+        // A key retired in one ceremony is still trusted there.
+        let address = KEY.parse::<CoordinatorKey>().unwrap().address().to_string();
+        assert!(!check(&["--coordinator-key", KEY], &[
+            "--previous-coordinator-addresses",
+            &address
+        ]));
+        assert!(!check(&["--test-ceremony", "--session-key", KEY], &[
+            "--previous-session-keys",
+            KEY
+        ]));
+        assert!(check(&["--session-key", KEY], &[
+            "--previous-session-keys",
+            KEY
+        ]));
+
+        // Test ceremonies derive their keys from the seed.
+        assert!(!check(&["--test-ceremony"], &["--test-ceremony"]));
+        assert!(check(&["--test-ceremony"], &[
+            "--test-ceremony",
+            "--test-seed",
+            "1"
+        ]));
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = TestDir::new("test-tenants-load");
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("ceremonies.json");
+        for (json, valid) in [
+            (r#"[{ "id": "a" }, { "id": "b-2" }]"#, true),
+            (r#"[{ "id": "a" }, { "id": "a" }]"#, false),
+            (r#"[{ "id": "A" }]"#, false),
+            (r#"[{ "id": "" }]"#, false),
+        ] {
+            fs::write(&file, json).unwrap();
+            assert_eq!(load(&file).is_ok(), valid, "{json}");
+        }
+    }
+}
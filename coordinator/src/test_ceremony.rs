@@ -0,0 +1,166 @@
+//! Deterministic test ceremony.
+//!
+//! `--test-ceremony` runs a throwaway ceremony for integration tests and
+//! frontend development, where a full join, contribute and verify cycle takes
+//! seconds:
+//!
+//! * the sub-ceremonies have the tiny [`SIZES`], so contributions verify in
+//!   milliseconds,
+//! * the coordinator and session keys are derived from `--test-seed` unless
+//!   given explicitly, so tokens and receipts are reproducible across restarts,
+//! * lottery draws, the random factors of verification and the subgroup checks
+//!   of uploads are drawn from the same seed, so runs are reproducible end to
+//!   end, and
+//! * `POST /login` issues a session token for any identity, without signing in.
+//!   The body is an optional `{"identity": ...}`, by default every login gets a
+//!   fresh `git|{n}|tester{n}` identity. The same [`Mock`] provider is also
+//!   available as `POST /login/mock`, see [`crate::auth`].
+//!
+//! Anyone can log in as anyone, so it must never be used for a real ceremony.
+
+use crate::{
+    auth::{AuthError, AuthProvider, AuthProviders, Identity, LoginResponse},
+    session::{Attributes, SharedSessionKeys},
+};
+use axum::{async_trait, Extension, Json};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use tracing::info;
+
+/// Number of G1 and G2 powers of each test sub-ceremony.
+pub const SIZES: [(usize, usize); 4] = [(8, 3), (16, 3), (32, 3), (64, 3)];
+
+/// RNG for the keys of a test ceremony.
+#[must_use]
+pub fn rng(seed: u64) -> StdRng {
+    StdRng::seed_from_u64(seed)
+}
+
+/// Sign-in provider accepting anyone, counting logins without an identity.
+#[derive(Debug, Default)]
+pub struct Mock(AtomicU64);
+
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct LoginRequest {
+    pub identity: Option<String>,
+}
+
+#[async_trait]
+impl AuthProvider for Mock {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn authenticate(&self, credentials: Value) -> Result<Identity, AuthError> {
+        let request: Option<LoginRequest> =
+            serde_json::from_value(credentials).map_err(|_| AuthError::InvalidCredentials)?;
+        let identity = request
+            .and_then(|request| request.identity)
+            .unwrap_or_else(|| {
+                let n = self.0.fetch_add(1, Ordering::Relaxed) + 1;
+                format!("git|{n}|tester{n}")
+            });
+        info!(%identity, "Test login");
+        Ok(Identity {
+            identity,
+            attributes: Attributes::default(),
+        })
+    }
+}
+
+/// Issue a session token without authentication.
+///
+/// # Errors
+///
+/// Returns an [`AuthError`] if the mock provider is not enabled.
+///
+/// # Panics
+///
+/// Never, login requests serialize.
+pub async fn login(
+    Extension(key): Extension<SharedSessionKeys>,
+    Extension(providers): Extension<Arc<AuthProviders>>,
+    request: Option<Json<LoginRequest>>,
+) -> Result<Json<LoginResponse>, AuthError> {
+    let credentials = request.map_or(Value::Null, |Json(request)| {
+        serde_json::to_value(request).expect("Login requests serialize")
+    });
+    providers.login(&key, "mock", credentials).await.map(Json)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{app, client::CeremonyClient, entropy::Entropy, test::TestDir, Options};
+    use axum::Server;
+    use clap::Parser;
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        time::Duration,
+    };
+    use url::Url;
+
+    async fn serve(dir: &str) -> Url {
+        let options = Options::parse_from([
+            "coordinator",
+            "--test-ceremony",
+            "--test-seed",
+            "42",
+            "--transcript-dir",
+            dir,
+        ]);
+        let app = app(&options).await.unwrap();
+        let server = Server::bind(&SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0))
+            .serve(app.into_make_service());
+        let url = format!("http://{}/", server.local_addr()).parse().unwrap();
+        tokio::spawn(server);
+        url
+    }
+
+    #[tokio::test]
+    async fn test_ceremony() {
+        let mut receipts = Vec::new();
+        for run in 0..2 {
+            let dir = TestDir::new(&format!("test-ceremony-{run}"));
+            let url = serve(dir.to_str().unwrap()).await;
+
+            let mut client = CeremonyClient::new(url)
+                .with_poll_interval(Duration::from_millis(10))
+                .with_sizes(SIZES.to_vec());
+            if run == 1 {
+                client = client.with_chunk_size(4096);
+            }
+            let identity = client.test_login(None).await.unwrap().identity.clone();
+            assert_eq!(identity, "git|1|tester1");
+            client.join_queue().await.unwrap();
+            client.await_turn().await.unwrap();
+            let mut entropy = Entropy::deterministic();
+            entropy.add_user_input("test");
+            let receipt = client.contribute(&entropy).await.unwrap();
+            receipts.push(receipt);
+
+            client.set_display_name(Some("Tester")).await.unwrap();
+            let participants = client.participants().await.unwrap();
+            assert_eq!(participants.len(), 1);
+            assert_eq!(participants[0].index, 1);
+            assert_eq!(participants[0].display_name.as_deref(), Some("Tester"));
+            assert_eq!(participants[0].pot_pubkeys.len(), SIZES.len());
+            let details = client.contribution(1).await.unwrap();
+            assert_eq!(details.pot_pubkeys, participants[0].pot_pubkeys);
+            assert_eq!(
+                details.transcript_after.as_ref(),
+                Some(&participants[0].transcript_hash)
+            );
+            assert!(details.transcript_before.is_some());
+            assert!(client.contribution(2).await.is_err());
+        }
+        // Same seed and entropy, same transcript and coordinator signature,
+        // whether uploaded at once or in chunks.
+        assert_eq!(receipts[0], receipts[1]);
+    }
+}
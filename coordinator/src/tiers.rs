@@ -0,0 +1,195 @@
+//! Priority tiers and reserved windows in the queue.
+//!
+//! Operators can give some identities precedence, e.g. ecosystem projects, or
+//! reserve time for contributors that need scheduling, e.g. air-gapped
+//! setups. Tiers are loaded from a JSON file given with `--queue-tiers`, for
+//! example
+//!
+//! ```json
+//! [
+//!     { "name": "ecosystem", "priority": 10, "identities": ["git|1234|geth"] },
+//!     {
+//!         "name": "airgapped",
+//!         "identities": ["eth|0x8ba1f109551bd432803012645ac136ddd64dba72"],
+//!         "reserved": [{ "start": 1669852800, "end": 1669856400 }]
+//!     }
+//! ]
+//! ```
+//!
+//! The next contributor is picked among the waiting participants of the
+//! highest priority, in the order of the queue mode. During a reserved window,
+//! which is in unix seconds and excludes the end, only members of the tier may
+//! start. Identities in no tier are in the [`DEFAULT_TIER`] with priority zero,
+//! and the first matching tier applies to identities listed more than once.
+
+use eyre::{ensure, Result as EyreResult, WrapErr};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Name of the tier of identities not listed in any tier.
+pub const DEFAULT_TIER: &str = "default";
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tier {
+    pub name:       String,
+    /// Higher priorities go first.
+    #[serde(default)]
+    pub priority:   i64,
+    pub identities: Vec<String>,
+    #[serde(default)]
+    pub reserved:   Vec<Window>,
+}
+
+/// Time span in unix seconds, excluding `end`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Window {
+    pub start: u64,
+    pub end:   u64,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Tiers(Vec<Tier>);
+
+impl Tiers {
+    /// Load tiers from a JSON file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can not be read or the tiers are invalid.
+    pub fn load(path: &Path) -> EyreResult<Self> {
+        let json = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Could not read queue tiers {}", path.display()))?;
+        let tiers: Self = serde_json::from_str(&json)
+            .wrap_err_with(|| format!("Invalid queue tiers {}", path.display()))?;
+        tiers.validate()?;
+        Ok(tiers)
+    }
+
+    fn validate(&self) -> EyreResult<()> {
+        let mut names = HashSet::new();
+        for tier in &self.0 {
+            ensure!(
+                tier.name != DEFAULT_TIER && names.insert(&tier.name),
+                "Duplicate queue tier {}",
+                tier.name
+            );
+            for window in &tier.reserved {
+                ensure!(
+                    window.start < window.end,
+                    "Empty reserved window in queue tier {}",
+                    tier.name
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the listed identities with `pseudonym` of them, see
+    /// [`crate::pseudonym`].
+    #[must_use]
+    pub fn pseudonymized(mut self, pseudonym: impl Fn(&str) -> String) -> Self {
+        for tier in &mut self.0 {
+            for identity in &mut tier.identities {
+                *identity = pseudonym(identity);
+            }
+        }
+        self
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Name of the tier of `identity`.
+    #[must_use]
+    pub fn name(&self, identity: &str) -> &str {
+        self.tier(identity).map_or(DEFAULT_TIER, |tier| &tier.name)
+    }
+
+    #[must_use]
+    pub fn priority(&self, identity: &str) -> i64 {
+        self.tier(identity).map_or(0, |tier| tier.priority)
+    }
+
+    /// The tier with a reserved window at unix time `now`, if any.
+    #[must_use]
+    pub fn reserved_at(&self, now: u64) -> Option<&Tier> {
+        self.0.iter().find(|tier| {
+            tier.reserved
+                .iter()
+                .any(|window| window.start <= now && now < window.end)
+        })
+    }
+
+    /// The tier with a reserved window now, if any.
+    #[must_use]
+    pub fn reserved(&self) -> Option<&Tier> {
+        self.reserved_at(now())
+    }
+
+    /// Whether `identity` may start while `reserved` holds the slot.
+    #[must_use]
+    pub fn admits(reserved: Option<&Tier>, identity: &str) -> bool {
+        reserved.map_or(true, |tier| tier.identities.iter().any(|i| i == identity))
+    }
+
+    fn tier(&self, identity: &str) -> Option<&Tier> {
+        self.0
+            .iter()
+            .find(|tier| tier.identities.iter().any(|i| i == identity))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time before unix epoch")
+        .as_secs()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_tiers() {
+        let tiers: Tiers = serde_json::from_str(
+            r#"[
+                { "name": "ecosystem", "priority": 10, "identities": ["git|1|geth", "eth|0x01"] },
+                {
+                    "name": "airgapped",
+                    "identities": ["eth|0x01", "eth|0x02"],
+                    "reserved": [{ "start": 100, "end": 200 }]
+                }
+            ]"#,
+        )
+        .unwrap();
+        tiers.validate().unwrap();
+        assert_eq!(tiers.name("git|1|geth"), "ecosystem");
+        assert_eq!(tiers.name("eth|0x01"), "ecosystem");
+        assert_eq!(tiers.name("eth|0x02"), "airgapped");
+        assert_eq!(tiers.name("git|2|other"), DEFAULT_TIER);
+        assert_eq!(tiers.priority("eth|0x01"), 10);
+        assert_eq!(tiers.priority("eth|0x02"), 0);
+
+        assert_eq!(tiers.reserved_at(99), None);
+        let reserved = tiers.reserved_at(100);
+        assert_eq!(reserved.unwrap().name, "airgapped");
+        assert_eq!(tiers.reserved_at(200), None);
+        assert!(Tiers::admits(reserved, "eth|0x01"));
+        assert!(!Tiers::admits(reserved, "git|1|geth"));
+        assert!(Tiers::admits(None, "git|1|geth"));
+
+        let duplicate: Tiers =
+            serde_json::from_str(r#"[{ "name": "default", "identities": [] }]"#).unwrap();
+        assert!(duplicate.validate().is_err());
+    }
+}
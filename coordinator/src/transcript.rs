@@ -0,0 +1,699 @@
+//! Transcript download.
+//!
+//! `GET /ceremony/transcript/{index}` serves sub-transcript `index` as the
+//! concatenation of its G1 powers, G2 powers, running products and pubkeys in
+//! uncompressed arkworks encoding. A single `Range: bytes=...` is honored so
+//! light clients can fetch just the parts they need.
+//!
+//! `GET /ceremony/transcript` serves the full transcript, by default in binary:
+//! for every sub-transcript its byte length as u64 LE followed by its
+//! encoding. With `?format=json` or `Accept: application/json` it serves the
+//! transcript JSON of the specification instead, and with `?format=powers`
+//! only the current powers as a batch contribution JSON, which is all a
+//! contributor needs and does not grow with the number of contributions. The
+//! `ETag` is the transcript hash with a suffix for the format, so downloads
+//! can be resumed with `Range` and `If-Range`, and are gzip or deflate
+//! compressed if the client accepts it and did not request a range.
+//!
+//! Full transcript bodies are encoded once per contribution and shared by all
+//! requests. After every accepted contribution the powers, plain and gzip
+//! compressed, and the binary transcript are encoded in the background, so the
+//! next contributor does not wait for them after `/contribution/start`.
+//! Sub-transcript ranges are encoded from the same snapshot on a blocking
+//! thread, without holding the ceremony lock.
+
+use crate::{
+    cache::{RangeCache, RangeKey},
+    ceremony::SharedCeremony,
+    compression::{self, Encoding},
+    events::EventBus,
+};
+use ark_bls12_381::{G1Affine, G2Affine};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use axum::{
+    body::Bytes,
+    extract::{Path, Query},
+    http::{
+        header::{
+            ACCEPT, ACCEPT_ENCODING, ACCEPT_RANGES, CONTENT_ENCODING, CONTENT_RANGE, CONTENT_TYPE,
+            ETAG, IF_NONE_MATCH, IF_RANGE, RANGE, VARY,
+        },
+        HeaderMap, HeaderValue, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    Extension,
+};
+use kzg_ceremony_crypto::{BatchTranscript, ContributionsJson, Transcript};
+use once_cell::sync::{Lazy, OnceCell};
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use serde::Deserialize;
+use std::{
+    cmp::min,
+    collections::HashMap,
+    convert::Infallible,
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+use thiserror::Error;
+use tokio::task::spawn_blocking;
+use tracing::info;
+
+/// Bodies encoded ahead of requests after every contribution: what the next
+/// contributor downloads and the binary transcript.
+const PRECOMPUTED: [(Format, Encoding); 3] = [
+    (Format::Powers, Encoding::Identity),
+    (Format::Powers, Encoding::Gzip),
+    (Format::Binary, Encoding::Identity),
+];
+
+static BODY_CACHE: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "transcript_body_cache_total",
+        "Full transcript bodies served, by whether they were already encoded.",
+        &["result"]
+    )
+    .unwrap()
+});
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum TranscriptDecodeError {
+    #[error("Sub-transcript {0} is truncated")]
+    Truncated(usize),
+    #[error("Sub-transcript {0} has an invalid length")]
+    Length(usize),
+    #[error("Sub-transcript {0} contains an invalid point")]
+    InvalidPoint(usize),
+    #[error("Unexpected bytes after the last sub-transcript")]
+    TrailingBytes,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum TranscriptError {
+    #[error("No sub-transcript with index {0}")]
+    NotFound(usize),
+    #[error("Range not satisfiable")]
+    InvalidRange(usize),
+}
+
+/// Serve a sub-transcript, or a byte range of it.
+pub async fn handler(
+    Path(index): Path<usize>,
+    headers: HeaderMap,
+    Extension(ceremony): Extension<SharedCeremony>,
+    Extension(cache): Extension<Arc<RangeCache>>,
+    Extension(full): Extension<Arc<FullTranscriptCache>>,
+) -> Result<Response, TranscriptError> {
+    // Encode from a snapshot, so the ceremony lock is not held meanwhile.
+    let full = full.get(&ceremony).await;
+    let transcript = full
+        .transcript
+        .transcripts
+        .get(index)
+        .ok_or(TranscriptError::NotFound(index))?;
+    let len = encoded_len(transcript);
+    let range = match headers.get(RANGE) {
+        Some(header) => Some(
+            header
+                .to_str()
+                .ok()
+                .and_then(|header| parse_range(header, len))
+                .ok_or(TranscriptError::InvalidRange(len))?,
+        ),
+        None => None,
+    };
+    let bytes_range = range.clone().unwrap_or(0..len);
+    let key = RangeKey::new(full.hash, index, &bytes_range);
+    let bytes = spawn_blocking(move || {
+        cache
+            .get_or_insert_with(key, || {
+                Ok::<_, Infallible>(encode_range(
+                    &full.transcript.transcripts[index],
+                    bytes_range,
+                ))
+            })
+            .unwrap_or_else(|e| match e {})
+    })
+    .await
+    .expect("Encoding task panicked");
+
+    let headers = [
+        (CONTENT_TYPE, "application/octet-stream".to_string()),
+        (ACCEPT_RANGES, "bytes".to_string()),
+    ];
+    Ok(match range {
+        Some(range) => (
+            StatusCode::PARTIAL_CONTENT,
+            headers,
+            [(
+                CONTENT_RANGE,
+                format!("bytes {}-{}/{}", range.start, range.end - 1, len),
+            )],
+            bytes,
+        )
+            .into_response(),
+        None => (headers, bytes).into_response(),
+    })
+}
+
+/// Representation of the full transcript.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Format {
+    /// Length prefixed sub-transcripts, see [`encode_all`].
+    Binary,
+    /// The transcript JSON of the specification, including the witness.
+    Json,
+    /// The current powers only, as a batch contribution JSON.
+    Powers,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Deserialize)]
+pub struct FullQuery {
+    format: Option<Format>,
+}
+
+impl Format {
+    const fn content_type(self) -> &'static str {
+        match self {
+            Self::Binary => "application/octet-stream",
+            Self::Json | Self::Powers => "application/json",
+        }
+    }
+
+    /// The first of the supported media types listed in an `Accept` header.
+    fn from_accept(header: &str) -> Self {
+        header
+            .split(',')
+            .find_map(|media| match media.split(';').next().map(str::trim) {
+                Some("application/json") => Some(Self::Json),
+                Some("application/octet-stream") => Some(Self::Binary),
+                _ => None,
+            })
+            .unwrap_or(Self::Binary)
+    }
+
+    fn encode(self, batch: &BatchTranscript) -> Bytes {
+        match self {
+            Self::Binary => encode_all(batch),
+            Self::Json => serde_json::to_vec(&batch.to_json())
+                .expect("Transcripts serialize")
+                .into(),
+            Self::Powers => {
+                let contributions = batch
+                    .transcripts
+                    .iter()
+                    .map(Transcript::contribution)
+                    .collect::<Vec<_>>();
+                serde_json::to_vec(&ContributionsJson::from_contributions(&contributions))
+                    .expect("Contributions serialize")
+                    .into()
+            }
+        }
+    }
+}
+
+/// The full transcript for the latest contribution.
+#[derive(Debug, Default)]
+pub struct FullTranscriptCache {
+    latest: Mutex<Option<Arc<FullTranscript>>>,
+}
+
+#[derive(Debug)]
+struct FullTranscript {
+    hash:       [u8; 32],
+    transcript: BatchTranscript,
+    bodies:     Mutex<Bodies>,
+}
+
+/// Bodies by format and content encoding, computed on first use.
+type Bodies = HashMap<(Format, Encoding), Arc<OnceCell<Bytes>>>;
+
+impl FullTranscriptCache {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the current transcript, replacing the cached bodies if it
+    /// changed since the last request.
+    async fn get(&self, ceremony: &SharedCeremony) -> Arc<FullTranscript> {
+        let ceremony = ceremony.lock().await;
+        let hash = ceremony.hash();
+        let latest = self.latest.lock().unwrap().clone();
+        if let Some(latest) = latest.filter(|latest| latest.hash == hash) {
+            return latest;
+        }
+        let full = Arc::new(FullTranscript {
+            hash,
+            transcript: ceremony.transcript().clone(),
+            bodies: Mutex::default(),
+        });
+        drop(ceremony);
+        *self.latest.lock().unwrap() = Some(full.clone());
+        full
+    }
+
+    /// Replace the cached transcript with the current one and encode the
+    /// [`PRECOMPUTED`] bodies.
+    pub async fn precompute(&self, ceremony: &SharedCeremony) {
+        let full = self.get(ceremony).await;
+        spawn_blocking(move || {
+            for (format, encoding) in PRECOMPUTED {
+                full.encode(format, encoding);
+            }
+        })
+        .await
+        .expect("Encoding task panicked");
+    }
+}
+
+/// Precompute the transcript bodies on start and after every contribution.
+pub fn spawn(cache: Arc<FullTranscriptCache>, ceremony: SharedCeremony, events: EventBus) {
+    tokio::spawn(async move {
+        let mut receiver = events.subscribe();
+        loop {
+            cache.precompute(&ceremony).await;
+            if !EventBus::next_contribution(&mut receiver).await {
+                return;
+            }
+        }
+    });
+}
+
+impl FullTranscript {
+    fn etag(&self, format: Format) -> String {
+        let suffix = match format {
+            Format::Binary => "",
+            Format::Json => "-json",
+            Format::Powers => "-powers",
+        };
+        format!("\"0x{}{}\"", hex::encode(self.hash), suffix)
+    }
+
+    async fn body(self: &Arc<Self>, format: Format, encoding: Encoding) -> Bytes {
+        let cached = self
+            .bodies
+            .lock()
+            .unwrap()
+            .get(&(format, encoding))
+            .map_or(false, |cell| cell.get().is_some());
+        BODY_CACHE
+            .with_label_values(&[if cached { "hit" } else { "miss" }])
+            .inc();
+        let full = self.clone();
+        spawn_blocking(move || full.encode(format, encoding))
+            .await
+            .expect("Encoding task panicked")
+    }
+
+    fn encode(&self, format: Format, encoding: Encoding) -> Bytes {
+        let cell = self
+            .bodies
+            .lock()
+            .unwrap()
+            .entry((format, encoding))
+            .or_default()
+            .clone();
+        cell.get_or_init(|| {
+            if encoding == Encoding::Identity {
+                info!(?format, "Encoding full transcript");
+                return format.encode(&self.transcript);
+            }
+            let body = self.encode(format, Encoding::Identity);
+            let compressed = Bytes::from(encoding.compress(&body));
+            compression::record("response", encoding, body.len(), compressed.len());
+            compressed
+        })
+        .clone()
+    }
+}
+
+/// Serve the full transcript in the requested format, or a byte range of it.
+///
+/// The format is taken from `?format=`, or else from the `Accept` header.
+pub async fn full_handler(
+    headers: HeaderMap,
+    Query(query): Query<FullQuery>,
+    Extension(ceremony): Extension<SharedCeremony>,
+    Extension(cache): Extension<Arc<FullTranscriptCache>>,
+) -> Result<Response, TranscriptError> {
+    let format = query.format.unwrap_or_else(|| {
+        headers
+            .get(ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .map_or(Format::Binary, Format::from_accept)
+    });
+    let full = cache.get(&ceremony).await;
+    let etag = full.etag(format);
+    let common = [
+        (CONTENT_TYPE, format.content_type().to_string()),
+        (ACCEPT_RANGES, "bytes".to_string()),
+        (ETAG, etag.clone()),
+        (VARY, "Accept, Accept-Encoding".to_string()),
+    ];
+    if headers
+        .get(IF_NONE_MATCH)
+        .map_or(false, |value| matches_etag(value, &etag))
+    {
+        return Ok((StatusCode::NOT_MODIFIED, common).into_response());
+    }
+
+    // A range for a different version of the transcript is ignored.
+    let range_header = headers.get(RANGE).filter(|_| {
+        headers
+            .get(IF_RANGE)
+            .map_or(true, |value| matches_etag(value, &etag))
+    });
+    if let Some(header) = range_header {
+        let body = full.body(format, Encoding::Identity).await;
+        let len = body.len();
+        let range = header
+            .to_str()
+            .ok()
+            .and_then(|header| parse_range(header, len))
+            .ok_or(TranscriptError::InvalidRange(len))?;
+        return Ok((
+            StatusCode::PARTIAL_CONTENT,
+            common,
+            [(
+                CONTENT_RANGE,
+                format!("bytes {}-{}/{}", range.start, range.end - 1, len),
+            )],
+            body.slice(range),
+        )
+            .into_response());
+    }
+
+    let encoding = headers
+        .get(ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map_or(Encoding::Identity, Encoding::negotiate);
+    let body = full.body(format, encoding).await;
+    if encoding == Encoding::Identity {
+        Ok((common, body).into_response())
+    } else {
+        let content_encoding = [(CONTENT_ENCODING, encoding.as_str().to_string())];
+        Ok((common, content_encoding, body).into_response())
+    }
+}
+
+impl IntoResponse for TranscriptError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::NotFound(_) => (StatusCode::NOT_FOUND, self.to_string()).into_response(),
+            Self::InvalidRange(len) => (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(CONTENT_RANGE, format!("bytes */{len}"))],
+                self.to_string(),
+            )
+                .into_response(),
+        }
+    }
+}
+
+/// Length in bytes of the encoded sub-transcript.
+#[must_use]
+pub fn encoded_len(transcript: &Transcript) -> usize {
+    let g1 = G1Affine::default().uncompressed_size();
+    let g2 = G2Affine::default().uncompressed_size();
+    (transcript.g1_powers.len() + transcript.products.len()) * g1
+        + (transcript.g2_powers.len() + transcript.pubkeys.len()) * g2
+}
+
+/// Encode all sub-transcripts, each prefixed with its byte length.
+#[must_use]
+pub fn encode_all(batch: &BatchTranscript) -> Bytes {
+    let mut body = Vec::new();
+    for transcript in &batch.transcripts {
+        let len = encoded_len(transcript);
+        body.extend_from_slice(&(len as u64).to_le_bytes());
+        body.extend_from_slice(&encode_range(transcript, 0..len));
+    }
+    body.into()
+}
+
+/// Decode a full transcript as produced by [`encode_all`].
+///
+/// `sizes` are the number of G1 and G2 powers of each sub-transcript. All
+/// points are checked to be in the prime order subgroup.
+pub fn decode_all(
+    bytes: &[u8],
+    sizes: &[(usize, usize)],
+) -> Result<BatchTranscript, TranscriptDecodeError> {
+    let g1 = G1Affine::default().uncompressed_size();
+    let g2 = G2Affine::default().uncompressed_size();
+    let mut rest = bytes;
+    let transcripts = sizes
+        .iter()
+        .enumerate()
+        .map(|(index, &(num_g1, num_g2))| {
+            if rest.len() < 8 {
+                return Err(TranscriptDecodeError::Truncated(index));
+            }
+            let (len, tail) = rest.split_at(8);
+            let len = u64::from_le_bytes(len.try_into().unwrap());
+            let len = usize::try_from(len).map_err(|_| TranscriptDecodeError::Length(index))?;
+            if tail.len() < len {
+                return Err(TranscriptDecodeError::Truncated(index));
+            }
+            let (mut bytes, tail) = tail.split_at(len);
+            rest = tail;
+            let fixed = num_g1 * g1 + num_g2 * g2;
+            if len < fixed || (len - fixed) % (g1 + g2) != 0 {
+                return Err(TranscriptDecodeError::Length(index));
+            }
+            let num_contributions = (len - fixed) / (g1 + g2);
+            let error = |_| TranscriptDecodeError::InvalidPoint(index);
+            Ok(Transcript {
+                g1_powers:  decode_points(&mut bytes, num_g1).map_err(error)?,
+                g2_powers:  decode_points(&mut bytes, num_g2).map_err(error)?,
+                products:   decode_points(&mut bytes, num_contributions).map_err(error)?,
+                pubkeys:    decode_points(&mut bytes, num_contributions).map_err(error)?,
+                // Identity signatures are not part of the binary encoding.
+                signatures: vec![None; num_contributions],
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if !rest.is_empty() {
+        return Err(TranscriptDecodeError::TrailingBytes);
+    }
+    Ok(transcripts.into())
+}
+
+fn decode_points<P: CanonicalDeserialize>(
+    bytes: &mut &[u8],
+    n: usize,
+) -> Result<Vec<P>, SerializationError> {
+    (0..n)
+        .map(|_| P::deserialize_uncompressed(&mut *bytes))
+        .collect()
+}
+
+/// Encode only the bytes in `range` of the sub-transcript.
+#[must_use]
+pub fn encode_range(transcript: &Transcript, range: Range<usize>) -> Bytes {
+    let mut out = Vec::with_capacity(range.len());
+    let mut offset = 0;
+    offset = encode_section(&transcript.g1_powers, offset, &range, &mut out);
+    offset = encode_section(&transcript.g2_powers, offset, &range, &mut out);
+    offset = encode_section(&transcript.products, offset, &range, &mut out);
+    encode_section(&transcript.pubkeys, offset, &range, &mut out);
+    out.into()
+}
+
+/// Append the part of `points`, located at `offset`, that overlaps `range`.
+/// Returns the offset of the next section.
+fn encode_section<P: CanonicalSerialize + Default>(
+    points: &[P],
+    offset: usize,
+    range: &Range<usize>,
+    out: &mut Vec<u8>,
+) -> usize {
+    let size = P::default().uncompressed_size();
+    let end = offset + points.len() * size;
+    let start = range.start.max(offset);
+    let stop = range.end.min(end);
+    if start < stop {
+        let first = (start - offset) / size;
+        let last = (stop - offset + size - 1) / size;
+        let mut buffer = Vec::with_capacity((last - first) * size);
+        for point in &points[first..last] {
+            point
+                .serialize_uncompressed(&mut buffer)
+                .expect("Writing to a Vec can not fail");
+        }
+        let skip = start - offset - first * size;
+        out.extend_from_slice(&buffer[skip..skip + stop - start]);
+    }
+    end
+}
+
+/// Whether an `If-None-Match` or `If-Range` header matches `etag`.
+fn matches_etag(header: &HeaderValue, etag: &str) -> bool {
+    header.to_str().map_or(false, |header| {
+        header
+            .split(',')
+            .any(|tag| tag.trim() == "*" || tag.trim() == etag)
+    })
+}
+
+/// Parse a single-range `Range` header for a resource of `len` bytes.
+fn parse_range(header: &str, len: usize) -> Option<Range<usize>> {
+    let spec = header.strip_prefix("bytes=")?.trim();
+    let (start, end) = spec.split_once('-')?;
+    let range = if start.is_empty() {
+        // Suffix range: the last `end` bytes.
+        let suffix: usize = end.parse().ok()?;
+        len.saturating_sub(suffix)..len
+    } else {
+        let start: usize = start.parse().ok()?;
+        let end = if end.is_empty() {
+            len
+        } else {
+            min(end.parse::<usize>().ok()?.checked_add(1)?, len)
+        };
+        start..end
+    };
+    (range.start < range.end).then_some(range)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use kzg_ceremony_crypto::{BatchTranscriptJson, SanityChecks};
+
+    #[test]
+    fn test_encode_range() {
+        let transcript = Transcript::new(5, 3);
+        let len = encoded_len(&transcript);
+        let full = encode_range(&transcript, 0..len);
+        assert_eq!(full.len(), len);
+        for range in [0..10, 90..100, 470..490, 5 * 96..5 * 96 + 1, 700..len] {
+            assert_eq!(encode_range(&transcript, range.clone()), full[range]);
+        }
+    }
+
+    #[test]
+    fn test_encode_all() {
+        let sizes = [(4, 2), (8, 2)];
+        let batch = BatchTranscript::new(&sizes);
+        let body = encode_all(&batch);
+        let first = encoded_len(&batch.transcripts[0]);
+        assert_eq!(body[..8], (first as u64).to_le_bytes());
+        assert_eq!(body.len(), 16 + first + encoded_len(&batch.transcripts[1]));
+
+        assert_eq!(decode_all(&body, &sizes).unwrap(), batch);
+        assert_eq!(
+            decode_all(&body[..body.len() - 1], &sizes),
+            Err(TranscriptDecodeError::Truncated(1))
+        );
+        assert_eq!(
+            decode_all(&body, &[(4, 2)]),
+            Err(TranscriptDecodeError::TrailingBytes)
+        );
+        assert_eq!(
+            decode_all(&body, &[(5, 2), (8, 2)]),
+            Err(TranscriptDecodeError::Length(0))
+        );
+    }
+
+    #[test]
+    fn test_formats() {
+        let sizes = [(4, 2), (8, 2)];
+        let batch = BatchTranscript::new(&sizes);
+        let full = FullTranscript {
+            hash:       batch.hash(),
+            transcript: batch.clone(),
+            bodies:     Mutex::default(),
+        };
+        assert_eq!(
+            full.encode(Format::Binary, Encoding::Identity),
+            encode_all(&batch)
+        );
+
+        let json = full.encode(Format::Json, Encoding::Identity);
+        let json: BatchTranscriptJson = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json.parse().unwrap(), batch);
+
+        let powers = full.encode(Format::Powers, Encoding::Gzip);
+        let powers = Encoding::Gzip.decompress(&powers, 100_000).unwrap();
+        let powers: ContributionsJson = serde_json::from_slice(&powers).unwrap();
+        let contributions = powers
+            .parse_with_sizes(&sizes, SanityChecks::INITIAL)
+            .unwrap();
+        assert_eq!(contributions[1], batch.transcripts[1].contribution());
+
+        assert_ne!(full.etag(Format::Binary), full.etag(Format::Powers));
+        assert_eq!(Format::from_accept("application/json"), Format::Json);
+        assert_eq!(
+            Format::from_accept("application/octet-stream, application/json;q=0.5"),
+            Format::Binary
+        );
+        assert_eq!(Format::from_accept("*/*"), Format::Binary);
+    }
+
+    #[test]
+    fn test_headers() {
+        let etag = "\"0x01\"";
+        assert!(matches_etag(&HeaderValue::from_static("\"0x01\""), etag));
+        assert!(matches_etag(
+            &HeaderValue::from_static("\"0x02\", \"0x01\""),
+            etag
+        ));
+        assert!(matches_etag(&HeaderValue::from_static("*"), etag));
+        assert!(!matches_etag(&HeaderValue::from_static("\"0x02\""), etag));
+        assert_eq!(Encoding::negotiate("gzip, deflate, br"), Encoding::Gzip);
+        assert_eq!(Encoding::negotiate("br;q=1.0, gzip;q=0.8"), Encoding::Gzip);
+        assert_eq!(Encoding::negotiate("gzip;q=0"), Encoding::Identity);
+        assert_eq!(Encoding::negotiate("identity"), Encoding::Identity);
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Some(0..100));
+        assert_eq!(parse_range("bytes=900-", 1000), Some(900..1000));
+        assert_eq!(parse_range("bytes=-100", 1000), Some(900..1000));
+        assert_eq!(parse_range("bytes=900-2000", 1000), Some(900..1000));
+        assert_eq!(parse_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_range("bytes=0-1,5-9", 1000), None);
+        assert_eq!(parse_range("items=0-1", 1000), None);
+    }
+}
+
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench {
+    use super::*;
+    use criterion::{black_box, BenchmarkId, Criterion};
+    use kzg_ceremony_crypto::SIZES;
+
+    pub fn group(criterion: &mut Criterion) {
+        bench_powers(criterion);
+    }
+
+    /// Serving the powers to the next contributor, encoded on request or
+    /// precomputed.
+    fn bench_powers(criterion: &mut Criterion) {
+        let batch = BatchTranscript::new(&SIZES);
+        criterion.bench_function("transcript/powers/encode", |bencher| {
+            bencher.iter(|| black_box(Format::Powers.encode(black_box(&batch))));
+        });
+        let full = FullTranscript {
+            hash:       batch.hash(),
+            transcript: batch,
+            bodies:     Mutex::default(),
+        };
+        for (format, encoding) in PRECOMPUTED {
+            full.encode(format, encoding);
+        }
+        for encoding in [Encoding::Identity, Encoding::Gzip] {
+            criterion.bench_with_input(
+                BenchmarkId::new("transcript/powers/cached", encoding.as_str()),
+                &encoding,
+                |bencher, &encoding| {
+                    bencher.iter(|| black_box(full.encode(Format::Powers, encoding)));
+                },
+            );
+        }
+    }
+}
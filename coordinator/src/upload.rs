@@ -0,0 +1,220 @@
+//! Bounded streaming request bodies.
+//!
+//! Contribution uploads are several megabytes. Instead of buffering the body
+//! unconditionally, [`Upload`] rejects oversized requests up front based on
+//! `Content-Length`, reads the body chunk by chunk, so the socket applies
+//! backpressure to the client, aborts as soon as the limit is exceeded and
+//! hashes the bytes as they arrive.
+//!
+//! Bodies with `Content-Encoding: gzip` or `deflate` are decompressed after
+//! receiving, again bounded by the limit, and hashed as decompressed. Other
+//! encodings are rejected with `415 Unsupported Media Type`.
+//!
+//! If a [`BodyTimeout`] is set, a client pausing for longer than that between
+//! chunks is answered with `408 Request Timeout`.
+
+use crate::{
+    compression::{self, Encoding},
+    limits::BodyTimeout,
+};
+use axum::{
+    async_trait,
+    body::{Bytes, HttpBody},
+    extract::{FromRequest, RequestParts},
+    http::{
+        header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH},
+        StatusCode,
+    },
+    response::{IntoResponse, Response},
+    BoxError, Extension, Json,
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::time::timeout;
+use tracing::debug;
+
+#[derive(Debug, Error)]
+pub enum UploadError {
+    #[error("Upload exceeds the maximum size of {0} bytes")]
+    TooLarge(usize),
+    #[error("Error receiving upload: {0}")]
+    Body(BoxError),
+    #[error("Unsupported content encoding {0}")]
+    UnsupportedEncoding(String),
+    #[error("Could not decompress upload: {0}")]
+    Decompress(std::io::Error),
+    #[error("Timed out waiting for the upload")]
+    Timeout,
+    #[error("Request body already consumed")]
+    Consumed,
+}
+
+/// Maximum size of an upload in bytes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MaxUploadSize(pub usize);
+
+/// A fully received request body with its SHA-256 hash.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Upload {
+    pub bytes: Bytes,
+    pub hash:  [u8; 32],
+}
+
+#[async_trait]
+impl<B> FromRequest<B> for Upload
+where
+    B: HttpBody<Data = Bytes> + Unpin + Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = UploadError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Extension(MaxUploadSize(max)) = Extension::<MaxUploadSize>::from_request(req)
+            .await
+            .expect("MaxUploadSize extension missing");
+        let declared = req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<usize>().ok());
+        if declared.map_or(false, |len| len > max) {
+            return Err(UploadError::TooLarge(max));
+        }
+        let encoding = match req.headers().get(CONTENT_ENCODING) {
+            None => Encoding::Identity,
+            Some(value) => value
+                .to_str()
+                .ok()
+                .and_then(Encoding::from_content_encoding)
+                .ok_or_else(|| {
+                    UploadError::UnsupportedEncoding(
+                        String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                    )
+                })?,
+        };
+        let stall = req.extensions().get::<BodyTimeout>().map(|t| t.0);
+        let mut body = req.take_body().ok_or(UploadError::Consumed)?;
+
+        let mut bytes = Vec::with_capacity(declared.unwrap_or_default());
+        let mut hasher = Sha256::new();
+        loop {
+            let chunk = match stall {
+                Some(stall) => timeout(stall, body.data())
+                    .await
+                    .map_err(|_| UploadError::Timeout)?,
+                None => body.data().await,
+            };
+            let chunk = match chunk {
+                Some(chunk) => chunk.map_err(|e| UploadError::Body(e.into()))?,
+                None => break,
+            };
+            if bytes.len() + chunk.len() > max {
+                return Err(UploadError::TooLarge(max));
+            }
+            if encoding == Encoding::Identity {
+                hasher.update(&chunk);
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        if encoding != Encoding::Identity {
+            let encoded = bytes.len();
+            bytes = encoding
+                .decompress(&bytes, max)
+                .map_err(UploadError::Decompress)?;
+            if bytes.len() > max {
+                return Err(UploadError::TooLarge(max));
+            }
+            compression::record("request", encoding, bytes.len(), encoded);
+            hasher.update(&bytes);
+        }
+        debug!(
+            len = bytes.len(),
+            encoding = encoding.as_str(),
+            "Received upload"
+        );
+        Ok(Self {
+            bytes: bytes.into(),
+            hash:  hasher.finalize().into(),
+        })
+    }
+}
+
+impl IntoResponse for UploadError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::Body(_) | Self::Decompress(_) => StatusCode::BAD_REQUEST,
+            Self::UnsupportedEncoding(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::Timeout => StatusCode::REQUEST_TIMEOUT,
+            Self::Consumed => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let body = Json(json!({ "error": self.to_string() }));
+        (status, [(ACCEPT_ENCODING, compression::ACCEPTED)], body).into_response()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use axum::{body::Body, http::Request};
+    use futures::stream;
+
+    async fn upload(body: Body, content_length: Option<usize>) -> Result<Upload, UploadError> {
+        upload_encoded(body, content_length, None).await
+    }
+
+    async fn upload_encoded(
+        body: Body,
+        content_length: Option<usize>,
+        encoding: Option<&str>,
+    ) -> Result<Upload, UploadError> {
+        let mut request = Request::builder().extension(MaxUploadSize(10));
+        if let Some(len) = content_length {
+            request = request.header(CONTENT_LENGTH, len);
+        }
+        if let Some(encoding) = encoding {
+            request = request.header(CONTENT_ENCODING, encoding);
+        }
+        Upload::from_request(&mut RequestParts::new(request.body(body).unwrap())).await
+    }
+
+    #[tokio::test]
+    async fn test_upload() {
+        let chunks = ["hello ", "world"].map(Ok::<_, std::io::Error>);
+        let result = upload(Body::wrap_stream(stream::iter(chunks)), None).await;
+        assert!(matches!(result, Err(UploadError::TooLarge(10))));
+
+        let result = upload(Body::from("hello"), Some(11)).await;
+        assert!(matches!(result, Err(UploadError::TooLarge(10))));
+
+        let chunks = ["hel", "lo"].map(Ok::<_, std::io::Error>);
+        let received = upload(Body::wrap_stream(stream::iter(chunks)), None)
+            .await
+            .unwrap();
+        assert_eq!(received.bytes, "hello");
+        assert_eq!(received.hash, <[u8; 32]>::from(Sha256::digest(b"hello")));
+    }
+
+    #[tokio::test]
+    async fn test_compressed_upload() {
+        // Gzip headers alone exceed the test limit.
+        let body = Encoding::Deflate.compress(b"hello");
+        let received = upload_encoded(Body::from(body), None, Some("deflate"))
+            .await
+            .unwrap();
+        assert_eq!(received.bytes, "hello");
+        assert_eq!(received.hash, <[u8; 32]>::from(Sha256::digest(b"hello")));
+
+        // The limit applies to the decompressed size.
+        let body = Encoding::Deflate.compress(&[0; 20]);
+        assert!(body.len() <= 10);
+        let result = upload_encoded(Body::from(body), None, Some("deflate")).await;
+        assert!(matches!(result, Err(UploadError::TooLarge(10))));
+
+        let result = upload_encoded(Body::from("hello"), None, Some("gzip")).await;
+        assert!(matches!(result, Err(UploadError::Decompress(_))));
+        let result = upload_encoded(Body::from("hello"), None, Some("br")).await;
+        assert!(matches!(result, Err(UploadError::UnsupportedEncoding(_))));
+    }
+}
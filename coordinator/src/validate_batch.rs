@@ -0,0 +1,155 @@
+//! Bulk pre-validation of candidate contributions.
+//!
+//! Client implementers submit contribution files computed on top of a known
+//! batch contribution. This validates each of them and emits a
+//! machine-readable conformance report.
+
+use crate::scheduler;
+use clap::Parser;
+use eyre::{ensure, Result as EyreResult, WrapErr};
+use kzg_ceremony_crypto::{BatchTranscript, ContributionsJson, SanityChecks, Transcript};
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tracing::{info, instrument, warn};
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+pub struct Options {
+    /// Batch contribution the candidates were computed on top of.
+    #[clap(long)]
+    pub transcript: PathBuf,
+
+    /// Directory containing the candidate contribution files (`*.json`).
+    pub directory: PathBuf,
+
+    /// Write the report to this file instead of stdout.
+    #[clap(long)]
+    pub report: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub transcript: PathBuf,
+    pub passed:     usize,
+    pub failed:     usize,
+    pub results:    Vec<FileReport>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileReport {
+    pub file:  PathBuf,
+    pub valid: bool,
+    pub error: Option<String>,
+}
+
+#[allow(clippy::unused_async)] // Same signature as the other commands
+pub async fn main(options: Options) -> EyreResult<()> {
+    let transcript = load_transcript(&options.transcript)?;
+
+    let mut files = fs::read_dir(&options.directory)
+        .wrap_err_with(|| format!("Could not read {}", options.directory.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    files.retain(|path| path.extension().map_or(false, |ext| ext == "json"));
+    files.sort();
+
+    let results = files
+        .into_iter()
+        .map(|file| {
+            let result = validate_file(&transcript, &file);
+            if let Err(error) = &result {
+                warn!(file = %file.display(), "Invalid contribution: {:#}", error);
+            }
+            FileReport {
+                file,
+                valid: result.is_ok(),
+                error: result.err().map(|e| format!("{e:#}")),
+            }
+        })
+        .collect::<Vec<_>>();
+    let passed = results.iter().filter(|r| r.valid).count();
+    let report = Report {
+        transcript: options.transcript,
+        passed,
+        failed: results.len() - passed,
+        results,
+    };
+    info!(
+        passed = report.passed,
+        failed = report.failed,
+        "Validated contributions"
+    );
+
+    let json = serde_json::to_string_pretty(&report)?;
+    match options.report {
+        Some(path) => fs::write(path, json)?,
+        None => println!("{json}"),
+    }
+    Ok(())
+}
+
+fn load_transcript(path: &Path) -> EyreResult<BatchTranscript> {
+    let json = fs::read_to_string(path)
+        .wrap_err_with(|| format!("Could not read transcript {}", path.display()))?;
+    // The batch may be the initial contribution, which is degenerate.
+    let contributions =
+        serde_json::from_str::<ContributionsJson>(&json)?.parse_with(SanityChecks::INITIAL)?;
+    Ok(contributions
+        .iter()
+        .map(Transcript::from_contribution)
+        .collect::<Result<Vec<_>, _>>()?
+        .into())
+}
+
+#[allow(clippy::disallowed_methods)] // False positive from macro
+#[instrument(level = "info", skip(transcript))]
+fn validate_file(transcript: &BatchTranscript, path: &Path) -> EyreResult<()> {
+    let transcripts = &transcript.transcripts;
+    let json = fs::read_to_string(path)?;
+    let contributions = serde_json::from_str::<ContributionsJson>(&json)?.parse()?;
+    ensure!(
+        contributions.len() == transcripts.len(),
+        "Expected {} contributions, got {}",
+        transcripts.len(),
+        contributions.len()
+    );
+    let jobs = contributions
+        .iter()
+        .zip(transcripts.iter())
+        .enumerate()
+        .collect::<Vec<_>>();
+    scheduler::verify_all(&jobs, |i, (contribution, transcript)| {
+        // Verification assumes the points are in the prime order subgroups.
+        contribution
+            .check_subgroups()
+            .and_then(|()| contribution.verify(transcript))
+            .wrap_err_with(|| format!("Verification failed for contribution {i}"))
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test::TestDir;
+
+    #[test]
+    fn test_invalid_files() {
+        let transcript = BatchTranscript::new(&kzg_ceremony_crypto::SIZES);
+        let dir = TestDir::new("validate-batch");
+        fs::create_dir_all(&dir).unwrap();
+
+        let garbage = dir.join("garbage.json");
+        fs::write(&garbage, "not json").unwrap();
+        assert!(validate_file(&transcript, &garbage).is_err());
+
+        let empty = dir.join("empty.json");
+        fs::write(&empty, r#"{"subContributions": []}"#).unwrap();
+        let error = validate_file(&transcript, &empty).unwrap_err();
+        assert!(error.to_string().contains("number of contributions"));
+    }
+}
@@ -0,0 +1,199 @@
+//! Independent verification of a published transcript.
+//!
+//! Re-runs the full verification chain on a transcript in the format served
+//! at `/ceremony/transcript`: every point is checked to be in the prime order
+//! subgroup, every running product is checked against its pubkey and the
+//! final powers are checked for consistency with the last running product.
+//! Finally the powers are used for a KZG commitment and opening, as a smoke
+//! test of the SRS.
+
+use crate::{
+    client::CeremonyClient,
+    transcript::{decode_all, encoded_len},
+};
+use clap::Parser;
+use eyre::{bail, Result as EyreResult, WrapErr};
+use indicatif::{ProgressBar, ProgressStyle};
+use kzg_ceremony_crypto::{Kzg, Transcript, VerifyError, SIZES};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::{fs, path::PathBuf, time::Duration};
+use tracing::{error, info, instrument};
+use url::Url;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+pub struct Options {
+    /// Transcript file, or the base url of a coordinator to download it from.
+    pub source: String,
+
+    /// Write the report to this file instead of stdout.
+    #[clap(long)]
+    pub report: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub source:          String,
+    pub valid:           bool,
+    /// Set when the transcript could not be decoded, this includes points
+    /// outside the prime order subgroup.
+    pub error:           Option<String>,
+    pub sub_transcripts: Vec<SubTranscriptReport>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubTranscriptReport {
+    pub index:             usize,
+    pub num_g1_powers:     usize,
+    pub num_g2_powers:     usize,
+    pub num_contributions: usize,
+    pub valid:             bool,
+    pub error:             Option<String>,
+}
+
+pub async fn main(options: Options) -> EyreResult<()> {
+    let bytes = read_source(&options.source).await?;
+    let report = verify(options.source, &bytes, &SIZES);
+
+    let json = serde_json::to_string_pretty(&report)?;
+    match options.report {
+        Some(path) => fs::write(path, json)?,
+        None => println!("{json}"),
+    }
+    if !report.valid {
+        bail!("Transcript verification failed");
+    }
+    info!("Transcript is valid");
+    Ok(())
+}
+
+/// Read a transcript file, or download the transcript if `source` is the url
+/// of a coordinator.
+pub async fn read_source(source: &str) -> EyreResult<Vec<u8>> {
+    match source.parse::<Url>() {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {
+            info!(%url, "Downloading transcript");
+            Ok(CeremonyClient::new(url).transcript().await?.to_vec())
+        }
+        _ => fs::read(source).wrap_err_with(|| format!("Could not read transcript {source}")),
+    }
+}
+
+#[allow(clippy::disallowed_methods)] // False positive from macro
+#[instrument(level = "info", skip(bytes), fields(len = bytes.len()))]
+fn verify(source: String, bytes: &[u8], sizes: &[(usize, usize)]) -> Report {
+    let spinner = ProgressBar::new_spinner().with_message("Decoding and checking subgroups");
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    let transcript = decode_all(bytes, sizes);
+    spinner.finish_and_clear();
+    let transcripts = match transcript {
+        Ok(transcript) => transcript.transcripts,
+        Err(error) => {
+            error!(?error, "Could not decode transcript");
+            return Report {
+                source,
+                valid: false,
+                error: Some(error.to_string()),
+                sub_transcripts: Vec::new(),
+            };
+        }
+    };
+
+    let steps = transcripts
+        .iter()
+        .map(|t| t.products.len() as u64 + 1)
+        .sum();
+    let progress = ProgressBar::new(steps).with_style(
+        ProgressStyle::with_template("{msg} {wide_bar} {pos}/{len} [{elapsed_precise}]")
+            .expect("Valid progress template"),
+    );
+    let sub_transcripts = transcripts
+        .iter()
+        .enumerate()
+        .map(|(index, transcript)| {
+            progress.set_message(format!("Sub-transcript {index}"));
+            let result = verify_transcript(transcript, &progress)
+                .map_err(|error| error.to_string())
+                .and_then(|()| kzg_smoke_test(transcript));
+            if let Err(error) = &result {
+                error!(index, ?error, "Invalid sub-transcript");
+            }
+            SubTranscriptReport {
+                index,
+                num_g1_powers: transcript.g1_powers.len(),
+                num_g2_powers: transcript.g2_powers.len(),
+                num_contributions: transcript.products.len().saturating_sub(1),
+                valid: result.is_ok(),
+                error: result.err(),
+            }
+        })
+        .collect::<Vec<_>>();
+    progress.finish_and_clear();
+    info!(
+        bytes = transcripts.iter().map(encoded_len).sum::<usize>(),
+        "Verified transcript"
+    );
+    Report {
+        source,
+        valid: sub_transcripts.iter().all(|r| r.valid),
+        error: None,
+        sub_transcripts,
+    }
+}
+
+/// Same checks as [`Transcript::verify`], reporting progress per step.
+fn verify_transcript(transcript: &Transcript, progress: &ProgressBar) -> Result<(), VerifyError> {
+    transcript.verify_powers()?;
+    progress.inc(1);
+    (0..transcript.products.len())
+        .into_par_iter()
+        .try_for_each(|index| {
+            transcript.verify_step(index)?;
+            progress.inc(1);
+            Ok(())
+        })
+}
+
+/// Check that the powers work for KZG commitments.
+fn kzg_smoke_test(transcript: &Transcript) -> Result<(), String> {
+    let kzg = Kzg::from_transcript(transcript).map_err(|error| error.to_string())?;
+    if kzg.smoke_test(&mut rand::thread_rng()) {
+        Ok(())
+    } else {
+        Err("KZG smoke test failed".to_string())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::transcript::encode_all;
+    use kzg_ceremony_crypto::{BatchTranscript, SecretRng, SecretScalar};
+
+    #[test]
+    fn test_verify() {
+        let sizes = [(16, 3), (32, 3)];
+        let mut batch = BatchTranscript::new(&sizes);
+        for transcript in &mut batch.transcripts {
+            let mut contribution = transcript.contribution();
+            contribution.add_tau(&SecretScalar::random(&mut SecretRng::new()));
+            transcript.add(contribution);
+        }
+        let report = verify("valid".into(), &encode_all(&batch), &sizes);
+        assert!(report.valid);
+        assert_eq!(report.sub_transcripts.len(), sizes.len());
+        assert_eq!(report.sub_transcripts[0].num_contributions, 1);
+
+        batch.transcripts[0].pubkeys[1] = batch.transcripts[0].pubkeys[0];
+        let report = verify("tampered".into(), &encode_all(&batch), &sizes);
+        assert!(!report.valid);
+        assert!(!report.sub_transcripts[0].valid);
+        assert!(report.sub_transcripts[1].valid);
+
+        let report = verify("truncated".into(), &[0; 4], &sizes);
+        assert!(!report.valid);
+        assert!(report.error.is_some());
+    }
+}
@@ -0,0 +1,287 @@
+//! Write-ahead log of applied contributions.
+//!
+//! The transcript store recovers its own files from a crash half-way through
+//! an append, see [`crate::storage`]. This log covers the ceremony around it:
+//! before a verified contribution is stored and applied to the in-memory
+//! transcript, a `begin` entry with its index, the pseudonym of the
+//! contributor and the hash of its pubkeys is appended and synced. It is
+//! followed by `commit` once the contribution is applied, or `abort` if storing
+//! it failed.
+//!
+//! On startup every committed entry is checked against the stored transcript,
+//! and a trailing `begin` is resolved: if the store holds the contribution it
+//! is committed, otherwise aborted. Either way the pseudonym is logged, since
+//! the participant did not get a receipt. A store that disagrees with the log
+//! is refused.
+//!
+//! The log is `contributions.wal` in the transcript directory, one JSON entry
+//! per line.
+
+use ark_bls12_381::G2Affine;
+use ark_serialize::CanonicalSerialize;
+use eyre::{bail, ensure, Result as EyreResult, WrapErr};
+use kzg_ceremony_crypto::{BatchTranscript, Contribution};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::Path,
+};
+use tracing::{info, instrument, warn};
+
+const FILE: &str = "contributions.wal";
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Entry {
+    Begin {
+        index:     usize,
+        /// Pseudonym of the contributor, see [`crate::pseudonym`]. `None` for
+        /// contributions followed from a primary coordinator.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pseudonym: Option<String>,
+        /// Hash of the pubkeys, see [`contribution_hash`].
+        hash:      String,
+    },
+    Commit {
+        index: usize,
+    },
+    Abort {
+        index: usize,
+    },
+}
+
+#[derive(Debug)]
+pub struct Wal {
+    file: File,
+}
+
+impl Wal {
+    /// Open the log in `dir` and reconcile it with the stored `transcript`,
+    /// whose contributions covered the sub-ceremonies in `rounds`.
+    #[allow(clippy::disallowed_methods)] // False positive from macro
+    #[instrument(level = "info", skip(transcript, rounds))]
+    pub fn open(
+        dir: &Path,
+        transcript: &BatchTranscript,
+        rounds: &[Vec<usize>],
+    ) -> EyreResult<Self> {
+        let path = dir.join(FILE);
+        let stored = stored_hashes(transcript, rounds);
+        let mut begun = BTreeMap::new();
+        let mut committed = 0;
+        if path.exists() {
+            let contents = fs::read_to_string(&path)
+                .wrap_err_with(|| format!("Could not read {}", path.display()))?;
+            let mut lines = contents.lines().peekable();
+            while let Some(line) = lines.next() {
+                let entry = match serde_json::from_str(line) {
+                    Ok(entry) => entry,
+                    // The last entry may be torn by the crash.
+                    Err(_) if lines.peek().is_none() => {
+                        warn!("Ignoring incomplete last entry of the write-ahead log");
+                        break;
+                    }
+                    Err(error) => return Err(error.into()),
+                };
+                match entry {
+                    Entry::Begin {
+                        index,
+                        pseudonym,
+                        hash,
+                    } => {
+                        begun.insert(index, (pseudonym, hash));
+                    }
+                    Entry::Commit { index } => {
+                        let (_, hash) = begun
+                            .remove(&index)
+                            .ok_or_else(|| eyre::eyre!("Commit of {} without begin", index))?;
+                        check(&stored, index, &hash)?;
+                        committed += 1;
+                    }
+                    Entry::Abort { index } => {
+                        begun.remove(&index);
+                    }
+                }
+            }
+        }
+        let mut wal = Self {
+            file: OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .wrap_err_with(|| format!("Could not open {}", path.display()))?,
+        };
+        for (index, (pseudonym, hash)) in begun {
+            if index <= rounds.len() {
+                check(&stored, index, &hash)?;
+                warn!(
+                    index,
+                    ?pseudonym,
+                    "Contribution was stored before a crash, but no receipt was issued"
+                );
+                wal.append(&Entry::Commit { index })?;
+            } else {
+                warn!(index, ?pseudonym, "Contribution was lost in a crash");
+                wal.append(&Entry::Abort { index })?;
+            }
+        }
+        info!(committed, "Checked write-ahead log against the transcript");
+        Ok(wal)
+    }
+
+    /// Record that contribution `index` is about to be applied.
+    pub fn begin(
+        &mut self,
+        index: usize,
+        pseudonym: Option<&str>,
+        contributions: &[Option<Contribution>],
+    ) -> EyreResult<()> {
+        let pubkeys = contributions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, contribution)| Some((i, &contribution.as_ref()?.pubkey)));
+        self.append(&Entry::Begin {
+            index,
+            pseudonym: pseudonym.map(str::to_string),
+            hash: format!("0x{}", hex::encode(contribution_hash(pubkeys))),
+        })
+    }
+
+    /// Record that contribution `index` was applied.
+    pub fn commit(&mut self, index: usize) -> EyreResult<()> {
+        self.append(&Entry::Commit { index })
+    }
+
+    /// Record that contribution `index` was not applied.
+    pub fn abort(&mut self, index: usize) -> EyreResult<()> {
+        self.append(&Entry::Abort { index })
+    }
+
+    fn append(&mut self, entry: &Entry) -> EyreResult<()> {
+        writeln!(self.file, "{}", serde_json::to_string(entry)?)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+/// SHA-256 over the sub-ceremony as little-endian `u64` and the uncompressed
+/// pubkey of every covered sub-ceremony, in order.
+fn contribution_hash<'a>(pubkeys: impl Iterator<Item = (usize, &'a G2Affine)>) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    let mut buffer = Vec::new();
+    for (sub_ceremony, pubkey) in pubkeys {
+        buffer.clear();
+        pubkey
+            .serialize_uncompressed(&mut buffer)
+            .expect("Serializing to a vector does not fail");
+        hasher.update((sub_ceremony as u64).to_le_bytes());
+        hasher.update(&buffer);
+    }
+    hasher.finalize().into()
+}
+
+/// Hashes of the stored contributions, `None` where the witness is missing.
+fn stored_hashes(transcript: &BatchTranscript, rounds: &[Vec<usize>]) -> Vec<Option<String>> {
+    // Witness entry of every sub-ceremony after the contribution, the first
+    // entry is the initial state.
+    let mut positions = vec![0; transcript.transcripts.len()];
+    rounds
+        .iter()
+        .map(|round| {
+            let pubkeys = round
+                .iter()
+                .map(|&i| {
+                    positions[i] += 1;
+                    transcript.transcripts[i]
+                        .pubkeys
+                        .get(positions[i])
+                        .map(|pubkey| (i, pubkey))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some(format!(
+                "0x{}",
+                hex::encode(contribution_hash(pubkeys.into_iter()))
+            ))
+        })
+        .collect()
+}
+
+/// Check that stored contribution `index` has `hash`.
+fn check(stored: &[Option<String>], index: usize, hash: &str) -> EyreResult<()> {
+    ensure!(
+        index > 0 && index <= stored.len(),
+        "Contribution {} of the write-ahead log is not in the transcript",
+        index
+    );
+    if stored[index - 1].as_deref() != Some(hash) {
+        bail!(
+            "Contribution {} of the transcript does not match the write-ahead log",
+            index
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{storage::TranscriptStore, test::TestDir};
+    use kzg_ceremony_crypto::{SecretRng, SecretScalar};
+
+    #[test]
+    fn test_recovery() {
+        let dir = TestDir::new("wal");
+        let batch = BatchTranscript::new(&[(4, 2), (8, 2)]);
+        let mut store = TranscriptStore::create(&dir, &batch).unwrap();
+        let mut rng = SecretRng::new();
+        let mut contribute = |covered: &[usize]| {
+            let batch = store.load().unwrap();
+            let contributions = batch
+                .transcripts
+                .iter()
+                .enumerate()
+                .map(|(i, transcript)| {
+                    covered.contains(&i).then(|| {
+                        let mut contribution = transcript.contribution();
+                        contribution.add_tau(&SecretScalar::random(&mut rng));
+                        contribution
+                    })
+                })
+                .collect::<Vec<_>>();
+            store.append(&contributions).unwrap();
+            contributions
+        };
+        let open = || {
+            let store = TranscriptStore::open(&dir).unwrap();
+            Wal::open(&dir, &store.load().unwrap(), &store.rounds().unwrap())
+        };
+
+        let mut wal = open().unwrap();
+        let first = contribute(&[0, 1]);
+        wal.begin(1, Some("alice"), &first).unwrap();
+        wal.commit(1).unwrap();
+        // Crash after storing the second contribution.
+        let second = contribute(&[1]);
+        wal.begin(2, Some("bob"), &second).unwrap();
+        drop(wal);
+        let mut wal = open().unwrap();
+        // Crash before storing the third contribution.
+        wal.begin(3, Some("carol"), &first).unwrap();
+        drop(wal);
+        open().unwrap();
+        let contents = fs::read_to_string(dir.join(FILE)).unwrap();
+        assert!(contents.contains(r#"{"type":"commit","index":2}"#));
+        assert!(contents.contains(r#"{"type":"abort","index":3}"#));
+
+        // A store that does not match the log is refused.
+        let mut wal = open().unwrap();
+        wal.begin(3, Some("carol"), &first).unwrap();
+        contribute(&[0]);
+        wal.commit(3).unwrap();
+        drop(wal);
+        assert!(open().is_err());
+    }
+}
@@ -0,0 +1,6 @@
+#![no_main]
+
+use kzg_ceremony_crypto::fuzz::parse_contributions;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| parse_contributions(data));
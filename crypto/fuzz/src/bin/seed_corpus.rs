@@ -0,0 +1,37 @@
+//! Write corpus seeds for the fuzz targets.
+//!
+//! Usage: `cargo run --bin seed_corpus [contribution.json]`, by default from
+//! the `initialContribution.json` of the specification submodule, or the
+//! generators if it is not checked out.
+
+use kzg_ceremony_crypto::{
+    fuzz::{contribution_seeds, point_seeds},
+    ContributionsJson,
+};
+use std::{env, fs, io, path::Path};
+
+const SPEC_CONTRIBUTION: &str = "../../specs/initialContribution.json";
+
+fn main() -> io::Result<()> {
+    let path = env::args().nth(1);
+    let contributions = match fs::read(path.as_deref().unwrap_or(SPEC_CONTRIBUTION)) {
+        Ok(json) => serde_json::from_slice(&json)?,
+        Err(error) if path.is_none() => {
+            eprintln!("Using generators, {}: {}", SPEC_CONTRIBUTION, error);
+            ContributionsJson::initial()
+        }
+        Err(error) => return Err(error),
+    };
+    write("parse_points", &point_seeds(&contributions))?;
+    write("parse_contributions", &contribution_seeds(&contributions))
+}
+
+fn write(target: &str, seeds: &[Vec<u8>]) -> io::Result<()> {
+    let dir = Path::new("corpus").join(target);
+    fs::create_dir_all(&dir)?;
+    for (i, seed) in seeds.iter().enumerate() {
+        fs::write(dir.join(format!("seed-{}", i)), seed)?;
+    }
+    println!("Wrote {} seeds to {}", seeds.len(), dir.display());
+    Ok(())
+}
@@ -0,0 +1,233 @@
+//! Interchangeable implementations of the curve operations.
+//!
+//! Verification only needs a handful of operations on public data: scalar
+//! multiplication, MSMs, a pairing product, subgroup checks and the compressed
+//! point encoding. [`CurveBackend`] collects them so a deployment can pick
+//! the fastest implementation available to it at build time. [`Arkworks`] is
+//! always available, [`crate::blst::Blst`] with the `blst` feature, and
+//! [`DefaultBackend`] is the one the crate uses.
+//!
+//! All operations run in variable time. Secret scalars go through
+//! [`crate::Contribution::add_tau`] instead.
+
+use crate::{
+    crypto, format_g,
+    msm::{msm_with_window, window_size},
+    parse_g, ParseError,
+};
+use ark_bls12_381::{g1, g2, Bls12_381, Fr, G1Affine, G1Projective, G2Affine, G2Projective};
+use ark_ec::{AffineCurve, PairingEngine};
+use ark_ff::{One, PrimeField};
+use rayon::prelude::*;
+
+/// Scalars of the MSMs, in canonical form.
+pub type ScalarInt = <Fr as PrimeField>::BigInt;
+
+/// The backend used throughout the crate, selected by feature flags.
+#[cfg(not(feature = "blst"))]
+pub type DefaultBackend = Arkworks;
+
+/// The backend used throughout the crate, selected by feature flags.
+#[cfg(feature = "blst")]
+pub type DefaultBackend = crate::blst::Blst;
+
+/// Curve operations on public data.
+pub trait CurveBackend {
+    /// Name for logs and benchmarks.
+    const NAME: &'static str;
+
+    fn g1_mul(point: &G1Affine, scalar: &Fr) -> G1Projective;
+
+    fn g2_mul(point: &G2Affine, scalar: &Fr) -> G2Projective;
+
+    /// `sum_i scalars[i] * bases[i]`, ignoring excess bases or scalars.
+    fn g1_msm(bases: &[G1Affine], scalars: &[ScalarInt]) -> G1Projective;
+
+    /// `sum_i scalars[i] * bases[i]`, ignoring excess bases or scalars.
+    fn g2_msm(bases: &[G2Affine], scalars: &[ScalarInt]) -> G2Projective;
+
+    /// Whether `prod_i e(p_i, q_i) = 1`.
+    fn pairing_product_is_one(pairs: &[(G1Affine, G2Affine)]) -> bool;
+
+    fn g1_subgroup_check(point: &G1Affine) -> bool;
+
+    fn g2_subgroup_check(point: &G2Affine) -> bool;
+
+    /// Same as [`parse_g`].
+    ///
+    /// # Errors
+    ///
+    /// The same as [`parse_g`].
+    fn g1_decode(hex: &str) -> Result<G1Affine, ParseError>;
+
+    /// Same as [`parse_g`].
+    ///
+    /// # Errors
+    ///
+    /// The same as [`parse_g`].
+    fn g2_decode(hex: &str) -> Result<G2Affine, ParseError>;
+
+    /// Same as [`format_g`].
+    fn g1_encode(point: &G1Affine) -> String;
+
+    /// Same as [`format_g`].
+    fn g2_encode(point: &G2Affine) -> String;
+}
+
+/// Subgroup check of the [`DefaultBackend`].
+#[must_use]
+pub fn g1_subgroup_check(point: &G1Affine) -> bool {
+    DefaultBackend::g1_subgroup_check(point)
+}
+
+/// Subgroup check of the [`DefaultBackend`].
+#[must_use]
+pub fn g2_subgroup_check(point: &G2Affine) -> bool {
+    DefaultBackend::g2_subgroup_check(point)
+}
+
+/// The arkworks implementation, with the optimizations of this crate.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Arkworks;
+
+/// Number of Miller loops per parallel task.
+const CHUNK_SIZE: usize = 16;
+
+impl CurveBackend for Arkworks {
+    const NAME: &'static str = "arkworks";
+
+    fn g1_mul(point: &G1Affine, scalar: &Fr) -> G1Projective {
+        crypto::g1_mul_glv(point, *scalar)
+    }
+
+    fn g2_mul(point: &G2Affine, scalar: &Fr) -> G2Projective {
+        point.mul(*scalar)
+    }
+
+    fn g1_msm(bases: &[G1Affine], scalars: &[ScalarInt]) -> G1Projective {
+        msm_with_window(bases, scalars, window_size(bases.len()))
+    }
+
+    fn g2_msm(bases: &[G2Affine], scalars: &[ScalarInt]) -> G2Projective {
+        msm_with_window(bases, scalars, window_size(bases.len()))
+    }
+
+    fn pairing_product_is_one(pairs: &[(G1Affine, G2Affine)]) -> bool {
+        let product = pairs
+            .par_chunks(CHUNK_SIZE)
+            .map(|chunk| {
+                let prepared = chunk
+                    .iter()
+                    .map(|(p, q)| ((*p).into(), (*q).into()))
+                    .collect::<Vec<_>>();
+                Bls12_381::miller_loop(&prepared)
+            })
+            .reduce(<Bls12_381 as PairingEngine>::Fqk::one, |a, b| a * b);
+        Bls12_381::final_exponentiation(&product).map_or(false, |result| result.is_one())
+    }
+
+    fn g1_subgroup_check(point: &G1Affine) -> bool {
+        crypto::g1_subgroup_check(point)
+    }
+
+    fn g2_subgroup_check(point: &G2Affine) -> bool {
+        crypto::g2_subgroup_check(point)
+    }
+
+    fn g1_decode(hex: &str) -> Result<G1Affine, ParseError> {
+        parse_g::<g1::Parameters>(hex)
+    }
+
+    fn g2_decode(hex: &str) -> Result<G2Affine, ParseError> {
+        parse_g::<g2::Parameters>(hex)
+    }
+
+    fn g1_encode(point: &G1Affine) -> String {
+        format_g(point)
+    }
+
+    fn g2_encode(point: &G2Affine) -> String {
+        format_g(point)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test::{arb_fr, arb_g1, arb_g2};
+    use ark_bls12_381::{Fq, Fq2};
+    use ark_ec::{msm::VariableBaseMSM, ProjectiveCurve};
+    use ark_ff::Zero;
+    use proptest::proptest;
+
+    /// Check `B` against the plain arkworks operations.
+    pub fn conformance<B: CurveBackend>() {
+        // Points with small x are on the curve, but not in the subgroups.
+        let p = (0_u64..)
+            .find_map(|x| G1Affine::get_point_from_x(Fq::from(x), false))
+            .unwrap();
+        let q = (0_u64..)
+            .find_map(|x| G2Affine::get_point_from_x(Fq2::from(x), false))
+            .unwrap();
+        assert!(!B::g1_subgroup_check(&p));
+        assert!(!B::g2_subgroup_check(&q));
+        assert_eq!(
+            B::g1_decode(&format_g(&p)),
+            Err(ParseError::InvalidSubgroup)
+        );
+        assert_eq!(
+            B::g2_decode(&format_g(&q)),
+            Err(ParseError::InvalidSubgroup)
+        );
+        for hex in [
+            "",
+            "0x",
+            "0x00",
+            &"f".repeat(98),
+            &format!("0x{}", "f".repeat(96)),
+        ] {
+            assert_eq!(B::g1_decode(hex), parse_g::<g1::Parameters>(hex));
+        }
+        assert_eq!(B::g1_encode(&G1Affine::zero()), format_g(&G1Affine::zero()));
+        assert_eq!(
+            B::g2_decode(&format_g(&G2Affine::zero())),
+            Ok(G2Affine::zero())
+        );
+        assert!(B::g1_msm(&[], &[]).is_zero());
+        assert!(B::pairing_product_is_one(&[]));
+
+        proptest!(|(p in arb_g1(), q in arb_g2(), s in arb_fr(), t in arb_fr())| {
+            assert_eq!(B::g1_mul(&p, &s), p.mul(s));
+            assert_eq!(B::g2_mul(&q, &s), q.mul(s));
+
+            let scalars = [s.into_repr(), t.into_repr()];
+            let g1 = [p, G1Affine::prime_subgroup_generator()];
+            let g2 = [q, G2Affine::zero()];
+            assert_eq!(B::g1_msm(&g1, &scalars), VariableBaseMSM::multi_scalar_mul(&g1, &scalars));
+            assert_eq!(B::g2_msm(&g2, &scalars), VariableBaseMSM::multi_scalar_mul(&g2, &scalars));
+
+            let ps = p.mul(s).into_affine();
+            let qs = q.mul(s).into_affine();
+            assert!(B::pairing_product_is_one(&[(ps, q), (-p, qs), (G1Affine::zero(), q)]));
+            assert_eq!(B::pairing_product_is_one(&[(ps, q), (-p, q)]), s.is_one());
+
+            assert!(B::g1_subgroup_check(&p));
+            assert!(B::g2_subgroup_check(&q));
+            assert_eq!(B::g1_encode(&p), format_g(&p));
+            assert_eq!(B::g2_encode(&q), format_g(&q));
+            assert_eq!(B::g1_decode(&format_g(&p)), Ok(p));
+            assert_eq!(B::g2_decode(&format_g(&q)), Ok(q));
+        });
+    }
+
+    #[test]
+    fn test_arkworks() {
+        conformance::<Arkworks>();
+    }
+
+    #[cfg(feature = "blst")]
+    #[test]
+    fn test_blst() {
+        conformance::<crate::blst::Blst>();
+    }
+}
@@ -0,0 +1,369 @@
+//! Batched G1 subgroup checks.
+//!
+//! Checking each of the 32768 G1 powers individually dominates contribution
+//! parsing. Instead, points are summed in random subsets and only the sums are
+//! checked. A point outside the subgroup has a non-zero cofactor component,
+//! which can only be cancelled by the other points in at most one of the two
+//! choices of including it or not. Each subset therefore catches it with
+//! probability at least one half, independent of the cofactor's factors.
+//!
+//! Random scalars larger than one would not help: the G1 cofactor has a factor
+//! three, so a random scalar cancels a 3-torsion component a third of the
+//! time anyway.
+//!
+//! [`parse_g1_batch`] decodes points in parallel with a single batched check
+//! and reports every invalid point, not just the first.
+//!
+//! The subsets are drawn from the thread RNG, or from a [`VerificationRng`]
+//! with the `_with_rng` variants, so checks can be reproduced.
+
+use crate::{g1_subgroup_check, parse_g_unchecked, ParseError, VerificationRng};
+use ark_bls12_381::{g1, G1Affine, G1Projective};
+use ark_ec::ProjectiveCurve;
+use ark_ff::Zero;
+use rand::Rng;
+use rayon::prelude::*;
+use std::fmt;
+use thiserror::Error;
+use tracing::instrument;
+
+/// Number of invalid points listed in the error message.
+const MAX_LISTED: usize = 8;
+
+/// Points that failed to parse, by increasing index.
+#[derive(Clone, PartialEq, Debug, Error)]
+pub struct InvalidPoints(pub Vec<(usize, ParseError)>);
+
+/// Number of random subsets, giving a soundness error of `2^-ROUNDS`.
+const ROUNDS: usize = 64;
+
+/// Number of points assigned to a parallel task.
+const CHUNK_SIZE: usize = 1024;
+
+/// Below this many points, bisection checks points individually.
+const MIN_BISECT: usize = 64;
+
+/// Check that all points are in the prime order subgroup.
+///
+/// Returns `false` if any point is not in the subgroup, except with
+/// probability `2^-64`.
+#[must_use]
+pub fn batch_subgroup_check(points: &[G1Affine]) -> bool {
+    batch_subgroup_check_with_rng(points, &mut VerificationRng::new())
+}
+
+/// [`batch_subgroup_check`] with the subsets drawn from `rng`.
+#[must_use]
+#[instrument(level = "info", skip_all, fields(n = points.len()))]
+pub fn batch_subgroup_check_with_rng(points: &[G1Affine], rng: &mut VerificationRng) -> bool {
+    if points.len() <= ROUNDS {
+        return points.par_iter().all(g1_subgroup_check);
+    }
+    let sums = G1Projective::batch_normalization_into_affine(&subset_sums(points, rng));
+    sums.par_iter().all(g1_subgroup_check)
+}
+
+/// Locate a point that is not in the prime order subgroup.
+///
+/// Bisects using [`batch_subgroup_check`] and returns the lowest index found.
+#[must_use]
+pub fn find_non_subgroup_point(points: &[G1Affine]) -> Option<usize> {
+    find_non_subgroup_point_with_rng(points, &mut VerificationRng::new())
+}
+
+/// [`find_non_subgroup_point`] with the subsets drawn from `rng`.
+#[must_use]
+#[instrument(level = "info", skip_all, fields(n = points.len()))]
+pub fn find_non_subgroup_point_with_rng(
+    points: &[G1Affine],
+    rng: &mut VerificationRng,
+) -> Option<usize> {
+    if points.len() <= MIN_BISECT {
+        return points.iter().position(|point| !g1_subgroup_check(point));
+    }
+    if batch_subgroup_check_with_rng(points, rng) {
+        return None;
+    }
+    let (left, right) = points.split_at(points.len() / 2);
+    find_non_subgroup_point_with_rng(left, rng)
+        .or_else(|| find_non_subgroup_point_with_rng(right, rng).map(|i| i + left.len()))
+}
+
+/// Locate all points that are not in the prime order subgroup.
+///
+/// Like [`find_non_subgroup_point`], but bisects into both halves. Returns
+/// the indices in increasing order.
+#[must_use]
+pub fn find_non_subgroup_points(points: &[G1Affine]) -> Vec<usize> {
+    find_non_subgroup_points_with_rng(points, &mut VerificationRng::new())
+}
+
+/// [`find_non_subgroup_points`] with the subsets drawn from `rng`.
+#[must_use]
+#[instrument(level = "info", skip_all, fields(n = points.len()))]
+pub fn find_non_subgroup_points_with_rng(
+    points: &[G1Affine],
+    rng: &mut VerificationRng,
+) -> Vec<usize> {
+    if points.len() <= MIN_BISECT {
+        return (0..points.len())
+            .filter(|&i| !g1_subgroup_check(&points[i]))
+            .collect();
+    }
+    if batch_subgroup_check_with_rng(points, rng) {
+        return Vec::new();
+    }
+    let (left, right) = points.split_at(points.len() / 2);
+    // The halves are searched in parallel, each with its own RNG.
+    let mut right_rng = VerificationRng::from_seed(rng.gen());
+    let (mut found, right_found) = rayon::join(
+        || find_non_subgroup_points_with_rng(left, rng),
+        || find_non_subgroup_points_with_rng(right, &mut right_rng),
+    );
+    found.extend(right_found.into_iter().map(|i| i + left.len()));
+    found
+}
+
+/// Parse compressed G1 points in parallel, with a single batched subgroup
+/// check.
+///
+/// # Errors
+///
+/// Returns all points that are invalid, both encodings that do not decode and
+/// points outside the subgroup.
+pub fn parse_g1_batch<S: AsRef<str> + Sync>(hexes: &[S]) -> Result<Vec<G1Affine>, InvalidPoints> {
+    parse_g1_batch_with_rng(hexes, &mut VerificationRng::new())
+}
+
+/// [`parse_g1_batch`] with the subsets drawn from `rng`.
+///
+/// # Errors
+///
+/// Same as [`parse_g1_batch`].
+#[instrument(level = "info", skip_all, fields(n = hexes.len()))]
+pub fn parse_g1_batch_with_rng<S: AsRef<str> + Sync>(
+    hexes: &[S],
+    rng: &mut VerificationRng,
+) -> Result<Vec<G1Affine>, InvalidPoints> {
+    let parsed = hexes
+        .par_iter()
+        .map(|hex| parse_g_unchecked::<g1::Parameters>(hex.as_ref()))
+        .collect::<Vec<_>>();
+    let mut invalid = parsed
+        .iter()
+        .enumerate()
+        .filter_map(|(i, point)| point.as_ref().err().map(|e| (i, *e)))
+        .collect::<Vec<_>>();
+    // Only decoded points are checked, keeping track of their indices.
+    let (indices, points): (Vec<_>, Vec<_>) = parsed
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, point)| point.ok().map(|point| (i, point)))
+        .unzip();
+    if invalid.is_empty() && batch_subgroup_check_with_rng(&points, rng) {
+        return Ok(points);
+    }
+    invalid.extend(
+        find_non_subgroup_points_with_rng(&points, rng)
+            .into_iter()
+            .map(|i| (indices[i], ParseError::InvalidSubgroup)),
+    );
+    if invalid.is_empty() {
+        return Ok(points);
+    }
+    invalid.sort_unstable_by_key(|(i, _)| *i);
+    Err(InvalidPoints(invalid))
+}
+
+impl InvalidPoints {
+    /// The invalid point with the lowest index.
+    #[must_use]
+    pub fn first(&self) -> (usize, ParseError) {
+        self.0[0]
+    }
+}
+
+impl fmt::Display for InvalidPoints {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid points")?;
+        for (n, (i, error)) in self.0.iter().take(MAX_LISTED).enumerate() {
+            let separator = if n == 0 { ":" } else { "," };
+            write!(f, "{separator} {i} ({error})")?;
+        }
+        if self.0.len() > MAX_LISTED {
+            write!(f, " and {} more", self.0.len() - MAX_LISTED)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sum of a random subset of `points` for each round.
+///
+/// Bit `j` of a point's random mask decides whether it is in subset `j`.
+/// Chunks are summed in parallel, each with an RNG seeded from `rng`.
+fn subset_sums(points: &[G1Affine], rng: &mut VerificationRng) -> Vec<G1Projective> {
+    let seeds = points
+        .chunks(CHUNK_SIZE)
+        .map(|_| rng.gen())
+        .collect::<Vec<_>>();
+    points
+        .par_chunks(CHUNK_SIZE)
+        .zip(seeds)
+        .map(|(chunk, seed)| {
+            let mut rng = VerificationRng::from_seed(seed);
+            let mut sums = vec![G1Projective::zero(); ROUNDS];
+            for point in chunk {
+                let mut mask: u64 = rng.gen();
+                while mask != 0 {
+                    sums[mask.trailing_zeros() as usize].add_assign_mixed(point);
+                    mask &= mask - 1;
+                }
+            }
+            sums
+        })
+        .reduce(
+            || vec![G1Projective::zero(); ROUNDS],
+            |mut a, b| {
+                for (a, b) in a.iter_mut().zip(b) {
+                    *a += b;
+                }
+                a
+            },
+        )
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::format_g;
+    use ark_bls12_381::{Fq, Fr};
+    use ark_ec::AffineCurve;
+    use ark_ff::{FpParameters, PrimeField, UniformRand};
+
+    /// Cofactor of G1 divided by three.
+    const COFACTOR_THIRD: [u64; 2] = [0x2eaa_e38e_5555_8e39, 0x1324_2eaa_c71c_a072];
+
+    fn subgroup_points(n: usize) -> Vec<G1Affine> {
+        let mut rng = rand::thread_rng();
+        let points = (0..n)
+            .map(|_| G1Affine::prime_subgroup_generator().mul(Fr::rand(&mut rng)))
+            .collect::<Vec<_>>();
+        G1Projective::batch_normalization_into_affine(&points)
+    }
+
+    /// A point on the curve with a component of order three.
+    fn three_torsion_point() -> G1Affine {
+        let mut rng = rand::thread_rng();
+        loop {
+            let point = match G1Affine::get_point_from_x(Fq::rand(&mut rng), false) {
+                Some(point) => point,
+                None => continue,
+            };
+            let torsion = point
+                .into_projective()
+                .mul(<Fr as PrimeField>::Params::MODULUS)
+                .mul(COFACTOR_THIRD);
+            if !torsion.is_zero() {
+                let bad = G1Affine::prime_subgroup_generator().into_projective() + torsion;
+                return bad.into_affine();
+            }
+        }
+    }
+
+    #[test]
+    fn test_batch_subgroup_check() {
+        let mut points = subgroup_points(1000);
+        assert!(batch_subgroup_check(&points));
+        assert_eq!(find_non_subgroup_point(&points), None);
+
+        points[737] = three_torsion_point();
+        assert!(!g1_subgroup_check(&points[737]));
+        assert!(!batch_subgroup_check(&points));
+        assert_eq!(find_non_subgroup_point(&points), Some(737));
+
+        points[12] = three_torsion_point();
+        assert_eq!(find_non_subgroup_point(&points), Some(12));
+        assert!(!batch_subgroup_check(&points[..20]));
+        assert!(batch_subgroup_check(&points[13..700]));
+        assert_eq!(find_non_subgroup_points(&points), vec![12, 737]);
+    }
+
+    #[test]
+    fn test_with_rng() {
+        let points = subgroup_points(3 * CHUNK_SIZE);
+        let sums = |seed| subset_sums(&points, &mut VerificationRng::seed_from_u64(seed));
+        assert_eq!(sums(1), sums(1));
+        assert_ne!(sums(1), sums(2));
+
+        let mut points = points;
+        points[2500] = three_torsion_point();
+        let mut rng = VerificationRng::seed_from_u64(1);
+        assert!(!batch_subgroup_check_with_rng(&points, &mut rng));
+        assert_eq!(
+            find_non_subgroup_point_with_rng(&points, &mut rng),
+            Some(2500)
+        );
+        assert_eq!(find_non_subgroup_points_with_rng(&points, &mut rng), vec![
+            2500
+        ]);
+    }
+
+    #[test]
+    fn test_parse_g1_batch() {
+        let points = subgroup_points(200);
+        let mut hexes = points.iter().map(format_g).collect::<Vec<_>>();
+        assert_eq!(parse_g1_batch(&hexes), Ok(points));
+
+        hexes[150] = format_g(&three_torsion_point());
+        hexes[3] = "0x00".to_string();
+        hexes[99] = format_g(&three_torsion_point());
+        hexes[42].replace_range(2..4, "00");
+        let error = parse_g1_batch(&hexes).unwrap_err();
+        assert_eq!(
+            error,
+            InvalidPoints(vec![
+                (3, ParseError::InvalidLength(98, 4)),
+                (42, ParseError::NotCompressed),
+                (99, ParseError::InvalidSubgroup),
+                (150, ParseError::InvalidSubgroup),
+            ])
+        );
+        assert_eq!(error.first(), (3, ParseError::InvalidLength(98, 4)));
+        assert!(error
+            .to_string()
+            .starts_with("Invalid points: 3 (Invalid length of hex string"));
+    }
+}
+
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench {
+    use super::*;
+    use crate::bench::rand_g1;
+    use criterion::{black_box, BenchmarkId, Criterion};
+
+    pub fn group(criterion: &mut Criterion) {
+        bench_batch_subgroup_check(criterion);
+    }
+
+    fn bench_batch_subgroup_check(criterion: &mut Criterion) {
+        for (n1, _) in crate::SIZES {
+            let points = (0..n1).map(|_| rand_g1()).collect::<Vec<_>>();
+            criterion.bench_with_input(
+                BenchmarkId::new("batch_subgroup_check/individual", n1),
+                &n1,
+                |bencher, _| {
+                    bencher
+                        .iter(|| black_box(black_box(&points).par_iter().all(g1_subgroup_check)));
+                },
+            );
+            criterion.bench_with_input(
+                BenchmarkId::new("batch_subgroup_check/batched", n1),
+                &n1,
+                |bencher, _| {
+                    bencher.iter(|| black_box(batch_subgroup_check(black_box(&points))));
+                },
+            );
+        }
+    }
+}
@@ -0,0 +1,565 @@
+//! Transcript of all sub-ceremonies.
+//!
+//! A [`BatchTranscript`] holds one [`Transcript`] per sub-ceremony, in the
+//! order of [`crate::SIZES`]. Contributions to it cover any non-empty subset
+//! of the sub-ceremonies and are verified and applied as a whole. Its JSON
+//! encoding follows the `transcript.json` format of the specification. The
+//! witness section makes the full chain of pubkeys available to external
+//! verifiers, see [`WitnessJson`].
+
+use crate::{
+    batch_check::parse_g1_batch_with_rng,
+    error_code::{serialize_error, ErrorCode},
+    format_g, parse_g, Contribution, ParseError, PowersOfTau, Transcript, VerificationRng,
+    VerifyError,
+};
+use ark_bls12_381::{g1, g2};
+use ark_serialize::CanonicalSerialize;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::instrument;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BatchTranscript {
+    pub transcripts: Vec<Transcript>,
+}
+
+/// JSON encoding of a [`BatchTranscript`].
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchTranscriptJson {
+    pub transcripts: Vec<TranscriptJson>,
+}
+
+/// JSON encoding of a [`Transcript`], with points in zcash format.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscriptJson {
+    pub num_g1_powers: usize,
+    pub num_g2_powers: usize,
+    pub powers_of_tau: PowersOfTau,
+    pub witness:       WitnessJson,
+}
+
+/// The running products, pubkeys and identity signatures of all
+/// contributions to a sub-ceremony, starting with the generators.
+///
+/// Entry `i` of `runningProducts` is entry `i - 1` updated with the secret of
+/// `potPubkeys[i]`. Contributions without an identity signature have an empty
+/// string in `blsSignatures`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WitnessJson {
+    pub running_products: Vec<String>,
+    pub pot_pubkeys:      Vec<String>,
+    pub bls_signatures:   Vec<String>,
+}
+
+/// Error parsing a [`BatchTranscriptJson`].
+#[derive(Clone, Copy, PartialEq, Debug, Error)]
+pub enum TranscriptsError {
+    #[error("Error in transcript {0}: {1}")]
+    InvalidTranscript(usize, #[source] TranscriptError),
+}
+
+/// Error parsing a [`TranscriptJson`].
+#[derive(Clone, Copy, PartialEq, Debug, Error)]
+pub enum TranscriptError {
+    #[error("Inconsistent number of G1 powers: numG1Powers = {0}, len = {1}")]
+    InconsistentNumG1Powers(usize, usize),
+    #[error("Inconsistent number of G2 powers: numG2Powers = {0}, len = {1}")]
+    InconsistentNumG2Powers(usize, usize),
+    #[error("Inconsistent witness: {0} running products, {1} pubkeys and {2} signatures")]
+    InconsistentWitness(usize, usize, usize),
+    #[error("Error parsing G1 power {0}: {1}")]
+    InvalidG1Power(usize, #[source] ParseError),
+    #[error("Error parsing G2 power {0}: {1}")]
+    InvalidG2Power(usize, #[source] ParseError),
+    #[error("Error parsing running product {0}: {1}")]
+    InvalidRunningProduct(usize, #[source] ParseError),
+    #[error("Error parsing potPubkey {0}: {1}")]
+    InvalidPubKey(usize, #[source] ParseError),
+    #[error("Error parsing blsSignature {0}: {1}")]
+    InvalidBlsSignature(usize, #[source] ParseError),
+}
+
+impl ErrorCode for TranscriptsError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidTranscript(..) => "invalid_transcript",
+        }
+    }
+}
+
+impl Serialize for TranscriptsError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Self::InvalidTranscript(index, error) = self;
+        serialize_error(serializer, self, &[("index", *index)], Some(error))
+    }
+}
+
+impl ErrorCode for TranscriptError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::InconsistentNumG1Powers(..) => "inconsistent_num_g1_powers",
+            Self::InconsistentNumG2Powers(..) => "inconsistent_num_g2_powers",
+            Self::InconsistentWitness(..) => "inconsistent_witness",
+            Self::InvalidG1Power(..) => "invalid_g1_power",
+            Self::InvalidG2Power(..) => "invalid_g2_power",
+            Self::InvalidRunningProduct(..) => "invalid_running_product",
+            Self::InvalidPubKey(..) => "invalid_pubkey",
+            Self::InvalidBlsSignature(..) => "invalid_bls_signature",
+        }
+    }
+}
+
+impl Serialize for TranscriptError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (fields, cause): (&[_], _) = match self {
+            Self::InconsistentNumG1Powers(expected, got)
+            | Self::InconsistentNumG2Powers(expected, got) => {
+                (&[("expected", *expected), ("got", *got)], None)
+            }
+            Self::InconsistentWitness(products, pubkeys, signatures) => (
+                &[
+                    ("products", *products),
+                    ("pubkeys", *pubkeys),
+                    ("signatures", *signatures),
+                ],
+                None,
+            ),
+            Self::InvalidG1Power(index, error)
+            | Self::InvalidG2Power(index, error)
+            | Self::InvalidRunningProduct(index, error)
+            | Self::InvalidPubKey(index, error)
+            | Self::InvalidBlsSignature(index, error) => (&[("index", *index)], Some(error)),
+        };
+        serialize_error(serializer, self, fields, cause)
+    }
+}
+
+/// Error verifying a contribution to a [`BatchTranscript`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum BatchVerifyError {
+    #[error("Unexpected number of contributions: expected {0}, got {1}")]
+    InvalidContributionCount(usize, usize),
+    #[error("Contribution covers no sub-ceremony")]
+    NoSubContributions,
+    #[error("Contribution {0} failed verification: {1}")]
+    VerificationFailed(usize, #[source] VerifyError),
+}
+
+impl ErrorCode for BatchVerifyError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidContributionCount(..) => "invalid_contribution_count",
+            Self::NoSubContributions => "no_sub_contributions",
+            Self::VerificationFailed(..) => "verification_failed",
+        }
+    }
+}
+
+impl Serialize for BatchVerifyError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (fields, cause): (&[_], _) = match self {
+            Self::InvalidContributionCount(expected, got) => {
+                (&[("expected", *expected), ("got", *got)], None)
+            }
+            Self::NoSubContributions => (&[], None),
+            Self::VerificationFailed(index, error) => (&[("index", *index)], Some(error)),
+        };
+        serialize_error(serializer, self, fields, cause)
+    }
+}
+
+impl BatchTranscript {
+    /// Transcripts of the given sizes containing generators only.
+    #[must_use]
+    pub fn new(sizes: &[(usize, usize)]) -> Self {
+        Self {
+            transcripts: sizes
+                .iter()
+                .map(|(num_g1, num_g2)| Transcript::new(*num_g1, *num_g2))
+                .collect(),
+        }
+    }
+
+    /// Number of G1 and G2 powers of each sub-transcript.
+    #[must_use]
+    pub fn sizes(&self) -> Vec<(usize, usize)> {
+        self.transcripts
+            .iter()
+            .map(|transcript| (transcript.g1_powers.len(), transcript.g2_powers.len()))
+            .collect()
+    }
+
+    /// The sub-ceremonies covered by `contributions`.
+    ///
+    /// `contributions` must have an entry for every sub-ceremony, `None` for
+    /// those not contributed to, and cover at least one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of entries is wrong or all are `None`.
+    pub fn sub_ceremonies(
+        &self,
+        contributions: &[Option<Contribution>],
+    ) -> Result<Vec<usize>, BatchVerifyError> {
+        if contributions.len() != self.transcripts.len() {
+            return Err(BatchVerifyError::InvalidContributionCount(
+                self.transcripts.len(),
+                contributions.len(),
+            ));
+        }
+        let sub_ceremonies = contributions
+            .iter()
+            .enumerate()
+            .filter_map(|(index, contribution)| contribution.as_ref().map(|_| index))
+            .collect::<Vec<_>>();
+        if sub_ceremonies.is_empty() {
+            return Err(BatchVerifyError::NoSubContributions);
+        }
+        Ok(sub_ceremonies)
+    }
+
+    /// Verify `contributions` and apply them if all are valid.
+    ///
+    /// Sub-contributions are verified concurrently.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if [`Self::sub_ceremonies`] does, or if a
+    /// sub-contribution is invalid. If several are, the error of the lowest
+    /// sub-ceremony is returned.
+    #[instrument(level = "info", skip_all)]
+    pub fn verify_add(
+        &mut self,
+        contributions: Vec<Option<Contribution>>,
+    ) -> Result<(), BatchVerifyError> {
+        self.sub_ceremonies(&contributions)?;
+        let results = contributions
+            .par_iter()
+            .zip(&self.transcripts)
+            .enumerate()
+            .filter_map(|(index, (contribution, transcript))| {
+                contribution.as_ref().map(|contribution| {
+                    contribution
+                        .verify(transcript)
+                        .map_err(|e| BatchVerifyError::VerificationFailed(index, e))
+                })
+            })
+            .collect::<Vec<_>>();
+        results.into_iter().collect::<Result<(), _>>()?;
+        self.add(contributions);
+        Ok(())
+    }
+
+    /// Append (already verified) contributions, `None` entries leave their
+    /// sub-ceremony unchanged.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not exactly one entry per sub-ceremony.
+    pub fn add(&mut self, contributions: Vec<Option<Contribution>>) {
+        assert_eq!(self.transcripts.len(), contributions.len());
+        for (transcript, contribution) in self.transcripts.iter_mut().zip(contributions) {
+            if let Some(contribution) = contribution {
+                transcript.add(contribution);
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn to_json(&self) -> BatchTranscriptJson {
+        BatchTranscriptJson {
+            transcripts: self.transcripts.iter().map(TranscriptJson::from).collect(),
+        }
+    }
+
+    /// SHA-256 over the uncompressed encoding of all points, by sub-ceremony
+    /// the G1 powers, G2 powers, running products and pubkeys. Identity
+    /// signatures are not covered.
+    #[must_use]
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        let mut buffer = Vec::new();
+        for transcript in &self.transcripts {
+            hash_points(&mut hasher, &mut buffer, &transcript.g1_powers);
+            hash_points(&mut hasher, &mut buffer, &transcript.g2_powers);
+            hash_points(&mut hasher, &mut buffer, &transcript.products);
+            hash_points(&mut hasher, &mut buffer, &transcript.pubkeys);
+        }
+        hasher.finalize().into()
+    }
+}
+
+impl From<Vec<Transcript>> for BatchTranscript {
+    fn from(transcripts: Vec<Transcript>) -> Self {
+        Self { transcripts }
+    }
+}
+
+impl BatchTranscriptJson {
+    /// Parse all sub-transcripts, checking that points are in the prime order
+    /// subgroup.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the first sub-transcript that is invalid.
+    pub fn parse(&self) -> Result<BatchTranscript, TranscriptsError> {
+        self.parse_with_rng(&mut VerificationRng::new())
+    }
+
+    /// [`Self::parse`] with the subgroup checks seeded from `rng`.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`Self::parse`].
+    pub fn parse_with_rng(
+        &self,
+        rng: &mut VerificationRng,
+    ) -> Result<BatchTranscript, TranscriptsError> {
+        self.transcripts
+            .iter()
+            .enumerate()
+            .map(|(i, transcript)| {
+                transcript
+                    .parse_with_rng(rng)
+                    .map_err(|e| TranscriptsError::InvalidTranscript(i, e))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(BatchTranscript::from)
+    }
+}
+
+impl TranscriptJson {
+    /// Parse the powers and witness, checking that points are in the prime
+    /// order subgroup.
+    ///
+    /// This does not verify the transcript, see [`Transcript::verify`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the lengths are inconsistent or a point is invalid.
+    pub fn parse(&self) -> Result<Transcript, TranscriptError> {
+        self.parse_with_rng(&mut VerificationRng::new())
+    }
+
+    /// [`Self::parse`] with the subgroup checks seeded from `rng`.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`Self::parse`].
+    pub fn parse_with_rng(&self, rng: &mut VerificationRng) -> Result<Transcript, TranscriptError> {
+        if self.powers_of_tau.g1_powers.len() != self.num_g1_powers {
+            return Err(TranscriptError::InconsistentNumG1Powers(
+                self.num_g1_powers,
+                self.powers_of_tau.g1_powers.len(),
+            ));
+        }
+        if self.powers_of_tau.g2_powers.len() != self.num_g2_powers {
+            return Err(TranscriptError::InconsistentNumG2Powers(
+                self.num_g2_powers,
+                self.powers_of_tau.g2_powers.len(),
+            ));
+        }
+        let witness = &self.witness;
+        if witness.running_products.len() != witness.pot_pubkeys.len()
+            || witness.bls_signatures.len() != witness.pot_pubkeys.len()
+        {
+            return Err(TranscriptError::InconsistentWitness(
+                witness.running_products.len(),
+                witness.pot_pubkeys.len(),
+                witness.bls_signatures.len(),
+            ));
+        }
+        let g1_powers =
+            parse_g1_batch_with_rng(&self.powers_of_tau.g1_powers, rng).map_err(|e| {
+                let (i, e) = e.first();
+                TranscriptError::InvalidG1Power(i, e)
+            })?;
+        let products = parse_g1_batch_with_rng(&witness.running_products, rng).map_err(|e| {
+            let (i, e) = e.first();
+            TranscriptError::InvalidRunningProduct(i, e)
+        })?;
+        let g2_powers = self
+            .powers_of_tau
+            .g2_powers
+            .par_iter()
+            .enumerate()
+            .map(|(i, hex)| {
+                parse_g::<g2::Parameters>(hex).map_err(|e| TranscriptError::InvalidG2Power(i, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let pubkeys = witness
+            .pot_pubkeys
+            .par_iter()
+            .enumerate()
+            .map(|(i, hex)| {
+                parse_g::<g2::Parameters>(hex).map_err(|e| TranscriptError::InvalidPubKey(i, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let signatures = witness
+            .bls_signatures
+            .par_iter()
+            .enumerate()
+            .map(|(i, hex)| {
+                if hex.is_empty() {
+                    return Ok(None);
+                }
+                parse_g::<g1::Parameters>(hex)
+                    .map(Some)
+                    .map_err(|e| TranscriptError::InvalidBlsSignature(i, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Transcript {
+            g1_powers,
+            g2_powers,
+            products,
+            pubkeys,
+            signatures,
+        })
+    }
+}
+
+impl From<&Transcript> for TranscriptJson {
+    fn from(transcript: &Transcript) -> Self {
+        Self {
+            num_g1_powers: transcript.g1_powers.len(),
+            num_g2_powers: transcript.g2_powers.len(),
+            powers_of_tau: PowersOfTau {
+                g1_powers: transcript.g1_powers.par_iter().map(format_g).collect(),
+                g2_powers: transcript.g2_powers.par_iter().map(format_g).collect(),
+            },
+            witness:       WitnessJson {
+                running_products: transcript.products.par_iter().map(format_g).collect(),
+                pot_pubkeys:      transcript.pubkeys.par_iter().map(format_g).collect(),
+                bls_signatures:   transcript
+                    .signatures
+                    .iter()
+                    .map(|signature| signature.as_ref().map(format_g).unwrap_or_default())
+                    .collect(),
+            },
+        }
+    }
+}
+
+fn hash_points<P: CanonicalSerialize>(hasher: &mut Sha256, buffer: &mut Vec<u8>, points: &[P]) {
+    for point in points {
+        buffer.clear();
+        point
+            .serialize_uncompressed(&mut *buffer)
+            .expect("Writing to a Vec can not fail");
+        hasher.update(&buffer);
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{SecretRng, SecretScalar};
+
+    fn contribute(transcript: &Transcript) -> Contribution {
+        let mut contribution = transcript.contribution();
+        contribution.add_tau(&SecretScalar::random(&mut SecretRng::new()));
+        contribution
+    }
+
+    #[test]
+    fn test_verify_add() {
+        let mut batch = BatchTranscript::new(&[(4, 2), (8, 3)]);
+        let hash = batch.hash();
+        assert_eq!(
+            batch.verify_add(vec![None]),
+            Err(BatchVerifyError::InvalidContributionCount(2, 1))
+        );
+        assert_eq!(
+            batch.verify_add(vec![None, None]),
+            Err(BatchVerifyError::NoSubContributions)
+        );
+
+        // An invalid sub-contribution rejects the whole contribution.
+        let mut invalid = contribute(&batch.transcripts[0]);
+        invalid.g1_powers.swap(2, 3);
+        let valid = contribute(&batch.transcripts[1]);
+        assert!(matches!(
+            batch.verify_add(vec![Some(invalid), Some(valid.clone())]),
+            Err(BatchVerifyError::VerificationFailed(0, _))
+        ));
+        assert_eq!(batch.hash(), hash);
+
+        batch.verify_add(vec![None, Some(valid)]).unwrap();
+        assert_ne!(batch.hash(), hash);
+        assert_eq!(batch.transcripts[0].pubkeys.len(), 1);
+        assert_eq!(batch.transcripts[1].pubkeys.len(), 2);
+        batch.transcripts[1].verify().unwrap();
+    }
+
+    #[test]
+    fn test_to_json() {
+        let mut batch = BatchTranscript::new(&[(4, 2)]);
+        let contribution = contribute(&batch.transcripts[0]);
+        batch.add(vec![Some(contribution.clone())]);
+        let json = serde_json::to_value(batch.to_json()).unwrap();
+        let transcript = &json["transcripts"][0];
+        assert_eq!(transcript["numG1Powers"], 4);
+        assert_eq!(
+            transcript["powersOfTau"]["G1Powers"][1],
+            format_g(&contribution.g1_powers[1])
+        );
+        assert_eq!(
+            transcript["witness"]["runningProducts"]
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(
+            transcript["witness"]["potPubkeys"][1],
+            format_g(&contribution.pubkey)
+        );
+        assert_eq!(transcript["witness"]["blsSignatures"][0], "");
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let mut batch = BatchTranscript::new(&[(4, 2), (8, 2)]);
+        let tau = SecretScalar::random(&mut SecretRng::new());
+        let mut contribution = batch.transcripts[0].contribution();
+        contribution.add_tau(&tau);
+        contribution.sign_identity(&tau, "eth|0x0000000000000000000000000000000000000000");
+        batch.add(vec![Some(contribution), None]);
+        batch.add(vec![None, Some(contribute(&batch.transcripts[1]))]);
+
+        let json = batch.to_json();
+        assert!(!json.transcripts[0].witness.bls_signatures[1].is_empty());
+        let parsed =
+            serde_json::from_str::<BatchTranscriptJson>(&serde_json::to_string(&json).unwrap())
+                .unwrap()
+                .parse()
+                .unwrap();
+        assert_eq!(parsed, batch);
+        parsed.transcripts[0].verify().unwrap();
+
+        let mut invalid = json.clone();
+        invalid.transcripts[1].witness.bls_signatures.pop();
+        assert_eq!(
+            invalid.parse(),
+            Err(TranscriptsError::InvalidTranscript(
+                1,
+                TranscriptError::InconsistentWitness(2, 2, 1)
+            ))
+        );
+        let mut invalid = json;
+        invalid.transcripts[0].witness.pot_pubkeys[1] = "0x00".to_string();
+        assert!(matches!(
+            invalid.parse(),
+            Err(TranscriptsError::InvalidTranscript(
+                0,
+                TranscriptError::InvalidPubKey(1, _)
+            ))
+        ));
+    }
+}
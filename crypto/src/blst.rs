@@ -0,0 +1,277 @@
+//! Verification with the blst library.
+//!
+//! With the `blst` feature, [`Blst`] implements the [`CurveBackend`] using
+//! [blst](https://github.com/supranational/blst), which is considerably
+//! faster than arkworks on the verification path, and becomes the
+//! [`crate::DefaultBackend`]. The rest of the crate keeps working with
+//! arkworks types, the functions here convert at the boundary.
+//!
+//! Both libraries keep coordinates in Montgomery form, but conversion goes
+//! through the canonical integer to not depend on that. This costs a field
+//! multiplication per coordinate, negligible next to a subgroup check or a
+//! Miller loop.
+
+use crate::{
+    backend::{CurveBackend, ScalarInt},
+    parse_g,
+    zcash_format::parse_hex,
+    ParseError,
+};
+use ::blst::{
+    blst_fp, blst_fp12, blst_fp2, blst_fp_from_uint64, blst_p1, blst_p1_affine,
+    blst_p1_affine_compress, blst_p1_affine_in_g1, blst_p1_from_affine, blst_p1_mult,
+    blst_p1_to_affine, blst_p1_uncompress, blst_p2, blst_p2_affine, blst_p2_affine_compress,
+    blst_p2_affine_in_g2, blst_p2_from_affine, blst_p2_mult, blst_p2_to_affine, blst_p2_uncompress,
+    blst_uint64_from_fp, MultiPoint, BLST_ERROR,
+};
+use ark_bls12_381::{g1, g2, Fq, Fq2, Fr, G1Affine, G1Projective, G2Affine, G2Projective};
+use ark_ec::AffineCurve;
+use ark_ff::{biginteger::BigInteger384, BigInteger, FpParameters, PrimeField, Zero};
+use std::ptr::{addr_of, addr_of_mut};
+
+#[must_use]
+pub fn fq_to_blst(value: &Fq) -> blst_fp {
+    let mut result = blst_fp::default();
+    // SAFETY: Both point to six limbs.
+    unsafe { blst_fp_from_uint64(addr_of_mut!(result), value.into_repr().0.as_ptr()) };
+    result
+}
+
+/// # Panics
+///
+/// Panics if `value` is not reduced, which blst never produces.
+#[must_use]
+pub fn fq_from_blst(value: &blst_fp) -> Fq {
+    let mut limbs = [0_u64; 6];
+    // SAFETY: Both point to six limbs.
+    unsafe { blst_uint64_from_fp(limbs.as_mut_ptr(), value) };
+    Fq::from_repr(BigInteger384(limbs)).expect("blst field elements are reduced")
+}
+
+#[must_use]
+pub fn fq2_to_blst(value: &Fq2) -> blst_fp2 {
+    blst_fp2 {
+        fp: [fq_to_blst(&value.c0), fq_to_blst(&value.c1)],
+    }
+}
+
+#[must_use]
+pub fn fq2_from_blst(value: &blst_fp2) -> Fq2 {
+    Fq2::new(fq_from_blst(&value.fp[0]), fq_from_blst(&value.fp[1]))
+}
+
+/// The point at infinity is all zeros in blst.
+#[must_use]
+pub fn g1_to_blst(point: &G1Affine) -> blst_p1_affine {
+    if point.infinity {
+        return blst_p1_affine::default();
+    }
+    blst_p1_affine {
+        x: fq_to_blst(&point.x),
+        y: fq_to_blst(&point.y),
+    }
+}
+
+pub fn g1_from_blst(point: &blst_p1_affine) -> G1Affine {
+    if *point == blst_p1_affine::default() {
+        return G1Affine::zero();
+    }
+    G1Affine::new(fq_from_blst(&point.x), fq_from_blst(&point.y), false)
+}
+
+/// The point at infinity is all zeros in blst.
+#[must_use]
+pub fn g2_to_blst(point: &G2Affine) -> blst_p2_affine {
+    if point.infinity {
+        return blst_p2_affine::default();
+    }
+    blst_p2_affine {
+        x: fq2_to_blst(&point.x),
+        y: fq2_to_blst(&point.y),
+    }
+}
+
+pub fn g2_from_blst(point: &blst_p2_affine) -> G2Affine {
+    if *point == blst_p2_affine::default() {
+        return G2Affine::zero();
+    }
+    G2Affine::new(fq2_from_blst(&point.x), fq2_from_blst(&point.y), false)
+}
+
+fn g1_from_blst_projective(point: &blst_p1) -> G1Affine {
+    let mut affine = blst_p1_affine::default();
+    // SAFETY: Valid references.
+    unsafe { blst_p1_to_affine(addr_of_mut!(affine), point) };
+    g1_from_blst(&affine)
+}
+
+fn g2_from_blst_projective(point: &blst_p2) -> G2Affine {
+    let mut affine = blst_p2_affine::default();
+    // SAFETY: Valid references.
+    unsafe { blst_p2_to_affine(addr_of_mut!(affine), point) };
+    g2_from_blst(&affine)
+}
+
+/// The blst implementation.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Blst;
+
+/// Bits in a scalar.
+const NBITS: usize = <Fr as PrimeField>::Params::MODULUS_BITS as usize;
+
+impl CurveBackend for Blst {
+    const NAME: &'static str = "blst";
+
+    fn g1_mul(point: &G1Affine, scalar: &Fr) -> G1Projective {
+        let mut projective = blst_p1::default();
+        let mut result = blst_p1::default();
+        let scalar = scalar.into_repr().to_bytes_le();
+        // SAFETY: Valid references, the scalar has `NBITS` bits.
+        unsafe {
+            blst_p1_from_affine(addr_of_mut!(projective), &g1_to_blst(point));
+            blst_p1_mult(
+                addr_of_mut!(result),
+                addr_of!(projective),
+                scalar.as_ptr(),
+                NBITS,
+            );
+        }
+        g1_from_blst_projective(&result).into_projective()
+    }
+
+    fn g2_mul(point: &G2Affine, scalar: &Fr) -> G2Projective {
+        let mut projective = blst_p2::default();
+        let mut result = blst_p2::default();
+        let scalar = scalar.into_repr().to_bytes_le();
+        // SAFETY: Valid references, the scalar has `NBITS` bits.
+        unsafe {
+            blst_p2_from_affine(addr_of_mut!(projective), &g2_to_blst(point));
+            blst_p2_mult(
+                addr_of_mut!(result),
+                addr_of!(projective),
+                scalar.as_ptr(),
+                NBITS,
+            );
+        }
+        g2_from_blst_projective(&result).into_projective()
+    }
+
+    fn g1_msm(bases: &[G1Affine], scalars: &[ScalarInt]) -> G1Projective {
+        let n = bases.len().min(scalars.len());
+        if n == 0 {
+            return G1Projective::zero();
+        }
+        let bases = bases[..n].iter().map(g1_to_blst).collect::<Vec<_>>();
+        let sum = bases.mult(&scalar_bytes(&scalars[..n]), NBITS);
+        g1_from_blst_projective(&sum).into_projective()
+    }
+
+    fn g2_msm(bases: &[G2Affine], scalars: &[ScalarInt]) -> G2Projective {
+        let n = bases.len().min(scalars.len());
+        if n == 0 {
+            return G2Projective::zero();
+        }
+        let bases = bases[..n].iter().map(g2_to_blst).collect::<Vec<_>>();
+        let sum = bases.mult(&scalar_bytes(&scalars[..n]), NBITS);
+        g2_from_blst_projective(&sum).into_projective()
+    }
+
+    fn pairing_product_is_one(pairs: &[(G1Affine, G2Affine)]) -> bool {
+        // Pairings with infinity are one, and blst does not expect them.
+        let (ps, qs): (Vec<_>, Vec<_>) = pairs
+            .iter()
+            .filter(|(p, q)| !p.infinity && !q.infinity)
+            .map(|(p, q)| (g1_to_blst(p), g2_to_blst(q)))
+            .unzip();
+        if ps.is_empty() {
+            return true;
+        }
+        blst_fp12::miller_loop_n(&qs, &ps).final_exp() == blst_fp12::default()
+    }
+
+    fn g1_subgroup_check(point: &G1Affine) -> bool {
+        // SAFETY: Valid reference.
+        unsafe { blst_p1_affine_in_g1(&g1_to_blst(point)) }
+    }
+
+    fn g2_subgroup_check(point: &G2Affine) -> bool {
+        // SAFETY: Valid reference.
+        unsafe { blst_p2_affine_in_g2(&g2_to_blst(point)) }
+    }
+
+    fn g1_decode(hex: &str) -> Result<G1Affine, ParseError> {
+        let mut bytes = [0_u8; 48];
+        let mut point = blst_p1_affine::default();
+        // SAFETY: Valid references, the encoding has 48 bytes.
+        let valid = parse_hex(hex, &mut bytes).is_ok()
+            && unsafe { blst_p1_uncompress(addr_of_mut!(point), bytes.as_ptr()) }
+                == BLST_ERROR::BLST_SUCCESS;
+        if !valid {
+            // Invalid encodings are rare, let arkworks find the exact error.
+            return parse_g::<g1::Parameters>(hex);
+        }
+        if !Self::g1_subgroup_check(&g1_from_blst(&point)) {
+            return Err(ParseError::InvalidSubgroup);
+        }
+        Ok(g1_from_blst(&point))
+    }
+
+    fn g2_decode(hex: &str) -> Result<G2Affine, ParseError> {
+        let mut bytes = [0_u8; 96];
+        let mut point = blst_p2_affine::default();
+        // SAFETY: Valid references, the encoding has 96 bytes.
+        let valid = parse_hex(hex, &mut bytes).is_ok()
+            && unsafe { blst_p2_uncompress(addr_of_mut!(point), bytes.as_ptr()) }
+                == BLST_ERROR::BLST_SUCCESS;
+        if !valid {
+            // Invalid encodings are rare, let arkworks find the exact error.
+            return parse_g::<g2::Parameters>(hex);
+        }
+        if !Self::g2_subgroup_check(&g2_from_blst(&point)) {
+            return Err(ParseError::InvalidSubgroup);
+        }
+        Ok(g2_from_blst(&point))
+    }
+
+    fn g1_encode(point: &G1Affine) -> String {
+        let mut bytes = [0_u8; 48];
+        // SAFETY: Valid references, the encoding has 48 bytes.
+        unsafe { blst_p1_affine_compress(bytes.as_mut_ptr(), &g1_to_blst(point)) };
+        format!("0x{}", hex::encode(bytes))
+    }
+
+    fn g2_encode(point: &G2Affine) -> String {
+        let mut bytes = [0_u8; 96];
+        // SAFETY: Valid references, the encoding has 96 bytes.
+        unsafe { blst_p2_affine_compress(bytes.as_mut_ptr(), &g2_to_blst(point)) };
+        format!("0x{}", hex::encode(bytes))
+    }
+}
+
+/// Scalars as concatenated little-endian bytes.
+fn scalar_bytes(scalars: &[ScalarInt]) -> Vec<u8> {
+    scalars.iter().flat_map(BigInteger::to_bytes_le).collect()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test::{arb_g1, arb_g2};
+    use proptest::proptest;
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(
+            g1_from_blst(&g1_to_blst(&G1Affine::zero())),
+            G1Affine::zero()
+        );
+        assert_eq!(
+            g2_from_blst(&g2_to_blst(&G2Affine::zero())),
+            G2Affine::zero()
+        );
+        proptest!(|(p in arb_g1(), q in arb_g2())| {
+            assert_eq!(g1_from_blst(&g1_to_blst(&p)), p);
+            assert_eq!(g2_from_blst(&g2_to_blst(&q)), q);
+        });
+    }
+}
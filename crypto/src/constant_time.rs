@@ -0,0 +1,194 @@
+//! Constant-time scalar multiplication for secret scalars.
+//!
+//! The arkworks multiplication and [`crate::crypto::g1_mul_glv`] skip leading
+//! zeros and branch on every bit of the scalar, which leaks tau through
+//! timing. That is fine for verification, where all scalars are public, but
+//! not when a contributor applies their secret.
+//!
+//! [`mul_secret`] instead uses a fixed window with a regular signed-digit
+//! recoding: the scalar is made odd by adding the group order if needed, and
+//! is then written as 64 odd digits in `±1, ±3, ..., ±15`. Every window does
+//! the same four doublings and one addition, and the table entry is looked up
+//! by reading all entries with conditional selects, so neither the sequence
+//! of operations nor the memory access pattern depend on the scalar.
+//!
+//! The group law of arkworks still branches on points at infinity and equal
+//! inputs. Neither happens for a non-zero scalar except with negligible
+//! probability.
+
+use ark_bls12_381::{Fq, Fq2, Fr, FrParameters};
+use ark_ec::{
+    models::SWModelParameters,
+    short_weierstrass_jacobian::{GroupAffine, GroupProjective},
+    ProjectiveCurve,
+};
+use ark_ff::{biginteger::BigInteger384, BigInteger, FpParameters, PrimeField};
+use std::ops::{AddAssign, Neg};
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq};
+use zeroize::Zeroizing;
+
+/// Bits per window.
+const WINDOW: u32 = 4;
+
+/// Number of signed digits of a 256 bit scalar.
+const DIGITS: usize = 64;
+
+/// Odd multiples of the base in the table, `1, 3, ..., 2^WINDOW - 1`.
+const TABLE_SIZE: usize = 1 << (WINDOW - 1);
+
+/// Selection between two values without branching on the choice.
+pub trait ConditionalSelect: Sized {
+    /// `a` if `choice` is zero, `b` if it is one.
+    fn select(a: &Self, b: &Self, choice: Choice) -> Self;
+}
+
+impl ConditionalSelect for Fq {
+    fn select(a: &Self, b: &Self, choice: Choice) -> Self {
+        let mut limbs = [0_u64; 6];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            *limb = u64::conditional_select(&(a.0).0[i], &(b.0).0[i], choice);
+        }
+        Self::new(BigInteger384(limbs))
+    }
+}
+
+impl ConditionalSelect for Fq2 {
+    fn select(a: &Self, b: &Self, choice: Choice) -> Self {
+        Self::new(
+            Fq::select(&a.c0, &b.c0, choice),
+            Fq::select(&a.c1, &b.c1, choice),
+        )
+    }
+}
+
+impl<P> ConditionalSelect for GroupProjective<P>
+where
+    P: SWModelParameters,
+    P::BaseField: ConditionalSelect,
+{
+    fn select(a: &Self, b: &Self, choice: Choice) -> Self {
+        Self::new(
+            P::BaseField::select(&a.x, &b.x, choice),
+            P::BaseField::select(&a.y, &b.y, choice),
+            P::BaseField::select(&a.z, &b.z, choice),
+        )
+    }
+}
+
+/// `scalar * base` in constant time, for secret scalars.
+pub fn mul_secret<P>(base: &GroupAffine<P>, scalar: &Fr) -> GroupProjective<P>
+where
+    P: SWModelParameters<ScalarField = Fr>,
+    P::BaseField: ConditionalSelect,
+{
+    let digits = recode(scalar);
+
+    // Odd multiples of the base.
+    let base = GroupProjective::<P>::from(*base);
+    let double = base.double();
+    let mut table = [base; TABLE_SIZE];
+    for i in 1..TABLE_SIZE {
+        table[i] = table[i - 1];
+        table[i].add_assign(&double);
+    }
+
+    let mut result = lookup(&table, digits[DIGITS - 1]);
+    for digit in digits[..DIGITS - 1].iter().rev() {
+        for _ in 0..WINDOW {
+            result.double_in_place();
+        }
+        result.add_assign(&lookup(&table, *digit));
+    }
+    result
+}
+
+/// The odd signed digits `d_i` with `k = sum d_i 2^(WINDOW i)`, least
+/// significant first, where `k` is `scalar` or `scalar` plus the group order,
+/// whichever is odd.
+fn recode(scalar: &Fr) -> Zeroizing<[i8; DIGITS]> {
+    let mut k = Zeroizing::new(scalar.into_repr());
+    let mut odd = *k;
+    // Does not overflow, the order is less than 2^255.
+    odd.add_nocarry(&FrParameters::MODULUS);
+    let is_even = Choice::from((k.0[0] & 1) as u8 ^ 1);
+    for (limb, odd) in k.0.iter_mut().zip(odd.0) {
+        *limb = u64::conditional_select(limb, &odd, is_even);
+    }
+
+    // With k odd, `d = (k mod 2^(WINDOW + 1)) - 2^WINDOW` is odd and
+    // `(k - d) / 2^WINDOW` is again odd.
+    let mut digits = Zeroizing::new([0_i8; DIGITS]);
+    for digit in &mut digits[..DIGITS - 1] {
+        #[allow(clippy::cast_possible_truncation)] // At most WINDOW + 1 bits
+        let low = (k.0[0] & ((1 << (WINDOW + 1)) - 1)) as i8;
+        *digit = low - (1 << WINDOW);
+        k.divn(WINDOW);
+        k.0[0] |= 1;
+    }
+    #[allow(clippy::cast_possible_truncation)] // Less than 2^WINDOW
+    let last = k.0[0] as i8;
+    digits[DIGITS - 1] = last;
+    digits
+}
+
+/// `digit * base` from the table of odd multiples, reading every entry.
+fn lookup<P>(table: &[GroupProjective<P>; TABLE_SIZE], digit: i8) -> GroupProjective<P>
+where
+    P: SWModelParameters,
+    P::BaseField: ConditionalSelect,
+{
+    let negative = digit >> 7;
+    #[allow(clippy::cast_sign_loss)] // Absolute value
+    let index = (((digit ^ negative) - negative) >> 1) as u8;
+    let mut result = table[0];
+    for (i, entry) in table.iter().enumerate() {
+        #[allow(clippy::cast_possible_truncation)] // Less than TABLE_SIZE
+        let choice = (i as u8).ct_eq(&index);
+        result = GroupProjective::select(&result, entry, choice);
+    }
+    #[allow(clippy::cast_sign_loss)]
+    let is_negative = Choice::from((negative & 1) as u8);
+    GroupProjective::select(&result, &result.neg(), is_negative)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test::{arb_fr, arb_g1, arb_g2};
+    use ark_bls12_381::{G1Affine, G2Affine};
+    use ark_ec::AffineCurve;
+    use ark_ff::{One, Zero};
+    use proptest::proptest;
+
+    #[test]
+    fn test_recode() {
+        for scalar in [Fr::zero(), Fr::one(), -Fr::one(), Fr::from(0x1234_u64)] {
+            let digits = recode(&scalar);
+            assert!(digits.iter().all(|d| d % 2 != 0 && d.abs() < 16));
+            let sum = digits
+                .iter()
+                .rev()
+                .fold(Fr::zero(), |sum, d| sum * Fr::from(16_u64) + Fr::from(*d));
+            assert_eq!(sum, scalar);
+        }
+    }
+
+    #[test]
+    fn test_mul_secret_g1() {
+        let g = G1Affine::prime_subgroup_generator();
+        assert!(mul_secret(&g, &Fr::zero()).is_zero());
+        assert_eq!(mul_secret(&g, &Fr::one()), g.into_projective());
+        proptest!(|(p in arb_g1(), s in arb_fr())| {
+            assert_eq!(mul_secret(&p, &s), p.mul(s));
+        });
+    }
+
+    #[test]
+    fn test_mul_secret_g2() {
+        proptest!(|(p in arb_g2(), s in arb_fr())| {
+            assert_eq!(mul_secret(&p, &s), p.mul(s));
+        });
+        let g = G2Affine::prime_subgroup_generator();
+        assert_eq!(mul_secret(&g, &-Fr::one()), -g.into_projective());
+    }
+}
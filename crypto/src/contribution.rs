@@ -1,42 +1,70 @@
-use crate::{crypto::g1_mul_glv, g1_subgroup_check, g2_subgroup_check, parse_g, ParseError};
+use crate::{
+    batch_check::{find_non_subgroup_point_with_rng, parse_g1_batch_with_rng},
+    constant_time::mul_secret,
+    error_code::{serialize_error, ErrorCode},
+    fixed_base::{self, pairing_with_g2_generator},
+    format_g, g2_subgroup_check,
+    pairing_check::PairingCheck,
+    parse_g, signature, CurveBackend, DefaultBackend, ParseError, PubkeyProof, PubkeyProofJson,
+    SecretScalar, VerificationRng,
+};
 use ark_bls12_381::{g1, g2, Bls12_381, Fr, G1Affine, G1Projective, G2Affine, G2Projective};
-use ark_ec::{msm::VariableBaseMSM, AffineCurve, PairingEngine, ProjectiveCurve};
-use ark_ff::{One, PrimeField, UniformRand, Zero};
+use ark_ec::{AffineCurve, PairingEngine, ProjectiveCurve};
+use ark_ff::{PrimeField, UniformRand, Zero};
+use rand::Rng;
 use rayon::prelude::*;
-use serde::{Deserialize, Serialize};
-use std::{cmp::max, iter};
+use serde::{Deserialize, Serialize, Serializer};
+use std::{cmp::max, collections::HashMap, hash::Hash, iter};
 use thiserror::Error;
-use tracing::{error, instrument};
-use zeroize::Zeroizing;
+use tracing::instrument;
 
+/// A sub-ceremony transcript: the current powers of tau and the witness of
+/// how they were built, one running product and pubkey per contribution.
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Transcript {
-    pub g1_powers: Vec<G1Affine>,
-    pub g2_powers: Vec<G2Affine>,
-    pub products:  Vec<G1Affine>,
-    pub pubkeys:   Vec<G2Affine>,
+    pub g1_powers:  Vec<G1Affine>,
+    pub g2_powers:  Vec<G2Affine>,
+    pub products:   Vec<G1Affine>,
+    pub pubkeys:    Vec<G2Affine>,
+    /// The identity signatures that came with the pubkeys, if any.
+    pub signatures: Vec<Option<G1Affine>>,
 }
 
+/// A contribution to a sub-ceremony: the updated powers of tau and the
+/// pubkey `tau * G2` of the secret that was added.
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Contribution {
-    pub pubkey:    G2Affine,
-    pub g1_powers: Vec<G1Affine>,
-    pub g2_powers: Vec<G2Affine>,
+    pub pubkey:        G2Affine,
+    pub g1_powers:     Vec<G1Affine>,
+    pub g2_powers:     Vec<G2Affine>,
+    /// Optional signature of the contributor's identity under tau.
+    pub bls_signature: Option<G1Affine>,
+    /// Optional proof of knowledge of tau for the pubkey.
+    pub pubkey_proof:  Option<PubkeyProof>,
 }
 
+/// JSON encoding of a contribution to all sub-ceremonies, as specified.
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ContributionsJson {
     pub sub_contributions: Vec<ContributionJson>,
+    /// Optional EIP-712 signature of the `potPubkeys` by an Ethereum address.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ecdsa_signature:   Option<String>,
 }
 
+/// JSON encoding of a [`Contribution`], with points in zcash format.
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ContributionJson {
-    pub num_g1_powers: usize,
-    pub num_g2_powers: usize,
-    pub powers_of_tau: PowersOfTau,
-    pub pot_pubkey:    Option<String>,
+    pub num_g1_powers:    usize,
+    pub num_g2_powers:    usize,
+    pub powers_of_tau:    PowersOfTau,
+    pub pot_pubkey:       Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bls_signature:    Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pot_pubkey_proof: Option<PubkeyProofJson>,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
@@ -46,14 +74,20 @@ pub struct PowersOfTau {
     pub g2_powers: Vec<String>,
 }
 
+/// Error parsing a [`ContributionsJson`].
 #[derive(Clone, Copy, PartialEq, Debug, Error)]
 pub enum ContributionsError {
     #[error("Error in contribution {0}: {1}")]
     InvalidContribution(usize, #[source] ContributionError),
     #[error("Unexpected number of contributions: expected {0}, got {1}")]
     InvalidContributionCount(usize, usize),
+    #[error("Contribution {0} matches no sub-ceremony or is out of order")]
+    UnexpectedSubCeremony(usize),
+    #[error("Contribution has no sub-contributions")]
+    NoSubContributions,
 }
 
+/// Error parsing a [`ContributionJson`].
 #[derive(Clone, Copy, PartialEq, Debug, Error)]
 pub enum ContributionError {
     #[error("Unexpected number of G1 powers: expected {0}, got {1}")]
@@ -70,45 +104,261 @@ pub enum ContributionError {
     InvalidG2Power(usize, #[source] ParseError),
     #[error("Error parsing potPubkey: {0}")]
     InvalidPubKey(#[source] ParseError),
+    #[error("Error parsing blsSignature: {0}")]
+    InvalidBlsSignature(#[source] ParseError),
+    #[error("Error parsing potPubkeyProof: {0}")]
+    InvalidPubkeyProof(#[source] ParseError),
+    #[error("G1 power {0} is the point at infinity")]
+    ZeroG1Power(usize),
+    #[error("G2 power {0} is the point at infinity")]
+    ZeroG2Power(usize),
+    #[error("Contribution has a trivial tau of zero or one")]
+    TrivialTau,
+    #[error("G1 powers {0} and {1} are equal")]
+    DuplicateG1Power(usize, usize),
+    #[error("G2 powers {0} and {1} are equal")]
+    DuplicateG2Power(usize, usize),
+}
+
+impl ErrorCode for ContributionsError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidContribution(..) => "invalid_contribution",
+            Self::InvalidContributionCount(..) => "invalid_contribution_count",
+            Self::UnexpectedSubCeremony(_) => "unexpected_sub_ceremony",
+            Self::NoSubContributions => "no_sub_contributions",
+        }
+    }
+}
+
+impl Serialize for ContributionsError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (fields, cause): (&[_], _) = match self {
+            Self::InvalidContribution(index, error) => (&[("index", *index)], Some(error)),
+            Self::InvalidContributionCount(expected, got) => {
+                (&[("expected", *expected), ("got", *got)], None)
+            }
+            Self::UnexpectedSubCeremony(index) => (&[("index", *index)], None),
+            Self::NoSubContributions => (&[], None),
+        };
+        serialize_error(serializer, self, fields, cause)
+    }
+}
+
+impl ErrorCode for ContributionError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::UnexpectedNumG1Powers(..) => "unexpected_num_g1_powers",
+            Self::UnexpectedNumG2Powers(..) => "unexpected_num_g2_powers",
+            Self::InconsistentNumG1Powers(..) => "inconsistent_num_g1_powers",
+            Self::InconsistentNumG2Powers(..) => "inconsistent_num_g2_powers",
+            Self::InvalidG1Power(..) => "invalid_g1_power",
+            Self::InvalidG2Power(..) => "invalid_g2_power",
+            Self::InvalidPubKey(_) => "invalid_pubkey",
+            Self::InvalidBlsSignature(_) => "invalid_bls_signature",
+            Self::InvalidPubkeyProof(_) => "invalid_pubkey_proof",
+            Self::ZeroG1Power(_) => "zero_g1_power",
+            Self::ZeroG2Power(_) => "zero_g2_power",
+            Self::TrivialTau => "trivial_tau",
+            Self::DuplicateG1Power(..) => "duplicate_g1_power",
+            Self::DuplicateG2Power(..) => "duplicate_g2_power",
+        }
+    }
+}
+
+impl Serialize for ContributionError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (fields, cause): (&[_], _) = match self {
+            Self::UnexpectedNumG1Powers(expected, got)
+            | Self::UnexpectedNumG2Powers(expected, got)
+            | Self::InconsistentNumG1Powers(expected, got)
+            | Self::InconsistentNumG2Powers(expected, got) => {
+                (&[("expected", *expected), ("got", *got)], None)
+            }
+            Self::InvalidG1Power(index, error) | Self::InvalidG2Power(index, error) => {
+                (&[("index", *index)], Some(error))
+            }
+            Self::InvalidPubKey(error)
+            | Self::InvalidBlsSignature(error)
+            | Self::InvalidPubkeyProof(error) => (&[], Some(error)),
+            Self::ZeroG1Power(index) | Self::ZeroG2Power(index) => (&[("index", *index)], None),
+            Self::TrivialTau => (&[], None),
+            Self::DuplicateG1Power(first, second) | Self::DuplicateG2Power(first, second) => {
+                (&[("first", *first), ("second", *second)], None)
+            }
+        };
+        serialize_error(serializer, self, fields, cause)
+    }
+}
+
+/// Optional sanity checks on parsed contributions.
+///
+/// Points at infinity are always rejected. The other checks reject degenerate
+/// contributions, but must be disabled for the initial contribution, which
+/// consists of generators only.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SanityChecks {
+    /// Reject a tau of one, i.e. the first G1 power or the `potPubkey` equal
+    /// to the generator.
+    pub nontrivial_tau:  bool,
+    /// Reject repeated powers, which happen when tau has a small order.
+    pub distinct_powers: bool,
+}
+
+impl SanityChecks {
+    /// Checks for the initial contribution.
+    pub const INITIAL: Self = Self {
+        nontrivial_tau:  false,
+        distinct_powers: false,
+    };
+}
+
+impl Default for SanityChecks {
+    fn default() -> Self {
+        Self {
+            nontrivial_tau:  true,
+            distinct_powers: true,
+        }
+    }
+}
+
+/// Error verifying a [`Contribution`] or [`Transcript`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum VerifyError {
+    #[error("Unexpected number of G1 powers: expected {0}, got {1}")]
+    UnexpectedNumG1Powers(usize, usize),
+    #[error("Unexpected number of G2 powers: expected {0}, got {1}")]
+    UnexpectedNumG2Powers(usize, usize),
+    #[error("Contribution needs at least two G1 and two G2 powers")]
+    TooFewPowers,
+    #[error("Transcript has no running product")]
+    EmptyTranscript,
+    #[error("Pubkey is not in the prime-order subgroup")]
+    PubkeyNotInSubgroup,
+    #[error("G1 power {0} is not in the prime-order subgroup")]
+    G1NotInSubgroup(usize),
+    #[error("G2 power {0} is not in the prime-order subgroup")]
+    G2NotInSubgroup(usize),
+    #[error("First G1 power is not the previous running product times the pubkey")]
+    PubkeyMismatch,
+    #[error("G1 powers are not successive powers of tau")]
+    InconsistentG1Powers,
+    #[error("G2 powers are not consistent with the G1 powers")]
+    InconsistentG2Powers,
+    #[error("Transcript has {0} running products but {1} pubkeys")]
+    ChainLength(usize, usize),
+    #[error("Transcript does not start at the generators")]
+    InvalidGenesis,
+    #[error("Running product {0} is not the previous product times pubkey {0}")]
+    ProductMismatch(usize),
+    #[error("First G1 power is not the latest running product")]
+    PowersMismatch,
+}
+
+impl ErrorCode for VerifyError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::UnexpectedNumG1Powers(..) => "unexpected_num_g1_powers",
+            Self::UnexpectedNumG2Powers(..) => "unexpected_num_g2_powers",
+            Self::TooFewPowers => "too_few_powers",
+            Self::EmptyTranscript => "empty_transcript",
+            Self::PubkeyNotInSubgroup => "pubkey_not_in_subgroup",
+            Self::G1NotInSubgroup(_) => "g1_not_in_subgroup",
+            Self::G2NotInSubgroup(_) => "g2_not_in_subgroup",
+            Self::PubkeyMismatch => "pubkey_mismatch",
+            Self::InconsistentG1Powers => "inconsistent_g1_powers",
+            Self::InconsistentG2Powers => "inconsistent_g2_powers",
+            Self::ChainLength(..) => "chain_length",
+            Self::InvalidGenesis => "invalid_genesis",
+            Self::ProductMismatch(_) => "product_mismatch",
+            Self::PowersMismatch => "powers_mismatch",
+        }
+    }
+}
+
+impl Serialize for VerifyError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let fields: &[_] = match *self {
+            Self::UnexpectedNumG1Powers(expected, got)
+            | Self::UnexpectedNumG2Powers(expected, got) => &[("expected", expected), ("got", got)],
+            Self::G1NotInSubgroup(index)
+            | Self::G2NotInSubgroup(index)
+            | Self::ProductMismatch(index) => &[("index", index)],
+            Self::ChainLength(products, pubkeys) => &[("products", products), ("pubkeys", pubkeys)],
+            _ => &[],
+        };
+        serialize_error(serializer, self, fields, None::<&()>)
+    }
 }
 
 impl ContributionsJson {
+    #[must_use]
     pub fn initial() -> Self {
+        Self::initial_with_sizes(&crate::SIZES)
+    }
+
+    /// Generators only, for sub-ceremonies of the given sizes.
+    #[must_use]
+    pub fn initial_with_sizes(sizes: &[(usize, usize)]) -> Self {
         Self {
-            sub_contributions: crate::SIZES
+            sub_contributions: sizes
                 .iter()
                 .map(|(num_g1, num_g2)| ContributionJson::initial(*num_g1, *num_g2))
                 .collect(),
+            ecdsa_signature:   None,
         }
     }
 
-    pub fn from_json(json: &str) -> Result<Self, ContributionsError> {
-        // let json = serde_json::from_str(json)?;
-        // let validation = schema.validate(&initial);
-        // if !validation.is_strictly_valid() {
-        //     for error in validation.errors {
-        //         error!("{}", error);
-        //     }
-        //     for missing in validation.missing {
-        //         error!("Missing {}", missing);
-        //     }
-        //     // TODO bail!("Initial contribution is not valid.");
-        // }
-        // info!("Initial contribution is json-schema valid.");
-        // TODO:
-        todo!()
+    /// Encode contributions for upload.
+    #[must_use]
+    pub fn from_contributions(contributions: &[Contribution]) -> Self {
+        Self {
+            sub_contributions: contributions.iter().map(ContributionJson::from).collect(),
+            ecdsa_signature:   None,
+        }
     }
 
+    /// Parse a contribution to all sub-ceremonies.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the first sub-contribution that is invalid.
     pub fn parse(&self) -> Result<Vec<Contribution>, ContributionsError> {
-        if self.sub_contributions.len() != crate::SIZES.len() {
+        self.parse_with(SanityChecks::default())
+    }
+
+    /// [`Self::parse`] with the given sanity checks.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`Self::parse`].
+    pub fn parse_with(
+        &self,
+        checks: SanityChecks,
+    ) -> Result<Vec<Contribution>, ContributionsError> {
+        self.parse_with_sizes(&crate::SIZES, checks)
+    }
+
+    /// Parse a contribution to sub-ceremonies of the given sizes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of sub-contributions or their sizes do
+    /// not match, or naming the first sub-contribution that is invalid.
+    pub fn parse_with_sizes(
+        &self,
+        sizes: &[(usize, usize)],
+        checks: SanityChecks,
+    ) -> Result<Vec<Contribution>, ContributionsError> {
+        if self.sub_contributions.len() != sizes.len() {
             return Err(ContributionsError::InvalidContributionCount(
-                4,
+                sizes.len(),
                 self.sub_contributions.len(),
             ));
         }
         self.sub_contributions
             .iter()
-            .zip(crate::SIZES.iter())
+            .zip(sizes.iter())
             .map(|(c, (num_g1, num_g2))| {
                 if c.num_g1_powers != *num_g1 {
                     return Err(ContributionError::UnexpectedNumG1Powers(
@@ -117,9 +367,9 @@ impl ContributionsJson {
                     ));
                 }
                 if c.num_g2_powers != *num_g2 {
-                    return Err(ContributionError::UnexpectedNumG1Powers(
-                        *num_g1,
-                        c.num_g1_powers,
+                    return Err(ContributionError::UnexpectedNumG2Powers(
+                        *num_g2,
+                        c.num_g2_powers,
                     ));
                 }
                 Ok(())
@@ -132,68 +382,297 @@ impl ContributionsJson {
             .par_iter()
             .enumerate()
             .map(|(i, c)| {
-                c.parse()
+                c.parse_with(checks)
                     .map_err(|e| ContributionsError::InvalidContribution(i, e))
             })
             .collect::<Result<Vec<_>, _>>()
     }
+
+    /// Parse a contribution to a subset of the sub-ceremonies.
+    ///
+    /// Sub-contributions are matched to sub-ceremonies by their number of
+    /// powers and must be in the order of [`crate::SIZES`]. The result has an
+    /// entry for every sub-ceremony, `None` for the ones not contributed to.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error of [`Self::parse_subset_exhaustive`].
+    pub fn parse_subset(&self) -> Result<Vec<Option<Contribution>>, ContributionsError> {
+        self.parse_subset_with(SanityChecks::default())
+    }
+
+    /// [`Self::parse_subset`] with the given sanity checks.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`Self::parse_subset`].
+    pub fn parse_subset_with(
+        &self,
+        checks: SanityChecks,
+    ) -> Result<Vec<Option<Contribution>>, ContributionsError> {
+        self.parse_subset_with_sizes(&crate::SIZES, checks)
+    }
+
+    /// Parse a contribution to a subset of sub-ceremonies of the given sizes.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`Self::parse_subset`].
+    pub fn parse_subset_with_sizes(
+        &self,
+        sizes: &[(usize, usize)],
+        checks: SanityChecks,
+    ) -> Result<Vec<Option<Contribution>>, ContributionsError> {
+        self.parse_subset_exhaustive(sizes, checks)
+            .map_err(|errors| errors[0])
+    }
+
+    /// Like [`Self::parse_subset_with_sizes`], but reports every error
+    /// instead of only the first.
+    ///
+    /// Errors are in order of sub-contribution, and within a sub-contribution
+    /// in the order of [`ContributionJson::parse_exhaustive`]. Misplaced
+    /// sub-contributions are not parsed.
+    ///
+    /// # Errors
+    ///
+    /// Returns all errors if there are no sub-contributions, they are
+    /// misplaced or any of them is invalid.
+    pub fn parse_subset_exhaustive(
+        &self,
+        sizes: &[(usize, usize)],
+        checks: SanityChecks,
+    ) -> Result<Vec<Option<Contribution>>, Vec<ContributionsError>> {
+        self.parse_subset_exhaustive_with_rng(sizes, checks, &mut VerificationRng::new())
+    }
+
+    /// [`Self::parse_subset_exhaustive`] with the subgroup checks seeded from
+    /// `rng`.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`Self::parse_subset_exhaustive`].
+    pub fn parse_subset_exhaustive_with_rng(
+        &self,
+        sizes: &[(usize, usize)],
+        checks: SanityChecks,
+        rng: &mut VerificationRng,
+    ) -> Result<Vec<Option<Contribution>>, Vec<ContributionsError>> {
+        if self.sub_contributions.is_empty() {
+            return Err(vec![ContributionsError::NoSubContributions]);
+        }
+        let mut next = 0;
+        let slots = self
+            .sub_contributions
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let slot = sizes
+                    .iter()
+                    .position(|size| *size == (c.num_g1_powers, c.num_g2_powers))
+                    .filter(|slot| *slot >= next)
+                    .ok_or(ContributionsError::UnexpectedSubCeremony(i))?;
+                next = slot + 1;
+                Ok(slot)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| vec![error])?;
+        let seeds = self
+            .sub_contributions
+            .iter()
+            .map(|_| rng.gen())
+            .collect::<Vec<_>>();
+        let parsed = self
+            .sub_contributions
+            .par_iter()
+            .zip(seeds)
+            .map(|(c, seed)| {
+                c.parse_exhaustive_with_rng(checks, &mut VerificationRng::from_seed(seed))
+            })
+            .collect::<Vec<_>>();
+        let errors = parsed
+            .iter()
+            .enumerate()
+            .flat_map(|(i, result)| {
+                result
+                    .as_ref()
+                    .err()
+                    .into_iter()
+                    .flatten()
+                    .map(move |e| ContributionsError::InvalidContribution(i, *e))
+            })
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        let mut contributions = vec![None; sizes.len()];
+        for (slot, contribution) in slots.into_iter().zip(parsed) {
+            contributions[slot] = contribution.ok();
+        }
+        Ok(contributions)
+    }
 }
 
 impl ContributionJson {
+    #[must_use]
     pub fn initial(num_g1_powers: usize, num_g2_powers: usize) -> Self {
         Self {
             num_g1_powers,
             num_g2_powers,
             powers_of_tau: PowersOfTau::initial(num_g1_powers, num_g2_powers),
             pot_pubkey: None,
+            bls_signature: None,
+            pot_pubkey_proof: None,
         }
     }
 
+    /// Parse the powers, pubkey and signatures.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error of [`Self::parse_exhaustive`].
     pub fn parse(&self) -> Result<Contribution, ContributionError> {
+        self.parse_with(SanityChecks::default())
+    }
+
+    /// [`Self::parse`] with the given sanity checks.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`Self::parse`].
+    pub fn parse_with(&self, checks: SanityChecks) -> Result<Contribution, ContributionError> {
+        self.parse_exhaustive(checks).map_err(|errors| errors[0])
+    }
+
+    /// Like [`Self::parse_with`], but reports every error instead of only
+    /// the first.
+    ///
+    /// Errors are reported in the order of the fields and by increasing
+    /// index. The sanity checks only run once all points parsed.
+    ///
+    /// # Errors
+    ///
+    /// Returns all errors if the lengths are inconsistent, a point is invalid
+    /// or a sanity check fails.
+    pub fn parse_exhaustive(
+        &self,
+        checks: SanityChecks,
+    ) -> Result<Contribution, Vec<ContributionError>> {
+        self.parse_exhaustive_with_rng(checks, &mut VerificationRng::new())
+    }
+
+    /// [`Self::parse_exhaustive`] with the subgroup checks seeded from `rng`.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`Self::parse_exhaustive`].
+    ///
+    /// # Panics
+    ///
+    /// Never, the parsed points are only unwrapped if there were no errors.
+    pub fn parse_exhaustive_with_rng(
+        &self,
+        checks: SanityChecks,
+        rng: &mut VerificationRng,
+    ) -> Result<Contribution, Vec<ContributionError>> {
+        let mut errors = Vec::new();
         if self.powers_of_tau.g1_powers.len() != self.num_g1_powers {
-            return Err(ContributionError::InconsistentNumG1Powers(
+            errors.push(ContributionError::InconsistentNumG1Powers(
                 self.num_g1_powers,
                 self.powers_of_tau.g1_powers.len(),
             ));
         }
         if self.powers_of_tau.g2_powers.len() != self.num_g2_powers {
-            return Err(ContributionError::InconsistentNumG2Powers(
+            errors.push(ContributionError::InconsistentNumG2Powers(
                 self.num_g2_powers,
                 self.powers_of_tau.g2_powers.len(),
             ));
         }
-        let g1_powers = self
-            .powers_of_tau
-            .g1_powers
-            .par_iter()
-            .enumerate()
-            .map(|(i, hex)| {
-                parse_g::<g1::Parameters>(hex).map_err(|e| ContributionError::InvalidG1Power(i, e))
+        // Subgroup checks are much faster in a batch.
+        let g1_powers = parse_g1_batch_with_rng(&self.powers_of_tau.g1_powers, rng)
+            .map_err(|invalid| {
+                errors.extend(
+                    invalid
+                        .0
+                        .into_iter()
+                        .map(|(i, e)| ContributionError::InvalidG1Power(i, e)),
+                );
             })
-            .collect::<Result<Vec<_>, _>>()?;
+            .ok();
         let g2_powers = self
             .powers_of_tau
             .g2_powers
             .par_iter()
-            .enumerate()
-            .map(|(i, hex)| {
-                parse_g::<g2::Parameters>(hex).map_err(|e| ContributionError::InvalidG2Power(i, e))
-            })
-            .collect::<Result<Vec<_>, _>>()?;
-        let pubkey = if let Some(pubkey) = &self.pot_pubkey {
-            parse_g::<g2::Parameters>(pubkey).map_err(ContributionError::InvalidPubKey)?
-        } else {
-            G2Affine::zero()
+            .map(|hex| parse_g::<g2::Parameters>(hex))
+            .collect::<Vec<_>>();
+        errors.extend(g2_powers.iter().enumerate().filter_map(|(i, point)| {
+            point
+                .as_ref()
+                .err()
+                .map(|e| ContributionError::InvalidG2Power(i, *e))
+        }));
+        let pubkey = self
+            .pot_pubkey
+            .as_ref()
+            .map_or_else(
+                || Ok(G2Affine::zero()),
+                |pubkey| parse_g::<g2::Parameters>(pubkey),
+            )
+            .map_err(|e| errors.push(ContributionError::InvalidPubKey(e)));
+        let bls_signature = self
+            .bls_signature
+            .as_ref()
+            .map(|signature| parse_g::<g1::Parameters>(signature))
+            .transpose()
+            .map_err(|e| errors.push(ContributionError::InvalidBlsSignature(e)));
+        let pubkey_proof = self
+            .pot_pubkey_proof
+            .as_ref()
+            .map(PubkeyProof::try_from)
+            .transpose()
+            .map_err(|e| errors.push(ContributionError::InvalidPubkeyProof(e)));
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        let contribution = Contribution {
+            pubkey:        pubkey.expect("Errors are handled above"),
+            g1_powers:     g1_powers.expect("Errors are handled above"),
+            g2_powers:     g2_powers
+                .into_iter()
+                .collect::<Result<_, _>>()
+                .expect("Errors are handled above"),
+            bls_signature: bls_signature.expect("Errors are handled above"),
+            pubkey_proof:  pubkey_proof.expect("Errors are handled above"),
         };
-        Ok(Contribution {
-            pubkey,
-            g1_powers,
-            g2_powers,
-        })
+        let errors = contribution.sanity_check(checks, self.pot_pubkey.is_some());
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(contribution)
+    }
+}
+
+impl From<&Contribution> for ContributionJson {
+    fn from(contribution: &Contribution) -> Self {
+        Self {
+            num_g1_powers:    contribution.g1_powers.len(),
+            num_g2_powers:    contribution.g2_powers.len(),
+            powers_of_tau:    PowersOfTau {
+                g1_powers: contribution.g1_powers.par_iter().map(format_g).collect(),
+                g2_powers: contribution.g2_powers.par_iter().map(format_g).collect(),
+            },
+            pot_pubkey:       Some(format_g(&contribution.pubkey)),
+            bls_signature:    contribution.bls_signature.as_ref().map(format_g),
+            pot_pubkey_proof: contribution
+                .pubkey_proof
+                .as_ref()
+                .map(PubkeyProofJson::from),
+        }
     }
 }
 
 impl PowersOfTau {
+    #[must_use]
     pub fn initial(num_g1_powers: usize, num_g2_powers: usize) -> Self {
         Self {
             g1_powers: vec!["0x97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb".to_string(); num_g1_powers],
@@ -206,128 +685,527 @@ impl Transcript {
     #[must_use]
     pub fn new(num_g1: usize, num_g2: usize) -> Self {
         Self {
-            pubkeys:   vec![G2Affine::prime_subgroup_generator()],
-            products:  vec![G1Affine::prime_subgroup_generator()],
-            g1_powers: vec![G1Affine::prime_subgroup_generator(); num_g1],
-            g2_powers: vec![G2Affine::prime_subgroup_generator(); num_g2],
+            pubkeys:    vec![G2Affine::prime_subgroup_generator()],
+            products:   vec![G1Affine::prime_subgroup_generator()],
+            signatures: vec![None],
+            g1_powers:  vec![G1Affine::prime_subgroup_generator(); num_g1],
+            g2_powers:  vec![G2Affine::prime_subgroup_generator(); num_g2],
         }
     }
+
+    /// Transcript whose current powers are those of `contribution`.
+    ///
+    /// Only the latest running product and pubkey are known, which is all that
+    /// is required to verify a contribution on top of it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`VerifyError::TooFewPowers`] if the contribution has fewer
+    /// than two G1 powers, so there is no running product.
+    pub fn from_contribution(contribution: &Contribution) -> Result<Self, VerifyError> {
+        let product = *contribution
+            .g1_powers
+            .get(1)
+            .ok_or(VerifyError::TooFewPowers)?;
+        Ok(Self {
+            pubkeys:    vec![contribution.pubkey],
+            products:   vec![product],
+            signatures: vec![contribution.bls_signature],
+            g1_powers:  contribution.g1_powers.clone(),
+            g2_powers:  contribution.g2_powers.clone(),
+        })
+    }
+
+    /// The current powers, to build the next contribution on.
+    ///
+    /// The pubkey is the generator, so that after [`Contribution::add_tau`]
+    /// it commits to the new secret only.
+    #[must_use]
+    pub fn contribution(&self) -> Contribution {
+        Contribution {
+            pubkey:        G2Affine::prime_subgroup_generator(),
+            g1_powers:     self.g1_powers.clone(),
+            g2_powers:     self.g2_powers.clone(),
+            bls_signature: None,
+            pubkey_proof:  None,
+        }
+    }
+
+    /// Append an (already verified) contribution to the transcript.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the contribution has a different number of powers.
+    pub fn add(&mut self, contribution: Contribution) {
+        assert_eq!(self.g1_powers.len(), contribution.g1_powers.len());
+        assert_eq!(self.g2_powers.len(), contribution.g2_powers.len());
+        self.products.push(contribution.g1_powers[1]);
+        self.pubkeys.push(contribution.pubkey);
+        self.signatures.push(contribution.bls_signature);
+        self.g1_powers = contribution.g1_powers;
+        self.g2_powers = contribution.g2_powers;
+    }
+
+    /// Verify the entire transcript from the generators to the current powers.
+    ///
+    /// Points are assumed to be in the correct subgroups.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first inconsistency found.
+    #[instrument(level = "info", skip_all, fields(n = self.products.len()))]
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        self.verify_with_rng(&mut VerificationRng::new())
+    }
+
+    /// [`Self::verify`] with the random factors of the batched checks drawn
+    /// from `rng`.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`Self::verify`].
+    pub fn verify_with_rng(&self, rng: &mut VerificationRng) -> Result<(), VerifyError> {
+        self.verify_powers_with_rng(rng)?;
+        self.verify_witness_with_rng(rng)
+    }
+
+    /// Check the whole witness in one pass: every running product must be the
+    /// previous one updated with the corresponding pubkey, starting from the
+    /// generators.
+    ///
+    /// This is [`Self::verify_step`] for every index, with all pairing
+    /// equations batched into a single check. If it fails, the steps are
+    /// checked individually to locate the first invalid one.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the first invalid step.
+    #[instrument(level = "info", skip_all, fields(n = self.products.len()))]
+    pub fn verify_witness(&self) -> Result<(), VerifyError> {
+        self.verify_witness_with_rng(&mut VerificationRng::new())
+    }
+
+    /// [`Self::verify_witness`] with the random factors drawn from `rng`.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`Self::verify_witness`].
+    pub fn verify_witness_with_rng(&self, rng: &mut VerificationRng) -> Result<(), VerifyError> {
+        if self.products.len() != self.pubkeys.len() {
+            return Err(VerifyError::ChainLength(
+                self.products.len(),
+                self.pubkeys.len(),
+            ));
+        }
+        self.verify_step(0)?;
+        if let Some(index) = self.pubkeys.iter().position(Zero::is_zero) {
+            return Err(VerifyError::ProductMismatch(index));
+        }
+        // Drawn up front, so they do not depend on scheduling.
+        let factors = (1..self.products.len())
+            .map(|_| Fr::rand(rng))
+            .collect::<Vec<_>>();
+        let check = (1..self.products.len())
+            .into_par_iter()
+            .fold(PairingCheck::default, |mut check, index| {
+                check.add_scaled(
+                    factors[index - 1],
+                    &self.products[index],
+                    &self.products[index - 1],
+                    &self.pubkeys[index],
+                );
+                check
+            })
+            .reduce(PairingCheck::default, PairingCheck::merge);
+        if check.verify() {
+            return Ok(());
+        }
+        (1..self.products.len())
+            .find_map(|index| self.verify_step(index).err())
+            .map_or(Ok(()), Err)
+    }
+
+    /// Check that the current powers are consistent and built on the latest
+    /// running product.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the powers do not start at the generators, are
+    /// not built on the latest product or are inconsistent.
+    #[instrument(level = "info", skip_all)]
+    pub fn verify_powers(&self) -> Result<(), VerifyError> {
+        self.verify_powers_with_rng(&mut VerificationRng::new())
+    }
+
+    /// [`Self::verify_powers`] with the random factors drawn from `rng`.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`Self::verify_powers`].
+    pub fn verify_powers_with_rng(&self, rng: &mut VerificationRng) -> Result<(), VerifyError> {
+        if self.products.len() != self.pubkeys.len() {
+            return Err(VerifyError::ChainLength(
+                self.products.len(),
+                self.pubkeys.len(),
+            ));
+        }
+        if self.g1_powers.len() < 2 || self.g2_powers.len() < 2 {
+            return Err(VerifyError::TooFewPowers);
+        }
+        if self.g1_powers[0] != G1Affine::prime_subgroup_generator()
+            || self.g2_powers[0] != G2Affine::prime_subgroup_generator()
+        {
+            return Err(VerifyError::InvalidGenesis);
+        }
+        let product = self.products.last().ok_or(VerifyError::EmptyTranscript)?;
+        if self.g1_powers[1] != *product {
+            return Err(VerifyError::PowersMismatch);
+        }
+        let contribution = self.contribution();
+        if !contribution.verify_g1(rng) {
+            return Err(VerifyError::InconsistentG1Powers);
+        }
+        if !contribution.verify_g2(rng) {
+            return Err(VerifyError::InconsistentG2Powers);
+        }
+        Ok(())
+    }
+
+    /// Check that running product `index` is the previous product updated
+    /// with pubkey `index`. The first product and pubkey must be the
+    /// generators.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the step is missing or does not hold.
+    pub fn verify_step(&self, index: usize) -> Result<(), VerifyError> {
+        let (product, pubkey) = match (self.products.get(index), self.pubkeys.get(index)) {
+            (Some(product), Some(pubkey)) => (product, pubkey),
+            _ => {
+                return Err(VerifyError::ChainLength(
+                    self.products.len(),
+                    self.pubkeys.len(),
+                ))
+            }
+        };
+        if index == 0 {
+            if *product != G1Affine::prime_subgroup_generator()
+                || *pubkey != G2Affine::prime_subgroup_generator()
+            {
+                return Err(VerifyError::InvalidGenesis);
+            }
+            return Ok(());
+        }
+        if pubkey.is_zero()
+            || pairing_with_g2_generator(*product)
+                != Bls12_381::pairing(self.products[index - 1], *pubkey)
+        {
+            return Err(VerifyError::ProductMismatch(index));
+        }
+        Ok(())
+    }
 }
 
 impl Contribution {
+    #[must_use]
     pub fn new(num_g1: usize, num_g2: usize) -> Self {
         Self {
-            pubkey:    G2Affine::prime_subgroup_generator(),
-            g1_powers: vec![G1Affine::prime_subgroup_generator(); num_g1],
-            g2_powers: vec![G2Affine::prime_subgroup_generator(); num_g2],
+            pubkey:        G2Affine::prime_subgroup_generator(),
+            g1_powers:     vec![G1Affine::prime_subgroup_generator(); num_g1],
+            g2_powers:     vec![G2Affine::prime_subgroup_generator(); num_g2],
+            bls_signature: None,
+            pubkey_proof:  None,
+        }
+    }
+
+    /// Reasons to reject a degenerate contribution, see [`SanityChecks`].
+    fn sanity_check(&self, checks: SanityChecks, has_pubkey: bool) -> Vec<ContributionError> {
+        let mut errors = Vec::new();
+        errors
+            .extend(positions(&self.g1_powers, Zero::is_zero).map(ContributionError::ZeroG1Power));
+        errors
+            .extend(positions(&self.g2_powers, Zero::is_zero).map(ContributionError::ZeroG2Power));
+        if checks.nontrivial_tau {
+            let g1_trivial = self.g1_powers.get(1) == Some(&G1Affine::prime_subgroup_generator());
+            let pubkey_trivial = has_pubkey
+                && (self.pubkey.is_zero() || self.pubkey == G2Affine::prime_subgroup_generator());
+            if g1_trivial || pubkey_trivial {
+                errors.push(ContributionError::TrivialTau);
+            }
+        }
+        if checks.distinct_powers {
+            errors.extend(
+                duplicates(&self.g1_powers)
+                    .into_iter()
+                    .map(|(i, j)| ContributionError::DuplicateG1Power(i, j)),
+            );
+            errors.extend(
+                duplicates(&self.g2_powers)
+                    .into_iter()
+                    .map(|(i, j)| ContributionError::DuplicateG2Power(i, j)),
+            );
         }
+        errors
     }
 
+    /// Check that the pubkey and all powers are in the prime-order subgroups.
+    ///
+    /// Reports the first point that fails the check.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the first point outside its subgroup.
+    pub fn check_subgroups(&self) -> Result<(), VerifyError> {
+        self.check_subgroups_with_rng(&mut VerificationRng::new())
+    }
+
+    /// [`Self::check_subgroups`] with the G1 checks seeded from `rng`.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`Self::check_subgroups`].
     #[instrument(level = "info", skip_all, fields(n1=self.g1_powers.len(), n2=self.g2_powers.len()))]
-    pub fn subgroup_check(&self) {
-        assert!(self.pubkey.is_in_correct_subgroup_assuming_on_curve());
-        self.g1_powers
-            .par_iter()
-            .for_each(|point| assert!(g1_subgroup_check(point)));
-        self.g2_powers
+    pub fn check_subgroups_with_rng(&self, rng: &mut VerificationRng) -> Result<(), VerifyError> {
+        if !self.pubkey.is_in_correct_subgroup_assuming_on_curve() {
+            return Err(VerifyError::PubkeyNotInSubgroup);
+        }
+        if let Some(i) = find_non_subgroup_point_with_rng(&self.g1_powers, rng) {
+            return Err(VerifyError::G1NotInSubgroup(i));
+        }
+        if let Some(i) = self
+            .g2_powers
             .par_iter()
-            .for_each(|point| assert!(g2_subgroup_check(point)));
+            .position_first(|point| !g2_subgroup_check(point))
+        {
+            return Err(VerifyError::G2NotInSubgroup(i));
+        }
+        Ok(())
     }
 
     #[instrument(level = "info", skip_all)]
-    pub fn add_tau(&mut self, tau: &Fr) {
+    pub fn add_tau(&mut self, tau: &SecretScalar) {
         let n_tau = max(self.g1_powers.len(), self.g2_powers.len());
-        let powers = Self::pow_table(&tau, n_tau);
+        let powers = tau.powers(n_tau);
         self.mul_g1(&powers[0..self.g1_powers.len()]);
         self.mul_g2(&powers[0..self.g2_powers.len()]);
-        self.pubkey = self.pubkey.mul(*tau).into_affine();
+        self.pubkey = mul_secret(&self.pubkey, tau.expose_secret()).into_affine();
+        // A signature or proof for the previous pubkey is no longer valid.
+        self.bls_signature = None;
+        self.pubkey_proof = None;
     }
 
+    /// Bind `identity` to this contribution by signing it with `tau`.
+    ///
+    /// Must be called with the same `tau` as the last [`Self::add_tau`].
+    pub fn sign_identity(&mut self, tau: &SecretScalar, identity: &str) {
+        self.bls_signature = Some(signature::sign_identity(tau, identity));
+    }
+
+    /// Prove knowledge of `tau` for the pubkey, bound to `identity`.
+    ///
+    /// Must be called with the same `tau` as the last [`Self::add_tau`].
+    pub fn prove_possession(&mut self, tau: &SecretScalar, identity: &str) {
+        self.pubkey_proof = Some(PubkeyProof::prove(tau, &self.pubkey, identity));
+    }
+
+    /// Whether the contribution carries a valid proof of knowledge of tau
+    /// for `identity`.
+    ///
+    /// Returns `None` if there is no proof.
+    #[must_use]
     #[instrument(level = "info", skip_all)]
-    fn pow_table(tau: &Fr, n: usize) -> Zeroizing<Vec<Fr>> {
-        let mut powers = Zeroizing::new(Vec::with_capacity(n));
-        let mut pow_tau = Zeroizing::new(Fr::one());
-        powers.push(*pow_tau);
-        for _ in 1..n {
-            *pow_tau *= *tau;
-            powers.push(*pow_tau);
-        }
-        powers
+    pub fn verify_possession(&self, identity: &str) -> Option<bool> {
+        self.pubkey_proof
+            .map(|proof| proof.verify(&self.pubkey, identity))
     }
 
+    /// Whether the contribution carries a valid signature of `identity`.
+    ///
+    /// Returns `None` if there is no signature.
+    #[must_use]
+    #[instrument(level = "info", skip_all)]
+    pub fn verify_identity(&self, identity: &str) -> Option<bool> {
+        self.bls_signature
+            .map(|sig| signature::verify_identity(&self.pubkey, identity, &sig))
+    }
+
+    /// Multiply the powers by the secret `scalars`, in constant time.
     #[instrument(level = "info", skip_all)]
     fn mul_g1(&mut self, scalars: &[Fr]) {
         let projective = self
             .g1_powers
             .par_iter()
             .zip(scalars.par_iter())
-            .map(|(c, pow_tau)| g1_mul_glv(c, *pow_tau))
+            .map(|(c, pow_tau)| mul_secret(c, pow_tau))
             .collect::<Vec<_>>();
         self.g1_powers = G1Projective::batch_normalization_into_affine(&projective[..]);
     }
 
+    /// Multiply the powers by the secret `scalars`, in constant time.
     #[instrument(level = "info", skip_all)]
     fn mul_g2(&mut self, scalars: &[Fr]) {
         let projective = self
             .g2_powers
             .par_iter()
             .zip(scalars.par_iter())
-            .map(|(c, pow_tau)| c.mul(*pow_tau))
+            .map(|(c, pow_tau)| mul_secret(c, pow_tau))
             .collect::<Vec<_>>();
         self.g2_powers = G2Projective::batch_normalization_into_affine(&projective[..]);
     }
 
+    /// Verify that the contribution is a valid update of `transcript`.
+    ///
+    /// Points are assumed to be in the correct subgroups, see
+    /// [`Self::check_subgroups`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of powers differs, the transcript is
+    /// empty, or the powers are inconsistent or not built on its latest
+    /// running product.
     #[instrument(level = "info", skip_all)]
-    pub fn verify(&self, transcript: &Transcript) {
-        assert_eq!(self.g1_powers.len(), transcript.g1_powers.len());
-        assert_eq!(self.g2_powers.len(), transcript.g2_powers.len());
-        self.verify_pubkey(transcript.products.last().unwrap());
-        self.verify_g1();
-        self.verify_g2();
+    pub fn verify(&self, transcript: &Transcript) -> Result<(), VerifyError> {
+        self.verify_with_rng(transcript, &mut VerificationRng::new())
+    }
+
+    /// [`Self::verify`] with the random factors of the power checks drawn
+    /// from `rng`.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`Self::verify`].
+    pub fn verify_with_rng(
+        &self,
+        transcript: &Transcript,
+        rng: &mut VerificationRng,
+    ) -> Result<(), VerifyError> {
+        if self.g1_powers.len() != transcript.g1_powers.len() {
+            return Err(VerifyError::UnexpectedNumG1Powers(
+                transcript.g1_powers.len(),
+                self.g1_powers.len(),
+            ));
+        }
+        if self.g2_powers.len() != transcript.g2_powers.len() {
+            return Err(VerifyError::UnexpectedNumG2Powers(
+                transcript.g2_powers.len(),
+                self.g2_powers.len(),
+            ));
+        }
+        if self.g1_powers.len() < 2 || self.g2_powers.len() < 2 {
+            return Err(VerifyError::TooFewPowers);
+        }
+        let product = transcript
+            .products
+            .last()
+            .ok_or(VerifyError::EmptyTranscript)?;
+        if !self.verify_pubkey(product) {
+            return Err(VerifyError::PubkeyMismatch);
+        }
+        if !self.verify_g1(rng) {
+            return Err(VerifyError::InconsistentG1Powers);
+        }
+        if !self.verify_g2(rng) {
+            return Err(VerifyError::InconsistentG2Powers);
+        }
+        Ok(())
     }
 
     #[instrument(level = "info", skip_all)]
-    fn verify_pubkey(&self, prev_product: &G1Affine) {
-        assert_eq!(
-            Bls12_381::pairing(self.g1_powers[1], G2Affine::prime_subgroup_generator()),
-            Bls12_381::pairing(*prev_product, self.pubkey)
-        );
+    fn verify_pubkey(&self, prev_product: &G1Affine) -> bool {
+        pairing_with_g2_generator(self.g1_powers[1])
+            == Bls12_381::pairing(*prev_product, self.pubkey)
     }
 
     #[instrument(level = "info", skip_all)]
-    fn verify_g1(&self) {
-        let (factors, sum) = random_factors(self.g1_powers.len() - 1);
-        let lhs_g1 = VariableBaseMSM::multi_scalar_mul(&self.g1_powers[1..], &factors[..]);
-        let lhs_g2 = G2Affine::prime_subgroup_generator().mul(sum);
-        let rhs_g1 =
-            VariableBaseMSM::multi_scalar_mul(&self.g1_powers[..factors.len()], &factors[..]);
-        let rhs_g2 = self.g2_powers[1].mul(sum);
-        assert_eq!(
-            Bls12_381::pairing(lhs_g1, lhs_g2),
-            Bls12_381::pairing(rhs_g1, rhs_g2)
-        );
+    fn verify_g1(&self, rng: &mut VerificationRng) -> bool {
+        let (factors, sum) = random_factors(self.g1_powers.len() - 1, rng);
+        let lhs_g1 = DefaultBackend::g1_msm(&self.g1_powers[1..], &factors[..]);
+        let lhs_g2 = fixed_base::g2_generator().mul(&sum);
+        let rhs_g1 = DefaultBackend::g1_msm(&self.g1_powers[..factors.len()], &factors[..]);
+        let rhs_g2 = DefaultBackend::g2_mul(&self.g2_powers[1], &sum);
+        pairings_equal(&lhs_g1, &lhs_g2, &rhs_g1, &rhs_g2)
     }
 
     #[instrument(level = "info", skip_all)]
-    fn verify_g2(&self) {
-        let (factors, sum) = random_factors(self.g2_powers.len());
-        let lhs_g1 =
-            VariableBaseMSM::multi_scalar_mul(&self.g1_powers[..factors.len()], &factors[..]);
-        let lhs_g2 = G2Affine::prime_subgroup_generator().mul(sum);
-        let rhs_g1 = G1Affine::prime_subgroup_generator().mul(sum);
-        let rhs_g2 = VariableBaseMSM::multi_scalar_mul(&self.g2_powers[..], &factors[..]);
-        assert_eq!(
-            Bls12_381::pairing(lhs_g1, lhs_g2),
-            Bls12_381::pairing(rhs_g1, rhs_g2)
-        );
+    fn verify_g2(&self, rng: &mut VerificationRng) -> bool {
+        let (factors, sum) = random_factors(self.g2_powers.len(), rng);
+        let lhs_g1 = DefaultBackend::g1_msm(&self.g1_powers[..factors.len()], &factors[..]);
+        let lhs_g2 = fixed_base::g2_generator().mul(&sum);
+        let rhs_g1 = fixed_base::g1_generator().mul(&sum);
+        let rhs_g2 = DefaultBackend::g2_msm(&self.g2_powers[..], &factors[..]);
+        pairings_equal(&lhs_g1, &lhs_g2, &rhs_g1, &rhs_g2)
     }
 }
 
-fn random_factors(n: usize) -> (Vec<<Fr as PrimeField>::BigInt>, Fr) {
-    let mut rng = rand::thread_rng();
+/// Whether `e(a, b) = e(c, d)`.
+fn pairings_equal(a: &G1Projective, b: &G2Projective, c: &G1Projective, d: &G2Projective) -> bool {
+    let g1 = G1Projective::batch_normalization_into_affine(&[*a, -*c]);
+    let g2 = G2Projective::batch_normalization_into_affine(&[*b, *d]);
+    DefaultBackend::pairing_product_is_one(&[(g1[0], g2[0]), (g1[1], g2[1])])
+}
+
+/// Add a secret to each sub-contribution, processing at most `max_parallel`
+/// sub-contributions at once.
+///
+/// `progress` is called with the index of every sub-contribution as it
+/// completes. The result does not depend on scheduling.
+#[instrument(level = "info", skip_all, fields(n = contributions.len()))]
+pub fn add_tau_batch<F>(
+    contributions: &mut [Contribution],
+    taus: &[SecretScalar],
+    max_parallel: usize,
+    progress: F,
+) where
+    F: Fn(usize) + Sync,
+{
+    assert_eq!(contributions.len(), taus.len());
+    let max_parallel = max(max_parallel, 1);
+    contributions
+        .chunks_mut(max_parallel)
+        .zip(taus.chunks(max_parallel))
+        .enumerate()
+        .for_each(|(chunk, (contributions, taus))| {
+            contributions
+                .par_iter_mut()
+                .zip(taus.par_iter())
+                .enumerate()
+                .for_each(|(i, (contribution, tau))| {
+                    contribution.add_tau(tau);
+                    progress(chunk * max_parallel + i);
+                });
+        });
+}
+
+/// Indices of the items satisfying `predicate`.
+fn positions<'a, T>(
+    items: &'a [T],
+    predicate: impl Fn(&T) -> bool + 'a,
+) -> impl Iterator<Item = usize> + 'a {
+    items
+        .iter()
+        .enumerate()
+        .filter_map(move |(i, item)| predicate(item).then_some(i))
+}
+
+/// Every repeated item, as the index of its first occurrence and the index
+/// of the repetition.
+fn duplicates<T: Eq + Hash>(points: &[T]) -> Vec<(usize, usize)> {
+    let mut seen = HashMap::with_capacity(points.len());
+    points
+        .iter()
+        .enumerate()
+        .filter_map(|(j, point)| {
+            let i = *seen.entry(point).or_insert(j);
+            (i != j).then_some((i, j))
+        })
+        .collect()
+}
+
+/// `n` random scalars drawn from `rng` and their sum.
+fn random_factors(n: usize, rng: &mut VerificationRng) -> (Vec<<Fr as PrimeField>::BigInt>, Fr) {
     let mut sum = Fr::zero();
     let factors = iter::from_fn(|| {
-        let r = Fr::rand(&mut rng);
+        let r = Fr::rand(rng);
         sum += r;
         Some(r.0)
     })
@@ -339,39 +1217,336 @@ fn random_factors(n: usize) -> (Vec<<Fr as PrimeField>::BigInt>, Fr) {
 #[cfg(test)]
 pub mod test {
     use super::*;
-    use ark_ff::UniformRand;
+    use crate::SecretRng;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_sanity_checks() {
+        let initial = ContributionJson::initial(4, 3);
+        assert_eq!(initial.parse(), Err(ContributionError::TrivialTau));
+        assert!(initial.parse_with(SanityChecks::INITIAL).is_ok());
+
+        let mut contribution = Contribution::new(4, 3);
+        contribution.add_tau(&SecretScalar::random(&mut SecretRng::new()));
+        let json = ContributionJson::from(&contribution);
+        assert_eq!(json.parse(), Ok(contribution));
+
+        let mut zero = json.clone();
+        zero.powers_of_tau.g1_powers[2] = format_g(&G1Affine::zero());
+        assert_eq!(zero.parse(), Err(ContributionError::ZeroG1Power(2)));
+        assert_eq!(
+            zero.parse_with(SanityChecks::INITIAL),
+            Err(ContributionError::ZeroG1Power(2))
+        );
+
+        let mut trivial = json.clone();
+        trivial.pot_pubkey = Some(format_g(&G2Affine::prime_subgroup_generator()));
+        assert_eq!(trivial.parse(), Err(ContributionError::TrivialTau));
+
+        let mut duplicate = json;
+        duplicate.powers_of_tau.g2_powers[2] = duplicate.powers_of_tau.g2_powers[1].clone();
+        assert_eq!(
+            duplicate.parse(),
+            Err(ContributionError::DuplicateG2Power(1, 2))
+        );
+        assert!(duplicate
+            .parse_with(SanityChecks {
+                distinct_powers: false,
+                ..SanityChecks::default()
+            })
+            .is_ok());
+    }
+
+    #[test]
+    fn test_parse_exhaustive() {
+        let mut contribution = Contribution::new(8, 3);
+        contribution.add_tau(&SecretScalar::random(&mut SecretRng::new()));
+        let json = ContributionJson::from(&contribution);
+        assert_eq!(
+            json.parse_exhaustive(SanityChecks::default()),
+            Ok(contribution)
+        );
+
+        let mut invalid = json.clone();
+        invalid.powers_of_tau.g1_powers[5] = "0x00".to_string();
+        invalid.powers_of_tau.g1_powers[2].replace_range(2..4, "00");
+        invalid.powers_of_tau.g2_powers[1] = format_g(&G2Affine::zero());
+        invalid.pot_pubkey = Some("0x".to_string());
+        let expected = vec![
+            ContributionError::InvalidG1Power(2, ParseError::NotCompressed),
+            ContributionError::InvalidG1Power(5, ParseError::InvalidLength(98, 4)),
+            ContributionError::InvalidPubKey(ParseError::InvalidLength(194, 2)),
+        ];
+        assert_eq!(
+            invalid.parse_exhaustive(SanityChecks::default()),
+            Err(expected.clone())
+        );
+        assert_eq!(invalid.parse(), Err(expected[0]));
+
+        // Sanity checks run once everything parsed.
+        let mut degenerate = json;
+        degenerate.powers_of_tau.g1_powers[3] = format_g(&G1Affine::zero());
+        degenerate.powers_of_tau.g2_powers[2] = degenerate.powers_of_tau.g2_powers[1].clone();
+        degenerate.powers_of_tau.g1_powers[6] = degenerate.powers_of_tau.g1_powers[4].clone();
+        let mut valid = Contribution::new(4, 2);
+        valid.add_tau(&SecretScalar::random(&mut SecretRng::new()));
+        let contributions = ContributionsJson {
+            sub_contributions: vec![ContributionJson::from(&valid), degenerate],
+            ecdsa_signature:   None,
+        };
+        assert_eq!(
+            contributions.parse_subset_exhaustive(&[(4, 2), (8, 3)], SanityChecks::default()),
+            Err(vec![
+                ContributionsError::InvalidContribution(1, ContributionError::ZeroG1Power(3)),
+                ContributionsError::InvalidContribution(
+                    1,
+                    ContributionError::DuplicateG1Power(4, 6)
+                ),
+                ContributionsError::InvalidContribution(
+                    1,
+                    ContributionError::DuplicateG2Power(1, 2)
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_sign_identity() {
+        let tau = SecretScalar::random(&mut SecretRng::new());
+        let mut contribution = Contribution::new(4, 2);
+        assert_eq!(contribution.verify_identity("git|1234|recmo"), None);
+        contribution.add_tau(&tau);
+        contribution.sign_identity(&tau, "git|1234|recmo");
+        assert_eq!(contribution.verify_identity("git|1234|recmo"), Some(true));
+        assert_eq!(contribution.verify_identity("git|1234|eve"), Some(false));
+        contribution.add_tau(&tau);
+        assert_eq!(contribution.verify_identity("git|1234|recmo"), None);
+    }
+
+    #[test]
+    fn test_prove_possession() {
+        let tau = SecretScalar::random(&mut SecretRng::new());
+        let mut contribution = Contribution::new(4, 2);
+        contribution.add_tau(&tau);
+        assert_eq!(contribution.verify_possession("git|1234|recmo"), None);
+        contribution.prove_possession(&tau, "git|1234|recmo");
+        let parsed = ContributionJson::from(&contribution).parse().unwrap();
+        assert_eq!(parsed, contribution);
+        assert_eq!(parsed.verify_possession("git|1234|recmo"), Some(true));
+        assert_eq!(parsed.verify_possession("git|1234|eve"), Some(false));
+        contribution.add_tau(&tau);
+        assert_eq!(contribution.verify_possession("git|1234|recmo"), None);
+    }
+
+    #[test]
+    fn test_add_tau_batch() {
+        let mut rng = SecretRng::new();
+        let taus = (0..4)
+            .map(|_| SecretScalar::random(&mut rng))
+            .collect::<Vec<_>>();
+        let initial = [(16, 3), (32, 3), (64, 3), (128, 3)]
+            .iter()
+            .map(|(n1, n2)| Contribution::new(*n1, *n2))
+            .collect::<Vec<_>>();
+        let mut expected = initial.clone();
+        for (contribution, tau) in expected.iter_mut().zip(taus.iter()) {
+            contribution.add_tau(tau);
+        }
+        for max_parallel in [0, 1, 3, 4] {
+            let completed = Mutex::new(Vec::new());
+            let mut contributions = initial.clone();
+            add_tau_batch(&mut contributions, &taus, max_parallel, |i| {
+                completed.lock().unwrap().push(i);
+            });
+            assert_eq!(contributions, expected);
+            let mut completed = completed.into_inner().unwrap();
+            completed.sort_unstable();
+            assert_eq!(completed, vec![0, 1, 2, 3]);
+        }
+    }
 
     #[test]
     fn verify() {
-        let mut transcript = Transcript::new(32768, 65);
+        let transcript = Transcript::new(32768, 65);
         let mut contrib = Contribution::new(32768, 65);
-        contrib.verify(&transcript);
-        let mut rng = rand::thread_rng();
-        contrib.add_tau(&Fr::rand(&mut rng));
-        contrib.verify(&transcript);
+        contrib.verify(&transcript).unwrap();
+        let mut rng = SecretRng::new();
+        contrib.add_tau(&SecretScalar::random(&mut rng));
+        contrib.verify(&transcript).unwrap();
+    }
+
+    #[test]
+    fn test_verify_errors() {
+        let transcript = Transcript::new(16, 3);
+        let mut contrib = Contribution::new(16, 3);
+        contrib.add_tau(&SecretScalar::random(&mut SecretRng::new()));
+        contrib.check_subgroups().unwrap();
+        contrib.verify(&transcript).unwrap();
+
+        assert_eq!(
+            Contribution::new(8, 3).verify(&transcript),
+            Err(VerifyError::UnexpectedNumG1Powers(16, 8))
+        );
+        assert_eq!(
+            Contribution::new(16, 1).verify(&Transcript::new(16, 1)),
+            Err(VerifyError::TooFewPowers)
+        );
+        assert_eq!(
+            Transcript::from_contribution(&Contribution::new(1, 2)),
+            Err(VerifyError::TooFewPowers)
+        );
+        let mut tampered = contrib.clone();
+        tampered.pubkey = G2Affine::prime_subgroup_generator();
+        assert_eq!(
+            tampered.verify(&transcript),
+            Err(VerifyError::PubkeyMismatch)
+        );
+        let mut tampered = contrib.clone();
+        tampered.g1_powers.swap(2, 3);
+        assert_eq!(
+            tampered.verify(&transcript),
+            Err(VerifyError::InconsistentG1Powers)
+        );
+        let mut tampered = contrib;
+        tampered.g2_powers[2] = G2Affine::prime_subgroup_generator();
+        assert_eq!(
+            tampered.verify(&transcript),
+            Err(VerifyError::InconsistentG2Powers)
+        );
+    }
+
+    #[test]
+    fn test_verify_transcript() {
+        let mut rng = SecretRng::new();
+        let mut transcript = Transcript::new(16, 3);
+        transcript.verify().unwrap();
+        for _ in 0..3 {
+            let mut contribution = transcript.contribution();
+            contribution.add_tau(&SecretScalar::random(&mut rng));
+            transcript.add(contribution);
+        }
+        transcript.verify().unwrap();
+
+        // Verification randomness can be injected for reproducible runs.
+        let seeded = || VerificationRng::seed_from_u64(42);
+        transcript.verify_with_rng(&mut seeded()).unwrap();
+        assert_eq!(
+            random_factors(4, &mut seeded()),
+            random_factors(4, &mut seeded())
+        );
+
+        let mut tampered = transcript.clone();
+        tampered.pubkeys[2] = tampered.pubkeys[1];
+        assert_eq!(tampered.verify(), Err(VerifyError::ProductMismatch(2)));
+        let mut tampered = transcript.clone();
+        tampered.products[0] = tampered.products[1];
+        assert_eq!(tampered.verify_step(0), Err(VerifyError::InvalidGenesis));
+        let mut tampered = transcript.clone();
+        tampered.products.pop();
+        assert_eq!(tampered.verify(), Err(VerifyError::ChainLength(3, 4)));
+        let mut tampered = transcript;
+        tampered.g1_powers.swap(2, 3);
+        assert_eq!(tampered.verify(), Err(VerifyError::InconsistentG1Powers));
+    }
+
+    #[test]
+    fn test_verify_witness() {
+        let mut rng = SecretRng::new();
+        let mut transcript = Transcript::new(4, 2);
+        for _ in 0..20 {
+            let mut contribution = transcript.contribution();
+            contribution.add_tau(&SecretScalar::random(&mut rng));
+            transcript.add(contribution);
+        }
+        transcript.verify_witness().unwrap();
+
+        // The first invalid step is reported.
+        let mut tampered = transcript.clone();
+        tampered.products.swap(7, 12);
+        assert_eq!(
+            tampered.verify_witness(),
+            Err(VerifyError::ProductMismatch(7))
+        );
+        let mut tampered = transcript.clone();
+        tampered.pubkeys[5] = G2Affine::zero();
+        assert_eq!(
+            tampered.verify_witness(),
+            Err(VerifyError::ProductMismatch(5))
+        );
+        let mut tampered = transcript;
+        tampered.pubkeys[0] = tampered.pubkeys[1];
+        assert_eq!(tampered.verify_witness(), Err(VerifyError::InvalidGenesis));
+    }
+
+    #[test]
+    fn test_parse_subset() {
+        let initial = ContributionsJson::initial();
+        let subset = |indices: &[usize]| ContributionsJson {
+            sub_contributions: indices
+                .iter()
+                .map(|i| initial.sub_contributions[*i].clone())
+                .collect(),
+            ecdsa_signature:   None,
+        };
+        let parsed = subset(&[1])
+            .parse_subset_with(SanityChecks::INITIAL)
+            .unwrap();
+        assert_eq!(parsed.len(), crate::SIZES.len());
+        assert!(parsed[0].is_none() && parsed[2].is_none() && parsed[3].is_none());
+        assert_eq!(parsed[1].as_ref().unwrap().g1_powers.len(), 8192);
+
+        assert_eq!(
+            subset(&[]).parse_subset(),
+            Err(ContributionsError::NoSubContributions)
+        );
+        assert_eq!(
+            subset(&[1, 0]).parse_subset(),
+            Err(ContributionsError::UnexpectedSubCeremony(1))
+        );
+        assert_eq!(
+            subset(&[2, 2]).parse_subset(),
+            Err(ContributionsError::UnexpectedSubCeremony(1))
+        );
+        let mut unknown = subset(&[0]);
+        unknown.sub_contributions[0].num_g2_powers = 3;
+        assert_eq!(
+            unknown.parse_subset(),
+            Err(ContributionsError::UnexpectedSubCeremony(0))
+        );
+
+        let sizes = [(8, 3), (16, 3)];
+        let tiny = ContributionsJson::initial_with_sizes(&sizes);
+        let parsed = tiny
+            .parse_subset_with_sizes(&sizes, SanityChecks::INITIAL)
+            .unwrap();
+        assert_eq!(parsed[1].as_ref().unwrap().g1_powers.len(), 16);
+        assert_eq!(
+            tiny.parse_with(SanityChecks::INITIAL),
+            Err(ContributionsError::InvalidContributionCount(4, 2))
+        );
     }
 }
 
 #[cfg(feature = "bench")]
 #[doc(hidden)]
 pub mod bench {
-    use crate::bench::rand_fr;
+    use crate::{bench::rand_secret, SecretRng};
 
     use super::*;
-    use ark_ff::UniformRand;
     use criterion::{black_box, BatchSize, BenchmarkId, Criterion};
 
     pub fn group(criterion: &mut Criterion) {
         bench_pow_tau(criterion);
         bench_add_tau(criterion);
+        bench_add_tau_batch(criterion);
         bench_verify(criterion);
     }
 
     fn bench_pow_tau(criterion: &mut Criterion) {
         criterion.bench_function("contribution/pow_tau", move |bencher| {
-            let mut rng = rand::thread_rng();
-            let tau = Zeroizing::new(Fr::rand(&mut rng));
-            bencher.iter(|| black_box(Contribution::pow_table(black_box(&tau), 32768)));
+            let mut rng = SecretRng::new();
+            let tau = SecretScalar::random(&mut rng);
+            bencher.iter(|| black_box(black_box(&tau).powers(32768)));
         });
     }
 
@@ -383,7 +1558,7 @@ pub mod bench {
                 move |bencher, (n1, n2)| {
                     let mut contrib = Contribution::new(*n1, *n2);
                     bencher.iter_batched(
-                        || rand_fr(),
+                        rand_secret,
                         |tau| contrib.add_tau(&tau),
                         BatchSize::SmallInput,
                     );
@@ -392,6 +1567,31 @@ pub mod bench {
         }
     }
 
+    fn bench_add_tau_batch(criterion: &mut Criterion) {
+        for max_parallel in [1, 2, 4] {
+            criterion.bench_with_input(
+                BenchmarkId::new("contribution/add_tau_batch", max_parallel),
+                &max_parallel,
+                move |bencher, max_parallel| {
+                    let mut contributions = crate::SIZES
+                        .iter()
+                        .map(|(n1, n2)| Contribution::new(*n1, *n2))
+                        .collect::<Vec<_>>();
+                    bencher.iter_batched(
+                        || {
+                            crate::SIZES
+                                .iter()
+                                .map(|_| rand_secret())
+                                .collect::<Vec<_>>()
+                        },
+                        |taus| add_tau_batch(&mut contributions, &taus, *max_parallel, |_| {}),
+                        BatchSize::SmallInput,
+                    );
+                },
+            );
+        }
+    }
+
     fn bench_verify(criterion: &mut Criterion) {
         for size in crate::SIZES {
             criterion.bench_with_input(
@@ -400,7 +1600,7 @@ pub mod bench {
                 move |bencher, (n1, n2)| {
                     let mut transcript = Transcript::new(*n1, *n2);
                     let mut contrib = Contribution::new(*n1, *n2);
-                    contrib.add_tau(&rand_fr());
+                    contrib.add_tau(&rand_secret());
                     bencher.iter(|| black_box(contrib.verify(&transcript)));
                 },
             );
@@ -9,7 +9,7 @@ use ark_ec::{bls12::Bls12Parameters, AffineCurve, ProjectiveCurve};
 use ark_ff::{field_new, Field, PrimeField, Zero};
 use std::ops::{AddAssign, Neg};
 
-/// is_in_correct_subgroup_assuming_on_curve
+/// `is_in_correct_subgroup_assuming_on_curve`
 #[inline]
 #[must_use]
 pub fn g1_subgroup_check(p: &G1Affine) -> bool {
@@ -48,7 +48,6 @@ pub fn g2_subgroup_check(point: &G2Affine) -> bool {
 }
 
 #[inline]
-#[must_use]
 fn g1_mul_bigint(base: &G1Affine, scalar: &[u64]) -> G1Projective {
     let mut res = G1Projective::zero();
     for b in ark_ff::BitIteratorBE::without_leading_zeros(scalar) {
@@ -61,7 +60,6 @@ fn g1_mul_bigint(base: &G1Affine, scalar: &[u64]) -> G1Projective {
 }
 
 #[inline]
-#[must_use]
 fn g1_mul_bigint_proj(base: &G1Projective, scalar: &[u64]) -> G1Projective {
     let mut res = G1Projective::zero();
     for b in ark_ff::BitIteratorBE::without_leading_zeros(scalar) {
@@ -74,7 +72,6 @@ fn g1_mul_bigint_proj(base: &G1Projective, scalar: &[u64]) -> G1Projective {
 }
 
 #[inline]
-#[must_use]
 fn g2_mul_bigint(base: &G2Affine, scalar: &[u64]) -> G2Projective {
     let mut res = G2Projective::zero();
     for b in ark_ff::BitIteratorBE::without_leading_zeros(scalar) {
@@ -87,7 +84,6 @@ fn g2_mul_bigint(base: &G2Affine, scalar: &[u64]) -> G2Projective {
 }
 
 #[inline]
-#[must_use]
 pub fn g1_endomorphism(p: &G1Affine) -> G1Affine {
     /// BETA is a non-trivial cubic root of unity in Fq.
     const BETA: Fq = field_new!(Fq, "793479390729215512621379701633421447060886740281060493010456487427281649075476305620758731620350");
@@ -95,13 +91,12 @@ pub fn g1_endomorphism(p: &G1Affine) -> G1Affine {
     // Endomorphism of the points on the curve.
     // endomorphism_p(x,y) = (BETA * x, y)
     // where BETA is a non-trivial cubic root of unity in Fq.
-    let mut res = (*p).clone();
+    let mut res = *p;
     res.x *= BETA;
     res
 }
 
 #[inline]
-#[must_use]
 pub fn g2_endomorphism(p: &G2Affine) -> G2Affine {
     // The p-power endomorphism for G2 is defined as follows:
     // 1. Note that G2 is defined on curve E': y^2 = x^3 + 4(u+1).
@@ -132,9 +127,9 @@ pub fn g2_endomorphism(p: &G2Affine) -> G2Affine {
     res.x.frobenius_map(1);
     res.y.frobenius_map(1);
 
-    let tmp_x = res.x.clone();
-    res.x.c0 = -P_POWER_ENDOMORPHISM_COEFF_0_1 * &tmp_x.c1;
-    res.x.c1 = P_POWER_ENDOMORPHISM_COEFF_0_1 * &tmp_x.c0;
+    let tmp_x = res.x;
+    res.x.c0 = -P_POWER_ENDOMORPHISM_COEFF_0_1 * tmp_x.c1;
+    res.x.c1 = P_POWER_ENDOMORPHISM_COEFF_0_1 * tmp_x.c0;
     res.y *= Fq2::new(
         P_POWER_ENDOMORPHISM_COEFF_1_0,
         P_POWER_ENDOMORPHISM_COEFF_1_1,
@@ -143,7 +138,6 @@ pub fn g2_endomorphism(p: &G2Affine) -> G2Affine {
     res
 }
 
-const G1_LAMBDA: u64 = 0xd201_0000_0001_0000;
 const G1_LAMBDA_2: [u64; 2] = [0x0000_0001_0000_0000, 0xac45_a401_0001_a402];
 
 #[inline]
@@ -152,13 +146,15 @@ fn g1_split(tau: Fr) -> (u128, u128) {
     let mut tau = tau.into_repr().0;
     let mut divisor = G1_LAMBDA_2;
     ruint::algorithms::div_rem(&mut tau, &mut divisor);
-    let k0 = (divisor[0] as u128) | (divisor[1] as u128) << 64;
-    let k1 = (tau[0] as u128) | (tau[1] as u128) << 64;
+    let k0 = u128::from(divisor[0]) | u128::from(divisor[1]) << 64;
+    let k1 = u128::from(tau[0]) | u128::from(tau[1]) << 64;
     (k0, k1)
 }
 
 /// Implements scalar-point multiplication using Gallant-Lambert-Vanstone (GLV).
-#[must_use]
+///
+/// Runs in variable time, so `tau` must be public. Secret scalars go through
+/// the constant-time multiplication in [`crate::Contribution::add_tau`].
 pub fn g1_mul_glv(p: &G1Affine, tau: Fr) -> G1Projective {
     let (k0, k1) = g1_split(tau);
 
@@ -240,7 +236,7 @@ pub mod test {
     #[test]
     fn test_g2_endomorphism() {
         proptest!(|(p in arb_g2())| {
-            let value = g2_endomorphism(&p);
+            let _value = g2_endomorphism(&p);
             // TODO: Compute expected value
             // let expected = g2_mul_bigint(&p, &G1_LAMBDA_2).neg().into_affine();
             // assert_eq!(value, expected);
@@ -263,9 +259,13 @@ pub mod test {
 pub mod bench {
     use super::*;
     use crate::bench::{rand_fr, rand_g1, rand_g2};
+    use ark_ff::UniformRand;
     use criterion::{black_box, BatchSize, Criterion};
 
     pub fn group(criterion: &mut Criterion) {
+        bench_fq_mul(criterion);
+        bench_fq_square(criterion);
+        bench_fq2_mul(criterion);
         bench_g1_endo(criterion);
         bench_g1_check(criterion);
         bench_g1_check_endo(criterion);
@@ -278,6 +278,31 @@ pub mod bench {
         bench_g2_mul(criterion);
     }
 
+    // Field arithmetic is where the `asm` feature makes a difference, compare
+    // with `--no-default-features` and with `-C target-feature=+bmi2,+adx`.
+    fn bench_fq_mul(criterion: &mut Criterion) {
+        criterion.bench_function("fq_mul", move |bencher| {
+            let mut rng = rand::thread_rng();
+            let (a, b) = (Fq::rand(&mut rng), Fq::rand(&mut rng));
+            bencher.iter(|| black_box(black_box(a) * black_box(b)));
+        });
+    }
+
+    fn bench_fq_square(criterion: &mut Criterion) {
+        criterion.bench_function("fq_square", move |bencher| {
+            let a = Fq::rand(&mut rand::thread_rng());
+            bencher.iter(|| black_box(black_box(a).square()));
+        });
+    }
+
+    fn bench_fq2_mul(criterion: &mut Criterion) {
+        criterion.bench_function("fq2_mul", move |bencher| {
+            let mut rng = rand::thread_rng();
+            let (a, b) = (Fq2::rand(&mut rng), Fq2::rand(&mut rng));
+            bencher.iter(|| black_box(black_box(a) * black_box(b)));
+        });
+    }
+
     fn bench_g1_endo(criterion: &mut Criterion) {
         criterion.bench_function("g1_endomorphism", move |bencher| {
             bencher.iter_batched(
@@ -0,0 +1,176 @@
+//! Entropy for contribution secrets.
+//!
+//! A contribution is only as good as the secret behind it, so rather than
+//! trusting a single source, [`Entropy`] mixes several:
+//!
+//! * 64 bytes of operating system randomness,
+//! * any strings the contributor provides,
+//! * timing jitter of a busy loop, and
+//! * optionally a round of a public randomness beacon such as [drand](https://drand.love).
+//!   The beacon is public, it only guarantees the secret was not chosen before
+//!   the round.
+//!
+//! Each input is appended to a buffer as
+//! `len(label) || label || len(data) || data`, with the lengths as
+//! little-endian `u64`, so that no two sequences of inputs produce the same
+//! buffer. The secret for sub-contribution `index`
+//! is then derived with HKDF-SHA512 using [`SALT`] as the salt, the buffer as
+//! input key material and `"tau" || index` as the info, and the 64 byte output
+//! is reduced modulo the scalar field order.
+//!
+//! This is the only way besides [`SecretScalar::random`] to obtain a secret.
+//! Only [`Entropy::deterministic`] leaves out the operating system randomness.
+
+use crate::{SecretRng, SecretScalar};
+use hkdf::Hkdf;
+use rand::RngCore;
+use sha2::Sha512;
+use std::{fmt, time::Instant};
+use zeroize::Zeroizing;
+
+/// HKDF salt, versioned so that a change in mixing changes all secrets.
+pub const SALT: &[u8] = b"kzg-ceremony-entropy-v1";
+
+/// Bytes of operating system randomness to use.
+const OS_ENTROPY: usize = 64;
+
+/// Number of timing samples to take.
+const JITTER_SAMPLES: usize = 1024;
+
+/// Accumulated entropy inputs, zeroed on drop.
+#[derive(Clone)]
+pub struct Entropy {
+    buffer: Zeroizing<Vec<u8>>,
+}
+
+impl Entropy {
+    /// Entropy from operating system randomness, timing jitter and `user`.
+    #[must_use]
+    pub fn gather(user: &str) -> Self {
+        let mut entropy = Self::deterministic();
+        entropy.add_os_randomness();
+        entropy.add_jitter();
+        entropy.add_user_input(user);
+        entropy
+    }
+
+    /// Empty entropy, without operating system randomness.
+    ///
+    /// The secrets are only as unpredictable as the inputs added later. This
+    /// is for test ceremonies, which reproduce contributions from `--entropy`.
+    #[must_use]
+    pub fn deterministic() -> Self {
+        Self {
+            buffer: Zeroizing::new(Vec::new()),
+        }
+    }
+
+    pub fn add_os_randomness(&mut self) {
+        let mut bytes = Zeroizing::new([0_u8; OS_ENTROPY]);
+        SecretRng::new().fill_bytes(bytes.as_mut());
+        self.add(b"os", bytes.as_ref());
+    }
+
+    pub fn add_user_input(&mut self, input: &str) {
+        self.add(b"user", input.as_bytes());
+    }
+
+    /// Add the durations of rounds of busy work of varying length, which
+    /// depend on caches, frequency scaling and scheduling.
+    pub fn add_jitter(&mut self) {
+        let mut samples = Zeroizing::new(Vec::with_capacity(JITTER_SAMPLES * 8));
+        let mut state = 0_u64;
+        let mut last = Instant::now();
+        for i in 0..JITTER_SAMPLES {
+            for j in 0..(i % 7 + 1) * 64 {
+                state = state.wrapping_mul(6_364_136_223_846_793_005) ^ j as u64;
+            }
+            // Mix in the state so the busy work can not be optimized away.
+            let now = Instant::now();
+            let sample = u64::from((now - last).subsec_nanos()) ^ state;
+            samples.extend_from_slice(&sample.to_le_bytes());
+            last = now;
+        }
+        self.add(b"jitter", &samples);
+    }
+
+    /// Add a beacon round and its randomness.
+    pub fn add_beacon(&mut self, round: u64, randomness: &[u8]) {
+        let mut data = round.to_le_bytes().to_vec();
+        data.extend_from_slice(randomness);
+        self.add(b"drand", &data);
+    }
+
+    /// Secret for sub-contribution `index`.
+    ///
+    /// # Panics
+    ///
+    /// Never, 64 bytes is a valid HKDF-SHA512 output length.
+    #[must_use]
+    pub fn tau(&self, index: usize) -> SecretScalar {
+        let hkdf = Hkdf::<Sha512>::new(Some(SALT), &self.buffer);
+        let mut info = b"tau".to_vec();
+        info.extend_from_slice(&(index as u64).to_le_bytes());
+        let mut okm = Zeroizing::new([0_u8; 64]);
+        hkdf.expand(&info, okm.as_mut())
+            .expect("64 bytes is a valid HKDF-SHA512 output length");
+        SecretScalar::from_le_bytes_mod_order(okm.as_ref())
+    }
+
+    fn add(&mut self, label: &[u8], data: &[u8]) {
+        for part in [label, data] {
+            self.buffer
+                .extend_from_slice(&(part.len() as u64).to_le_bytes());
+            self.buffer.extend_from_slice(part);
+        }
+    }
+}
+
+impl fmt::Debug for Entropy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Entropy(..)")
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use ark_ff::{BigInteger, PrimeField};
+
+    fn entropy(inputs: &[&str]) -> Entropy {
+        let mut entropy = Entropy::deterministic();
+        for input in inputs {
+            entropy.add_user_input(input);
+        }
+        entropy
+    }
+
+    #[test]
+    fn test_mixing() {
+        let a = entropy(&["hello", "world"]);
+        assert_eq!(a.tau(0), entropy(&["hello", "world"]).tau(0));
+        assert_ne!(a.tau(0), a.tau(1));
+        // Inputs are length prefixed, so boundaries matter.
+        assert_ne!(a.tau(0), entropy(&["hellow", "orld"]).tau(0));
+        assert_ne!(a.tau(0), entropy(&["helloworld"]).tau(0));
+        assert_ne!(a.tau(0), entropy(&["world", "hello"]).tau(0));
+
+        // Labels separate sources with equal data.
+        let mut beacon = Entropy::deterministic();
+        beacon.add_beacon(1, b"hello");
+        assert_ne!(beacon.tau(0), entropy(&["\x01\0\0\0\0\0\0\0hello"]).tau(0));
+
+        // Gathered entropy differs between calls.
+        assert_ne!(Entropy::gather("").tau(0), Entropy::gather("").tau(0));
+    }
+
+    /// Computed independently from the description in the module docs.
+    #[test]
+    fn test_vector() {
+        let tau = entropy(&["kzg"]).tau(3);
+        assert_eq!(
+            hex::encode(tau.expose_secret().into_repr().to_bytes_le()),
+            "1eb44e1345fe0651713cce3a532b0947ec439f31657130747eaf6c36cd2ed22f"
+        );
+    }
+}
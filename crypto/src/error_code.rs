@@ -0,0 +1,111 @@
+//! Machine readable errors.
+//!
+//! Parse and verification errors serialize to an object with a stable
+//! `snake_case` code, the display message and the variant's fields:
+//!
+//! ```json
+//! {
+//!   "code": "invalid_contribution",
+//!   "message": "Error in contribution 1: Error parsing G1 power 5: Point is not compressed",
+//!   "index": 1,
+//!   "cause": {
+//!     "code": "invalid_g1_power",
+//!     "message": "Error parsing G1 power 5: Point is not compressed",
+//!     "index": 5,
+//!     "cause": { "code": "not_compressed", "message": "Point is not compressed" }
+//!   }
+//! }
+//! ```
+//!
+//! Clients should match on codes and fields; messages may change.
+
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use std::error::Error;
+
+/// Error with a stable identifier.
+pub trait ErrorCode: Error {
+    /// Identifier of the error variant, unique within the error type.
+    fn code(&self) -> &'static str;
+}
+
+/// Serialize `error` with the given fields and optional cause.
+pub fn serialize_error<S, E, C>(
+    serializer: S,
+    error: &E,
+    fields: &[(&str, usize)],
+    cause: Option<&C>,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    E: ErrorCode,
+    C: Serialize,
+{
+    let mut map = serializer.serialize_map(None)?;
+    map.serialize_entry("code", error.code())?;
+    map.serialize_entry("message", &error.to_string())?;
+    for (key, value) in fields {
+        map.serialize_entry(key, value)?;
+    }
+    if let Some(cause) = cause {
+        map.serialize_entry("cause", cause)?;
+    }
+    map.end()
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{BatchVerifyError, ContributionError, ContributionsError, ParseError, VerifyError};
+    use serde_json::json;
+
+    #[test]
+    fn test_serialize() {
+        let error = ContributionsError::InvalidContribution(
+            1,
+            ContributionError::InvalidG1Power(5, ParseError::NotCompressed),
+        );
+        assert_eq!(
+            serde_json::to_value(error).unwrap(),
+            json!({
+                "code": "invalid_contribution",
+                "message": "Error in contribution 1: Error parsing G1 power 5: Point is not compressed",
+                "index": 1,
+                "cause": {
+                    "code": "invalid_g1_power",
+                    "message": "Error parsing G1 power 5: Point is not compressed",
+                    "index": 5,
+                    "cause": { "code": "not_compressed", "message": "Point is not compressed" }
+                }
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(ContributionError::DuplicateG2Power(1, 3)).unwrap(),
+            json!({
+                "code": "duplicate_g2_power",
+                "message": "G2 powers 1 and 3 are equal",
+                "first": 1,
+                "second": 3,
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(BatchVerifyError::VerificationFailed(
+                2,
+                VerifyError::G2NotInSubgroup(7)
+            ))
+            .unwrap(),
+            json!({
+                "code": "verification_failed",
+                "message": "Contribution 2 failed verification: G2 power 7 is not in the prime-order subgroup",
+                "index": 2,
+                "cause": {
+                    "code": "g2_not_in_subgroup",
+                    "message": "G2 power 7 is not in the prime-order subgroup",
+                    "index": 7,
+                }
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(ParseError::InvalidLength(98, 4)).unwrap()["expected"],
+            98
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! Precomputed tables for the generators.
+//!
+//! Every verification multiplies the G1 and G2 generators by fresh random
+//! scalars and pairs points with the G2 generator. The generators are the
+//! only bases shared between verifications: the MSM bases are the powers of
+//! the contribution under test, which change with every contribution. The
+//! tables are built on first use and kept for the lifetime of the process.
+//!
+//! Table lookups depend on the scalar, so [`FixedBase::mul`] is not constant
+//! time and must only be used with public scalars, never with tau.
+
+use ark_bls12_381::{Bls12_381, Fr, G1Affine, G1Projective, G2Affine, G2Projective};
+use ark_ec::{msm::FixedBaseMSM, AffineCurve, PairingEngine, ProjectiveCurve};
+use ark_ff::{FpParameters, PrimeField};
+use once_cell::sync::OnceCell;
+
+/// Bits per window. Tables hold `2^WINDOW` points per window, so multiples
+/// cost one addition per window.
+const WINDOW: usize = 8;
+
+const SCALAR_BITS: usize = <Fr as PrimeField>::Params::MODULUS_BITS as usize;
+
+/// Windowed multiples of a fixed base.
+#[derive(Clone, Debug)]
+pub struct FixedBase<G: ProjectiveCurve> {
+    table: Vec<Vec<G::Affine>>,
+}
+
+impl<G: ProjectiveCurve<ScalarField = Fr>> FixedBase<G> {
+    #[must_use]
+    pub fn new(base: G) -> Self {
+        Self {
+            table: FixedBaseMSM::get_window_table(SCALAR_BITS, WINDOW, base),
+        }
+    }
+
+    /// `scalar` times the base. Not constant time.
+    #[must_use]
+    pub fn mul(&self, scalar: &Fr) -> G {
+        let windows = (SCALAR_BITS + WINDOW - 1) / WINDOW;
+        FixedBaseMSM::windowed_mul(windows, WINDOW, &self.table, scalar)
+    }
+}
+
+/// Table for the G1 generator.
+pub fn g1_generator() -> &'static FixedBase<G1Projective> {
+    static TABLE: OnceCell<FixedBase<G1Projective>> = OnceCell::new();
+    TABLE.get_or_init(|| FixedBase::new(G1Affine::prime_subgroup_generator().into_projective()))
+}
+
+/// Table for the G2 generator.
+pub fn g2_generator() -> &'static FixedBase<G2Projective> {
+    static TABLE: OnceCell<FixedBase<G2Projective>> = OnceCell::new();
+    TABLE.get_or_init(|| FixedBase::new(G2Affine::prime_subgroup_generator().into_projective()))
+}
+
+/// The G2 generator prepared for pairings.
+pub fn g2_generator_prepared() -> &'static <Bls12_381 as PairingEngine>::G2Prepared {
+    static PREPARED: OnceCell<<Bls12_381 as PairingEngine>::G2Prepared> = OnceCell::new();
+    PREPARED.get_or_init(|| G2Affine::prime_subgroup_generator().into())
+}
+
+/// `e(p, G2)`, using the prepared generator.
+#[must_use]
+pub fn pairing_with_g2_generator(p: G1Affine) -> <Bls12_381 as PairingEngine>::Fqk {
+    let pair = (p.into(), g2_generator_prepared().clone());
+    Bls12_381::final_exponentiation(&Bls12_381::miller_loop(&[pair]))
+        .expect("Final exponentiation of a pairing is defined")
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test::{arb_fr, arb_g1};
+    use proptest::proptest;
+
+    #[test]
+    fn test_generator_mul() {
+        proptest!(|(s in arb_fr())| {
+            assert_eq!(
+                g1_generator().mul(&s),
+                G1Affine::prime_subgroup_generator().mul(s)
+            );
+            assert_eq!(
+                g2_generator().mul(&s),
+                G2Affine::prime_subgroup_generator().mul(s)
+            );
+        });
+    }
+
+    #[test]
+    fn test_pairing() {
+        proptest!(|(p in arb_g1())| {
+            assert_eq!(
+                pairing_with_g2_generator(p),
+                Bls12_381::pairing(p, G2Affine::prime_subgroup_generator())
+            );
+        });
+    }
+}
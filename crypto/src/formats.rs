@@ -0,0 +1,200 @@
+//! Transcript and contribution formats of other ceremony implementations.
+//!
+//! * [`SequencerTranscriptJson`] is the `transcript.json` of the reference
+//!   sequencer, also read by py-kzg-ceremony. It is a [`BatchTranscriptJson`]
+//!   with the participant id and ECDSA signature of every contribution, the
+//!   first entry being the empty generator contribution. Every contribution
+//!   covers all sub-ceremonies.
+//! * [`ZkpartyContributionsJson`] is the contribution format of the zkparty
+//!   client. It is a [`ContributionsJson`] with `contributions` in place of
+//!   `subContributions` and without proofs of knowledge.
+//!
+//! Conversions keep the points as they are, so the result is exactly as valid
+//! as the input.
+
+use crate::{BatchTranscriptJson, ContributionJson, ContributionsJson, TranscriptJson};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum FormatError {
+    #[error("Transcript {0} has {2} contributions, expected {1}")]
+    WitnessLength(usize, usize, usize),
+    #[error("Expected {0} ECDSA signatures, got {1}")]
+    EcdsaSignatureCount(usize, usize),
+}
+
+/// The reference sequencer's `transcript.json`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SequencerTranscriptJson {
+    pub transcripts:                  Vec<TranscriptJson>,
+    pub participant_ids:              Vec<String>,
+    /// Empty strings for contributions without signature.
+    pub participant_ecdsa_signatures: Vec<String>,
+}
+
+/// A contribution in the zkparty client's format.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ZkpartyContributionsJson {
+    pub contributions:   Vec<ContributionJson>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ecdsa_signature: Option<String>,
+}
+
+impl SequencerTranscriptJson {
+    /// Export a transcript with the ids of its contributors.
+    ///
+    /// `participant_ids` has an entry for every contribution including the
+    /// generators, which is the empty string. ECDSA signatures are not part
+    /// of a [`BatchTranscriptJson`] and exported as empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a witness does not have an entry per id.
+    pub fn from_batch(
+        batch: BatchTranscriptJson,
+        participant_ids: Vec<String>,
+    ) -> Result<Self, FormatError> {
+        check_witness_lengths(&batch.transcripts, participant_ids.len())?;
+        Ok(Self {
+            transcripts: batch.transcripts,
+            participant_ecdsa_signatures: vec![String::new(); participant_ids.len()],
+            participant_ids,
+        })
+    }
+
+    /// Import the transcript and the ids of its contributors.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of signatures or witness entries does
+    /// not match the number of ids.
+    pub fn into_batch(self) -> Result<(BatchTranscriptJson, Vec<String>), FormatError> {
+        let len = self.participant_ids.len();
+        if self.participant_ecdsa_signatures.len() != len {
+            return Err(FormatError::EcdsaSignatureCount(
+                len,
+                self.participant_ecdsa_signatures.len(),
+            ));
+        }
+        check_witness_lengths(&self.transcripts, len)?;
+        Ok((
+            BatchTranscriptJson {
+                transcripts: self.transcripts,
+            },
+            self.participant_ids,
+        ))
+    }
+}
+
+fn check_witness_lengths(transcripts: &[TranscriptJson], len: usize) -> Result<(), FormatError> {
+    transcripts
+        .iter()
+        .enumerate()
+        .try_for_each(|(i, transcript)| {
+            let got = transcript.witness.pot_pubkeys.len();
+            if got == len {
+                Ok(())
+            } else {
+                Err(FormatError::WitnessLength(i, len, got))
+            }
+        })
+}
+
+impl From<ContributionsJson> for ZkpartyContributionsJson {
+    /// Drops the proofs of knowledge, which the zkparty format has no field
+    /// for.
+    fn from(contributions: ContributionsJson) -> Self {
+        Self {
+            contributions:   contributions
+                .sub_contributions
+                .into_iter()
+                .map(|contribution| ContributionJson {
+                    pot_pubkey_proof: None,
+                    ..contribution
+                })
+                .collect(),
+            ecdsa_signature: contributions.ecdsa_signature,
+        }
+    }
+}
+
+impl From<ZkpartyContributionsJson> for ContributionsJson {
+    fn from(contributions: ZkpartyContributionsJson) -> Self {
+        Self {
+            sub_contributions: contributions.contributions,
+            ecdsa_signature:   contributions.ecdsa_signature,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{BatchTranscript, SecretRng, SecretScalar};
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_sequencer_transcript() {
+        let mut transcript = BatchTranscript::new(&[(4, 2), (8, 2)]);
+        let contributions = transcript
+            .transcripts
+            .iter()
+            .map(|sub| {
+                let mut contribution = sub.contribution();
+                contribution.add_tau(&SecretScalar::random(&mut SecretRng::new()));
+                Some(contribution)
+            })
+            .collect();
+        transcript.add(contributions);
+        let batch = transcript.to_json();
+        let ids = vec![String::new(), "git|1234|example".to_string()];
+
+        let exported = SequencerTranscriptJson::from_batch(batch.clone(), ids.clone()).unwrap();
+        let value = serde_json::to_value(&exported).unwrap();
+        assert_eq!(value["participantIds"], json!(["", "git|1234|example"]));
+        assert_eq!(value["participantEcdsaSignatures"], json!(["", ""]));
+        assert!(value["transcripts"][1]["powersOfTau"]["G1Powers"].is_array());
+        let imported: SequencerTranscriptJson = serde_json::from_value(value).unwrap();
+        assert_eq!(imported.into_batch(), Ok((batch.clone(), ids)));
+
+        assert_eq!(
+            SequencerTranscriptJson::from_batch(batch.clone(), vec![String::new()]),
+            Err(FormatError::WitnessLength(0, 1, 2))
+        );
+        let mut uneven = batch;
+        uneven.transcripts[1].witness.pot_pubkeys.pop();
+        let uneven = SequencerTranscriptJson {
+            transcripts:                  uneven.transcripts,
+            participant_ids:              vec![String::new(); 2],
+            participant_ecdsa_signatures: vec![String::new(); 2],
+        };
+        assert_eq!(
+            uneven.clone().into_batch(),
+            Err(FormatError::WitnessLength(1, 2, 1))
+        );
+        let missing = SequencerTranscriptJson {
+            participant_ecdsa_signatures: vec![],
+            ..uneven
+        };
+        assert_eq!(
+            missing.into_batch(),
+            Err(FormatError::EcdsaSignatureCount(2, 0))
+        );
+    }
+
+    #[test]
+    fn test_zkparty_contributions() {
+        let mut contributions = ContributionsJson::initial();
+        contributions.ecdsa_signature = Some("0x1234".to_string());
+        let exported = ZkpartyContributionsJson::from(contributions.clone());
+        let value = serde_json::to_value(&exported).unwrap();
+        assert_eq!(value["ecdsaSignature"], "0x1234");
+        assert_eq!(value["contributions"][0]["numG1Powers"], 4096);
+        assert_eq!(value.get("subContributions"), None::<&Value>);
+        let imported: ZkpartyContributionsJson = serde_json::from_value(value).unwrap();
+        assert_eq!(ContributionsJson::from(imported), contributions);
+    }
+}
@@ -0,0 +1,167 @@
+//! Fuzzing entry points and corpus seeds.
+//!
+//! The parsers face untrusted input from the internet, so they are fuzzed
+//! with `cargo fuzz` from `crypto/fuzz`. The targets there only call the
+//! functions in this module, which are enabled with the `fuzz` feature and
+//! also run over the seeds in the tests.
+//!
+//! Seeds are derived from a contribution file in the format of the
+//! specification, e.g. its `initialContribution.json`. Full contributions are
+//! megabytes, so the seeds keep only the first few powers of each
+//! sub-contribution.
+
+use crate::{format_g, parse_g, ContributionsJson, Sqrt};
+use ark_bls12_381::{g1, g2};
+use ark_ec::models::SWModelParameters;
+
+/// Number of powers kept per sub-contribution in seeds.
+const SEED_POWERS: usize = 2;
+
+/// Parse `data` as a G1 and as a G2 point.
+///
+/// Panics if a point parses but does not encode back to the same string,
+/// ignoring the case of the hex digits.
+pub fn parse_points(data: &[u8]) {
+    if let Ok(hex) = std::str::from_utf8(data) {
+        round_trip::<g1::Parameters>(hex);
+        round_trip::<g2::Parameters>(hex);
+    }
+}
+
+/// Parse `data` as a contribution, in full and as a subset.
+pub fn parse_contributions(data: &[u8]) {
+    if let Ok(json) = serde_json::from_slice::<ContributionsJson>(data) {
+        let _ = json.parse();
+        let _ = json.parse_subset();
+        for contribution in &json.sub_contributions {
+            let _ = contribution.parse();
+        }
+    }
+}
+
+/// Seeds for [`parse_points`], every distinct point in `contributions`.
+#[must_use]
+pub fn point_seeds(contributions: &ContributionsJson) -> Vec<Vec<u8>> {
+    let mut seeds = Vec::new();
+    for contribution in &shrink(contributions).sub_contributions {
+        let points = contribution
+            .powers_of_tau
+            .g1_powers
+            .iter()
+            .chain(&contribution.powers_of_tau.g2_powers)
+            .chain(&contribution.pot_pubkey)
+            .chain(&contribution.bls_signature);
+        for point in points {
+            let seed = point.as_bytes().to_vec();
+            if !seeds.contains(&seed) {
+                seeds.push(seed);
+            }
+        }
+    }
+    seeds
+}
+
+/// Seeds for [`parse_contributions`], `contributions` with all and with each
+/// single sub-contribution, shrunk.
+///
+/// # Panics
+///
+/// Never, contributions always serialize to JSON.
+#[must_use]
+pub fn contribution_seeds(contributions: &ContributionsJson) -> Vec<Vec<u8>> {
+    let shrunk = shrink(contributions);
+    let mut seeds = vec![serde_json::to_vec(&shrunk).expect("Contributions serialize")];
+    for contribution in &shrunk.sub_contributions {
+        let single = ContributionsJson {
+            sub_contributions: vec![contribution.clone()],
+            ecdsa_signature:   shrunk.ecdsa_signature.clone(),
+        };
+        seeds.push(serde_json::to_vec(&single).expect("Contributions serialize"));
+    }
+    seeds
+}
+
+fn round_trip<P: SWModelParameters>(hex: &str)
+where
+    P::BaseField: Sqrt,
+{
+    if let Ok(point) = parse_g::<P>(hex) {
+        assert_eq!(
+            format_g::<P>(&point),
+            hex.to_ascii_lowercase(),
+            "Point does not round trip"
+        );
+    }
+}
+
+/// Keep only the first [`SEED_POWERS`] powers of every sub-contribution.
+fn shrink(contributions: &ContributionsJson) -> ContributionsJson {
+    ContributionsJson {
+        sub_contributions: contributions
+            .sub_contributions
+            .iter()
+            .map(|contribution| {
+                let mut contribution = contribution.clone();
+                let powers = &mut contribution.powers_of_tau;
+                powers.g1_powers.truncate(SEED_POWERS);
+                powers.g2_powers.truncate(SEED_POWERS);
+                contribution.num_g1_powers = powers.g1_powers.len();
+                contribution.num_g2_powers = powers.g2_powers.len();
+                contribution
+            })
+            .collect(),
+        ecdsa_signature:   contributions.ecdsa_signature.clone(),
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{testing::arb_contribution, SIZES};
+    use proptest::{
+        strategy::{Strategy, ValueTree},
+        test_runner::TestRunner,
+    };
+
+    #[test]
+    fn test_seeds() {
+        let initial = ContributionsJson::initial();
+        // The generators, the same in every sub-contribution.
+        assert_eq!(point_seeds(&initial).len(), 2);
+        let seeds = contribution_seeds(&initial);
+        assert_eq!(seeds.len(), SIZES.len() + 1);
+        assert!(seeds.iter().all(|seed| seed.len() < 4096));
+        for seed in seeds {
+            parse_contributions(&seed);
+        }
+
+        let mut runner = TestRunner::deterministic();
+        let contribution = arb_contribution(8, 3)
+            .new_tree(&mut runner)
+            .unwrap()
+            .current();
+        let contributions = ContributionsJson {
+            sub_contributions: vec![contribution],
+            ecdsa_signature:   None,
+        };
+        for seed in point_seeds(&contributions) {
+            parse_points(&seed);
+            parse_points(&seed.to_ascii_uppercase());
+        }
+    }
+
+    #[test]
+    fn test_garbage() {
+        for data in [
+            &b""[..],
+            b"0x",
+            b"0xc0",
+            b"\xff\xfe",
+            br#"{"subContributions": [{}]}"#,
+            br#"{"subContributions": []}"#,
+        ] {
+            parse_points(data);
+            parse_contributions(data);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! KZG polynomial commitments on the ceremony output.
+//!
+//! A commitment to `p(x) = Σ c_i x^i` is `[p(tau)]_1 = Σ c_i [tau^i]_1`, an
+//! MSM over the G1 powers. An opening at `z` to `y = p(z)` is the commitment
+//! to the quotient `q(x) = (p(x) - y) / (x - z)` and is checked with
+//!
+//! ```text
+//! e(C - [y]_1 + z π, G2) = e(π, [tau]_2)
+//! ```
+//!
+//! This is not meant for production use, but to check end to end that the
+//! powers of a transcript make a working SRS.
+
+use crate::{msm, pairing_check::PairingCheck, Transcript};
+use ark_bls12_381::{Fr, G1Affine, G2Affine};
+use ark_ec::{AffineCurve, ProjectiveCurve};
+use ark_ff::{PrimeField, UniformRand, Zero};
+use rand::Rng;
+use thiserror::Error;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum KzgError {
+    #[error("Polynomial has {0} coefficients, but the SRS supports at most {1}")]
+    DegreeTooLarge(usize, usize),
+    #[error("SRS needs at least two G2 powers")]
+    TooFewG2Powers,
+}
+
+/// Structured reference string of the powers of tau.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Kzg<'a> {
+    g1_powers: &'a [G1Affine],
+    g2_tau:    G2Affine,
+}
+
+impl<'a> Kzg<'a> {
+    /// The SRS of the given powers.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are fewer than two G2 powers.
+    pub fn new(g1_powers: &'a [G1Affine], g2_powers: &[G2Affine]) -> Result<Self, KzgError> {
+        let g2_tau = *g2_powers.get(1).ok_or(KzgError::TooFewG2Powers)?;
+        Ok(Self { g1_powers, g2_tau })
+    }
+
+    /// The SRS of the current powers of `transcript`.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`Self::new`].
+    pub fn from_transcript(transcript: &'a Transcript) -> Result<Self, KzgError> {
+        Self::new(&transcript.g1_powers, &transcript.g2_powers)
+    }
+
+    /// Commit to the polynomial with the given coefficients, lowest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the polynomial has more coefficients than there are
+    /// G1 powers.
+    pub fn commit(&self, polynomial: &[Fr]) -> Result<G1Affine, KzgError> {
+        if polynomial.len() > self.g1_powers.len() {
+            return Err(KzgError::DegreeTooLarge(
+                polynomial.len(),
+                self.g1_powers.len(),
+            ));
+        }
+        let scalars = polynomial
+            .iter()
+            .map(PrimeField::into_repr)
+            .collect::<Vec<_>>();
+        Ok(msm(&self.g1_powers[..polynomial.len()], &scalars).into_affine())
+    }
+
+    /// Evaluate the polynomial at `z` and prove the value.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`Self::commit`].
+    pub fn open(&self, polynomial: &[Fr], z: Fr) -> Result<(Fr, G1Affine), KzgError> {
+        // Synthetic division by `x - z`, the remainder is `p(z)`.
+        let mut quotient = vec![Fr::zero(); polynomial.len().saturating_sub(1)];
+        let mut value = Fr::zero();
+        for (i, coefficient) in polynomial.iter().enumerate().rev() {
+            if i < quotient.len() {
+                quotient[i] = value;
+            }
+            value = value * z + coefficient;
+        }
+        Ok((value, self.commit(&quotient)?))
+    }
+
+    /// Check a proof that the committed polynomial evaluates to `value` at
+    /// `z`.
+    #[must_use]
+    pub fn verify(&self, commitment: &G1Affine, z: Fr, value: Fr, proof: &G1Affine) -> bool {
+        let generator = G1Affine::prime_subgroup_generator();
+        let lhs = commitment.into_projective() - generator.mul(value) + proof.mul(z);
+        let mut check = PairingCheck::default();
+        check.add(&lhs.into_affine(), proof, &self.g2_tau);
+        check.verify()
+    }
+
+    /// Commit to a random polynomial of maximal degree, open it at a random
+    /// point and check that the opening verifies and a wrong value does not.
+    ///
+    /// # Panics
+    ///
+    /// Never, the polynomial fits the SRS by construction.
+    pub fn smoke_test<R: Rng>(&self, rng: &mut R) -> bool {
+        let polynomial = (0..self.g1_powers.len())
+            .map(|_| Fr::rand(rng))
+            .collect::<Vec<_>>();
+        let z = Fr::rand(rng);
+        let commitment = self.commit(&polynomial).expect("Polynomial fits the SRS");
+        let (value, proof) = self.open(&polynomial, z).expect("Polynomial fits the SRS");
+        self.verify(&commitment, z, value, &proof)
+            && !self.verify(&commitment, z, value + Fr::from(1_u64), &proof)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{SecretRng, SecretScalar};
+
+    #[test]
+    fn test_kzg() {
+        let mut rng = SecretRng::new();
+        let mut transcript = Transcript::new(16, 2);
+        let mut contribution = transcript.contribution();
+        contribution.add_tau(&SecretScalar::random(&mut rng));
+        transcript.add(contribution);
+        let kzg = Kzg::from_transcript(&transcript).unwrap();
+        assert!(kzg.smoke_test(&mut rng));
+
+        // p(x) = 3 + 2x + x^2, p(2) = 11
+        let polynomial = [3_u64, 2, 1].map(Fr::from);
+        let commitment = kzg.commit(&polynomial).unwrap();
+        let (value, proof) = kzg.open(&polynomial, Fr::from(2_u64)).unwrap();
+        assert_eq!(value, Fr::from(11_u64));
+        assert!(kzg.verify(&commitment, Fr::from(2_u64), value, &proof));
+        assert!(!kzg.verify(&commitment, Fr::from(3_u64), value, &proof));
+        assert_eq!(
+            kzg.commit(&[Fr::zero(); 17]),
+            Err(KzgError::DegreeTooLarge(17, 16))
+        );
+        assert_eq!(
+            Kzg::new(&transcript.g1_powers, &transcript.g2_powers[..1]),
+            Err(KzgError::TooFewG2Powers)
+        );
+
+        // G2 power inconsistent with the G1 powers.
+        let g2_powers = [transcript.g2_powers[0], transcript.g2_powers[0]];
+        let kzg = Kzg::new(&transcript.g1_powers, &g2_powers).unwrap();
+        assert!(!kzg.smoke_test(&mut rng));
+    }
+}
@@ -0,0 +1,186 @@
+//! Lagrange basis of the powers of tau.
+//!
+//! EIP-4844 commits to blobs in evaluation form, so consumers of the SRS need
+//! `[L_i(tau)]_1` for the Lagrange polynomials `L_i` over the `n`-th roots of
+//! unity `w^i`. These are the inverse discrete Fourier transform of the
+//! monomial powers `[tau^j]_1`.
+
+use crate::g1_mul_glv;
+use ark_bls12_381::{Fr, G1Affine, G1Projective};
+use ark_ec::{AffineCurve, ProjectiveCurve};
+use ark_ff::{FftField, Field, One};
+use rayon::prelude::*;
+use tracing::instrument;
+
+/// The G1 powers of tau in the Lagrange basis over the roots of unity of
+/// order `powers.len()`, in natural order.
+///
+/// This is a radix-2 FFT, `n log n / 2` scalar multiplications for `n`
+/// powers. Points are normalized to affine after every layer, which costs a
+/// single inversion and makes the multiplications cheaper.
+///
+/// # Panics
+///
+/// Panics if the number of powers is not a power of two up to `2^32`.
+#[must_use]
+#[instrument(level = "info", skip_all, fields(n = powers.len()))]
+pub fn lagrange_g1(powers: &[G1Affine]) -> Vec<G1Affine> {
+    let n = powers.len();
+    assert!(
+        n.is_power_of_two(),
+        "Number of powers must be a power of two"
+    );
+    let omega = Fr::get_root_of_unity(n).expect("Fr has roots of unity up to order 2^32");
+    let omega_inv = omega.inverse().unwrap();
+    let twiddles = (0..n / 2)
+        .scan(Fr::one(), |twiddle, _| {
+            let current = *twiddle;
+            *twiddle *= omega_inv;
+            Some(current)
+        })
+        .collect::<Vec<_>>();
+
+    let mut values = powers.to_vec();
+    bit_reverse(&mut values);
+    let mut layer = vec![G1Projective::default(); n];
+    let mut size = 2;
+    while size <= n {
+        butterflies(&values, &mut layer, size, &twiddles);
+        values = G1Projective::batch_normalization_into_affine(&layer);
+        size *= 2;
+    }
+    let n_inv = Fr::from(n as u64).inverse().unwrap();
+    layer
+        .par_iter_mut()
+        .zip(values.par_iter())
+        .for_each(|(scaled, value)| *scaled = g1_mul_glv(value, n_inv));
+    G1Projective::batch_normalization_into_affine(&layer)
+}
+
+/// Permute `values` to bit-reversed order.
+fn bit_reverse<T>(values: &mut [T]) {
+    let n = values.len();
+    if n <= 1 {
+        return;
+    }
+    let shift = usize::BITS - n.trailing_zeros();
+    for i in 0..n {
+        let j = i.reverse_bits() >> shift;
+        if i < j {
+            values.swap(i, j);
+        }
+    }
+}
+
+/// One FFT layer combining pairs of transforms of length `size / 2` in
+/// `values` into transforms of length `size` in `out`.
+fn butterflies(values: &[G1Affine], out: &mut [G1Projective], size: usize, twiddles: &[Fr]) {
+    let half = size / 2;
+    let stride = twiddles.len() / half;
+    out.par_chunks_mut(size)
+        .zip(values.par_chunks(size))
+        .for_each(|(out, chunk)| {
+            let (low, high) = chunk.split_at(half);
+            let (out_low, out_high) = out.split_at_mut(half);
+            out_low
+                .par_iter_mut()
+                .zip(out_high.par_iter_mut())
+                .enumerate()
+                .for_each(|(k, (sum, difference))| {
+                    let product = if k == 0 {
+                        high[0].into_projective()
+                    } else {
+                        g1_mul_glv(&high[k], twiddles[k * stride])
+                    };
+                    *sum = product.add_mixed(&low[k]);
+                    *difference = (-product).add_mixed(&low[k]);
+                });
+        });
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{test::arb_g1, Contribution, SecretScalar};
+    use ark_ff::{PrimeField, UniformRand, Zero};
+    use proptest::{collection::vec, proptest, test_runner::Config};
+
+    /// The naive transform is quadratic, and slow in debug builds.
+    const CASES: u32 = 8;
+
+    /// Inverse DFT by direct evaluation.
+    fn lagrange_naive(points: &[G1Affine]) -> Vec<G1Affine> {
+        let n = points.len();
+        let omega_inv = Fr::get_root_of_unity(n).unwrap().inverse().unwrap();
+        let n_inv = Fr::from(n as u64).inverse().unwrap();
+        (0..n)
+            .map(|i| {
+                points
+                    .iter()
+                    .enumerate()
+                    .fold(G1Projective::zero(), |sum, (j, point)| {
+                        let scalar = n_inv * omega_inv.pow([(i * j) as u64]);
+                        sum + point.mul(scalar.into_repr())
+                    })
+                    .into_affine()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lagrange_naive() {
+        for log_n in 0..=4 {
+            proptest!(Config::with_cases(CASES), |(points in vec(arb_g1(), 1 << log_n))| {
+                assert_eq!(lagrange_g1(&points), lagrange_naive(&points));
+            });
+        }
+    }
+
+    #[test]
+    fn test_lagrange_g1() {
+        let mut rng = rand::thread_rng();
+        let tau = Fr::rand(&mut rng);
+        let mut contribution = Contribution::new(8, 2);
+        contribution.add_tau(&SecretScalar::new(tau));
+        let lagrange = lagrange_g1(&contribution.g1_powers);
+
+        // L_i(tau) = w^i (tau^n - 1) / (n (tau - w^i))
+        let n = 8_u64;
+        let omega = Fr::get_root_of_unity(8).unwrap();
+        let generator = G1Affine::prime_subgroup_generator();
+        let mut root = Fr::one();
+        for point in &lagrange {
+            let value = root * (tau.pow([n]) - Fr::one()) / (Fr::from(n) * (tau - root));
+            assert_eq!(*point, generator.mul(value.into_repr()).into_affine());
+            root *= omega;
+        }
+
+        // Sum of the Lagrange polynomials is one.
+        let sum = lagrange
+            .iter()
+            .fold(G1Projective::zero(), ProjectiveCurve::add_mixed);
+        assert_eq!(sum.into_affine(), generator);
+        assert_eq!(lagrange_g1(&[generator]), vec![generator]);
+    }
+}
+
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench {
+    use super::*;
+    use crate::bench::rand_g1;
+    use criterion::{black_box, BenchmarkId, Criterion};
+
+    pub fn group(criterion: &mut Criterion) {
+        bench_lagrange_g1(criterion);
+    }
+
+    fn bench_lagrange_g1(criterion: &mut Criterion) {
+        for (n1, _) in crate::SIZES {
+            let points = (0..n1).map(|_| rand_g1()).collect::<Vec<_>>();
+            criterion.bench_with_input(BenchmarkId::new("lagrange_g1", n1), &n1, |bencher, _| {
+                bencher.iter(|| black_box(lagrange_g1(black_box(&points))));
+            });
+        }
+    }
+}
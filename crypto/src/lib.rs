@@ -1,15 +1,83 @@
 #![doc = include_str!("../Readme.md")]
 #![warn(clippy::all, clippy::pedantic, clippy::cargo, clippy::nursery)]
 #![cfg_attr(any(test, feature = "bench"), allow(clippy::wildcard_imports))]
+// Spans are banned from async code, see `clippy.toml`, and this crate has none.
+#![allow(clippy::disallowed_methods)]
 
+mod backend;
+mod batch_check;
+mod batch_transcript;
+#[cfg(feature = "blst")]
+pub mod blst;
+mod constant_time;
 mod contribution;
 mod crypto;
+mod entropy;
+mod error_code;
+mod fixed_base;
+mod formats;
+#[cfg(any(test, feature = "fuzz"))]
+pub mod fuzz;
+mod kzg;
+mod lagrange;
+mod msm;
+mod pairing_check;
+mod pok;
+mod rng;
+mod secret;
+mod signature;
+mod sqrt;
+#[cfg(test)]
+mod test_vectors;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 mod zcash_format;
 
-pub use contribution::{Contribution, ContributionError, ContributionsError, Transcript};
-pub use crypto::{g1_subgroup_check, g2_subgroup_check};
-pub use zcash_format::{parse_g, ParseError};
+pub use backend::{
+    g1_subgroup_check, g2_subgroup_check, Arkworks, CurveBackend, DefaultBackend, ScalarInt,
+};
+pub use batch_check::{
+    batch_subgroup_check, batch_subgroup_check_with_rng, find_non_subgroup_point,
+    find_non_subgroup_point_with_rng, find_non_subgroup_points, find_non_subgroup_points_with_rng,
+    parse_g1_batch, parse_g1_batch_with_rng, InvalidPoints,
+};
+pub use batch_transcript::{
+    BatchTranscript, BatchTranscriptJson, BatchVerifyError, TranscriptError, TranscriptJson,
+    TranscriptsError, WitnessJson,
+};
+pub use contribution::{
+    add_tau_batch, Contribution, ContributionError, ContributionJson, ContributionsError,
+    ContributionsJson, PowersOfTau, SanityChecks, Transcript, VerifyError,
+};
+pub use crypto::g1_mul_glv;
+pub use entropy::Entropy;
+pub use error_code::ErrorCode;
+pub use formats::{FormatError, SequencerTranscriptJson, ZkpartyContributionsJson};
+pub use kzg::{Kzg, KzgError};
+pub use lagrange::lagrange_g1;
+pub use msm::{msm, msm_with_window, window_size};
+pub use pok::{PubkeyProof, PubkeyProofJson, NONCE_DST, POK_DST};
+pub use rng::{SecretRng, VerificationRng};
+pub use secret::SecretScalar;
+pub use signature::{hash_to_g1, sign_identity, verify_identity, BLS_DST};
+pub use sqrt::{sqrt_fq, sqrt_fq2, Sqrt};
+pub use zcash_format::{
+    format_g, parse_g, parse_g_bytes, parse_g_bytes_unchecked, parse_g_unchecked, ParseError,
+};
+
+/// Whether field multiplications use the arkworks assembly backend.
+///
+/// Requires the `asm` feature and a build for a target with BMI2 and ADX, e.g.
+/// `RUSTFLAGS="-C target-feature=+bmi2,+adx"` or `-C target-cpu=native`.
+/// Otherwise the portable Rust implementation is used.
+pub const ASM: bool = cfg!(all(
+    feature = "asm",
+    target_arch = "x86_64",
+    target_feature = "bmi2",
+    target_feature = "adx"
+));
 
+/// Number of G1 and G2 powers of each sub-ceremony.
 pub const SIZES: [(usize, usize); 4] = [(4096, 65), (8192, 65), (16384, 65), (32768, 65)];
 
 #[cfg(test)]
@@ -20,6 +88,9 @@ pub mod test {
     use proptest::{arbitrary::any, strategy::Strategy};
     use ruint::aliases::U256;
 
+    /// # Panics
+    ///
+    /// Never, `n` is reduced below the modulus first.
     pub fn arb_fr() -> impl Strategy<Value = Fr> {
         any::<U256>().prop_map(|mut n| {
             n %= U256::from(FrParameters::MODULUS);
@@ -50,6 +121,11 @@ pub mod bench {
         Fr::rand(&mut rng)
     }
 
+    #[must_use]
+    pub fn rand_secret() -> SecretScalar {
+        SecretScalar::random(&mut SecretRng::new())
+    }
+
     pub fn rand_g1() -> G1Affine {
         G1Affine::prime_subgroup_generator()
             .mul(rand_fr())
@@ -64,7 +140,11 @@ pub mod bench {
 
     pub fn group(criterion: &mut Criterion) {
         crypto::bench::group(criterion);
+        batch_check::bench::group(criterion);
         zcash_format::bench::group(criterion);
+        sqrt::bench::group(criterion);
         contribution::bench::group(criterion);
+        msm::bench::group(criterion);
+        lagrange::bench::group(criterion);
     }
 }
@@ -0,0 +1,242 @@
+//! Pippenger multi-scalar multiplication.
+//!
+//! Same algorithm as arkworks' `VariableBaseMSM`, but with a tunable window
+//! size and parallelism over both windows and chunks of points. Arkworks only
+//! parallelizes over windows, which leaves cores idle when there are fewer
+//! windows than threads.
+
+use ark_ec::{AffineCurve, ProjectiveCurve};
+use ark_ff::{BigInteger, FpParameters, PrimeField, Zero};
+use rayon::prelude::*;
+use std::cmp::max;
+
+type BigInt<G> = <<G as AffineCurve>::ScalarField as PrimeField>::BigInt;
+
+/// Compute `sum_i scalars[i] * bases[i]` using a heuristic window size.
+pub fn msm<G: AffineCurve>(bases: &[G], scalars: &[BigInt<G>]) -> G::Projective {
+    msm_with_window(bases, scalars, window_size(bases.len()))
+}
+
+/// Heuristic window size for an MSM over `n` points.
+///
+/// Bucket accumulation costs about `n` additions per window and the bucket
+/// sums `2^c` additions, so `c` is chosen around `log2(n) - log2(log2(n))`.
+#[must_use]
+pub fn window_size(n: usize) -> usize {
+    if n < 32 {
+        return 3;
+    }
+    let log_n = log2(n);
+    max(log_n - log2(log_n) + 1, 3)
+}
+
+/// Number of bits required to represent `n`.
+const fn log2(n: usize) -> usize {
+    (usize::BITS - n.leading_zeros()) as usize
+}
+
+/// Compute `sum_i scalars[i] * bases[i]` using windows of `window` bits.
+///
+/// # Panics
+///
+/// Panics if `window` is zero or larger than 31.
+pub fn msm_with_window<G: AffineCurve>(
+    bases: &[G],
+    scalars: &[BigInt<G>],
+    window: usize,
+) -> G::Projective {
+    assert!(window > 0 && window < 32, "Invalid window size {window}");
+    let n = bases.len().min(scalars.len());
+    if n == 0 {
+        return G::Projective::zero();
+    }
+    let num_bits = <G::ScalarField as PrimeField>::Params::MODULUS_BITS as usize;
+    let num_windows = (num_bits + window - 1) / window;
+
+    // Split points in chunks so there are at least as many tasks as threads.
+    let chunks = max(rayon::current_num_threads() / num_windows, 1);
+    let chunk_size = max((n + chunks - 1) / chunks, 1);
+
+    let window_sums = (0..num_windows)
+        .into_par_iter()
+        .map(|w| {
+            bases[..n]
+                .par_chunks(chunk_size)
+                .zip(scalars[..n].par_chunks(chunk_size))
+                .map(|(bases, scalars)| window_sum(bases, scalars, w * window, window))
+                .reduce(G::Projective::zero, |a, b| a + b)
+        })
+        .collect::<Vec<_>>();
+
+    // Combine windows from the highest down, doubling in between.
+    window_sums
+        .into_iter()
+        .rev()
+        .fold(G::Projective::zero(), |mut total, sum| {
+            for _ in 0..window {
+                total.double_in_place();
+            }
+            total + sum
+        })
+}
+
+/// Sum of `digit * base` for the `window` bits of the scalars at `offset`.
+fn window_sum<G: AffineCurve>(
+    bases: &[G],
+    scalars: &[BigInt<G>],
+    offset: usize,
+    window: usize,
+) -> G::Projective {
+    let mut buckets = vec![G::Projective::zero(); (1 << window) - 1];
+    for (base, scalar) in bases.iter().zip(scalars) {
+        let digit = digit(scalar, offset, window);
+        if digit != 0 {
+            buckets[digit - 1].add_assign_mixed(base);
+        }
+    }
+    // sum_d d * bucket[d] via running sums.
+    let mut running = G::Projective::zero();
+    let mut sum = G::Projective::zero();
+    for bucket in buckets.into_iter().rev() {
+        running += bucket;
+        sum += running;
+    }
+    sum
+}
+
+/// Extract `window` bits starting at bit `offset` of `scalar`.
+#[allow(clippy::cast_possible_truncation)] // Masked to less than 32 bits
+fn digit<B: BigInteger>(scalar: &B, offset: usize, window: usize) -> usize {
+    let limbs = scalar.as_ref();
+    let limb = offset / 64;
+    let shift = offset % 64;
+    if limb >= limbs.len() {
+        return 0;
+    }
+    let mut bits = limbs[limb] >> shift;
+    if shift + window > 64 && limb + 1 < limbs.len() {
+        bits |= limbs[limb + 1] << (64 - shift);
+    }
+    (bits & ((1 << window) - 1)) as usize
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use ark_bls12_381::{Fr, G1Affine, G2Affine};
+    use ark_ec::msm::VariableBaseMSM;
+    use ark_ff::UniformRand;
+
+    #[test]
+    fn test_window_size() {
+        assert_eq!(window_size(0), 3);
+        assert_eq!(window_size(64), 5);
+        assert_eq!(window_size(4096), 10);
+        assert_eq!(window_size(32768), 12);
+    }
+
+    fn check_msm<G: AffineCurve<ScalarField = Fr>>(n: usize) {
+        let mut rng = rand::thread_rng();
+        let bases = (0..n)
+            .map(|_| {
+                G::prime_subgroup_generator()
+                    .mul(Fr::rand(&mut rng))
+                    .into_affine()
+            })
+            .collect::<Vec<_>>();
+        let scalars = (0..n)
+            .map(|_| Fr::rand(&mut rng).into_repr())
+            .collect::<Vec<_>>();
+        let expected = VariableBaseMSM::multi_scalar_mul(&bases, &scalars);
+        assert_eq!(msm(&bases, &scalars), expected);
+        for window in [1, 2, 5, 8, 13] {
+            assert_eq!(msm_with_window(&bases, &scalars, window), expected);
+        }
+    }
+
+    #[test]
+    fn test_msm_g1() {
+        for n in [0, 1, 7, 40] {
+            check_msm::<G1Affine>(n);
+        }
+    }
+
+    #[test]
+    fn test_msm_g2() {
+        for n in [1, 7] {
+            check_msm::<G2Affine>(n);
+        }
+    }
+
+    #[test]
+    fn test_msm_identity() {
+        let bases = vec![G1Affine::prime_subgroup_generator(); 3];
+        let one = <Fr as PrimeField>::BigInt::from(1);
+        assert_eq!(
+            msm(&bases, &[one; 3]),
+            G1Affine::prime_subgroup_generator().mul(3_u64)
+        );
+        assert!(msm::<G2Affine>(&[], &[]).is_zero());
+    }
+}
+
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench {
+    use super::*;
+    use crate::bench::{rand_fr, rand_g1, rand_g2};
+    use ark_ec::msm::VariableBaseMSM;
+    use criterion::{black_box, BenchmarkId, Criterion};
+
+    pub fn group(criterion: &mut Criterion) {
+        bench_msm_g1(criterion);
+        bench_msm_g2(criterion);
+    }
+
+    fn bench_msm_g1(criterion: &mut Criterion) {
+        for (n1, _) in crate::SIZES {
+            let bases = (0..n1).map(|_| rand_g1()).collect::<Vec<_>>();
+            let scalars = (0..n1).map(|_| rand_fr().into_repr()).collect::<Vec<_>>();
+            criterion.bench_with_input(BenchmarkId::new("msm/ark/g1", n1), &n1, |bencher, _| {
+                bencher.iter(|| {
+                    black_box(VariableBaseMSM::multi_scalar_mul(
+                        black_box(&bases),
+                        black_box(&scalars),
+                    ))
+                });
+            });
+            for window in [window_size(n1) - 1, window_size(n1), window_size(n1) + 1] {
+                criterion.bench_with_input(
+                    BenchmarkId::new(format!("msm/pippenger/g1/{n1}"), window),
+                    &window,
+                    |bencher, window| {
+                        bencher.iter(|| {
+                            black_box(msm_with_window(
+                                black_box(&bases),
+                                black_box(&scalars),
+                                *window,
+                            ))
+                        });
+                    },
+                );
+            }
+        }
+    }
+
+    fn bench_msm_g2(criterion: &mut Criterion) {
+        let n2 = crate::SIZES[0].1;
+        let bases = (0..n2).map(|_| rand_g2()).collect::<Vec<_>>();
+        let scalars = (0..n2).map(|_| rand_fr().into_repr()).collect::<Vec<_>>();
+        criterion.bench_function("msm/ark/g2", |bencher| {
+            bencher.iter(|| {
+                black_box(VariableBaseMSM::multi_scalar_mul(
+                    black_box(&bases),
+                    black_box(&scalars),
+                ))
+            });
+        });
+        criterion.bench_function("msm/pippenger/g2", |bencher| {
+            bencher.iter(|| black_box(msm(black_box(&bases), black_box(&scalars))));
+        });
+    }
+}
@@ -0,0 +1,120 @@
+//! Batched pairing equations.
+//!
+//! Checking `e(a_i, G2) = e(b_i, c_i)` for many `i` separately costs two
+//! pairings each. Instead, every equation is scaled by a random factor `r_i`
+//! and their product is checked:
+//!
+//! ```text
+//! e(-Σ r_i a_i, G2) · Π e(r_i b_i, c_i) = 1
+//! ```
+//!
+//! The left sides share the G2 generator, so they collapse into a single
+//! pairing. What remains is one Miller loop per equation and a single final
+//! exponentiation. If any equation does not hold, the product is one with
+//! probability at most `1/r`, the order of the scalar field.
+//!
+//! Points are assumed to be in the prime order subgroups. The pairings are
+//! computed by the [`DefaultBackend`].
+
+use crate::{CurveBackend, DefaultBackend, VerificationRng};
+use ark_bls12_381::{Fr, G1Affine, G1Projective, G2Affine};
+use ark_ec::{AffineCurve, ProjectiveCurve};
+use ark_ff::UniformRand;
+
+/// Accumulates equations `e(a, G2) = e(b, c)` to check them at once.
+#[derive(Clone, Debug, Default)]
+pub struct PairingCheck {
+    /// Sum of the scaled left sides, paired with the G2 generator.
+    generator: G1Projective,
+    /// Scaled right sides.
+    pairs:     Vec<(G1Projective, G2Affine)>,
+}
+
+impl PairingCheck {
+    /// Add the equation `e(a, G2) = e(b, c)`.
+    pub fn add(&mut self, a: &G1Affine, b: &G1Affine, c: &G2Affine) {
+        self.add_with_rng(a, b, c, &mut VerificationRng::new());
+    }
+
+    /// [`Self::add`] with the random factor drawn from `rng`.
+    pub fn add_with_rng(
+        &mut self,
+        a: &G1Affine,
+        b: &G1Affine,
+        c: &G2Affine,
+        rng: &mut VerificationRng,
+    ) {
+        self.add_scaled(Fr::rand(rng), a, b, c);
+    }
+
+    /// Add the equation scaled by `r`, which must be unpredictable to whoever
+    /// chose the points.
+    pub(crate) fn add_scaled(&mut self, r: Fr, a: &G1Affine, b: &G1Affine, c: &G2Affine) {
+        self.generator += a.mul(r);
+        self.pairs.push((b.mul(r), *c));
+    }
+
+    /// Combine with the equations of `other`.
+    #[must_use]
+    pub fn merge(mut self, other: Self) -> Self {
+        self.generator += other.generator;
+        self.pairs.extend(other.pairs);
+        self
+    }
+
+    /// Whether all equations hold, except with negligible probability.
+    #[must_use]
+    pub fn verify(&self) -> bool {
+        let g1 = self.pairs.iter().map(|(b, _)| *b).collect::<Vec<_>>();
+        let g1 = G1Projective::batch_normalization_into_affine(&g1);
+        let generator = (-self.generator).into_affine();
+        let pairs = g1
+            .into_iter()
+            .zip(self.pairs.iter().map(|(_, c)| *c))
+            .chain([(generator, G2Affine::prime_subgroup_generator())])
+            .collect::<Vec<_>>();
+        DefaultBackend::pairing_product_is_one(&pairs)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test::arb_fr;
+    use proptest::proptest;
+
+    #[test]
+    fn test_pairing_check() {
+        proptest!(|(x in arb_fr(), y in arb_fr())| {
+            let g1 = G1Affine::prime_subgroup_generator();
+            let g2 = G2Affine::prime_subgroup_generator();
+            let a = g1.mul(x * y).into_affine();
+            let b = g1.mul(x).into_affine();
+            let c = g2.mul(y).into_affine();
+
+            let mut check = PairingCheck::default();
+            assert!(check.verify());
+            check.add(&a, &b, &c);
+            check.add(&b, &g1, &g2.mul(x).into_affine());
+            assert!(check.verify());
+
+            let mut other = PairingCheck::default();
+            other.add(&a, &g1, &c);
+            assert_eq!(check.merge(other).verify(), y == x * y);
+        });
+    }
+
+    #[test]
+    fn test_add_with_rng() {
+        let g1 = G1Affine::prime_subgroup_generator();
+        let g2 = G2Affine::prime_subgroup_generator();
+        let checks = [1, 1, 2].map(|seed| {
+            let mut check = PairingCheck::default();
+            check.add_with_rng(&g1, &g1, &g2, &mut VerificationRng::seed_from_u64(seed));
+            check
+        });
+        assert_eq!(checks[0].generator, checks[1].generator);
+        assert_ne!(checks[0].generator, checks[2].generator);
+        assert!(checks.iter().all(PairingCheck::verify));
+    }
+}
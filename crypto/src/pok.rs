@@ -0,0 +1,172 @@
+//! Proof of knowledge of tau for a `potPubkey`.
+//!
+//! Without it, a contributor could publish `pubkey = tau * G2 - previous` for
+//! some other contributor's pubkey and claim a contribution it cannot open.
+//! The proof is a Schnorr proof in G2, made non-interactive with Fiat-Shamir
+//! and bound to the contributor's identity:
+//!
+//! * Prover: derive `k = H_k(tau, pubkey, identity)`, set `R = k * G2`, `c =
+//!   H(pubkey, R, identity)` and `s = k + c * tau`.
+//! * Verifier: check `s * G2 == R + c * pubkey`.
+//!
+//! `H` is SHA-512 over [`POK_DST`], the compressed `pubkey` and `R` and the
+//! identity, reduced modulo the scalar field order. `H_k` is SHA-512 over
+//! [`NONCE_DST`], tau as 32 little-endian bytes, the compressed `pubkey` and
+//! the identity, likewise reduced. Like RFC 6979 the nonce is deterministic,
+//! so it is as secret as tau and no RNG, seeded or not, can leak it.
+
+use crate::{
+    constant_time::mul_secret, fixed_base, format_g, parse_g, zcash_format::parse_hex, ParseError,
+    SecretScalar,
+};
+use ark_bls12_381::{g2, Fr, G2Affine};
+use ark_ec::{AffineCurve, ProjectiveCurve};
+use ark_ff::{BigInteger, BigInteger256, PrimeField, Zero};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+use zeroize::Zeroizing;
+
+/// Domain separation tag for the Fiat-Shamir challenge.
+pub const POK_DST: &[u8] = b"KZG_CEREMONY_POT_PUBKEY_POK_V1";
+
+/// Domain separation tag for the nonce.
+pub const NONCE_DST: &[u8] = b"KZG_CEREMONY_POT_PUBKEY_POK_NONCE_V1";
+
+/// Schnorr proof of knowledge of tau for `pubkey = tau * G2`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PubkeyProof {
+    pub commitment: G2Affine,
+    pub response:   Fr,
+}
+
+/// JSON encoding of a [`PubkeyProof`]: the commitment as a compressed G2
+/// point and the response as a 32 byte big-endian integer.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct PubkeyProofJson {
+    pub commitment: String,
+    pub response:   String,
+}
+
+impl PubkeyProof {
+    /// Prove knowledge of `tau` for `pubkey = tau * G2`, bound to `identity`.
+    #[must_use]
+    pub fn prove(tau: &SecretScalar, pubkey: &G2Affine, identity: &str) -> Self {
+        let nonce = nonce(tau, pubkey, identity);
+        let commitment =
+            mul_secret(&G2Affine::prime_subgroup_generator(), nonce.expose_secret()).into_affine();
+        let challenge = challenge(pubkey, &commitment, identity);
+        let response = Zeroizing::new(*nonce.expose_secret() + challenge * tau.expose_secret());
+        Self {
+            commitment,
+            response: *response,
+        }
+    }
+
+    /// Check the proof for `pubkey` and `identity`.
+    #[must_use]
+    pub fn verify(&self, pubkey: &G2Affine, identity: &str) -> bool {
+        if pubkey.is_zero() {
+            return false;
+        }
+        let challenge = challenge(pubkey, &self.commitment, identity);
+        let lhs = fixed_base::g2_generator().mul(&self.response);
+        let rhs = self.commitment.into_projective() + pubkey.mul(challenge);
+        lhs == rhs
+    }
+}
+
+impl From<&PubkeyProof> for PubkeyProofJson {
+    fn from(proof: &PubkeyProof) -> Self {
+        Self {
+            commitment: format_g(&proof.commitment),
+            response:   format!(
+                "0x{}",
+                hex::encode(proof.response.into_repr().to_bytes_be())
+            ),
+        }
+    }
+}
+
+impl TryFrom<&PubkeyProofJson> for PubkeyProof {
+    type Error = ParseError;
+
+    fn try_from(json: &PubkeyProofJson) -> Result<Self, Self::Error> {
+        let commitment = parse_g::<g2::Parameters>(&json.commitment)?;
+        let mut bytes = [0_u8; 32];
+        parse_hex(&json.response, &mut bytes)?;
+        bytes.reverse();
+        let mut limbs = [0_u64; 4];
+        for (limb, chunk) in limbs.iter_mut().zip(bytes.chunks_exact(8)) {
+            *limb = u64::from_le_bytes(chunk.try_into().unwrap());
+        }
+        let response = Fr::from_repr(BigInteger256(limbs)).ok_or(ParseError::InvalidScalar)?;
+        Ok(Self {
+            commitment,
+            response,
+        })
+    }
+}
+
+fn nonce(tau: &SecretScalar, pubkey: &G2Affine, identity: &str) -> SecretScalar {
+    let tau = Zeroizing::new(tau.expose_secret().into_repr().to_bytes_le());
+    let mut hash = Zeroizing::new([0_u8; 64]);
+    hash.copy_from_slice(
+        &Sha512::new()
+            .chain(NONCE_DST)
+            .chain(tau.as_slice())
+            .chain(format_g(pubkey).as_bytes())
+            .chain(identity.as_bytes())
+            .finalize(),
+    );
+    SecretScalar::from_le_bytes_mod_order(hash.as_ref())
+}
+
+fn challenge(pubkey: &G2Affine, commitment: &G2Affine, identity: &str) -> Fr {
+    let hash = Sha512::new()
+        .chain(POK_DST)
+        .chain(format_g(pubkey).as_bytes())
+        .chain(format_g(commitment).as_bytes())
+        .chain(identity.as_bytes())
+        .finalize();
+    Fr::from_le_bytes_mod_order(&hash)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::SecretRng;
+
+    #[test]
+    fn test_prove_verify() {
+        let mut rng = SecretRng::new();
+        let tau = SecretScalar::random(&mut rng);
+        let pubkey = G2Affine::prime_subgroup_generator()
+            .mul(*tau.expose_secret())
+            .into_affine();
+        let proof = PubkeyProof::prove(&tau, &pubkey, "git|1234|recmo");
+        assert!(proof.verify(&pubkey, "git|1234|recmo"));
+        assert!(!proof.verify(&pubkey, "git|1234|eve"));
+
+        // The nonce is derived from tau and the message.
+        assert_eq!(PubkeyProof::prove(&tau, &pubkey, "git|1234|recmo"), proof);
+        let other_proof = PubkeyProof::prove(&tau, &pubkey, "git|1234|eve");
+        assert_ne!(other_proof.commitment, proof.commitment);
+
+        // A pubkey derived from another one by subtraction can not be proven.
+        let other = G2Affine::prime_subgroup_generator()
+            .mul(*SecretScalar::random(&mut rng).expose_secret())
+            .into_affine();
+        let forged = (pubkey.into_projective() - other.into_projective()).into_affine();
+        assert!(!proof.verify(&forged, "git|1234|recmo"));
+        assert!(!proof.verify(&G2Affine::zero(), "git|1234|recmo"));
+
+        let json = PubkeyProofJson::from(&proof);
+        assert_eq!(PubkeyProof::try_from(&json), Ok(proof));
+        let mut invalid = json;
+        invalid.response = format!("0x{}", "ff".repeat(32));
+        assert_eq!(
+            PubkeyProof::try_from(&invalid),
+            Err(ParseError::InvalidScalar)
+        );
+    }
+}
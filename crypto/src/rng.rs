@@ -0,0 +1,124 @@
+//! Sources of randomness.
+//!
+//! Secrets and verification need randomness with different properties:
+//!
+//! * [`SecretRng`] draws from the operating system and can not be seeded. It is
+//!   the only RNG [`SecretScalar::random`] accepts, so tau can not be drawn
+//!   from a test seed by accident.
+//! * [`VerificationRng`] draws the random factors of batched checks. These only
+//!   need to be unpredictable to whoever chose the checked points, so it is a
+//!   fast seedable CSPRNG, seeded per check. Tests seed it deterministically.
+//!
+//! [`SecretScalar::random`]: crate::SecretScalar::random
+
+use rand::{
+    rngs::{OsRng, StdRng},
+    CryptoRng, Error, RngCore, SeedableRng,
+};
+
+/// Operating system randomness, for secrets.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SecretRng(OsRng);
+
+/// Seedable randomness, for the random factors of verification.
+#[derive(Clone, Debug)]
+pub struct VerificationRng(StdRng);
+
+impl SecretRng {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(OsRng)
+    }
+}
+
+impl RngCore for SecretRng {
+    fn next_u32(&mut self) -> u32 {
+        self.0.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill_bytes(dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.0.try_fill_bytes(dest)
+    }
+}
+
+impl CryptoRng for SecretRng {}
+
+impl VerificationRng {
+    /// Seeded from the thread RNG.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the thread RNG can not be seeded from the operating system.
+    /// Drawing from the seeded thread RNG does not fail.
+    #[must_use]
+    pub fn new() -> Self {
+        Self(StdRng::from_rng(rand::thread_rng()).expect("Thread RNG does not fail"))
+    }
+
+    #[must_use]
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        Self(StdRng::from_seed(seed))
+    }
+
+    /// Deterministic randomness for tests.
+    #[must_use]
+    pub fn seed_from_u64(seed: u64) -> Self {
+        Self(StdRng::seed_from_u64(seed))
+    }
+}
+
+impl Default for VerificationRng {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RngCore for VerificationRng {
+    fn next_u32(&mut self) -> u32 {
+        self.0.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill_bytes(dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.0.try_fill_bytes(dest)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_verification_rng() {
+        let draw = |mut rng: VerificationRng| rng.gen::<[u8; 32]>();
+        assert_eq!(
+            draw(VerificationRng::seed_from_u64(1)),
+            draw(VerificationRng::seed_from_u64(1))
+        );
+        assert_ne!(
+            draw(VerificationRng::seed_from_u64(1)),
+            draw(VerificationRng::seed_from_u64(2))
+        );
+        assert_ne!(draw(VerificationRng::new()), draw(VerificationRng::new()));
+        assert_ne!(
+            SecretRng::new().gen::<[u8; 32]>(),
+            SecretRng::new().gen::<[u8; 32]>()
+        );
+    }
+}
@@ -0,0 +1,105 @@
+//! Secret scalars.
+//!
+//! The contribution secret tau must not outlive the contribution. It is held
+//! in a [`SecretScalar`], which zeroes the scalar on drop, is not `Copy`, has
+//! a redacted `Debug` and no serialization, so it cannot end up in logs or
+//! uploads by accident. Copies made on the stack inside the curve arithmetic
+//! are out of reach.
+
+use crate::SecretRng;
+use ark_bls12_381::Fr;
+use ark_ff::{One, PrimeField, UniformRand};
+use std::fmt;
+use zeroize::Zeroizing;
+
+/// A secret scalar such as tau, zeroed on drop.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SecretScalar(Zeroizing<Fr>);
+
+impl SecretScalar {
+    /// Take ownership of a secret. Prefer the other constructors, which do not
+    /// leave a copy with the caller.
+    #[must_use]
+    pub(crate) fn new(value: Fr) -> Self {
+        Self(Zeroizing::new(value))
+    }
+
+    /// Draw a fresh secret from operating system randomness.
+    #[must_use]
+    pub fn random(rng: &mut SecretRng) -> Self {
+        Self::new(Fr::rand(rng))
+    }
+
+    /// Reduce uniform bytes, e.g. a KDF output, modulo the field order.
+    #[must_use]
+    pub(crate) fn from_le_bytes_mod_order(bytes: &[u8]) -> Self {
+        Self::new(Fr::from_le_bytes_mod_order(bytes))
+    }
+
+    /// Access the scalar for arithmetic. Do not store copies of it.
+    #[must_use]
+    pub fn expose_secret(&self) -> &Fr {
+        &self.0
+    }
+
+    /// The powers `tau^0, tau^1, ..., tau^(n-1)`.
+    #[must_use]
+    pub fn powers(&self, n: usize) -> Zeroizing<Vec<Fr>> {
+        let mut powers = Zeroizing::new(Vec::with_capacity(n));
+        let mut pow_tau = Zeroizing::new(Fr::one());
+        if n > 0 {
+            powers.push(*pow_tau);
+        }
+        for _ in 1..n {
+            *pow_tau *= *self.0;
+            powers.push(*pow_tau);
+        }
+        powers
+    }
+}
+
+impl fmt::Debug for SecretScalar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretScalar(..)")
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{Contribution, ContributionJson};
+    use ark_ff::BigInteger;
+
+    #[test]
+    fn test_powers() {
+        let tau = SecretScalar::new(Fr::from(3_u64));
+        assert_eq!(*tau.powers(4), vec![
+            Fr::from(1_u64),
+            Fr::from(3_u64),
+            Fr::from(9_u64),
+            Fr::from(27_u64)
+        ]);
+        assert!(tau.powers(0).is_empty());
+    }
+
+    #[test]
+    fn test_not_serialized() {
+        let tau = SecretScalar::random(&mut SecretRng::new());
+        let mut contribution = Contribution::new(4, 3);
+        contribution.add_tau(&tau);
+        contribution.sign_identity(&tau, "git|1234|recmo");
+        let json = serde_json::to_string(&ContributionJson::from(&contribution)).unwrap();
+        let debug = format!("{tau:?} {contribution:?}");
+
+        let repr = tau.expose_secret().into_repr();
+        let le = hex::encode(repr.to_bytes_le());
+        let be = hex::encode(repr.to_bytes_be());
+        let decimal = tau.expose_secret().to_string();
+        for output in [json, debug] {
+            assert!(!output.contains(&le));
+            assert!(!output.contains(&be));
+            assert!(!output.contains(&decimal));
+        }
+        assert_eq!(format!("{tau:?}"), "SecretScalar(..)");
+    }
+}
@@ -0,0 +1,66 @@
+//! BLS signatures over participant identities.
+//!
+//! A contributor can bind their identity to a contribution by signing it with
+//! their secret tau: `signature = tau * H(identity)` in G1, which is checked
+//! against the contribution's `potPubkey = tau * G2`.
+
+use crate::{constant_time::mul_secret, parse_g_bytes, SecretScalar};
+use ark_bls12_381::{g1, Bls12_381, G1Affine, G2Affine};
+use ark_ec::{AffineCurve, PairingEngine, ProjectiveCurve};
+use bls12_381::{
+    hash_to_curve::{ExpandMsgXmd, HashToCurve},
+    G1Projective,
+};
+
+/// Domain separation tag for BLS signatures with proof of possession.
+pub const BLS_DST: &[u8] = b"BLS_SIG_BLS12381G1_XMD:SHA-256_SSWU_RO_POP_";
+
+/// Hash `message` to G1 using the `BLS12381G1_XMD:SHA-256_SSWU_RO_` suite.
+pub fn hash_to_g1(message: &[u8]) -> G1Affine {
+    hash_to_g1_dst(message, BLS_DST)
+}
+
+fn hash_to_g1_dst(message: &[u8], dst: &[u8]) -> G1Affine {
+    let point =
+        <G1Projective as HashToCurve<ExpandMsgXmd<sha2::Sha256>>>::hash_to_curve(message, dst);
+    // Both libraries use the ZCash encoding.
+    let compressed = bls12_381::G1Affine::from(point).to_compressed();
+    parse_g_bytes::<g1::Parameters, 48>(&compressed).expect("Hash to curve produces valid points.")
+}
+
+/// Sign `identity` with the secret `tau`.
+pub fn sign_identity(tau: &SecretScalar, identity: &str) -> G1Affine {
+    mul_secret(&hash_to_g1(identity.as_bytes()), tau.expose_secret()).into_affine()
+}
+
+/// Check `signature` over `identity` against `pubkey = tau * G2`.
+#[must_use]
+pub fn verify_identity(pubkey: &G2Affine, identity: &str, signature: &G1Affine) -> bool {
+    Bls12_381::pairing(*signature, G2Affine::prime_subgroup_generator())
+        == Bls12_381::pairing(hash_to_g1(identity.as_bytes()), *pubkey)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{format_g, SecretRng};
+
+    #[test]
+    fn test_hash_to_g1() {
+        // Test vector from RFC 9380 appendix J.9.1 for the empty message.
+        let point = hash_to_g1_dst(b"", b"QUUX-V01-CS02-with-BLS12381G1_XMD:SHA-256_SSWU_RO_");
+        assert_eq!(format_g(&point), "0x852926add2207b76ca4fa57a8734416c8dc95e24501772c814278700eed6d1e4e8cf62d9c09db0fac349612b759e79a1");
+    }
+
+    #[test]
+    fn test_sign_verify() {
+        let mut rng = SecretRng::new();
+        let tau = SecretScalar::random(&mut rng);
+        let pubkey = G2Affine::prime_subgroup_generator()
+            .mul(*tau.expose_secret())
+            .into_affine();
+        let signature = sign_identity(&tau, "git|1234|recmo");
+        assert!(verify_identity(&pubkey, "git|1234|recmo", &signature));
+        assert!(!verify_identity(&pubkey, "git|1234|eve", &signature));
+    }
+}
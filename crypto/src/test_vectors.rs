@@ -0,0 +1,108 @@
+//! Conformance with the test vectors in `crypto/testvectors`.
+//!
+//! The vectors are generated by this implementation, not taken from the
+//! ceremony specs, so they guard against regressions but do not by themselves
+//! show interoperability with other implementations.
+//!
+//! * `points.json` lists G1 and G2 point encodings.
+//! * `transcripts/*.json` each hold a batch transcript as served by
+//!   `/ceremony/transcript`.
+//!
+//! Every vector names the [`ErrorCode`] the input must be rejected with, or
+//! `null` if it must be accepted. Transcripts are accepted if they parse and
+//! every sub-transcript passes [`Transcript::verify`].
+
+use crate::{parse_g, BatchTranscriptJson, ErrorCode, Transcript, TranscriptsError};
+use ark_bls12_381::{g1, g2};
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Group {
+    G1,
+    G2,
+}
+
+#[derive(Debug, Deserialize)]
+struct PointVector {
+    description: String,
+    group:       Group,
+    point:       String,
+    error:       Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TranscriptVector {
+    description: String,
+    error:       Option<String>,
+    transcript:  BatchTranscriptJson,
+}
+
+fn vectors_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("testvectors")
+}
+
+fn read<T: for<'de> Deserialize<'de>>(path: &Path) -> T {
+    let json = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Error reading {}: {}", path.display(), e));
+    serde_json::from_str(&json)
+        .unwrap_or_else(|e| panic!("Error parsing {}: {}", path.display(), e))
+}
+
+fn check_point(vector: &PointVector) -> Result<(), &'static str> {
+    match vector.group {
+        Group::G1 => parse_g::<g1::Parameters>(&vector.point).map(drop),
+        Group::G2 => parse_g::<g2::Parameters>(&vector.point).map(drop),
+    }
+    .map_err(|error| error.code())
+}
+
+fn check_transcript(json: &BatchTranscriptJson) -> Result<(), &'static str> {
+    let batch = json
+        .parse()
+        .map_err(|TranscriptsError::InvalidTranscript(_, error)| error.code())?;
+    batch
+        .transcripts
+        .iter()
+        .try_for_each(Transcript::verify)
+        .map_err(|error| error.code())
+}
+
+#[test]
+fn test_points() {
+    let vectors: Vec<PointVector> = read(&vectors_dir().join("points.json"));
+    assert!(!vectors.is_empty());
+    for vector in &vectors {
+        assert_eq!(
+            check_point(vector).err(),
+            vector.error.as_deref(),
+            "{}",
+            vector.description
+        );
+    }
+}
+
+#[test]
+fn test_transcripts() {
+    let mut paths = fs::read_dir(vectors_dir().join("transcripts"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "json"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty());
+    for path in paths {
+        let vector: TranscriptVector = read(&path);
+        assert_eq!(
+            check_transcript(&vector.transcript).err(),
+            vector.error.as_deref(),
+            "{}: {}",
+            path.display(),
+            vector.description
+        );
+    }
+}
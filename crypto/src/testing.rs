@@ -0,0 +1,228 @@
+//! Proptest generators of valid and near-valid contributions.
+//!
+//! Enabled with the `testing` feature, so downstream crates and the verifier
+//! CLI can fuzz their parsing against the same inputs as this crate.
+//! [`arb_contribution`] produces valid contributions on top of
+//! [`Transcript::new`], [`arb_corrupted_contribution`] applies a single
+//! [`Corruption`] to one of them. [`Corruption::expected_error`] is the error
+//! [`ContributionJson::parse`] must report for it.
+
+use crate::{
+    parse_g_unchecked, ContributionError, ContributionJson, ParseError, SecretScalar, Sqrt,
+    Transcript,
+};
+use ark_bls12_381::{g1, g2, Fr, G1Affine, G2Affine};
+use ark_ec::{
+    models::SWModelParameters, short_weierstrass_jacobian::GroupAffine, AffineCurve,
+    ProjectiveCurve,
+};
+use ark_ff::PrimeField;
+use proptest::{arbitrary::any, prop_oneof, strategy::Strategy};
+
+/// A single defect in an otherwise valid [`ContributionJson`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Corruption {
+    /// The last G1 power is missing.
+    G1Length,
+    /// The last G2 power is missing.
+    G2Length,
+    /// G1 power with an x coordinate that is not on the curve.
+    G1OffCurve(usize),
+    /// G2 power with an x coordinate that is not on the curve.
+    G2OffCurve(usize),
+    /// G1 power on the curve, but outside the prime-order subgroup.
+    G1NotInSubgroup(usize),
+    /// G2 power on the curve, but outside the prime-order subgroup.
+    G2NotInSubgroup(usize),
+    /// G1 power with the sign bit flipped, i.e. negated. Parses, but fails
+    /// verification.
+    G1FlippedSign(usize),
+    /// G2 power with the sign bit flipped, i.e. negated. Parses, but fails
+    /// verification.
+    G2FlippedSign(usize),
+}
+
+impl Corruption {
+    /// Apply the corruption to `json`.
+    pub fn apply(self, json: &mut ContributionJson) {
+        let g1_powers = &mut json.powers_of_tau.g1_powers;
+        let g2_powers = &mut json.powers_of_tau.g2_powers;
+        match self {
+            Self::G1Length => {
+                g1_powers.pop();
+            }
+            Self::G2Length => {
+                g2_powers.pop();
+            }
+            Self::G1OffCurve(i) => {
+                g1_powers[i] = mutate_x::<g1::Parameters>(&g1_powers[i], |point| {
+                    point == Err(ParseError::InvalidXCoordinate)
+                });
+            }
+            Self::G2OffCurve(i) => {
+                g2_powers[i] = mutate_x::<g2::Parameters>(&g2_powers[i], |point| {
+                    point == Err(ParseError::InvalidXCoordinate)
+                });
+            }
+            Self::G1NotInSubgroup(i) => {
+                g1_powers[i] = mutate_x::<g1::Parameters>(&g1_powers[i], |point| {
+                    point.map_or(false, |p| !p.is_in_correct_subgroup_assuming_on_curve())
+                });
+            }
+            Self::G2NotInSubgroup(i) => {
+                g2_powers[i] = mutate_x::<g2::Parameters>(&g2_powers[i], |point| {
+                    point.map_or(false, |p| !p.is_in_correct_subgroup_assuming_on_curve())
+                });
+            }
+            Self::G1FlippedSign(i) => g1_powers[i] = flip_sign(&g1_powers[i]),
+            Self::G2FlippedSign(i) => g2_powers[i] = flip_sign(&g2_powers[i]),
+        }
+    }
+
+    /// The error parsing the corrupted `json` reports, `None` if it parses.
+    #[must_use]
+    pub const fn expected_error(self, json: &ContributionJson) -> Option<ContributionError> {
+        Some(match self {
+            Self::G1Length => ContributionError::InconsistentNumG1Powers(
+                json.num_g1_powers,
+                json.num_g1_powers - 1,
+            ),
+            Self::G2Length => ContributionError::InconsistentNumG2Powers(
+                json.num_g2_powers,
+                json.num_g2_powers - 1,
+            ),
+            Self::G1OffCurve(i) => {
+                ContributionError::InvalidG1Power(i, ParseError::InvalidXCoordinate)
+            }
+            Self::G2OffCurve(i) => {
+                ContributionError::InvalidG2Power(i, ParseError::InvalidXCoordinate)
+            }
+            Self::G1NotInSubgroup(i) => {
+                ContributionError::InvalidG1Power(i, ParseError::InvalidSubgroup)
+            }
+            Self::G2NotInSubgroup(i) => {
+                ContributionError::InvalidG2Power(i, ParseError::InvalidSubgroup)
+            }
+            Self::G1FlippedSign(_) | Self::G2FlippedSign(_) => return None,
+        })
+    }
+}
+
+pub fn arb_fr() -> impl Strategy<Value = Fr> {
+    any::<[u8; 32]>().prop_map(|bytes| Fr::from_le_bytes_mod_order(&bytes))
+}
+
+pub fn arb_g1() -> impl Strategy<Value = G1Affine> {
+    arb_fr().prop_map(|s| G1Affine::prime_subgroup_generator().mul(s).into_affine())
+}
+
+pub fn arb_g2() -> impl Strategy<Value = G2Affine> {
+    arb_fr().prop_map(|s| G2Affine::prime_subgroup_generator().mul(s).into_affine())
+}
+
+/// Valid contributions with a random tau to a sub-ceremony of the given size
+/// that has no contributions yet.
+pub fn arb_contribution(
+    num_g1_powers: usize,
+    num_g2_powers: usize,
+) -> impl Strategy<Value = ContributionJson> {
+    arb_fr().prop_map(move |tau| {
+        let mut contribution = Transcript::new(num_g1_powers, num_g2_powers).contribution();
+        contribution.add_tau(&SecretScalar::new(tau));
+        ContributionJson::from(&contribution)
+    })
+}
+
+/// Any corruption of a contribution of the given size.
+///
+/// Requires at least two G1 and two G2 powers.
+pub fn arb_corruption(
+    num_g1_powers: usize,
+    num_g2_powers: usize,
+) -> impl Strategy<Value = Corruption> {
+    prop_oneof![
+        (0..1).prop_map(|_| Corruption::G1Length),
+        (0..1).prop_map(|_| Corruption::G2Length),
+        (0..num_g1_powers).prop_map(Corruption::G1OffCurve),
+        (0..num_g2_powers).prop_map(Corruption::G2OffCurve),
+        (0..num_g1_powers).prop_map(Corruption::G1NotInSubgroup),
+        (0..num_g2_powers).prop_map(Corruption::G2NotInSubgroup),
+        (0..num_g1_powers).prop_map(Corruption::G1FlippedSign),
+        (0..num_g2_powers).prop_map(Corruption::G2FlippedSign),
+    ]
+}
+
+/// Contributions with a single corruption, and the corruption applied.
+pub fn arb_corrupted_contribution(
+    num_g1_powers: usize,
+    num_g2_powers: usize,
+) -> impl Strategy<Value = (ContributionJson, Corruption)> {
+    (
+        arb_contribution(num_g1_powers, num_g2_powers),
+        arb_corruption(num_g1_powers, num_g2_powers),
+    )
+        .prop_map(|(mut json, corruption)| {
+            corruption.apply(&mut json);
+            (json, corruption)
+        })
+}
+
+/// Change the low bits of the x coordinate of `hex` until `accept` holds
+/// for the parsed result.
+///
+/// About half of all x coordinates are on the curve, and almost none of those
+/// are in the subgroup, so few tries are needed.
+fn mutate_x<P: SWModelParameters>(
+    hex: &str,
+    accept: impl Fn(Result<GroupAffine<P>, ParseError>) -> bool,
+) -> String
+where
+    P::BaseField: Sqrt,
+{
+    let mut bytes = hex::decode(&hex[2..]).expect("Valid point encoding");
+    let last = bytes.len() - 1;
+    for _ in 0..=u8::MAX {
+        bytes[last] = bytes[last].wrapping_add(1);
+        let candidate = format!("0x{}", hex::encode(&bytes));
+        if accept(parse_g_unchecked::<P>(&candidate)) {
+            return candidate;
+        }
+    }
+    panic!("No acceptable x coordinate near {hex}");
+}
+
+fn flip_sign(hex: &str) -> String {
+    let mut bytes = hex::decode(&hex[2..]).expect("Valid point encoding");
+    bytes[0] ^= 0x20;
+    format!("0x{}", hex::encode(bytes))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use proptest::{proptest, test_runner::Config};
+
+    /// Every case does pairings, which are slow in debug builds.
+    const CASES: u32 = 32;
+
+    #[test]
+    fn test_arb_contribution() {
+        let transcript = Transcript::new(8, 3);
+        proptest!(Config::with_cases(CASES), |(json in arb_contribution(8, 3))| {
+            let contribution = json.parse().unwrap();
+            assert_eq!(contribution.verify(&transcript), Ok(()));
+        });
+    }
+
+    #[test]
+    fn test_arb_corrupted_contribution() {
+        let transcript = Transcript::new(8, 3);
+        let corrupted = arb_corrupted_contribution(8, 3);
+        proptest!(Config::with_cases(CASES), |((json, corruption) in corrupted)| {
+            match corruption.expected_error(&json) {
+                Some(error) => assert_eq!(json.parse(), Err(error)),
+                None => assert!(json.parse().unwrap().verify(&transcript).is_err()),
+            }
+        });
+    }
+}
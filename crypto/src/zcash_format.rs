@@ -1,12 +1,17 @@
+use crate::{
+    error_code::{serialize_error, ErrorCode},
+    sqrt::Sqrt,
+};
 use ark_ec::{
     models::{ModelParameters, SWModelParameters},
     short_weierstrass_jacobian::GroupAffine,
 };
 use ark_ff::{
     fields::{Field, FpParameters, PrimeField},
-    BigInteger, Zero,
+    BigInteger, ToBytes, Zero,
 };
 use hex::FromHexError;
+use serde::{Serialize, Serializer};
 use thiserror::Error;
 
 #[derive(Clone, Copy, PartialEq, Debug, Error)]
@@ -17,6 +22,8 @@ pub enum ParseError {
     MissingPrefix,
     #[error("Invalid hex string: {0}")]
     InvalidHex(#[from] FromHexError),
+    #[error("Invalid length of encoding: expected {0} bytes, got {1}")]
+    InvalidByteLength(usize, usize),
     #[error("Invalid x coordinate")]
     BigIntError,
     #[error("Point is not compressed")]
@@ -31,6 +38,40 @@ pub enum ParseError {
     InvalidXCoordinate,
     #[error("curve point is not in prime order subgroup")]
     InvalidSubgroup,
+    #[error("Number is too large for the scalar field")]
+    InvalidScalar,
+}
+
+impl ErrorCode for ParseError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidLength(..) => "invalid_length",
+            Self::MissingPrefix => "missing_prefix",
+            Self::InvalidHex(_) => "invalid_hex",
+            Self::InvalidByteLength(..) => "invalid_byte_length",
+            Self::BigIntError => "invalid_big_int",
+            Self::NotCompressed => "not_compressed",
+            Self::InvalidInfinity => "invalid_infinity",
+            Self::InvalidPrimeField(_) => "invalid_prime_field",
+            Self::InvalidExtensionField => "invalid_extension_field",
+            Self::InvalidXCoordinate => "invalid_x_coordinate",
+            Self::InvalidSubgroup => "invalid_subgroup",
+            Self::InvalidScalar => "invalid_scalar",
+        }
+    }
+}
+
+impl Serialize for ParseError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let fields: &[_] = match *self {
+            Self::InvalidLength(expected, got) | Self::InvalidByteLength(expected, got) => {
+                &[("expected", expected), ("got", got)]
+            }
+            Self::InvalidPrimeField(component) => &[("component", component)],
+            _ => &[],
+        };
+        serialize_error(serializer, self, fields, None::<&()>)
+    }
 }
 
 pub fn parse_hex(hex: &str, out: &mut [u8]) -> Result<(), ParseError> {
@@ -45,10 +86,107 @@ pub fn parse_hex(hex: &str, out: &mut [u8]) -> Result<(), ParseError> {
     Ok(())
 }
 
-/// Deserialize a ZCash spec encoded group element.
+/// Largest encoding of a point, a compressed G2 point of BLS12-381.
+const MAX_SIZE: usize = 96;
+
+/// Deserialize a `ZCash` spec encoded group element.
 ///
 /// See <https://github.com/zcash/librustzcash/blob/6e0364cd42a2b3d2b958a54771ef51a8db79dd29/pairing/src/bls12_381/README.md#serialization>
-pub fn parse_g<P: SWModelParameters>(hex: &str) -> Result<GroupAffine<P>, ParseError> {
+///
+/// # Errors
+///
+/// Returns an error if the encoding is invalid, the point is not on the curve
+/// or not in the prime order subgroup.
+pub fn parse_g<P: SWModelParameters>(hex: &str) -> Result<GroupAffine<P>, ParseError>
+where
+    P::BaseField: Sqrt,
+{
+    subgroup_check(parse_g_unchecked::<P>(hex)?)
+}
+
+/// Like [`parse_g`], but without checking that the point is in the prime
+/// order subgroup.
+///
+/// The caller is responsible for the subgroup check, e.g. using
+/// [`crate::batch_subgroup_check`].
+///
+/// # Errors
+///
+/// Returns an error if the encoding is invalid or the point is not on the
+/// curve.
+pub fn parse_g_unchecked<P: SWModelParameters>(hex: &str) -> Result<GroupAffine<P>, ParseError>
+where
+    P::BaseField: Sqrt,
+{
+    // Decode on the stack, this runs for every point of a transcript.
+    let mut buffer = [0_u8; MAX_SIZE];
+    let bytes = &mut buffer[..encoded_size::<P>()];
+    parse_hex(hex, bytes)?;
+    decode(bytes)
+}
+
+/// Like [`parse_g`], but from the encoding as bytes instead of hex.
+///
+/// # Errors
+///
+/// The same as [`parse_g`], except for hex decoding.
+pub fn parse_g_bytes<P: SWModelParameters, const N: usize>(
+    bytes: &[u8; N],
+) -> Result<GroupAffine<P>, ParseError>
+where
+    P::BaseField: Sqrt,
+{
+    subgroup_check(parse_g_bytes_unchecked::<P, N>(bytes)?)
+}
+
+/// Like [`parse_g_bytes`], but without checking that the point is in the
+/// prime order subgroup.
+///
+/// # Errors
+///
+/// The same as [`parse_g_unchecked`], except for hex decoding.
+pub fn parse_g_bytes_unchecked<P: SWModelParameters, const N: usize>(
+    bytes: &[u8; N],
+) -> Result<GroupAffine<P>, ParseError>
+where
+    P::BaseField: Sqrt,
+{
+    let expected_len = encoded_size::<P>();
+    if N != expected_len {
+        return Err(ParseError::InvalidByteLength(expected_len, N));
+    }
+    let mut bytes = *bytes;
+    decode(&mut bytes)
+}
+
+fn subgroup_check<P: SWModelParameters>(
+    point: GroupAffine<P>,
+) -> Result<GroupAffine<P>, ParseError> {
+    if !point.is_in_correct_subgroup_assuming_on_curve() {
+        return Err(ParseError::InvalidSubgroup);
+    }
+    Ok(point)
+}
+
+/// Size in bytes of the compressed encoding of a point.
+fn encoded_size<P: SWModelParameters>() -> usize {
+    type Extension<P> = <P as ModelParameters>::BaseField;
+    type Prime<P> = <Extension<P> as Field>::BasePrimeField;
+    type Int<P> = <Prime<P> as PrimeField>::BigInt;
+    let extension: usize = Extension::<P>::extension_degree()
+        .try_into()
+        .expect("Extension degree should fit usize.");
+    let size = extension * Int::<P>::NUM_LIMBS * 8;
+    assert!(size <= MAX_SIZE, "Encoding exceeds {MAX_SIZE} bytes.");
+    size
+}
+
+/// Decode a point from its encoding of [`encoded_size`] bytes. The bytes are
+/// used as scratch space.
+fn decode<P: SWModelParameters>(bytes: &mut [u8]) -> Result<GroupAffine<P>, ParseError>
+where
+    P::BaseField: Sqrt,
+{
     // Create some type aliases for the base extension, field and int types.
     type Extension<P> = <P as ModelParameters>::BaseField;
     type Prime<P> = <Extension<P> as Field>::BasePrimeField;
@@ -56,20 +194,13 @@ pub fn parse_g<P: SWModelParameters>(hex: &str) -> Result<GroupAffine<P>, ParseE
     let modulus = <Prime<P> as PrimeField>::Params::MODULUS;
 
     // Compute sizes
-    let extension: usize = Extension::<P>::extension_degree()
-        .try_into()
-        .expect("Extension degree should fit usize.");
     let element_size = Int::<P>::NUM_LIMBS * 8;
-    let size = extension * element_size;
     let padding_bits = element_size * 8 - modulus.num_bits() as usize;
     assert!(
         padding_bits >= 3,
         "ZCash encoding spec requires three prefix bits, but there is not enough padding."
     );
-
-    // Read hex string
-    let mut bytes = vec![0u8; size];
-    parse_hex(hex, &mut bytes)?;
+    debug_assert_eq!(bytes.len(), encoded_size::<P>());
 
     // Read and mask flags
     let compressed = bytes[0] & 0x80 != 0;
@@ -78,7 +209,7 @@ pub fn parse_g<P: SWModelParameters>(hex: &str) -> Result<GroupAffine<P>, ParseE
     bytes[0] &= 0x1f;
 
     // Read x coordinate
-    let mut elements = bytes[..]
+    let mut elements = bytes
         .chunks_exact_mut(element_size)
         .enumerate()
         .map(|(i, chunk)| {
@@ -87,7 +218,7 @@ pub fn parse_g<P: SWModelParameters>(hex: &str) -> Result<GroupAffine<P>, ParseE
             let mut x = Int::<P>::default();
             x.read_le(&mut reader)
                 .map_err(|_| ParseError::BigIntError)?;
-            if reader.len() != 0 {
+            if !reader.is_empty() {
                 return Err(ParseError::BigIntError);
             }
             if x >= modulus {
@@ -111,21 +242,56 @@ pub fn parse_g<P: SWModelParameters>(hex: &str) -> Result<GroupAffine<P>, ParseE
         }
         return Ok(GroupAffine::<P>::zero());
     }
-    let point =
-        GroupAffine::<P>::get_point_from_x(x, greatest).ok_or(ParseError::InvalidXCoordinate)?;
+    // Same as `GroupAffine::get_point_from_x`, with a faster square root.
+    let y = P::add_b(&(x.square() * x + P::mul_by_a(&x)))
+        .sqrt_fast()
+        .ok_or(ParseError::InvalidXCoordinate)?;
+    let y = if (y < -y) ^ greatest { y } else { -y };
+    let point = GroupAffine::<P>::new(x, y, false);
     debug_assert!(point.is_on_curve()); // Always true
-    if !point.is_in_correct_subgroup_assuming_on_curve() {
-        return Err(ParseError::InvalidSubgroup);
-    }
-
     Ok(point)
 }
 
+/// Serialize a group element in compressed `ZCash` spec encoding.
+///
+/// This is the inverse of [`parse_g`].
+///
+/// # Panics
+///
+/// Never, writing to a `Vec` can not fail.
+pub fn format_g<P: SWModelParameters>(point: &GroupAffine<P>) -> String {
+    type Extension<P> = <P as ModelParameters>::BaseField;
+    type Prime<P> = <Extension<P> as Field>::BasePrimeField;
+    type Int<P> = <Prime<P> as PrimeField>::BigInt;
+    let element_size = Int::<P>::NUM_LIMBS * 8;
+
+    // Write x coordinate as big-endian elements, highest component first.
+    let mut bytes = Vec::new();
+    point
+        .x
+        .write(&mut bytes)
+        .expect("Writing to a Vec can not fail.");
+    bytes.reverse();
+    debug_assert_eq!(bytes.len() % element_size, 0);
+
+    // Set flags
+    bytes[0] |= 0x80;
+    if point.is_zero() {
+        bytes.fill(0);
+        bytes[0] = 0xc0;
+    } else if point.y > -point.y {
+        bytes[0] |= 0x20;
+    }
+    format!("0x{}", hex::encode(bytes))
+}
+
 #[cfg(test)]
 pub mod test {
     use super::*;
-    use ark_bls12_381::{G1Affine, G2Affine};
+    use crate::test::{arb_g1, arb_g2};
+    use ark_bls12_381::{g1, g2, G1Affine, G2Affine};
     use ark_ec::AffineCurve;
+    use proptest::proptest;
 
     #[test]
     fn test_parse_g1() {
@@ -138,6 +304,56 @@ pub mod test {
         assert_eq!(parse_g("0xc00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000").unwrap(), G2Affine::zero());
         assert_eq!(parse_g("0x93e02b6052719f607dacd3a088274f65596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e024aa2b2f08f0a91260805272dc51051c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8").unwrap(), G2Affine::prime_subgroup_generator());
     }
+
+    #[test]
+    fn test_format_g() {
+        assert_eq!(format_g(&G1Affine::zero()), "0xc00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000");
+        assert_eq!(format_g(&G1Affine::prime_subgroup_generator()), "0x97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb");
+        assert_eq!(format_g(&G2Affine::prime_subgroup_generator()), "0x93e02b6052719f607dacd3a088274f65596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e024aa2b2f08f0a91260805272dc51051c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8");
+    }
+
+    #[test]
+    fn test_parse_g_bytes() {
+        let mut bytes = [0_u8; 48];
+        hex::decode_to_slice("97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb", &mut bytes).unwrap();
+        assert_eq!(
+            parse_g_bytes::<g1::Parameters, 48>(&bytes).unwrap(),
+            G1Affine::prime_subgroup_generator()
+        );
+        assert_eq!(
+            parse_g_bytes::<g2::Parameters, 48>(&bytes),
+            Err(ParseError::InvalidByteLength(96, 48))
+        );
+        bytes[0] &= 0x7f;
+        assert_eq!(
+            parse_g_bytes::<g1::Parameters, 48>(&bytes),
+            Err(ParseError::NotCompressed)
+        );
+    }
+
+    #[test]
+    fn test_parse_g_bytes_hex() {
+        proptest!(|(p in arb_g2())| {
+            let hex = format_g(&p);
+            let mut bytes = [0_u8; 96];
+            parse_hex(&hex, &mut bytes).unwrap();
+            assert_eq!(parse_g_bytes::<g2::Parameters, 96>(&bytes).unwrap(), p);
+        });
+    }
+
+    #[test]
+    fn test_format_parse_g1() {
+        proptest!(|(p in arb_g1())| {
+            assert_eq!(parse_g::<g1::Parameters>(&format_g(&p)).unwrap(), p);
+        });
+    }
+
+    #[test]
+    fn test_format_parse_g2() {
+        proptest!(|(p in arb_g2())| {
+            assert_eq!(parse_g::<g2::Parameters>(&format_g(&p)).unwrap(), p);
+        });
+    }
 }
 
 #[cfg(feature = "bench")]
@@ -150,6 +366,8 @@ pub mod bench {
     pub fn group(criterion: &mut Criterion) {
         bench_parse_g1(criterion);
         bench_parse_g2(criterion);
+        bench_parse_g1_bytes(criterion);
+        bench_decompress_g1(criterion);
     }
 
     fn bench_parse_g1(criterion: &mut Criterion) {
@@ -159,6 +377,28 @@ pub mod bench {
         });
     }
 
+    fn bench_parse_g1_bytes(criterion: &mut Criterion) {
+        let mut input = [0_u8; 48];
+        hex::decode_to_slice("97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb", &mut input).unwrap();
+        criterion.bench_function("parse_g1_bytes", move |bencher| {
+            bencher.iter(|| black_box(parse_g_bytes::<g1::Parameters, 48>(black_box(&input))));
+        });
+    }
+
+    fn bench_decompress_g1(criterion: &mut Criterion) {
+        let inputs = (0..1024)
+            .map(|_| format_g(&crate::bench::rand_g1()))
+            .collect::<Vec<_>>();
+        criterion.bench_function("decompress_g1_1024", move |bencher| {
+            bencher.iter(|| {
+                inputs
+                    .iter()
+                    .map(|input| parse_g_unchecked::<g1::Parameters>(black_box(input)))
+                    .collect::<Vec<_>>()
+            });
+        });
+    }
+
     fn bench_parse_g2(criterion: &mut Criterion) {
         let input = "0x93e02b6052719f607dacd3a088274f65596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e024aa2b2f08f0a91260805272dc51051c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8";
         criterion.bench_function("parse_g2", move |bencher| {